@@ -0,0 +1,18 @@
+-- Store hospital keys as bcrypt hashes instead of plain text
+-- The gateway verifies keys with bcrypt (`$2a$`/`$2b$`); pgcrypto produces compatible hashes
+CREATE EXTENSION IF NOT EXISTS pgcrypto;
+
+BEGIN;
+
+ALTER TABLE hospital_credentials ADD COLUMN key_hash TEXT;
+
+UPDATE hospital_credentials
+SET key_hash = crypt(hospital_key, gen_salt('bf', 12));
+
+ALTER TABLE hospital_credentials ALTER COLUMN key_hash SET NOT NULL;
+ALTER TABLE hospital_credentials DROP COLUMN hospital_key;
+
+COMMIT;
+
+-- New keys: INSERT INTO hospital_credentials (hospital_id, key_hash, consent_scope)
+--           VALUES ('<hospital_id>', crypt('<key>', gen_salt('bf', 12)), 'clinical');
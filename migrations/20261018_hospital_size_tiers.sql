@@ -0,0 +1,4 @@
+-- Payload size tier per hospital: NULL or 'standard' = POST_SIZE_LIMIT,
+-- 'premium' = PREMIUM_POST_SIZE_LIMIT
+ALTER TABLE hospital_credentials
+    ADD COLUMN size_tier TEXT CHECK (size_tier IN ('standard', 'premium'));
@@ -0,0 +1,32 @@
+-- Hospital groups: clinics under a parent hospital group, with per-clinic credentials
+-- A group key may submit on behalf of a clinic (`on_behalf_of` header) only through an explicit,
+-- non-revoked delegation record
+BEGIN;
+
+ALTER TABLE hospital_credentials
+    ADD COLUMN parent_hospital_id TEXT REFERENCES hospital_credentials (hospital_id),
+    -- Exams accepted per calendar month (UTC): NULL = unlimited - a group quota covers its clinics
+    ADD COLUMN monthly_exam_quota BIGINT CHECK (monthly_exam_quota >= 0);
+
+CREATE TABLE hospital_delegations (
+    group_id   TEXT NOT NULL REFERENCES hospital_credentials (hospital_id),
+    clinic_id  TEXT NOT NULL REFERENCES hospital_credentials (hospital_id),
+    granted_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    revoked_at TIMESTAMPTZ,
+    PRIMARY KEY (group_id, clinic_id)
+);
+
+-- Monthly submissions counted against the quotas, per hospital (clinic or group)
+CREATE TABLE hospital_monthly_usage (
+    hospital_id TEXT NOT NULL,
+    month       TEXT NOT NULL,
+    exams       BIGINT NOT NULL DEFAULT 0,
+    PRIMARY KEY (hospital_id, month)
+);
+
+COMMIT;
+
+-- New clinic: INSERT INTO hospital_credentials (hospital_id, key_hash, consent_scope, parent_hospital_id)
+--             VALUES ('<clinic_id>', crypt('<key>', gen_salt('bf', 12)), 'clinical', '<group_id>');
+-- Delegation: INSERT INTO hospital_delegations (group_id, clinic_id) VALUES ('<group_id>', '<clinic_id>');
+-- Revocation: UPDATE hospital_delegations SET revoked_at = now() WHERE group_id = '<group_id>' AND clinic_id = '<clinic_id>';
@@ -0,0 +1,21 @@
+-- Idempotency keys: the response of every accepted exam, replayed to the retries of the same exam
+-- (same 'Idempotency-Key' header, or same payload hash) within IDEMPOTENCY_WINDOW_S
+BEGIN;
+
+CREATE TABLE idempotency_keys (
+    hospital_id     TEXT NOT NULL,
+    exam_type       TEXT NOT NULL,
+    idempotency_key TEXT NOT NULL CHECK (length(idempotency_key) <= 255),
+    status          INTEGER NOT NULL,
+    -- The original JSON body
+    body            TEXT NOT NULL,
+    created_at      TIMESTAMPTZ NOT NULL DEFAULT now(),
+    PRIMARY KEY (hospital_id, exam_type, idempotency_key)
+);
+
+CREATE INDEX idempotency_keys_created_at ON idempotency_keys (created_at);
+
+COMMIT;
+
+-- Expired keys are reused in place; prune the rest periodically (e.g. daily, with the default window):
+-- DELETE FROM idempotency_keys WHERE created_at < now() - interval '1 day';
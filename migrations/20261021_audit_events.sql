@@ -0,0 +1,47 @@
+-- Audit trail: every authentication decision and every exam stored, published, dead-lettered or
+-- lost - append-only, rows are never updated nor deleted by the gateway
+BEGIN;
+
+CREATE TABLE audit_events (
+    id           BIGSERIAL PRIMARY KEY,
+    occurred_at  TIMESTAMPTZ NOT NULL,
+    -- authentication, exam_stored, exam_published, exam_dead_lettered or exam_lost
+    action       TEXT NOT NULL,
+    outcome      TEXT NOT NULL,
+    hospital_id  TEXT,
+    exam_type    TEXT,
+    exam_id      TEXT,
+    -- Hex SHA256 of the stored object
+    payload_hash TEXT,
+    -- Reason code of a denied authentication, 'OK' when granted
+    auth_result  TEXT,
+    client_ip    TEXT,
+    object_path  TEXT,
+    message_id   TEXT,
+    request_id   TEXT
+);
+
+CREATE INDEX audit_events_hospital ON audit_events (hospital_id, occurred_at);
+CREATE INDEX audit_events_exam ON audit_events (exam_id);
+CREATE INDEX audit_events_occurred_at ON audit_events (occurred_at);
+
+-- Append-only, even for roles that could otherwise change the table
+CREATE FUNCTION audit_events_append_only() RETURNS trigger AS $$
+BEGIN
+    RAISE EXCEPTION 'audit_events is append-only';
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER audit_events_no_update
+    BEFORE UPDATE OR DELETE ON audit_events
+    FOR EACH ROW EXECUTE FUNCTION audit_events_append_only();
+CREATE TRIGGER audit_events_no_truncate
+    BEFORE TRUNCATE ON audit_events
+    FOR EACH STATEMENT EXECUTE FUNCTION audit_events_append_only();
+
+REVOKE UPDATE, DELETE, TRUNCATE ON audit_events FROM PUBLIC;
+
+COMMIT;
+
+-- Retention is decided by compliance: archive old rows by exporting them, the trigger must be
+-- dropped by the table owner to delete anything
@@ -0,0 +1,37 @@
+-- Hospital keys managed through the admin API (`/v1/admin/hospitals`): several keys per hospital,
+-- each with an expiry and a revocation, plus the exam types and rate limit tier of the hospital
+BEGIN;
+
+CREATE TABLE hospital_keys (
+    -- Public id of the key, e.g. 'hk_1f2e3d4c5b6a7988' - never the key itself
+    key_id      TEXT PRIMARY KEY,
+    hospital_id TEXT NOT NULL REFERENCES hospital_credentials (hospital_id),
+    key_hash    TEXT NOT NULL,
+    -- First characters of the key, to recognise it in listings
+    key_prefix  TEXT,
+    created_at  TIMESTAMPTZ NOT NULL DEFAULT now(),
+    -- NULL = never expires
+    expires_at  TIMESTAMPTZ,
+    revoked_at  TIMESTAMPTZ
+);
+
+CREATE INDEX hospital_keys_hospital ON hospital_keys (hospital_id, created_at);
+
+-- The key of every hospital becomes its first key
+INSERT INTO hospital_keys (key_id, hospital_id, key_hash)
+SELECT 'hk_' || substr(md5(hospital_id), 1, 16), hospital_id, key_hash
+FROM hospital_credentials;
+
+ALTER TABLE hospital_credentials DROP COLUMN key_hash;
+
+ALTER TABLE hospital_credentials
+    -- Exam types the hospital may submit, e.g. '{ecg_exam}': NULL = all
+    ADD COLUMN allowed_exam_types TEXT[],
+    -- Scale of the ingest rate limit: NULL or 'standard', 'elevated' or 'unlimited'
+    ADD COLUMN rate_limit_tier TEXT CHECK (rate_limit_tier IN ('standard', 'elevated', 'unlimited'));
+
+COMMIT;
+
+-- Keys are issued, rotated and revoked through the admin API: they are generated by the gateway,
+-- returned once and only their bcrypt hash is stored
+-- Emergency revocation: UPDATE hospital_keys SET revoked_at = now() WHERE key_id = '<key_id>';
@@ -0,0 +1,31 @@
+-- Notify every gateway instance of the changes of the hospital registry (channel
+-- 'hospital_registry', payload = the changed hospital id), so cached credentials, tiers, exam types
+-- and delegations are forgotten within seconds - manual changes in SQL are notified as well
+BEGIN;
+
+CREATE FUNCTION hospital_registry_notify() RETURNS trigger AS $$
+DECLARE
+    changed jsonb := CASE TG_OP WHEN 'DELETE' THEN to_jsonb(OLD) ELSE to_jsonb(NEW) END;
+    id_column text;
+BEGIN
+    -- The trigger arguments name the hospital id columns of the table
+    FOREACH id_column IN ARRAY TG_ARGV LOOP
+        IF changed ->> id_column IS NOT NULL THEN
+            PERFORM pg_notify('hospital_registry', changed ->> id_column);
+        END IF;
+    END LOOP;
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER hospital_credentials_notify
+    AFTER INSERT OR UPDATE OR DELETE ON hospital_credentials
+    FOR EACH ROW EXECUTE FUNCTION hospital_registry_notify('hospital_id');
+CREATE TRIGGER hospital_keys_notify
+    AFTER INSERT OR UPDATE OR DELETE ON hospital_keys
+    FOR EACH ROW EXECUTE FUNCTION hospital_registry_notify('hospital_id');
+CREATE TRIGGER hospital_delegations_notify
+    AFTER INSERT OR UPDATE OR DELETE ON hospital_delegations
+    FOR EACH ROW EXECUTE FUNCTION hospital_registry_notify('group_id', 'clinic_id');
+
+COMMIT;
@@ -0,0 +1,4 @@
+-- When the exams of a hospital are answered: NULL or 'inline' = once stored and notified on
+-- Pub/Sub, 'after_storage' = once stored, Pub/Sub is notified in the background
+ALTER TABLE hospital_credentials
+    ADD COLUMN publish_mode TEXT CHECK (publish_mode IN ('inline', 'after_storage'));
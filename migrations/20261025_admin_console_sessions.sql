@@ -0,0 +1,25 @@
+-- Sessions of the browser admin console (ADMIN_CONSOLE_ORIGIN): the cookie holds a random token,
+-- only its SHA256 is kept - every login, request, refresh and logout is in the audit trail under
+-- the public session id
+BEGIN;
+
+CREATE TABLE admin_sessions (
+    -- Public id of the session, e.g. 'as_1f2e3d4c5b6a7988' - never the token itself
+    session_id   TEXT PRIMARY KEY,
+    token_hash   TEXT NOT NULL UNIQUE,
+    -- Sent back by the console in 'x-csrf-token' on every state-changing request
+    csrf_token   TEXT NOT NULL,
+    client_ip    TEXT,
+    created_at   TIMESTAMPTZ NOT NULL DEFAULT now(),
+    last_seen_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    -- Pushed back by each refresh, never past created_at + ADMIN_SESSION_MAX_S
+    expires_at   TIMESTAMPTZ NOT NULL,
+    revoked_at   TIMESTAMPTZ
+);
+
+CREATE INDEX admin_sessions_expires_at ON admin_sessions (expires_at);
+
+ALTER TABLE audit_events ADD COLUMN session_id TEXT;
+CREATE INDEX audit_events_session ON audit_events (session_id, occurred_at);
+
+COMMIT;
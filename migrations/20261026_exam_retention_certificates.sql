@@ -0,0 +1,8 @@
+-- Exam retention (EXAM_RETENTION_DAYS): every exam object deleted at the end of its retention is
+-- recorded as an 'exam_expired' event carrying its signed deletion certificate
+BEGIN;
+
+-- JSON certificate, signed with RETENTION_SIGNING_KEY - the compliance evidence of the deletion
+ALTER TABLE audit_events ADD COLUMN certificate TEXT;
+
+COMMIT;
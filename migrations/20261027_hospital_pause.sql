@@ -0,0 +1,3 @@
+-- Paused hospitals (`PATCH /v1/admin/hospitals/{id}` with `paused`, or `admin pause-hospital`): their
+-- exams are refused with 403 until they are resumed - NULL = not paused
+ALTER TABLE hospital_credentials ADD COLUMN paused_at TIMESTAMPTZ;
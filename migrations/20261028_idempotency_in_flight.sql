@@ -0,0 +1,16 @@
+-- In-flight idempotency keys: the request processing an exam claims its key, so a retry racing it
+-- waits for its response instead of storing and publishing the exam a second time. Claims are
+-- deleted once the exam is answered; the claim of a crashed instance expires and is taken over
+BEGIN;
+
+CREATE TABLE idempotency_in_flight (
+    hospital_id     TEXT NOT NULL,
+    exam_type       TEXT NOT NULL,
+    idempotency_key TEXT NOT NULL CHECK (length(idempotency_key) <= 255),
+    -- Id of the request holding the claim, so only it releases the claim
+    claim_id        TEXT NOT NULL,
+    expires_at      TIMESTAMPTZ NOT NULL,
+    PRIMARY KEY (hospital_id, exam_type, idempotency_key)
+);
+
+COMMIT;
@@ -0,0 +1,4 @@
+-- Payload of the idempotency keys: the hex SHA256 of the canonical JSON of the exam first accepted
+-- with the key, so a key sent again with another payload is refused (422) instead of replayed -
+-- NULL for streamed uploads and the keys remembered before, which are replayed unchecked
+ALTER TABLE idempotency_keys ADD COLUMN payload_hash TEXT;
@@ -0,0 +1,284 @@
+// Imports *****************************************************************************************
+// External Crates
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+// Internal Modules
+use crate::utils::reason_code::ReasonCode;
+use crate::utils::request_id::current_request_id;
+
+// Structs *****************************************************************************************
+/// Audited actions of the trail
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditAction {
+    /// A hospital credential was checked by the authentication middleware
+    Authentication,
+    /// An exam was written to the exam storage
+    ExamStored,
+    /// The notification of a stored exam was published
+    ExamPublished,
+    /// Storage or publish kept failing - the exam was kept for replay
+    ExamDeadLettered,
+    /// The exam could be neither processed nor kept for replay
+    ExamLost,
+    /// An operator queued a dead-lettered or spooled exam again
+    ExamRequeued,
+    /// An operator discarded a dead-lettered or spooled exam
+    ExamDiscarded,
+    /// A stored exam object was deleted at the end of its retention
+    ExamExpired,
+    /// A session of the admin console was opened, used, refreshed or closed
+    ConsoleSession,
+}
+
+impl AuditAction {
+    /// Stable snake_case name, as stored
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AuditAction::Authentication => "authentication",
+            AuditAction::ExamStored => "exam_stored",
+            AuditAction::ExamPublished => "exam_published",
+            AuditAction::ExamDeadLettered => "exam_dead_lettered",
+            AuditAction::ExamLost => "exam_lost",
+            AuditAction::ExamRequeued => "exam_requeued",
+            AuditAction::ExamDiscarded => "exam_discarded",
+            AuditAction::ExamExpired => "exam_expired",
+            AuditAction::ConsoleSession => "console_session",
+        }
+    }
+}
+
+/// One event of the audit trail - fields that do not apply to the action are None
+/// # Arguments
+/// * `occurred_at` - When the action happened
+/// * `action` - The audited action
+/// * `outcome` - `granted`/`denied` for authentications, `stored`, `published`, `dead_lettered`,
+///   `lost`, `requeued`, `discarded` or `deleted` for exams, `login`, `request`, `refresh`, `logout` or
+///   `denied` for console sessions
+/// * `hospital_id` - The authenticated hospital, or the claimed one of a denied authentication
+/// * `exam_type` - The exam type key
+/// * `exam_id` - The exam identifier
+/// * `payload_hash` - Hex SHA256 of the stored object (Parquet record or image)
+/// * `auth_result` - The reason code of a denied authentication, `OK` when granted
+/// * `client_ip` - The client address, as seen behind the load balancer
+/// * `object_path` - The location of the stored object or dead letter
+/// * `message_id` - The Pub/Sub message id of a published notification
+/// * `request_id` - The id of the request the action belongs to, if any
+/// * `session_id` - The public id of the admin console session, if any
+/// * `certificate` - The signed deletion certificate of an expired exam object (JSON)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AuditEvent {
+    pub occurred_at: DateTime<Utc>,
+    pub action: AuditAction,
+    pub outcome: String,
+    pub hospital_id: Option<String>,
+    pub exam_type: Option<String>,
+    pub exam_id: Option<String>,
+    pub payload_hash: Option<String>,
+    pub auth_result: Option<String>,
+    pub client_ip: Option<String>,
+    pub object_path: Option<String>,
+    pub message_id: Option<String>,
+    pub request_id: Option<String>,
+    pub session_id: Option<String>,
+    pub certificate: Option<String>,
+}
+
+impl AuditEvent {
+    /// Event of an authentication decision
+    /// # Arguments
+    /// * `hospital_id` - The authenticated or claimed hospital, empty if none was sent
+    /// * `refusal` - The reason of a denied authentication, None if granted
+    /// * `client_ip` - The client address
+    pub fn authentication(hospital_id: &str, refusal: Option<ReasonCode>, client_ip: &str) -> Self {
+        let mut event = Self::new(AuditAction::Authentication);
+        event.outcome = if refusal.is_some() {
+            "denied"
+        } else {
+            "granted"
+        }
+        .to_string();
+        event.hospital_id = Some(hospital_id.to_string()).filter(|id| !id.is_empty());
+        event.auth_result = Some(refusal.map_or("OK", |reason| reason.as_str()).to_string());
+        event.client_ip = Some(client_ip.to_string());
+        event
+    }
+
+    /// Event of an exam leaving a pipeline stage
+    /// # Arguments
+    /// * `action` - The audited action
+    /// * `exam_type` - The exam type key
+    /// * `exam_id` - The exam identifier
+    /// * `hospital_id` - The hospital that sent the exam
+    pub fn exam(action: AuditAction, exam_type: &str, exam_id: &str, hospital_id: &str) -> Self {
+        let mut event = Self::new(action);
+        event.outcome = match action {
+            AuditAction::ExamStored => "stored",
+            AuditAction::ExamPublished => "published",
+            AuditAction::ExamDeadLettered => "dead_lettered",
+            AuditAction::ExamRequeued => "requeued",
+            AuditAction::ExamDiscarded => "discarded",
+            AuditAction::ExamExpired => "deleted",
+            AuditAction::ExamLost | AuditAction::Authentication | AuditAction::ConsoleSession => {
+                "lost"
+            }
+        }
+        .to_string();
+        event.exam_type = Some(exam_type.to_string());
+        event.exam_id = Some(exam_id.to_string());
+        event.hospital_id = Some(hospital_id.to_string());
+        event
+    }
+
+    /// Event of an admin console session
+    /// # Arguments
+    /// * `outcome` - `login`, `request`, `refresh`, `logout` or `denied`
+    /// * `session_id` - The public id of the session, empty if none was found
+    /// * `client_ip` - The client address
+    pub fn console_session(outcome: &str, session_id: &str, client_ip: &str) -> Self {
+        let mut event = Self::new(AuditAction::ConsoleSession);
+        event.outcome = outcome.to_string();
+        event.session_id = Some(session_id.to_string()).filter(|id| !id.is_empty());
+        event.client_ip = Some(client_ip.to_string());
+        event
+    }
+
+    /// Set the hash of the stored object
+    /// # Arguments
+    /// * `bytes` - The stored object
+    pub fn payload(mut self, bytes: &[u8]) -> Self {
+        self.payload_hash = Some(payload_hash(bytes));
+        self
+    }
+
+    /// Set the location of the stored object or dead letter
+    pub fn object_path(mut self, object_path: impl Into<String>) -> Self {
+        self.object_path = Some(object_path.into());
+        self
+    }
+
+    /// Set the signed deletion certificate of an expired exam object
+    pub fn certificate(mut self, certificate: impl Into<String>) -> Self {
+        self.certificate = Some(certificate.into());
+        self
+    }
+
+    /// Set the Pub/Sub message id of the notification
+    pub fn message_id(mut self, message_id: impl Into<String>) -> Self {
+        self.message_id = Some(message_id.into());
+        self
+    }
+
+    /// Empty event of an action, in the request being served
+    fn new(action: AuditAction) -> Self {
+        Self {
+            occurred_at: Utc::now(),
+            action,
+            outcome: String::new(),
+            hospital_id: None,
+            exam_type: None,
+            exam_id: None,
+            payload_hash: None,
+            auth_result: None,
+            client_ip: None,
+            object_path: None,
+            message_id: None,
+            request_id: current_request_id().filter(|id| !id.is_empty()),
+            session_id: None,
+            certificate: None,
+        }
+    }
+}
+
+// MAIN FUNCTIONS **********************************************************************************
+/// Hex SHA256 of a stored object, as recorded in the trail
+/// # Arguments
+/// * `bytes` - The object
+pub fn payload_hash(bytes: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(bytes))
+}
+
+// TESTS *******************************************************************************************
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Happy path: granted and denied authentications carry their result
+    #[test]
+    fn authentication_events() {
+        let granted = AuditEvent::authentication("h1", None, "10.0.0.1");
+        assert_eq!(granted.outcome, "granted");
+        assert_eq!(granted.auth_result.as_deref(), Some("OK"));
+        let denied = AuditEvent::authentication("", Some(ReasonCode::TokenExpired), "10.0.0.1");
+        assert_eq!(denied.outcome, "denied");
+        assert_eq!(denied.auth_result.as_deref(), Some("TOKEN_EXPIRED"));
+        assert!(denied.hospital_id.is_none());
+    }
+
+    // Happy path: console session events carry the session, denied ones may not have one
+    #[test]
+    fn console_session_events() {
+        let login = AuditEvent::console_session("login", "as_0011223344556677", "10.0.0.1");
+        assert_eq!(login.session_id.as_deref(), Some("as_0011223344556677"));
+        assert_eq!(
+            serde_json::to_value(&login).unwrap()["action"],
+            "console_session"
+        );
+        let denied = AuditEvent::console_session("denied", "", "10.0.0.1");
+        assert_eq!(denied.outcome, "denied");
+        assert!(denied.session_id.is_none());
+    }
+
+    // Happy path: exam events carry the stored object and its hash
+    #[test]
+    fn exam_events() {
+        let event = AuditEvent::exam(AuditAction::ExamStored, "ecg_exam", "ecg_exam/h/p/t", "h")
+            .payload(b"abc")
+            .object_path("gs://bucket/ecg_exam/h/p/t.parquet");
+        assert_eq!(event.outcome, "stored");
+        assert_eq!(
+            event.payload_hash.as_deref(),
+            Some("ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad")
+        );
+        assert_eq!(
+            serde_json::to_value(&event).unwrap()["action"],
+            "exam_stored"
+        );
+    }
+
+    // Borderline: events keep their shape and round-trip, as stored and returned by the trail
+    #[test]
+    fn event_contract() {
+        let mut event = AuditEvent::exam(
+            AuditAction::ExamPublished,
+            "ecg_exam",
+            "ecg_exam/h1/p1/2026-10-01T120000.000Z",
+            "h1",
+        )
+        .message_id("1234567890");
+        event.occurred_at = "2026-10-01T12:00:00Z".parse().unwrap();
+        insta::assert_json_snapshot!(event, @r#"
+        {
+          "occurred_at": "2026-10-01T12:00:00Z",
+          "action": "exam_published",
+          "outcome": "published",
+          "hospital_id": "h1",
+          "exam_type": "ecg_exam",
+          "exam_id": "ecg_exam/h1/p1/2026-10-01T120000.000Z",
+          "payload_hash": null,
+          "auth_result": null,
+          "client_ip": null,
+          "object_path": null,
+          "message_id": "1234567890",
+          "request_id": null,
+          "session_id": null,
+          "certificate": null
+        }
+        "#);
+        let back: AuditEvent =
+            serde_json::from_value(serde_json::to_value(&event).unwrap()).unwrap();
+        assert_eq!(back, event);
+    }
+}
@@ -0,0 +1,386 @@
+// Imports *****************************************************************************************
+// External Crates
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
+use log::{info, warn};
+use serde::Deserialize;
+use sqlx::postgres::PgRow;
+use sqlx::{PgPool, Row};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc;
+
+// Internal Modules
+use crate::audit::audit_event::{AuditAction, AuditEvent};
+use crate::telemetry::metrics::record_audit_trail;
+use crate::utils::external_call::{Dependency, ExternalCall};
+
+// Constants ***************************************************************************************
+/// Most events waiting to be written before new ones are dropped to the fallback log
+const AUDIT_TRAIL_CAPACITY: usize = 4_096;
+/// Retries of the insert of an event
+const WRITE_RETRIES: u32 = 3;
+/// Events returned by a query without limit
+const DEFAULT_QUERY_LIMIT: i64 = 100;
+/// Most events returned by a query
+const MAX_QUERY_LIMIT: i64 = 1_000;
+/// Log target of the events that could not be written - kept apart from the `audit` lines
+const FALLBACK_TARGET: &str = "audit_trail";
+/// Interval of the checks of a flush
+const FLUSH_POLL: Duration = Duration::from_millis(50);
+/// Actions settling the notification of a stored exam
+const SETTLED_ACTIONS: [AuditAction; 5] = [
+    AuditAction::ExamPublished,
+    AuditAction::ExamDeadLettered,
+    AuditAction::ExamLost,
+    AuditAction::ExamDiscarded,
+    AuditAction::ExamExpired,
+];
+
+// Structs *****************************************************************************************
+/// Filters of an audit trail query - every filter is optional
+/// # Arguments
+/// * `hospital_id` - Events of this hospital
+/// * `exam_id` - Events of this exam
+/// * `action` - Events of this action
+/// * `session_id` - Events of this admin console session
+/// * `from` - Events at or after this instant (RFC 3339)
+/// * `to` - Events before this instant (RFC 3339)
+/// * `limit` - Most events returned, newest first (default DEFAULT_QUERY_LIMIT, capped at
+///   MAX_QUERY_LIMIT)
+#[derive(Debug, Default, Deserialize)]
+pub struct AuditQuery {
+    pub hospital_id: Option<String>,
+    pub exam_id: Option<String>,
+    pub action: Option<AuditAction>,
+    pub session_id: Option<String>,
+    pub from: Option<DateTime<Utc>>,
+    pub to: Option<DateTime<Utc>>,
+    pub limit: Option<i64>,
+}
+
+/// Events of one action for one hospital and exam type
+/// # Arguments
+/// * `hospital_id` - The hospital of the events
+/// * `exam_type` - The exam type key
+/// * `action` - The audited action
+/// * `count` - Events in the period
+#[derive(Debug, Clone, PartialEq)]
+pub struct ActionCount {
+    pub hospital_id: String,
+    pub exam_type: String,
+    pub action: String,
+    pub count: u64,
+}
+
+// Global variables ********************************************************************************
+/// Queue of the events to write, set once the trail is started
+static AUDIT_TRAIL: OnceLock<mpsc::Sender<AuditEvent>> = OnceLock::new();
+
+/// Events queued and not yet written (or logged as fallback)
+static PENDING_EVENTS: AtomicUsize = AtomicUsize::new(0);
+
+// MAIN FUNCTIONS **********************************************************************************
+/// Start writing the audit events to the append-only `audit_events` table
+/// - An event that cannot be written is logged in full under the `audit_trail` target, so the
+///   log sink keeps it
+/// # Arguments
+/// * `pool` - The shared database connection pool
+pub fn start_audit_trail(pool: PgPool) {
+    let (sender, mut receiver) = mpsc::channel::<AuditEvent>(AUDIT_TRAIL_CAPACITY);
+    if AUDIT_TRAIL.set(sender).is_err() {
+        return;
+    }
+    info!("Audit trail: writing to audit_events");
+    actix_web::rt::spawn(async move {
+        while let Some(event) = receiver.recv().await {
+            match insert_event(&pool, &event).await {
+                Ok(()) => record_audit_trail("written"),
+                Err(e) => {
+                    record_audit_trail("failed");
+                    warn!("Audit event could not be written: {e}");
+                    log_fallback(&event);
+                }
+            }
+            PENDING_EVENTS.fetch_sub(1, Ordering::AcqRel);
+        }
+    });
+}
+
+/// Queue an audit event for the trail - never blocks the request
+/// # Arguments
+/// * `event` - The event, ignored when the trail is not started
+pub fn record_audit(event: AuditEvent) {
+    let Some(sender) = AUDIT_TRAIL.get() else {
+        return;
+    };
+    PENDING_EVENTS.fetch_add(1, Ordering::AcqRel);
+    if let Err(e) = sender.try_send(event) {
+        PENDING_EVENTS.fetch_sub(1, Ordering::AcqRel);
+        record_audit_trail("dropped");
+        let (mpsc::error::TrySendError::Full(event) | mpsc::error::TrySendError::Closed(event)) = e;
+        log_fallback(&event);
+    }
+}
+
+/// Query the audit trail
+/// # Arguments
+/// * `pool` - The shared database connection pool
+/// * `query` - The filters
+/// # Returns
+/// * The matching events, newest first
+/// # Errors
+/// * Returns an error if the database cannot be queried
+pub async fn query_audit_events(pool: &PgPool, query: &AuditQuery) -> Result<Vec<AuditEvent>> {
+    let limit = query
+        .limit
+        .unwrap_or(DEFAULT_QUERY_LIMIT)
+        .clamp(1, MAX_QUERY_LIMIT);
+    let rows = ExternalCall::new(Dependency::Postgres, "audit_query")
+        .run(|| {
+            sqlx::query(
+                r#"
+                SELECT (extract(epoch FROM occurred_at) * 1000000)::bigint AS occurred_at_us,
+                       action, outcome, hospital_id, exam_type, exam_id, payload_hash,
+                       auth_result, client_ip, object_path, message_id, request_id, session_id,
+                       certificate
+                FROM audit_events
+                WHERE ($1::text IS NULL OR hospital_id = $1)
+                  AND ($2::text IS NULL OR exam_id = $2)
+                  AND ($3::text IS NULL OR action = $3)
+                  AND ($4::float8 IS NULL OR occurred_at >= to_timestamp($4::float8))
+                  AND ($5::float8 IS NULL OR occurred_at < to_timestamp($5::float8))
+                  AND ($6::text IS NULL OR session_id = $6)
+                ORDER BY occurred_at DESC, id DESC
+                LIMIT $7
+                "#,
+            )
+            .bind(query.hospital_id.as_deref())
+            .bind(query.exam_id.as_deref())
+            .bind(query.action.map(|action| action.as_str()))
+            .bind(query.from.map(epoch_seconds))
+            .bind(query.to.map(epoch_seconds))
+            .bind(query.session_id.as_deref())
+            .bind(limit)
+            .fetch_all(pool)
+        })
+        .await?;
+    rows.iter().map(event_from_row).collect()
+}
+
+/// Exams stored whose notification was never settled: no `exam_published`, `exam_dead_lettered`,
+/// `exam_lost`, `exam_discarded` nor `exam_expired` event followed their `exam_stored` event - what
+/// a crash between the answer and the ack of an `after_storage` exam leaves behind
+/// # Arguments
+/// * `pool` - The shared database connection pool
+/// * `from` - Exams stored at or after this instant
+/// * `to` - Exams stored before this instant - leave the notifications in flight time to settle
+/// * `limit` - Most events returned, oldest first (capped at MAX_QUERY_LIMIT)
+/// # Returns
+/// * The `exam_stored` events of the unsettled exams
+/// # Errors
+/// * Returns an error if the database cannot be queried
+pub async fn unpublished_exams(
+    pool: &PgPool,
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+    limit: i64,
+) -> Result<Vec<AuditEvent>> {
+    let settled: Vec<&str> = SETTLED_ACTIONS.iter().map(|a| a.as_str()).collect();
+    let rows = ExternalCall::new(Dependency::Postgres, "audit_unpublished")
+        .timeout(Duration::from_secs(60))
+        .run(|| {
+            sqlx::query(
+                r#"
+                SELECT (extract(epoch FROM s.occurred_at) * 1000000)::bigint AS occurred_at_us,
+                       s.action, s.outcome, s.hospital_id, s.exam_type, s.exam_id, s.payload_hash,
+                       s.auth_result, s.client_ip, s.object_path, s.message_id, s.request_id,
+                       s.session_id, s.certificate
+                FROM audit_events s
+                WHERE s.action = $1
+                  AND s.occurred_at >= to_timestamp($2::float8)
+                  AND s.occurred_at < to_timestamp($3::float8)
+                  AND NOT EXISTS (
+                      SELECT 1 FROM audit_events e
+                      WHERE e.exam_id = s.exam_id AND e.action = ANY($4)
+                  )
+                ORDER BY s.occurred_at, s.id
+                LIMIT $5
+                "#,
+            )
+            .bind(AuditAction::ExamStored.as_str())
+            .bind(epoch_seconds(from))
+            .bind(epoch_seconds(to))
+            .bind(&settled)
+            .bind(limit.clamp(1, MAX_QUERY_LIMIT))
+            .fetch_all(pool)
+        })
+        .await?;
+    rows.iter().map(event_from_row).collect()
+}
+
+/// Events of some actions of a hospital over a period, per exam type and action
+/// # Arguments
+/// * `pool` - The shared database connection pool
+/// * `hospital_id` - The hospital
+/// * `actions` - The actions counted
+/// * `from` - Events at or after this instant
+/// * `to` - Events before this instant
+/// # Errors
+/// * Returns an error if the database cannot be queried
+pub async fn count_actions(
+    pool: &PgPool,
+    hospital_id: &str,
+    actions: &[AuditAction],
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+) -> Result<Vec<ActionCount>> {
+    let actions: Vec<&str> = actions.iter().map(|a| a.as_str()).collect();
+    let rows = ExternalCall::new(Dependency::Postgres, "audit_count")
+        .run(|| {
+            sqlx::query(
+                r#"
+                SELECT hospital_id, exam_type, action, count(*) AS events
+                FROM audit_events
+                WHERE hospital_id = $1
+                  AND action = ANY($2)
+                  AND exam_type IS NOT NULL
+                  AND occurred_at >= to_timestamp($3::float8)
+                  AND occurred_at < to_timestamp($4::float8)
+                GROUP BY hospital_id, exam_type, action
+                "#,
+            )
+            .bind(hospital_id)
+            .bind(&actions)
+            .bind(epoch_seconds(from))
+            .bind(epoch_seconds(to))
+            .fetch_all(pool)
+        })
+        .await?;
+    rows.iter()
+        .map(|row| {
+            Ok(ActionCount {
+                hospital_id: row.try_get("hospital_id")?,
+                exam_type: row.try_get("exam_type")?,
+                action: row.try_get("action")?,
+                count: u64::try_from(row.try_get::<i64, _>("events")?)?,
+            })
+        })
+        .collect()
+}
+
+/// Wait for the queued events to be written - for short-lived processes (the operator CLI), whose
+/// events would otherwise be lost at exit
+/// # Arguments
+/// * `deadline` - The longest wait
+/// # Returns
+/// * The number of events still not written at the deadline
+pub async fn flush_audit_trail(deadline: Duration) -> usize {
+    let started = Instant::now();
+    while PENDING_EVENTS.load(Ordering::Acquire) > 0 && started.elapsed() < deadline {
+        tokio::time::sleep(FLUSH_POLL).await;
+    }
+    PENDING_EVENTS.load(Ordering::Acquire)
+}
+
+// SUPPORT FUNCTIONS *******************************************************************************
+/// Insert one event in the trail
+async fn insert_event(pool: &PgPool, event: &AuditEvent) -> Result<()> {
+    ExternalCall::new(Dependency::Postgres, "audit_insert")
+        .retries(WRITE_RETRIES)
+        .run(|| {
+            sqlx::query(
+                r#"
+                INSERT INTO audit_events
+                    (occurred_at, action, outcome, hospital_id, exam_type, exam_id, payload_hash,
+                     auth_result, client_ip, object_path, message_id, request_id, session_id,
+                     certificate)
+                VALUES (to_timestamp($1::float8), $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12,
+                        $13, $14)
+                "#,
+            )
+            .bind(epoch_seconds(event.occurred_at))
+            .bind(event.action.as_str())
+            .bind(&event.outcome)
+            .bind(event.hospital_id.as_deref())
+            .bind(event.exam_type.as_deref())
+            .bind(event.exam_id.as_deref())
+            .bind(event.payload_hash.as_deref())
+            .bind(event.auth_result.as_deref())
+            .bind(event.client_ip.as_deref())
+            .bind(event.object_path.as_deref())
+            .bind(event.message_id.as_deref())
+            .bind(event.request_id.as_deref())
+            .bind(event.session_id.as_deref())
+            .bind(event.certificate.as_deref())
+            .execute(pool)
+        })
+        .await?;
+    Ok(())
+}
+
+/// Event of a row of the trail
+fn event_from_row(row: &PgRow) -> Result<AuditEvent> {
+    let micros: i64 = row.try_get("occurred_at_us")?;
+    let action: String = row.try_get("action")?;
+    Ok(AuditEvent {
+        occurred_at: DateTime::from_timestamp_micros(micros)
+            .ok_or_else(|| anyhow!("Audit event timestamp out of range: {micros}"))?,
+        action: serde_json::from_value(serde_json::Value::String(action))?,
+        outcome: row.try_get("outcome")?,
+        hospital_id: row.try_get("hospital_id")?,
+        exam_type: row.try_get("exam_type")?,
+        exam_id: row.try_get("exam_id")?,
+        payload_hash: row.try_get("payload_hash")?,
+        auth_result: row.try_get("auth_result")?,
+        client_ip: row.try_get("client_ip")?,
+        object_path: row.try_get("object_path")?,
+        message_id: row.try_get("message_id")?,
+        request_id: row.try_get("request_id")?,
+        session_id: row.try_get("session_id")?,
+        certificate: row.try_get("certificate")?,
+    })
+}
+
+/// Seconds since the epoch, with microsecond precision - timestamps cross to Postgres as numbers
+fn epoch_seconds(at: DateTime<Utc>) -> f64 {
+    at.timestamp_micros() as f64 / 1_000_000.0
+}
+
+/// Log an event that did not reach the trail, in full
+fn log_fallback(event: &AuditEvent) {
+    match serde_json::to_string(event) {
+        Ok(json) => warn!(target: FALLBACK_TARGET, "{json}"),
+        Err(e) => warn!(target: FALLBACK_TARGET, "Audit event not serialized: {e}"),
+    }
+}
+
+// TESTS *******************************************************************************************
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Happy path: query filters deserialize from a query string
+    #[test]
+    fn query_deserialized() {
+        let query = actix_web::web::Query::<AuditQuery>::from_query(
+            "hospital_id=h1&action=exam_published&from=2026-10-01T00:00:00Z&limit=10",
+        )
+        .unwrap()
+        .into_inner();
+        assert_eq!(query.hospital_id.as_deref(), Some("h1"));
+        assert_eq!(query.action, Some(AuditAction::ExamPublished));
+        assert_eq!(query.from.map(epoch_seconds), Some(1_790_812_800.0));
+        assert_eq!(query.limit, Some(10));
+    }
+
+    // Borderline: timestamps keep their microseconds across the epoch conversion
+    #[test]
+    fn epoch_seconds_keeps_micros() {
+        let at = DateTime::from_timestamp_micros(1_790_812_800_123_456).unwrap();
+        let back =
+            DateTime::from_timestamp_micros((epoch_seconds(at) * 1_000_000.0).round() as i64);
+        assert_eq!(back, Some(at));
+    }
+}
@@ -0,0 +1,179 @@
+// Imports *****************************************************************************************
+// External Crates
+use actix_web::body::{EitherBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::middleware::Next;
+use actix_web::{web, Error, HttpMessage, HttpRequest, ResponseError};
+use anyhow::{anyhow, Result};
+use log::warn;
+use std::sync::Arc;
+use subtle::ConstantTimeEq;
+
+// Internal Modules
+use crate::authentication::console_session::ConsoleSession;
+use crate::config::settings::{AuthSettings, Settings};
+use crate::routes::registry::{route_of, RouteAuth};
+use crate::telemetry::metrics::record_auth_failure;
+use crate::utils::api_error::ApiError;
+use crate::utils::reason_code::ReasonCode;
+
+// MAIN FUNCTION ***********************************************************************************
+/// Authenticate an operator calling one of the internal endpoints
+/// # Arguments
+/// * `req` - The HTTP request containing the 'admin_key' header, or the cookie of an admin console
+///   session verified by `console_session_middleware`
+/// # Returns
+/// * `Result<()>` - Ok(()) if the key matches ADMIN_API_KEY or the session is valid, Err otherwise
+pub fn authenticate_admin(req: &HttpRequest) -> Result<()> {
+    // STEP 1: A console session was verified by the middleware, with its origin and CSRF token
+    if req.extensions().get::<ConsoleSession>().is_some() {
+        return Ok(());
+    }
+
+    // STEP 2: Get the expected key - internal endpoints are disabled when it is not set
+    let settings = req.app_data::<web::Data<Arc<Settings>>>();
+    let expected = admin_api_key(settings.map(|settings| &settings.auth));
+
+    // STEP 3: Compare against the provided header - failures are counted for alerting
+    let provided = req
+        .headers()
+        .get("admin_key")
+        .and_then(|v| v.to_str().ok())
+        .ok_or_else(|| anyhow!("Authentication failed: Missing valid headers"));
+    expected
+        .and_then(|expected| check_admin_key(provided?, &expected))
+        .inspect_err(|e| record_auth_failure("admin", ReasonCode::of_auth_failure(&e.to_string())))
+}
+
+/// Refuse the requests to an operator route (`auth: Operator`) without the admin key or an admin
+/// console session, before they reach the handler - must run inside `console_session_middleware`
+/// # Arguments
+/// * `req` - The incoming request
+/// * `next` - The rest of the service chain
+/// # Returns
+/// * The response of the handler, or 401 if the operator could not be authenticated
+pub async fn operator_auth_middleware(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<EitherBody<impl MessageBody>>, Error> {
+    let operator_route =
+        route_of(req.path()).is_some_and(|route| route.auth == RouteAuth::Operator);
+    if operator_route {
+        if let Err(e) = authenticate_admin(req.request()) {
+            let client_ip = req
+                .connection_info()
+                .realip_remote_addr()
+                .unwrap_or("unknown")
+                .to_string();
+            warn!(target: "audit", "operator_denied path={} client_ip={client_ip} reason={e}", req.path());
+            let response = ApiError::Unauthorized(e.to_string()).error_response();
+            return Ok(req.into_response(response).map_into_right_body());
+        }
+    }
+    Ok(next.call(req).await?.map_into_left_body())
+}
+
+/// Authenticate an operator logging in to the admin console with the admin key
+/// # Arguments
+/// * `provided` - The key typed by the operator
+/// * `auth` - The authentication settings, holding ADMIN_API_KEY
+/// # Returns
+/// * `Result<()>` - Ok(()) if the key matches ADMIN_API_KEY, Err otherwise
+pub fn authenticate_admin_key(provided: &str, auth: &AuthSettings) -> Result<()> {
+    admin_api_key(Some(auth))
+        .and_then(|expected| check_admin_key(provided, &expected))
+        .inspect_err(|e| record_auth_failure("admin", ReasonCode::of_auth_failure(&e.to_string())))
+}
+
+// SUPPORTING FUNCTIONS ****************************************************************************
+/// The configured ADMIN_API_KEY
+/// # Arguments
+/// * `auth` - The authentication settings, None if the application has none
+/// # Errors
+/// * Returns an error if it is not set - internal endpoints are then disabled
+fn admin_api_key(auth: Option<&AuthSettings>) -> Result<String> {
+    auth.and_then(|auth| auth.admin_api_key.as_ref())
+        .map(|key| key.expose().to_string())
+        .ok_or_else(|| anyhow!("Authentication failed: Internal endpoints are disabled"))
+}
+
+/// Compare the provided admin key against the expected one, in constant time
+/// # Arguments
+/// * `provided` - The key sent by the caller
+/// * `expected` - The configured admin key
+/// # Returns
+/// * `Result<()>` - Ok(()) if the keys match and are not empty, Err otherwise
+fn check_admin_key(provided: &str, expected: &str) -> Result<()> {
+    if expected.is_empty() || !bool::from(provided.as_bytes().ct_eq(expected.as_bytes())) {
+        return Err(anyhow!("Authentication failed: Invalid credentials"));
+    }
+    Ok(())
+}
+
+// TESTS *******************************************************************************************
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn admin_key_matches() {
+        assert!(check_admin_key("k1", "k1").is_ok());
+    }
+
+    #[test]
+    fn admin_key_mismatch_or_empty() {
+        assert!(check_admin_key("k1", "k2").is_err());
+        assert!(check_admin_key("", "").is_err());
+    }
+
+    // Security: an operator route is refused without credentials before its handler runs, the
+    // console login (key in its body) is not
+    #[actix_web::test]
+    async fn operator_routes_enforced() {
+        use actix_web::middleware::from_fn;
+        use actix_web::{get, post, test, web, App, HttpResponse};
+
+        #[get("/metrics")]
+        async fn metrics() -> HttpResponse {
+            HttpResponse::Ok().finish()
+        }
+        #[post("/console/login")]
+        async fn login() -> HttpResponse {
+            HttpResponse::Ok().finish()
+        }
+        let app = test::init_service(
+            App::new().service(
+                web::scope("/internal/v1")
+                    .wrap(from_fn(operator_auth_middleware))
+                    .service(metrics)
+                    .service(login),
+            ),
+        )
+        .await;
+        let refused = test::call_service(
+            &app,
+            test::TestRequest::get()
+                .uri("/internal/v1/metrics")
+                .to_request(),
+        )
+        .await;
+        assert_eq!(refused.status(), 401);
+        let wrong_key = test::call_service(
+            &app,
+            test::TestRequest::get()
+                .uri("/internal/v1/metrics")
+                .insert_header(("admin_key", "not-the-key"))
+                .to_request(),
+        )
+        .await;
+        assert_eq!(wrong_key.status(), 401);
+        let logged_in = test::call_service(
+            &app,
+            test::TestRequest::post()
+                .uri("/internal/v1/console/login")
+                .to_request(),
+        )
+        .await;
+        assert_eq!(logged_in.status(), 200);
+    }
+}
@@ -1,61 +1,340 @@
 // Imports *****************************************************************************************
 // External Crates
-use actix_web::HttpRequest;
+use actix_web::dev::Payload;
+use actix_web::{web, FromRequest, HttpMessage, HttpRequest};
 use anyhow::{anyhow, Result};
-use sqlx::postgres::PgPoolOptions;
-use sqlx::{Pool, Postgres, Row};
+use moka::future::Cache;
+use sha2::{Digest, Sha256};
+use sqlx::postgres::{PgPoolOptions, PgRow};
+use sqlx::{PgPool, Pool, Postgres, Row};
+use std::future::{ready, Ready};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::LazyLock;
+use std::time::Duration;
 
 // Internal Modules
-use crate::utils::get_headers::get_headers;
+use crate::authentication::jwt::{jwt_settings, verify_bearer_token, JwtSettings};
+use crate::config::settings::{AuthSettings, DatabaseSettings, DEFAULT_AUTH_CACHE_TTL_S};
+use crate::models::models_consent::ConsentScope;
+use crate::models::models_ids::canonical_hex;
+use crate::models::models_publish_modes::PublishMode;
+use crate::models::models_rate_tiers::RateTier;
+use crate::models::models_size_tiers::SizeTier;
+use crate::services::service_hospital_groups::{record_group_member, HospitalGroup};
+use crate::services::service_hospital_registry::MAX_ACTIVE_KEYS;
+use crate::utils::api_error::ApiError;
+use crate::utils::external_call::{Dependency, ExternalCall};
+use crate::utils::get_headers::{bearer_token, get_headers, on_behalf_of};
+
+// Constants ***************************************************************************************
+/// Most credential pairs kept in the cache
+const AUTH_CACHE_CAPACITY: u64 = 10_000;
+/// bcrypt cost of the hash verified for unknown hospitals
+const DUMMY_HASH_COST: u32 = 12;
+
+// Structs *****************************************************************************************
+/// Hospital authenticated by the middleware of the `/v1` scope, extracted by the handlers
+/// # Arguments
+/// * `hospital_id` - The hospital the credential belongs to - the payload must match it
+/// * `consent_scope` - The data-sharing consent of the hospital
+/// * `size_tier` - The payload size tier of the hospital, setting its body limit
+/// * `monthly_quota` - Exams accepted per month for the hospital, None if unlimited
+/// * `group` - The hospital group of a clinic - its quota also applies to the clinic
+/// * `allowed_exam_types` - The exam types the hospital may submit, None if all
+/// * `rate_tier` - The rate limit tier of the hospital, scaling the ingest rate limit
+/// * `publish_mode` - Whether the exams of the hospital are answered before their notification
+/// * `paused` - Whether the exams of the hospital are refused until an operator resumes it
+#[derive(Debug, Clone, PartialEq)]
+pub struct AuthenticatedHospital {
+    pub hospital_id: String,
+    pub consent_scope: ConsentScope,
+    pub size_tier: SizeTier,
+    pub monthly_quota: Option<u64>,
+    pub group: Option<HospitalGroup>,
+    pub allowed_exam_types: Option<Vec<String>>,
+    pub rate_tier: RateTier,
+    pub publish_mode: PublishMode,
+    pub paused: bool,
+}
+
+impl AuthenticatedHospital {
+    /// Check that a payload was sent by the hospital it claims to come from
+    /// # Arguments
+    /// * `payload_hospital_id` - The `hospital_id` of the payload
+    /// # Errors
+    /// * Returns `ApiError::Forbidden` if the payload belongs to another hospital
+    pub fn check_payload(&self, payload_hospital_id: &str) -> Result<(), ApiError> {
+        if payload_hospital_id != canonical_hex(&self.hospital_id) {
+            return Err(ApiError::Forbidden(
+                "Payload hospital_id does not match the credential".to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Check that the hospital, or the group it submits under, is not paused
+    /// # Errors
+    /// * Returns `ApiError::Forbidden` while an operator keeps the hospital paused
+    pub fn check_not_paused(&self) -> Result<(), ApiError> {
+        if self.paused {
+            return Err(ApiError::Forbidden(
+                "Hospital is paused - its exams are refused until it is resumed".to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Whether the hospital may submit exams of a type
+    /// # Arguments
+    /// * `exam_type` - The exam type of the submission route
+    pub fn allows_exam_type(&self, exam_type: &str) -> bool {
+        self.allowed_exam_types
+            .as_ref()
+            .is_none_or(|types| types.iter().any(|allowed| allowed == exam_type))
+    }
+}
+
+impl FromRequest for AuthenticatedHospital {
+    type Error = ApiError;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    /// The hospital stored in the request by the authentication middleware
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        ready(
+            req.extensions()
+                .get::<AuthenticatedHospital>()
+                .cloned()
+                .ok_or_else(|| {
+                    ApiError::Unauthorized("Authentication failed: Missing valid headers".into())
+                }),
+        )
+    }
+}
+
+// Global variables ********************************************************************************
+/// Lifetime of the cached credentials and delegations, in seconds (AUTH_CACHE_TTL_S)
+static AUTH_CACHE_TTL_S: AtomicU64 = AtomicU64::new(DEFAULT_AUTH_CACHE_TTL_S);
+
+/// Validated credentials, keyed by hospital id and the SHA256 of the key - only successful
+/// lookups are cached, and the entries of a hospital are forgotten when its registry entry or keys
+/// change (registry notifications), at the latest after AUTH_CACHE_TTL_S
+static AUTH_CACHE: LazyLock<Cache<(String, String), AuthenticatedHospital>> =
+    LazyLock::new(auth_cache);
+
+/// Clinics a group may submit for, keyed by group and clinic id - only existing delegations are
+/// cached, and forgotten like the credentials
+static DELEGATION_CACHE: LazyLock<Cache<(String, String), AuthenticatedHospital>> =
+    LazyLock::new(auth_cache);
+
+/// Hash verified when the hospital is unknown, so unknown and known hospitals take as long
+static DUMMY_HASH: LazyLock<String> =
+    LazyLock::new(|| bcrypt::hash("unknown-hospital", DUMMY_HASH_COST).unwrap_or_default());
 
 // MAIN FUNCTION ***********************************************************************************
-/// Authenticate hospital based on headers in the HTTP request
+/// Set the lifetime of the cached credentials - called once at startup, before the first
+/// authentication builds the caches
+/// # Arguments
+/// * `auth` - The authentication settings
+pub fn init_auth_cache(auth: &AuthSettings) {
+    AUTH_CACHE_TTL_S.store(auth.cache_ttl_s, Ordering::Relaxed);
+}
+
+/// Authenticate hospital based on headers in the HTTP request - its key, or its bearer token when
+/// HOSPITAL_AUTH_MODE is `jwt`
 /// # Arguments
 /// * `req` - The HTTP request containing headers for authentication
+/// * `pool` - The shared database connection pool
 /// # Returns
-/// * `Result<()>` - Ok(()) if authentication is successful, Err otherwise
-pub async fn authenticate_hospital(req: HttpRequest) -> Result<()> {
+/// * `Result<AuthenticatedHospital>` - The hospital and its data-sharing consent if authentication
+///   is successful, Err otherwise
+pub async fn authenticate_hospital(
+    req: HttpRequest,
+    pool: &PgPool,
+) -> Result<AuthenticatedHospital> {
+    // STEP 1-4: The hospital of the key or of the bearer token
+    let hospital = match jwt_settings() {
+        None => authenticate_key(&req, pool).await?,
+        Some(jwt) => authenticate_token(&req, jwt, pool).await?,
+    };
+
+    // STEP 5: A group key may submit for one of its clinics, through an explicit delegation
+    let hospital = match on_behalf_of(&req) {
+        Some(clinic_id) if canonical_hex(&clinic_id) != canonical_hex(&hospital.hospital_id) => {
+            resolve_delegation(&hospital, &clinic_id, pool).await?
+        }
+        _ => hospital,
+    };
+    record_group_member(&hospital);
+
+    // If all checks pass, return the hospital with its consent and tier recorded in the registry
+    Ok(hospital)
+}
+
+/// Create the database connection pool shared by all requests - called once at startup
+/// # Arguments
+/// * `database` - The database settings, validated at startup
+/// # Returns
+/// * `Result<PgPool>` - A connection pool to the Postgres database
+pub async fn connect_to_database(database: &DatabaseSettings) -> Result<PgPool> {
+    // STEP 1: Create the database connection string
+    let database_url = database.url();
+
+    // STEP 2: Create the connection pool
+    let pool = ExternalCall::new(Dependency::Postgres, "connect")
+        .retries(1)
+        .run(|| {
+            PgPoolOptions::new()
+                .max_connections(database.max_connections)
+                .connect(&database_url)
+        })
+        .await?;
+
+    Ok(pool)
+}
+
+/// Read again whether a hospital, or the group it submits under, is paused - for the sessions
+/// outliving the authentication of their request
+/// # Arguments
+/// * `hospital` - The hospital, as authenticated when its session opened
+/// * `pool` - The shared database connection pool
+/// # Errors
+/// * Returns an error if Postgres cannot be reached
+pub async fn paused_now(hospital: &AuthenticatedHospital, pool: &PgPool) -> Result<bool> {
+    let group_id = hospital.group.as_ref().map(|group| group.group_id.as_str());
+    let row = ExternalCall::new(Dependency::Postgres, "hospital_paused")
+        .run(|| {
+            sqlx::query(
+                r#"
+                SELECT COALESCE(bool_or(paused_at IS NOT NULL), false) AS paused
+                FROM hospital_credentials
+                WHERE hospital_id = $1 OR hospital_id = $2
+                "#,
+            )
+            .bind(&hospital.hospital_id)
+            .bind(group_id)
+            .fetch_one(pool)
+        })
+        .await?;
+    Ok(row.try_get("paused")?)
+}
+
+/// Forget the credentials and delegations validated by this instance - called when registry
+/// notifications may have been missed
+pub fn forget_validated_credentials() {
+    AUTH_CACHE.invalidate_all();
+    DELEGATION_CACHE.invalidate_all();
+}
+
+/// Forget the cached entries of a hospital whose registry entry, keys or delegations changed -
+/// with the clinics of a group, which carry its quota
+/// # Arguments
+/// * `hospital_id` - The hospital, as registered
+pub fn forget_hospital(hospital_id: &str) {
+    let id = hospital_id.to_string();
+    let credentials = AUTH_CACHE.invalidate_entries_if(move |(cached_id, _), hospital| {
+        *cached_id == id
+            || hospital
+                .group
+                .as_ref()
+                .is_some_and(|group| group.group_id == id)
+    });
+    let id = hospital_id.to_string();
+    let delegations = DELEGATION_CACHE
+        .invalidate_entries_if(move |(group_id, clinic_id), _| *group_id == id || *clinic_id == id);
+    if credentials.is_err() || delegations.is_err() {
+        forget_validated_credentials();
+    }
+}
+
+// SUPPORTING FUNCTIONS ****************************************************************************
+/// Authenticate a hospital by its `hospital_id` and `hospital_key` headers
+/// # Arguments
+/// * `req` - The HTTP request
+/// * `pool` - The database connection pool
+/// # Errors
+/// * Returns an error if a header is missing or the key is invalid
+async fn authenticate_key(req: &HttpRequest, pool: &PgPool) -> Result<AuthenticatedHospital> {
     // STEP 1: Extract headers
-    let (hospital_id, hospital_key) = get_headers(req)?;
+    let (hospital_id, hospital_key) = get_headers(req.clone())?;
 
     // STEP 2: Validate headers exist
     if hospital_id.is_empty() || hospital_key.is_empty() {
         return Err(anyhow!("Authentication failed: Missing valid headers"));
     }
 
-    // STEP 3: Validate hospital credentials against database // TODO: check GCP connection
-    let pool = connect_to_database().await?;
-    validate_hospital_credentials(&hospital_id, &hospital_key, &pool).await?;
+    // STEP 3: Serve recently validated credentials from the cache
+    let cache_key = auth_cache_key(&hospital_id, &hospital_key);
 
-    // If all checks pass, return Ok
-    Ok(())
+    // STEP 4: Validate hospital credentials against database
+    match AUTH_CACHE.get(&cache_key).await {
+        Some(hospital) => Ok(hospital),
+        None => {
+            let hospital = validate_hospital_credentials(&hospital_id, hospital_key, pool).await?;
+            AUTH_CACHE.insert(cache_key, hospital.clone()).await;
+            Ok(hospital)
+        }
+    }
 }
 
-// SUPPORTING FUNCTIONS ****************************************************************************authenticate_hospital
-/// Function to connect to the database and return a connection object (pool)
-/// # Returns
-/// * `Result<Pool<Postgres>>` - A connection pool to the Postgres database
-async fn connect_to_database() -> Result<Pool<Postgres>> {
-    // STEP 1: Get database connection parameters from environment variables
-    let db_user = std::env::var("DB_USER")?;
-    let db_pass = std::env::var("DB_PASSWORD")?;
-    let db_host = std::env::var("DB_HOST")?;
-    let db_port = std::env::var("DB_PORT")?;
-    let db_name = std::env::var("DB_NAME")?;
-
-    // STEP 2: Create the database connection string
-    let database_url = format!(
-        "postgres://{}:{}@{}:{}/{}",
-        db_user, db_pass, db_host, db_port, db_name
-    );
-
-    // STEP 3: Create the connection pool
-    let pool = PgPoolOptions::new()
-        .max_connections(5)
-        .connect(&database_url)
-        .await?;
+/// Authenticate a hospital by the bearer token of its identity provider - the token is verified on
+/// every request, only the registry entry of its `sub` is cached
+/// # Arguments
+/// * `req` - The HTTP request
+/// * `jwt` - The bearer token settings
+/// * `pool` - The database connection pool
+/// # Errors
+/// * Returns `ApiError::InvalidToken` if the token is refused, an error if it is missing or the
+///   hospital is not in the registry
+async fn authenticate_token(
+    req: &HttpRequest,
+    jwt: &JwtSettings,
+    pool: &PgPool,
+) -> Result<AuthenticatedHospital> {
+    // STEP 1: Extract the bearer token
+    let token =
+        bearer_token(req).ok_or_else(|| anyhow!("Authentication failed: Missing bearer token"))?;
 
-    Ok(pool)
+    // STEP 2: Verify it against the JWKS of the identity provider
+    let claims = verify_bearer_token(&token, jwt).await?;
+
+    // STEP 3: Serve recently read registry entries from the cache
+    let cache_key = auth_cache_key(&claims.sub, &token);
+    if let Some(hospital) = AUTH_CACHE.get(&cache_key).await {
+        return Ok(hospital);
+    }
+
+    // STEP 4: Read the registry entry of the hospital
+    let hospital = registered_hospital(&claims.sub, pool).await?;
+    AUTH_CACHE.insert(cache_key, hospital.clone()).await;
+    Ok(hospital)
+}
+
+/// Cache a validated credential, as a successful lookup would - for the tests of the middleware
+#[cfg(test)]
+pub(crate) async fn cache_credentials(hospital_key: &str, hospital: AuthenticatedHospital) {
+    let cache_key = auth_cache_key(&hospital.hospital_id, hospital_key);
+    AUTH_CACHE.insert(cache_key, hospital).await;
+}
+
+/// Cache of validated credentials or delegations, expiring after AUTH_CACHE_TTL_S
+fn auth_cache() -> Cache<(String, String), AuthenticatedHospital> {
+    let ttl = AUTH_CACHE_TTL_S.load(Ordering::Relaxed);
+    Cache::builder()
+        .max_capacity(AUTH_CACHE_CAPACITY)
+        .time_to_live(Duration::from_secs(ttl))
+        .support_invalidation_closures()
+        .build()
+}
+
+/// Cache key of a credential pair - the key itself is never kept in memory longer than the request
+/// # Arguments
+/// * `hospital_id` - The ID of the hospital
+/// * `hospital_key` - The key of the hospital
+fn auth_cache_key(hospital_id: &str, hospital_key: &str) -> (String, String) {
+    (
+        hospital_id.to_string(),
+        format!("{:x}", Sha256::digest(hospital_key.as_bytes())),
+    )
 }
 
 /// Function to validate hospital credentials against the database
@@ -64,32 +343,227 @@ async fn connect_to_database() -> Result<Pool<Postgres>> {
 /// * `hospital_key` - The key of the hospital to validate
 /// * `pool` - The database connection pool
 /// # Returns
-/// * `Result<()>` - Ok(()) if credentials are valid, Err otherwise
+/// * `Result<AuthenticatedHospital>` - The hospital with its consent scope and size tier if the key
+///   matches one of its active keys (neither revoked nor expired), Err otherwise (a missing scope is
+///   treated as clinical-only, a missing tier as standard)
 async fn validate_hospital_credentials(
     hospital_id: &str,
-    hospital_key: &str,
+    hospital_key: String,
     pool: &Pool<Postgres>,
-) -> Result<()> {
-    // STEP 1: Query the database for hospital credentials
-    let row = sqlx::query(
-        r#"
-        SELECT COUNT(*) as checker 
-        FROM hospital_credentials
-        WHERE hospital_id = $1 AND hospital_key = $2
-        "#,
-    )
-    .bind(hospital_id)
-    .bind(hospital_key)
-    .fetch_one(pool)
-    .await?;
+) -> Result<AuthenticatedHospital> {
+    // STEP 1: Query the database for the bcrypt hashes of the active keys, newest first
+    let row = ExternalCall::new(Dependency::Postgres, "validate_hospital_credentials")
+        .retries(1)
+        .run(|| {
+            sqlx::query(
+                r#"
+                SELECT c.consent_scope, c.size_tier, c.monthly_exam_quota,
+                       c.allowed_exam_types, c.rate_limit_tier, c.publish_mode,
+                       c.paused_at IS NOT NULL AS paused,
+                       c.parent_hospital_id, p.monthly_exam_quota AS parent_monthly_exam_quota,
+                       ARRAY(
+                           SELECT k.key_hash FROM hospital_keys k
+                           WHERE k.hospital_id = c.hospital_id AND k.revoked_at IS NULL
+                             AND (k.expires_at IS NULL OR k.expires_at > now())
+                           ORDER BY k.created_at DESC
+                           LIMIT $2
+                       ) AS key_hashes
+                FROM hospital_credentials c
+                LEFT JOIN hospital_credentials p ON p.hospital_id = c.parent_hospital_id
+                WHERE c.hospital_id = $1
+                "#,
+            )
+            .bind(hospital_id)
+            .bind(MAX_ACTIVE_KEYS)
+            .fetch_optional(pool)
+        })
+        .await?;
+
+    // STEP 2: Verify the key in constant time, off the async workers (bcrypt is slow by design)
+    // Unknown hospitals, or hospitals without active key, are verified against a dummy hash, so
+    // they cannot be told apart by timing
+    let key_hashes: Vec<String> = match &row {
+        Some(row) => row.try_get("key_hashes")?,
+        None => Vec::new(),
+    };
+    let key_matches = web::block(move || {
+        if key_hashes.is_empty() {
+            verify_hospital_key(&hospital_key, &DUMMY_HASH);
+            return false;
+        }
+        key_hashes
+            .iter()
+            .any(|key_hash| verify_hospital_key(&hospital_key, key_hash))
+    })
+    .await
+    .map_err(|e| anyhow!("Authentication failed: Key verification not scheduled: {e}"))?;
+    let row = row
+        .filter(|_| key_matches)
+        .ok_or_else(|| anyhow!("Authentication failed: Invalid credentials"))?;
+
+    // STEP 3: Read the registry entry, with the group of a clinic
+    registry_entry_with_group(hospital_id, &row)
+}
 
-    // STEP 2: Check if credentials are valid
-    let checker: i32 = row.try_get("checker")?;
-    if checker == 0 {
-        return Err(anyhow!("Authentication failed: Invalid credentials"));
+/// Read the registry entry of a hospital authenticated by its identity provider - no key is checked
+/// # Arguments
+/// * `hospital_id` - The `sub` claim of the verified bearer token
+/// * `pool` - The database connection pool
+/// # Errors
+/// * Returns an error if the hospital is not in the registry
+async fn registered_hospital(
+    hospital_id: &str,
+    pool: &Pool<Postgres>,
+) -> Result<AuthenticatedHospital> {
+    let row = ExternalCall::new(Dependency::Postgres, "registered_hospital")
+        .retries(1)
+        .run(|| {
+            sqlx::query(
+                r#"
+                SELECT c.consent_scope, c.size_tier, c.monthly_exam_quota,
+                       c.allowed_exam_types, c.rate_limit_tier, c.publish_mode,
+                       c.paused_at IS NOT NULL AS paused,
+                       c.parent_hospital_id, p.monthly_exam_quota AS parent_monthly_exam_quota
+                FROM hospital_credentials c
+                LEFT JOIN hospital_credentials p ON p.hospital_id = c.parent_hospital_id
+                WHERE c.hospital_id = $1
+                "#,
+            )
+            .bind(hospital_id)
+            .fetch_optional(pool)
+        })
+        .await?;
+    let row = row.ok_or_else(|| anyhow!("Authentication failed: Hospital not registered"))?;
+    registry_entry_with_group(hospital_id, &row)
+}
+
+/// Resolve the clinic a group submits for - the clinic must be a child of the group and the
+/// delegation must not be revoked
+/// # Arguments
+/// * `group` - The authenticated group
+/// * `clinic_id` - The clinic of the `on_behalf_of` header
+/// * `pool` - The database connection pool
+/// # Returns
+/// * The clinic with its own consent, tier and quota, under the group
+/// # Errors
+/// * Returns `ApiError::Forbidden` if the group holds no delegation for the clinic
+async fn resolve_delegation(
+    group: &AuthenticatedHospital,
+    clinic_id: &str,
+    pool: &Pool<Postgres>,
+) -> Result<AuthenticatedHospital> {
+    // STEP 1: Serve recent delegations from the cache
+    let cache_key = (group.hospital_id.clone(), clinic_id.to_string());
+    if let Some(clinic) = DELEGATION_CACHE.get(&cache_key).await {
+        return Ok(clinic);
     }
 
-    Ok(())
+    // STEP 2: Query the delegation and the registry entry of the clinic
+    let row = ExternalCall::new(Dependency::Postgres, "resolve_delegation")
+        .retries(1)
+        .run(|| {
+            sqlx::query(
+                r#"
+                SELECT c.consent_scope, c.size_tier, c.monthly_exam_quota,
+                       c.allowed_exam_types, c.rate_limit_tier, c.publish_mode,
+                       c.paused_at IS NOT NULL AS paused
+                FROM hospital_delegations d
+                JOIN hospital_credentials c ON c.hospital_id = d.clinic_id
+                WHERE d.group_id = $1 AND d.clinic_id = $2
+                  AND d.revoked_at IS NULL AND c.parent_hospital_id = d.group_id
+                "#,
+            )
+            .bind(&group.hospital_id)
+            .bind(clinic_id)
+            .fetch_optional(pool)
+        })
+        .await?;
+    let row = row.ok_or_else(|| {
+        anyhow::Error::new(ApiError::Forbidden(
+            "No delegation of the hospital group for this clinic".to_string(),
+        ))
+    })?;
+
+    // STEP 3: The clinic, under the quota of the group
+    let mut clinic = registry_entry(clinic_id, &row)?;
+    // A paused group submits for none of its clinics
+    clinic.paused |= group.paused;
+    clinic.group = Some(HospitalGroup {
+        group_id: group.hospital_id.clone(),
+        monthly_quota: group.monthly_quota,
+    });
+    DELEGATION_CACHE.insert(cache_key, clinic.clone()).await;
+    Ok(clinic)
+}
+
+/// Read the data-sharing consent, tiers, quota, exam types, publish mode and pause of a registry
+/// entry - unknown values fail closed, a missing scope is clinical-only, a missing tier standard, a
+/// missing quota or missing exam types unlimited, a missing publish mode inline
+/// # Arguments
+/// * `hospital_id` - The hospital id of the entry
+/// * `row` - The row with `consent_scope`, `size_tier`, `monthly_exam_quota`,
+///   `allowed_exam_types`, `rate_limit_tier`, `publish_mode` and `paused`
+fn registry_entry(hospital_id: &str, row: &PgRow) -> Result<AuthenticatedHospital> {
+    let consent_scope: Option<String> = row.try_get("consent_scope")?;
+    let consent_scope = match consent_scope {
+        Some(scope) => scope.parse()?,
+        None => ConsentScope::Clinical,
+    };
+    let size_tier: Option<String> = row.try_get("size_tier")?;
+    let size_tier = match size_tier {
+        Some(tier) => tier.parse()?,
+        None => SizeTier::Standard,
+    };
+    let monthly_quota: Option<i64> = row.try_get("monthly_exam_quota")?;
+    let rate_tier: Option<String> = row.try_get("rate_limit_tier")?;
+    let rate_tier = match rate_tier {
+        Some(tier) => tier.parse()?,
+        None => RateTier::Standard,
+    };
+    let publish_mode: Option<String> = row.try_get("publish_mode")?;
+    let publish_mode = match publish_mode {
+        Some(mode) => mode.parse()?,
+        None => PublishMode::Inline,
+    };
+    Ok(AuthenticatedHospital {
+        hospital_id: hospital_id.to_string(),
+        consent_scope,
+        size_tier,
+        monthly_quota: monthly_quota.map(|quota| quota.max(0) as u64),
+        group: None,
+        allowed_exam_types: row.try_get("allowed_exam_types")?,
+        rate_tier,
+        publish_mode,
+        paused: row.try_get("paused")?,
+    })
+}
+
+/// Read a registry entry with the group of a clinic
+/// # Arguments
+/// * `hospital_id` - The hospital id of the entry
+/// * `row` - The row of `registry_entry`, with `parent_hospital_id` and
+///   `parent_monthly_exam_quota`
+fn registry_entry_with_group(hospital_id: &str, row: &PgRow) -> Result<AuthenticatedHospital> {
+    let mut hospital = registry_entry(hospital_id, row)?;
+    let parent_hospital_id: Option<String> = row.try_get("parent_hospital_id")?;
+    if let Some(group_id) = parent_hospital_id {
+        let quota: Option<i64> = row.try_get("parent_monthly_exam_quota")?;
+        hospital.group = Some(HospitalGroup {
+            group_id,
+            monthly_quota: quota.map(|quota| quota.max(0) as u64),
+        });
+    }
+    Ok(hospital)
+}
+
+/// Verify a hospital key against its stored bcrypt hash - the comparison is constant-time
+/// # Arguments
+/// * `hospital_key` - The key sent by the hospital
+/// * `key_hash` - The stored hash (`$2a$`/`$2b$`, e.g. from pgcrypto `crypt(key, gen_salt('bf'))`)
+/// # Returns
+/// * true if the key matches, false if it does not or the hash is malformed
+fn verify_hospital_key(hospital_key: &str, key_hash: &str) -> bool {
+    bcrypt::verify(hospital_key, key_hash).unwrap_or(false)
 }
 
 // TESTS *******************************************************************************************
@@ -127,6 +601,119 @@ mod tests {
         }
     }
 
+    // Cache keys never contain the raw hospital key and differ per key
+    #[test]
+    async fn test_auth_cache_key() {
+        let (id, hashed) = auth_cache_key("h1", "secret");
+        assert_eq!(id, "h1");
+        assert!(!hashed.contains("secret"));
+        assert_ne!(auth_cache_key("h1", "other"), (id, hashed));
+    }
+
+    // Keys are verified against their hash, never compared in plain text
+    #[test]
+    async fn test_verify_hospital_key() {
+        let key_hash = bcrypt::hash("secret", 4).unwrap();
+        assert!(verify_hospital_key("secret", &key_hash));
+        assert!(!verify_hospital_key("Secret", &key_hash));
+        assert!(!verify_hospital_key("secret", "secret"));
+        assert!(verify_hospital_key("unknown-hospital", &DUMMY_HASH));
+    }
+
+    // Payloads of another hospital are refused
+    #[test]
+    async fn test_check_payload() {
+        let hospital = AuthenticatedHospital {
+            hospital_id: "h1".to_string(),
+            consent_scope: ConsentScope::Clinical,
+            size_tier: SizeTier::Standard,
+            monthly_quota: None,
+            group: None,
+            allowed_exam_types: None,
+            rate_tier: RateTier::Standard,
+            publish_mode: PublishMode::Inline,
+            paused: false,
+        };
+        assert!(hospital.check_payload("h1").is_ok());
+        // The payload id is canonical (lowercase hex), the header may not be
+        let upper = AuthenticatedHospital {
+            hospital_id: "ABC1".to_string(),
+            consent_scope: ConsentScope::Clinical,
+            size_tier: SizeTier::Standard,
+            monthly_quota: None,
+            group: None,
+            allowed_exam_types: None,
+            rate_tier: RateTier::Standard,
+            publish_mode: PublishMode::Inline,
+            paused: false,
+        };
+        assert!(upper.check_payload("abc1").is_ok());
+        assert!(matches!(
+            hospital.check_payload("h2"),
+            Err(ApiError::Forbidden(_))
+        ));
+    }
+
+    // Only the entries of the changed hospital, and of the clinics of a changed group, are forgotten
+    #[tokio::test]
+    async fn test_forget_hospital() {
+        let entry = |hospital_id: &str, group_id: Option<&str>| AuthenticatedHospital {
+            hospital_id: hospital_id.to_string(),
+            consent_scope: ConsentScope::Clinical,
+            size_tier: SizeTier::Standard,
+            monthly_quota: None,
+            group: group_id.map(|group_id| HospitalGroup {
+                group_id: group_id.to_string(),
+                monthly_quota: Some(5),
+            }),
+            allowed_exam_types: None,
+            rate_tier: RateTier::Standard,
+            publish_mode: PublishMode::Inline,
+            paused: false,
+        };
+        let keys = [
+            auth_cache_key("forget-h1", "k"),
+            auth_cache_key("forget-c1", "k"),
+            auth_cache_key("forget-h2", "k"),
+        ];
+        AUTH_CACHE
+            .insert(keys[0].clone(), entry("forget-h1", None))
+            .await;
+        AUTH_CACHE
+            .insert(keys[1].clone(), entry("forget-c1", Some("forget-h1")))
+            .await;
+        AUTH_CACHE
+            .insert(keys[2].clone(), entry("forget-h2", None))
+            .await;
+
+        forget_hospital("forget-h1");
+        assert!(AUTH_CACHE.get(&keys[0]).await.is_none());
+        assert!(AUTH_CACHE.get(&keys[1]).await.is_none());
+        assert!(AUTH_CACHE.get(&keys[2]).await.is_some());
+    }
+
+    // Hospitals without exam types may submit all of them, the others only theirs
+    #[test]
+    async fn test_allows_exam_type() {
+        let mut hospital = AuthenticatedHospital {
+            hospital_id: "h1".to_string(),
+            consent_scope: ConsentScope::Clinical,
+            size_tier: SizeTier::Standard,
+            monthly_quota: None,
+            group: None,
+            allowed_exam_types: None,
+            rate_tier: RateTier::Standard,
+            publish_mode: PublishMode::Inline,
+            paused: false,
+        };
+        assert!(hospital.allows_exam_type("xray_exam"));
+        hospital.allowed_exam_types = Some(vec!["ecg_exam".to_string()]);
+        assert!(hospital.allows_exam_type("ecg_exam"));
+        assert!(!hospital.allows_exam_type("xray_exam"));
+        hospital.allowed_exam_types = Some(Vec::new());
+        assert!(!hospital.allows_exam_type("ecg_exam"));
+    }
+
     // 2. Missing headers
     #[test]
     async fn test_get_headers_missing() {
@@ -0,0 +1,489 @@
+// Imports *****************************************************************************************
+// External Crates
+use actix_cors::Cors;
+use actix_web::body::{EitherBody, MessageBody};
+use actix_web::cookie::{time, Cookie, SameSite};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::header::{self, HeaderValue};
+use actix_web::http::Method;
+use actix_web::middleware::{Condition, DefaultHeaders, Next};
+use actix_web::{web, Error, HttpMessage, ResponseError};
+use anyhow::{anyhow, Result};
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use chrono::{DateTime, Utc};
+use log::{error, info, warn};
+use sha2::{Digest, Sha256};
+use sqlx::postgres::PgRow;
+use sqlx::{PgPool, Row};
+use std::sync::OnceLock;
+use subtle::ConstantTimeEq;
+
+// Internal Modules
+use crate::audit::audit_event::AuditEvent;
+use crate::audit::audit_trail::record_audit;
+use crate::utils::api_error::ApiError;
+use crate::utils::external_call::{Dependency, ExternalCall};
+
+// Constants ***************************************************************************************
+/// Cookie of the console session - the `__Host-` prefix pins it to this host, Secure and Path=/
+pub const SESSION_COOKIE: &str = "__Host-sentinela_console";
+/// Header carrying the CSRF token of the session on state-changing requests
+pub const CSRF_HEADER: &str = "x-csrf-token";
+/// Random bytes of the session token and of the CSRF token
+const TOKEN_BYTES: usize = 32;
+/// Seconds a browser may cache a CORS preflight
+const CORS_MAX_AGE_S: usize = 600;
+
+// Structs *****************************************************************************************
+/// Settings of the browser admin console
+/// # Arguments
+/// * `origin` - The only origin allowed to call the admin APIs from a browser, e.g.
+///   `https://console.example.org`
+/// * `session_ttl_s` - Lifetime of a session, pushed back by each refresh
+/// * `session_max_s` - Longest life of a session, refreshes included
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConsoleSettings {
+    pub origin: String,
+    pub session_ttl_s: u64,
+    pub session_max_s: u64,
+}
+
+/// Admin console session verified by `console_session_middleware`, stored in the request so
+/// `authenticate_admin` accepts it
+/// # Arguments
+/// * `session_id` - The public id of the session, as audited
+/// * `csrf_token` - The token the console sends back in 'x-csrf-token'
+/// * `expires_at` - When the session ends unless refreshed
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConsoleSession {
+    pub session_id: String,
+    pub csrf_token: String,
+    pub expires_at: DateTime<Utc>,
+}
+
+// Global variables ********************************************************************************
+/// Console settings, set once at startup when the console is enabled
+static CONSOLE_SETTINGS: OnceLock<ConsoleSettings> = OnceLock::new();
+
+// MAIN FUNCTIONS **********************************************************************************
+/// Enable the admin console
+/// # Arguments
+/// * `console` - The console settings
+pub fn init_console_settings(console: ConsoleSettings) {
+    if CONSOLE_SETTINGS.set(console).is_err() {
+        warn!("Admin console settings already initialized");
+    }
+}
+
+/// Console settings, None when the admin console is disabled
+pub fn console_settings() -> Option<&'static ConsoleSettings> {
+    CONSOLE_SETTINGS.get()
+}
+
+/// Verify the admin console session of the admin and internal requests carrying its cookie
+/// - Browser requests must come from ADMIN_CONSOLE_ORIGIN, state-changing ones must carry the CSRF
+///   token of the session in 'x-csrf-token'
+/// - A valid session is stored in the request and audited; an unknown, expired or revoked one is
+///   audited as denied and the request goes on - the handler then answers 401 unless an
+///   'admin_key' is sent
+/// # Arguments
+/// * `req` - The incoming request
+/// * `next` - The rest of the service chain
+/// # Returns
+/// * The response of the handler, or 403 if the origin or the CSRF token is refused
+pub async fn console_session_middleware(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<EitherBody<impl MessageBody>>, Error> {
+    let token = req.cookie(SESSION_COOKIE).map(|c| c.value().to_string());
+    let (Some(console), Some(token)) = (console_settings(), token) else {
+        return Ok(next.call(req).await?.map_into_left_body());
+    };
+    let client_ip = req
+        .connection_info()
+        .realip_remote_addr()
+        .unwrap_or("unknown")
+        .to_string();
+    let changes_state = !matches!(*req.method(), Method::GET | Method::HEAD | Method::OPTIONS);
+
+    // STEP 1: Cookies are only honoured from the console origin
+    if let Err(e) = check_origin(
+        req.headers().get(header::ORIGIN),
+        &console.origin,
+        changes_state,
+    ) {
+        warn!("Console session refused - {}: {e}", req.path());
+        record_audit(AuditEvent::console_session("denied", "", &client_ip));
+        let response = ApiError::Forbidden(e.to_string()).error_response();
+        return Ok(req.into_response(response).map_into_right_body());
+    }
+
+    // STEP 2: Find the session of the cookie
+    let Some(pool) = req.app_data::<web::Data<PgPool>>().cloned() else {
+        error!("Console session error: no database pool configured");
+        let response = ApiError::Internal.error_response();
+        return Ok(req.into_response(response).map_into_right_body());
+    };
+    let session = match find_session(&pool, &token).await {
+        Ok(Some(session)) => session,
+        Ok(None) => {
+            record_audit(AuditEvent::console_session("denied", "", &client_ip));
+            return Ok(next.call(req).await?.map_into_left_body());
+        }
+        Err(e) => {
+            error!("Console session lookup failed: {e}");
+            let response = ApiError::Internal.error_response();
+            return Ok(req.into_response(response).map_into_right_body());
+        }
+    };
+
+    // STEP 3: State-changing requests must carry the CSRF token of the session
+    let csrf = req.headers().get(CSRF_HEADER).and_then(|v| v.to_str().ok());
+    if changes_state && !csrf_matches(csrf, &session.csrf_token) {
+        warn!(
+            "Console session refused - {}: CSRF token mismatch",
+            req.path()
+        );
+        record_audit(AuditEvent::console_session(
+            "denied",
+            &session.session_id,
+            &client_ip,
+        ));
+        let response = ApiError::Forbidden("Missing or invalid CSRF token".to_string());
+        return Ok(req
+            .into_response(response.error_response())
+            .map_into_right_body());
+    }
+
+    // STEP 4: Every request of the session is audited
+    info!(target: "audit", "console_request session_id={} method={} path={}",
+        session.session_id, req.method(), req.path());
+    record_audit(AuditEvent::console_session(
+        "request",
+        &session.session_id,
+        &client_ip,
+    ));
+    req.extensions_mut().insert(session);
+    Ok(next.call(req).await?.map_into_left_body())
+}
+
+/// Strict CORS of the admin APIs: only ADMIN_CONSOLE_ORIGIN, with credentials - disabled (no CORS
+/// headers, browsers refuse cross-origin calls) when the console is not enabled
+pub fn console_cors() -> Condition<Cors> {
+    let Some(console) = console_settings() else {
+        return Condition::new(false, Cors::default());
+    };
+    let cors = Cors::default()
+        .allowed_origin(&console.origin)
+        .allowed_methods(["GET", "POST", "PATCH", "DELETE"])
+        .allowed_headers([
+            header::CONTENT_TYPE,
+            header::HeaderName::from_static(CSRF_HEADER),
+        ])
+        .supports_credentials()
+        .max_age(CORS_MAX_AGE_S);
+    Condition::new(true, cors)
+}
+
+/// Security headers of the admin APIs: never cached, never framed, no content sniffing nor
+/// referrer, HTTPS only
+pub fn security_headers() -> DefaultHeaders {
+    DefaultHeaders::new()
+        .add((header::CACHE_CONTROL, "no-store"))
+        .add((header::X_CONTENT_TYPE_OPTIONS, "nosniff"))
+        .add((header::X_FRAME_OPTIONS, "DENY"))
+        .add((header::REFERRER_POLICY, "no-referrer"))
+        .add((
+            header::CONTENT_SECURITY_POLICY,
+            "default-src 'none'; frame-ancestors 'none'",
+        ))
+        .add((
+            header::STRICT_TRANSPORT_SECURITY,
+            "max-age=31536000; includeSubDomains",
+        ))
+}
+
+/// Open a session after a successful login
+/// # Arguments
+/// * `pool` - The shared database connection pool
+/// * `console` - The console settings
+/// * `client_ip` - The client address
+/// # Returns
+/// * The token of the cookie and the session
+/// # Errors
+/// * Returns an error if the tokens cannot be generated or the session cannot be written
+pub async fn open_session(
+    pool: &PgPool,
+    console: &ConsoleSettings,
+    client_ip: &str,
+) -> Result<(String, ConsoleSession)> {
+    let token = random_token()?;
+    let csrf_token = random_token()?;
+    let session_id = session_id()?;
+    let row = ExternalCall::new(Dependency::Postgres, "open_console_session")
+        .run(|| {
+            sqlx::query(
+                r#"
+                INSERT INTO admin_sessions (session_id, token_hash, csrf_token, client_ip, expires_at)
+                VALUES ($1, $2, $3, $4, now() + make_interval(secs => $5::float8))
+                RETURNING session_id, csrf_token,
+                          (extract(epoch FROM expires_at) * 1000000)::bigint AS expires_at_us
+                "#,
+            )
+            .bind(&session_id)
+            .bind(token_hash(&token))
+            .bind(&csrf_token)
+            .bind(client_ip)
+            .bind(console.session_ttl_s as f64)
+            .fetch_one(pool)
+        })
+        .await?;
+    Ok((token, session_from_row(&row)?))
+}
+
+/// Refresh a session: its token and CSRF token are replaced and its expiry pushed back, never past
+/// ADMIN_SESSION_MAX_S after the login
+/// # Arguments
+/// * `pool` - The shared database connection pool
+/// * `console` - The console settings
+/// * `session` - The session verified by the middleware
+/// # Returns
+/// * The new token of the cookie and the session, None if the session was revoked meanwhile
+/// # Errors
+/// * Returns an error if the tokens cannot be generated or the session cannot be written
+pub async fn refresh_session(
+    pool: &PgPool,
+    console: &ConsoleSettings,
+    session: &ConsoleSession,
+) -> Result<Option<(String, ConsoleSession)>> {
+    let token = random_token()?;
+    let csrf_token = random_token()?;
+    let row = ExternalCall::new(Dependency::Postgres, "refresh_console_session")
+        .run(|| {
+            sqlx::query(
+                r#"
+                UPDATE admin_sessions
+                SET token_hash = $2, csrf_token = $3, last_seen_at = now(),
+                    expires_at = LEAST(now() + make_interval(secs => $4::float8),
+                                       created_at + make_interval(secs => $5::float8))
+                WHERE session_id = $1 AND revoked_at IS NULL AND expires_at > now()
+                RETURNING session_id, csrf_token,
+                          (extract(epoch FROM expires_at) * 1000000)::bigint AS expires_at_us
+                "#,
+            )
+            .bind(&session.session_id)
+            .bind(token_hash(&token))
+            .bind(&csrf_token)
+            .bind(console.session_ttl_s as f64)
+            .bind(console.session_max_s as f64)
+            .fetch_optional(pool)
+        })
+        .await?;
+    row.map(|row| Ok((token, session_from_row(&row)?)))
+        .transpose()
+}
+
+/// Revoke a session at logout - its cookie is refused from then on
+/// # Arguments
+/// * `pool` - The shared database connection pool
+/// * `session_id` - The public id of the session
+/// # Errors
+/// * Returns an error if the session cannot be written
+pub async fn revoke_session(pool: &PgPool, session_id: &str) -> Result<()> {
+    ExternalCall::new(Dependency::Postgres, "revoke_console_session")
+        .run(|| {
+            sqlx::query(
+                "UPDATE admin_sessions SET revoked_at = now() \
+                 WHERE session_id = $1 AND revoked_at IS NULL",
+            )
+            .bind(session_id)
+            .execute(pool)
+        })
+        .await?;
+    Ok(())
+}
+
+/// Cookie of a session: HttpOnly, Secure, SameSite=Strict, for the whole host
+/// # Arguments
+/// * `token` - The token of the session, empty to clear the cookie
+/// * `max_age_s` - Seconds the browser keeps the cookie, 0 to clear it
+pub fn session_cookie(token: &str, max_age_s: i64) -> Cookie<'static> {
+    Cookie::build(SESSION_COOKIE, token.to_string())
+        .path("/")
+        .secure(true)
+        .http_only(true)
+        .same_site(SameSite::Strict)
+        .max_age(time::Duration::seconds(max_age_s))
+        .finish()
+}
+
+/// Check the 'Origin' header of a browser request against the console origin
+/// # Arguments
+/// * `origin` - The 'Origin' header, if any
+/// * `console_origin` - ADMIN_CONSOLE_ORIGIN
+/// * `required` - Whether a missing header is refused (state-changing requests and logins)
+/// # Errors
+/// * Returns an error if the origin is another one, or missing when required
+pub fn check_origin(
+    origin: Option<&HeaderValue>,
+    console_origin: &str,
+    required: bool,
+) -> Result<()> {
+    match origin.map(|v| v.to_str()) {
+        Some(Ok(origin)) if origin == console_origin => Ok(()),
+        Some(_) => Err(anyhow!("Origin not allowed for the admin console")),
+        None if required => Err(anyhow!("Missing Origin header")),
+        None => Ok(()),
+    }
+}
+
+// SUPPORT FUNCTIONS *******************************************************************************
+/// Find the live session of a cookie and record its use
+async fn find_session(pool: &PgPool, token: &str) -> Result<Option<ConsoleSession>> {
+    let row = ExternalCall::new(Dependency::Postgres, "find_console_session")
+        .retries(1)
+        .run(|| {
+            sqlx::query(
+                r#"
+                UPDATE admin_sessions SET last_seen_at = now()
+                WHERE token_hash = $1 AND revoked_at IS NULL AND expires_at > now()
+                RETURNING session_id, csrf_token,
+                          (extract(epoch FROM expires_at) * 1000000)::bigint AS expires_at_us
+                "#,
+            )
+            .bind(token_hash(token))
+            .fetch_optional(pool)
+        })
+        .await?;
+    row.as_ref().map(session_from_row).transpose()
+}
+
+/// Session of a row with `session_id`, `csrf_token` and `expires_at_us`
+fn session_from_row(row: &PgRow) -> Result<ConsoleSession> {
+    let micros: i64 = row.try_get("expires_at_us")?;
+    Ok(ConsoleSession {
+        session_id: row.try_get("session_id")?,
+        csrf_token: row.try_get("csrf_token")?,
+        expires_at: DateTime::from_timestamp_micros(micros)
+            .ok_or_else(|| anyhow!("Session expiry out of range: {micros}"))?,
+    })
+}
+
+/// Compare the CSRF token of a request with the one of its session, in constant time
+fn csrf_matches(provided: Option<&str>, expected: &str) -> bool {
+    provided.is_some_and(|provided| {
+        !expected.is_empty() && bool::from(provided.as_bytes().ct_eq(expected.as_bytes()))
+    })
+}
+
+/// Normalize and check ADMIN_CONSOLE_ORIGIN: scheme and host (and port) only, https unless local
+pub(crate) fn console_origin(raw: &str) -> Result<String> {
+    let origin = raw.trim().trim_end_matches('/');
+    let local = ["http://localhost", "http://127.0.0.1"]
+        .iter()
+        .any(|local| origin == *local || origin.starts_with(&format!("{local}:")));
+    let host = origin
+        .strip_prefix("https://")
+        .filter(|host| !host.is_empty());
+    if !local && host.is_none() {
+        return Err(anyhow!(
+            "Invalid ADMIN_CONSOLE_ORIGIN: {raw} (an https origin, http only for localhost)"
+        ));
+    }
+    if host.is_some_and(|host| host.contains('/')) {
+        return Err(anyhow!(
+            "Invalid ADMIN_CONSOLE_ORIGIN: {raw} (scheme and host only, no path)"
+        ));
+    }
+    Ok(origin.to_string())
+}
+
+/// Random URL-safe token
+fn random_token() -> Result<String> {
+    let mut bytes = [0u8; TOKEN_BYTES];
+    getrandom::getrandom(&mut bytes).map_err(|e| anyhow!("Session token not generated: {e}"))?;
+    Ok(URL_SAFE_NO_PAD.encode(bytes))
+}
+
+/// Random public id of a session, e.g. 'as_1f2e3d4c5b6a7988'
+fn session_id() -> Result<String> {
+    let mut id = [0u8; 8];
+    getrandom::getrandom(&mut id).map_err(|e| anyhow!("Session id not generated: {e}"))?;
+    Ok(format!(
+        "as_{}",
+        id.iter().map(|b| format!("{b:02x}")).collect::<String>()
+    ))
+}
+
+/// Hex SHA256 of a session token, as stored - the token itself is never kept
+fn token_hash(token: &str) -> String {
+    format!("{:x}", Sha256::digest(token.as_bytes()))
+}
+
+// TESTS *******************************************************************************************
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Happy path: the session cookie cannot be read by scripts nor sent cross-site
+    #[test]
+    fn cookie_attributes() {
+        let cookie = session_cookie("token", 900).to_string();
+        assert!(cookie.starts_with("__Host-sentinela_console=token"));
+        for attribute in [
+            "HttpOnly",
+            "Secure",
+            "SameSite=Strict",
+            "Path=/",
+            "Max-Age=900",
+        ] {
+            assert!(cookie.contains(attribute), "{cookie} lacks {attribute}");
+        }
+        assert!(!cookie.contains("Domain"));
+    }
+
+    // Error handling: other origins are refused, a missing one only when required
+    #[test]
+    fn origin_checked() {
+        let console = "https://console.example.org";
+        let own = HeaderValue::from_static("https://console.example.org");
+        let other = HeaderValue::from_static("https://evil.example.com");
+        assert!(check_origin(Some(&own), console, true).is_ok());
+        assert!(check_origin(Some(&other), console, false).is_err());
+        assert!(check_origin(None, console, false).is_ok());
+        assert!(check_origin(None, console, true).is_err());
+    }
+
+    // Error handling: the CSRF token must be present and match
+    #[test]
+    fn csrf_checked() {
+        assert!(csrf_matches(Some("abc"), "abc"));
+        assert!(!csrf_matches(Some("abd"), "abc"));
+        assert!(!csrf_matches(None, "abc"));
+        assert!(!csrf_matches(Some(""), ""));
+    }
+
+    // Borderline: the console origin is https, http only for localhost, without path
+    #[test]
+    fn console_origin_parsed() {
+        assert_eq!(
+            console_origin("https://console.example.org/").unwrap(),
+            "https://console.example.org"
+        );
+        assert!(console_origin("http://localhost:5173").is_ok());
+        assert!(console_origin("http://console.example.org").is_err());
+        assert!(console_origin("http://localhost.evil.com").is_err());
+        assert!(console_origin("https://console.example.org/app").is_err());
+        assert!(console_origin("https://").is_err());
+    }
+
+    // Happy path: tokens are random and only their hash is stored
+    #[test]
+    fn tokens_generated() {
+        let token = random_token().unwrap();
+        assert_ne!(token, random_token().unwrap());
+        assert_eq!(token_hash(&token).len(), 64);
+        assert!(session_id().unwrap().starts_with("as_"));
+    }
+}
@@ -0,0 +1,408 @@
+// Imports *****************************************************************************************
+// External Crates
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use google_cloud_auth::project::Config as AuthConfig;
+use google_cloud_auth::token::DefaultTokenSourceProvider;
+#[cfg(feature = "grpc")]
+use google_cloud_gax::conn::Environment;
+#[cfg(feature = "grpc")]
+use google_cloud_pubsub::client::ClientConfig as PubSubClientConfig;
+use google_cloud_storage::client::ClientConfig as GcsClientConfig;
+use google_cloud_storage::sign::SignBy;
+use google_cloud_token::{TokenSource, TokenSourceProvider};
+use serde::Deserialize;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+
+// Internal Modules
+use crate::config::settings::GcpSettings;
+use crate::utils::external_call::{Dependency, ExternalCall};
+
+// Constants ***************************************************************************************
+/// OAuth scope requested for the GCS and Pub/Sub clients
+const CLOUD_PLATFORM_SCOPE: &str = "https://www.googleapis.com/auth/cloud-platform";
+/// OAuth scope of the GCS client used to write exams
+const GCS_READ_WRITE_SCOPE: &str = "https://www.googleapis.com/auth/devstorage.read_write";
+/// OAuth scope of the GCS client used to read exams back (export, plugins)
+const GCS_READ_ONLY_SCOPE: &str = "https://www.googleapis.com/auth/devstorage.read_only";
+/// Lifetime requested for impersonated access tokens
+const IMPERSONATED_TOKEN_LIFETIME_S: u32 = 3600;
+/// Impersonated tokens are refreshed this long before they expire
+const TOKEN_REFRESH_MARGIN_S: i64 = 300;
+
+// Structs *****************************************************************************************
+/// Identity used by the service to call GCP
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GcpIdentity {
+    /// Application default credentials (metadata server, user credentials or
+    /// an `external_account` file for workload identity federation)
+    ApplicationDefault,
+    /// Short-lived tokens of the given service account, minted from the application default
+    /// credentials through the IAM Credentials API
+    Impersonated(String),
+}
+
+impl GcpIdentity {
+    /// Identity of the service, from its GCP settings
+    /// # Arguments
+    /// * `settings` - The GCP settings
+    /// # Errors
+    /// * Returns an error if GCP_FORBID_SERVICE_ACCOUNT_KEYS is set and a long-lived key is used
+    pub fn from_settings(settings: &GcpSettings) -> Result<Self> {
+        // Long-lived service account keys can be forbidden by policy
+        if settings.forbid_service_account_keys {
+            if let Some(path) = &settings.application_credentials {
+                let content = std::fs::read_to_string(path).map_err(|e| {
+                    anyhow!("Cannot read GOOGLE_APPLICATION_CREDENTIALS {path}: {e}")
+                })?;
+                ensure_not_service_account_key(&content)?;
+            }
+        }
+        Ok(match &settings.impersonate_service_account {
+            Some(account) => GcpIdentity::Impersonated(account.clone()),
+            None => GcpIdentity::ApplicationDefault,
+        })
+    }
+
+    /// Identity of one subsystem: its own service account if set, otherwise the identity of the
+    /// service
+    /// # Arguments
+    /// * `settings` - The GCP settings
+    /// * `account` - The service account to impersonate for the subsystem
+    /// # Errors
+    /// * Returns an error if the identity of the service is not allowed
+    pub fn from_settings_for(settings: &GcpSettings, account: Option<&str>) -> Result<Self> {
+        let identity = Self::from_settings(settings)?;
+        Ok(match account {
+            Some(account) => GcpIdentity::Impersonated(account.to_string()),
+            None => identity,
+        })
+    }
+
+    /// Human-readable description for startup logs
+    /// # Arguments
+    /// * `settings` - The GCP settings, locating the application default credentials
+    pub fn describe(&self, settings: &GcpSettings) -> String {
+        match self {
+            GcpIdentity::ApplicationDefault => match credentials_type(settings).as_deref() {
+                Some("external_account") => {
+                    "workload identity federation (external account)".to_string()
+                }
+                Some(other) => format!("application default credentials ({other})"),
+                None => "application default credentials (metadata server)".to_string(),
+            },
+            GcpIdentity::Impersonated(account) => format!("impersonated service account {account}"),
+        }
+    }
+}
+
+/// Access level of a GCS client - ingestion writes, export and plugin loading only read
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GcsAccess {
+    ReadWrite,
+    ReadOnly,
+}
+
+impl GcsAccess {
+    /// OAuth scope requested for this access level
+    fn scope(&self) -> &'static str {
+        match self {
+            GcsAccess::ReadWrite => GCS_READ_WRITE_SCOPE,
+            GcsAccess::ReadOnly => GCS_READ_ONLY_SCOPE,
+        }
+    }
+
+    /// Stable lowercase name, used in startup logs
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            GcsAccess::ReadWrite => "read_write",
+            GcsAccess::ReadOnly => "read_only",
+        }
+    }
+}
+
+/// Token source minting impersonated access tokens and caching them until shortly before expiry
+#[derive(Debug)]
+pub struct ImpersonatedTokenSource {
+    target: String,
+    scope: &'static str,
+    source: Arc<dyn TokenSource>,
+    http: reqwest::Client,
+    cache: Mutex<Option<(String, DateTime<Utc>)>>,
+}
+
+/// Response of the IAM Credentials generateAccessToken call
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GenerateAccessTokenResponse {
+    access_token: String,
+    expire_time: DateTime<Utc>,
+}
+
+#[async_trait]
+impl TokenSource for ImpersonatedTokenSource {
+    async fn token(&self) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+        // STEP 1: Serve the cached token while it is comfortably valid
+        let mut cache = self.cache.lock().await;
+        if let Some((token, expiry)) = cache.as_ref() {
+            if (*expiry - Utc::now()).num_seconds() > TOKEN_REFRESH_MARGIN_S {
+                return Ok(token.clone());
+            }
+        }
+
+        // STEP 2: Mint a new token for the target service account
+        let url = format!(
+            "https://iamcredentials.googleapis.com/v1/projects/-/serviceAccounts/{}:generateAccessToken",
+            self.target
+        );
+        let body = serde_json::json!({
+            "scope": [self.scope],
+            "lifetime": format!("{IMPERSONATED_TOKEN_LIFETIME_S}s"),
+        });
+        let response: GenerateAccessTokenResponse =
+            ExternalCall::new(Dependency::Iam, "generate_access_token")
+                .retries(2)
+                .run(|| async {
+                    let source_token = self.source.token().await.map_err(|e| e.to_string())?;
+                    let response = self
+                        .http
+                        .post(&url)
+                        .header("Authorization", source_token)
+                        .json(&body)
+                        .send()
+                        .await
+                        .map_err(|e| e.to_string())?;
+                    let status = response.status();
+                    if !status.is_success() {
+                        let detail = response.text().await.unwrap_or_default();
+                        return Err(format!("{} {detail}", status.as_u16()));
+                    }
+                    response
+                        .json::<GenerateAccessTokenResponse>()
+                        .await
+                        .map_err(|e| e.to_string())
+                })
+                .await
+                .map_err(|e| anyhow!("Impersonation of {} failed: {e}", self.target))?;
+
+        let token = format!("Bearer {}", response.access_token);
+        *cache = Some((token.clone(), response.expire_time));
+        Ok(token)
+    }
+}
+
+/// Provider handing out the shared impersonated token source
+#[derive(Debug, Clone)]
+pub struct ImpersonatedTokenSourceProvider {
+    source: Arc<ImpersonatedTokenSource>,
+}
+
+impl TokenSourceProvider for ImpersonatedTokenSourceProvider {
+    fn token_source(&self) -> Arc<dyn TokenSource> {
+        self.source.clone()
+    }
+}
+
+impl ImpersonatedTokenSourceProvider {
+    /// Build an impersonating provider over the application default credentials, and mint a first
+    /// token so that a missing `roles/iam.serviceAccountTokenCreator` grant fails at startup
+    /// # Arguments
+    /// * `target` - The email of the service account to impersonate
+    /// * `scope` - The OAuth scope of the minted tokens
+    /// # Errors
+    /// * Returns an error if the default credentials or the impersonation are unusable
+    pub async fn new(target: &str, scope: &'static str) -> Result<(Self, Option<String>)> {
+        let scopes = [CLOUD_PLATFORM_SCOPE];
+        let base = DefaultTokenSourceProvider::new(AuthConfig {
+            audience: None,
+            scopes: Some(&scopes),
+            sub: None,
+        })
+        .await
+        .map_err(|e| anyhow!("Cannot load application default credentials: {e}"))?;
+        let project_id = base.project_id.clone();
+        let provider = Self {
+            source: Arc::new(ImpersonatedTokenSource {
+                target: target.to_string(),
+                scope,
+                source: base.token_source(),
+                http: reqwest::Client::new(),
+                cache: Mutex::new(None),
+            }),
+        };
+        provider.source.token().await.map_err(|e| {
+            anyhow!(
+                "{e} - the runtime identity needs roles/iam.serviceAccountTokenCreator on {target}"
+            )
+        })?;
+        Ok((provider, project_id))
+    }
+}
+
+// MAIN FUNCTIONS **********************************************************************************
+/// Build the GCS client configuration for the given identity
+/// # Arguments
+/// * `identity` - The identity the client authenticates as
+/// * `access` - The access level, which restricts the OAuth scope of the tokens
+/// # Errors
+/// * Returns an error if authentication cannot be set up
+pub async fn gcs_client_config(
+    identity: &GcpIdentity,
+    access: GcsAccess,
+) -> Result<GcsClientConfig> {
+    match identity {
+        GcpIdentity::ApplicationDefault => {
+            // Keep the project and signing setup of the default credentials, narrow the scope
+            let mut config = GcsClientConfig::default().with_auth().await?;
+            let scopes = [access.scope()];
+            config.token_source_provider = Box::new(
+                DefaultTokenSourceProvider::new(AuthConfig {
+                    audience: None,
+                    scopes: Some(&scopes),
+                    sub: None,
+                })
+                .await?,
+            );
+            Ok(config)
+        }
+        GcpIdentity::Impersonated(account) => {
+            let (provider, project_id) =
+                ImpersonatedTokenSourceProvider::new(account, access.scope()).await?;
+            Ok(GcsClientConfig {
+                token_source_provider: Box::new(provider),
+                default_google_access_id: Some(account.clone()),
+                default_sign_by: Some(SignBy::SignBytes),
+                project_id,
+                ..Default::default()
+            })
+        }
+    }
+}
+
+/// Token source of an identity for one OAuth scope, for the REST clients (BigQuery sink)
+/// # Arguments
+/// * `identity` - The identity the tokens are minted for
+/// * `scope` - The OAuth scope of the tokens
+/// # Errors
+/// * Returns an error if the credentials or the impersonation are unusable
+#[cfg(feature = "bigquery")]
+pub async fn token_source(
+    identity: &GcpIdentity,
+    scope: &'static str,
+) -> Result<Arc<dyn TokenSource>> {
+    match identity {
+        GcpIdentity::ApplicationDefault => {
+            let scopes = [scope];
+            let provider = DefaultTokenSourceProvider::new(AuthConfig {
+                audience: None,
+                scopes: Some(&scopes),
+                sub: None,
+            })
+            .await
+            .map_err(|e| anyhow!("Cannot load application default credentials: {e}"))?;
+            Ok(provider.token_source())
+        }
+        GcpIdentity::Impersonated(account) => {
+            let (provider, _) = ImpersonatedTokenSourceProvider::new(account, scope).await?;
+            Ok(provider.token_source())
+        }
+    }
+}
+
+/// Build the PubSub client configuration for the given identity
+/// # Arguments
+/// * `identity` - The identity the client authenticates as
+/// * `project_id` - Overrides the project of the credentials (partner projects)
+/// # Errors
+/// * Returns an error if authentication cannot be set up
+#[cfg(feature = "grpc")]
+pub async fn pubsub_client_config(
+    identity: &GcpIdentity,
+    project_id: Option<&str>,
+) -> Result<PubSubClientConfig> {
+    let mut config = match identity {
+        GcpIdentity::ApplicationDefault => PubSubClientConfig::default().with_auth().await?,
+        GcpIdentity::Impersonated(account) => {
+            let (provider, credentials_project) =
+                ImpersonatedTokenSourceProvider::new(account, CLOUD_PLATFORM_SCOPE).await?;
+            let mut config = PubSubClientConfig::default();
+            if let Environment::GoogleCloud(_) = config.environment {
+                config.environment = Environment::GoogleCloud(Box::new(provider));
+                config.project_id = credentials_project;
+            }
+            config
+        }
+    };
+    if let Some(project_id) = project_id {
+        config.project_id = Some(project_id.to_string());
+    }
+    Ok(config)
+}
+
+// SUPPORT FUNCTIONS *******************************************************************************
+/// Type of the credentials file referenced by GOOGLE_APPLICATION_CREDENTIALS, if any
+fn credentials_type(settings: &GcpSettings) -> Option<String> {
+    let path = settings.application_credentials.as_ref()?;
+    let content = std::fs::read_to_string(path).ok()?;
+    let json: serde_json::Value = serde_json::from_str(&content).ok()?;
+    json.get("type")?.as_str().map(str::to_string)
+}
+
+/// Reject long-lived service account keys
+/// # Arguments
+/// * `credentials_json` - The content of the credentials file
+fn ensure_not_service_account_key(credentials_json: &str) -> Result<()> {
+    let json: serde_json::Value = serde_json::from_str(credentials_json)
+        .map_err(|e| anyhow!("Invalid credentials file: {e}"))?;
+    if json.get("type").and_then(|t| t.as_str()) == Some("service_account") {
+        return Err(anyhow!(
+            "Long-lived service account keys are forbidden (GCP_FORBID_SERVICE_ACCOUNT_KEYS): \
+             use workload identity federation or GCP_IMPERSONATE_SERVICE_ACCOUNT"
+        ));
+    }
+    Ok(())
+}
+
+// TESTS *******************************************************************************************
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn service_account_keys_rejected() {
+        assert!(ensure_not_service_account_key(r#"{"type": "service_account"}"#).is_err());
+        assert!(ensure_not_service_account_key(r#"{"type": "external_account"}"#).is_ok());
+        assert!(ensure_not_service_account_key("not json").is_err());
+    }
+
+    #[test]
+    fn token_response_parses() {
+        let r: GenerateAccessTokenResponse = serde_json::from_str(
+            r#"{"accessToken": "ya29.x", "expireTime": "2030-01-01T00:00:00Z"}"#,
+        )
+        .unwrap();
+        assert_eq!(r.access_token, "ya29.x");
+        assert!(r.expire_time > Utc::now());
+    }
+
+    #[test]
+    fn gcs_access_scopes() {
+        assert!(GcsAccess::ReadOnly
+            .scope()
+            .ends_with("devstorage.read_only"));
+        assert!(GcsAccess::ReadWrite
+            .scope()
+            .ends_with("devstorage.read_write"));
+    }
+
+    #[test]
+    fn impersonated_identity_describes_target() {
+        let identity = GcpIdentity::Impersonated("sa@p.iam.gserviceaccount.com".to_string());
+        let settings = crate::config::settings::test_settings();
+        assert!(identity
+            .describe(&settings.gcp)
+            .contains("sa@p.iam.gserviceaccount.com"));
+    }
+}
@@ -1 +1,9 @@
+pub mod admin;
 pub mod auth;
+pub mod console_session;
+pub mod gcp_identity;
+pub mod jwt;
+pub mod middleware;
+pub mod rate_limit;
+#[cfg(feature = "redis")]
+pub mod rate_limit_redis;
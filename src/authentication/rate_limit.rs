@@ -0,0 +1,664 @@
+// Imports *****************************************************************************************
+// External Crates
+use actix_web::body::{EitherBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::StatusCode;
+use actix_web::middleware::Next;
+use actix_web::{web, Error, HttpMessage, HttpRequest, ResponseError};
+use anyhow::{anyhow, Context, Result};
+use log::{info, warn};
+use std::collections::{BTreeMap, HashMap};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+// Internal Modules
+use crate::authentication::auth::AuthenticatedHospital;
+#[cfg(feature = "redis")]
+use crate::authentication::rate_limit_redis::RedisWindows;
+use crate::config::settings::AuthSettings;
+use crate::models::models_rate_tiers::RateTier;
+use crate::routes::registry::route_of;
+use crate::telemetry::metrics::{record_rate_limit, record_rate_limit_lockout};
+use crate::utils::api_error::ApiError;
+use crate::utils::reason_code::ReasonCode;
+
+// Constants ***************************************************************************************
+/// Policies when RATE_LIMITS does not set them - the admin API is stricter and locks out clients
+/// after repeated authentication failures
+const DEFAULT_RATE_LIMITS: &str = "ingest:600/60,admin:30/60:5/900,internal:300/60";
+/// Clients tracked per scope before the idle ones are forgotten
+const MAX_TRACKED_CLIENTS: usize = 10_000;
+
+// Structs *****************************************************************************************
+/// API scopes, each with its own policy, client counters and metrics
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum RateScope {
+    /// The hospital API (`/v1`) - clients are addresses, whatever hospital they claim
+    Ingest,
+    /// The operator actions of the internal API (`/internal/v1`) and of the hospital admin API
+    /// (`/v1/admin`) - clients are addresses
+    Admin,
+    /// The monitoring endpoints of the internal API (`/metrics`, `/readiness`) - clients are
+    /// addresses
+    Internal,
+}
+
+impl RateScope {
+    /// Stable lowercase name, used in RATE_LIMITS and the metric labels
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RateScope::Ingest => "ingest",
+            RateScope::Admin => "admin",
+            RateScope::Internal => "internal",
+        }
+    }
+
+    /// Scope of a request path: the rate limit its route registers, or for paths no route matches
+    /// the one of their API - None for the public routes and unscoped paths
+    fn of_path(path: &str) -> Option<Self> {
+        if let Some(route) = route_of(path) {
+            return route.rate_limit;
+        }
+        if path.starts_with("/internal/v1/") || path.starts_with("/v1/admin/") {
+            Some(RateScope::Admin)
+        } else if path.starts_with("/v1/") {
+            Some(RateScope::Ingest)
+        } else {
+            None
+        }
+    }
+}
+
+impl FromStr for RateScope {
+    type Err = anyhow::Error;
+
+    fn from_str(raw: &str) -> Result<Self> {
+        match raw {
+            "ingest" => Ok(RateScope::Ingest),
+            "admin" => Ok(RateScope::Admin),
+            "internal" => Ok(RateScope::Internal),
+            other => Err(anyhow!("Unknown rate limit scope '{other}'")),
+        }
+    }
+}
+
+/// Rate limit of a scope, declared as `requests/window_s`, optionally followed by the lockout
+/// `:failures/lockout_s` - e.g. `30/60:5/900`
+/// # Arguments
+/// * `requests` - Requests of a client per window
+/// * `window` - The fixed window the requests are counted in
+/// * `lockout` - Consecutive authentication failures locking a client out, and for how long
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RatePolicy {
+    pub requests: u32,
+    pub window: Duration,
+    pub lockout: Option<(u32, Duration)>,
+}
+
+impl FromStr for RatePolicy {
+    type Err = anyhow::Error;
+
+    fn from_str(raw: &str) -> Result<Self> {
+        let (rate, lockout) = match raw.split_once(':') {
+            Some((rate, lockout)) => (rate, Some(lockout)),
+            None => (raw, None),
+        };
+        let (requests, window) = count_per_seconds(rate)?;
+        let lockout = lockout.map(count_per_seconds).transpose()?;
+        Ok(Self {
+            requests,
+            window,
+            lockout,
+        })
+    }
+}
+
+/// Counters of one client in a scope
+/// # Arguments
+/// * `window_started` - When the current window started
+/// * `requests` - Requests admitted in the current window
+/// * `failures` - Consecutive authentication failures
+/// * `locked_until` - End of the lockout of the client, if locked out
+/// * `tier` - The rate limit tier of the hospital last authenticated from the address
+#[derive(Debug, Clone, Copy)]
+struct ClientCounters {
+    window_started: Instant,
+    requests: u32,
+    failures: u32,
+    locked_until: Option<Instant>,
+    tier: RateTier,
+}
+
+/// Per-scope rate limits (RATE_LIMITS) - every scope keeps its own client counters, so a busy
+/// ingest client never uses up the admin budget of its address
+/// - The request windows are counted by this instance, or in Redis when RATE_LIMIT_REDIS_URL is
+///   set (`redis` feature) so that the instances share them
+pub struct RateLimiter {
+    scopes: BTreeMap<RateScope, (RatePolicy, Mutex<HashMap<String, ClientCounters>>)>,
+    trusted_proxy_hops: usize,
+    #[cfg(feature = "redis")]
+    redis: Option<RedisWindows>,
+}
+
+impl RateLimiter {
+    /// Create the limiter from the authentication settings: the policies of RATE_LIMITS, with the
+    /// windows counted in the Redis of RATE_LIMIT_REDIS_URL when set
+    /// # Arguments
+    /// * `auth` - The authentication settings
+    /// # Errors
+    /// * Returns an error if RATE_LIMIT_REDIS_URL is set and Redis cannot be reached
+    pub async fn from_settings(auth: &AuthSettings) -> Result<Arc<Self>> {
+        #[cfg_attr(not(feature = "redis"), allow(unused_mut))]
+        let mut limiter = Self::with_policies(&auth.rate_limits, auth.trusted_proxy_hops);
+        #[cfg(feature = "redis")]
+        if let Some(url) = &auth.rate_limit_redis_url {
+            limiter.redis = Some(RedisWindows::connect(url.expose()).await?);
+            info!("Rate limit windows shared in Redis (RATE_LIMIT_REDIS_URL)");
+        }
+        Ok(Arc::new(limiter))
+    }
+
+    /// Limiter counting the request windows on this instance
+    /// # Arguments
+    /// * `policies` - The policy of each limited scope, the scopes not listed are not limited
+    /// * `trusted_proxy_hops` - The proxies appending to X-Forwarded-For in front of the service
+    ///   (TRUSTED_PROXY_HOPS)
+    fn with_policies(
+        policies: &BTreeMap<RateScope, RatePolicy>,
+        trusted_proxy_hops: usize,
+    ) -> Self {
+        let scopes = policies
+            .iter()
+            .map(|(scope, policy)| {
+                info!("Rate limit of the {} scope: {policy:?}", scope.as_str());
+                (*scope, (*policy, Mutex::new(HashMap::new())))
+            })
+            .collect();
+        Self {
+            scopes,
+            trusted_proxy_hops,
+            #[cfg(feature = "redis")]
+            redis: None,
+        }
+    }
+
+    /// Admit a request of a client in the window shared by the instances if any, else in the
+    /// window of this instance
+    /// # Arguments
+    /// * `scope` - The API scope of the request
+    /// * `client` - The client key in the scope
+    /// * `now` - When the request arrived
+    /// # Errors
+    /// * Returns RateLimited once the window is used up or while the client is locked out
+    async fn admit_request(
+        &self,
+        scope: RateScope,
+        client: &str,
+        now: Instant,
+    ) -> Result<(), ApiError> {
+        #[cfg(feature = "redis")]
+        if let Some(redis) = &self.redis {
+            return self.admit_shared(redis, scope, client, now).await;
+        }
+        self.admit(scope, client, now)
+    }
+
+    /// Admit a request of a client, counting it against the window of the scope
+    /// # Arguments
+    /// * `scope` - The API scope of the request
+    /// * `client` - The client key in the scope
+    /// * `now` - When the request arrived
+    /// # Errors
+    /// * Returns RateLimited with the reason RATE_LIMITED once the window is used up, or
+    ///   AUTH_LOCKED_OUT while the client is locked out - the delay is the wait until admitted
+    fn admit(&self, scope: RateScope, client: &str, now: Instant) -> Result<(), ApiError> {
+        let Some((policy, clients)) = self.scopes.get(&scope) else {
+            return Ok(());
+        };
+        let mut clients = clients.lock().unwrap_or_else(|e| e.into_inner());
+        let counters = tracked(&mut clients, policy, client, now);
+
+        // STEP 1: Locked out clients wait for the end of the lockout
+        counters.check_lockout(scope, now)?;
+
+        // STEP 2: Count the request in the current window
+        if now.duration_since(counters.window_started) >= policy.window {
+            counters.window_started = now;
+            counters.requests = 0;
+        }
+        let Some(requests) = counters.tier.scale(policy.requests) else {
+            record_rate_limit(scope.as_str(), "allowed");
+            return Ok(());
+        };
+        if counters.requests >= requests {
+            record_rate_limit(scope.as_str(), "limited");
+            return Err(ApiError::RateLimited {
+                retry_after_s: seconds_until(now, counters.window_started + policy.window),
+                reason: ReasonCode::RateLimited,
+            });
+        }
+        counters.requests += 1;
+        record_rate_limit(scope.as_str(), "allowed");
+        Ok(())
+    }
+
+    /// Admit a request of a client, counting it against the window of the scope shared in Redis:
+    /// the lockout and tier stay with this instance, and the window of this instance takes over
+    /// while Redis does not answer
+    /// # Arguments
+    /// * `redis` - The windows shared by the instances
+    /// * `scope` - The API scope of the request
+    /// * `client` - The client key in the scope
+    /// * `now` - When the request arrived
+    /// # Errors
+    /// * Returns RateLimited with the reason RATE_LIMITED once the window is used up, or
+    ///   AUTH_LOCKED_OUT while the client is locked out - the delay is the wait until admitted
+    #[cfg(feature = "redis")]
+    async fn admit_shared(
+        &self,
+        redis: &RedisWindows,
+        scope: RateScope,
+        client: &str,
+        now: Instant,
+    ) -> Result<(), ApiError> {
+        let Some((policy, clients)) = self.scopes.get(&scope) else {
+            return Ok(());
+        };
+
+        // STEP 1: Locked out clients wait for the end of the lockout
+        let tier = {
+            let mut clients = clients.lock().unwrap_or_else(|e| e.into_inner());
+            let counters = tracked(&mut clients, policy, client, now);
+            counters.check_lockout(scope, now)?;
+            counters.tier
+        };
+
+        // STEP 2: Count the request in the shared window
+        let Some(requests) = tier.scale(policy.requests) else {
+            record_rate_limit(scope.as_str(), "allowed");
+            return Ok(());
+        };
+        match redis.count(scope, client, policy.window).await {
+            Ok((counted, remaining_s)) if counted > requests => {
+                record_rate_limit(scope.as_str(), "limited");
+                Err(ApiError::RateLimited {
+                    retry_after_s: remaining_s.max(1),
+                    reason: ReasonCode::RateLimited,
+                })
+            }
+            Ok(_) => {
+                record_rate_limit(scope.as_str(), "allowed");
+                Ok(())
+            }
+            Err(e) => {
+                warn!("Shared rate limit window unavailable, counting on this instance - {e}");
+                self.admit(scope, client, now)
+            }
+        }
+    }
+
+    /// Count the authentication outcome of an admitted request - a success clears the failures,
+    /// the failures of a scope with a lockout lock the client out once they reach its count
+    /// # Arguments
+    /// * `scope` - The API scope of the request
+    /// * `client` - The client key in the scope
+    /// * `refused` - Whether the credentials of the request were refused
+    /// * `now` - When the response was sent
+    fn record_authentication(&self, scope: RateScope, client: &str, refused: bool, now: Instant) {
+        let Some((policy, clients)) = self.scopes.get(&scope) else {
+            return;
+        };
+        let Some((failures, lockout)) = policy.lockout else {
+            return;
+        };
+        let mut clients = clients.lock().unwrap_or_else(|e| e.into_inner());
+        let Some(counters) = clients.get_mut(client) else {
+            return;
+        };
+        if !refused {
+            counters.failures = 0;
+            return;
+        }
+        counters.failures += 1;
+        if counters.failures >= failures {
+            counters.failures = 0;
+            counters.locked_until = Some(now + lockout);
+            record_rate_limit_lockout(scope.as_str());
+            warn!(target: "audit", "rate_limit_lockout scope={} client={client} failures={failures} lockout_s={}",
+                scope.as_str(), lockout.as_secs());
+        }
+    }
+
+    /// Remember the rate limit tier of a hospital authenticated from an address - the next
+    /// requests of the address are counted against the policy of the scope scaled by the tier
+    /// # Arguments
+    /// * `scope` - The API scope of the request
+    /// * `client` - The client key in the scope
+    /// * `tier` - The tier of the hospital
+    fn record_tier(&self, scope: RateScope, client: &str, tier: RateTier) {
+        let Some((_, clients)) = self.scopes.get(&scope) else {
+            return;
+        };
+        let mut clients = clients.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(counters) = clients.get_mut(client) {
+            counters.tier = tier;
+        }
+    }
+}
+
+impl ClientCounters {
+    /// Counters of a client seen for the first time
+    fn new(now: Instant) -> Self {
+        Self {
+            window_started: now,
+            requests: 0,
+            failures: 0,
+            locked_until: None,
+            tier: RateTier::Standard,
+        }
+    }
+
+    /// Refuse the requests of a locked out client until the end of its lockout
+    /// # Arguments
+    /// * `scope` - The API scope of the request
+    /// * `now` - When the request arrived
+    /// # Errors
+    /// * Returns RateLimited with the reason AUTH_LOCKED_OUT while the client is locked out
+    fn check_lockout(&self, scope: RateScope, now: Instant) -> Result<(), ApiError> {
+        match self.locked_until.filter(|until| *until > now) {
+            Some(locked_until) => {
+                record_rate_limit(scope.as_str(), "locked_out");
+                Err(ApiError::RateLimited {
+                    retry_after_s: seconds_until(now, locked_until),
+                    reason: ReasonCode::AuthLockedOut,
+                })
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// Whether the counters still matter: current window, failures or lockout pending
+    fn is_active(&self, policy: &RatePolicy, now: Instant) -> bool {
+        now.duration_since(self.window_started) < policy.window
+            || self.failures > 0
+            || self.locked_until.is_some_and(|until| until > now)
+    }
+}
+
+// MAIN FUNCTION ***********************************************************************************
+/// Apply the rate limit of the API scope of every request, before its authentication, scaled by
+/// the rate limit tier of the hospital, and lock out the clients of a scope that keep failing
+/// authentication
+/// # Arguments
+/// * `req` - The incoming request
+/// * `next` - The rest of the service chain
+/// # Returns
+/// * The response of the handler, or 429 with 'Retry-After' when the client is over the limit of
+///   the scope (`RATE_LIMITED`) or locked out (`AUTH_LOCKED_OUT`)
+pub async fn rate_limit_middleware(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<EitherBody<impl MessageBody>>, Error> {
+    let limiter = req.app_data::<web::Data<Arc<RateLimiter>>>().cloned();
+    let (Some(limiter), Some(scope)) = (limiter, RateScope::of_path(req.path())) else {
+        return Ok(next.call(req).await?.map_into_left_body());
+    };
+
+    // STEP 1: Admit the request in the scope
+    let client = format!(
+        "ip:{}",
+        client_address(req.request(), limiter.trusted_proxy_hops)
+    );
+    if let Err(e) = limiter.admit_request(scope, &client, Instant::now()).await {
+        warn!("Rate limited - {} ({}): {client}", req.path(), e.reason());
+        return Ok(req.into_response(e.error_response()).map_into_right_body());
+    }
+
+    // STEP 2: Count its authentication outcome for the lockout, and remember the rate limit tier
+    // of the authenticated hospital
+    let response = next.call(req).await?;
+    let refused = response.status() == StatusCode::UNAUTHORIZED;
+    limiter.record_authentication(scope, &client, refused, Instant::now());
+    let tier = response
+        .request()
+        .extensions()
+        .get::<AuthenticatedHospital>()
+        .map(|hospital| hospital.rate_tier);
+    if let Some(tier) = tier {
+        limiter.record_tier(scope, &client, tier);
+    }
+    Ok(response.map_into_left_body())
+}
+
+// SUPPORTING FUNCTIONS ****************************************************************************
+/// Address of the client of a request - the one the outermost trusted proxy received it from,
+/// so that the client cannot choose it
+/// - Without trusted proxies, the peer of the connection
+/// - Behind `trusted_proxy_hops` proxies, the X-Forwarded-For entry appended by the outermost
+///   one, counting from the right; the entries left of it are chosen by the client
+/// # Arguments
+/// * `req` - The incoming request
+/// * `trusted_proxy_hops` - The proxies appending to X-Forwarded-For in front of the service
+/// # Returns
+/// * The client address, the peer of the connection when X-Forwarded-For has fewer entries than
+///   trusted proxies
+pub(crate) fn client_address(req: &HttpRequest, trusted_proxy_hops: usize) -> String {
+    let peer = || {
+        req.peer_addr()
+            .map_or("unknown".to_string(), |addr| addr.ip().to_string())
+    };
+    if trusted_proxy_hops == 0 {
+        return peer();
+    }
+    let forwarded: Vec<&str> = req
+        .headers()
+        .get_all("x-forwarded-for")
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .map(str::trim)
+        .collect();
+    forwarded
+        .len()
+        .checked_sub(trusted_proxy_hops)
+        .and_then(|index| forwarded.get(index))
+        .filter(|address| !address.is_empty())
+        .map_or_else(peer, |address| address.to_string())
+}
+
+/// Policies of a RATE_LIMITS declaration, e.g. `admin:10/60:3/1800,ingest:off` - scopes not
+/// listed keep DEFAULT_RATE_LIMITS, `off` disables the limit of a scope
+/// # Arguments
+/// * `raw` - Comma separated `scope:policy` entries
+/// # Errors
+/// * Returns an error if an entry names an unknown scope or a malformed policy
+pub(crate) fn parse_rate_limits(raw: &str) -> Result<BTreeMap<RateScope, RatePolicy>> {
+    let mut policies = BTreeMap::new();
+    for entry in DEFAULT_RATE_LIMITS
+        .split(',')
+        .chain(raw.split(','))
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+    {
+        let (scope, policy) = entry
+            .split_once(':')
+            .ok_or_else(|| anyhow!("Rate limit '{entry}' is not scope:policy"))?;
+        let scope: RateScope = scope.trim().parse()?;
+        match policy.trim() {
+            "off" => policies.remove(&scope),
+            policy => policies.insert(scope, policy.parse::<RatePolicy>()?),
+        };
+    }
+    Ok(policies)
+}
+
+/// Parse `count/seconds`
+fn count_per_seconds(raw: &str) -> Result<(u32, Duration)> {
+    let (count, seconds) = raw
+        .split_once('/')
+        .ok_or_else(|| anyhow!("Rate '{raw}' is not count/seconds"))?;
+    let count: u32 = count.trim().parse().context(format!("Rate '{raw}'"))?;
+    let seconds: u64 = seconds.trim().parse().context(format!("Rate '{raw}'"))?;
+    if count == 0 || seconds == 0 {
+        return Err(anyhow!("Rate '{raw}' must be positive"));
+    }
+    Ok((count, Duration::from_secs(seconds)))
+}
+
+/// Counters of a client in a scope, created on its first request - the idle clients are
+/// forgotten once MAX_TRACKED_CLIENTS are tracked
+fn tracked<'a>(
+    clients: &'a mut HashMap<String, ClientCounters>,
+    policy: &RatePolicy,
+    client: &str,
+    now: Instant,
+) -> &'a mut ClientCounters {
+    if clients.len() >= MAX_TRACKED_CLIENTS {
+        clients.retain(|_, counters| counters.is_active(policy, now));
+    }
+    clients
+        .entry(client.to_string())
+        .or_insert_with(|| ClientCounters::new(now))
+}
+
+/// Whole seconds until an instant, at least 1
+fn seconds_until(now: Instant, until: Instant) -> u64 {
+    until.duration_since(now).as_secs_f64().ceil().max(1.0) as u64
+}
+
+// TESTS *******************************************************************************************
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn reason(result: Result<(), ApiError>) -> Option<ReasonCode> {
+        result.err().map(|e| e.reason())
+    }
+
+    fn limiter(raw: &str) -> Result<Arc<RateLimiter>> {
+        Ok(Arc::new(RateLimiter::with_policies(
+            &parse_rate_limits(raw)?,
+            0,
+        )))
+    }
+
+    // Happy path: declared policies override the defaults, scope by scope
+    #[test]
+    fn policies_declared() {
+        let limiter = limiter("admin:10/60:3/1800, ingest:off").unwrap();
+        assert!(!limiter.scopes.contains_key(&RateScope::Ingest));
+        assert_eq!(
+            limiter.scopes[&RateScope::Admin].0,
+            RatePolicy {
+                requests: 10,
+                window: Duration::from_secs(60),
+                lockout: Some((3, Duration::from_secs(1800))),
+            }
+        );
+        assert_eq!(limiter.scopes[&RateScope::Internal].0.requests, 300);
+        assert!(parse_rate_limits("billing:1/1").is_err());
+        assert!(parse_rate_limits("admin:0/60").is_err());
+    }
+
+    // Happy path: a client over the window waits for the next one, other scopes are unaffected
+    #[test]
+    fn window_limited_per_scope() {
+        let limiter = limiter("admin:2/60").unwrap();
+        let now = Instant::now();
+        assert!(limiter.admit(RateScope::Admin, "ip:a", now).is_ok());
+        assert!(limiter.admit(RateScope::Admin, "ip:a", now).is_ok());
+        let refused = limiter.admit(RateScope::Admin, "ip:a", now + Duration::from_secs(15));
+        assert!(matches!(
+            refused,
+            Err(ApiError::RateLimited {
+                retry_after_s: 45,
+                reason: ReasonCode::RateLimited
+            })
+        ));
+        assert!(limiter.admit(RateScope::Internal, "ip:a", now).is_ok());
+        assert!(limiter.admit(RateScope::Admin, "ip:b", now).is_ok());
+        let next_window = now + Duration::from_secs(60);
+        assert!(limiter.admit(RateScope::Admin, "ip:a", next_window).is_ok());
+    }
+
+    // Error handling: consecutive authentication failures lock the client out of the scope only
+    #[test]
+    fn lockout_after_failures() {
+        let limiter = limiter("admin:100/60:2/900").unwrap();
+        let now = Instant::now();
+        for refused in [true, false, true, true] {
+            assert!(limiter.admit(RateScope::Admin, "ip:a", now).is_ok());
+            limiter.record_authentication(RateScope::Admin, "ip:a", refused, now);
+        }
+        assert_eq!(
+            reason(limiter.admit(RateScope::Admin, "ip:a", now)),
+            Some(ReasonCode::AuthLockedOut)
+        );
+        assert!(limiter.admit(RateScope::Ingest, "ip:a", now).is_ok());
+        let after = now + Duration::from_secs(900);
+        assert!(limiter.admit(RateScope::Admin, "ip:a", after).is_ok());
+    }
+
+    // Happy path: the tier of the hospital authenticated from an address scales its window,
+    // unlimited skips it
+    #[test]
+    fn window_scaled_by_tier() {
+        let limiter = limiter("ingest:1/60").unwrap();
+        let now = Instant::now();
+        assert!(limiter.admit(RateScope::Ingest, "ip:a", now).is_ok());
+        assert!(limiter.admit(RateScope::Ingest, "ip:a", now).is_err());
+        limiter.record_tier(RateScope::Ingest, "ip:a", RateTier::Elevated);
+        for _ in 0..3 {
+            assert!(limiter.admit(RateScope::Ingest, "ip:a", now).is_ok());
+        }
+        assert!(limiter.admit(RateScope::Ingest, "ip:a", now).is_err());
+        limiter.record_tier(RateScope::Ingest, "ip:a", RateTier::Unlimited);
+        assert!(limiter.admit(RateScope::Ingest, "ip:a", now).is_ok());
+    }
+
+    // Error handling: the client cannot choose its address through forwarding headers
+    #[test]
+    fn client_address_not_spoofed() {
+        let peer = "10.0.0.9:4000".parse().unwrap();
+        let request = |forwarded: &[&str]| {
+            let mut request = actix_web::test::TestRequest::default()
+                .peer_addr(peer)
+                .insert_header(("forwarded", "for=1.1.1.1"))
+                .insert_header(("x-real-ip", "2.2.2.2"));
+            for value in forwarded {
+                request = request.append_header(("x-forwarded-for", *value));
+            }
+            request.to_http_request()
+        };
+        // Without trusted proxies, only the connection counts
+        assert_eq!(client_address(&request(&["3.3.3.3"]), 0), "10.0.0.9");
+        // Behind one proxy, the entry it appended - not the ones the client sent before it
+        let rotated = request(&["6.6.6.6, 7.7.7.7", "8.8.8.8"]);
+        assert_eq!(client_address(&rotated, 1), "8.8.8.8");
+        assert_eq!(client_address(&rotated, 2), "7.7.7.7");
+        // Fewer entries than proxies: the request did not come through them
+        assert_eq!(client_address(&request(&[]), 1), "10.0.0.9");
+    }
+
+    // Borderline: probes are never limited, the monitoring endpoints have their own scope
+    #[test]
+    fn scope_of_paths() {
+        assert_eq!(RateScope::of_path("/v1/health_check"), None);
+        assert_eq!(RateScope::of_path("/v1/ecg_exam"), Some(RateScope::Ingest));
+        assert_eq!(
+            RateScope::of_path("/internal/v1/metrics"),
+            Some(RateScope::Internal)
+        );
+        assert_eq!(
+            RateScope::of_path("/internal/v1/audit_events"),
+            Some(RateScope::Admin)
+        );
+        assert_eq!(
+            RateScope::of_path("/v1/admin/hospitals"),
+            Some(RateScope::Admin)
+        );
+        // Paths no route matches count against their API
+        assert_eq!(RateScope::of_path("/v1/admin/nope"), Some(RateScope::Admin));
+        assert_eq!(RateScope::of_path("/v1/nope"), Some(RateScope::Ingest));
+    }
+}
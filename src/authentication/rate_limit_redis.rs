@@ -0,0 +1,105 @@
+// Imports *****************************************************************************************
+// External Crates
+use anyhow::{anyhow, Result};
+use redis::aio::ConnectionManager;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+// Internal Modules
+use crate::authentication::rate_limit::RateScope;
+use crate::utils::external_call::{Dependency, ExternalCall};
+
+// MAIN STRUCT *************************************************************************************
+/// Request windows of the rate limits counted in Redis (RATE_LIMIT_REDIS_URL), so that every
+/// instance behind the load balancer draws from the same budget of a client
+/// - Windows are aligned on the epoch, the key of a window expires with it
+/// - Lockouts and tiers stay with the instance that saw the authentication
+pub struct RedisWindows {
+    connection: ConnectionManager,
+}
+
+impl RedisWindows {
+    /// Connect to Redis - the connection is re-established in the background when it drops
+    /// # Arguments
+    /// * `url` - The Redis URL, e.g. `redis://:password@10.0.0.5:6379/0`
+    /// # Errors
+    /// * Returns an error if the URL is malformed or Redis cannot be reached
+    pub async fn connect(url: &str) -> Result<Self> {
+        let client =
+            redis::Client::open(url).map_err(|e| anyhow!("Invalid RATE_LIMIT_REDIS_URL: {e}"))?;
+        let connection = ExternalCall::new(Dependency::Redis, "connect")
+            .timeout(Duration::from_secs(5))
+            .run(|| ConnectionManager::new(client.clone()))
+            .await?;
+        Ok(Self { connection })
+    }
+
+    /// Count a request of a client in the current window of its scope
+    /// # Arguments
+    /// * `scope` - The API scope of the request
+    /// * `client` - The client key in the scope
+    /// * `window` - The window of the policy of the scope
+    /// # Returns
+    /// * The requests of the client in the window, this one included, and the seconds until the
+    ///   window ends
+    /// # Errors
+    /// * Returns an error if Redis does not answer in time
+    pub async fn count(
+        &self,
+        scope: RateScope,
+        client: &str,
+        window: Duration,
+    ) -> Result<(u32, u64)> {
+        let (key, remaining_s) = window_key(scope, client, window, SystemTime::now());
+        let (requests,): (u32,) = ExternalCall::new(Dependency::Redis, "count_request")
+            .run(|| {
+                let mut connection = self.connection.clone();
+                let key = key.clone();
+                async move {
+                    redis::pipe()
+                        .atomic()
+                        .incr(&key, 1)
+                        .expire(&key, window.as_secs() as i64)
+                        .ignore()
+                        .query_async(&mut connection)
+                        .await
+                }
+            })
+            .await?;
+        Ok((requests, remaining_s))
+    }
+}
+
+// SUPPORTING FUNCTIONS ****************************************************************************
+/// Key of the window of a client at an instant, and the seconds until the window ends
+fn window_key(scope: RateScope, client: &str, window: Duration, now: SystemTime) -> (String, u64) {
+    let now_s = now.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+    let window_s = window.as_secs().max(1);
+    let index = now_s / window_s;
+    (
+        format!("rate_limit:{}:{client}:{index}", scope.as_str()),
+        (index + 1) * window_s - now_s,
+    )
+}
+
+// TESTS *******************************************************************************************
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Happy path: the instants of one window share a key, the next window starts a new one
+    #[test]
+    fn windows_aligned() {
+        let window = Duration::from_secs(60);
+        let at = |s: u64| UNIX_EPOCH + Duration::from_secs(s);
+        let (key, remaining_s) = window_key(RateScope::Admin, "ip:a", window, at(6_015));
+        assert_eq!(key, "rate_limit:admin:ip:a:100");
+        assert_eq!(remaining_s, 45);
+        assert_eq!(
+            window_key(RateScope::Admin, "ip:a", window, at(6_059)).0,
+            key
+        );
+        let (next, remaining_s) = window_key(RateScope::Admin, "ip:a", window, at(6_060));
+        assert_eq!(next, "rate_limit:admin:ip:a:101");
+        assert_eq!(remaining_s, 60);
+    }
+}
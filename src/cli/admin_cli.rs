@@ -0,0 +1,574 @@
+// Imports *****************************************************************************************
+// External Crates
+use anyhow::{anyhow, Result};
+use chrono::{Duration, Utc};
+use log::info;
+use serde::de::DeserializeOwned;
+use serde_json::{json, Map, Value};
+use sqlx::PgPool;
+use std::time::Duration as StdDuration;
+use validator::Validate;
+
+// Internal Modules
+use crate::audit::audit_trail::{flush_audit_trail, start_audit_trail, unpublished_exams};
+use crate::authentication::auth::connect_to_database;
+use crate::authentication::gcp_identity::{GcpIdentity, GcsAccess};
+use crate::config::settings::Settings;
+use crate::models::models_hospital_admin::{HospitalCreate, HospitalUpdate, KeyRotation};
+use crate::publishers::publisher::publisher_from_settings;
+use crate::services::service_hospital_registry::{create_hospital, rotate_keys, update_hospital};
+use crate::services::service_message_format::init_message_formats;
+use crate::services::service_migrations::{
+    apply_migrations, baseline_migrations, migration_status,
+};
+use crate::services::service_queue_admin::{ItemFilter, QueueAdmin, QueueStore, Selection};
+use crate::storage::exam_storage::storage_from_settings;
+
+// Constants ***************************************************************************************
+/// Usage printed by `admin help` and on a malformed command
+const USAGE: &str = "\
+Usage: sentinela_exam_receiver admin <command> [arguments]
+
+Commands, run against the backends of the environment (DB_*, STORAGE_BACKEND, PUBLISH_BACKEND):
+  create-hospital <hospital_id> [--consent-scope S] [--size-tier T] [--rate-limit-tier T]
+      [--publish-mode M] [--allowed-exam-types a,b] [--monthly-exam-quota N]
+      [--key-expires-at RFC3339]
+                      Register a hospital and print its first key - shown only once
+  rotate-key <hospital_id> [--grace-s N] [--expires-at RFC3339]
+                      Issue a new key, the active ones expire after the grace period
+  pause-hospital <hospital_id> [--resume]
+                      Refuse the exams of a hospital with 403, or accept them again
+  replay [<id>...] [--exam-type T] [--hospital-id H] [--stage S] [--reason R] [--before RFC3339]
+      [--limit N] [--dry-run]
+                      Publish again the dead letters of stored exams, by id or filter
+  reconcile [--since-s N] [--settle-s N] [--limit N]
+                      List the exams stored whose notification was never published
+  migrate [--status] [--dry-run] [--baseline VERSION]
+                      Apply the pending migrations embedded in the binary
+  help                Print this message";
+/// Window of a reconciliation when --since-s is not given - 7 days
+const DEFAULT_RECONCILE_SINCE_S: i64 = 604_800;
+/// Age of the most recent exams reconciled when --settle-s is not given - their notification may
+/// still be in flight
+const DEFAULT_RECONCILE_SETTLE_S: i64 = 3_600;
+/// Exams listed by a reconciliation when --limit is not given
+const DEFAULT_RECONCILE_LIMIT: i64 = 1_000;
+/// Longest wait for the audit events of a command to be written before exiting
+const AUDIT_FLUSH_DEADLINE: StdDuration = StdDuration::from_secs(10);
+
+// Structs *****************************************************************************************
+/// Kind of the value of a flag
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Flag {
+    /// A string
+    Text,
+    /// An unsigned integer
+    Number,
+    /// A comma-separated list of strings
+    List,
+    /// No value: true when present
+    Switch,
+}
+
+/// Operator command, parsed from the arguments after `admin`
+#[derive(Debug)]
+pub enum AdminCommand {
+    CreateHospital(HospitalCreate),
+    RotateKey {
+        hospital_id: String,
+        rotation: KeyRotation,
+    },
+    PauseHospital {
+        hospital_id: String,
+        paused: bool,
+    },
+    Replay {
+        selection: Selection,
+        dry_run: bool,
+    },
+    Reconcile {
+        since_s: i64,
+        settle_s: i64,
+        limit: i64,
+    },
+    Migrate {
+        status: bool,
+        dry_run: bool,
+        baseline: Option<String>,
+    },
+    Help,
+}
+
+// MAIN FUNCTIONS **********************************************************************************
+/// Run an operator command against the configured backends and exit - the library services are
+/// called directly, as the admin API would, and every change is audited with the operator name
+/// # Arguments
+/// * `settings` - The loaded settings
+/// * `args` - The arguments after `admin`
+/// # Errors
+/// * Returns an error if the command is malformed or fails - the process then exits non-zero
+pub async fn run_admin_cli(settings: &Settings, args: &[String]) -> std::io::Result<()> {
+    let command = match parse_command(args) {
+        Ok(command) => command,
+        Err(e) => {
+            eprintln!("{e}\n\n{USAGE}");
+            return Err(std::io::Error::other(e.to_string()));
+        }
+    };
+    let output = run_command(settings, command)
+        .await
+        .map_err(|e| std::io::Error::other(e.to_string()))?;
+    if let Some(output) = output {
+        println!("{}", serde_json::to_string_pretty(&output)?);
+    }
+    Ok(())
+}
+
+/// Parse an operator command
+/// # Arguments
+/// * `args` - The arguments after `admin`
+/// # Errors
+/// * Returns an error for an unknown command or flag, a missing or malformed value
+pub fn parse_command(args: &[String]) -> Result<AdminCommand> {
+    let Some((command, args)) = args.split_first() else {
+        return Ok(AdminCommand::Help);
+    };
+    match command.as_str() {
+        "create-hospital" => {
+            let (hospital_id, mut fields) = with_hospital_id(
+                args,
+                &[
+                    ("consent-scope", Flag::Text),
+                    ("size-tier", Flag::Text),
+                    ("rate-limit-tier", Flag::Text),
+                    ("publish-mode", Flag::Text),
+                    ("allowed-exam-types", Flag::List),
+                    ("monthly-exam-quota", Flag::Number),
+                    ("key-expires-at", Flag::Text),
+                ],
+            )?;
+            fields.insert("hospital_id".to_string(), Value::String(hospital_id));
+            let create: HospitalCreate = from_fields(fields)?;
+            create.validate()?;
+            Ok(AdminCommand::CreateHospital(create))
+        }
+        "rotate-key" => {
+            let (hospital_id, fields) = with_hospital_id(
+                args,
+                &[("grace-s", Flag::Number), ("expires-at", Flag::Text)],
+            )?;
+            Ok(AdminCommand::RotateKey {
+                hospital_id,
+                rotation: from_fields(fields)?,
+            })
+        }
+        "pause-hospital" => {
+            let (hospital_id, fields) = with_hospital_id(args, &[("resume", Flag::Switch)])?;
+            Ok(AdminCommand::PauseHospital {
+                hospital_id,
+                paused: !switch(&fields, "resume"),
+            })
+        }
+        "replay" => {
+            let (ids, mut fields) = parse_flags(
+                args,
+                &[
+                    ("exam-type", Flag::Text),
+                    ("hospital-id", Flag::Text),
+                    ("stage", Flag::Text),
+                    ("reason", Flag::Text),
+                    ("before", Flag::Text),
+                    ("limit", Flag::Number),
+                    ("dry-run", Flag::Switch),
+                ],
+            )?;
+            let dry_run = switch(&fields, "dry-run");
+            fields.remove("dry_run");
+            // Explicit ids, or every dead letter matching the filter
+            let filter = match (ids.is_empty(), fields.is_empty()) {
+                (true, _) => Some(from_fields::<ItemFilter>(fields)?),
+                (false, true) => None,
+                (false, false) => return Err(anyhow!("Replay either ids or a filter, not both")),
+            };
+            Ok(AdminCommand::Replay {
+                selection: Selection { ids, filter },
+                dry_run,
+            })
+        }
+        "reconcile" => {
+            let (positionals, fields) = parse_flags(
+                args,
+                &[
+                    ("since-s", Flag::Number),
+                    ("settle-s", Flag::Number),
+                    ("limit", Flag::Number),
+                ],
+            )?;
+            no_positionals(&positionals)?;
+            Ok(AdminCommand::Reconcile {
+                since_s: number(&fields, "since_s").unwrap_or(DEFAULT_RECONCILE_SINCE_S),
+                settle_s: number(&fields, "settle_s").unwrap_or(DEFAULT_RECONCILE_SETTLE_S),
+                limit: number(&fields, "limit").unwrap_or(DEFAULT_RECONCILE_LIMIT),
+            })
+        }
+        "migrate" => {
+            let (positionals, fields) = parse_flags(
+                args,
+                &[
+                    ("status", Flag::Switch),
+                    ("dry-run", Flag::Switch),
+                    ("baseline", Flag::Text),
+                ],
+            )?;
+            no_positionals(&positionals)?;
+            Ok(AdminCommand::Migrate {
+                status: switch(&fields, "status"),
+                dry_run: switch(&fields, "dry-run"),
+                baseline: fields
+                    .get("baseline")
+                    .and_then(Value::as_str)
+                    .map(str::to_string),
+            })
+        }
+        "help" | "--help" | "-h" => Ok(AdminCommand::Help),
+        other => Err(anyhow!("Unknown admin command: {other}")),
+    }
+}
+
+// SUPPORT FUNCTIONS *******************************************************************************
+/// Run a parsed command
+/// # Returns
+/// * The JSON document printed to stdout, None for the usage
+async fn run_command(settings: &Settings, command: AdminCommand) -> Result<Option<Value>> {
+    if let AdminCommand::Help = command {
+        println!("{USAGE}");
+        return Ok(None);
+    }
+
+    // STEP 1: Every command runs against the registry database, and audits what it changes
+    let pool = connect_to_database(&settings.database).await?;
+    start_audit_trail(pool.clone());
+    let operator = operator();
+
+    // STEP 2: Run the command
+    let output = match command {
+        AdminCommand::CreateHospital(create) => {
+            let key = create_hospital(&pool, &create)
+                .await?
+                .ok_or_else(|| anyhow!("Hospital {} already registered", create.hospital_id))?;
+            info!(target: "audit", "hospital_created hospital_id={} via=cli operator={operator}", create.hospital_id);
+            json!(key)
+        }
+        AdminCommand::RotateKey {
+            hospital_id,
+            rotation,
+        } => {
+            let (key, retiring) = rotate_keys(&pool, &hospital_id, &rotation)
+                .await?
+                .ok_or_else(|| anyhow!("Hospital {hospital_id} not registered"))?;
+            info!(target: "audit", "hospital_keys_rotated hospital_id={hospital_id} retiring={} via=cli operator={operator}", retiring.join(","));
+            json!({ "key": key, "retiring_key_ids": retiring })
+        }
+        AdminCommand::PauseHospital {
+            hospital_id,
+            paused,
+        } => {
+            let update = HospitalUpdate {
+                paused: Some(paused),
+                ..Default::default()
+            };
+            if !update_hospital(&pool, &hospital_id, &update).await? {
+                return Err(anyhow!("Hospital {hospital_id} not registered"));
+            }
+            info!(target: "audit", "hospital_updated hospital_id={hospital_id} paused={paused} via=cli operator={operator}");
+            json!({ "hospital_id": hospital_id, "paused": paused })
+        }
+        AdminCommand::Replay { selection, dry_run } => {
+            replay(settings, &selection, dry_run, &operator).await?
+        }
+        AdminCommand::Reconcile {
+            since_s,
+            settle_s,
+            limit,
+        } => reconcile(&pool, since_s, settle_s, limit).await?,
+        AdminCommand::Migrate {
+            status,
+            dry_run,
+            baseline,
+        } => migrate(&pool, status, dry_run, baseline.as_deref(), &operator).await?,
+        AdminCommand::Help => Value::Null,
+    };
+
+    // STEP 3: The process exits next - write the queued audit events first
+    let unwritten = flush_audit_trail(AUDIT_FLUSH_DEADLINE).await;
+    if unwritten > 0 {
+        eprintln!("{unwritten} audit events not written to the trail - see the audit_trail logs");
+    }
+    Ok(Some(output))
+}
+
+/// Publish again the dead letters of stored exams - the ones of the local DEAD_LETTER_DIR and of
+/// DEAD_LETTER_BUCKET
+async fn replay(
+    settings: &Settings,
+    selection: &Selection,
+    dry_run: bool,
+    operator: &str,
+) -> Result<Value> {
+    // STEP 1: The storage and notification backends of the gateway
+    let identity = GcpIdentity::from_settings(&settings.gcp)?;
+    let pubsub = crate::init_pubsub_clients(&identity)
+        .await
+        .map_err(|e| anyhow!(e.to_string()))?;
+    init_message_formats(settings.publish.message_formats.clone());
+    let storage =
+        storage_from_settings(&settings.storage, &settings.gcp, GcsAccess::ReadWrite).await?;
+    let publisher = publisher_from_settings(&settings.publish, &pubsub).await?;
+    let queue_admin = QueueAdmin::dead_letters_only(&settings.storage, &storage, publisher);
+
+    // STEP 2: The dead letters selected - listed only in a dry run
+    if dry_run {
+        let filter = selection.filter.clone().unwrap_or_default();
+        let mut items = Vec::new();
+        if selection.ids.is_empty() {
+            items = queue_admin.list(QueueStore::DeadLetter, &filter).await?;
+        }
+        for id in &selection.ids {
+            items.extend(queue_admin.peek(QueueStore::DeadLetter, id).await?);
+        }
+        return Ok(json!({ "dry_run": true, "items": items }));
+    }
+    let ids = queue_admin
+        .select(QueueStore::DeadLetter, selection)
+        .await
+        .map_err(|e| anyhow!(e.to_string()))?;
+
+    // STEP 3: Publish them again - each requeue is audited by the queue admin
+    let outcomes = queue_admin.requeue(QueueStore::DeadLetter, &ids).await;
+    let requeued = outcomes.iter().filter(|o| o.outcome == "requeued").count();
+    info!(target: "audit", "queue_requeued store=dead_letter items={} requeued={requeued} via=cli operator={operator}", ids.len());
+    Ok(json!({ "requeued": requeued, "outcomes": outcomes }))
+}
+
+/// List the exams stored whose notification was never settled, from the audit trail
+async fn reconcile(pool: &PgPool, since_s: i64, settle_s: i64, limit: i64) -> Result<Value> {
+    let now = Utc::now();
+    let from = now - Duration::seconds(since_s);
+    let to = now - Duration::seconds(settle_s);
+    let exams = unpublished_exams(pool, from, to, limit).await?;
+    Ok(json!({
+        "from": from,
+        "to": to,
+        "unpublished": exams.len(),
+        "exams": exams,
+    }))
+}
+
+/// Show, baseline or apply the migrations
+async fn migrate(
+    pool: &PgPool,
+    status: bool,
+    dry_run: bool,
+    baseline: Option<&str>,
+    operator: &str,
+) -> Result<Value> {
+    if status {
+        return Ok(json!({ "migrations": migration_status(pool).await? }));
+    }
+    if let Some(version) = baseline {
+        let recorded = baseline_migrations(pool, version).await?;
+        info!(target: "audit", "migrations_baselined version={version} recorded={} via=cli operator={operator}", recorded.len());
+        return Ok(json!({ "baselined": recorded }));
+    }
+    let applied = apply_migrations(pool, dry_run).await?;
+    if !dry_run {
+        info!(target: "audit", "migrations_applied applied={} via=cli operator={operator}", applied.len());
+    }
+    Ok(json!({ "dry_run": dry_run, "migrations": applied }))
+}
+
+/// Name of the operator running the command, for the audit lines
+fn operator() -> String {
+    ["SUDO_USER", "USER", "LOGNAME"]
+        .iter()
+        .find_map(|var| std::env::var(var).ok().filter(|name| !name.is_empty()))
+        .unwrap_or_else(|| "unknown".to_string())
+}
+
+/// Split the hospital id, first positional argument, from the flags
+fn with_hospital_id(
+    args: &[String],
+    known: &[(&str, Flag)],
+) -> Result<(String, Map<String, Value>)> {
+    let (positionals, fields) = parse_flags(args, known)?;
+    match positionals.as_slice() {
+        [hospital_id] => Ok((hospital_id.clone(), fields)),
+        [] => Err(anyhow!("Missing hospital id")),
+        _ => Err(anyhow!("Expected a single hospital id")),
+    }
+}
+
+/// Parse `--name value`, `--name=value` and switches into a JSON object keyed by snake_case name
+/// - the positional arguments are returned apart
+fn parse_flags(
+    args: &[String],
+    known: &[(&str, Flag)],
+) -> Result<(Vec<String>, Map<String, Value>)> {
+    let mut positionals = Vec::new();
+    let mut fields = Map::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let Some(flag) = arg.strip_prefix("--") else {
+            positionals.push(arg.clone());
+            continue;
+        };
+        let (name, inline) = match flag.split_once('=') {
+            Some((name, value)) => (name, Some(value.to_string())),
+            None => (flag, None),
+        };
+        let kind = known
+            .iter()
+            .find(|(known, _)| *known == name)
+            .map(|(_, kind)| *kind)
+            .ok_or_else(|| anyhow!("Unknown flag --{name}"))?;
+        let value = match (kind, inline) {
+            (Flag::Switch, None) => Value::Bool(true),
+            (Flag::Switch, Some(_)) => return Err(anyhow!("--{name} takes no value")),
+            (kind, inline) => {
+                let raw = inline
+                    .or_else(|| args.next().cloned())
+                    .ok_or_else(|| anyhow!("Missing value of --{name}"))?;
+                match kind {
+                    Flag::Number => Value::from(
+                        raw.parse::<u64>()
+                            .map_err(|_| anyhow!("--{name} must be a positive integer"))?,
+                    ),
+                    Flag::List => Value::from(
+                        raw.split(',')
+                            .map(str::trim)
+                            .filter(|item| !item.is_empty())
+                            .collect::<Vec<_>>(),
+                    ),
+                    _ => Value::String(raw),
+                }
+            }
+        };
+        fields.insert(name.replace('-', "_"), value);
+    }
+    Ok((positionals, fields))
+}
+
+/// Deserialize the flags into the body the admin API takes, so both are validated the same way
+fn from_fields<T: DeserializeOwned>(fields: Map<String, Value>) -> Result<T> {
+    serde_json::from_value(Value::Object(fields)).map_err(|e| anyhow!("Invalid arguments: {e}"))
+}
+
+/// Whether a switch is set
+fn switch(fields: &Map<String, Value>, name: &str) -> bool {
+    fields
+        .get(&name.replace('-', "_"))
+        .is_some_and(|v| v == &Value::Bool(true))
+}
+
+/// Value of a number flag
+fn number(fields: &Map<String, Value>, name: &str) -> Option<i64> {
+    fields
+        .get(name)
+        .and_then(Value::as_u64)
+        .map(|n| n.min(i64::MAX as u64) as i64)
+}
+
+/// Refuse positional arguments to a command that takes none
+fn no_positionals(positionals: &[String]) -> Result<()> {
+    match positionals.first() {
+        Some(arg) => Err(anyhow!("Unexpected argument {arg}")),
+        None => Ok(()),
+    }
+}
+
+// TESTS *******************************************************************************************
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::models_hospital_admin::ExamType;
+    use crate::models::models_publish_modes::PublishMode;
+    use crate::services::service_dead_letter::FailedStage;
+
+    fn args(line: &str) -> Vec<String> {
+        line.split_whitespace().map(str::to_string).collect()
+    }
+
+    // Happy path: create-hospital flags are read as the admin API body
+    #[test]
+    fn create_hospital_parsed() {
+        let command = parse_command(&args(
+            "create-hospital h1 --publish-mode after_storage --allowed-exam-types ecg_exam,xray_exam \
+             --monthly-exam-quota=500",
+        ))
+        .unwrap();
+        let AdminCommand::CreateHospital(create) = command else {
+            panic!("not a create-hospital");
+        };
+        assert_eq!(create.hospital_id, "h1");
+        assert_eq!(create.publish_mode, Some(PublishMode::AfterStorage));
+        assert_eq!(
+            create.allowed_exam_types,
+            Some(vec![ExamType::EcgExam, ExamType::XrayExam])
+        );
+        assert_eq!(create.monthly_exam_quota, Some(500));
+    }
+
+    // Happy path: pause and resume, replay by filter, migrate switches
+    #[test]
+    fn commands_parsed() {
+        assert!(matches!(
+            parse_command(&args("pause-hospital h1")).unwrap(),
+            AdminCommand::PauseHospital { paused: true, .. }
+        ));
+        assert!(matches!(
+            parse_command(&args("pause-hospital h1 --resume")).unwrap(),
+            AdminCommand::PauseHospital { paused: false, .. }
+        ));
+        let AdminCommand::Replay { selection, dry_run } =
+            parse_command(&args("replay --stage publish --hospital-id h1 --dry-run")).unwrap()
+        else {
+            panic!("not a replay");
+        };
+        assert!(dry_run);
+        let filter = selection.filter.unwrap();
+        assert_eq!(filter.stage, Some(FailedStage::Publish));
+        assert_eq!(filter.hospital_id.as_deref(), Some("h1"));
+        assert!(matches!(
+            parse_command(&args("migrate --baseline 20261026")).unwrap(),
+            AdminCommand::Migrate { baseline: Some(v), status: false, .. } if v == "20261026"
+        ));
+        assert!(matches!(
+            parse_command(&args("reconcile --settle-s 60")).unwrap(),
+            AdminCommand::Reconcile {
+                settle_s: 60,
+                since_s: DEFAULT_RECONCILE_SINCE_S,
+                ..
+            }
+        ));
+        assert!(matches!(parse_command(&[]).unwrap(), AdminCommand::Help));
+    }
+
+    // Error handling: malformed commands are refused before touching any backend
+    #[test]
+    fn malformed_commands_refused() {
+        for line in [
+            "drop-hospital h1",
+            "create-hospital",
+            "create-hospital h1 h2",
+            "create-hospital h1 --size-tier huge",
+            "rotate-key h1 --grace-s soon",
+            "rotate-key h1 --grace-s",
+            "pause-hospital h1 --force",
+            "pause-hospital h1 --resume=yes",
+            "replay local/a.json --hospital-id h1",
+            "migrate now",
+        ] {
+            assert!(parse_command(&args(line)).is_err(), "{line}");
+        }
+    }
+}
@@ -0,0 +1,88 @@
+#![cfg_attr(not(test), deny(clippy::unwrap_used))]
+#![cfg_attr(not(test), deny(clippy::expect_used))]
+#![cfg_attr(not(test), deny(clippy::panic))]
+
+// Imports *****************************************************************************************
+// External Crates
+#[cfg(feature = "grpc")]
+use authentication::gcp_identity::pubsub_client_config;
+use authentication::gcp_identity::{gcs_client_config, GcpIdentity, GcsAccess};
+use config::settings::GcpSettings;
+#[cfg(feature = "grpc")]
+use google_cloud_pubsub::client::Client as PubSubClient;
+use google_cloud_storage::client::Client as GcsClient;
+use log::info;
+use publishers::publisher::PubSubClients;
+#[cfg(feature = "grpc")]
+use services::service_pubsub_router::TopicAdmin;
+use std::sync::Arc;
+
+// Internal Modules
+pub mod audit;
+pub mod authentication;
+pub mod cli;
+pub mod config;
+pub mod models;
+pub mod publishers;
+pub mod routes;
+pub mod services;
+pub mod sinks;
+pub mod storage;
+pub mod telemetry;
+pub mod utils;
+
+// Load tests: built with the unit tests, next to the criterion benchmarks of tests/load
+#[cfg(all(test, feature = "load-tests"))]
+#[path = "../tests/load/mod.rs"]
+mod load_tests;
+
+// Support Functions *******************************************************************************
+
+/// function to initialize a GCS client with its own identity and access level
+/// # Arguments
+/// * `gcp` - The GCP settings, naming the service account of each access level
+/// * `access` - The access level of the client
+/// # Errors
+/// Returns an error if the GCS client configuration or authentication fails.
+pub async fn init_gcs_client(
+    gcp: &GcpSettings,
+    access: GcsAccess,
+) -> Result<Arc<GcsClient>, Box<dyn std::error::Error>> {
+    let account = match access {
+        GcsAccess::ReadWrite => gcp.gcs_write_service_account.as_deref(),
+        GcsAccess::ReadOnly => gcp.gcs_read_service_account.as_deref(),
+    };
+    let identity = GcpIdentity::from_settings_for(gcp, account)?;
+    info!(
+        "GCS {} identity: {}",
+        access.as_str(),
+        identity.describe(gcp)
+    );
+    let gcs_config = gcs_client_config(&identity, access).await?;
+    Ok(Arc::new(GcsClient::new(gcs_config)))
+}
+
+/// function to initialize the PubSub client and its topic admin, which reads the topic schemas
+/// # Errors
+/// Returns an error if the PubSub client configuration or authentication fails.
+#[cfg(feature = "grpc")]
+pub async fn init_pubsub_clients(
+    identity: &GcpIdentity,
+) -> Result<PubSubClients, Box<dyn std::error::Error>> {
+    let pubsub_config = pubsub_client_config(identity, None).await?;
+    let topic_admin = TopicAdmin::new(&pubsub_config).await?;
+    let client = PubSubClient::new(pubsub_config).await?;
+    Ok(PubSubClients {
+        client: Arc::new(client),
+        topic_admin: Arc::new(topic_admin),
+    })
+}
+
+/// function standing in for the PubSub clients in a build without the `grpc` feature - the
+/// settings only accept the `log` backends there
+#[cfg(not(feature = "grpc"))]
+pub async fn init_pubsub_clients(
+    _identity: &GcpIdentity,
+) -> Result<PubSubClients, Box<dyn std::error::Error>> {
+    Ok(PubSubClients {})
+}
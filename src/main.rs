@@ -2,29 +2,37 @@
 #![cfg_attr(not(test), deny(clippy::expect_used))]
 #![cfg_attr(not(test), deny(clippy::panic))]
 
-// TODO - after PoC: DEV PROD environment variables -> config file
-
 // Imports *****************************************************************************************
 // External Crates
+use actix_web::http::KeepAlive;
+use actix_web::middleware::from_fn;
 use actix_web::{mime, web, App, HttpServer};
+use authentication::auth::connect_to_database;
+use authentication::gcp_identity::{GcpIdentity, GcsAccess};
+use config::settings::Settings;
 use dotenv::dotenv;
-use google_cloud_pubsub::client::{Client as PubSubClient, ClientConfig as PubSubClientConfig};
-use google_cloud_storage::client::{Client as GcsClient, ClientConfig as GcsClientConfig};
-use log::info;
-use std::sync::Arc;
+use log::{info, warn};
+use models::models_size_tiers::SizeTier;
+use services::service_billing::BillingService;
+use services::service_compatibility::{check_compatibility_at_startup, run_compatibility_checks};
+use services::service_ingest_queue::IngestQueue;
+use services::service_queue_admin::QueueAdmin;
+use services::service_readiness::ReadinessProbe;
+use services::service_wasm_plugins::PluginRegistry;
+use std::sync::{Arc, OnceLock};
+use std::time::{Duration, Instant};
+use telemetry::middleware::{
+    connection_metrics, correlation_middleware, request_metrics_middleware,
+};
+use telemetry::trace_context::format_log_line;
+use utils::api_error::json_error_handler;
+use utils::drain_state::{DrainReason, DRAIN_STATE};
 
 // Internal Modules
-mod authentication;
-mod models;
-mod routes;
-mod services;
-mod utils;
-
-// Global variables ********************************************************************************
-// Connection Constants
-pub const PORT: u16 = 8080;
-pub const HOST: &str = "0.0.0.0";
-pub const POST_SIZE_LIMIT: usize = 512_000;
+use sentinela_exam_receiver::{
+    audit, authentication, cli, config, init_pubsub_clients, models, publishers, routes, services,
+    storage, telemetry, utils,
+};
 
 // Main ********************************************************************************************
 #[actix_web::main]
@@ -35,53 +43,374 @@ async fn main() -> std::io::Result<()> {
 
     // Initialize logger
     std::env::set_var("RUST_LOG", "info");
-    env_logger::init();
+    env_logger::Builder::from_default_env()
+        .format(format_log_line)
+        .init();
     info!("Starting the ActixWeb server: SENTINELA EXAM RECEIVER");
 
+    // Typed settings (environment, CONFIG_FILE and its DEPLOY_ENV overrides), validated once - a
+    // misconfigured deployment fails here, listing every missing key
+    let settings = Arc::new(Settings::load().map_err(|e| std::io::Error::other(e.to_string()))?);
+    info!(
+        "Settings loaded for the {} environment",
+        settings.deploy_env
+    );
+
+    // Operator CLI (`admin <command>`): one command against the configured backends, then exit -
+    // no server is started
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.first().is_some_and(|arg| arg == "admin") {
+        return cli::admin_cli::run_admin_cli(&settings, &args[1..]).await;
+    }
+
+    // Log configuration drift against the last deployment - never blocks startup
+    if let Err(e) = utils::config_drift::check_config_drift(&settings) {
+        warn!("Could not check configuration drift: {e}");
+    }
+
+    // Exam timestamps come from the host clock: checked against the time server (CLOCK_DRIFT_SERVER)
+    // before serving traffic, then every CLOCK_DRIFT_CHECK_INTERVAL_S - a drift above
+    // CLOCK_DRIFT_MAX_MS refuses to start unless CLOCK_DRIFT_REFUSE_START=false, then fails readiness
+    let clock = settings.clock.clone();
+    utils::clock_drift::check_clock_at_startup(&clock)
+        .await
+        .map_err(|e| std::io::Error::other(e.to_string()))?;
+    actix_web::rt::spawn(utils::clock_drift::run_clock_drift_checks(clock));
+
     // Initialize GCP clients once
-    // GCS Client
-    let gcs_client = init_gcs_client()
+    // Identity: application default (incl. workload identity federation) or impersonation
+    let identity = GcpIdentity::from_settings(&settings.gcp)
+        .map_err(|e| std::io::Error::other(e.to_string()))?;
+    info!("GCP identity: {}", identity.describe(&settings.gcp));
+    // PubSub Clients (none in a build without the `grpc` feature)
+    let pubsub = init_pubsub_clients(&identity)
+        .await
+        .map_err(|e| std::io::Error::other(e.to_string()))?;
+
+    // Structured audit entries for Cloud Logging (AUDIT_SINK), next to the audit log lines
+    telemetry::audit_export::start_audit_export(
+        settings.events.audit_sink,
+        settings.gcp.project.clone(),
+    );
+
+    // Bearer tokens of the hospitals (HOSPITAL_AUTH_MODE=jwt): the JWKS is fetched before serving
+    // traffic, then refreshed in the background - an unreachable identity provider is only logged,
+    // unknown key ids fetch it again
+    if let Some(jwt) = settings.auth.jwt.clone() {
+        if let Err(e) = authentication::jwt::refresh_jwks(&jwt).await {
+            warn!("Could not fetch the JWKS at startup: {e}");
+        }
+        actix_web::rt::spawn(authentication::jwt::run_jwks_refresh(jwt.clone()));
+        authentication::jwt::init_jwt_settings(jwt);
+    }
+
+    // Validated credentials are cached for AUTH_CACHE_TTL_S
+    authentication::auth::init_auth_cache(&settings.auth);
+
+    // Browser admin console (ADMIN_CONSOLE_ORIGIN): cookie sessions and strict CORS on the admin
+    // APIs - disabled when the origin is not set
+    if let Some(console) = settings.auth.console.clone() {
+        authentication::console_session::init_console_settings(console);
+    }
+
+    // Opt-in research mirror (RESEARCH_SAMPLE_BUCKET): a small share of the consented exams is
+    // de-identified and copied with the ingestion identity
+    if let Some(sampling) = settings.research.clone() {
+        services::service_research_sampling::init_research_sampling(sampling);
+    }
+
+    // Backlog of unpublished notifications at which exams are deferred, then refused
+    // (PUBLISH_BACKLOG_DEFER_AT, PUBLISH_BACKLOG_REJECT_AT)
+    utils::publish_backlog::PUBLISH_BACKLOG.configure(&settings.publish);
+
+    // Saturation thresholds of the downstream consumers, and the retry budget of the external calls
+    services::service_downstream_feedback::init_downstream_settings(settings.downstream.clone());
+    utils::external_call::init_retry_budget(settings.retry_budget);
+
+    // Encoding of the notifications per topic (PUBSUB_MESSAGE_FORMATS) while consumers move to Avro
+    services::service_message_format::init_message_formats(
+        settings.publish.message_formats.clone(),
+    );
+
+    // Pre-shared zstd dictionaries of the device models (ZSTD_DICTIONARY_DIR) for compressed ECG
+    // bodies, served at /v1/zstd_dictionaries
+    services::service_zstd_dictionaries::init_zstd_dictionaries(
+        services::service_zstd_dictionaries::ZstdDictionaries::from_settings(
+            settings.ingest.zstd_dictionary_dir.as_deref(),
+        )
+        .map_err(|e| std::io::Error::other(e.to_string()))?,
+    );
+
+    // Malware scanner of the binary payloads (SCAN_BACKEND), run before anything is stored
+    let scanner = services::service_scan::scanner_from_settings(&settings.scan)
+        .map_err(|e| std::io::Error::other(e.to_string()))?;
+
+    // Postgres pool shared by every request (hospital authentication)
+    let db_pool = connect_to_database(&settings.database)
+        .await
+        .map_err(|e| std::io::Error::other(e.to_string()))?;
+
+    // Per-scope rate limits of the hospital and internal APIs (RATE_LIMITS), with the windows
+    // shared by the instances in Redis when RATE_LIMIT_REDIS_URL is set
+    let rate_limiter = authentication::rate_limit::RateLimiter::from_settings(&settings.auth)
         .await
         .map_err(|e| std::io::Error::other(e.to_string()))?;
-    // PubSub Client
-    let pubsub_client = init_pubsub_client()
+
+    // Append-only audit trail of the authentications and exams (audit_events table)
+    audit::audit_trail::start_audit_trail(db_pool.clone());
+
+    // Cached registry entries forgotten on registry changes (hospital_registry notifications)
+    services::service_registry_events::start_registry_listener(db_pool.clone());
+
+    // Responses of the accepted exams, replayed to their retries (IDEMPOTENCY_WINDOW_S)
+    let idempotency =
+        services::service_idempotency::IdempotencyStore::new(&settings.ingest, db_pool.clone());
+
+    // Exam storage (STORAGE_BACKEND) and notification backend (PUBLISH_BACKEND) - with Pub/Sub,
+    // every routed topic is validated before serving traffic
+    // With GCS, ingestion writes and the read path (export, plugins) use separate identities
+    // (GCP_GCS_WRITE_SERVICE_ACCOUNT, GCP_GCS_READ_SERVICE_ACCOUNT) and scopes
+    let storage = storage::exam_storage::storage_from_settings(
+        &settings.storage,
+        &settings.gcp,
+        GcsAccess::ReadWrite,
+    )
+    .await
+    .map_err(|e| std::io::Error::other(e.to_string()))?;
+    let read_storage = storage::exam_storage::ReadOnlyStorage(
+        storage::exam_storage::storage_from_settings(
+            &settings.storage,
+            &settings.gcp,
+            GcsAccess::ReadOnly,
+        )
+        .await
+        .map_err(|e| std::io::Error::other(e.to_string()))?,
+    );
+    let publisher = publishers::publisher::publisher_from_settings(&settings.publish, &pubsub)
         .await
         .map_err(|e| std::io::Error::other(e.to_string()))?;
 
+    // Billing events sink and monthly totals
+    let billing = Arc::new(BillingService::from_settings(
+        &settings.events.billing,
+        &pubsub,
+        &identity,
+    ));
+
+    // Background workers storing and publishing ECG exams accepted with 202
+    let ingest_queue = IngestQueue::start(
+        settings.clone(),
+        storage.clone(),
+        publisher.clone(),
+        billing.clone(),
+    );
+
+    // Operator access to the dead letters and the spool (/internal/v1/queues)
+    let queue_admin = Arc::new(QueueAdmin::new(
+        &settings.storage,
+        &storage,
+        publisher.clone(),
+        ingest_queue.clone(),
+    ));
+
+    // Readiness of the dependencies (storage, Pub/Sub, Postgres), checked once before serving
+    // traffic and on demand by /v1/readyz - a dependency down is logged, not fatal
+    let readiness = Arc::new(ReadinessProbe::new(
+        storage.clone(),
+        publisher.clone(),
+        db_pool.clone(),
+        settings.storage.bucket_name.clone(),
+    ));
+    readiness.check_at_startup().await;
+
+    // Schemas the binary writes to (Postgres migrations, Pub/Sub schema registry), checked before
+    // serving traffic and every COMPATIBILITY_CHECK_INTERVAL_S - a mismatch fails readiness
+    let compatibility = settings.compatibility.clone();
+    check_compatibility_at_startup(&db_pool, &publisher, &compatibility).await;
+    actix_web::rt::spawn(run_compatibility_checks(
+        db_pool.clone(),
+        publisher.clone(),
+        compatibility,
+    ));
+
+    // Experimental per-hospital WASM transformation plugins, verified against their pinned digest
+    let plugins = Arc::new(
+        PluginRegistry::from_settings(
+            &settings.plugins,
+            &settings.storage.bucket_name,
+            &read_storage,
+        )
+        .await
+        .map_err(|e| std::io::Error::other(e.to_string()))?,
+    );
+
+    // Maintenance mode can be requested at boot - the health check then returns 503
+    if settings.server.maintenance_mode {
+        DRAIN_STATE.set(DrainReason::Maintenance, true);
+        warn!("MAINTENANCE_MODE is set - health check reports draining");
+    }
+
+    // Background listener for the inference service saturation feedback
+    #[cfg(feature = "grpc")]
+    actix_web::rt::spawn(
+        services::service_downstream_feedback::listen_downstream_feedback(
+            settings.downstream.feedback_subscription.clone(),
+            pubsub.client.clone(),
+        ),
+    );
+    // Background listener for the consumers' reports on the message formats they read
+    #[cfg(feature = "grpc")]
+    actix_web::rt::spawn(services::service_message_format::listen_format_reports(
+        settings.publish.format_report_subscription.clone(),
+        pubsub.client.clone(),
+    ));
+    // Periodic rejection digests for the data-quality dashboards
+    actix_web::rt::spawn(services::service_rejection_digest::run_rejection_digest(
+        settings.events.clone(),
+        pubsub.clone(),
+        identity.clone(),
+    ));
+    // Schemas of the Parquet files written, published to the exam bucket (schemas/parquet/)
+    actix_web::rt::spawn(services::service_parquet_schemas::publish_parquet_schemas(
+        settings.storage.bucket_name.clone(),
+        storage.clone(),
+    ));
+    // Scheduled garbage collection of staging/quarantine objects
+    actix_web::rt::spawn(services::service_storage_gc::run_storage_gc(
+        settings.lifecycle.clone(),
+        settings.storage.bucket_name.clone(),
+        storage.clone(),
+    ));
+    // Scheduled deletion of the exams past their retention, with signed certificates
+    actix_web::rt::spawn(services::service_exam_retention::run_exam_retention(
+        settings.lifecycle.clone(),
+        settings.storage.bucket_name.clone(),
+        storage.clone(),
+    ));
+
     // ActixWeb server initialization
-    HttpServer::new(move || {
-        info!("Server is running on https://{HOST}:{PORT}");
+    let address = format!("{}:{}", settings.server.host, settings.server.port);
+    let grace = settings.server.drain_grace_period_s;
+    let shutdown_deadline = Duration::from_secs(settings.server.shutdown_deadline_s);
+    let server_settings = settings.server.clone();
+    // Hospital middleware reuses few connections for many submissions: a keep-alive above the
+    // idle timeout of the load balancer and h2 multiplexing avoid reconnecting per exam
+    let keep_alive = match server_settings.keep_alive_s {
+        0 => KeepAlive::Disabled,
+        keep_alive_s => KeepAlive::Timeout(Duration::from_secs(keep_alive_s)),
+    };
+    info!(
+        "HTTP/2 (h2c): {}, keep-alive: {}s, {} connections per worker",
+        server_settings.http2_enabled,
+        server_settings.keep_alive_s,
+        server_settings.max_connections
+    );
+    let draining_queue = ingest_queue.clone();
+    let running_on = address.clone();
+    let server = HttpServer::new(move || {
+        info!("Server is running on https://{running_on}");
         App::new()
-            .app_data(web::Data::new(gcs_client.clone()))
-            .app_data(web::Data::new(pubsub_client.clone()))
+            .app_data(web::Data::new(settings.clone()))
+            .app_data(web::Data::new(db_pool.clone()))
+            .app_data(web::Data::new(storage.clone()))
+            .app_data(web::Data::new(read_storage.clone()))
+            .app_data(web::Data::new(publisher.clone()))
+            .app_data(web::Data::new(billing.clone()))
+            .app_data(web::Data::new(plugins.clone()))
+            .app_data(web::Data::new(ingest_queue.clone()))
+            .app_data(web::Data::new(queue_admin.clone()))
+            .app_data(web::Data::new(scanner.clone()))
+            .app_data(web::Data::new(idempotency.clone()))
+            .app_data(web::Data::new(readiness.clone()))
+            .app_data(web::Data::new(rate_limiter.clone()))
             .app_data(
                 web::JsonConfig::default()
-                    .limit(POST_SIZE_LIMIT)
-                    .content_type(|mime| mime == mime::APPLICATION_JSON),
+                    // Upper bound of all tiers and of decompressed bodies - the limits of the hospital
+                    // are applied by its authentication middleware
+                    .limit(SizeTier::max_json_limit(&settings.server))
+                    .content_type(|mime| mime == mime::APPLICATION_JSON)
+                    .error_handler(json_error_handler),
             )
+            // Every request gets an id and a trace: echoed in 'x-request-id', 'traceparent', the
+            // JSON bodies and the log lines
+            .wrap(from_fn(correlation_middleware))
+            // Requests and latencies per route pattern and status, for /internal/v1/metrics
+            .wrap(from_fn(request_metrics_middleware))
             .configure(routes::config)
     })
     .workers(num_cpus::get())
-    .bind(format!("{HOST}:{PORT}"))?
-    .run()
-    .await
-}
+    .keep_alive(keep_alive)
+    .client_request_timeout(Duration::from_millis(
+        server_settings.client_request_timeout_ms,
+    ))
+    .max_connections(server_settings.max_connections)
+    .max_connection_rate(server_settings.max_connection_rate)
+    // Applies to the sockets bound after it
+    .backlog(server_settings.listen_backlog)
+    // Connections accepted and served per protocol, for /internal/v1/metrics
+    .on_connect(connection_metrics)
+    .disable_signals()
+    .shutdown_timeout(shutdown_deadline.as_secs());
+    // HTTP/1.1 and HTTP/2 are told apart by the connection preface, so HTTP/1.1 clients are served
+    // on the same port either way
+    let server = if server_settings.http2_enabled {
+        server.bind_auto_h2c(&address)?
+    } else {
+        server.bind(&address)?
+    }
+    .run();
 
-// Support Functions *******************************************************************************
+    // Drain on SIGTERM/SIGINT: fail the health check first, stop once the load balancer moved away
+    // - the in-flight requests then get up to SHUTDOWN_DEADLINE_S
+    let handle = server.handle();
+    let stopped_at = Arc::new(OnceLock::new());
+    let stopping = stopped_at.clone();
+    actix_web::rt::spawn(async move {
+        wait_for_shutdown_signal().await;
+        DRAIN_STATE.set(DrainReason::ShutdownInitiated, true);
+        warn!("Shutdown signal received - draining for {grace}s before stopping");
+        tokio::time::sleep(Duration::from_secs(grace)).await;
+        let _ = stopping.set(Instant::now());
+        handle.stop(true).await;
+    });
+
+    let served = server.await;
 
-/// function to initialize the GCS client
-/// # Errors
-/// Returns an error if the GCS client configuration or authentication fails.
-async fn init_gcs_client() -> Result<Arc<GcsClient>, Box<dyn std::error::Error>> {
-    let gcs_config = GcsClientConfig::default().with_auth().await?;
-    Ok(Arc::new(GcsClient::new(gcs_config)))
+    // The server no longer takes exams: the queued ones and their publishes get what the requests
+    // left of SHUTDOWN_DEADLINE_S, the rest is spilled to the spool for the next start
+    let remaining = stopped_at.get().map_or(shutdown_deadline, |at| {
+        shutdown_deadline.saturating_sub(at.elapsed())
+    });
+    let unfinished = draining_queue.drain(remaining).await;
+    if unfinished > 0 {
+        warn!(
+            "Stopped with {unfinished} exams still processing - only two-phase exams are replayed"
+        );
+    }
+    served
 }
 
-/// function to initialize the PubSub client
-/// # Errors
-/// Returns an error if the PubSub client configuration or authentication fails.
-async fn init_pubsub_client() -> Result<Arc<PubSubClient>, Box<dyn std::error::Error>> {
-    let pubsub_config = PubSubClientConfig::default().with_auth().await?;
-    let pubsub_client = PubSubClient::new(pubsub_config).await?;
-    Ok(Arc::new(pubsub_client))
+// Support Functions *******************************************************************************
+
+/// function to wait for SIGTERM (container stop) or SIGINT (Ctrl+C)
+async fn wait_for_shutdown_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        match signal(SignalKind::terminate()) {
+            Ok(mut sigterm) => {
+                tokio::select! {
+                    _ = sigterm.recv() => {}
+                    _ = tokio::signal::ctrl_c() => {}
+                }
+            }
+            Err(_) => {
+                let _ = tokio::signal::ctrl_c().await;
+            }
+        }
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = tokio::signal::ctrl_c().await;
+    }
 }
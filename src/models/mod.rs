@@ -1 +1,15 @@
-pub mod models_exams;
\ No newline at end of file
+pub mod models_consent;
+pub mod models_deprecations;
+pub mod models_dicom;
+pub mod models_ecg_quality;
+pub mod models_ecg_stream;
+pub mod models_exams;
+pub mod models_fhir;
+pub mod models_hash_check;
+pub mod models_hospital_admin;
+pub mod models_ids;
+pub mod models_publish_modes;
+pub mod models_rate_tiers;
+pub mod models_size_tiers;
+pub mod models_topics;
+pub mod models_validation_profiles;
@@ -0,0 +1,73 @@
+// Imports *****************************************************************************************
+// External Crates
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+// Internal Modules
+
+// MAIN FUNCTIONS AND STRUCTS **********************************************************************
+/// Data-sharing consent of a hospital, as recorded in the hospital registry
+/// Scopes are ordered: a research consent also covers clinical use
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ConsentScope {
+    /// Exams may only be used for the clinical care of the patient
+    Clinical,
+    /// Exams may also be used for research
+    Research,
+}
+
+impl ConsentScope {
+    /// Stable lowercase name, used in Pub/Sub attributes, Parquet files and routing rules
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ConsentScope::Clinical => "clinical",
+            ConsentScope::Research => "research",
+        }
+    }
+
+    /// Whether this consent covers a destination requiring `required`
+    pub fn allows(&self, required: ConsentScope) -> bool {
+        *self >= required
+    }
+}
+
+impl FromStr for ConsentScope {
+    type Err = anyhow::Error;
+
+    fn from_str(raw: &str) -> Result<Self> {
+        match raw.trim().to_ascii_lowercase().as_str() {
+            "clinical" => Ok(ConsentScope::Clinical),
+            "research" => Ok(ConsentScope::Research),
+            other => Err(anyhow!("Unknown consent scope '{other}'")),
+        }
+    }
+}
+
+// TESTS *******************************************************************************************
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn consent_research_covers_clinical() {
+        assert!(ConsentScope::Research.allows(ConsentScope::Clinical));
+        assert!(ConsentScope::Research.allows(ConsentScope::Research));
+        assert!(ConsentScope::Clinical.allows(ConsentScope::Clinical));
+        assert!(!ConsentScope::Clinical.allows(ConsentScope::Research));
+    }
+
+    #[test]
+    fn consent_parses() {
+        assert_eq!(
+            " Research ".parse::<ConsentScope>().unwrap(),
+            ConsentScope::Research
+        );
+        assert!("marketing".parse::<ConsentScope>().is_err());
+        assert_eq!(
+            serde_json::to_value(ConsentScope::Clinical).unwrap(),
+            "clinical"
+        );
+    }
+}
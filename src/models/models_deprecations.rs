@@ -0,0 +1,136 @@
+// Imports *****************************************************************************************
+// External Crates
+use chrono::NaiveDate;
+use serde::Serialize;
+
+// Internal Modules
+use crate::models::models_exams::{PayloadEcg, PayloadXray};
+
+// Constants ***************************************************************************************
+/// Payload fields scheduled for removal - a field may only be removed from a model once its
+/// sunset date has passed and the usage report shows no hospital still sending it
+pub const DEPRECATED_FIELDS: [DeprecatedField; 2] = [
+    DeprecatedField {
+        exam_type: "ecg_exam",
+        field: "hospital_key",
+        sunset: "2027-04-30",
+        guidance: "authenticate with the hospital_key header only",
+    },
+    DeprecatedField {
+        exam_type: "xray_exam",
+        field: "hospital_key",
+        sunset: "2027-04-30",
+        guidance: "authenticate with the hospital_key header only",
+    },
+];
+
+// MAIN FUNCTIONS AND STRUCTS **********************************************************************
+/// A payload field scheduled for removal
+/// # Arguments
+/// * `exam_type` - The exam type key of the payload
+/// * `field` - The JSON name of the field
+/// * `sunset` - The date (`YYYY-MM-DD`) after which the field is rejected
+/// * `guidance` - What hospitals must do instead
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct DeprecatedField {
+    pub exam_type: &'static str,
+    pub field: &'static str,
+    pub sunset: &'static str,
+    pub guidance: &'static str,
+}
+
+impl DeprecatedField {
+    /// Warning returned to the hospital in the response body
+    pub fn warning(&self) -> String {
+        format!(
+            "Field '{}' is deprecated and will be removed on {}: {}",
+            self.field, self.sunset, self.guidance
+        )
+    }
+}
+
+/// Payloads able to report which of their deprecated fields were sent
+pub trait DeprecatedFields {
+    /// The exam type key of the payload
+    const EXAM_TYPE: &'static str;
+
+    /// JSON names of the fields present in this payload
+    fn fields_present(&self) -> Vec<&'static str>;
+
+    /// Deprecated fields sent in this payload
+    fn deprecated_fields_used(&self) -> Vec<DeprecatedField> {
+        let present = self.fields_present();
+        DEPRECATED_FIELDS
+            .into_iter()
+            .filter(|d| d.exam_type == Self::EXAM_TYPE && present.contains(&d.field))
+            .collect()
+    }
+}
+
+impl DeprecatedFields for PayloadEcg {
+    const EXAM_TYPE: &'static str = "ecg_exam";
+
+    fn fields_present(&self) -> Vec<&'static str> {
+        // Only optional fields can be deprecated - required ones are always present
+        self.hospital_key
+            .is_some()
+            .then_some("hospital_key")
+            .into_iter()
+            .collect()
+    }
+}
+
+impl DeprecatedFields for PayloadXray {
+    const EXAM_TYPE: &'static str = "xray_exam";
+
+    fn fields_present(&self) -> Vec<&'static str> {
+        [
+            self.hospital_key.is_some().then_some("hospital_key"),
+            self.view_position.is_some().then_some("view_position"),
+        ]
+        .into_iter()
+        .flatten()
+        .collect()
+    }
+}
+
+/// Value of the `Sunset` header (RFC 8594) for the deprecated fields used: the earliest sunset
+/// # Arguments
+/// * `used` - The deprecated fields sent in a payload
+/// # Returns
+/// * The HTTP date of the earliest sunset, or None if no deprecated field was used
+pub fn sunset_header(used: &[DeprecatedField]) -> Option<String> {
+    used.iter()
+        .filter_map(|d| NaiveDate::parse_from_str(d.sunset, "%Y-%m-%d").ok())
+        .min()
+        .map(|date| date.format("%a, %d %b %Y 00:00:00 GMT").to_string())
+}
+
+// TESTS *******************************************************************************************
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Every sunset date must parse, or the Sunset header would silently be dropped
+    #[test]
+    fn sunset_dates_parse() {
+        for field in DEPRECATED_FIELDS {
+            assert!(NaiveDate::parse_from_str(field.sunset, "%Y-%m-%d").is_ok());
+        }
+    }
+
+    #[test]
+    fn sunset_header_is_http_date() {
+        assert_eq!(
+            sunset_header(&DEPRECATED_FIELDS[..1]).unwrap(),
+            "Fri, 30 Apr 2027 00:00:00 GMT"
+        );
+        assert!(sunset_header(&[]).is_none());
+    }
+
+    #[test]
+    fn deprecated_field_warning() {
+        let warning = DEPRECATED_FIELDS[0].warning();
+        assert!(warning.contains("hospital_key") && warning.contains("2027-04-30"));
+    }
+}
@@ -0,0 +1,381 @@
+// Imports *****************************************************************************************
+// External Crates
+use anyhow::{anyhow, bail, Result};
+use serde::Serialize;
+use std::ops::Range;
+
+// Internal Modules
+
+// Constants ***************************************************************************************
+/// A DICOM tag: (group, element)
+pub type Tag = (u16, u16);
+
+pub const FILE_META_GROUP_LENGTH: Tag = (0x0002, 0x0000);
+pub const MEDIA_STORAGE_SOP_INSTANCE_UID: Tag = (0x0002, 0x0003);
+pub const TRANSFER_SYNTAX_UID: Tag = (0x0002, 0x0010);
+pub const ACQUISITION_DATETIME: Tag = (0x0008, 0x002A);
+pub const ACQUISITION_DATE: Tag = (0x0008, 0x0022);
+pub const ACQUISITION_TIME: Tag = (0x0008, 0x0032);
+pub const STUDY_DATE: Tag = (0x0008, 0x0020);
+pub const STUDY_TIME: Tag = (0x0008, 0x0030);
+pub const SOP_CLASS_UID: Tag = (0x0008, 0x0016);
+pub const SOP_INSTANCE_UID: Tag = (0x0008, 0x0018);
+pub const MODALITY: Tag = (0x0008, 0x0060);
+pub const PATIENT_ID: Tag = (0x0010, 0x0020);
+pub const PATIENT_IDENTITY_REMOVED: Tag = (0x0012, 0x0062);
+pub const DEIDENTIFICATION_METHOD: Tag = (0x0012, 0x0063);
+pub const STUDY_INSTANCE_UID: Tag = (0x0020, 0x000D);
+pub const SERIES_INSTANCE_UID: Tag = (0x0020, 0x000E);
+pub const ROWS: Tag = (0x0028, 0x0010);
+pub const COLUMNS: Tag = (0x0028, 0x0011);
+pub const PIXEL_DATA: Tag = (0x7FE0, 0x0010);
+
+/// Implicit VR Little Endian - every other supported syntax is Explicit VR Little Endian
+const IMPLICIT_VR_LITTLE_ENDIAN: &str = "1.2.840.10008.1.2";
+/// Syntaxes whose dataset is not plain little endian
+const UNSUPPORTED_TRANSFER_SYNTAXES: [&str; 2] = ["1.2.840.10008.1.2.1.99", "1.2.840.10008.1.2.2"];
+/// VRs encoded with a 4-byte length in Explicit VR
+const LONG_VRS: [&[u8; 2]; 12] = [
+    b"OB", b"OD", b"OF", b"OL", b"OV", b"OW", b"SQ", b"SV", b"UC", b"UN", b"UR", b"UT",
+];
+/// Length of a value whose end is marked by a delimiter
+const UNDEFINED_LENGTH: u32 = 0xFFFF_FFFF;
+/// Item, item delimiter and sequence delimiter tags
+const ITEM: Tag = (0xFFFE, 0xE000);
+const ITEM_DELIMITER: Tag = (0xFFFE, 0xE00D);
+const SEQUENCE_DELIMITER: Tag = (0xFFFE, 0xE0DD);
+/// Deepest sequence nesting accepted
+const MAX_NESTING: usize = 16;
+
+// Structs *****************************************************************************************
+/// One top-level element of a DICOM file
+/// # Arguments
+/// * `tag` - The tag of the element
+/// * `vr` - Its value representation, if the file is Explicit VR
+/// * `value` - The byte range of its value in the file (sequences include their delimiter)
+/// * `raw` - The byte range of the whole element, header included
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DicomElement {
+    pub tag: Tag,
+    pub vr: Option<[u8; 2]>,
+    pub value: Range<usize>,
+    pub raw: Range<usize>,
+}
+
+/// A parsed DICOM Part 10 file - the elements point into the original bytes
+/// # Arguments
+/// * `bytes` - The file
+/// * `transfer_syntax_uid` - The transfer syntax of the dataset
+/// * `meta` - The file meta information elements (group 0002)
+/// * `elements` - The top-level dataset elements, nested sequences are not split
+#[derive(Debug, Clone)]
+pub struct DicomFile<'a> {
+    pub bytes: &'a [u8],
+    pub transfer_syntax_uid: String,
+    pub meta: Vec<DicomElement>,
+    pub elements: Vec<DicomElement>,
+}
+
+/// Metadata extracted from a DICOM X-ray, sent with the exam - UIDs are already pseudonymized
+/// # Arguments
+/// * `modality` - The modality, e.g. `DX` or `CR`
+/// * `sop_class_uid` - The SOP class of the image (not identifying)
+/// * `study_instance_uid` / `series_instance_uid` / `sop_instance_uid` - Pseudonymized UIDs
+/// * `acquisition_datetime` - When the image was acquired, falling back to the study date
+/// * `transfer_syntax_uid` - The transfer syntax of the stored file
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct DicomMetadata {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub modality: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sop_class_uid: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub study_instance_uid: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub series_instance_uid: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sop_instance_uid: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub acquisition_datetime: Option<String>,
+    pub transfer_syntax_uid: String,
+}
+
+// MAIN FUNCTIONS **********************************************************************************
+impl<'a> DicomFile<'a> {
+    /// Parse a DICOM Part 10 file (128-byte preamble, `DICM`, file meta information, dataset)
+    /// # Arguments
+    /// * `bytes` - The file
+    /// # Errors
+    /// * Returns an error if the file is truncated, malformed, or not little endian
+    pub fn parse(bytes: &'a [u8]) -> Result<Self> {
+        // STEP 1: Preamble and prefix
+        if bytes.get(128..132) != Some(b"DICM".as_slice()) {
+            bail!("Missing DICM prefix");
+        }
+
+        // STEP 2: File meta information - always Explicit VR Little Endian
+        let mut pos = 132;
+        let mut meta = Vec::new();
+        while pos < bytes.len() && read_tag(bytes, pos)?.0 == 0x0002 {
+            let element = read_element(bytes, pos, true, 0)?;
+            pos = element.raw.end;
+            meta.push(element);
+        }
+        let transfer_syntax_uid = meta
+            .iter()
+            .find(|e| e.tag == TRANSFER_SYNTAX_UID)
+            .map(|e| trim_value(&bytes[e.value.clone()]))
+            .ok_or_else(|| anyhow!("Missing transfer syntax"))?;
+        if UNSUPPORTED_TRANSFER_SYNTAXES.contains(&transfer_syntax_uid.as_str()) {
+            bail!("Unsupported transfer syntax {transfer_syntax_uid}");
+        }
+
+        // STEP 3: Top-level dataset elements
+        let explicit = transfer_syntax_uid != IMPLICIT_VR_LITTLE_ENDIAN;
+        let mut elements = Vec::new();
+        while pos < bytes.len() {
+            let element = read_element(bytes, pos, explicit, 0)?;
+            pos = element.raw.end;
+            elements.push(element);
+        }
+        Ok(DicomFile {
+            bytes,
+            transfer_syntax_uid,
+            meta,
+            elements,
+        })
+    }
+
+    /// Whether the dataset is Explicit VR
+    pub fn explicit_vr(&self) -> bool {
+        self.transfer_syntax_uid != IMPLICIT_VR_LITTLE_ENDIAN
+    }
+
+    /// Top-level element of the dataset (or of the file meta information)
+    pub fn element(&self, tag: Tag) -> Option<&DicomElement> {
+        self.meta
+            .iter()
+            .chain(self.elements.iter())
+            .find(|e| e.tag == tag)
+    }
+
+    /// Unsigned short value of an element (US), e.g. the image rows and columns
+    pub fn u16_value(&self, tag: Tag) -> Option<u16> {
+        self.element(tag)
+            .filter(|e| e.value.len() >= 2)
+            .and_then(|e| read_u16(self.bytes, e.value.start).ok())
+    }
+
+    /// Text value of an element, without its padding - None if absent or empty
+    pub fn string(&self, tag: Tag) -> Option<String> {
+        self.element(tag)
+            .map(|e| trim_value(&self.bytes[e.value.clone()]))
+            .filter(|v| !v.is_empty())
+    }
+}
+
+/// Encode one element in the transfer syntax of a file
+/// # Arguments
+/// * `tag` - The tag of the element
+/// * `vr` - Its value representation - written in Explicit VR only
+/// * `value` - The value, padded to an even length (`\0` for UIDs, spaces for text)
+/// * `explicit` - Whether the dataset is Explicit VR
+pub fn encode_element(tag: Tag, vr: &[u8; 2], value: &[u8], explicit: bool) -> Vec<u8> {
+    let mut value = value.to_vec();
+    if value.len() % 2 == 1 {
+        value.push(if vr == b"UI" || vr == b"OB" { 0 } else { b' ' });
+    }
+    let mut out = Vec::with_capacity(value.len() + 12);
+    out.extend_from_slice(&tag.0.to_le_bytes());
+    out.extend_from_slice(&tag.1.to_le_bytes());
+    if !explicit {
+        out.extend_from_slice(&(value.len() as u32).to_le_bytes());
+    } else if LONG_VRS.contains(&vr) {
+        out.extend_from_slice(vr);
+        out.extend_from_slice(&[0, 0]);
+        out.extend_from_slice(&(value.len() as u32).to_le_bytes());
+    } else {
+        out.extend_from_slice(vr);
+        out.extend_from_slice(&(value.len() as u16).to_le_bytes());
+    }
+    out.extend_from_slice(&value);
+    out
+}
+
+// SUPPORTING FUNCTIONS ****************************************************************************
+/// Read one element starting at `pos`, skipping over nested sequences
+/// # Arguments
+/// * `bytes` - The file
+/// * `pos` - The offset of the element
+/// * `explicit` - Whether the dataset is Explicit VR
+/// * `depth` - The sequence nesting of the element
+fn read_element(bytes: &[u8], pos: usize, explicit: bool, depth: usize) -> Result<DicomElement> {
+    let tag = read_tag(bytes, pos)?;
+    if tag.0 == 0xFFFE {
+        bail!("Unexpected item tag {tag:04X?} at offset {pos}");
+    }
+    let (vr, length, header) = if explicit {
+        let vr: [u8; 2] = slice(bytes, pos + 4, 2)?.try_into()?;
+        if LONG_VRS.contains(&&vr) {
+            (Some(vr), read_u32(bytes, pos + 8)?, 12)
+        } else {
+            (Some(vr), u32::from(read_u16(bytes, pos + 6)?), 8)
+        }
+    } else {
+        (None, read_u32(bytes, pos + 4)?, 8)
+    };
+    let start = pos + header;
+    let end = if length == UNDEFINED_LENGTH {
+        skip_items(bytes, start, explicit, depth + 1)?
+    } else {
+        let end = start + length as usize;
+        if end > bytes.len() {
+            bail!("Element {tag:04X?} is truncated");
+        }
+        end
+    };
+    Ok(DicomElement {
+        tag,
+        vr,
+        value: start..end,
+        raw: pos..end,
+    })
+}
+
+/// Skip the items of a value of undefined length (sequence or encapsulated pixel data)
+/// # Returns
+/// * The offset after its sequence delimiter
+fn skip_items(bytes: &[u8], mut pos: usize, explicit: bool, depth: usize) -> Result<usize> {
+    if depth > MAX_NESTING {
+        bail!("Sequences nested deeper than {MAX_NESTING}");
+    }
+    loop {
+        let tag = read_tag(bytes, pos)?;
+        let length = read_u32(bytes, pos + 4)?;
+        match tag {
+            SEQUENCE_DELIMITER => return Ok(pos + 8),
+            ITEM if length == UNDEFINED_LENGTH => {
+                // Item of undefined length: its elements run up to the item delimiter
+                pos += 8;
+                while read_tag(bytes, pos)? != ITEM_DELIMITER {
+                    pos = read_element(bytes, pos, explicit, depth)?.raw.end;
+                }
+                pos += 8;
+            }
+            ITEM => {
+                pos += 8 + length as usize;
+                if pos > bytes.len() {
+                    bail!("Sequence item is truncated");
+                }
+            }
+            other => bail!("Unexpected tag {other:04X?} in a sequence"),
+        }
+    }
+}
+
+/// Bytes of the file at `pos`, or an error if it is truncated
+fn slice(bytes: &[u8], pos: usize, len: usize) -> Result<&[u8]> {
+    bytes
+        .get(pos..pos + len)
+        .ok_or_else(|| anyhow!("File truncated at offset {pos}"))
+}
+
+fn read_u16(bytes: &[u8], pos: usize) -> Result<u16> {
+    Ok(u16::from_le_bytes(slice(bytes, pos, 2)?.try_into()?))
+}
+
+fn read_u32(bytes: &[u8], pos: usize) -> Result<u32> {
+    Ok(u32::from_le_bytes(slice(bytes, pos, 4)?.try_into()?))
+}
+
+fn read_tag(bytes: &[u8], pos: usize) -> Result<Tag> {
+    Ok((read_u16(bytes, pos)?, read_u16(bytes, pos + 2)?))
+}
+
+/// Text of a value without its `\0` or space padding
+fn trim_value(value: &[u8]) -> String {
+    String::from_utf8_lossy(value)
+        .trim_end_matches(['\0', ' '])
+        .trim_start()
+        .to_string()
+}
+
+// TESTS *******************************************************************************************
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// Build a DICOM file from (tag, vr, value) elements, in the given transfer syntax
+    pub(crate) fn dicom_file(
+        transfer_syntax: &str,
+        elements: &[(Tag, &[u8; 2], &[u8])],
+    ) -> Vec<u8> {
+        let mut meta = encode_element(TRANSFER_SYNTAX_UID, b"UI", transfer_syntax.as_bytes(), true);
+        let mut file = vec![0u8; 128];
+        file.extend_from_slice(b"DICM");
+        file.extend(encode_element(
+            FILE_META_GROUP_LENGTH,
+            b"UL",
+            &(meta.len() as u32).to_le_bytes(),
+            true,
+        ));
+        file.append(&mut meta);
+        let explicit = transfer_syntax != IMPLICIT_VR_LITTLE_ENDIAN;
+        for (tag, vr, value) in elements {
+            file.extend(encode_element(*tag, vr, value, explicit));
+        }
+        file
+    }
+
+    // Happy path: top-level values are read in both little endian syntaxes
+    #[test]
+    fn parse_explicit_and_implicit() {
+        for syntax in ["1.2.840.10008.1.2.1", IMPLICIT_VR_LITTLE_ENDIAN] {
+            let bytes = dicom_file(
+                syntax,
+                &[
+                    (MODALITY, b"CS", b"DX"),
+                    (STUDY_INSTANCE_UID, b"UI", b"1.2.3"),
+                ],
+            );
+            let file = DicomFile::parse(&bytes).unwrap();
+            assert_eq!(file.transfer_syntax_uid, syntax);
+            assert_eq!(file.string(MODALITY).as_deref(), Some("DX"));
+            assert_eq!(file.string(STUDY_INSTANCE_UID).as_deref(), Some("1.2.3"));
+            assert_eq!(file.elements.len(), 2);
+        }
+    }
+
+    // Sequences of undefined length are skipped as one element
+    #[test]
+    fn parse_undefined_length_sequence() {
+        let mut bytes = dicom_file("1.2.840.10008.1.2.1", &[]);
+        bytes.extend_from_slice(&[0x08, 0x00, 0x40, 0x11]); // (0008,1140) SQ
+        bytes.extend_from_slice(b"SQ\0\0");
+        bytes.extend_from_slice(&UNDEFINED_LENGTH.to_le_bytes());
+        bytes.extend_from_slice(&[0xFE, 0xFF, 0x00, 0xE0]); // item of undefined length
+        bytes.extend_from_slice(&UNDEFINED_LENGTH.to_le_bytes());
+        bytes.extend(encode_element(SOP_INSTANCE_UID, b"UI", b"9.9", true));
+        bytes.extend_from_slice(&[0xFE, 0xFF, 0x0D, 0xE0, 0, 0, 0, 0]);
+        bytes.extend_from_slice(&[0xFE, 0xFF, 0xDD, 0xE0, 0, 0, 0, 0]);
+        bytes.extend(encode_element(MODALITY, b"CS", b"CR", true));
+
+        let file = DicomFile::parse(&bytes).unwrap();
+        assert_eq!(file.elements.len(), 2);
+        assert_eq!(file.elements[0].tag, (0x0008, 0x1140));
+        // Nested values are not top-level values
+        assert_eq!(file.string(SOP_INSTANCE_UID), None);
+        assert_eq!(file.string(MODALITY).as_deref(), Some("CR"));
+    }
+
+    // Error handling: not DICOM, truncated, big endian
+    #[test]
+    fn parse_rejects_invalid_files() {
+        assert!(DicomFile::parse(b"plain text").is_err());
+        let mut bytes = dicom_file("1.2.840.10008.1.2.1", &[(MODALITY, b"CS", b"DX")]);
+        bytes.truncate(bytes.len() - 1);
+        assert!(DicomFile::parse(&bytes).is_err());
+        let bytes = dicom_file("1.2.840.10008.1.2.2", &[]);
+        assert!(DicomFile::parse(&bytes).is_err());
+    }
+}
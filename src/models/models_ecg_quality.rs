@@ -0,0 +1,185 @@
+// Imports *****************************************************************************************
+// External Crates
+use validator::ValidationError;
+
+// Constants ***************************************************************************************
+pub const ECG_MAX_AMPLITUDE: f32 = 2.0; // Largest accepted absolute sample, in mV
+pub const ECG_DEFAULT_SAMPLING_RATE_HZ: f32 = 500.0; // Assumed when the payload does not declare it
+pub const ECG_DEFAULT_DURATION_S: f32 = 10.0; // Assumed when the payload does not declare it
+pub const ECG_MIN_SAMPLING_RATE_HZ: f32 = 100.0; // Lowest accepted declared sampling rate
+pub const ECG_MAX_SAMPLING_RATE_HZ: f32 = 10_000.0; // Highest accepted declared sampling rate
+pub const ECG_MAX_DURATION_S: f32 = 60.0; // Longest accepted declared recording
+pub const ECG_CLIPPING_MIN_RUN: usize = 10; // Consecutive samples at the limit that mean clipping
+pub const ECG_CLIPPING_MARGIN: f32 = 1e-3; // Samples this close to the limit count as at the limit
+pub const ECG_EINTHOVEN_MAX_RMS: f64 = 0.05; // Largest accepted RMS of III - (II - I), in mV
+
+// MAIN FUNCTIONS **********************************************************************************
+/// Reject NaN and infinite samples - JSON cannot carry them, but out of range numbers overflow to
+/// infinity and FHIR decimals and binary message formats can carry both
+/// # Arguments
+/// * `values` - The samples of one lead
+/// # Returns
+/// * A Result containing a unit type or a ValidationError
+pub fn check_finite(values: &[f32]) -> Result<(), ValidationError> {
+    match values.iter().position(|v| !v.is_finite()) {
+        Some(index) => Err(ValidationError::new("non_finite")
+            .with_message(format!("Sample {index} is not a finite number").into())),
+        None => Ok(()),
+    }
+}
+
+/// Reject a lead that stays at the amplitude limit, the signature of a saturated amplifier
+/// # Arguments
+/// * `values` - The samples of one lead
+/// # Returns
+/// * A Result containing a unit type or a ValidationError
+pub fn check_clipping(values: &[f32]) -> Result<(), ValidationError> {
+    let mut run = 0;
+    for (index, value) in values.iter().enumerate() {
+        if value.abs() >= ECG_MAX_AMPLITUDE - ECG_CLIPPING_MARGIN {
+            run += 1;
+            if run == ECG_CLIPPING_MIN_RUN {
+                let start = index + 1 - ECG_CLIPPING_MIN_RUN;
+                return Err(ValidationError::new("clipping").with_message(
+                    format!("Lead is clipped at the amplitude limit from sample {start}").into(),
+                ));
+            }
+        } else {
+            run = 0;
+        }
+    }
+    Ok(())
+}
+
+/// Check Einthoven's law: lead III is lead II minus lead I, up to noise
+/// # Arguments
+/// * `lead_i` - The samples of lead I
+/// * `lead_ii` - The samples of lead II
+/// * `lead_iii` - The samples of lead III, of the same length
+/// # Returns
+/// * A Result containing a unit type or a ValidationError
+pub fn check_einthoven(
+    lead_i: &[f32],
+    lead_ii: &[f32],
+    lead_iii: &[f32],
+) -> Result<(), ValidationError> {
+    if lead_i.is_empty() {
+        return Ok(());
+    }
+    // STEP 1: RMS of the residual, accumulated in f64 to keep the precision on long recordings
+    let squared: f64 = lead_i
+        .iter()
+        .zip(lead_ii)
+        .zip(lead_iii)
+        .map(|((i, ii), iii)| f64::from(iii - (ii - i)).powi(2))
+        .sum();
+    let rms = (squared / lead_i.len() as f64).sqrt();
+
+    // STEP 2: Compare against the tolerance
+    if rms > ECG_EINTHOVEN_MAX_RMS {
+        return Err(ValidationError::new("lead_inconsistent").with_message(
+            format!("Lead III differs from lead II - lead I by {rms:.3} mV RMS").into(),
+        ));
+    }
+    Ok(())
+}
+
+/// Number of samples each lead must have, from the declared sampling metadata
+/// # Arguments
+/// * `sampling_rate_hz` - The declared sampling rate, ECG_DEFAULT_SAMPLING_RATE_HZ if absent
+/// * `duration_s` - The declared recording duration, ECG_DEFAULT_DURATION_S if absent
+/// # Returns
+/// * The expected lead length, or a ValidationError if the metadata is out of range or does not
+///   describe a whole number of samples
+pub fn expected_lead_length(
+    sampling_rate_hz: Option<f32>,
+    duration_s: Option<f32>,
+) -> Result<usize, ValidationError> {
+    let rate = sampling_rate_hz.unwrap_or(ECG_DEFAULT_SAMPLING_RATE_HZ);
+    let duration = duration_s.unwrap_or(ECG_DEFAULT_DURATION_S);
+    if !(ECG_MIN_SAMPLING_RATE_HZ..=ECG_MAX_SAMPLING_RATE_HZ).contains(&rate) {
+        return Err(ValidationError::new("sampling").with_message(
+            format!(
+                "sampling_rate_hz must be between {ECG_MIN_SAMPLING_RATE_HZ} and \
+                 {ECG_MAX_SAMPLING_RATE_HZ}"
+            )
+            .into(),
+        ));
+    }
+    if !(duration > 0.0 && duration <= ECG_MAX_DURATION_S) {
+        return Err(ValidationError::new("sampling").with_message(
+            format!("duration_s must be positive and at most {ECG_MAX_DURATION_S}").into(),
+        ));
+    }
+    let samples = f64::from(rate) * f64::from(duration);
+    if (samples - samples.round()).abs() > 1e-3 {
+        return Err(ValidationError::new("sampling").with_message(
+            format!("sampling_rate_hz * duration_s is not a whole number of samples ({samples})")
+                .into(),
+        ));
+    }
+    Ok(samples.round() as usize)
+}
+
+// TESTS *******************************************************************************************
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::models_exams::ECG_LEAD_LENGTH;
+
+    // Happy path: finite samples pass, NaN and infinity are refused with their position
+    #[test]
+    fn finite_samples() {
+        assert!(check_finite(&[0.0, -1.5, 2.0]).is_ok());
+        let error = check_finite(&[0.0, f32::NAN]).unwrap_err();
+        assert_eq!(error.code, "non_finite");
+        assert!(error.message.unwrap().contains("Sample 1"));
+        assert!(check_finite(&[f32::NEG_INFINITY]).is_err());
+    }
+
+    // Borderline: a peak touching the limit is not clipping, a plateau on it is
+    #[test]
+    fn clipping_runs() {
+        let mut lead = vec![0.1; 100];
+        lead[10..10 + ECG_CLIPPING_MIN_RUN - 1].fill(ECG_MAX_AMPLITUDE);
+        assert!(check_clipping(&lead).is_ok());
+        lead[50..50 + ECG_CLIPPING_MIN_RUN].fill(-1.9995);
+        let error = check_clipping(&lead).unwrap_err();
+        assert_eq!(error.code, "clipping");
+        assert!(error.message.unwrap().contains("sample 50"));
+    }
+
+    // Happy path: derived limb leads pass; Error handling: swapped leads are refused
+    #[test]
+    fn einthoven_consistency() {
+        let lead_i: Vec<f32> = (0..500).map(|t| (t as f32 / 20.0).sin() * 0.4).collect();
+        let lead_ii: Vec<f32> = (0..500).map(|t| (t as f32 / 15.0).cos() * 0.8).collect();
+        let lead_iii: Vec<f32> = lead_ii.iter().zip(&lead_i).map(|(b, a)| b - a).collect();
+        assert!(check_einthoven(&lead_i, &lead_ii, &lead_iii).is_ok());
+        let error = check_einthoven(&lead_ii, &lead_i, &lead_iii).unwrap_err();
+        assert_eq!(error.code, "lead_inconsistent");
+    }
+
+    // Happy path: the defaults describe the historical fixed lead length
+    #[test]
+    fn sampling_defaults() {
+        assert_eq!(expected_lead_length(None, None).unwrap(), ECG_LEAD_LENGTH);
+        assert_eq!(expected_lead_length(Some(250.0), Some(12.0)).unwrap(), 3000);
+        assert_eq!(expected_lead_length(None, Some(2.5)).unwrap(), 1250);
+    }
+
+    // Error handling: out of range or fractional sampling metadata is refused
+    #[test]
+    fn sampling_refused() {
+        for (rate, duration) in [
+            (Some(50.0), None),
+            (Some(f32::NAN), None),
+            (None, Some(0.0)),
+            (None, Some(61.0)),
+            (Some(333.0), Some(0.01)),
+        ] {
+            let error = expected_lead_length(rate, duration).unwrap_err();
+            assert_eq!(error.code, "sampling", "{rate:?} {duration:?}");
+        }
+    }
+}
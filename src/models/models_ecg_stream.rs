@@ -0,0 +1,239 @@
+// Imports *****************************************************************************************
+// External Crates
+use serde::{Deserialize, Serialize};
+
+// Internal Modules
+use crate::models::models_ecg_quality::expected_lead_length;
+use crate::models::models_exams::PayloadEcg;
+use crate::models::models_ids::Sha256Hex;
+
+// Constants ***************************************************************************************
+/// Leads of every chunk, in this order: I, II, III, aVR, aVL, aVF, V1 to V6
+pub const STREAM_LEADS: usize = 12;
+/// Windows of samples a session may buffer before the chunks are refused
+const MAX_BUFFERED_WINDOWS: usize = 2;
+
+// Structs *****************************************************************************************
+/// Frame sent by a device on the ECG stream, as a JSON text message
+/// - `open` once, first: the patient and the sampling metadata of the windows
+/// - `samples` then: the next samples of the 12 leads, numbered from 0 without gaps
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum StreamFrame {
+    Open {
+        patient_id: Sha256Hex,
+        hospital_id: Sha256Hex,
+        sampling_rate_hz: Option<f32>,
+        duration_s: Option<f32>,
+    },
+    Samples {
+        seq: u64,
+        leads: Vec<Vec<f32>>,
+    },
+}
+
+/// Outcome of a window, sent back to the device as a JSON text message
+/// # Arguments
+/// * `window` - The index of the window in the session, from 0
+/// * `status` - The HTTP status the window would have got from `POST /v1/ecg_exam`
+/// * `body` - The response body of that status (exam id, or error code and reason)
+#[derive(Debug, Serialize)]
+pub struct WindowOutcome {
+    pub window: u64,
+    pub status: u16,
+    pub body: serde_json::Value,
+}
+
+/// Reassembly of the fixed-length windows of a session from the chunks of a device
+/// # Arguments
+/// * `patient_id` / `hospital_id` - The ids of the `open` frame, copied to every window
+/// * `sampling_rate_hz` / `duration_s` - The sampling metadata of every window
+/// * `window_len` - Samples per lead of a window
+/// * `leads` - The samples received and not yet part of a window
+/// * `next_seq` - The sequence number expected next
+#[derive(Debug)]
+pub struct WindowAssembler {
+    patient_id: Sha256Hex,
+    hospital_id: Sha256Hex,
+    sampling_rate_hz: Option<f32>,
+    duration_s: Option<f32>,
+    window_len: usize,
+    leads: Vec<Vec<f32>>,
+    next_seq: u64,
+}
+
+impl WindowAssembler {
+    /// Start the reassembly of a session from its `open` frame
+    /// # Errors
+    /// * Returns the reason if the frame is not `open` or its sampling metadata is invalid
+    pub fn open(frame: StreamFrame) -> Result<Self, String> {
+        let StreamFrame::Open {
+            patient_id,
+            hospital_id,
+            sampling_rate_hz,
+            duration_s,
+        } = frame
+        else {
+            return Err("The first frame must be 'open'".to_string());
+        };
+        let window_len = expected_lead_length(sampling_rate_hz, duration_s).map_err(|e| {
+            e.message
+                .map_or_else(|| e.code.to_string(), |m| m.to_string())
+        })?;
+        Ok(Self {
+            patient_id,
+            hospital_id,
+            sampling_rate_hz,
+            duration_s,
+            window_len,
+            leads: (0..STREAM_LEADS)
+                .map(|_| Vec::with_capacity(window_len))
+                .collect(),
+            next_seq: 0,
+        })
+    }
+
+    /// Add a chunk of samples and take the windows it completes
+    /// # Arguments
+    /// * `frame` - A `samples` frame
+    /// # Returns
+    /// * The completed windows, oldest first - the remaining samples wait for the next chunks
+    /// # Errors
+    /// * Returns the reason if the frame is not `samples`, out of sequence, malformed, or would
+    ///   buffer more than MAX_BUFFERED_WINDOWS windows
+    pub fn push(&mut self, frame: StreamFrame) -> Result<Vec<PayloadEcg>, String> {
+        // STEP 1: The next chunk, with the same number of samples for the 12 leads
+        let StreamFrame::Samples { seq, leads } = frame else {
+            return Err("The session is already open".to_string());
+        };
+        if seq != self.next_seq {
+            return Err(format!("Expected chunk {}, got {seq}", self.next_seq));
+        }
+        if leads.len() != STREAM_LEADS {
+            return Err(format!(
+                "A chunk has {STREAM_LEADS} leads, got {}",
+                leads.len()
+            ));
+        }
+        let samples = leads[0].len();
+        if leads.iter().any(|lead| lead.len() != samples) {
+            return Err("Every lead of a chunk must have the same number of samples".to_string());
+        }
+        if self.leads[0].len() + samples > self.window_len * MAX_BUFFERED_WINDOWS {
+            return Err(format!(
+                "Chunk too large: at most {} samples may be buffered per lead",
+                self.window_len * MAX_BUFFERED_WINDOWS
+            ));
+        }
+        self.next_seq += 1;
+        for (buffered, chunk) in self.leads.iter_mut().zip(leads) {
+            buffered.extend(chunk);
+        }
+
+        // STEP 2: Cut the complete windows
+        let mut windows = Vec::new();
+        while self.leads[0].len() >= self.window_len {
+            let mut window: Vec<Vec<f32>> = self
+                .leads
+                .iter_mut()
+                .map(|lead| lead.drain(..self.window_len).collect())
+                .collect();
+            windows.push(self.payload(&mut window));
+        }
+        Ok(windows)
+    }
+
+    /// Samples per lead still waiting for a complete window
+    pub fn pending_samples(&self) -> usize {
+        self.leads[0].len()
+    }
+
+    /// ECG payload of a window of 12 leads
+    fn payload(&self, leads: &mut [Vec<f32>]) -> PayloadEcg {
+        let mut lead = |index: usize| std::mem::take(&mut leads[index]);
+        PayloadEcg {
+            patient_id: self.patient_id.clone(),
+            hospital_id: self.hospital_id.clone(),
+            hospital_key: None,
+            lead_i: lead(0),
+            lead_ii: lead(1),
+            lead_iii: lead(2),
+            lead_avr: lead(3),
+            lead_avl: lead(4),
+            lead_avf: lead(5),
+            lead_v1: lead(6),
+            lead_v2: lead(7),
+            lead_v3: lead(8),
+            lead_v4: lead(9),
+            lead_v5: lead(10),
+            lead_v6: lead(11),
+            sampling_rate_hz: self.sampling_rate_hz,
+            duration_s: self.duration_s,
+        }
+    }
+}
+
+// TESTS *******************************************************************************************
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn open(sampling_rate_hz: f32, duration_s: f32) -> StreamFrame {
+        serde_json::from_value(serde_json::json!({
+            "type": "open",
+            "patient_id": "AB12",
+            "hospital_id": "AB",
+            "sampling_rate_hz": sampling_rate_hz,
+            "duration_s": duration_s,
+        }))
+        .unwrap()
+    }
+
+    fn samples(seq: u64, count: usize) -> StreamFrame {
+        StreamFrame::Samples {
+            seq,
+            leads: (0..STREAM_LEADS)
+                .map(|lead| (0..count).map(|i| (lead * 1000 + i) as f32).collect())
+                .collect(),
+        }
+    }
+
+    // Happy path: chunks of any size are cut into fixed-length windows, in order
+    #[test]
+    fn windows_reassembled() {
+        let mut assembler = WindowAssembler::open(open(100.0, 2.0)).unwrap();
+        assert!(assembler.push(samples(0, 150)).unwrap().is_empty());
+        let windows = assembler.push(samples(1, 250)).unwrap();
+        assert_eq!(windows.len(), 2);
+        assert_eq!(windows[0].lead_i.len(), 200);
+        assert_eq!(windows[0].lead_i[0], 0.0);
+        assert_eq!(windows[1].lead_i[0], 50.0);
+        assert_eq!(windows[1].lead_v6[0], 11_000.0 + 50.0);
+        assert_eq!(windows[1].patient_id.to_string(), "ab12");
+        assert_eq!(windows[1].sampling_rate_hz, Some(100.0));
+        assert_eq!(assembler.pending_samples(), 0);
+    }
+
+    // Error handling: gaps, malformed chunks and reopening are refused
+    #[test]
+    fn chunks_refused() {
+        let mut assembler = WindowAssembler::open(open(100.0, 2.0)).unwrap();
+        assert!(assembler.push(samples(1, 10)).is_err());
+        let mut ragged = samples(0, 10);
+        if let StreamFrame::Samples { leads, .. } = &mut ragged {
+            leads[3].pop();
+        }
+        assert!(assembler.push(ragged).is_err());
+        assert!(assembler.push(open(100.0, 2.0)).is_err());
+        assert!(WindowAssembler::open(samples(0, 10)).is_err());
+        assert!(WindowAssembler::open(open(1.0, 2.0)).is_err());
+    }
+
+    // Borderline: a chunk may not buffer more than two windows
+    #[test]
+    fn buffer_bounded() {
+        let mut assembler = WindowAssembler::open(open(100.0, 2.0)).unwrap();
+        assert!(assembler.push(samples(0, 401)).is_err());
+        assert_eq!(assembler.push(samples(0, 400)).unwrap().len(), 2);
+    }
+}
@@ -2,25 +2,42 @@
 // External Crates
 use base64::engine::general_purpose::STANDARD;
 use base64::Engine;
-use image::ImageReader;
+use image::{ImageFormat, ImageReader};
 use serde::{Deserialize, Serialize};
 use std::io::Cursor;
+use utoipa::openapi::schema::{
+    Array, ArrayBuilder, KnownFormat, Object, ObjectBuilder, SchemaFormat, Type,
+};
+use utoipa::{IntoParams, ToSchema};
 use validator::{Validate, ValidationError};
 
 // Internal Modules
+use crate::models::models_ecg_quality::{
+    check_clipping, check_einthoven, check_finite, expected_lead_length, ECG_DEFAULT_DURATION_S,
+    ECG_DEFAULT_SAMPLING_RATE_HZ, ECG_MAX_AMPLITUDE, ECG_MAX_DURATION_S, ECG_MAX_SAMPLING_RATE_HZ,
+    ECG_MIN_SAMPLING_RATE_HZ,
+};
+use crate::models::models_ids::Sha256Hex;
 
 // Constants ***************************************************************************************
-pub const ECG_LEAD_LENGTH: usize = 5000; // Length of each ECG lead
+pub const ECG_LEAD_LENGTH: usize = 5000; // Length of each ECG lead without sampling metadata
+pub const XRAY_IMAGE_SIZE: u32 = 1024; // Width and height of the chest X-ray image
+pub const XRAY_MAX_IMAGE_BYTES: usize = 3 * 1024 * 1024; // Largest accepted (decoded) X-ray image
+pub const XRAY_VIEW_POSITIONS: [&str; 4] = ["PA", "AP", "LL", "RL"]; // Accepted projections
+pub const XRAY_SIGNATURE_BYTES: usize = 132; // Leading bytes needed to recognise an uploaded image
+pub const SHA256_HEX_LENGTH: usize = 64; // Hex characters of a SHA256 digest (hospital id)
+pub const PATIENT_ID_MAX_LENGTH: usize = 100; // Longest accepted patient id
 
 // MAIN FUNCTIONS AND STRUCTS **********************************************************************
 // Payload struct for the ECG exam data-------------------------------------------------------------
-#[derive(Debug, Serialize, Deserialize, Validate, Clone)]
+#[derive(Debug, Serialize, Deserialize, Validate, Clone, ToSchema)]
 #[serde(deny_unknown_fields)]
+#[validate(schema(function = "validate_ecg_signal"))]
 /// Data Model for the ECG exam
 /// # Arguments
-/// * `patient_id` - A string representing the patient id (SHA256 hash)
-/// * `hospital_id` - A string representing the hospital id (SHA256 hash)
-/// * `hospital_key` - A string representing the hospital key (SHA256 hash)
+/// * `patient_id` - The patient id (SHA256 hash, lowercased when it is hex)
+/// * `hospital_id` - The hospital id (SHA256 hash, lowercased when it is hex)
+/// * `hospital_key` - Deprecated, optional copy of the hospital key header (SHA256 hash)
 /// * `lead_i` - A vector of f32 representing the Lead I of the ECG exam
 /// * `lead_ii` - A vector of f32 representing the Lead II of the ECG exam
 /// * `lead_iii` - A vector of f32 representing the Lead III of the ECG exam
@@ -33,79 +50,209 @@ pub const ECG_LEAD_LENGTH: usize = 5000; // Length of each ECG lead
 /// * `lead_v4` - A vector of f32 representing the Lead V4 of the ECG exam
 /// * `lead_v5` - A vector of f32 representing the Lead V5 of the ECG exam
 /// * `lead_v6` - A vector of f32 representing the Lead V6 of the ECG exam
+/// * `sampling_rate_hz` - Optional sampling rate of the leads, 500 Hz if absent
+/// * `duration_s` - Optional duration of the recording, 10 s if absent
 /// # Returns
 /// * A Payload struct containing the data of the ECG exam
 pub struct PayloadEcg {
     // Patient id as a string - validation by custom function
     #[validate(custom(function = "validate_patient_id"))]
-    pub patient_id: String,
+    #[schema(schema_with = patient_id_schema)]
+    pub patient_id: Sha256Hex,
 
     // Hospital id as a string - SHA256 hash
     #[validate(custom(function = "validate_sha256"))]
-    pub hospital_id: String,
+    #[schema(schema_with = sha256_schema)]
+    pub hospital_id: Sha256Hex,
 
-    // Hospital key as a string - with exact length of 100 characters
+    // Hospital key as a string - deprecated: the hospital_key header authenticates the request
     #[validate(length(max = 100))]
-    pub hospital_key: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schema(max_length = 100)]
+    pub hospital_key: Option<String>,
 
     // Lead I should be valid by custom validation function
     #[validate(custom(function = "validate_ecg_leads"))]
+    #[schema(schema_with = ecg_lead_schema)]
     pub lead_i: Vec<f32>,
     // Lead II should be valid by custom validation function
     #[validate(custom(function = "validate_ecg_leads"))]
+    #[schema(schema_with = ecg_lead_schema)]
     pub lead_ii: Vec<f32>,
     // Lead III should be valid by custom validation function
     #[validate(custom(function = "validate_ecg_leads"))]
+    #[schema(schema_with = ecg_lead_schema)]
     pub lead_iii: Vec<f32>,
     // Lead aVR should be valid by custom validation function
     #[validate(custom(function = "validate_ecg_leads"))]
+    #[schema(schema_with = ecg_lead_schema)]
     pub lead_avr: Vec<f32>,
     // Lead aVL should be valid by custom validation function
     #[validate(custom(function = "validate_ecg_leads"))]
+    #[schema(schema_with = ecg_lead_schema)]
     pub lead_avl: Vec<f32>,
     // Lead aVF should be valid by custom validation function
     #[validate(custom(function = "validate_ecg_leads"))]
+    #[schema(schema_with = ecg_lead_schema)]
     pub lead_avf: Vec<f32>,
     // Lead V1 should be valid by custom validation function
     #[validate(custom(function = "validate_ecg_leads"))]
+    #[schema(schema_with = ecg_lead_schema)]
     pub lead_v1: Vec<f32>,
     // Lead V2 should be valid by custom validation function
     #[validate(custom(function = "validate_ecg_leads"))]
+    #[schema(schema_with = ecg_lead_schema)]
     pub lead_v2: Vec<f32>,
     // Lead V3 should be valid by custom validation function
     #[validate(custom(function = "validate_ecg_leads"))]
+    #[schema(schema_with = ecg_lead_schema)]
     pub lead_v3: Vec<f32>,
     // Lead V4 should be valid by custom validation function
     #[validate(custom(function = "validate_ecg_leads"))]
+    #[schema(schema_with = ecg_lead_schema)]
     pub lead_v4: Vec<f32>,
     // Lead V5 should be valid by custom validation function
     #[validate(custom(function = "validate_ecg_leads"))]
+    #[schema(schema_with = ecg_lead_schema)]
     pub lead_v5: Vec<f32>,
     // Lead V6 should be valid by custom validation function
     #[validate(custom(function = "validate_ecg_leads"))]
+    #[schema(schema_with = ecg_lead_schema)]
     pub lead_v6: Vec<f32>,
+
+    // Sampling metadata - every lead must have sampling_rate_hz * duration_s samples
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schema(schema_with = sampling_rate_schema)]
+    pub sampling_rate_hz: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schema(schema_with = duration_schema)]
+    pub duration_s: Option<f32>,
 }
 
 // Payload struct for the XRAY exam data -----------------------------------------------------------
-#[derive(Debug, Serialize, Deserialize, Validate, Clone)]
+#[derive(Debug, Serialize, Deserialize, Validate, Clone, ToSchema)]
 #[serde(deny_unknown_fields)]
 /// Data Model for the XRAY exam
+/// # Arguments
+/// * `patient_id` - The patient id (SHA256 hash, lowercased when it is hex)
+/// * `hospital_id` - The hospital id (SHA256 hash, lowercased when it is hex)
+/// * `hospital_key` - Deprecated, optional copy of the hospital key header
+/// * `image` - The chest X-ray as a base64 encoded PNG or JPEG of XRAY_IMAGE_SIZE pixels squared
+/// * `view_position` - Optional projection of the image (PA, AP, LL or RL)
+/// # Returns
+/// * A Payload struct containing the data of the XRAY exam
 pub struct PayloadXray {
     // Patient id as a string - validation by custom function
     #[validate(custom(function = "validate_patient_id"))]
-    pub patient_id: String,
+    #[schema(schema_with = patient_id_schema)]
+    pub patient_id: Sha256Hex,
 
     // Hospital id as a string - SHA256 hash
     #[validate(custom(function = "validate_sha256"))]
-    pub hospital_id: String,
+    #[schema(schema_with = sha256_schema)]
+    pub hospital_id: Sha256Hex,
 
-    // Hospital key as a string - with exact length of 100 characters
+    // Hospital key as a string - deprecated: the hospital_key header authenticates the request
     #[validate(length(max = 100))]
-    pub hospital_key: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schema(max_length = 100)]
+    pub hospital_key: Option<String>,
 
     // Image as a base64 encoded string
     #[validate(custom(function = "validate_1024_base64_image"))]
+    #[schema(schema_with = xray_image_schema)]
     pub image: String,
+
+    // Projection of the image - optional, one of XRAY_VIEW_POSITIONS
+    #[validate(custom(function = "validate_view_position"))]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schema(schema_with = view_position_schema)]
+    pub view_position: Option<String>,
+}
+
+// Metadata of the streamed XRAY upload -----------------------------------------------------------
+#[derive(Debug, Serialize, Deserialize, Validate, Clone, ToSchema, IntoParams)]
+#[serde(deny_unknown_fields)]
+#[into_params(parameter_in = Query)]
+/// Data Model for the metadata of a streamed XRAY upload - sent as the `metadata` part of a
+/// multipart body, or as query parameters of a raw image body
+/// # Arguments
+/// * `patient_id` - The patient id (SHA256 hash, lowercased when it is hex)
+/// * `hospital_id` - The hospital id (SHA256 hash, lowercased when it is hex)
+/// * `view_position` - Optional projection of the image (PA, AP, LL or RL)
+/// # Returns
+/// * A struct containing the metadata of the uploaded XRAY exam
+pub struct XrayUploadMetadata {
+    // Patient id as a string - validation by custom function
+    #[validate(custom(function = "validate_patient_id"))]
+    #[schema(schema_with = patient_id_schema)]
+    #[param(schema_with = patient_id_schema)]
+    pub patient_id: Sha256Hex,
+
+    // Hospital id as a string - SHA256 hash
+    #[validate(custom(function = "validate_sha256"))]
+    #[schema(schema_with = sha256_schema)]
+    #[param(schema_with = sha256_schema)]
+    pub hospital_id: Sha256Hex,
+
+    // Projection of the image - optional, one of XRAY_VIEW_POSITIONS
+    #[validate(custom(function = "validate_view_position"))]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schema(schema_with = view_position_schema)]
+    #[param(schema_with = view_position_schema)]
+    pub view_position: Option<String>,
+}
+
+/// Format of a streamed XRAY image - declared by the content type, confirmed by its signature
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum XrayImageFormat {
+    Png,
+    Jpeg,
+    Dicom,
+}
+
+impl XrayImageFormat {
+    /// Format declared by a content type, if it is accepted for upload
+    /// # Arguments
+    /// * `content_type` - The media type of the body or of the image part, without parameters
+    pub fn from_content_type(content_type: &str) -> Option<Self> {
+        match content_type.trim().to_ascii_lowercase().as_str() {
+            "image/png" => Some(XrayImageFormat::Png),
+            "image/jpeg" => Some(XrayImageFormat::Jpeg),
+            "application/dicom" => Some(XrayImageFormat::Dicom),
+            _ => None,
+        }
+    }
+
+    /// Whether the leading bytes of the image carry the signature of the format
+    /// # Arguments
+    /// * `head` - The first XRAY_SIGNATURE_BYTES of the image (fewer if the image is shorter)
+    pub fn matches_signature(&self, head: &[u8]) -> bool {
+        match self {
+            XrayImageFormat::Png => head.starts_with(b"\x89PNG\r\n\x1a\n"),
+            XrayImageFormat::Jpeg => head.starts_with(&[0xFF, 0xD8, 0xFF]),
+            // DICOM Part 10: 128 bytes of preamble, then the DICM prefix
+            XrayImageFormat::Dicom => head.get(128..132) == Some(b"DICM".as_slice()),
+        }
+    }
+
+    /// File extension of the stored image
+    pub fn extension(&self) -> &'static str {
+        match self {
+            XrayImageFormat::Png => "png",
+            XrayImageFormat::Jpeg => "jpeg",
+            XrayImageFormat::Dicom => "dcm",
+        }
+    }
+
+    /// Content type of the stored image
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            XrayImageFormat::Png => "image/png",
+            XrayImageFormat::Jpeg => "image/jpeg",
+            XrayImageFormat::Dicom => "application/dicom",
+        }
+    }
 }
 
 // SUPPORTING FUNCTIONS ****************************************************************************
@@ -115,10 +262,9 @@ pub struct PayloadXray {
 /// # Returns
 /// * A Result containing a unit type or a ValidationError
 fn validate_sha256(sha256: &str) -> Result<(), ValidationError> {
-    if sha256.len() != 64 || !sha256.chars().all(|c| c.is_ascii_hexdigit()) {
-        Err(ValidationError::new(
-            "Patient_id must be a valid SHA256 hash",
-        ))
+    if sha256.len() != SHA256_HEX_LENGTH || !sha256.chars().all(|c| c.is_ascii_hexdigit()) {
+        Err(ValidationError::new("invalid_id")
+            .with_message("Patient_id must be a valid SHA256 hash".into()))
     } else {
         Ok(())
     }
@@ -130,8 +276,8 @@ fn validate_sha256(sha256: &str) -> Result<(), ValidationError> {
 /// # Returns
 /// * A Result containing a unit type or a ValidationError
 fn validate_patient_id(patient_id: &str) -> Result<(), ValidationError> {
-    if patient_id.is_empty() || patient_id.len() > 100 {
-        return Err(ValidationError::new("Invalid patient ID length"));
+    if patient_id.is_empty() || patient_id.len() > PATIENT_ID_MAX_LENGTH {
+        Err(ValidationError::new("invalid_id").with_message("Invalid patient ID length".into()))
     } else {
         Ok(())
     }
@@ -143,26 +289,52 @@ fn validate_patient_id(patient_id: &str) -> Result<(), ValidationError> {
 /// # Returns
 /// * A Result containing a unit type or a ValidationError
 fn validate_ecg_leads(values: &[f32]) -> Result<(), ValidationError> {
-    // Check if the length of the leads is exactly ECG_LEAD_LENGTH samples
-    if values.len() != ECG_LEAD_LENGTH {
-        return Err(ValidationError::new(
-            "Leads must contain exactly ECG_LEAD_LENGTH samples",
-        ));
-    }
+    // Check that every sample is a number - NaN would pass the amplitude check
+    check_finite(values)?;
     // Check if the values are within the valid range
-    let max_amplitude = 2.0;
-    if values.iter().any(|&v| v.abs() > max_amplitude) {
-        return Err(ValidationError::new(
-            "Leads values must be between -2.0 and 2.0",
-        ));
+    if values.iter().any(|&v| v.abs() > ECG_MAX_AMPLITUDE) {
+        return Err(ValidationError::new("amplitude")
+            .with_message("Leads values must be between -2.0 and 2.0".into()));
     }
     // Check if the patient is not flat-line
     if values.iter().all(|&v| v == 0.0) {
-        return Err(ValidationError::new(
-            "Leads cannot be flat-line (all values are zero)",
+        return Err(ValidationError::new("flat_line")
+            .with_message("Leads cannot be flat-line (all values are zero)".into()));
+    }
+    // Check that the amplifier did not saturate
+    check_clipping(values)
+}
+
+/// Custom validation function for the ECG signal as a whole, run once every lead is valid
+/// # Arguments
+/// * `payload` - The ECG payload
+/// # Returns
+/// * A Result containing a unit type or a ValidationError
+fn validate_ecg_signal(payload: &PayloadEcg) -> Result<(), ValidationError> {
+    // STEP 1: Lead length declared by the sampling metadata
+    let expected = expected_lead_length(payload.sampling_rate_hz, payload.duration_s)?;
+    let leads = [
+        ("lead_i", &payload.lead_i),
+        ("lead_ii", &payload.lead_ii),
+        ("lead_iii", &payload.lead_iii),
+        ("lead_avr", &payload.lead_avr),
+        ("lead_avl", &payload.lead_avl),
+        ("lead_avf", &payload.lead_avf),
+        ("lead_v1", &payload.lead_v1),
+        ("lead_v2", &payload.lead_v2),
+        ("lead_v3", &payload.lead_v3),
+        ("lead_v4", &payload.lead_v4),
+        ("lead_v5", &payload.lead_v5),
+        ("lead_v6", &payload.lead_v6),
+    ];
+    if let Some((name, lead)) = leads.iter().find(|(_, lead)| lead.len() != expected) {
+        return Err(ValidationError::new("lead_length").with_message(
+            format!("{name} has {} samples, expected {expected}", lead.len()).into(),
         ));
     }
-    Ok(())
+
+    // STEP 2: The limb leads must agree with each other
+    check_einthoven(&payload.lead_i, &payload.lead_ii, &payload.lead_iii)
 }
 
 /// Custom validation function for 1024x1024 base64 encoded image
@@ -171,15 +343,26 @@ fn validate_ecg_leads(values: &[f32]) -> Result<(), ValidationError> {
 /// # Returns
 /// * A Result containing a unit type or a ValidationError
 fn validate_1024_base64_image(base64_str: &str) -> Result<(), ValidationError> {
-    const IMAGE_SIZE: u32 = 1024;
+    const IMAGE_SIZE: u32 = XRAY_IMAGE_SIZE;
+    // Reject oversized images before decoding anything
+    if base64_str.len() > XRAY_MAX_IMAGE_BYTES.div_ceil(3) * 4 {
+        return Err(ValidationError::new("image_too_large"));
+    }
     // Bring it to raw bytes
     let decoded = STANDARD
         .decode(base64_str)
         .map_err(|_| ValidationError::new("invalid_base64"))?;
-    // read the image from the raw bytes
-    let img = ImageReader::new(Cursor::new(decoded))
+    if decoded.len() > XRAY_MAX_IMAGE_BYTES {
+        return Err(ValidationError::new("image_too_large"));
+    }
+    // read the image from the raw bytes - only PNG and JPEG are stored
+    let reader = ImageReader::new(Cursor::new(decoded))
         .with_guessed_format()
-        .map_err(|_| ValidationError::new("invalid_image_format"))?
+        .map_err(|_| ValidationError::new("invalid_image_format"))?;
+    if !matches!(reader.format(), Some(ImageFormat::Png | ImageFormat::Jpeg)) {
+        return Err(ValidationError::new("invalid_image_format"));
+    }
+    let img = reader
         .decode()
         .map_err(|_| ValidationError::new("decode_error"))?;
     // Check if dimensions are IMAGE_SIZE by IMAGE_SIZE
@@ -190,16 +373,112 @@ fn validate_1024_base64_image(base64_str: &str) -> Result<(), ValidationError> {
     }
 }
 
+/// Custom validation function for the X-ray view position
+/// # Arguments
+/// * `view_position` - A string representing the projection of the image
+/// # Returns
+/// * A Result containing a unit type or a ValidationError
+fn validate_view_position(view_position: &str) -> Result<(), ValidationError> {
+    if XRAY_VIEW_POSITIONS.contains(&view_position) {
+        Ok(())
+    } else {
+        Err(ValidationError::new("invalid_view_position"))
+    }
+}
+
+/// OpenAPI schema of a patient id, as checked by validate_patient_id
+fn patient_id_schema() -> Object {
+    ObjectBuilder::new()
+        .schema_type(Type::String)
+        .min_length(Some(1))
+        .max_length(Some(PATIENT_ID_MAX_LENGTH))
+        .description(Some("Patient id, SHA256 hash - lowercased when it is hex"))
+        .build()
+}
+
+/// OpenAPI schema of a hospital id, as checked by validate_sha256
+fn sha256_schema() -> Object {
+    ObjectBuilder::new()
+        .schema_type(Type::String)
+        .pattern(Some(format!("^[0-9a-fA-F]{{{SHA256_HEX_LENGTH}}}$")))
+        .description(Some("Hospital id, SHA256 hex digest - lowercased"))
+        .build()
+}
+
+/// OpenAPI schema of an ECG lead, as checked by validate_ecg_leads and validate_ecg_signal
+fn ecg_lead_schema() -> Array {
+    let sample = ObjectBuilder::new()
+        .schema_type(Type::Number)
+        .format(Some(SchemaFormat::KnownFormat(KnownFormat::Float)))
+        .minimum(Some(-f64::from(ECG_MAX_AMPLITUDE)))
+        .maximum(Some(f64::from(ECG_MAX_AMPLITUDE)))
+        .description(Some("Sample in mV"));
+    let max_samples = f64::from(ECG_MAX_SAMPLING_RATE_HZ) * f64::from(ECG_MAX_DURATION_S);
+    ArrayBuilder::new()
+        .items(sample)
+        .min_items(Some(1))
+        .max_items(Some(max_samples as usize))
+        .description(Some(format!(
+            "sampling_rate_hz * duration_s samples ({ECG_LEAD_LENGTH} by default), neither \
+             flat-line nor clipped"
+        )))
+        .build()
+}
+
+/// OpenAPI schema of the declared sampling rate, as checked by expected_lead_length
+fn sampling_rate_schema() -> Object {
+    ObjectBuilder::new()
+        .schema_type(Type::Number)
+        .format(Some(SchemaFormat::KnownFormat(KnownFormat::Float)))
+        .minimum(Some(f64::from(ECG_MIN_SAMPLING_RATE_HZ)))
+        .maximum(Some(f64::from(ECG_MAX_SAMPLING_RATE_HZ)))
+        .default(Some(ECG_DEFAULT_SAMPLING_RATE_HZ.into()))
+        .build()
+}
+
+/// OpenAPI schema of the declared recording duration, as checked by expected_lead_length
+fn duration_schema() -> Object {
+    ObjectBuilder::new()
+        .schema_type(Type::Number)
+        .format(Some(SchemaFormat::KnownFormat(KnownFormat::Float)))
+        .exclusive_minimum(Some(0.0))
+        .maximum(Some(f64::from(ECG_MAX_DURATION_S)))
+        .default(Some(ECG_DEFAULT_DURATION_S.into()))
+        .build()
+}
+
+/// OpenAPI schema of the base64 X-ray image, as checked by validate_1024_base64_image
+fn xray_image_schema() -> Object {
+    ObjectBuilder::new()
+        .schema_type(Type::String)
+        .content_encoding("base64")
+        .max_length(Some(XRAY_MAX_IMAGE_BYTES.div_ceil(3) * 4))
+        .description(Some(format!(
+            "PNG or JPEG of {XRAY_IMAGE_SIZE}x{XRAY_IMAGE_SIZE} pixels, at most \
+             {XRAY_MAX_IMAGE_BYTES} bytes decoded"
+        )))
+        .build()
+}
+
+/// OpenAPI schema of the view position, as checked by validate_view_position
+fn view_position_schema() -> Object {
+    ObjectBuilder::new()
+        .schema_type(Type::String)
+        .enum_values(Some(XRAY_VIEW_POSITIONS))
+        .build()
+}
+
 // TESTS *******************************************************************************************
 #[cfg(test)]
 mod tests {
     use super::*;
+    use actix_web::web::Query;
     use serde_json::json;
     use validator::Validate;
 
     /// Helper function to create a hex string of a given length
     fn hex_of(len: usize, ch: char) -> String {
-        std::iter::repeat(ch).take(len).collect()
+        std::iter::repeat_n(ch, len).collect()
     }
 
     /// Generates a valid SHA256 hash string of 64 characters
@@ -229,9 +508,9 @@ mod tests {
     /// Generates a Payload with valid IDs and a lead
     fn payload_with_lead(lead: Vec<f32>) -> PayloadEcg {
         PayloadEcg {
-            patient_id: valid_id(),
-            hospital_id: valid_id(),
-            hospital_key: valid_hospital_key(),
+            patient_id: valid_id().into(),
+            hospital_id: valid_id().into(),
+            hospital_key: Some(valid_hospital_key()),
             lead_i: lead.clone(),
             lead_ii: lead.clone(),
             lead_iii: lead.clone(),
@@ -244,6 +523,8 @@ mod tests {
             lead_v4: lead.clone(),
             lead_v5: lead.clone(),
             lead_v6: lead,
+            sampling_rate_hz: None,
+            duration_s: None,
         }
     }
 
@@ -282,10 +563,21 @@ mod tests {
     }
 
     #[test]
-    /// Tests the out-of-range length for ECG leads validation
-    fn leads_out_of_range_length() {
-        let v = lead_with(ECG_LEAD_LENGTH - 1, 0.5);
-        assert!(validate_ecg_leads(&v).is_err());
+    /// Tests the error case for ECG leads validation with NaN and infinite samples
+    fn leads_error_non_finite() {
+        let mut v = valid_lead();
+        v[10] = f32::NAN;
+        assert_eq!(validate_ecg_leads(&v).unwrap_err().code, "non_finite");
+        v[10] = f32::INFINITY;
+        assert_eq!(validate_ecg_leads(&v).unwrap_err().code, "non_finite");
+    }
+
+    #[test]
+    /// Tests the error case for ECG leads validation with a lead clipped at the limit
+    fn leads_error_clipping() {
+        let mut v = valid_lead();
+        v[100..200].fill(2.0);
+        assert_eq!(validate_ecg_leads(&v).unwrap_err().code, "clipping");
     }
 
     #[test]
@@ -327,14 +619,44 @@ mod tests {
         assert!(p.validate().is_err());
     }
 
+    #[test]
+    /// Tests that the lead length follows the declared sampling metadata
+    fn payload_sampling_metadata() {
+        let mut p = payload_with_lead(lead_with(2500, 0.5));
+        let errors = p.validate().unwrap_err();
+        assert_eq!(errors.field_errors()["__all__"][0].code, "lead_length");
+        p.sampling_rate_hz = Some(250.0);
+        assert!(p.validate().is_ok());
+        p.duration_s = Some(5.0);
+        let errors = p.validate().unwrap_err();
+        assert_eq!(errors.field_errors()["__all__"][0].code, "lead_length");
+        p.sampling_rate_hz = Some(20_000.0);
+        let errors = p.validate().unwrap_err();
+        assert_eq!(errors.field_errors()["__all__"][0].code, "sampling");
+    }
+
+    #[test]
+    /// Tests the error case for Payload validation with lead III not derived from I and II
+    fn payload_error_einthoven() {
+        let mut p = payload_with_lead(valid_lead());
+        p.lead_iii = (0..ECG_LEAD_LENGTH)
+            .map(|t| (t as f32 / 50.0).sin())
+            .collect();
+        let errors = p.validate().unwrap_err();
+        assert_eq!(
+            errors.field_errors()["__all__"][0].code,
+            "lead_inconsistent"
+        );
+    }
+
     #[test]
     /// Tests the borderline case for Payload validation with exact length and lower amplitude
     fn payload_borderline_ok_ids_and_edge_leads() {
         let lead = lead_with(ECG_LEAD_LENGTH, 2.0);
         let mut p = payload_with_lead(lead);
-        p.patient_id = hex_of(64, 'A'); // uppercase hex, exact len
-        p.hospital_id = hex_of(64, '0');
-        p.hospital_key = hex_of(100, 'f');
+        p.patient_id = hex_of(64, 'A').into(); // uppercase hex, exact len
+        p.hospital_id = hex_of(64, '0').into();
+        p.hospital_key = Some(hex_of(100, 'f'));
         assert!(p.validate().is_ok());
     }
 
@@ -372,4 +694,212 @@ mod tests {
         let res: Result<PayloadEcg, _> = serde_json::from_value(v);
         assert!(res.is_err());
     }
+
+    #[test]
+    /// Tests that mixed-case ids are stored under the same lowercase path
+    fn payload_serde_lowercases_ids() {
+        let v = json!({
+            "patient_id": hex_of(64, 'A'),
+            "hospital_id": hex_of(64, 'B'),
+            "image": "",
+        });
+        let p: PayloadXray = serde_json::from_value(v).unwrap();
+        assert_eq!(&*p.patient_id, hex_of(64, 'a'));
+        assert_eq!(&*p.hospital_id, hex_of(64, 'b'));
+    }
+
+    // ---------- PayloadXray::validate ----------
+    /// Encodes a grayscale image of the given size as base64 PNG
+    fn png_base64(width: u32, height: u32) -> String {
+        let img = image::GrayImage::from_pixel(width, height, image::Luma([128]));
+        let mut buffer = Cursor::new(Vec::new());
+        img.write_to(&mut buffer, ImageFormat::Png).unwrap();
+        STANDARD.encode(buffer.into_inner())
+    }
+
+    /// Generates a PayloadXray with valid IDs and the given image
+    fn xray_with_image(image: String) -> PayloadXray {
+        PayloadXray {
+            patient_id: valid_id().into(),
+            hospital_id: valid_id().into(),
+            hospital_key: Some(valid_hospital_key()),
+            image,
+            view_position: Some("PA".to_string()),
+        }
+    }
+
+    #[test]
+    /// Tests the happy path for PayloadXray validation
+    fn xray_happy_path() {
+        let p = xray_with_image(png_base64(XRAY_IMAGE_SIZE, XRAY_IMAGE_SIZE));
+        assert!(p.validate().is_ok());
+    }
+
+    #[test]
+    /// Tests the error case for PayloadXray validation with wrong dimensions
+    fn xray_error_dimensions() {
+        let p = xray_with_image(png_base64(512, 512));
+        assert!(p.validate().is_err());
+    }
+
+    #[test]
+    /// Tests the error cases for oversized images, invalid base64 and non-image bytes
+    fn xray_error_image_content() {
+        let too_large = "A".repeat(XRAY_MAX_IMAGE_BYTES.div_ceil(3) * 4 + 4);
+        assert!(validate_1024_base64_image(&too_large).is_err());
+        assert!(validate_1024_base64_image("not base64!").is_err());
+        assert!(validate_1024_base64_image(&STANDARD.encode(b"plain text")).is_err());
+    }
+
+    #[test]
+    /// Tests the view position: optional, but restricted when present
+    fn xray_view_position() {
+        let mut p = xray_with_image(png_base64(XRAY_IMAGE_SIZE, XRAY_IMAGE_SIZE));
+        p.view_position = None;
+        assert!(p.validate().is_ok());
+        p.view_position = Some("XX".to_string());
+        assert!(p.validate().is_err());
+    }
+    // ---------- XrayUploadMetadata / XrayImageFormat ----------
+    #[test]
+    /// Tests the metadata of a raw upload, sent as query parameters
+    fn upload_metadata_from_query() {
+        let query = format!(
+            "patient_id={}&hospital_id={}&view_position=PA",
+            valid_id(),
+            hex_of(64, 'B')
+        );
+        let metadata = Query::<XrayUploadMetadata>::from_query(&query)
+            .unwrap()
+            .into_inner();
+        assert!(metadata.validate().is_ok());
+        assert_eq!(&*metadata.hospital_id, hex_of(64, 'b'));
+        let query = format!(
+            "patient_id={}&hospital_id={}&view_position=XX",
+            valid_id(),
+            valid_id()
+        );
+        let metadata = Query::<XrayUploadMetadata>::from_query(&query)
+            .unwrap()
+            .into_inner();
+        assert!(metadata.validate().is_err());
+    }
+
+    #[test]
+    /// Tests the declared format is only accepted with its signature
+    fn upload_format_signature() {
+        let png = XrayImageFormat::from_content_type("Image/PNG").unwrap();
+        assert!(png.matches_signature(b"\x89PNG\r\n\x1a\n...."));
+        assert!(!png.matches_signature(&[0xFF, 0xD8, 0xFF, 0xE0]));
+
+        let mut dicom = vec![0u8; 128];
+        dicom.extend_from_slice(b"DICM");
+        let format = XrayImageFormat::from_content_type("application/dicom").unwrap();
+        assert!(format.matches_signature(&dicom));
+        assert!(!format.matches_signature(&dicom[..130]));
+        assert_eq!(format.extension(), "dcm");
+
+        assert_eq!(XrayImageFormat::from_content_type("image/gif"), None);
+    }
+
+    // ---------- contracts ----------
+    #[test]
+    /// Tests that the payloads sent by the hospitals keep their field names, order and types, and
+    /// round-trip unchanged
+    fn payload_contracts() {
+        let mut ecg = payload_with_lead(vec![0.5, -0.25]);
+        ecg.patient_id = "p1".into();
+        ecg.hospital_id = "h1".into();
+        ecg.hospital_key = Some("k1".to_string());
+        ecg.sampling_rate_hz = Some(62.5);
+        ecg.duration_s = Some(0.5);
+        insta::assert_json_snapshot!(ecg, @r#"
+        {
+          "patient_id": "p1",
+          "hospital_id": "h1",
+          "hospital_key": "k1",
+          "lead_i": [
+            0.5,
+            -0.25
+          ],
+          "lead_ii": [
+            0.5,
+            -0.25
+          ],
+          "lead_iii": [
+            0.5,
+            -0.25
+          ],
+          "lead_avr": [
+            0.5,
+            -0.25
+          ],
+          "lead_avl": [
+            0.5,
+            -0.25
+          ],
+          "lead_avf": [
+            0.5,
+            -0.25
+          ],
+          "lead_v1": [
+            0.5,
+            -0.25
+          ],
+          "lead_v2": [
+            0.5,
+            -0.25
+          ],
+          "lead_v3": [
+            0.5,
+            -0.25
+          ],
+          "lead_v4": [
+            0.5,
+            -0.25
+          ],
+          "lead_v5": [
+            0.5,
+            -0.25
+          ],
+          "lead_v6": [
+            0.5,
+            -0.25
+          ],
+          "sampling_rate_hz": 62.5,
+          "duration_s": 0.5
+        }
+        "#);
+        let back: PayloadEcg = serde_json::from_value(json!(ecg)).unwrap();
+        assert_eq!(json!(back), json!(ecg));
+
+        let xray: PayloadXray = serde_json::from_value(json!({
+            "patient_id": "p1",
+            "hospital_id": "h1",
+            "image": "iVBORw0KGgo=",
+            "view_position": "PA"
+        }))
+        .unwrap();
+        insta::assert_json_snapshot!(xray, @r#"
+        {
+          "patient_id": "p1",
+          "hospital_id": "h1",
+          "image": "iVBORw0KGgo=",
+          "view_position": "PA"
+        }
+        "#);
+
+        let upload: XrayUploadMetadata = Query::<XrayUploadMetadata>::from_query(
+            "patient_id=p1&hospital_id=h1&view_position=AP",
+        )
+        .unwrap()
+        .into_inner();
+        insta::assert_json_snapshot!(upload, @r#"
+        {
+          "patient_id": "p1",
+          "hospital_id": "h1",
+          "view_position": "AP"
+        }
+        "#);
+    }
 }
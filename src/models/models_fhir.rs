@@ -0,0 +1,457 @@
+// Imports *****************************************************************************************
+// External Crates
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+// Internal Modules
+use crate::models::models_exams::PayloadEcg;
+
+// Constants ***************************************************************************************
+/// Media type of FHIR JSON resources
+pub const FHIR_JSON: &str = "application/fhir+json";
+/// ISO/IEEE 11073 MDC code system of the ECG leads
+const MDC_SYSTEM: &str = "urn:oid:2.16.840.1.113883.6.24";
+/// MDC code and mnemonic of each of the 12 leads, in the order of `PayloadEcg`
+const ECG_LEAD_CODES: [(&str, &str); 12] = [
+    ("131329", "MDC_ECG_LEAD_I"),
+    ("131330", "MDC_ECG_LEAD_II"),
+    ("131389", "MDC_ECG_LEAD_III"),
+    ("131390", "MDC_ECG_LEAD_AVR"),
+    ("131391", "MDC_ECG_LEAD_AVL"),
+    ("131392", "MDC_ECG_LEAD_AVF"),
+    ("131331", "MDC_ECG_LEAD_V1"),
+    ("131332", "MDC_ECG_LEAD_V2"),
+    ("131333", "MDC_ECG_LEAD_V3"),
+    ("131334", "MDC_ECG_LEAD_V4"),
+    ("131335", "MDC_ECG_LEAD_V5"),
+    ("131336", "MDC_ECG_LEAD_V6"),
+];
+/// `PayloadEcg` field of each lead, for the FHIRPath of validation issues
+const ECG_LEAD_FIELDS: [&str; 12] = [
+    "lead_i", "lead_ii", "lead_iii", "lead_avr", "lead_avl", "lead_avf", "lead_v1", "lead_v2",
+    "lead_v3", "lead_v4", "lead_v5", "lead_v6",
+];
+
+// Structs *****************************************************************************************
+/// FHIR R4 `Observation` carrying a 12-lead ECG - only the elements the gateway reads are modelled,
+/// any other element is ignored
+/// # Arguments
+/// * `resource_type` - Must be `Observation`
+/// * `subject` - The patient, by identifier (the patient SHA256) or `Patient/{id}` reference
+/// * `performer` - The hospital, by identifier - the authenticated hospital if absent
+/// * `component` - One component per lead, its `valueSampledData` in mV
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FhirObservation {
+    pub resource_type: String,
+    #[serde(default)]
+    pub subject: Option<FhirReference>,
+    #[serde(default)]
+    pub performer: Vec<FhirReference>,
+    #[serde(default)]
+    pub component: Vec<FhirComponent>,
+}
+
+/// FHIR `Reference`, by literal reference or by identifier
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct FhirReference {
+    #[serde(default)]
+    pub reference: Option<String>,
+    #[serde(default)]
+    pub identifier: Option<FhirIdentifier>,
+}
+
+/// FHIR `Identifier` - only its value is used
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct FhirIdentifier {
+    #[serde(default)]
+    pub value: Option<String>,
+}
+
+/// FHIR `Observation.component`
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FhirComponent {
+    pub code: FhirCodeableConcept,
+    #[serde(default)]
+    pub value_sampled_data: Option<FhirSampledData>,
+}
+
+/// FHIR `CodeableConcept` - only its codings are used
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct FhirCodeableConcept {
+    #[serde(default)]
+    pub coding: Vec<FhirCoding>,
+}
+
+/// FHIR `Coding`
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct FhirCoding {
+    #[serde(default)]
+    pub system: Option<String>,
+    #[serde(default)]
+    pub code: Option<String>,
+}
+
+/// FHIR `SampledData`: sample = origin + factor * data, data as space separated decimals, one
+/// sample every `period` milliseconds
+#[derive(Debug, Clone, Deserialize)]
+pub struct FhirSampledData {
+    pub origin: FhirQuantity,
+    #[serde(default)]
+    pub period: Option<f64>,
+    #[serde(default)]
+    pub factor: Option<f64>,
+    pub dimensions: u32,
+    #[serde(default)]
+    pub data: Option<String>,
+}
+
+/// FHIR `Quantity` - `uV` is converted to mV, any other unit is taken as mV
+#[derive(Debug, Clone, Deserialize)]
+pub struct FhirQuantity {
+    pub value: f64,
+    #[serde(default)]
+    pub code: Option<String>,
+}
+
+/// One issue of a FHIR `OperationOutcome`
+/// # Arguments
+/// * `severity` - `error` for refused resources
+/// * `code` - The FHIR issue type, e.g. `invalid`, `required` or `structure`
+/// * `diagnostics` - What is wrong
+/// * `expression` - FHIRPath of the offending element
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct FhirIssue {
+    pub severity: &'static str,
+    pub code: &'static str,
+    pub diagnostics: String,
+    pub expression: Vec<String>,
+}
+
+/// FHIR `OperationOutcome` returned when an Observation is refused
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OperationOutcome {
+    pub resource_type: &'static str,
+    pub issue: Vec<FhirIssue>,
+}
+
+// MAIN FUNCTIONS **********************************************************************************
+impl FhirObservation {
+    /// Map the Observation to the internal ECG payload - the payload is validated afterwards, as
+    /// for the JSON route
+    /// # Arguments
+    /// * `authenticated_hospital_id` - The hospital id used when no performer is given
+    /// # Returns
+    /// * The ECG payload, or the issues of every element that could not be mapped
+    pub fn to_payload_ecg(
+        &self,
+        authenticated_hospital_id: &str,
+    ) -> Result<PayloadEcg, Vec<FhirIssue>> {
+        let mut issues = Vec::new();
+
+        // STEP 1: Resource type, patient and hospital
+        if self.resource_type != "Observation" {
+            issues.push(FhirIssue::error(
+                "invalid",
+                format!("Expected an Observation, got {}", self.resource_type),
+                "resourceType",
+            ));
+        }
+        let patient_id = self
+            .subject
+            .as_ref()
+            .and_then(|subject| reference_id(subject, "Patient/"));
+        if patient_id.is_none() {
+            issues.push(FhirIssue::error(
+                "required",
+                "The subject must identify the patient".to_string(),
+                "Observation.subject",
+            ));
+        }
+        let hospital_id = self
+            .performer
+            .first()
+            .and_then(|performer| reference_id(performer, "Organization/"))
+            .unwrap_or_else(|| authenticated_hospital_id.to_string());
+
+        // STEP 2: One sampled lead per MDC lead code
+        let mut leads: [Option<Vec<f32>>; 12] = Default::default();
+        let mut coded = [false; 12];
+        for (index, component) in self.component.iter().enumerate() {
+            let path = format!("Observation.component[{index}]");
+            let Some(lead) = component.lead_index() else {
+                continue;
+            };
+            if std::mem::replace(&mut coded[lead], true) {
+                issues.push(FhirIssue::error(
+                    "invalid",
+                    format!("Lead {} is sent twice", ECG_LEAD_CODES[lead].1),
+                    &path,
+                ));
+                continue;
+            }
+            match component
+                .value_sampled_data
+                .as_ref()
+                .map(FhirSampledData::samples)
+            {
+                Some(Ok(samples)) => leads[lead] = Some(samples),
+                Some(Err(e)) => issues.push(FhirIssue::error(
+                    "invalid",
+                    e,
+                    &format!("{path}.valueSampledData"),
+                )),
+                None => issues.push(FhirIssue::error(
+                    "required",
+                    "Lead components must have a valueSampledData".to_string(),
+                    &path,
+                )),
+            }
+        }
+        for (lead, coded) in coded.iter().enumerate() {
+            if !coded {
+                issues.push(FhirIssue::error(
+                    "required",
+                    format!("Missing lead {}", ECG_LEAD_CODES[lead].1),
+                    "Observation.component",
+                ));
+            }
+        }
+        if !issues.is_empty() {
+            return Err(issues);
+        }
+
+        // STEP 3: The sampling rate, from the period of the first lead that declares one
+        let sampling_rate_hz = self
+            .component
+            .iter()
+            .filter_map(|component| component.value_sampled_data.as_ref()?.period)
+            .find(|period| *period > 0.0)
+            .map(|period| (1000.0 / period) as f32);
+
+        // STEP 4: The payload, leads in the order of the model
+        let [lead_i, lead_ii, lead_iii, lead_avr, lead_avl, lead_avf, lead_v1, lead_v2, lead_v3, lead_v4, lead_v5, lead_v6] =
+            leads.map(Option::unwrap_or_default);
+        Ok(PayloadEcg {
+            patient_id: patient_id.unwrap_or_default().into(),
+            hospital_id: hospital_id.into(),
+            hospital_key: None,
+            lead_i,
+            lead_ii,
+            lead_iii,
+            lead_avr,
+            lead_avl,
+            lead_avf,
+            lead_v1,
+            lead_v2,
+            lead_v3,
+            lead_v4,
+            lead_v5,
+            lead_v6,
+            sampling_rate_hz,
+            duration_s: None,
+        })
+    }
+}
+
+impl FhirComponent {
+    /// Index of the lead coded by the component, in the order of `PayloadEcg`
+    fn lead_index(&self) -> Option<usize> {
+        self.code.coding.iter().find_map(|coding| {
+            let code = coding.code.as_deref()?;
+            let mdc = coding
+                .system
+                .as_deref()
+                .is_none_or(|system| system == MDC_SYSTEM);
+            ECG_LEAD_CODES
+                .iter()
+                .position(|(number, mnemonic)| mdc && (code == *number || code == *mnemonic))
+        })
+    }
+}
+
+impl FhirSampledData {
+    /// Samples in mV
+    /// # Errors
+    /// * Returns the diagnostics if the data is missing, multi-dimensional or not numeric
+    fn samples(&self) -> Result<Vec<f32>, String> {
+        if self.dimensions != 1 {
+            return Err(format!("Expected 1 dimension, got {}", self.dimensions));
+        }
+        let scale = match self.origin.code.as_deref() {
+            Some("uV") => 0.001,
+            _ => 1.0,
+        };
+        let factor = self.factor.unwrap_or(1.0);
+        self.data
+            .as_deref()
+            .ok_or_else(|| "Missing data".to_string())?
+            .split_whitespace()
+            .map(|value| {
+                value
+                    .parse::<f64>()
+                    .map(|value| ((self.origin.value + factor * value) * scale) as f32)
+                    .map_err(|_| format!("Invalid sample '{value}'"))
+            })
+            .collect()
+    }
+}
+
+impl FhirIssue {
+    fn error(code: &'static str, diagnostics: String, expression: &str) -> Self {
+        FhirIssue {
+            severity: "error",
+            code,
+            diagnostics,
+            expression: vec![expression.to_string()],
+        }
+    }
+}
+
+impl OperationOutcome {
+    /// Outcome of the issues found while mapping an Observation
+    pub fn from_issues(issue: Vec<FhirIssue>) -> Self {
+        OperationOutcome {
+            resource_type: "OperationOutcome",
+            issue,
+        }
+    }
+
+    /// Outcome of the validation of the mapped payload - leads are reported on their component
+    /// # Arguments
+    /// * `fields` - The validation messages per `PayloadEcg` field
+    pub fn from_validation(fields: &BTreeMap<String, Vec<String>>) -> Self {
+        let issue = fields
+            .iter()
+            .flat_map(|(field, messages)| {
+                let expression = match field.as_str() {
+                    "patient_id" => "Observation.subject".to_string(),
+                    "hospital_id" => "Observation.performer".to_string(),
+                    lead => ECG_LEAD_FIELDS
+                        .iter()
+                        .position(|f| *f == lead)
+                        .map(|i| {
+                            format!(
+                                "Observation.component.where(code.coding.code='{}')",
+                                ECG_LEAD_CODES[i].0
+                            )
+                        })
+                        .unwrap_or_else(|| "Observation".to_string()),
+                };
+                messages
+                    .iter()
+                    .map(move |message| FhirIssue::error("invalid", message.clone(), &expression))
+            })
+            .collect();
+        OperationOutcome::from_issues(issue)
+    }
+}
+
+// SUPPORTING FUNCTIONS ****************************************************************************
+/// Id of a referenced resource: its identifier value, else the id of its literal reference
+/// # Arguments
+/// * `reference` - The reference
+/// * `prefix` - The literal reference prefix, e.g. `Patient/`
+fn reference_id(reference: &FhirReference, prefix: &str) -> Option<String> {
+    reference
+        .identifier
+        .as_ref()
+        .and_then(|identifier| identifier.value.clone())
+        .or_else(|| {
+            reference
+                .reference
+                .as_deref()
+                .and_then(|r| r.strip_prefix(prefix))
+                .map(str::to_string)
+        })
+        .filter(|id| !id.is_empty())
+}
+
+// TESTS *******************************************************************************************
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::models_exams::ECG_LEAD_LENGTH;
+    use serde_json::json;
+    use validator::Validate;
+
+    fn observation_json(leads: usize, data: &str) -> serde_json::Value {
+        let component: Vec<_> = ECG_LEAD_CODES[..leads]
+            .iter()
+            .map(|(code, _)| {
+                json!({
+                    "code": {"coding": [{"system": MDC_SYSTEM, "code": code}]},
+                    "valueSampledData": {
+                        "origin": {"value": 0, "unit": "uV", "code": "uV"},
+                        "period": 2,
+                        "factor": 1.0,
+                        "dimensions": 1,
+                        "data": data,
+                    },
+                })
+            })
+            .collect();
+        json!({
+            "resourceType": "Observation",
+            "status": "final",
+            "subject": {"reference": format!("Patient/{}", "A".repeat(64))},
+            "component": component,
+        })
+    }
+
+    fn lead_data() -> String {
+        let mut data = vec!["0"; ECG_LEAD_LENGTH];
+        data[0] = "500";
+        data.join(" ")
+    }
+
+    // Happy path: 12 leads in uV map to a valid payload in mV
+    #[test]
+    fn observation_to_payload() {
+        let observation: FhirObservation =
+            serde_json::from_value(observation_json(12, &lead_data())).unwrap();
+        let payload = observation.to_payload_ecg(&"b".repeat(64)).unwrap();
+        assert_eq!(&*payload.patient_id, "a".repeat(64));
+        assert_eq!(&*payload.hospital_id, "b".repeat(64));
+        assert_eq!(payload.lead_v6.len(), ECG_LEAD_LENGTH);
+        assert_eq!(payload.lead_i[0], 0.5);
+        assert_eq!(payload.sampling_rate_hz, Some(500.0));
+        assert!(payload.validate().is_ok());
+    }
+
+    // Missing leads and malformed samples are reported on their element
+    #[test]
+    fn observation_issues() {
+        let observation: FhirObservation =
+            serde_json::from_value(observation_json(11, &lead_data())).unwrap();
+        let issues = observation.to_payload_ecg("h").unwrap_err();
+        assert_eq!(issues.len(), 1);
+        assert!(issues[0].diagnostics.contains("MDC_ECG_LEAD_V6"));
+
+        let observation: FhirObservation =
+            serde_json::from_value(observation_json(12, "1 2 E")).unwrap();
+        let issues = observation.to_payload_ecg("h").unwrap_err();
+        assert_eq!(issues.len(), 12);
+        assert_eq!(
+            issues[0].expression,
+            vec!["Observation.component[0].valueSampledData"]
+        );
+    }
+
+    // Validation messages of the payload point at the lead component
+    #[test]
+    fn validation_outcome() {
+        let mut fields = BTreeMap::new();
+        fields.insert("lead_ii".to_string(), vec!["flat-line".to_string()]);
+        let outcome = OperationOutcome::from_validation(&fields);
+        let body = serde_json::to_value(&outcome).unwrap();
+        assert_eq!(body["resourceType"], "OperationOutcome");
+        assert_eq!(body["issue"][0]["severity"], "error");
+        assert_eq!(
+            body["issue"][0]["expression"][0],
+            "Observation.component.where(code.coding.code='131330')"
+        );
+    }
+}
@@ -0,0 +1,256 @@
+// Imports *****************************************************************************************
+// External Crates
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use utoipa::ToSchema;
+use validator::Validate;
+
+// Internal Modules
+use crate::models::models_ids::canonical_hex;
+
+// Structs *****************************************************************************************
+/// Test vector of a hospital checking its identifier hashing - synthetic values only, nothing of
+/// it is stored or logged
+/// # Arguments
+/// * `test_identifier` - A synthetic identifier, as the hospital system would read it
+/// * `salt` - The salt agreed with the hospital at onboarding (or a test salt)
+/// * `candidate_hash` - The output of the hospital implementation for this identifier and salt
+#[derive(Debug, Deserialize, Validate, ToSchema)]
+pub struct HashCheckRequest {
+    #[validate(length(min = 1, max = 100))]
+    #[schema(min_length = 1, max_length = 100)]
+    pub test_identifier: String,
+    #[validate(length(min = 1, max = 256))]
+    #[schema(min_length = 1, max_length = 256)]
+    pub salt: String,
+    #[validate(length(min = 1, max = 256))]
+    #[schema(min_length = 1, max_length = 256)]
+    pub candidate_hash: String,
+}
+
+/// Known ways the hashing goes wrong during onboarding
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum HashMistake {
+    /// SHA256 of the identifier followed by the salt
+    SaltAppended,
+    /// SHA256 of the identifier alone
+    SaltMissing,
+    /// SHA256 of the salted identifier with a line feed, e.g. `echo` without `-n`
+    TrailingNewline,
+    /// The right digest, base64 encoded instead of hex
+    Base64Encoded,
+    /// Not a SHA256 hex digest (64 hex characters)
+    NotSha256Hex,
+    /// A SHA256 hex digest of something else - wrong salt, identifier or encoding
+    Unknown,
+}
+
+impl HashMistake {
+    /// Stable snake_case name, as serialized
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            HashMistake::SaltAppended => "salt_appended",
+            HashMistake::SaltMissing => "salt_missing",
+            HashMistake::TrailingNewline => "trailing_newline",
+            HashMistake::Base64Encoded => "base64_encoded",
+            HashMistake::NotSha256Hex => "not_sha256_hex",
+            HashMistake::Unknown => "unknown",
+        }
+    }
+
+    /// What the hospital should change
+    pub fn hint(&self) -> &'static str {
+        match self {
+            HashMistake::SaltAppended => {
+                "Prefix the salt: hash salt + identifier, not identifier + salt"
+            }
+            HashMistake::SaltMissing => "The salt is missing: hash salt + identifier",
+            HashMistake::TrailingNewline => {
+                "A line feed was hashed with the identifier - hash the exact bytes, without newline"
+            }
+            HashMistake::Base64Encoded => "Encode the digest as lowercase hex, not base64",
+            HashMistake::NotSha256Hex => "The hash must be 64 hex characters (SHA256)",
+            HashMistake::Unknown => {
+                "Check the salt, the identifier as read by your system, and UTF-8 encoding"
+            }
+        }
+    }
+}
+
+/// Outcome of a hashing self-check
+/// # Arguments
+/// * `matches` - Whether the candidate is the agreed hash of the test vector (case-insensitive)
+/// * `expected_hash` - The agreed hash of the test vector
+/// * `diagnosis` - The likely mistake when it does not match
+/// * `hint` - What to change when it does not match
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct HashCheckResult {
+    pub matches: bool,
+    pub expected_hash: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub diagnosis: Option<HashMistake>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schema(value_type = Option<String>)]
+    pub hint: Option<&'static str>,
+}
+
+// MAIN FUNCTIONS **********************************************************************************
+/// The agreed identifier hash: lowercase hex SHA256 of the salt followed by the identifier, both
+/// as UTF-8 bytes without separator
+/// # Arguments
+/// * `salt` - The salt agreed with the hospital
+/// * `identifier` - The identifier, exactly as read by the hospital system
+pub fn agreed_hash(salt: &str, identifier: &str) -> String {
+    hex_sha256(&[salt, identifier])
+}
+
+/// Check a candidate hash against the agreed scheme, and diagnose a mismatch
+/// # Arguments
+/// * `request` - The validated test vector
+/// # Returns
+/// * The outcome, with the likely mistake when the candidate does not match
+pub fn check_hash(request: &HashCheckRequest) -> HashCheckResult {
+    let expected_hash = agreed_hash(&request.salt, &request.test_identifier);
+    let candidate = request.candidate_hash.trim();
+    // Hex ids are lowercased by the gateway, so an uppercase digest is as good
+    if canonical_hex(candidate) == expected_hash {
+        return HashCheckResult {
+            matches: true,
+            expected_hash,
+            diagnosis: None,
+            hint: None,
+        };
+    }
+    let diagnosis = diagnose(request, candidate, &expected_hash);
+    HashCheckResult {
+        matches: false,
+        expected_hash,
+        diagnosis: Some(diagnosis),
+        hint: Some(diagnosis.hint()),
+    }
+}
+
+// SUPPORT FUNCTIONS *******************************************************************************
+/// Likely mistake behind a candidate that does not match
+/// # Arguments
+/// * `request` - The test vector
+/// * `candidate` - The trimmed candidate hash
+/// * `expected_hash` - The agreed hash of the test vector
+fn diagnose(request: &HashCheckRequest, candidate: &str, expected_hash: &str) -> HashMistake {
+    // STEP 1: Right digest, wrong encoding
+    if let Ok(bytes) = STANDARD.decode(candidate) {
+        if bytes.len() == 32 && hex_bytes(&bytes) == expected_hash {
+            return HashMistake::Base64Encoded;
+        }
+    }
+    if candidate.len() != 64 || !candidate.chars().all(|c| c.is_ascii_hexdigit()) {
+        return HashMistake::NotSha256Hex;
+    }
+
+    // STEP 2: Wrong input to the digest
+    let (salt, identifier) = (request.salt.as_str(), request.test_identifier.as_str());
+    let candidate = canonical_hex(candidate);
+    let variants = [
+        (HashMistake::SaltAppended, hex_sha256(&[identifier, salt])),
+        (HashMistake::SaltMissing, hex_sha256(&[identifier])),
+        (
+            HashMistake::TrailingNewline,
+            hex_sha256(&[salt, identifier, "\n"]),
+        ),
+    ];
+    variants
+        .into_iter()
+        .find(|(_, hash)| *hash == candidate)
+        .map_or(HashMistake::Unknown, |(mistake, _)| mistake)
+}
+
+/// Lowercase hex SHA256 of concatenated parts
+fn hex_sha256(parts: &[&str]) -> String {
+    let mut hasher = Sha256::new();
+    for part in parts {
+        hasher.update(part.as_bytes());
+    }
+    hex_bytes(&hasher.finalize())
+}
+
+/// Lowercase hex of bytes
+fn hex_bytes(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+// TESTS *******************************************************************************************
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(candidate_hash: String) -> HashCheckRequest {
+        HashCheckRequest {
+            test_identifier: "TEST-0001".to_string(),
+            salt: "onboarding-salt".to_string(),
+            candidate_hash,
+        }
+    }
+
+    // Happy path: the agreed hash matches, whatever its case
+    #[test]
+    fn agreed_hash_matches() {
+        let expected = agreed_hash("onboarding-salt", "TEST-0001");
+        assert_eq!(expected, hex_sha256(&["onboarding-saltTEST-0001"]));
+        assert!(check_hash(&request(expected.clone())).matches);
+        let result = check_hash(&request(expected.to_ascii_uppercase()));
+        assert!(result.matches);
+        assert!(result.diagnosis.is_none());
+    }
+
+    // Error handling: the recurring onboarding mistakes are recognized
+    #[test]
+    fn mistakes_diagnosed() {
+        let digest = Sha256::digest(b"onboarding-saltTEST-0001");
+        for (candidate, mistake) in [
+            (
+                hex_sha256(&["TEST-0001", "onboarding-salt"]),
+                HashMistake::SaltAppended,
+            ),
+            (hex_sha256(&["TEST-0001"]), HashMistake::SaltMissing),
+            (
+                hex_sha256(&["onboarding-saltTEST-0001\n"]),
+                HashMistake::TrailingNewline,
+            ),
+            (STANDARD.encode(digest), HashMistake::Base64Encoded),
+            ("1234".to_string(), HashMistake::NotSha256Hex),
+            (hex_sha256(&["other"]), HashMistake::Unknown),
+        ] {
+            let result = check_hash(&request(candidate));
+            assert!(!result.matches);
+            assert_eq!(result.diagnosis, Some(mistake));
+            assert_eq!(result.hint, Some(mistake.hint()));
+        }
+    }
+
+    // Borderline: empty or oversized test vectors are refused before hashing
+    #[test]
+    fn request_validated() {
+        assert!(request("a".repeat(64)).validate().is_ok());
+        assert!(request(String::new()).validate().is_err());
+        let mut long = request("a".repeat(64));
+        long.test_identifier = "x".repeat(101);
+        assert!(long.validate().is_err());
+    }
+
+    // Borderline: the self-check response keeps its shape
+    #[test]
+    fn result_contract() {
+        let candidate = hex_sha256(&["TEST-0001", "onboarding-salt"]);
+        insta::assert_json_snapshot!(check_hash(&request(candidate)), @r#"
+        {
+          "matches": false,
+          "expected_hash": "acdd9d344964baa8a197a126e18a26a59dbfa36c9244ed85b2c8d317050f4697",
+          "diagnosis": "salt_appended",
+          "hint": "Prefix the salt: hash salt + identifier, not identifier + salt"
+        }
+        "#);
+    }
+}
@@ -0,0 +1,215 @@
+// Imports *****************************************************************************************
+// External Crates
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Deserializer, Serialize};
+use validator::Validate;
+
+// Internal Modules
+use crate::models::models_consent::ConsentScope;
+use crate::models::models_publish_modes::PublishMode;
+use crate::models::models_rate_tiers::RateTier;
+use crate::models::models_size_tiers::SizeTier;
+
+// Structs *****************************************************************************************
+/// Exam types a hospital may be allowed to submit
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExamType {
+    EcgExam,
+    XrayExam,
+}
+
+impl ExamType {
+    /// Stable snake_case name, as stored in the registry and matched by the middleware
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ExamType::EcgExam => "ecg_exam",
+            ExamType::XrayExam => "xray_exam",
+        }
+    }
+}
+
+/// Registration of a new hospital - its first key is issued with it
+/// # Arguments
+/// * `hospital_id` - The id of the hospital, as sent in its `hospital_id` header
+/// * `consent_scope` - The data-sharing consent (default clinical)
+/// * `size_tier` - The payload size tier (default standard)
+/// * `rate_limit_tier` - The rate limit tier (default standard)
+/// * `publish_mode` - When the exams are answered (default inline)
+/// * `allowed_exam_types` - The exam types the hospital may submit (default all)
+/// * `monthly_exam_quota` - Exams accepted per month (default unlimited)
+/// * `key_expires_at` - Expiry of the first key (default never)
+#[derive(Debug, Deserialize, Validate)]
+pub struct HospitalCreate {
+    #[validate(length(min = 1, max = 100))]
+    pub hospital_id: String,
+    pub consent_scope: Option<ConsentScope>,
+    pub size_tier: Option<SizeTier>,
+    pub rate_limit_tier: Option<RateTier>,
+    pub publish_mode: Option<PublishMode>,
+    pub allowed_exam_types: Option<Vec<ExamType>>,
+    pub monthly_exam_quota: Option<u64>,
+    pub key_expires_at: Option<DateTime<Utc>>,
+}
+
+/// Change of the settings of a hospital - absent fields are kept
+/// # Arguments
+/// * `allowed_exam_types` - The exam types the hospital may submit, `null` for all
+/// * `rate_limit_tier` - The rate limit tier
+/// * `publish_mode` - When the exams are answered
+/// * `paused` - true to refuse the exams of the hospital, false to resume it
+#[derive(Debug, Default, Deserialize)]
+pub struct HospitalUpdate {
+    #[serde(default, deserialize_with = "present")]
+    pub allowed_exam_types: Option<Option<Vec<ExamType>>>,
+    pub rate_limit_tier: Option<RateTier>,
+    pub publish_mode: Option<PublishMode>,
+    pub paused: Option<bool>,
+}
+
+/// Issue of a new key, next to the active ones
+/// # Arguments
+/// * `expires_at` - Expiry of the key (default never)
+#[derive(Debug, Default, Deserialize)]
+pub struct KeyIssue {
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+/// Rotation of the keys of a hospital: a new key is issued and the active ones expire after a grace
+/// period, so the hospital can deploy the new key without downtime
+/// # Arguments
+/// * `grace_s` - Seconds the current keys keep working (default one day, at most 30 days)
+/// * `expires_at` - Expiry of the new key (default never)
+#[derive(Debug, Default, Deserialize)]
+pub struct KeyRotation {
+    pub grace_s: Option<u64>,
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+/// Key generated by the gateway - the only time the key is returned, only its hash is stored
+/// # Arguments
+/// * `hospital_id` - The hospital of the key
+/// * `key_id` - The public id of the key, to revoke it
+/// * `hospital_key` - The key, to send in the `hospital_key` header
+/// * `expires_at` - When the key stops working, None if never
+#[derive(Debug, Serialize)]
+pub struct IssuedKey {
+    pub hospital_id: String,
+    pub key_id: String,
+    pub hospital_key: String,
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+/// State of a key of a hospital
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum KeyStatus {
+    Active,
+    Expired,
+    Revoked,
+}
+
+/// Key of a hospital, as listed to operators - never the key nor its hash
+/// # Arguments
+/// * `key_id` - The public id of the key
+/// * `key_prefix` - The first characters of the key, None for keys set before the admin API
+/// * `created_at` - When the key was issued
+/// * `expires_at` - When the key stops working, None if never
+/// * `revoked_at` - When the key was revoked, if it was
+/// * `status` - Whether the key works now
+#[derive(Debug, Serialize)]
+pub struct HospitalKey {
+    pub key_id: String,
+    pub key_prefix: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub revoked_at: Option<DateTime<Utc>>,
+    pub status: KeyStatus,
+}
+
+// MAIN FUNCTIONS **********************************************************************************
+/// State of a key at an instant - a revoked key stays revoked once expired
+/// # Arguments
+/// * `expires_at` - The expiry of the key, if any
+/// * `revoked_at` - The revocation of the key, if any
+/// * `now` - The instant
+pub fn key_status(
+    expires_at: Option<DateTime<Utc>>,
+    revoked_at: Option<DateTime<Utc>>,
+    now: DateTime<Utc>,
+) -> KeyStatus {
+    if revoked_at.is_some() {
+        KeyStatus::Revoked
+    } else if expires_at.is_some_and(|expires_at| expires_at <= now) {
+        KeyStatus::Expired
+    } else {
+        KeyStatus::Active
+    }
+}
+
+// SUPPORTING FUNCTIONS ****************************************************************************
+/// Tell an explicit `null` (Some(None)) from an absent field (None, the default)
+fn present<'de, D, T>(deserializer: D) -> Result<Option<Option<T>>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    Option::<T>::deserialize(deserializer).map(Some)
+}
+
+// TESTS *******************************************************************************************
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Happy path: a registration with defaults deserializes and validates
+    #[test]
+    fn create_deserialized() {
+        let create: HospitalCreate = serde_json::from_value(serde_json::json!({
+            "hospital_id": "h1",
+            "rate_limit_tier": "elevated",
+            "allowed_exam_types": ["ecg_exam"],
+        }))
+        .unwrap();
+        assert!(create.validate().is_ok());
+        assert_eq!(create.rate_limit_tier, Some(RateTier::Elevated));
+        assert_eq!(create.allowed_exam_types, Some(vec![ExamType::EcgExam]));
+        assert_eq!(create.consent_scope, None);
+
+        let unknown = serde_json::from_value::<HospitalCreate>(serde_json::json!({
+            "hospital_id": "h1",
+            "allowed_exam_types": ["mri_exam"],
+        }));
+        assert!(unknown.is_err());
+    }
+
+    // Borderline: an update tells an explicit null (all exam types) from an absent field
+    #[test]
+    fn update_null_or_absent() {
+        let update: HospitalUpdate =
+            serde_json::from_value(serde_json::json!({ "allowed_exam_types": null })).unwrap();
+        assert_eq!(update.allowed_exam_types, Some(None));
+        let update: HospitalUpdate =
+            serde_json::from_value(serde_json::json!({ "rate_limit_tier": "unlimited" })).unwrap();
+        assert_eq!(update.allowed_exam_types, None);
+        assert_eq!(update.rate_limit_tier, Some(RateTier::Unlimited));
+        assert_eq!(update.publish_mode, None);
+        let update: HospitalUpdate =
+            serde_json::from_value(serde_json::json!({ "publish_mode": "after_storage" })).unwrap();
+        assert_eq!(update.publish_mode, Some(PublishMode::AfterStorage));
+    }
+
+    // Happy path: revocation wins over expiry, keys expire at their expiry
+    #[test]
+    fn status_of_keys() {
+        let now = Utc::now();
+        let past = now - chrono::Duration::seconds(1);
+        assert_eq!(key_status(None, None, now), KeyStatus::Active);
+        assert_eq!(key_status(Some(now), None, now), KeyStatus::Expired);
+        assert_eq!(key_status(Some(past), Some(past), now), KeyStatus::Revoked);
+        assert_eq!(
+            key_status(Some(now + chrono::Duration::seconds(1)), None, now),
+            KeyStatus::Active
+        );
+    }
+}
@@ -0,0 +1,83 @@
+// Imports *****************************************************************************************
+// External Crates
+use serde::{Deserialize, Deserializer, Serialize};
+use std::fmt;
+use std::ops::Deref;
+
+// Internal Modules
+
+// Structs *****************************************************************************************
+/// Identifier sent as a SHA256 hex digest (hospital and patient ids)
+/// - Hex digests are normalized to lowercase when deserialized, so `ABC…` and `abc…` name the same
+///   patient and the same storage path; other values are kept as sent and left to validation
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash, Serialize)]
+#[serde(transparent)]
+pub struct Sha256Hex(String);
+
+impl From<&str> for Sha256Hex {
+    fn from(value: &str) -> Self {
+        Sha256Hex(canonical_hex(value))
+    }
+}
+
+impl From<String> for Sha256Hex {
+    fn from(value: String) -> Self {
+        Sha256Hex::from(value.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for Sha256Hex {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Ok(Sha256Hex::from(String::deserialize(deserializer)?))
+    }
+}
+
+impl Deref for Sha256Hex {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Display for Sha256Hex {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+// SUPPORTING FUNCTIONS ****************************************************************************
+/// Canonical form of an identifier: lowercase if it is a hex string, unchanged otherwise
+/// # Arguments
+/// * `value` - The identifier as sent or stored
+pub fn canonical_hex(value: &str) -> String {
+    if !value.is_empty() && value.chars().all(|c| c.is_ascii_hexdigit()) {
+        value.to_ascii_lowercase()
+    } else {
+        value.to_string()
+    }
+}
+
+// TESTS *******************************************************************************************
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Happy path: mixed-case digests deserialize to the same lowercase id
+    #[test]
+    fn sha256_hex_lowercased() {
+        let upper: Sha256Hex = serde_json::from_str("\"ABCDEF0123\"").unwrap();
+        let lower: Sha256Hex = serde_json::from_str("\"abcdef0123\"").unwrap();
+        assert_eq!(upper, lower);
+        assert_eq!(&*upper, "abcdef0123");
+        assert_eq!(serde_json::to_string(&upper).unwrap(), "\"abcdef0123\"");
+    }
+
+    // Values that are not hex are never altered - distinct ids stay distinct
+    #[test]
+    fn non_hex_kept() {
+        assert_eq!(canonical_hex("Patient-42"), "Patient-42");
+        assert_eq!(canonical_hex(""), "");
+        assert_eq!(&*Sha256Hex::from("XYZ"), "XYZ");
+    }
+}
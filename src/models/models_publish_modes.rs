@@ -0,0 +1,62 @@
+// Imports *****************************************************************************************
+// External Crates
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+// Internal Modules
+
+// MAIN FUNCTIONS AND STRUCTS **********************************************************************
+/// When the exams of a hospital are answered, as recorded in the hospital registry
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PublishMode {
+    /// Answered once the exam is stored and its Pub/Sub notification acked
+    #[default]
+    Inline,
+    /// Answered once the exam is stored - the notification is published in the background,
+    /// dead-lettered for replay if it fails
+    AfterStorage,
+}
+
+impl PublishMode {
+    /// Stable snake_case name, as stored in the registry
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PublishMode::Inline => "inline",
+            PublishMode::AfterStorage => "after_storage",
+        }
+    }
+}
+
+impl FromStr for PublishMode {
+    type Err = anyhow::Error;
+
+    fn from_str(raw: &str) -> Result<Self> {
+        match raw.trim().to_ascii_lowercase().as_str() {
+            "inline" => Ok(PublishMode::Inline),
+            "after_storage" => Ok(PublishMode::AfterStorage),
+            other => Err(anyhow!("Unknown publish mode '{other}'")),
+        }
+    }
+}
+
+// TESTS *******************************************************************************************
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mode_parses() {
+        assert_eq!(
+            " After_Storage ".parse::<PublishMode>().unwrap(),
+            PublishMode::AfterStorage
+        );
+        assert_eq!(PublishMode::default(), PublishMode::Inline);
+        assert!("async".parse::<PublishMode>().is_err());
+        assert_eq!(
+            serde_json::to_value(PublishMode::AfterStorage).unwrap(),
+            "after_storage"
+        );
+    }
+}
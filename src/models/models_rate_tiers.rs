@@ -0,0 +1,89 @@
+// Imports *****************************************************************************************
+// External Crates
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+// Internal Modules
+
+// Constants ***************************************************************************************
+/// Factor applied to the ingest rate limit of elevated hospitals
+const ELEVATED_RATE_FACTOR: u32 = 4;
+
+// MAIN FUNCTIONS AND STRUCTS **********************************************************************
+/// Rate limit tier of a hospital, as recorded in the hospital registry - scales the ingest policy
+/// of RATE_LIMITS
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RateTier {
+    /// The ingest policy as declared
+    Standard,
+    /// Large hospitals and batch imports: ELEVATED_RATE_FACTOR times the ingest policy
+    Elevated,
+    /// Trusted integrations: no ingest rate limit (quotas still apply)
+    Unlimited,
+}
+
+impl RateTier {
+    /// Stable lowercase name, as stored in the registry
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RateTier::Standard => "standard",
+            RateTier::Elevated => "elevated",
+            RateTier::Unlimited => "unlimited",
+        }
+    }
+
+    /// Requests per window of a hospital of this tier
+    /// # Arguments
+    /// * `requests` - Requests per window of the ingest policy
+    /// # Returns
+    /// * The scaled request count, None if the hospital is not limited
+    pub fn scale(&self, requests: u32) -> Option<u32> {
+        match self {
+            RateTier::Standard => Some(requests),
+            RateTier::Elevated => Some(requests.saturating_mul(ELEVATED_RATE_FACTOR)),
+            RateTier::Unlimited => None,
+        }
+    }
+}
+
+impl FromStr for RateTier {
+    type Err = anyhow::Error;
+
+    fn from_str(raw: &str) -> Result<Self> {
+        match raw.trim().to_ascii_lowercase().as_str() {
+            "standard" => Ok(RateTier::Standard),
+            "elevated" => Ok(RateTier::Elevated),
+            "unlimited" => Ok(RateTier::Unlimited),
+            other => Err(anyhow!("Unknown rate limit tier '{other}'")),
+        }
+    }
+}
+
+// TESTS *******************************************************************************************
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tier_scales_requests() {
+        assert_eq!(RateTier::Standard.scale(600), Some(600));
+        assert_eq!(RateTier::Elevated.scale(600), Some(2_400));
+        assert_eq!(RateTier::Elevated.scale(u32::MAX), Some(u32::MAX));
+        assert_eq!(RateTier::Unlimited.scale(600), None);
+    }
+
+    #[test]
+    fn tier_parses() {
+        assert_eq!(
+            " Elevated ".parse::<RateTier>().unwrap(),
+            RateTier::Elevated
+        );
+        assert!("gold".parse::<RateTier>().is_err());
+        assert_eq!(
+            serde_json::to_value(RateTier::Unlimited).unwrap(),
+            "unlimited"
+        );
+    }
+}
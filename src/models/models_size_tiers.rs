@@ -0,0 +1,109 @@
+// Imports *****************************************************************************************
+// External Crates
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+// Internal Modules
+use crate::config::settings::ServerSettings;
+
+// MAIN FUNCTIONS AND STRUCTS **********************************************************************
+/// Payload size tier of a hospital, as recorded in the hospital registry
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SizeTier {
+    /// Bodies up to POST_SIZE_LIMIT
+    Standard,
+    /// High-resolution devices: bodies up to PREMIUM_POST_SIZE_LIMIT
+    Premium,
+}
+
+impl SizeTier {
+    /// Stable lowercase name, as stored in the registry
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SizeTier::Standard => "standard",
+            SizeTier::Premium => "premium",
+        }
+    }
+
+    /// Largest request body accepted from a hospital of this tier, in bytes
+    /// # Arguments
+    /// * `server` - The server settings, which keep the limits of the larger tiers above the
+    ///   smaller ones
+    pub fn body_limit(&self, server: &ServerSettings) -> usize {
+        match self {
+            SizeTier::Standard => server.post_size_limit,
+            SizeTier::Premium => server.premium_post_size_limit,
+        }
+    }
+
+    /// Largest body limit of all tiers
+    pub fn max_body_limit(server: &ServerSettings) -> usize {
+        SizeTier::Premium.body_limit(server)
+    }
+
+    /// Limit of the JSON extractor, before the hospital is known: the largest tier limit, or the
+    /// decompressed size of a compressed body - the middleware applies the limits of the hospital
+    pub fn max_json_limit(server: &ServerSettings) -> usize {
+        SizeTier::max_body_limit(server).max(decompressed_body_limit(server))
+    }
+}
+
+impl FromStr for SizeTier {
+    type Err = anyhow::Error;
+
+    fn from_str(raw: &str) -> Result<Self> {
+        match raw.trim().to_ascii_lowercase().as_str() {
+            "standard" => Ok(SizeTier::Standard),
+            "premium" => Ok(SizeTier::Premium),
+            other => Err(anyhow!("Unknown size tier '{other}'")),
+        }
+    }
+}
+
+// SUPPORTING FUNCTIONS ****************************************************************************
+/// Largest body accepted by the streamed upload routes - those bodies are never buffered, so the
+/// limit does not depend on the size tier
+pub fn upload_body_limit(server: &ServerSettings) -> usize {
+    server.xray_upload_max_bytes
+}
+
+/// Largest decompressed size of a gzip or zstd body - the tier limit applies to the compressed
+/// bytes received, this ceiling to what they expand to (zip bombs)
+pub fn decompressed_body_limit(server: &ServerSettings) -> usize {
+    server.decompressed_body_max_bytes
+}
+
+// TESTS *******************************************************************************************
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::settings::{test_settings, DEFAULT_POST_SIZE_LIMIT};
+
+    #[test]
+    fn tier_limits_ordered() {
+        let server = test_settings().server;
+        assert_eq!(
+            SizeTier::Standard.body_limit(&server),
+            DEFAULT_POST_SIZE_LIMIT
+        );
+        assert!(SizeTier::Premium.body_limit(&server) >= SizeTier::Standard.body_limit(&server));
+        assert_eq!(
+            SizeTier::max_body_limit(&server),
+            SizeTier::Premium.body_limit(&server)
+        );
+        assert!(upload_body_limit(&server) >= SizeTier::max_body_limit(&server));
+        assert!(decompressed_body_limit(&server) >= SizeTier::max_body_limit(&server));
+        assert_eq!(
+            SizeTier::max_json_limit(&server),
+            decompressed_body_limit(&server)
+        );
+    }
+
+    #[test]
+    fn tier_parses() {
+        assert_eq!(" Premium ".parse::<SizeTier>().unwrap(), SizeTier::Premium);
+        assert!("gold".parse::<SizeTier>().is_err());
+    }
+}
@@ -0,0 +1,311 @@
+// Imports *****************************************************************************************
+// External Crates
+use anyhow::{anyhow, Result};
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+
+// Internal Modules
+use crate::models::models_consent::ConsentScope;
+
+// Constants ***************************************************************************************
+/// Deployment environments a topic can belong to
+pub const DEPLOY_ENVS: [&str; 3] = ["dev", "staging", "prod"];
+
+// MAIN FUNCTIONS AND STRUCTS **********************************************************************
+/// Pub/Sub topic id following the `{env}-{exam}-{version}` convention, e.g. `prod-ecg-v1`
+/// # Arguments
+/// * `env` - One of DEPLOY_ENVS
+/// * `exam` - Lowercase alphanumeric exam (or stream) name, e.g. `ecg`, `billing`
+/// * `version` - `v` followed by a number, e.g. `v2`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TopicName {
+    raw: String,
+    env: String,
+}
+
+impl TopicName {
+    /// Parse a topic id and check that it belongs to the running environment
+    /// # Arguments
+    /// * `raw` - The topic id
+    /// * `deploy_env` - The environment of this instance
+    /// # Errors
+    /// * Returns an error if the name breaks the convention or targets another environment
+    pub fn parse_for_env(raw: &str, deploy_env: &str) -> Result<Self> {
+        let topic: TopicName = raw.parse()?;
+        if topic.env != deploy_env {
+            return Err(anyhow!(
+                "Topic '{raw}' belongs to '{}' but this instance runs in '{deploy_env}' (DEPLOY_ENV)",
+                topic.env
+            ));
+        }
+        Ok(topic)
+    }
+
+    /// The full topic id
+    pub fn as_str(&self) -> &str {
+        &self.raw
+    }
+}
+
+impl FromStr for TopicName {
+    type Err = anyhow::Error;
+
+    fn from_str(raw: &str) -> Result<Self> {
+        let invalid = || {
+            anyhow!("Invalid topic name '{raw}': expected {{env}}-{{exam}}-{{version}}, e.g. dev-ecg-v1")
+        };
+        let mut parts = raw.split('-');
+        let (Some(env), Some(exam), Some(version), None) =
+            (parts.next(), parts.next(), parts.next(), parts.next())
+        else {
+            return Err(invalid());
+        };
+        if !DEPLOY_ENVS.contains(&env) {
+            return Err(anyhow!(
+                "Invalid topic name '{raw}': environment '{env}' is not one of {DEPLOY_ENVS:?}"
+            ));
+        }
+        if exam.is_empty()
+            || !exam
+                .chars()
+                .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit())
+        {
+            return Err(invalid());
+        }
+        if !version
+            .strip_prefix('v')
+            .is_some_and(|n| !n.is_empty() && n.chars().all(|c| c.is_ascii_digit()))
+        {
+            return Err(invalid());
+        }
+        Ok(TopicName {
+            raw: raw.to_string(),
+            env: env.to_string(),
+        })
+    }
+}
+
+impl fmt::Display for TopicName {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.raw)
+    }
+}
+
+/// Destination of the notifications of one exam type
+/// # Arguments
+/// * `topic` - The topic id, following the `{env}-{exam}-{version}` convention
+/// * `project` - The GCP project owning the topic, None for the service's own project
+/// * `required_consent` - The consent a hospital must have given for its exams to be routed here
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TopicRoute {
+    pub topic: TopicName,
+    pub project: Option<String>,
+    pub required_consent: ConsentScope,
+}
+
+// SUPPORT FUNCTIONS *******************************************************************************
+/// The route of an exam type
+/// # Errors
+/// * Returns an error if the exam type has no route
+pub fn route_of<'a>(
+    routes: &'a HashMap<String, TopicRoute>,
+    exam_type: &str,
+) -> Result<&'a TopicRoute> {
+    routes
+        .get(exam_type)
+        .ok_or_else(|| anyhow!("No Pub/Sub route for exam type '{exam_type}'"))
+}
+
+/// Check that the consent of a hospital covers the route of an exam type
+/// # Arguments
+/// * `routes` - The routing table
+/// * `exam_type` - The exam type key
+/// * `consent_scope` - The consent of the hospital the exam comes from
+/// # Errors
+/// * Returns an error if the exam type has no route or the route needs a wider consent
+pub fn check_route_consent(
+    routes: &HashMap<String, TopicRoute>,
+    exam_type: &str,
+    consent_scope: ConsentScope,
+) -> Result<()> {
+    let route = route_of(routes, exam_type)?;
+    if !consent_scope.allows(route.required_consent) {
+        return Err(anyhow!(
+            "Pub/Sub route '{exam_type}' ({}) requires '{}' consent, hospital consented to '{}'",
+            route.topic,
+            route.required_consent.as_str(),
+            consent_scope.as_str()
+        ));
+    }
+    Ok(())
+}
+
+/// Parse a routing table of the form `exam_type=topic,exam_type=project:topic@research`
+/// The optional `@scope` suffix is the consent required by the destination (default clinical)
+/// # Arguments
+/// * `raw` - The routing table
+/// * `deploy_env` - The environment every topic must belong to
+/// # Returns
+/// * A map of exam type to topic route
+pub(crate) fn parse_routes(raw: &str, deploy_env: &str) -> Result<HashMap<String, TopicRoute>> {
+    let mut routes = HashMap::new();
+    for entry in raw.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+        let (exam_type, destination) = entry
+            .split_once('=')
+            .ok_or_else(|| anyhow!("Invalid Pub/Sub route '{entry}': expected exam_type=topic"))?;
+        let (destination, required_consent) = match destination.split_once('@') {
+            Some((destination, scope)) => (
+                destination,
+                scope
+                    .parse()
+                    .map_err(|e| anyhow!("Invalid Pub/Sub route '{entry}': {e}"))?,
+            ),
+            None => (destination, ConsentScope::Clinical),
+        };
+        let (project, topic) = match destination.split_once(':') {
+            Some((project, topic)) => (Some(project.trim().to_string()), topic),
+            None => (None, destination),
+        };
+        if exam_type.trim().is_empty() || project.as_deref() == Some("") {
+            return Err(anyhow!("Invalid Pub/Sub route '{entry}'"));
+        }
+        let route = TopicRoute {
+            topic: TopicName::parse_for_env(topic.trim(), deploy_env)
+                .map_err(|e| anyhow!("Invalid Pub/Sub route '{entry}': {e}"))?,
+            project,
+            required_consent,
+        };
+        if routes.insert(exam_type.trim().to_string(), route).is_some() {
+            return Err(anyhow!(
+                "Duplicate Pub/Sub route for '{}'",
+                exam_type.trim()
+            ));
+        }
+    }
+    Ok(routes)
+}
+
+/// Parse partner credentials of the form `project=/path/to/credentials.json,project=impersonate:sa`
+/// # Arguments
+/// * `raw` - The credentials list
+/// # Returns
+/// * A map of project id to credentials file path
+pub(crate) fn parse_project_credentials(raw: &str) -> Result<HashMap<String, String>> {
+    raw.split(',')
+        .map(str::trim)
+        .filter(|e| !e.is_empty())
+        .map(|entry| {
+            entry
+                .split_once('=')
+                .map(|(p, path)| (p.trim().to_string(), path.trim().to_string()))
+                .ok_or_else(|| anyhow!("Invalid Pub/Sub credentials entry '{entry}'"))
+        })
+        .collect()
+}
+
+// TESTS *******************************************************************************************
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::settings::DEFAULT_ROUTES;
+
+    // Happy path: the environment segment is extracted
+    #[test]
+    fn topic_name_parses() {
+        let topic: TopicName = "prod-ecg-v12".parse().unwrap();
+        assert_eq!(topic.env, "prod");
+        assert_eq!(topic.to_string(), "prod-ecg-v12");
+        assert!("staging-billing-v1".parse::<TopicName>().is_ok());
+    }
+
+    // Error handling: names breaking the convention are rejected
+    #[test]
+    fn topic_name_invalid() {
+        for raw in [
+            "topic-ecg-dev",
+            "dev-ecg",
+            "dev-ecg-v1-extra",
+            "dev-ECG-v1",
+            "dev--v1",
+            "dev-ecg-1",
+            "dev-ecg-v",
+            "qa-ecg-v1",
+        ] {
+            assert!(raw.parse::<TopicName>().is_err(), "{raw} should be invalid");
+        }
+    }
+
+    // Error handling: dev traffic can never be routed to a prod topic
+    #[test]
+    fn topic_name_env_enforced() {
+        assert!(TopicName::parse_for_env("dev-ecg-v1", "dev").is_ok());
+        assert!(TopicName::parse_for_env("prod-ecg-v1", "dev").is_err());
+        assert!(TopicName::parse_for_env("dev-ecg-v1", "prod").is_err());
+    }
+
+    // Happy path: default table routes both exam types to the own project
+    #[test]
+    fn routes_default_table() {
+        let routes = parse_routes(DEFAULT_ROUTES, "dev").unwrap();
+        assert_eq!(routes["ecg_exam"].topic.as_str(), "dev-ecg-v1");
+        assert_eq!(routes["ecg_exam"].project, None);
+        assert_eq!(routes.len(), 2);
+    }
+
+    // Happy path: partner project override
+    #[test]
+    fn routes_project_override() {
+        let routes = parse_routes(
+            "ecg_exam=prod-ecg-v1, xray_exam = partner-1:prod-xray-v2",
+            "prod",
+        )
+        .unwrap();
+        assert_eq!(
+            routes["xray_exam"],
+            TopicRoute {
+                topic: "prod-xray-v2".parse().unwrap(),
+                project: Some("partner-1".to_string()),
+                required_consent: ConsentScope::Clinical,
+            }
+        );
+    }
+
+    // Error handling: malformed and duplicate routes are rejected
+    #[test]
+    fn routes_invalid() {
+        assert!(parse_routes("ecg_exam", "dev").is_err());
+        assert!(parse_routes("ecg_exam=", "dev").is_err());
+        assert!(parse_routes("ecg_exam=:dev-ecg-v1", "dev").is_err());
+        assert!(parse_routes("ecg_exam=dev-ecg-v1,ecg_exam=dev-ecg-v2", "dev").is_err());
+    }
+
+    // Happy path: research destinations need a research consent
+    #[test]
+    fn routes_consent_suffix() {
+        let routes = parse_routes(
+            "ecg_exam=partner-1:dev-ecg-v1@research,xray_exam=dev-xray-v1",
+            "dev",
+        )
+        .unwrap();
+        assert_eq!(routes["ecg_exam"].required_consent, ConsentScope::Research);
+        assert_eq!(routes["ecg_exam"].project.as_deref(), Some("partner-1"));
+        assert_eq!(routes["xray_exam"].required_consent, ConsentScope::Clinical);
+        assert!(parse_routes("ecg_exam=dev-ecg-v1@marketing", "dev").is_err());
+    }
+
+    // Error handling: topics of another environment are rejected at config load
+    #[test]
+    fn routes_wrong_environment() {
+        assert!(parse_routes("ecg_exam=prod-ecg-v1", "dev").is_err());
+        assert!(parse_routes("ecg_exam=topic-ecg-dev", "dev").is_err());
+    }
+
+    #[test]
+    fn project_credentials_parse() {
+        let creds = parse_project_credentials("p1=/a.json, p2=/b.json").unwrap();
+        assert_eq!(creds["p2"], "/b.json");
+        assert!(parse_project_credentials("p1").is_err());
+        assert!(parse_project_credentials("").unwrap().is_empty());
+    }
+}
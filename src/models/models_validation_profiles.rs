@@ -0,0 +1,280 @@
+// Imports *****************************************************************************************
+// External Crates
+use serde::Serialize;
+use serde_json::json;
+
+// Internal Modules
+use crate::config::settings::ServerSettings;
+use crate::models::models_ecg_quality::{
+    ECG_CLIPPING_MIN_RUN, ECG_DEFAULT_DURATION_S, ECG_DEFAULT_SAMPLING_RATE_HZ,
+    ECG_EINTHOVEN_MAX_RMS, ECG_MAX_AMPLITUDE, ECG_MAX_DURATION_S, ECG_MAX_SAMPLING_RATE_HZ,
+    ECG_MIN_SAMPLING_RATE_HZ,
+};
+use crate::models::models_exams::{
+    ECG_LEAD_LENGTH, XRAY_IMAGE_SIZE, XRAY_MAX_IMAGE_BYTES, XRAY_VIEW_POSITIONS,
+};
+use crate::models::models_size_tiers::upload_body_limit;
+
+// Constants ***************************************************************************************
+/// Validation profile currently applied to ECG exams
+pub const ECG_PROFILE: ProfileRef = ProfileRef {
+    id: "ecg_exam",
+    version: 3,
+};
+/// Validation profile currently applied to XRay exams
+pub const XRAY_PROFILE: ProfileRef = ProfileRef {
+    id: "xray_exam",
+    version: 3,
+};
+/// Validation profile currently applied to streamed XRay uploads
+pub const XRAY_UPLOAD_PROFILE: ProfileRef = ProfileRef {
+    id: "xray_upload",
+    version: 2,
+};
+
+// MAIN FUNCTIONS AND STRUCTS **********************************************************************
+/// Reference to one version of a validation profile, recorded with every exam
+/// Any change to the validation rules of an exam type must bump its version and keep the old
+/// definition in `profile_history`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct ProfileRef {
+    pub id: &'static str,
+    pub version: u32,
+}
+
+impl ProfileRef {
+    /// Compact form used in audit records, e.g. `ecg_exam@1`
+    pub fn label(&self) -> String {
+        format!("{}@{}", self.id, self.version)
+    }
+}
+
+/// Definition of one version of a validation profile
+/// # Arguments
+/// * `id` - The profile id (the exam type it validates)
+/// * `version` - The profile version
+/// * `current` - Whether this version is the one applied to new exams
+/// * `description` - What changed in this version
+/// * `rules` - The validation parameters of this version
+#[derive(Debug, Clone, Serialize)]
+pub struct ValidationProfile {
+    pub id: &'static str,
+    pub version: u32,
+    pub current: bool,
+    pub description: &'static str,
+    pub rules: serde_json::Value,
+}
+
+/// Every validation profile version ever applied, oldest first
+/// # Arguments
+/// * `server` - The server settings, with the upload body limit
+pub fn profile_history(server: &ServerSettings) -> Vec<ValidationProfile> {
+    let profiles = vec![
+        ValidationProfile {
+            id: "ecg_exam",
+            version: 1,
+            current: false,
+            description: "12 leads of fixed length, bounded amplitude, no flat-line lead",
+            rules: json!({
+                "patient_id_max_length": 100,
+                "hospital_id": "sha256",
+                "hospital_key_max_length": 100,
+                "leads": 12,
+                "lead_length": ECG_LEAD_LENGTH,
+                "max_abs_amplitude": 2.0,
+                "reject_flat_line": true,
+            }),
+        },
+        ValidationProfile {
+            id: "xray_exam",
+            version: 1,
+            current: false,
+            description: "Square base64 image of fixed size, any format the decoder supports",
+            rules: json!({
+                "patient_id_max_length": 100,
+                "hospital_id": "sha256",
+                "hospital_key_max_length": 100,
+                "image_size": 1024,
+            }),
+        },
+        ValidationProfile {
+            id: "xray_exam",
+            version: 2,
+            current: false,
+            description: "PNG/JPEG only, bounded image size, optional view position",
+            rules: json!({
+                "patient_id_max_length": 100,
+                "hospital_id": "sha256",
+                "hospital_key_max_length": 100,
+                "image_size": XRAY_IMAGE_SIZE,
+                "image_formats": ["png", "jpeg"],
+                "max_image_bytes": XRAY_MAX_IMAGE_BYTES,
+                "view_positions": XRAY_VIEW_POSITIONS,
+            }),
+        },
+        ValidationProfile {
+            id: "ecg_exam",
+            version: 2,
+            current: false,
+            description: "hospital_key in the body becomes optional (deprecated)",
+            rules: json!({
+                "patient_id_max_length": 100,
+                "hospital_id": "sha256",
+                "hospital_key_max_length": 100,
+                "hospital_key_optional": true,
+                "leads": 12,
+                "lead_length": ECG_LEAD_LENGTH,
+                "max_abs_amplitude": 2.0,
+                "reject_flat_line": true,
+            }),
+        },
+        ValidationProfile {
+            id: "xray_exam",
+            version: 3,
+            current: false,
+            description: "hospital_key in the body becomes optional (deprecated)",
+            rules: json!({
+                "patient_id_max_length": 100,
+                "hospital_id": "sha256",
+                "hospital_key_max_length": 100,
+                "hospital_key_optional": true,
+                "image_size": XRAY_IMAGE_SIZE,
+                "image_formats": ["png", "jpeg"],
+                "max_image_bytes": XRAY_MAX_IMAGE_BYTES,
+                "view_positions": XRAY_VIEW_POSITIONS,
+            }),
+        },
+        ValidationProfile {
+            id: "ecg_exam",
+            version: 3,
+            current: false,
+            description: "Signal quality: finite samples, no clipping, Einthoven consistency, \
+                          lead length from the declared sampling rate and duration",
+            rules: json!({
+                "patient_id_max_length": 100,
+                "hospital_id": "sha256",
+                "hospital_key_max_length": 100,
+                "hospital_key_optional": true,
+                "leads": 12,
+                "lead_length": "sampling_rate_hz * duration_s",
+                "default_sampling_rate_hz": ECG_DEFAULT_SAMPLING_RATE_HZ,
+                "default_duration_s": ECG_DEFAULT_DURATION_S,
+                "sampling_rate_hz_range": [ECG_MIN_SAMPLING_RATE_HZ, ECG_MAX_SAMPLING_RATE_HZ],
+                "max_duration_s": ECG_MAX_DURATION_S,
+                "max_abs_amplitude": ECG_MAX_AMPLITUDE,
+                "reject_flat_line": true,
+                "reject_non_finite": true,
+                "clipping_min_run": ECG_CLIPPING_MIN_RUN,
+                "einthoven_max_rms_mv": ECG_EINTHOVEN_MAX_RMS,
+            }),
+        },
+        ValidationProfile {
+            id: "xray_upload",
+            version: 1,
+            current: false,
+            description: "Streamed PNG/JPEG/DICOM upload, format checked by its signature only",
+            rules: json!({
+                "patient_id_max_length": 100,
+                "hospital_id": "sha256",
+                "image_formats": ["png", "jpeg", "dicom"],
+                "max_body_bytes": upload_body_limit(server),
+                "view_positions": XRAY_VIEW_POSITIONS,
+            }),
+        },
+        ValidationProfile {
+            id: "xray_upload",
+            version: 2,
+            current: false,
+            description: "DICOM files must parse as little endian Part 10 and are de-identified",
+            rules: json!({
+                "patient_id_max_length": 100,
+                "hospital_id": "sha256",
+                "image_formats": ["png", "jpeg", "dicom"],
+                "max_body_bytes": upload_body_limit(server),
+                "view_positions": XRAY_VIEW_POSITIONS,
+                "dicom_transfer_syntaxes": "little endian, not deflated",
+                "dicom_deidentification": "allowlist v1",
+            }),
+        },
+    ];
+    profiles
+        .into_iter()
+        .map(|mut profile| {
+            profile.current =
+                [ECG_PROFILE, XRAY_PROFILE, XRAY_UPLOAD_PROFILE].contains(&ProfileRef {
+                    id: profile.id,
+                    version: profile.version,
+                });
+            profile
+        })
+        .collect()
+}
+
+/// Definition of one profile version
+/// # Arguments
+/// * `id` - The profile id
+/// * `version` - The profile version
+/// * `server` - The server settings, with the upload body limit
+/// # Returns
+/// * The definition, or None if the version never existed
+pub fn find_profile(id: &str, version: u32, server: &ServerSettings) -> Option<ValidationProfile> {
+    profile_history(server)
+        .into_iter()
+        .find(|profile| profile.id == id && profile.version == version)
+}
+
+// TESTS *******************************************************************************************
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::settings::test_settings;
+
+    // Happy path: the current profiles are defined and flagged as current
+    #[test]
+    fn current_profiles_are_defined() {
+        let server = test_settings().server;
+        for current in [ECG_PROFILE, XRAY_PROFILE, XRAY_UPLOAD_PROFILE] {
+            let profile =
+                find_profile(current.id, current.version, &server).expect("current profile");
+            assert!(profile.current);
+        }
+        assert!(!find_profile("xray_exam", 2, &server).unwrap().current);
+        assert_eq!(XRAY_PROFILE.label(), "xray_exam@3");
+    }
+
+    // Error handling: unknown versions are not found
+    #[test]
+    fn unknown_profile_version() {
+        let server = test_settings().server;
+        assert!(find_profile("ecg_exam", 99, &server).is_none());
+        assert!(find_profile("audit", 1, &server).is_none());
+    }
+
+    // A rule constant changed without a new profile version would make past decisions
+    // unexplainable: the current definitions must match the constants used by the validators
+    #[test]
+    fn current_profiles_match_validators() {
+        let server = test_settings().server;
+        let ecg = find_profile(ECG_PROFILE.id, ECG_PROFILE.version, &server).unwrap();
+        assert_eq!(ecg.rules["default_sampling_rate_hz"], 500.0);
+        assert_eq!(ecg.rules["default_duration_s"], 10.0);
+        assert_eq!(ecg.rules["hospital_key_optional"], true);
+        assert_eq!(ecg.rules["clipping_min_run"], ECG_CLIPPING_MIN_RUN);
+        let xray = find_profile(XRAY_PROFILE.id, XRAY_PROFILE.version, &server).unwrap();
+        assert_eq!(xray.rules["image_size"], 1024);
+        assert_eq!(xray.rules["max_image_bytes"], 3 * 1024 * 1024);
+        assert_eq!(xray.rules["view_positions"].as_array().unwrap().len(), 4);
+    }
+
+    // Every (id, version) pair is unique
+    #[test]
+    fn profile_versions_unique() {
+        let server = test_settings().server;
+        let history = profile_history(&server);
+        for (i, a) in history.iter().enumerate() {
+            for b in &history[i + 1..] {
+                assert!(a.id != b.id || a.version != b.version);
+            }
+        }
+    }
+}
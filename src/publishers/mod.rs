@@ -0,0 +1,4 @@
+pub mod publisher;
+pub mod publisher_log;
+#[cfg(feature = "grpc")]
+pub mod publisher_pubsub;
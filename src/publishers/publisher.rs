@@ -0,0 +1,130 @@
+// Imports *****************************************************************************************
+// External Crates
+use anyhow::Result;
+use async_trait::async_trait;
+#[cfg(feature = "grpc")]
+use google_cloud_pubsub::client::Client as PubSubClient;
+use log::info;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+// Internal Modules
+use crate::config::settings::{PublishBackend, PublishSettings};
+use crate::models::models_consent::ConsentScope;
+use crate::publishers::publisher_log::LogPublisher;
+#[cfg(feature = "grpc")]
+use crate::services::service_pubsub_router::{PubSubRouter, TopicAdmin};
+
+// Structs *****************************************************************************************
+/// Notification of a stored exam, as handed to the publish backend
+/// # Arguments
+/// * `data` - The encoded notification (JSON or Avro)
+/// * `attributes` - The attributes the consumers filter on
+/// * `ordering_key` - The key ordering the notifications of a patient, empty for none
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct NotificationMessage {
+    pub data: Vec<u8>,
+    pub attributes: HashMap<String, String>,
+    pub ordering_key: String,
+}
+
+/// Pub/Sub clients of the service's own project - empty in a build without the `grpc` feature,
+/// which only has the log publisher and sinks
+/// # Arguments
+/// * `client` - The PubSub client of the `pubsub` backend, sinks and subscriptions
+/// * `topic_admin` - The topic admin reading the schema settings of the routed topics
+#[derive(Clone)]
+pub struct PubSubClients {
+    #[cfg(feature = "grpc")]
+    pub client: Arc<PubSubClient>,
+    #[cfg(feature = "grpc")]
+    pub topic_admin: Arc<TopicAdmin>,
+}
+
+/// Schema binding of a routed topic
+/// # Arguments
+/// * `exam_type` - The exam type routed to the topic
+/// * `topic` - The fully qualified topic name
+/// * `schema` - The schema of the registry bound to the topic, None if messages are not validated
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TopicSchema {
+    pub exam_type: String,
+    pub topic: String,
+    pub schema: Option<String>,
+}
+
+// MAIN TRAIT **************************************************************************************
+/// Destination of the notifications of the stored exams, routed per exam type
+/// Implementations must be cheap to share between workers
+#[async_trait]
+pub trait Publisher: Send + Sync {
+    /// Short name of the backend, used in logs
+    fn name(&self) -> &'static str;
+
+    /// The routed topic id of an exam type
+    /// # Errors
+    /// * Returns an error if the exam type has no route
+    fn topic_name(&self, exam_type: &str) -> Result<&str>;
+
+    /// Check that the consent of a hospital covers the destination of an exam type
+    /// # Arguments
+    /// * `exam_type` - The exam type key
+    /// * `consent_scope` - The consent of the hospital the exam comes from
+    /// # Errors
+    /// * Returns an error if the exam type has no route or the route needs a wider consent
+    fn check_consent(&self, exam_type: &str, consent_scope: ConsentScope) -> Result<()>;
+
+    /// Publish the notification of an exam, retrying transient failures
+    /// # Arguments
+    /// * `exam_type` - The exam type key, selecting the route
+    /// * `message` - The message, with its attributes and ordering key
+    /// # Returns
+    /// * The id of the published message
+    /// # Errors
+    /// * Returns an error if the message could not be published after its retries
+    async fn publish(&self, exam_type: &str, message: &NotificationMessage) -> Result<String>;
+
+    /// Check that the destinations of the notifications are reachable, for the readiness probe
+    /// - backends without a remote destination are always ready
+    /// # Errors
+    /// * Returns an error if a destination is unreachable or missing
+    async fn probe(&self) -> Result<()> {
+        Ok(())
+    }
+
+    /// Schema of the registry each routed topic validates messages against, for the startup
+    /// compatibility check - backends without a schema registry have no topic
+    /// # Returns
+    /// * The exam type, topic and bound schema (None for a topic without schema) of each route
+    /// # Errors
+    /// * Returns an error if the configuration of a topic cannot be read
+    async fn topic_schemas(&self) -> Result<Vec<TopicSchema>> {
+        Ok(Vec::new())
+    }
+}
+
+// MAIN FUNCTIONS **********************************************************************************
+/// Publisher selected by PUBLISH_BACKEND
+/// # Arguments
+/// * `settings` - The publish settings
+/// * `pubsub` - The Pub/Sub clients of the service's own project, used by the `pubsub` backend
+/// # Errors
+/// * Returns an error if the routing table is invalid, or a routed topic is unreachable
+#[cfg_attr(not(feature = "grpc"), allow(unused_variables))]
+pub async fn publisher_from_settings(
+    settings: &PublishSettings,
+    pubsub: &PubSubClients,
+) -> Result<Arc<dyn Publisher>> {
+    let publisher: Arc<dyn Publisher> = match settings.backend {
+        #[cfg(feature = "grpc")]
+        PublishBackend::PubSub => Arc::new(
+            PubSubRouter::new(settings, pubsub.client.clone(), pubsub.topic_admin.clone()).await?,
+        ),
+        PublishBackend::Log => Arc::new(LogPublisher::new(
+            settings.routes.clone(),
+            settings.log_file.clone(),
+        )),
+    };
+    info!("Exam publisher: {}", publisher.name());
+    Ok(publisher)
+}
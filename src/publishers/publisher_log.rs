@@ -0,0 +1,158 @@
+// Imports *****************************************************************************************
+// External Crates
+use actix_web::web;
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use log::info;
+use std::collections::HashMap;
+use std::io::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+// Internal Modules
+use crate::models::models_consent::ConsentScope;
+use crate::models::models_topics::{check_route_consent, route_of, TopicRoute};
+use crate::publishers::publisher::{NotificationMessage, Publisher};
+
+// MAIN STRUCT *************************************************************************************
+/// Publisher writing the notifications as JSON lines, to PUBLISH_LOG_FILE or to the log - local
+/// development without Pub/Sub (PUBLISH_BACKEND=log)
+/// The routing table and consent checks are those of the Pub/Sub backend, nothing is contacted
+pub struct LogPublisher {
+    routes: HashMap<String, TopicRoute>,
+    file: Option<String>,
+    published: AtomicU64,
+}
+
+impl LogPublisher {
+    /// Create a publisher over a routing table
+    pub fn new(routes: HashMap<String, TopicRoute>, file: Option<String>) -> Self {
+        Self {
+            routes,
+            file,
+            published: AtomicU64::new(0),
+        }
+    }
+}
+
+#[async_trait]
+impl Publisher for LogPublisher {
+    fn name(&self) -> &'static str {
+        "log"
+    }
+
+    fn topic_name(&self, exam_type: &str) -> Result<&str> {
+        route_of(&self.routes, exam_type).map(|r| r.topic.as_str())
+    }
+
+    fn check_consent(&self, exam_type: &str, consent_scope: ConsentScope) -> Result<()> {
+        check_route_consent(&self.routes, exam_type, consent_scope)
+    }
+
+    async fn publish(&self, exam_type: &str, message: &NotificationMessage) -> Result<String> {
+        let topic = self.topic_name(exam_type)?;
+        let message_id = format!("log-{}", self.published.fetch_add(1, Ordering::SeqCst) + 1);
+        let line = message_line(&message_id, topic, message).to_string();
+        match &self.file {
+            Some(path) => {
+                let path = path.clone();
+                web::block(move || -> std::io::Result<()> {
+                    let mut file = std::fs::OpenOptions::new()
+                        .create(true)
+                        .append(true)
+                        .open(&path)?;
+                    writeln!(file, "{line}")
+                })
+                .await
+                .map_err(|e| anyhow!("log.publish could not be scheduled: {e}"))?
+                .map_err(|e| anyhow!("log.publish failed: {e}"))?;
+            }
+            None => info!(target: "publish", "{line}"),
+        }
+        Ok(message_id)
+    }
+}
+
+// SUPPORT FUNCTIONS *******************************************************************************
+/// JSON line of a published message - the data is kept readable when it is UTF-8 (JSON), in
+/// base64 otherwise (Avro)
+fn message_line(message_id: &str, topic: &str, message: &NotificationMessage) -> serde_json::Value {
+    let data = match std::str::from_utf8(&message.data) {
+        Ok(text) => serde_json::json!({ "text": text }),
+        Err(_) => serde_json::json!({ "base64": STANDARD.encode(&message.data) }),
+    };
+    serde_json::json!({
+        "message_id": message_id,
+        "topic": topic,
+        "ordering_key": message.ordering_key,
+        "attributes": message.attributes,
+        "data": data,
+    })
+}
+
+// TESTS *******************************************************************************************
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::models_topics::TopicName;
+
+    fn publisher(file: Option<String>) -> LogPublisher {
+        let route = TopicRoute {
+            topic: TopicName::parse_for_env("dev-ecg-v1", "dev").unwrap(),
+            project: None,
+            required_consent: ConsentScope::Research,
+        };
+        LogPublisher::new(HashMap::from([("ecg_exam".to_string(), route)]), file)
+    }
+
+    // Happy path: messages are appended as JSON lines with increasing ids
+    #[actix_web::test]
+    async fn log_publisher_appends_lines() {
+        let path =
+            std::env::temp_dir().join(format!("sentinela-publish-{}.jsonl", std::process::id()));
+        let publisher = publisher(Some(path.display().to_string()));
+        let message = NotificationMessage {
+            data: br#"{"exam_id":"e"}"#.to_vec(),
+            ..Default::default()
+        };
+        assert_eq!(
+            publisher.publish("ecg_exam", &message).await.unwrap(),
+            "log-1"
+        );
+        assert_eq!(
+            publisher.publish("ecg_exam", &message).await.unwrap(),
+            "log-2"
+        );
+        let lines = std::fs::read_to_string(&path).unwrap();
+        let first: serde_json::Value = serde_json::from_str(lines.lines().next().unwrap()).unwrap();
+        assert_eq!(first["topic"], "dev-ecg-v1");
+        assert_eq!(first["data"]["text"], r#"{"exam_id":"e"}"#);
+        assert_eq!(lines.lines().count(), 2);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    // Error handling: routes and consents are enforced as for Pub/Sub
+    #[test]
+    fn log_publisher_checks_routes() {
+        let publisher = publisher(None);
+        assert!(publisher.topic_name("xray_exam").is_err());
+        assert!(publisher
+            .check_consent("ecg_exam", ConsentScope::Clinical)
+            .is_err());
+        assert!(publisher
+            .check_consent("ecg_exam", ConsentScope::Research)
+            .is_ok());
+    }
+
+    // Binary (Avro) payloads are kept in base64
+    #[test]
+    fn binary_data_encoded() {
+        let message = NotificationMessage {
+            data: vec![0xff, 0x00],
+            ..Default::default()
+        };
+        let line = message_line("log-1", "dev-ecg-v1", &message);
+        assert_eq!(line["data"]["base64"], "/wA=");
+    }
+}
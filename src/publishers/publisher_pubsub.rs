@@ -0,0 +1,58 @@
+// Imports *****************************************************************************************
+// External Crates
+use anyhow::{bail, Result};
+use async_trait::async_trait;
+use google_cloud_googleapis::pubsub::v1::PubsubMessage;
+
+// Internal Modules
+use crate::models::models_consent::ConsentScope;
+use crate::publishers::publisher::{NotificationMessage, Publisher, TopicSchema};
+use crate::services::service_pubsub_router::PubSubRouter;
+use crate::utils::external_call::{Dependency, ExternalCall, INGEST_RETRIES};
+
+// MAIN STRUCT *************************************************************************************
+/// The routed Pub/Sub topics (PUBLISH_BACKEND=pubsub)
+#[async_trait]
+impl Publisher for PubSubRouter {
+    fn name(&self) -> &'static str {
+        "pubsub"
+    }
+
+    fn topic_name(&self, exam_type: &str) -> Result<&str> {
+        PubSubRouter::topic_name(self, exam_type)
+    }
+
+    fn check_consent(&self, exam_type: &str, consent_scope: ConsentScope) -> Result<()> {
+        PubSubRouter::check_consent(self, exam_type, consent_scope)
+    }
+
+    async fn publish(&self, exam_type: &str, message: &NotificationMessage) -> Result<String> {
+        let publisher = self.topic(exam_type)?.new_publisher(None);
+        let message = PubsubMessage {
+            data: message.data.clone(),
+            attributes: message.attributes.clone(),
+            ordering_key: message.ordering_key.clone(),
+            ..Default::default()
+        };
+        let message_id = ExternalCall::new(Dependency::PubSub, "publish")
+            .retries(INGEST_RETRIES)
+            .run(|| async { publisher.publish(message.clone()).await.get().await })
+            .await?;
+        // An ack without a message ID does not prove the message was stored by Pub/Sub
+        if message_id.is_empty() {
+            bail!("Pub/Sub acked the {exam_type} message without a message ID");
+        }
+        Ok(message_id)
+    }
+
+    async fn probe(&self) -> Result<()> {
+        self.check_topics().await
+    }
+
+    async fn topic_schemas(&self) -> Result<Vec<TopicSchema>> {
+        PubSubRouter::topic_schemas(self).await
+    }
+}
+
+// TESTS *******************************************************************************************
+// DOCUMENTATION: Thin wrapper over the Pub/Sub publisher - covered by integration tests
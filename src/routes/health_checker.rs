@@ -1,24 +1,134 @@
-use crate::{HOST, PORT};
-use actix_web::{get, HttpResponse};
+use crate::config::Settings;
+use crate::routes::registry::register_route;
+use crate::services::service_compatibility::schemas_compatible;
+use crate::services::service_readiness::ReadinessProbe;
+use crate::utils::clock_drift::clock_within_threshold;
+use crate::utils::drain_state::DRAIN_STATE;
+use crate::utils::response_format::NegotiatedResponse;
+use actix_web::{get, web, HttpResponse};
+use serde_json::json;
+use std::sync::Arc;
 
 // Health Check Handler
+#[utoipa::path(
+    get,
+    path = "/v1/health_check",
+    tag = "probes",
+    responses(
+        (status = 200, description = "Serving"),
+        (status = 503, description = "Draining (maintenance, shutdown)")
+    )
+)]
 #[get("/health_check")]
-/// Health check endpoint to verify if the server is running
-pub async fn health_check_handler() -> HttpResponse {
-    HttpResponse::Ok().body(format!("SENTINELA EXAM GATEWAY server is running on {HOST}:{PORT}"))
+/// Health check endpoint used by the load balancer to route traffic
+/// Returns 503 while the instance is draining (maintenance, shutdown initiated)
+pub async fn health_check_handler(settings: web::Data<Arc<Settings>>) -> HttpResponse {
+    let reasons = DRAIN_STATE.reasons();
+    if !reasons.is_empty() {
+        let reasons: Vec<&str> = reasons.iter().map(|r| r.as_str()).collect();
+        return HttpResponse::ServiceUnavailable().body(format!(
+            "SENTINELA EXAM GATEWAY server is draining: {}",
+            reasons.join(", ")
+        ));
+    }
+    HttpResponse::Ok().body(format!(
+        "SENTINELA EXAM GATEWAY server is running on {}:{}",
+        settings.server.host, settings.server.port
+    ))
+}
+
+// Liveness Handler
+#[utoipa::path(
+    get,
+    path = "/v1/liveness",
+    tag = "probes",
+    responses(
+        (status = 200, description = "The process is up")
+    )
+)]
+#[get("/liveness")]
+/// Liveness endpoint to verify the process is up - stays 200 while draining
+pub async fn liveness_handler() -> HttpResponse {
+    HttpResponse::Ok().body("SENTINELA EXAM GATEWAY server is alive")
+}
+
+// Readiness Handler
+#[utoipa::path(
+    get,
+    path = "/v1/readyz",
+    tag = "probes",
+    responses(
+        (status = 200, description = "Ready, with the status of each dependency"),
+        (status = 503, description = "Not ready: a dependency is down, the instance is draining, \
+            its clock drifted or its schemas do not match")
+    )
+)]
+#[get("/readyz")]
+/// Readiness endpoint: whether the instance can accept exams - storage, Pub/Sub and Postgres
+/// answer, the instance is not draining, its clock is within CLOCK_DRIFT_MAX_MS and the schemas of
+/// Postgres and Pub/Sub are the ones it writes (COMPATIBILITY_CHECK). Unlike the
+/// health check, it calls the dependencies (at most once per PROBE_CACHE_TTL); the errors stay in
+/// the logs and the internal readiness
+/// Returns 503 with the status of each dependency when not ready
+pub async fn readyz_handler(probe: web::Data<Arc<ReadinessProbe>>) -> HttpResponse {
+    let draining: Vec<&str> = DRAIN_STATE.reasons().iter().map(|r| r.as_str()).collect();
+    let mut report = probe.check().await;
+    for check in &mut report.checks {
+        check.error = None;
+    }
+    let clock_trusted = clock_within_threshold();
+    let schemas_compatible = schemas_compatible();
+    let ready = report.ready && draining.is_empty() && clock_trusted && schemas_compatible;
+    let body = json!({
+        "ready": ready,
+        "draining": draining,
+        "clock_trusted": clock_trusted,
+        "schemas_compatible": schemas_compatible,
+        "checked_at": report.checked_at,
+        "checks": report.checks,
+    });
+    if ready {
+        HttpResponse::Ok().negotiated(&body)
+    } else {
+        HttpResponse::ServiceUnavailable().negotiated(&body)
+    }
+}
+
+// Route Registration ******************************************************************************
+register_route! {
+    scope: Hospital, version: 1, path: "/health_check",
+    auth: Public, rate_limit: None,
+    service: health_check_handler
+}
+register_route! {
+    scope: Hospital, version: 1, path: "/liveness",
+    auth: Public, rate_limit: None,
+    service: liveness_handler
+}
+register_route! {
+    scope: Hospital, version: 1, path: "/readyz",
+    auth: Public, rate_limit: None,
+    service: readyz_handler
 }
 
 // TESTS *******************************************************************************************
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::config::settings::test_settings;
+    use crate::services::service_readiness::tests::probe;
     use actix_web::{http::StatusCode, test, App};
 
     // Happy path: correct route + GET
     #[actix_web::test]
     /// Test the health check endpoint with a happy path scenario
     async fn health_check_happy_path() {
-        let app = test::init_service(App::new().service(health_check_handler)).await;
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(Arc::new(test_settings())))
+                .service(health_check_handler),
+        )
+        .await;
         let req = test::TestRequest::get().uri("/health_check").to_request();
         let resp = test::call_service(&app, req).await;
 
@@ -33,19 +143,59 @@ mod tests {
     #[actix_web::test]
     /// Test the health check endpoint with a wrong method (POST instead of GET)
     async fn health_check_wrong_method() {
-        let app = test::init_service(App::new().service(health_check_handler)).await;
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(Arc::new(test_settings())))
+                .service(health_check_handler),
+        )
+        .await;
         let req = test::TestRequest::post().uri("/health_check").to_request();
         let resp = test::call_service(&app, req).await;
         assert_ne!(resp.status(), StatusCode::OK);
     }
 
+    // Happy path: liveness
+    #[actix_web::test]
+    /// Test the liveness endpoint returns 200
+    async fn liveness_happy_path() {
+        let app = test::init_service(App::new().service(liveness_handler)).await;
+        let req = test::TestRequest::get().uri("/liveness").to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+    }
+
+    // Error handling: a dependency down makes the instance not ready, without exposing its error
+    #[actix_web::test]
+    /// Test the readiness endpoint with an unreachable database
+    async fn readyz_error_dependency_down() {
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(Arc::new(probe("readyz"))))
+                .service(readyz_handler),
+        )
+        .await;
+        let req = test::TestRequest::get().uri("/readyz").to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::SERVICE_UNAVAILABLE);
+
+        let body: serde_json::Value = test::read_body_json(resp).await;
+        assert_eq!(body["ready"], false);
+        assert_eq!(body["checks"].as_array().unwrap().len(), 4);
+        assert!(body["checks"][2].get("error").is_none());
+    }
+
     // Error handling: wrong path
     #[actix_web::test]
     /// Test the health check endpoint with a wrong path
     async fn health_check_error_wrong_path() {
-        let app = test::init_service(App::new().service(health_check_handler)).await;
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(Arc::new(test_settings())))
+                .service(health_check_handler),
+        )
+        .await;
         let req = test::TestRequest::get().uri("/wrong_path").to_request();
         let resp = test::call_service(&app, req).await;
         assert_eq!(resp.status(), StatusCode::NOT_FOUND);
     }
-}
\ No newline at end of file
+}
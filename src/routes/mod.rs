@@ -1,23 +1,120 @@
 // Imports *****************************************************************************************
 // External Crates
-use actix_web::web;
+use actix_web::middleware::from_fn;
+use actix_web::{middleware, web};
 
 // Internal Modules
+use crate::authentication::admin::operator_auth_middleware;
+use crate::authentication::console_session::{
+    console_cors, console_session_middleware, security_headers,
+};
+use crate::authentication::middleware::hospital_auth_middleware;
+use crate::authentication::rate_limit::rate_limit_middleware;
+use crate::routes::registry::{registered_routes, RouteScope};
+
 pub mod health_checker;
+pub mod registry;
+pub mod route_delete_hospital_key;
+pub mod route_get_audit_events;
+pub mod route_get_autoscale_hint;
+pub mod route_get_billing;
+pub mod route_get_config_drift;
+pub mod route_get_console_session;
+pub mod route_get_deprecations;
+pub mod route_get_ecg_stream;
+pub mod route_get_exam_export;
+pub mod route_get_exam_retention;
+pub mod route_get_exam_status;
+pub mod route_get_external_calls;
+pub mod route_get_hospital_keys;
+pub mod route_get_metrics;
+pub mod route_get_openapi;
+pub mod route_get_parquet_schemas;
+pub mod route_get_queues;
+pub mod route_get_readiness;
+pub mod route_get_stage_durations;
+pub mod route_get_stats;
+pub mod route_get_storage_gc;
+pub mod route_get_validation_profiles;
+pub mod route_get_zstd_dictionaries;
+pub mod route_patch_hospital;
+pub mod route_post_console_session;
 pub mod route_post_ecg_exam;
+pub mod route_post_exam_retention;
+pub mod route_post_fhir_observation;
+pub mod route_post_hash_check;
+pub mod route_post_hospitals;
+pub mod route_post_id_case_migration;
+pub mod route_post_maintenance;
+pub mod route_post_queues;
 pub mod route_post_xray_exam;
+pub mod route_post_xray_upload;
 
 // Router Configuration ****************************************************************************
+/// Mount every registered route (`register_route!` in its module) in its scope and version, each
+/// scope with its middlewares - a new endpoint only registers itself in its own module
 pub fn config(cfg: &mut web::ServiceConfig) {
-    // Register services for the application v1
-    cfg.service(
-        web::scope("/v1")
-            // Health Check
-            .service(health_checker::health_check_handler)
-            // ECG exam route
-            .service(route_post_ecg_exam::ecg_exam_handler)
-            // XRAY exam route
-            .service(route_post_xray_exam::xray_exam_handler)
-            // Future Enhancements: Add more routes here
-    );
-}
\ No newline at end of file
+    for ((scope, version), routes) in registered_routes() {
+        let prefix = scope.prefix(version);
+        let register = move |cfg: &mut web::ServiceConfig| {
+            for route in &routes {
+                (route.register)(cfg);
+            }
+        };
+        match scope {
+            // Hospital admin API - before the v1 scope, which would otherwise match it
+            RouteScope::Admin => {
+                cfg.service(
+                    web::scope(&prefix)
+                        // Operator routes: the admin key or a console session, before the handler
+                        .wrap(from_fn(operator_auth_middleware))
+                        // Admin console sessions: origin, CSRF token and audit of the
+                        // cookie-authenticated calls
+                        .wrap(from_fn(console_session_middleware))
+                        // Never cached nor framed
+                        .wrap(security_headers())
+                        // Admin rate limit and lockouts - operators authenticate with the admin key
+                        // or a console session
+                        .wrap(from_fn(rate_limit_middleware))
+                        // Cross-origin calls from the admin console only (ADMIN_CONSOLE_ORIGIN)
+                        .wrap(console_cors())
+                        .configure(register),
+                );
+            }
+            // OpenAPI specification and Swagger UI - public, before the v1 scope which would
+            // otherwise authenticate them
+            RouteScope::Root => register(cfg),
+            // Hospital API
+            RouteScope::Hospital => {
+                cfg.service(
+                    web::scope(&prefix)
+                        // Hospital authentication - the public routes (probes) are skipped
+                        .wrap(from_fn(hospital_auth_middleware))
+                        // Rate limit of the ingest scope, before authentication
+                        .wrap(from_fn(rate_limit_middleware))
+                        .configure(register),
+                );
+            }
+            // Internal (operator-only) API
+            RouteScope::Internal => {
+                cfg.service(
+                    web::scope(&prefix)
+                        // Compress JSON responses per Accept-Encoding (streamed Parquet opts out)
+                        .wrap(middleware::Compress::default())
+                        // Operator routes: the admin key or a console session, before the handler
+                        .wrap(from_fn(operator_auth_middleware))
+                        // Admin console sessions: origin, CSRF token and audit of the
+                        // cookie-authenticated calls
+                        .wrap(from_fn(console_session_middleware))
+                        // Never cached nor framed
+                        .wrap(security_headers())
+                        // Rate limits and lockouts of the admin and monitoring scopes
+                        .wrap(from_fn(rate_limit_middleware))
+                        // Cross-origin calls from the admin console only (ADMIN_CONSOLE_ORIGIN)
+                        .wrap(console_cors())
+                        .configure(register),
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,245 @@
+// Imports *****************************************************************************************
+// External Crates
+use actix_web::dev::ResourceDef;
+use actix_web::web;
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::sync::OnceLock;
+
+// Internal Modules
+use crate::authentication::rate_limit::RateScope;
+
+// Structs *****************************************************************************************
+/// Scopes of the API, in the order they are registered: the hospital admin API and the public
+/// documentation come before `/v1`, which would otherwise match them
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum RouteScope {
+    /// Hospital admin API (`/v{version}/admin`)
+    Admin,
+    /// Services mounted at the root of the application (the OpenAPI specification and Swagger UI)
+    Root,
+    /// Hospital API (`/v{version}`)
+    Hospital,
+    /// Operator-only API (`/internal/v{version}`)
+    Internal,
+}
+
+impl RouteScope {
+    /// Path prefix of the scope for an API version - empty for the root
+    pub fn prefix(&self, version: u8) -> String {
+        match self {
+            RouteScope::Admin => format!("/v{version}/admin"),
+            RouteScope::Root => String::new(),
+            RouteScope::Hospital => format!("/v{version}"),
+            RouteScope::Internal => format!("/internal/v{version}"),
+        }
+    }
+}
+
+/// Credentials a route requires
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RouteAuth {
+    /// None - load balancer probes and the documentation
+    Public,
+    /// Hospital credentials, checked by `hospital_auth_middleware`
+    Hospital,
+    /// The admin key or an admin console session, checked by `operator_auth_middleware`
+    Operator,
+    /// The admin key in the body of the console login, checked by the handler
+    ConsoleLogin,
+}
+
+/// A route, as its module registers it with `register_route!`
+/// # Arguments
+/// * `scope` - The scope the route is mounted in
+/// * `version` - The API version of the scope
+/// * `path` - The path of the route within the scope, as declared on its handler
+/// * `auth` - The credentials it requires
+/// * `rate_limit` - The rate limit scope it counts against, None for unlimited
+/// * `register` - Adds the handler to the scope
+pub struct RouteRegistration {
+    pub scope: RouteScope,
+    pub version: u8,
+    pub path: &'static str,
+    pub auth: RouteAuth,
+    pub rate_limit: Option<RateScope>,
+    pub register: fn(&mut web::ServiceConfig),
+}
+
+inventory::collect!(RouteRegistration);
+
+/// Register a route of the module - `config()` mounts it, and the authentication and rate limit
+/// middlewares read its requirements, without any other file to edit:
+/// `register_route! { scope: Hospital, version: 1, path: "/ecg_exam", auth: Hospital, rate_limit:
+/// Some(RateScope::Ingest), service: ecg_exam_handler }`
+macro_rules! register_route {
+    (
+        scope: $scope:ident, version: $version:literal, path: $path:literal,
+        auth: $auth:ident, rate_limit: $rate_limit:expr,
+        service: $service:expr
+    ) => {
+        inventory::submit! {
+            $crate::routes::registry::RouteRegistration {
+                scope: $crate::routes::registry::RouteScope::$scope,
+                version: $version,
+                path: $path,
+                auth: $crate::routes::registry::RouteAuth::$auth,
+                rate_limit: $rate_limit,
+                register: |cfg| {
+                    cfg.service($service);
+                },
+            }
+        }
+    };
+}
+pub(crate) use register_route;
+
+// Global variables ********************************************************************************
+/// Full path pattern of every registered route, for the middlewares that look a request up
+static ROUTE_PATTERNS: OnceLock<Vec<(ResourceDef, &'static RouteRegistration)>> = OnceLock::new();
+
+// MAIN FUNCTIONS **********************************************************************************
+/// Registered routes grouped by scope and version, in registration order - literal path segments
+/// before parameters, so a specific route is never shadowed by a pattern
+pub fn registered_routes() -> BTreeMap<(RouteScope, u8), Vec<&'static RouteRegistration>> {
+    let mut routes: BTreeMap<_, Vec<&'static RouteRegistration>> = BTreeMap::new();
+    for route in inventory::iter::<RouteRegistration> {
+        routes
+            .entry((route.scope, route.version))
+            .or_default()
+            .push(route);
+    }
+    for scoped in routes.values_mut() {
+        scoped.sort_by(|a, b| compare_paths(a.path, b.path));
+    }
+    routes
+}
+
+/// Registered route of a request path, None for a path no route matches
+/// # Arguments
+/// * `path` - The path of the request
+pub fn route_of(path: &str) -> Option<&'static RouteRegistration> {
+    ROUTE_PATTERNS
+        .get_or_init(|| {
+            registered_routes()
+                .into_iter()
+                .flat_map(|((scope, version), routes)| {
+                    routes.into_iter().map(move |route| {
+                        let pattern = format!("{}{}", scope.prefix(version), route.path);
+                        (ResourceDef::new(pattern), route)
+                    })
+                })
+                .collect()
+        })
+        .iter()
+        .find(|(pattern, _)| pattern.is_match(path))
+        .map(|(_, route)| *route)
+}
+
+/// Whether a request path reaches a public route
+/// # Arguments
+/// * `path` - The path of the request
+pub fn is_public_path(path: &str) -> bool {
+    route_of(path).is_some_and(|route| route.auth == RouteAuth::Public)
+}
+
+// SUPPORT FUNCTIONS *******************************************************************************
+/// Order of two route paths: segment by segment, literal segments before parameters
+fn compare_paths(a: &str, b: &str) -> Ordering {
+    fn segments(path: &str) -> Vec<(bool, &str)> {
+        path.split('/')
+            .map(|segment| (segment.starts_with('{'), segment))
+            .collect()
+    }
+    segments(a).cmp(&segments(b))
+}
+
+// TESTS *******************************************************************************************
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Happy path: every route module is mounted and its requirements are found by path
+    #[test]
+    fn routes_found_by_path() {
+        let probe = route_of("/v1/health_check").unwrap();
+        assert_eq!(probe.auth, RouteAuth::Public);
+        assert_eq!(probe.rate_limit, None);
+        let exam = route_of("/v1/ecg_exam").unwrap();
+        assert_eq!(
+            (exam.scope, exam.auth),
+            (RouteScope::Hospital, RouteAuth::Hospital)
+        );
+        assert_eq!(exam.rate_limit, Some(RateScope::Ingest));
+        let keys = route_of("/v1/admin/hospitals/h-1/keys/rotate").unwrap();
+        assert_eq!(
+            (keys.scope, keys.auth),
+            (RouteScope::Admin, RouteAuth::Operator)
+        );
+        assert_eq!(
+            route_of("/internal/v1/metrics").unwrap().rate_limit,
+            Some(RateScope::Internal)
+        );
+        assert_eq!(
+            route_of("/internal/v1/queues/spool/items/ecg_exam/a/b")
+                .unwrap()
+                .path,
+            "/queues/{store}/items/{id:.*}"
+        );
+        let stats = route_of("/v1/stats/exams").unwrap();
+        assert_eq!(
+            (stats.scope, stats.auth),
+            (RouteScope::Hospital, RouteAuth::Hospital)
+        );
+        assert!(is_public_path("/v1/docs/index.html"));
+        assert!(!is_public_path("/v1/xray_exam"));
+    }
+
+    // Error handling: unknown paths match no route and are never public
+    #[test]
+    fn unknown_paths_unmatched() {
+        assert!(route_of("/v1/nope").is_none());
+        assert!(route_of("/internal/v2/metrics").is_none());
+        assert!(!is_public_path("/v1/health_check/extra"));
+    }
+
+    // Borderline: hospital credentials only on the hospital API, operators on the other scopes
+    #[test]
+    fn requirements_consistent() {
+        for ((scope, _), routes) in registered_routes() {
+            for route in routes {
+                let expected = match (scope, route.auth) {
+                    (RouteScope::Hospital, RouteAuth::Hospital) => true,
+                    (RouteScope::Admin | RouteScope::Internal, RouteAuth::Operator) => true,
+                    (RouteScope::Internal, RouteAuth::ConsoleLogin) => {
+                        route.path == "/console/login"
+                    }
+                    (_, RouteAuth::Public) => route.rate_limit.is_none(),
+                    _ => false,
+                };
+                assert!(expected, "{scope:?} {}", route.path);
+            }
+        }
+    }
+
+    // Borderline: literal segments sort before parameters
+    #[test]
+    fn literal_segments_first() {
+        let mut paths = vec![
+            "/hospitals/{hospital_id}/keys/{key_id}",
+            "/hospitals/{hospital_id}",
+            "/hospitals/{hospital_id}/keys/rotate",
+            "/hospitals",
+        ];
+        paths.sort_by(|a, b| compare_paths(a, b));
+        assert_eq!(
+            paths,
+            [
+                "/hospitals",
+                "/hospitals/{hospital_id}",
+                "/hospitals/{hospital_id}/keys/rotate",
+                "/hospitals/{hospital_id}/keys/{key_id}",
+            ]
+        );
+    }
+}
@@ -0,0 +1,48 @@
+// Imports *****************************************************************************************
+// External Crates
+use actix_web::{delete, web, HttpRequest, HttpResponse};
+use log::info;
+use sqlx::PgPool;
+
+// Internal Modules
+use crate::authentication::admin::authenticate_admin;
+use crate::authentication::rate_limit::RateScope;
+use crate::routes::registry::register_route;
+use crate::services::service_hospital_registry::{registry_error, revoke_key};
+use crate::utils::api_error::ApiError;
+
+// Route Handlers ***********************************************************************************
+// Revoke Key Handler
+#[delete("/hospitals/{hospital_id}/keys/{key_id}")]
+/// Revoke a key of a hospital - refused at once by this instance, within seconds by the others
+/// (registry notifications)
+/// # Returns
+/// * An HttpResponse 204, 404 if the hospital has no such key not yet revoked
+pub async fn revoke_key_handler(
+    req: HttpRequest,
+    path: web::Path<(String, String)>,
+    db_pool: web::Data<PgPool>,
+) -> Result<HttpResponse, ApiError> {
+    // Prep: Authenticate operator
+    authenticate_admin(&req).map_err(|e| ApiError::Unauthorized(e.to_string()))?;
+
+    let (hospital_id, key_id) = path.into_inner();
+    if !revoke_key(&db_pool, &hospital_id, &key_id)
+        .await
+        .map_err(registry_error)?
+    {
+        return Err(ApiError::NotFound("Key not found or already revoked"));
+    }
+    info!(target: "audit", "hospital_key_revoked hospital_id={hospital_id} key_id={key_id}");
+    Ok(HttpResponse::NoContent().finish())
+}
+
+// Route Registration ******************************************************************************
+register_route! {
+    scope: Admin, version: 1, path: "/hospitals/{hospital_id}/keys/{key_id}",
+    auth: Operator, rate_limit: Some(RateScope::Admin),
+    service: revoke_key_handler
+}
+
+// TESTS *******************************************************************************************
+// DOCUMENTATION: Pass function only - no unit tests for route handlers
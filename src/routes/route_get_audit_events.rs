@@ -0,0 +1,58 @@
+// Imports *****************************************************************************************
+// External Crates
+use actix_web::{get, web, HttpRequest, HttpResponse};
+use log::{error, info};
+use serde_json::json;
+use sqlx::PgPool;
+
+// Internal Modules
+use crate::audit::audit_trail::{query_audit_events, AuditQuery};
+use crate::authentication::admin::authenticate_admin;
+use crate::authentication::rate_limit::RateScope;
+use crate::routes::registry::register_route;
+use crate::utils::api_error::ApiError;
+use crate::utils::response_format::NegotiatedResponse;
+
+// Route Handlers ***********************************************************************************
+// Audit Events Handler
+#[get("/audit_events")]
+/// Events of the audit trail - authentication decisions and exams stored, published,
+/// dead-lettered or lost - newest first
+/// # Arguments
+/// * `query` - Optional `hospital_id`, `exam_id`, `action`, `session_id`, `from`/`to` (RFC 3339)
+///   and `limit`
+/// # Returns
+/// * An HttpResponse with the matching events
+pub async fn audit_events_handler(
+    req: HttpRequest,
+    query: web::Query<AuditQuery>,
+    db_pool: web::Data<PgPool>,
+) -> Result<HttpResponse, ApiError> {
+    // Prep: Authenticate operator
+    authenticate_admin(&req).map_err(|e| ApiError::Unauthorized(e.to_string()))?;
+
+    let events = query_audit_events(&db_pool, &query).await.map_err(|e| {
+        error!("Audit trail query failed: {e}");
+        ApiError::Internal
+    })?;
+    // Reading the trail is audited as well
+    info!(target: "audit", "audit_query hospital_id={} exam_id={} action={} count={}",
+        query.hospital_id.as_deref().unwrap_or("any"),
+        query.exam_id.as_deref().unwrap_or("any"),
+        query.action.map_or("any", |action| action.as_str()),
+        events.len());
+    Ok(HttpResponse::Ok().negotiated(&json!({
+        "count": events.len(),
+        "events": events,
+    })))
+}
+
+// Route Registration ******************************************************************************
+register_route! {
+    scope: Internal, version: 1, path: "/audit_events",
+    auth: Operator, rate_limit: Some(RateScope::Admin),
+    service: audit_events_handler
+}
+
+// TESTS *******************************************************************************************
+// DOCUMENTATION: Pass function only - no unit tests for route handlers
@@ -0,0 +1,41 @@
+// Imports *****************************************************************************************
+// External Crates
+use actix_web::{get, web, HttpRequest, HttpResponse};
+use std::sync::Arc;
+
+// Internal Modules
+use crate::authentication::admin::authenticate_admin;
+use crate::authentication::rate_limit::RateScope;
+use crate::routes::registry::register_route;
+use crate::services::service_autoscale_hint::autoscale_hint;
+use crate::services::service_ingest_queue::IngestQueue;
+use crate::utils::api_error::ApiError;
+use crate::utils::response_format::NegotiatedResponse;
+
+// Route Handlers ***********************************************************************************
+// Autoscale Hint Handler
+#[get("/autoscale_hint")]
+/// Saturation signals of this instance for the external autoscaler - ingest queue depth, requests
+/// in flight, publish backlog and memory headroom, with their overall saturation and the scaling
+/// it calls for, so instances are added on pipeline pressure and not only on CPU
+/// # Returns
+/// * An HttpResponse with the flat signals (`saturation`, `hint`, `queue_depth`, ...)
+pub async fn autoscale_hint_handler(
+    req: HttpRequest,
+    ingest_queue: web::Data<Arc<IngestQueue>>,
+) -> Result<HttpResponse, ApiError> {
+    // Prep: Authenticate operator (the autoscaler sends the admin_key header)
+    authenticate_admin(&req).map_err(|e| ApiError::Unauthorized(e.to_string()))?;
+
+    Ok(HttpResponse::Ok().negotiated(&autoscale_hint(&ingest_queue)))
+}
+
+// Route Registration ******************************************************************************
+register_route! {
+    scope: Internal, version: 1, path: "/autoscale_hint",
+    auth: Operator, rate_limit: Some(RateScope::Internal),
+    service: autoscale_hint_handler
+}
+
+// TESTS *******************************************************************************************
+// DOCUMENTATION: Pass function only - no unit tests for route handlers
@@ -0,0 +1,51 @@
+// Imports *****************************************************************************************
+// External Crates
+use actix_web::{get, web, HttpRequest, HttpResponse};
+use serde_json::json;
+use std::sync::Arc;
+
+// Internal Modules
+use crate::authentication::admin::authenticate_admin;
+use crate::authentication::rate_limit::RateScope;
+use crate::routes::registry::register_route;
+use crate::services::service_billing::BillingService;
+use crate::utils::api_error::ApiError;
+use crate::utils::response_format::NegotiatedResponse;
+
+// Route Handlers ***********************************************************************************
+// Billing Summary Handler
+#[get("/billing/{month}")]
+/// Monthly usage per hospital and exam type, as aggregated by this instance, rolled up per
+/// hospital group
+/// # Arguments
+/// * `month` - The month as `YYYY-MM`
+/// # Returns
+/// * An HttpResponse with exam counts and bytes stored per hospital (and group) and exam type
+pub async fn billing_summary_handler(
+    req: HttpRequest,
+    month: web::Path<String>,
+    billing: web::Data<Arc<BillingService>>,
+) -> Result<HttpResponse, ApiError> {
+    // Prep: Authenticate operator
+    authenticate_admin(&req).map_err(|e| ApiError::Unauthorized(e.to_string()))?;
+    let month = month.into_inner();
+    if chrono::NaiveDate::parse_from_str(&format!("{month}-01"), "%Y-%m-%d").is_err() {
+        return Err(ApiError::BadRequest("Month must be YYYY-MM".to_string()));
+    }
+
+    Ok(HttpResponse::Ok().negotiated(&json!({
+        "month": month,
+        "hospitals": billing.monthly_summary(&month),
+        "groups": billing.monthly_group_summary(&month),
+    })))
+}
+
+// Route Registration ******************************************************************************
+register_route! {
+    scope: Internal, version: 1, path: "/billing/{month}",
+    auth: Operator, rate_limit: Some(RateScope::Admin),
+    service: billing_summary_handler
+}
+
+// TESTS *******************************************************************************************
+// DOCUMENTATION: Pass function only - no unit tests for route handlers
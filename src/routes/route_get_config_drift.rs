@@ -0,0 +1,43 @@
+// Imports *****************************************************************************************
+// External Crates
+use actix_web::{get, HttpRequest, HttpResponse};
+use serde_json::json;
+use std::sync::atomic::Ordering;
+
+// Internal Modules
+use crate::authentication::admin::authenticate_admin;
+use crate::authentication::rate_limit::RateScope;
+use crate::routes::registry::register_route;
+use crate::utils::api_error::ApiError;
+use crate::utils::config_drift::{CONFIG_DRIFT, CONFIG_DRIFT_KEYS};
+use crate::utils::response_format::NegotiatedResponse;
+
+// Route Handlers ***********************************************************************************
+// Config Drift Handler
+#[get("/config_drift")]
+/// Expose the `config_drift` metric computed at startup
+/// # Returns
+/// * An HttpResponse with the number and names of drifted configuration keys
+pub async fn config_drift_handler(req: HttpRequest) -> Result<HttpResponse, ApiError> {
+    // Prep: Authenticate operator
+    authenticate_admin(&req).map_err(|e| ApiError::Unauthorized(e.to_string()))?;
+
+    let keys = CONFIG_DRIFT_KEYS
+        .lock()
+        .map(|keys| keys.clone())
+        .unwrap_or_default();
+    Ok(HttpResponse::Ok().negotiated(&json!({
+        "config_drift": CONFIG_DRIFT.load(Ordering::Relaxed),
+        "drifted_keys": keys,
+    })))
+}
+
+// Route Registration ******************************************************************************
+register_route! {
+    scope: Internal, version: 1, path: "/config_drift",
+    auth: Operator, rate_limit: Some(RateScope::Admin),
+    service: config_drift_handler
+}
+
+// TESTS *******************************************************************************************
+// DOCUMENTATION: Pass function only - no unit tests for route handlers
@@ -0,0 +1,39 @@
+// Imports *****************************************************************************************
+// External Crates
+use actix_web::{get, HttpMessage, HttpRequest, HttpResponse};
+use serde_json::json;
+
+// Internal Modules
+use crate::authentication::console_session::ConsoleSession;
+use crate::authentication::rate_limit::RateScope;
+use crate::routes::registry::register_route;
+use crate::utils::api_error::ApiError;
+use crate::utils::response_format::NegotiatedResponse;
+
+// Route Handlers ***********************************************************************************
+// Console Session Handler
+#[get("/console/session")]
+/// The current admin console session - lets a reloaded console get its CSRF token back
+/// # Returns
+/// * An HttpResponse with the session id, CSRF token and expiry, 401 without a live session
+pub async fn console_session_handler(req: HttpRequest) -> Result<HttpResponse, ApiError> {
+    let extensions = req.extensions();
+    let session = extensions
+        .get::<ConsoleSession>()
+        .ok_or_else(|| ApiError::Unauthorized("No console session".to_string()))?;
+    Ok(HttpResponse::Ok().negotiated(&json!({
+        "session_id": session.session_id,
+        "csrf_token": session.csrf_token,
+        "expires_at": session.expires_at,
+    })))
+}
+
+// Route Registration ******************************************************************************
+register_route! {
+    scope: Internal, version: 1, path: "/console/session",
+    auth: Operator, rate_limit: Some(RateScope::Admin),
+    service: console_session_handler
+}
+
+// TESTS *******************************************************************************************
+// DOCUMENTATION: Pass function only - no unit tests for route handlers
@@ -0,0 +1,39 @@
+// Imports *****************************************************************************************
+// External Crates
+use actix_web::{get, HttpRequest, HttpResponse};
+use serde_json::json;
+
+// Internal Modules
+use crate::authentication::admin::authenticate_admin;
+use crate::authentication::rate_limit::RateScope;
+use crate::models::models_deprecations::DEPRECATED_FIELDS;
+use crate::routes::registry::register_route;
+use crate::utils::api_error::ApiError;
+use crate::utils::deprecation_usage::deprecated_usage_report;
+use crate::utils::response_format::NegotiatedResponse;
+
+// Route Handlers ***********************************************************************************
+// Deprecations Handler
+#[get("/deprecations")]
+/// Deprecated payload fields and the hospitals still sending them, as seen by this instance
+/// # Returns
+/// * An HttpResponse with the deprecated fields and their usage per hospital
+pub async fn deprecations_handler(req: HttpRequest) -> Result<HttpResponse, ApiError> {
+    // Prep: Authenticate operator
+    authenticate_admin(&req).map_err(|e| ApiError::Unauthorized(e.to_string()))?;
+
+    Ok(HttpResponse::Ok().negotiated(&json!({
+        "fields": DEPRECATED_FIELDS,
+        "usage": deprecated_usage_report(),
+    })))
+}
+
+// Route Registration ******************************************************************************
+register_route! {
+    scope: Internal, version: 1, path: "/deprecations",
+    auth: Operator, rate_limit: Some(RateScope::Admin),
+    service: deprecations_handler
+}
+
+// TESTS *******************************************************************************************
+// DOCUMENTATION: Pass function only - no unit tests for route handlers
@@ -0,0 +1,328 @@
+// Imports *****************************************************************************************
+// External Crates
+use actix_web::body::to_bytes;
+use actix_web::{get, web, HttpRequest, HttpResponse, ResponseError};
+use actix_ws::{AggregatedMessage, AggregatedMessageStream, CloseCode, CloseReason, Session};
+use futures_util::StreamExt;
+use log::{error, info, warn};
+use sqlx::PgPool;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+// Internal Modules
+use crate::authentication::auth::{paused_now, AuthenticatedHospital};
+use crate::authentication::rate_limit::RateScope;
+use crate::config::Settings;
+use crate::models::models_ecg_stream::{StreamFrame, WindowAssembler, WindowOutcome};
+use crate::models::models_exams::PayloadEcg;
+use crate::routes::registry::register_route;
+use crate::routes::route_post_ecg_exam::submit_ecg_exam;
+use crate::services::service_idempotency::IdempotencyStore;
+use crate::services::service_ingest_queue::IngestQueue;
+use crate::services::service_rejection_digest::record_rejection;
+use crate::services::service_wasm_plugins::PluginRegistry;
+use crate::utils::api_error::ApiError;
+use crate::utils::get_headers::idempotency_header;
+use crate::utils::request_id::request_id;
+
+// Constants ***************************************************************************************
+/// Exam type key used for plugins and rejection digests
+const EXAM_TYPE: &str = "ecg_exam";
+/// Session closed when the device sends nothing for this long
+const STREAM_IDLE_TIMEOUT: Duration = Duration::from_secs(30);
+/// Largest frame (or message of continuation frames) accepted from a device
+const STREAM_MAX_FRAME_BYTES: usize = 1_048_576;
+/// Submissions of a window refused by a full ingest queue before the refusal is sent
+const BACKPRESSURE_ATTEMPTS: u32 = 3;
+/// Longest wait before a refused window is submitted again
+const BACKPRESSURE_MAX_WAIT: Duration = Duration::from_secs(5);
+
+// Structs *****************************************************************************************
+/// What a session needs to submit its windows to the ECG pipeline
+struct StreamContext {
+    req: HttpRequest,
+    hospital: AuthenticatedHospital,
+    pool: web::Data<PgPool>,
+    ingest_queue: web::Data<Arc<IngestQueue>>,
+    plugins: web::Data<Arc<PluginRegistry>>,
+    idempotency: web::Data<Arc<IdempotencyStore>>,
+    settings: web::Data<Arc<Settings>>,
+}
+
+// Route Handlers ***********************************************************************************
+// ECG Stream Handler
+#[utoipa::path(
+    get,
+    path = "/v1/ecg_stream",
+    tag = "exams",
+    params(
+        ("Upgrade" = String, Header, description = "`websocket` - the device then sends an `open` \
+            frame and numbered `samples` chunks, and gets a `WindowOutcome` per complete window"),
+        ("on_behalf_of" = Option<String>, Header, description = "Clinic a hospital group submits \
+            for"),
+    ),
+    responses(
+        (status = 101, description = "WebSocket session opened"),
+        ApiError
+    ),
+    security(("hospital_id" = [], "hospital_key" = []), ("bearer_token" = []))
+)]
+#[get("/ecg_stream")]
+/// Stream ECG samples of a bedside monitor over a WebSocket: the device sends an `open` frame,
+/// then numbered `samples` chunks of the 12 leads; every complete window (sampling rate times
+/// duration of the `open` frame) goes through the pipeline of `POST /v1/ecg_exam` - quota,
+/// plugin, validation, queueing - and its outcome is sent back as a `WindowOutcome`
+/// - Windows are submitted one at a time and the socket is not read meanwhile, so a full ingest
+///   queue slows the device down (TCP backpressure) before a window is refused
+/// - Sessions are closed after ECG_STREAM_MAX_SESSION_S (default 1 hour) or STREAM_IDLE_TIMEOUT
+///   without frame; samples of an incomplete window are dropped
+/// # Returns
+/// * The WebSocket handshake response, 403 if the hospital is paused or may not submit ECG exams
+///   (a hospital paused later gets its next windows refused with 403), 400 with an
+///   'Idempotency-Key' (windows are keyed by their content) or without a WebSocket upgrade
+pub async fn ecg_stream_handler(
+    req: HttpRequest,
+    body: web::Payload,
+    hospital: AuthenticatedHospital,
+    pool: web::Data<PgPool>,
+    ingest_queue: web::Data<Arc<IngestQueue>>,
+    plugins: web::Data<Arc<PluginRegistry>>,
+    idempotency: web::Data<Arc<IdempotencyStore>>,
+    settings: web::Data<Arc<Settings>>,
+) -> Result<HttpResponse, ApiError> {
+    // Prep: The hospital was authenticated by the middleware of the scope
+    if !hospital.allows_exam_type(EXAM_TYPE) {
+        return Err(ApiError::Forbidden(format!(
+            "Exam type {EXAM_TYPE} is not allowed for this hospital"
+        )));
+    }
+    if idempotency_header(&req).is_some() {
+        return Err(ApiError::BadRequest(
+            "Idempotency-Key is not accepted on the ECG stream - windows are keyed by content"
+                .to_string(),
+        ));
+    }
+
+    let (response, session, stream) =
+        actix_ws::handle(&req, body).map_err(|e| ApiError::BadRequest(e.to_string()))?;
+    let stream = stream
+        .max_frame_size(STREAM_MAX_FRAME_BYTES)
+        .aggregate_continuations()
+        .max_continuation_size(STREAM_MAX_FRAME_BYTES);
+    info!(target: "audit", "ecg_stream_opened hospital_id={} request_id={}", hospital.hospital_id, request_id(&req));
+    let context = StreamContext {
+        req,
+        hospital,
+        pool,
+        ingest_queue,
+        plugins,
+        idempotency,
+        settings,
+    };
+    actix_web::rt::spawn(run_session(session, stream, context));
+    Ok(response)
+}
+
+// SUPPORT FUNCTIONS *******************************************************************************
+/// Read the frames of a session until it ends, submitting every complete window
+/// # Arguments
+/// * `session` - The sending half of the WebSocket
+/// * `stream` - The frames of the device
+/// * `context` - The hospital and the pipeline of the session
+async fn run_session(
+    mut session: Session,
+    mut stream: AggregatedMessageStream,
+    context: StreamContext,
+) {
+    let deadline =
+        Instant::now() + Duration::from_secs(context.settings.server.ecg_stream_max_session_s);
+    let mut assembler: Option<WindowAssembler> = None;
+    let mut windows: u64 = 0;
+
+    // STEP 1: Read the frames until the device closes, fails or times out
+    let close = loop {
+        let wait = deadline
+            .saturating_duration_since(Instant::now())
+            .min(STREAM_IDLE_TIMEOUT);
+        let message = match tokio::time::timeout(wait, stream.next()).await {
+            Err(_) if Instant::now() >= deadline => {
+                break closing(CloseCode::Policy, "Maximum session duration reached")
+            }
+            Err(_) => break closing(CloseCode::Policy, "Idle timeout"),
+            Ok(None) => break None,
+            Ok(Some(Err(e))) => break closing(CloseCode::Protocol, &e.to_string()),
+            Ok(Some(Ok(message))) => message,
+        };
+        let text = match message {
+            AggregatedMessage::Text(text) => text,
+            AggregatedMessage::Binary(_) => {
+                break closing(CloseCode::Unsupported, "Frames are JSON text messages")
+            }
+            AggregatedMessage::Ping(bytes) => {
+                if session.pong(&bytes).await.is_err() {
+                    break None;
+                }
+                continue;
+            }
+            AggregatedMessage::Pong(_) => continue,
+            AggregatedMessage::Close(reason) => break reason,
+        };
+
+        // STEP 2: Open the session, or cut the windows completed by the chunk
+        let frame = match serde_json::from_str::<StreamFrame>(&text) {
+            Ok(frame) => frame,
+            Err(e) => break closing(CloseCode::Invalid, &format!("Invalid frame: {e}")),
+        };
+        let completed = match assembler.as_mut() {
+            None => match WindowAssembler::open(frame) {
+                Ok(opened) => {
+                    assembler = Some(opened);
+                    continue;
+                }
+                Err(e) => break closing(CloseCode::Invalid, &e),
+            },
+            Some(assembler) => match assembler.push(frame) {
+                Ok(completed) => completed,
+                Err(e) => break closing(CloseCode::Invalid, &e),
+            },
+        };
+
+        // STEP 3: Submit the windows one at a time and send their outcome
+        let mut sent = true;
+        for payload in completed {
+            let outcome = submit_window(&context, payload, windows).await;
+            windows += 1;
+            let Ok(outcome) = serde_json::to_string(&outcome) else {
+                continue;
+            };
+            if session.text(outcome).await.is_err() {
+                sent = false;
+                break;
+            }
+        }
+        if !sent {
+            break None;
+        }
+    };
+
+    // STEP 4: Close the session - the samples of an incomplete window are dropped
+    let dropped = assembler
+        .as_ref()
+        .map_or(0, WindowAssembler::pending_samples);
+    let reason = close
+        .as_ref()
+        .and_then(|close| close.description.clone())
+        .unwrap_or_else(|| "closed".to_string());
+    info!(target: "audit", "ecg_stream_closed hospital_id={} windows={windows} dropped_samples={dropped} reason={reason}",
+        context.hospital.hospital_id);
+    let _ = session.close(close).await;
+}
+
+/// Submit a window to the ECG pipeline - retried while the ingest queue is full, so the device is
+/// slowed down before the window is refused
+/// # Arguments
+/// * `context` - The hospital and the pipeline of the session
+/// * `payload` - The window, as an ECG exam
+/// * `window` - The index of the window in the session
+/// # Returns
+/// * The outcome of the window, with the status and body `POST /v1/ecg_exam` would have returned
+async fn submit_window(context: &StreamContext, payload: PayloadEcg, window: u64) -> WindowOutcome {
+    // STEP 1: A hospital paused while its stream is open gets its next windows refused - every
+    // window accepted by the ECG pipeline counts against the monthly quotas, like a submitted exam
+    let response = match check_still_active(context).await {
+        Err(e) => {
+            record_rejection(
+                &context.hospital.hospital_id,
+                EXAM_TYPE,
+                e.reason(),
+                &request_id(&context.req),
+            );
+            e.error_response()
+        }
+        Ok(()) => submit_with_backpressure(context, payload).await,
+    };
+
+    // STEP 2: The response, as the outcome of the window
+    let status = response.status().as_u16();
+    let body = to_bytes(response.into_body())
+        .await
+        .ok()
+        .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+        .unwrap_or(serde_json::Value::Null);
+    WindowOutcome {
+        window,
+        status,
+        body,
+    }
+}
+
+/// Check that the hospital of a session was not paused since the session opened
+/// - The registry is read for every window; while Postgres cannot be reached the session keeps the
+///   state it was opened with (not paused)
+/// # Errors
+/// * Returns `ApiError::Forbidden` once the hospital, or its group, is paused
+async fn check_still_active(context: &StreamContext) -> Result<(), ApiError> {
+    match paused_now(&context.hospital, &context.pool).await {
+        Ok(paused) => AuthenticatedHospital {
+            paused,
+            ..context.hospital.clone()
+        }
+        .check_not_paused(),
+        Err(e) => {
+            warn!(
+                "Pause of {} not checked for the ECG stream window: {e}",
+                context.hospital.hospital_id
+            );
+            Ok(())
+        }
+    }
+}
+
+/// Submit a window, waiting for room in the ingest queue up to BACKPRESSURE_ATTEMPTS times
+async fn submit_with_backpressure(context: &StreamContext, payload: PayloadEcg) -> HttpResponse {
+    let mut attempt = 1;
+    loop {
+        let submitted = submit_ecg_exam(
+            &context.req,
+            context.hospital.clone(),
+            payload.clone(),
+            &context.ingest_queue,
+            &context.plugins,
+            &context.idempotency,
+            &context.pool,
+            &context.settings.ingest,
+        )
+        .await;
+        match submitted {
+            Ok(response) => return response,
+            Err(ApiError::RateLimited { retry_after_s, .. }) if attempt < BACKPRESSURE_ATTEMPTS => {
+                warn!("ECG stream window refused by a full queue - attempt {attempt}");
+                attempt += 1;
+                let wait = Duration::from_secs(retry_after_s).min(BACKPRESSURE_MAX_WAIT);
+                tokio::time::sleep(wait).await;
+            }
+            Err(e) => {
+                error!("ECG stream window refused: {e}");
+                return e.error_response();
+            }
+        }
+    }
+}
+
+/// Close reason of a session ended by the gateway
+fn closing(code: CloseCode, description: &str) -> Option<CloseReason> {
+    Some(CloseReason {
+        code,
+        description: Some(description.to_string()),
+    })
+}
+
+// Route Registration ******************************************************************************
+register_route! {
+    scope: Hospital, version: 1, path: "/ecg_stream",
+    auth: Hospital, rate_limit: Some(RateScope::Ingest),
+    service: ecg_stream_handler
+}
+
+// TESTS *******************************************************************************************
+// DOCUMENTATION: Pass function only - no unit tests for route handlers
@@ -0,0 +1,168 @@
+// Imports *****************************************************************************************
+// External Crates
+use actix_web::http::header::{self, ContentEncoding};
+use actix_web::{get, web, HttpRequest, HttpResponse};
+use log::{error, info};
+use serde::Deserialize;
+use std::sync::Arc;
+
+// Internal Modules
+use crate::authentication::admin::authenticate_admin;
+use crate::authentication::rate_limit::RateScope;
+use crate::config::Settings;
+use crate::routes::registry::register_route;
+use crate::services::service_exam_export::{
+    exam_parquet_size, fetch_exam_parquet, parquet_to_json, resolve_range, stream_exam_parquet,
+};
+use crate::storage::exam_storage::{ExamStorage, ReadOnlyStorage};
+use crate::utils::api_error::ApiError;
+use crate::utils::response_format::NegotiatedResponse;
+
+// Query Parameters ********************************************************************************
+/// Query parameters of the exam export endpoint
+/// # Arguments
+/// * `format` - `json` (default) for the canonical payload or `parquet` for the stored file
+/// * `downsample` - Keep only every n-th lead sample in the JSON export (default 1)
+#[derive(Debug, Deserialize)]
+pub struct ExportQuery {
+    format: Option<String>,
+    downsample: Option<usize>,
+}
+
+// Route Handlers ***********************************************************************************
+// Exam Export Handler
+#[get("/exam_export/{exam_id:.*}")]
+/// Export a single stored exam for support and debugging
+/// # Arguments
+/// * `exam_id` - The exam id (`{exam_type}/{hospital_id}/{patient_id}/{uuid}`)
+/// * `query` - The export format and optional lead downsampling factor
+/// * `settings` - The settings of the service, naming the exam bucket
+/// # Returns
+/// * An HttpResponse with the canonical JSON payload (compressed per Accept-Encoding) or the raw
+///   Parquet file streamed from storage, honouring single `Range` requests
+pub async fn exam_export_handler(
+    req: HttpRequest,
+    exam_id: web::Path<String>,
+    query: web::Query<ExportQuery>,
+    read_storage: web::Data<ReadOnlyStorage>,
+    settings: web::Data<Arc<Settings>>,
+) -> Result<HttpResponse, ApiError> {
+    let exam_id = exam_id.into_inner();
+    let format = query.format.clone().unwrap_or_else(|| "json".to_string());
+    let client_ip = req
+        .connection_info()
+        .realip_remote_addr()
+        .unwrap_or("unknown")
+        .to_string();
+
+    // Prep: Authenticate operator - every attempt to read waveform data is audited
+    if let Err(e) = authenticate_admin(&req) {
+        info!(target: "audit", "exam_export denied exam_id={exam_id} client_ip={client_ip} reason={e}");
+        return Err(ApiError::Unauthorized(e.to_string()));
+    }
+    if (format != "json" && format != "parquet") || query.downsample == Some(0) {
+        return Err(ApiError::BadRequest(
+            "Format must be json or parquet, downsample at least 1".to_string(),
+        ));
+    }
+
+    // STEP 1: Stream the raw file straight from storage
+    let bucket = &settings.storage.bucket_name;
+    if format == "parquet" {
+        return stream_parquet_export(&req, &exam_id, bucket, &read_storage.0, &client_ip).await;
+    }
+
+    // STEP 2: Fetch the stored Parquet - the JSON conversion needs the whole file
+    let parquet = match fetch_exam_parquet(&exam_id, bucket, read_storage.0.as_ref()).await {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            error!("Error while exporting exam {exam_id}: {e}");
+            info!(target: "audit", "exam_export failed exam_id={exam_id} format={format} client_ip={client_ip}");
+            return Err(ApiError::NotFound("Exam Not Found"));
+        }
+    };
+
+    // STEP 3: Convert it back to JSON
+    info!(target: "audit", "exam_export granted exam_id={exam_id} format={format} downsample={:?} client_ip={client_ip}", query.downsample);
+    match parquet_to_json(parquet, query.downsample.unwrap_or(1)) {
+        Ok(exam) => Ok(HttpResponse::Ok().negotiated(&exam)),
+        Err(e) => {
+            error!("Error while converting exam {exam_id}: {e}");
+            Err(ApiError::Internal)
+        }
+    }
+}
+
+// Support Functions *******************************************************************************
+/// Stream the stored Parquet of an exam, or the byte range asked for in the Range header
+/// # Arguments
+/// * `req` - The request, for the Range header
+/// * `exam_id` - The exam id
+/// * `bucket` - The exam bucket
+/// * `storage` - The exam storage backend
+/// * `client_ip` - The client address, for the audit log
+/// # Returns
+/// * 200 with the whole file, 206 with the range, 404 or 416
+async fn stream_parquet_export(
+    req: &HttpRequest,
+    exam_id: &str,
+    bucket: &str,
+    storage: &Arc<dyn ExamStorage>,
+    client_ip: &str,
+) -> Result<HttpResponse, ApiError> {
+    let size = match exam_parquet_size(exam_id, bucket, storage.as_ref()).await {
+        Ok(size) => size,
+        Err(e) => {
+            error!("Error while exporting exam {exam_id}: {e}");
+            info!(target: "audit", "exam_export failed exam_id={exam_id} format=parquet client_ip={client_ip}");
+            return Err(ApiError::NotFound("Exam Not Found"));
+        }
+    };
+    let range = match req
+        .headers()
+        .get(header::RANGE)
+        .and_then(|v| v.to_str().ok())
+        .map(|v| resolve_range(v, size))
+        .transpose()
+    {
+        Ok(range) => range.flatten(),
+        Err(_) => {
+            return Ok(HttpResponse::RangeNotSatisfiable()
+                .insert_header((header::CONTENT_RANGE, format!("bytes */{size}")))
+                .finish());
+        }
+    };
+    let body = match stream_exam_parquet(exam_id, bucket, storage.clone(), range) {
+        Ok(body) => body,
+        Err(e) => {
+            error!("Error while exporting exam {exam_id}: {e}");
+            return Err(ApiError::Internal);
+        }
+    };
+
+    info!(target: "audit", "exam_export granted exam_id={exam_id} format=parquet range={range:?} client_ip={client_ip}");
+    let mut response = match range {
+        Some((from, to)) => {
+            let mut response = HttpResponse::PartialContent();
+            response.insert_header((header::CONTENT_RANGE, format!("bytes {from}-{to}/{size}")));
+            response
+        }
+        None => HttpResponse::Ok(),
+    };
+    // Parquet is already compressed - never re-encode it
+    Ok(response
+        .content_type("application/vnd.apache.parquet")
+        .insert_header((header::ACCEPT_RANGES, "bytes"))
+        .insert_header(ContentEncoding::Identity)
+        .streaming(body))
+}
+
+// Route Registration ******************************************************************************
+register_route! {
+    scope: Internal, version: 1, path: "/exam_export/{exam_id:.*}",
+    auth: Operator, rate_limit: Some(RateScope::Admin),
+    service: exam_export_handler
+}
+
+// TESTS *******************************************************************************************
+// DOCUMENTATION: Pass function only - no unit tests for route handlers
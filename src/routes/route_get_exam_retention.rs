@@ -0,0 +1,35 @@
+// Imports *****************************************************************************************
+// External Crates
+use actix_web::{get, HttpRequest, HttpResponse};
+
+// Internal Modules
+use crate::authentication::admin::authenticate_admin;
+use crate::authentication::rate_limit::RateScope;
+use crate::routes::registry::register_route;
+use crate::services::service_exam_retention::retention_stats;
+use crate::utils::api_error::ApiError;
+use crate::utils::response_format::NegotiatedResponse;
+
+// Route Handlers ***********************************************************************************
+// Exam Retention Handler
+#[get("/exam_retention")]
+/// Expose the metrics of the enforcement of the exam retention policies
+/// # Returns
+/// * An HttpResponse with runs, scanned, expired, held, deleted objects/bytes and failures per
+///   policy
+pub async fn exam_retention_handler(req: HttpRequest) -> Result<HttpResponse, ApiError> {
+    // Prep: Authenticate operator
+    authenticate_admin(&req).map_err(|e| ApiError::Unauthorized(e.to_string()))?;
+
+    Ok(HttpResponse::Ok().negotiated(&retention_stats()))
+}
+
+// Route Registration ******************************************************************************
+register_route! {
+    scope: Internal, version: 1, path: "/exam_retention",
+    auth: Operator, rate_limit: Some(RateScope::Admin),
+    service: exam_retention_handler
+}
+
+// TESTS *******************************************************************************************
+// DOCUMENTATION: Pass function only - no unit tests for route handlers
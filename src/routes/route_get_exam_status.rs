@@ -0,0 +1,59 @@
+// Imports *****************************************************************************************
+// External Crates
+use actix_web::{get, web, HttpResponse};
+use std::sync::Arc;
+
+// Internal Modules
+use crate::authentication::auth::AuthenticatedHospital;
+use crate::authentication::rate_limit::RateScope;
+use crate::routes::registry::register_route;
+use crate::services::service_ingest_queue::{ExamStatus, IngestQueue};
+use crate::utils::api_error::ApiError;
+use crate::utils::response_format::NegotiatedResponse;
+
+// Route Handlers ***********************************************************************************
+// Exam Status Handler
+#[utoipa::path(
+    get,
+    path = "/v1/exam_status/{exam_id}",
+    tag = "exams",
+    params((
+        "exam_id" = String,
+        Path,
+        description = "The exam id returned when the exam was accepted"
+    )),
+    responses(
+        (status = 200, description = "State of the exam", body = ExamStatus),
+        ApiError
+    ),
+    security(("hospital_id" = [], "hospital_key" = []), ("bearer_token" = []))
+)]
+#[get("/exam_status/{exam_id:.*}")]
+/// Processing status of an exam accepted with 202 (or provisionally accepted with 201), for the
+/// hospital that sent it
+/// # Arguments
+/// * `exam_id` - The exam id returned when the exam was accepted
+/// # Returns
+/// * An HttpResponse with the state of the exam (queued, processing, committed or failed), or 404
+///   if the exam is unknown, belongs to another hospital or is no longer tracked
+pub async fn exam_status_handler(
+    exam_id: web::Path<String>,
+    hospital: AuthenticatedHospital,
+    ingest_queue: web::Data<Arc<IngestQueue>>,
+) -> Result<HttpResponse, ApiError> {
+    // Prep: The hospital was authenticated by the middleware of the scope
+    match ingest_queue.status(&exam_id, &hospital.hospital_id).await {
+        Some(status) => Ok(HttpResponse::Ok().negotiated(&status)),
+        None => Err(ApiError::NotFound("Unknown exam")),
+    }
+}
+
+// Route Registration ******************************************************************************
+register_route! {
+    scope: Hospital, version: 1, path: "/exam_status/{exam_id:.*}",
+    auth: Hospital, rate_limit: Some(RateScope::Ingest),
+    service: exam_status_handler
+}
+
+// TESTS *******************************************************************************************
+// DOCUMENTATION: Pass function only - no unit tests for route handlers
@@ -0,0 +1,34 @@
+// Imports *****************************************************************************************
+// External Crates
+use actix_web::{get, HttpRequest, HttpResponse};
+
+// Internal Modules
+use crate::authentication::admin::authenticate_admin;
+use crate::authentication::rate_limit::RateScope;
+use crate::routes::registry::register_route;
+use crate::utils::api_error::ApiError;
+use crate::utils::external_call::call_stats;
+use crate::utils::response_format::NegotiatedResponse;
+
+// Route Handlers ***********************************************************************************
+// External Calls Handler
+#[get("/external_calls")]
+/// Expose the metrics of the calls made to GCS, Pub/Sub and Postgres
+/// # Returns
+/// * An HttpResponse with call, attempt, failure, timeout and latency totals per operation
+pub async fn external_calls_handler(req: HttpRequest) -> Result<HttpResponse, ApiError> {
+    // Prep: Authenticate operator
+    authenticate_admin(&req).map_err(|e| ApiError::Unauthorized(e.to_string()))?;
+
+    Ok(HttpResponse::Ok().negotiated(&call_stats()))
+}
+
+// Route Registration ******************************************************************************
+register_route! {
+    scope: Internal, version: 1, path: "/external_calls",
+    auth: Operator, rate_limit: Some(RateScope::Admin),
+    service: external_calls_handler
+}
+
+// TESTS *******************************************************************************************
+// DOCUMENTATION: Pass function only - no unit tests for route handlers
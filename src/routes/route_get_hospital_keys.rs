@@ -0,0 +1,47 @@
+// Imports *****************************************************************************************
+// External Crates
+use actix_web::{get, web, HttpRequest, HttpResponse};
+use serde_json::json;
+use sqlx::PgPool;
+
+// Internal Modules
+use crate::authentication::admin::authenticate_admin;
+use crate::authentication::rate_limit::RateScope;
+use crate::routes::registry::register_route;
+use crate::services::service_hospital_registry::{list_keys, registry_error};
+use crate::utils::api_error::ApiError;
+use crate::utils::response_format::NegotiatedResponse;
+
+// Route Handlers ***********************************************************************************
+// Hospital Keys Handler
+#[get("/hospitals/{hospital_id}/keys")]
+/// Keys of a hospital with their status, newest first - never the keys nor their hashes
+/// # Returns
+/// * An HttpResponse with the keys, 404 if the hospital is not registered
+pub async fn hospital_keys_handler(
+    req: HttpRequest,
+    path: web::Path<String>,
+    db_pool: web::Data<PgPool>,
+) -> Result<HttpResponse, ApiError> {
+    // Prep: Authenticate operator
+    authenticate_admin(&req).map_err(|e| ApiError::Unauthorized(e.to_string()))?;
+
+    let keys = list_keys(&db_pool, &path)
+        .await
+        .map_err(registry_error)?
+        .ok_or(ApiError::NotFound("Hospital not registered"))?;
+    Ok(HttpResponse::Ok().negotiated(&json!({
+        "hospital_id": path.as_str(),
+        "keys": keys,
+    })))
+}
+
+// Route Registration ******************************************************************************
+register_route! {
+    scope: Admin, version: 1, path: "/hospitals/{hospital_id}/keys",
+    auth: Operator, rate_limit: Some(RateScope::Admin),
+    service: hospital_keys_handler
+}
+
+// TESTS *******************************************************************************************
+// DOCUMENTATION: Pass function only - no unit tests for route handlers
@@ -0,0 +1,36 @@
+// Imports *****************************************************************************************
+// External Crates
+use actix_web::{get, HttpRequest, HttpResponse};
+
+// Internal Modules
+use crate::authentication::admin::authenticate_admin;
+use crate::authentication::rate_limit::RateScope;
+use crate::routes::registry::register_route;
+use crate::telemetry::metrics::{render_metrics, PROMETHEUS_TEXT};
+use crate::utils::api_error::ApiError;
+
+// Route Handlers ***********************************************************************************
+// Metrics Handler
+#[get("/metrics")]
+/// Expose the metrics of this instance to Prometheus: requests per route and status, exams per
+/// hospital, rejections, stage durations, external calls and authentication failures
+/// # Returns
+/// * An HttpResponse with the metrics in the Prometheus text exposition format
+pub async fn metrics_handler(req: HttpRequest) -> Result<HttpResponse, ApiError> {
+    // Prep: Authenticate operator (the scraper sends the admin_key header)
+    authenticate_admin(&req).map_err(|e| ApiError::Unauthorized(e.to_string()))?;
+
+    Ok(HttpResponse::Ok()
+        .content_type(PROMETHEUS_TEXT)
+        .body(render_metrics()))
+}
+
+// Route Registration ******************************************************************************
+register_route! {
+    scope: Internal, version: 1, path: "/metrics",
+    auth: Operator, rate_limit: Some(RateScope::Internal),
+    service: metrics_handler
+}
+
+// TESTS *******************************************************************************************
+// DOCUMENTATION: Pass function only - no unit tests for route handlers
@@ -0,0 +1,29 @@
+// Imports *****************************************************************************************
+// External Crates
+use utoipa::OpenApi;
+use utoipa_swagger_ui::SwaggerUi;
+
+// Internal Modules
+use crate::routes::registry::register_route;
+use crate::utils::openapi::ApiDoc;
+
+// Route Handlers ***********************************************************************************
+// OpenAPI Documentation Service
+/// OpenAPI specification of the hospital API and its interactive documentation - public, so
+/// integrating hospitals can read the contract before they get their keys
+/// # Returns
+/// * The service answering `GET /v1/openapi.json` and the Swagger UI at `/v1/docs/`
+pub fn openapi_service() -> SwaggerUi {
+    SwaggerUi::new("/v1/docs/{_:.*}").url("/v1/openapi.json", ApiDoc::openapi())
+}
+
+// Route Registration ******************************************************************************
+// At the root, before the v1 scope which would otherwise authenticate it
+register_route! {
+    scope: Root, version: 1, path: "/v1/docs/{_:.*}",
+    auth: Public, rate_limit: None,
+    service: openapi_service()
+}
+
+// TESTS *******************************************************************************************
+// DOCUMENTATION: Pass function only - no unit tests for route handlers
@@ -0,0 +1,73 @@
+// Imports *****************************************************************************************
+// External Crates
+use actix_web::{get, web, HttpRequest, HttpResponse};
+use log::error;
+
+// Internal Modules
+use crate::authentication::admin::authenticate_admin;
+use crate::authentication::rate_limit::RateScope;
+use crate::routes::registry::register_route;
+use crate::services::service_parquet_schemas::{parquet_schemas, ParquetSchema};
+use crate::utils::api_error::ApiError;
+use crate::utils::response_format::NegotiatedResponse;
+
+// Route Handlers ***********************************************************************************
+// Parquet Schemas Handler
+#[get("/parquet_schemas")]
+/// Describe the Parquet files currently written, per exam type: columns, types, metadata keys and
+/// schema version
+/// # Returns
+/// * An HttpResponse with the schema of every exam type
+pub async fn parquet_schemas_handler(req: HttpRequest) -> Result<HttpResponse, ApiError> {
+    // Prep: Authenticate operator
+    authenticate_admin(&req).map_err(|e| ApiError::Unauthorized(e.to_string()))?;
+
+    Ok(HttpResponse::Ok().negotiated(&current_schemas()?))
+}
+
+// Parquet Schema Handler
+#[get("/parquet_schemas/{exam_type}")]
+/// Describe the Parquet files currently written for one exam type
+/// # Arguments
+/// * `path` - The exam type key, e.g. `ecg_exam`
+/// # Returns
+/// * An HttpResponse with the schema, or 404 if the exam type is not stored as Parquet
+pub async fn parquet_schema_handler(
+    req: HttpRequest,
+    path: web::Path<String>,
+) -> Result<HttpResponse, ApiError> {
+    // Prep: Authenticate operator
+    authenticate_admin(&req).map_err(|e| ApiError::Unauthorized(e.to_string()))?;
+
+    match current_schemas()?
+        .into_iter()
+        .find(|schema| schema.exam_type == path.as_str())
+    {
+        Some(schema) => Ok(HttpResponse::Ok().negotiated(&schema)),
+        None => Err(ApiError::NotFound("Exam type Not Found")),
+    }
+}
+
+// SUPPORT FUNCTIONS *******************************************************************************
+/// Schemas of the writers, 500 if a sample exam cannot be written
+fn current_schemas() -> Result<Vec<ParquetSchema>, ApiError> {
+    parquet_schemas().map_err(|e| {
+        error!("Parquet schemas not derived: {e}");
+        ApiError::Internal
+    })
+}
+
+// Route Registration ******************************************************************************
+register_route! {
+    scope: Internal, version: 1, path: "/parquet_schemas",
+    auth: Operator, rate_limit: Some(RateScope::Admin),
+    service: parquet_schemas_handler
+}
+register_route! {
+    scope: Internal, version: 1, path: "/parquet_schemas/{exam_type}",
+    auth: Operator, rate_limit: Some(RateScope::Admin),
+    service: parquet_schema_handler
+}
+
+// TESTS *******************************************************************************************
+// DOCUMENTATION: Pass function only - no unit tests for route handlers
@@ -0,0 +1,95 @@
+// Imports *****************************************************************************************
+// External Crates
+use actix_web::{get, web, HttpRequest, HttpResponse};
+use log::error;
+use std::sync::Arc;
+
+// Internal Modules
+use crate::authentication::admin::authenticate_admin;
+use crate::authentication::rate_limit::RateScope;
+use crate::routes::registry::register_route;
+use crate::services::service_queue_admin::{ItemFilter, QueueAdmin, QueueStore};
+use crate::utils::api_error::ApiError;
+use crate::utils::response_format::NegotiatedResponse;
+
+// Route Handlers ***********************************************************************************
+// Queue Summary Handler
+#[get("/queues")]
+/// Count the exams held out of the normal pipeline: dead letters, spool and outbox
+/// # Returns
+/// * An HttpResponse with the number of exams held by each store of this instance
+pub async fn queues_handler(
+    req: HttpRequest,
+    queue_admin: web::Data<Arc<QueueAdmin>>,
+) -> Result<HttpResponse, ApiError> {
+    // Prep: Authenticate operator
+    authenticate_admin(&req).map_err(|e| ApiError::Unauthorized(e.to_string()))?;
+
+    Ok(HttpResponse::Ok().negotiated(&queue_admin.summary().await))
+}
+
+// Queue Items Handler
+#[get("/queues/{store}/items")]
+/// List the items of a store, oldest first - filtered by exam_type, hospital_id, stage, reason,
+/// before and limit
+/// # Returns
+/// * An HttpResponse with the items and the metadata of their payload, never its values
+pub async fn queue_items_handler(
+    req: HttpRequest,
+    store: web::Path<QueueStore>,
+    filter: web::Query<ItemFilter>,
+    queue_admin: web::Data<Arc<QueueAdmin>>,
+) -> Result<HttpResponse, ApiError> {
+    // Prep: Authenticate operator
+    authenticate_admin(&req).map_err(|e| ApiError::Unauthorized(e.to_string()))?;
+
+    let items = queue_admin.list(*store, &filter).await.map_err(|e| {
+        error!("Listing of the {} store failed: {e}", store.as_str());
+        ApiError::StorageFailure
+    })?;
+    Ok(HttpResponse::Ok().negotiated(&items))
+}
+
+// Queue Item Handler
+#[get("/queues/{store}/items/{id:.*}")]
+/// Peek at one item of a store
+/// # Returns
+/// * An HttpResponse with the item, or 404 if the store does not hold it
+pub async fn queue_item_handler(
+    req: HttpRequest,
+    path: web::Path<(QueueStore, String)>,
+    queue_admin: web::Data<Arc<QueueAdmin>>,
+) -> Result<HttpResponse, ApiError> {
+    // Prep: Authenticate operator
+    authenticate_admin(&req).map_err(|e| ApiError::Unauthorized(e.to_string()))?;
+    let (store, id) = path.into_inner();
+
+    match queue_admin.peek(store, &id).await {
+        Ok(Some(item)) => Ok(HttpResponse::Ok().negotiated(&item)),
+        Ok(None) => Err(ApiError::NotFound("Item not found in the store")),
+        Err(e) => {
+            error!("Read of {id} in the {} store failed: {e}", store.as_str());
+            Err(ApiError::StorageFailure)
+        }
+    }
+}
+
+// Route Registration ******************************************************************************
+register_route! {
+    scope: Internal, version: 1, path: "/queues",
+    auth: Operator, rate_limit: Some(RateScope::Admin),
+    service: queues_handler
+}
+register_route! {
+    scope: Internal, version: 1, path: "/queues/{store}/items",
+    auth: Operator, rate_limit: Some(RateScope::Admin),
+    service: queue_items_handler
+}
+register_route! {
+    scope: Internal, version: 1, path: "/queues/{store}/items/{id:.*}",
+    auth: Operator, rate_limit: Some(RateScope::Admin),
+    service: queue_item_handler
+}
+
+// TESTS *******************************************************************************************
+// DOCUMENTATION: Pass function only - no unit tests for route handlers
@@ -0,0 +1,74 @@
+// Imports *****************************************************************************************
+// External Crates
+use actix_web::{get, web, HttpRequest, HttpResponse};
+use serde_json::json;
+use std::sync::Arc;
+
+// Internal Modules
+use crate::authentication::admin::authenticate_admin;
+use crate::authentication::rate_limit::RateScope;
+use crate::routes::registry::register_route;
+use crate::services::service_compatibility::compatibility_report;
+use crate::services::service_readiness::ReadinessProbe;
+use crate::utils::api_error::ApiError;
+use crate::utils::clock_drift::clock_check;
+use crate::utils::drain_state::DRAIN_STATE;
+use crate::utils::response_format::NegotiatedResponse;
+use crate::utils::storage_diagnostics::storage_diagnostics;
+
+// Route Handlers ***********************************************************************************
+// Readiness Handler
+#[get("/readiness")]
+/// Operator view of the readiness of the instance: the draining reasons, the checks of the
+/// dependencies with their errors, the active storage faults (permissions, missing bucket,
+/// quota) with what to do about them, the last clock drift check and the last schema
+/// compatibility check - the hospitals only see a neutral 503 while a fault lasts
+/// # Returns
+/// * An HttpResponse with `ready`, `draining`, `dependencies`, `storage`, `clock` and
+///   `compatibility` diagnostics - 503 when not ready, including a host clock drifting beyond
+///   CLOCK_DRIFT_MAX_MS or an enforced schema mismatch
+pub async fn readiness_handler(
+    req: HttpRequest,
+    probe: web::Data<Arc<ReadinessProbe>>,
+) -> Result<HttpResponse, ApiError> {
+    // Prep: Authenticate operator
+    authenticate_admin(&req).map_err(|e| ApiError::Unauthorized(e.to_string()))?;
+
+    let draining: Vec<&str> = DRAIN_STATE.reasons().iter().map(|r| r.as_str()).collect();
+    let dependencies = probe.check().await;
+    let storage = storage_diagnostics();
+    let clock = clock_check();
+    let clock_trusted = clock.as_ref().is_none_or(|check| check.within_threshold);
+    let compatibility = compatibility_report();
+    let schemas_compatible = compatibility
+        .as_ref()
+        .is_none_or(|report| report.allows_traffic());
+    let ready = draining.is_empty()
+        && dependencies.ready
+        && storage.is_empty()
+        && clock_trusted
+        && schemas_compatible;
+    let body = json!({
+        "ready": ready,
+        "draining": draining,
+        "dependencies": dependencies,
+        "storage": storage,
+        "clock": clock,
+        "compatibility": compatibility,
+    });
+    if ready {
+        Ok(HttpResponse::Ok().negotiated(&body))
+    } else {
+        Ok(HttpResponse::ServiceUnavailable().negotiated(&body))
+    }
+}
+
+// Route Registration ******************************************************************************
+register_route! {
+    scope: Internal, version: 1, path: "/readiness",
+    auth: Operator, rate_limit: Some(RateScope::Internal),
+    service: readiness_handler
+}
+
+// TESTS *******************************************************************************************
+// DOCUMENTATION: Pass function only - no unit tests for route handlers
@@ -0,0 +1,34 @@
+// Imports *****************************************************************************************
+// External Crates
+use actix_web::{get, HttpRequest, HttpResponse};
+
+// Internal Modules
+use crate::authentication::admin::authenticate_admin;
+use crate::authentication::rate_limit::RateScope;
+use crate::routes::registry::register_route;
+use crate::utils::api_error::ApiError;
+use crate::utils::response_format::NegotiatedResponse;
+use crate::utils::stage_metrics::stage_durations;
+
+// Route Handlers ***********************************************************************************
+// Stage Durations Handler
+#[get("/stage_durations")]
+/// Expose the duration histograms of the gateway-internal stages, for capacity planning
+/// # Returns
+/// * An HttpResponse with cumulative bucket counts, count and sum per exam type and stage
+pub async fn stage_durations_handler(req: HttpRequest) -> Result<HttpResponse, ApiError> {
+    // Prep: Authenticate operator
+    authenticate_admin(&req).map_err(|e| ApiError::Unauthorized(e.to_string()))?;
+
+    Ok(HttpResponse::Ok().negotiated(&stage_durations()))
+}
+
+// Route Registration ******************************************************************************
+register_route! {
+    scope: Internal, version: 1, path: "/stage_durations",
+    auth: Operator, rate_limit: Some(RateScope::Admin),
+    service: stage_durations_handler
+}
+
+// TESTS *******************************************************************************************
+// DOCUMENTATION: Pass function only - no unit tests for route handlers
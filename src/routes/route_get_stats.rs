@@ -0,0 +1,124 @@
+// Imports *****************************************************************************************
+// External Crates
+use actix_web::{get, web, HttpResponse};
+use chrono::{DateTime, Duration, NaiveTime, Utc};
+use log::{error, info};
+use serde::Deserialize;
+use sqlx::PgPool;
+use std::sync::Arc;
+use utoipa::IntoParams;
+
+// Internal Modules
+use crate::audit::audit_event::AuditAction;
+use crate::audit::audit_trail::count_actions;
+use crate::authentication::auth::AuthenticatedHospital;
+use crate::authentication::rate_limit::RateScope;
+use crate::config::Settings;
+use crate::routes::registry::register_route;
+use crate::services::service_tenant_stats::{TenantAggregate, TenantStats};
+use crate::utils::api_error::ApiError;
+use crate::utils::response_format::NegotiatedResponse;
+
+// Constants ***************************************************************************************
+/// Period of the statistics when `from` is not given
+const DEFAULT_PERIOD_DAYS: i64 = 30;
+/// Longest period of one query
+const MAX_PERIOD_DAYS: i64 = 366;
+/// Actions counted for the hospital
+const COUNTED_ACTIONS: [AuditAction; 3] = [
+    AuditAction::ExamStored,
+    AuditAction::ExamPublished,
+    AuditAction::ExamDeadLettered,
+];
+
+// Structs *****************************************************************************************
+/// Period of the statistics
+/// # Arguments
+/// * `from` - Start of the period (RFC 3339), DEFAULT_PERIOD_DAYS before `to` by default
+/// * `to` - End of the period (RFC 3339), now by default
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct StatsQuery {
+    pub from: Option<DateTime<Utc>>,
+    pub to: Option<DateTime<Utc>>,
+}
+
+// Route Handlers ***********************************************************************************
+// Exam Stats Handler
+#[utoipa::path(
+    get,
+    path = "/v1/stats/exams",
+    tag = "exams",
+    params(StatsQuery),
+    responses(
+        (status = 200, description = "Exams of the hospital per exam type and outcome", body = TenantStats),
+        ApiError
+    ),
+    security(("hospital_id" = [], "hospital_key" = []), ("bearer_token" = []))
+)]
+#[get("/stats/exams")]
+/// Exams of the authenticated hospital stored, published and dead-lettered over a period, per exam
+/// type - counts below the disclosure threshold are `null`, and noise may be added
+/// # Arguments
+/// * `query` - Optional `from`/`to` (RFC 3339), at most MAX_PERIOD_DAYS apart, widened to whole
+///   UTC days
+/// * `settings` - The settings of the service, holding the disclosure policy (STATS_*)
+/// # Returns
+/// * An HttpResponse with the counts of the hospital, or 400 for an invalid period
+pub async fn exam_stats_handler(
+    query: web::Query<StatsQuery>,
+    hospital: AuthenticatedHospital,
+    db_pool: web::Data<PgPool>,
+    settings: web::Data<Arc<Settings>>,
+) -> Result<HttpResponse, ApiError> {
+    // Prep: The hospital was authenticated by the middleware of the scope
+    let to = query.to.unwrap_or_else(Utc::now);
+    let from = query
+        .from
+        .unwrap_or(to - Duration::days(DEFAULT_PERIOD_DAYS));
+    if from >= to || to - from > Duration::days(MAX_PERIOD_DAYS) {
+        return Err(ApiError::BadRequest(format!(
+            "The period must start before it ends and span at most {MAX_PERIOD_DAYS} days"
+        )));
+    }
+    // The noise is fixed per UTC day: the period covers whole days, so two periods cutting a day
+    // differently never reveal the exact count of the part of the day between them
+    let from_day = from.date_naive();
+    let to_day = if to.time() == NaiveTime::MIN {
+        to.date_naive()
+    } else {
+        to.date_naive() + Duration::days(1)
+    };
+    let (from, to) = (
+        from_day.and_time(NaiveTime::MIN).and_utc(),
+        to_day.and_time(NaiveTime::MIN).and_utc(),
+    );
+
+    // STEP 1: Count the events of the hospital
+    let rows = count_actions(&db_pool, &hospital.hospital_id, &COUNTED_ACTIONS, from, to)
+        .await
+        .map_err(|e| {
+            error!("Stats query failed: {e}");
+            ApiError::Internal
+        })?;
+
+    // STEP 2: Aggregate under the scoping check and the disclosure policy
+    let mut aggregate = TenantAggregate::new(&hospital.hospital_id);
+    for row in &rows {
+        aggregate.add(&row.hospital_id, &row.exam_type, &row.action, row.count)?;
+    }
+    let stats = aggregate.finish(&settings.stats, from_day, to_day);
+    info!(target: "audit", "stats_query hospital_id={} from={} to={} groups={}",
+        hospital.hospital_id, from.to_rfc3339(), to.to_rfc3339(), stats.counts.len());
+    Ok(HttpResponse::Ok().negotiated(&stats))
+}
+
+// Route Registration ******************************************************************************
+register_route! {
+    scope: Hospital, version: 1, path: "/stats/exams",
+    auth: Hospital, rate_limit: Some(RateScope::Ingest),
+    service: exam_stats_handler
+}
+
+// TESTS *******************************************************************************************
+// DOCUMENTATION: Pass function only - no unit tests for route handlers
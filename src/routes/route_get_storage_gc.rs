@@ -0,0 +1,34 @@
+// Imports *****************************************************************************************
+// External Crates
+use actix_web::{get, HttpRequest, HttpResponse};
+
+// Internal Modules
+use crate::authentication::admin::authenticate_admin;
+use crate::authentication::rate_limit::RateScope;
+use crate::routes::registry::register_route;
+use crate::services::service_storage_gc::gc_stats;
+use crate::utils::api_error::ApiError;
+use crate::utils::response_format::NegotiatedResponse;
+
+// Route Handlers ***********************************************************************************
+// Storage GC Handler
+#[get("/storage_gc")]
+/// Expose the metrics of the garbage collection of the staging/quarantine prefixes
+/// # Returns
+/// * An HttpResponse with runs, scanned, expired, deleted objects/bytes and failures per prefix
+pub async fn storage_gc_handler(req: HttpRequest) -> Result<HttpResponse, ApiError> {
+    // Prep: Authenticate operator
+    authenticate_admin(&req).map_err(|e| ApiError::Unauthorized(e.to_string()))?;
+
+    Ok(HttpResponse::Ok().negotiated(&gc_stats()))
+}
+
+// Route Registration ******************************************************************************
+register_route! {
+    scope: Internal, version: 1, path: "/storage_gc",
+    auth: Operator, rate_limit: Some(RateScope::Admin),
+    service: storage_gc_handler
+}
+
+// TESTS *******************************************************************************************
+// DOCUMENTATION: Pass function only - no unit tests for route handlers
@@ -0,0 +1,69 @@
+// Imports *****************************************************************************************
+// External Crates
+use actix_web::{get, web, HttpRequest, HttpResponse};
+use std::sync::Arc;
+
+// Internal Modules
+use crate::authentication::admin::authenticate_admin;
+use crate::authentication::rate_limit::RateScope;
+use crate::config::Settings;
+use crate::models::models_validation_profiles::{find_profile, profile_history};
+use crate::routes::registry::register_route;
+use crate::utils::api_error::ApiError;
+use crate::utils::response_format::NegotiatedResponse;
+
+// Route Handlers ***********************************************************************************
+// Validation Profiles Handler
+#[get("/validation_profiles")]
+/// List every validation profile version ever applied, oldest first
+/// # Arguments
+/// * `settings` - The application settings
+/// # Returns
+/// * An HttpResponse with the profile definitions
+pub async fn validation_profiles_handler(
+    req: HttpRequest,
+    settings: web::Data<Arc<Settings>>,
+) -> Result<HttpResponse, ApiError> {
+    // Prep: Authenticate operator
+    authenticate_admin(&req).map_err(|e| ApiError::Unauthorized(e.to_string()))?;
+
+    Ok(HttpResponse::Ok().negotiated(&profile_history(&settings.server)))
+}
+
+// Validation Profile Handler
+#[get("/validation_profiles/{id}/{version}")]
+/// Fetch the definition of one profile version, e.g. the one recorded with a past exam
+/// # Arguments
+/// * `path` - The profile id and version
+/// * `settings` - The application settings
+/// # Returns
+/// * An HttpResponse with the profile definition, or 404 if the version never existed
+pub async fn validation_profile_handler(
+    req: HttpRequest,
+    path: web::Path<(String, u32)>,
+    settings: web::Data<Arc<Settings>>,
+) -> Result<HttpResponse, ApiError> {
+    // Prep: Authenticate operator
+    authenticate_admin(&req).map_err(|e| ApiError::Unauthorized(e.to_string()))?;
+
+    let (id, version) = path.into_inner();
+    match find_profile(&id, version, &settings.server) {
+        Some(profile) => Ok(HttpResponse::Ok().negotiated(&profile)),
+        None => Err(ApiError::NotFound("Profile Not Found")),
+    }
+}
+
+// Route Registration ******************************************************************************
+register_route! {
+    scope: Internal, version: 1, path: "/validation_profiles",
+    auth: Operator, rate_limit: Some(RateScope::Admin),
+    service: validation_profiles_handler
+}
+register_route! {
+    scope: Internal, version: 1, path: "/validation_profiles/{id}/{version}",
+    auth: Operator, rate_limit: Some(RateScope::Admin),
+    service: validation_profile_handler
+}
+
+// TESTS *******************************************************************************************
+// DOCUMENTATION: Pass function only - no unit tests for route handlers
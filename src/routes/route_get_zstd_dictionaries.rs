@@ -0,0 +1,87 @@
+// Imports *****************************************************************************************
+// External Crates
+use actix_web::http::header;
+use actix_web::{get, web, HttpResponse};
+
+// Internal Modules
+use crate::authentication::auth::AuthenticatedHospital;
+use crate::authentication::rate_limit::RateScope;
+use crate::routes::registry::register_route;
+use crate::services::service_zstd_dictionaries::{
+    list_zstd_dictionaries, zstd_dictionary, ZstdDictionary,
+};
+use crate::utils::api_error::ApiError;
+use crate::utils::response_format::NegotiatedResponse;
+
+// Route Handlers ***********************************************************************************
+// zstd Dictionaries Handler
+#[utoipa::path(
+    get,
+    path = "/v1/zstd_dictionaries",
+    tag = "exams",
+    responses(
+        (status = 200, description = "Dictionaries of the gateway, one per device model",
+            body = Vec<ZstdDictionary>),
+        ApiError
+    ),
+    security(("hospital_id" = [], "hospital_key" = []), ("bearer_token" = []))
+)]
+#[get("/zstd_dictionaries")]
+/// List the pre-shared zstd dictionaries - an ECG body compressed with one is sent with
+/// `Content-Encoding: zstd` and its id in the `zstd_dictionary` header
+/// # Returns
+/// * An HttpResponse with the id, device model, SHA256 and size of every dictionary
+pub async fn zstd_dictionaries_handler(_hospital: AuthenticatedHospital) -> HttpResponse {
+    // Prep: The hospital was authenticated by the middleware of the scope
+    HttpResponse::Ok().negotiated(&list_zstd_dictionaries())
+}
+
+// zstd Dictionary Handler
+#[utoipa::path(
+    get,
+    path = "/v1/zstd_dictionaries/{id}",
+    tag = "exams",
+    params(("id" = u32, Path, description = "The dictionary id")),
+    responses(
+        (status = 200, description = "The dictionary, as given to the zstd compressor",
+            content_type = "application/octet-stream", body = Vec<u8>),
+        ApiError
+    ),
+    security(("hospital_id" = [], "hospital_key" = []), ("bearer_token" = []))
+)]
+#[get("/zstd_dictionaries/{id}")]
+/// Download a pre-shared zstd dictionary - immutable, a retrained dictionary gets a new id
+/// # Arguments
+/// * `id` - The dictionary id
+/// # Returns
+/// * An HttpResponse with the dictionary bytes, or 404 if the gateway does not have it
+pub async fn zstd_dictionary_handler(
+    id: web::Path<u32>,
+    _hospital: AuthenticatedHospital,
+) -> Result<HttpResponse, ApiError> {
+    // Prep: The hospital was authenticated by the middleware of the scope
+    let dictionary = zstd_dictionary(*id).ok_or(ApiError::NotFound("Unknown zstd dictionary"))?;
+    Ok(HttpResponse::Ok()
+        .content_type("application/octet-stream")
+        .insert_header((header::ETAG, format!("\"{}\"", dictionary.sha256)))
+        .insert_header((
+            header::CACHE_CONTROL,
+            "private, max-age=31536000, immutable",
+        ))
+        .body(dictionary.bytes.clone()))
+}
+
+// Route Registration ******************************************************************************
+register_route! {
+    scope: Hospital, version: 1, path: "/zstd_dictionaries",
+    auth: Hospital, rate_limit: Some(RateScope::Ingest),
+    service: zstd_dictionaries_handler
+}
+register_route! {
+    scope: Hospital, version: 1, path: "/zstd_dictionaries/{id}",
+    auth: Hospital, rate_limit: Some(RateScope::Ingest),
+    service: zstd_dictionary_handler
+}
+
+// TESTS *******************************************************************************************
+// DOCUMENTATION: Pass function only - no unit tests for route handlers
@@ -0,0 +1,58 @@
+// Imports *****************************************************************************************
+// External Crates
+use actix_web::{patch, web, HttpRequest, HttpResponse};
+use log::info;
+use serde_json::json;
+use sqlx::PgPool;
+
+// Internal Modules
+use crate::authentication::admin::authenticate_admin;
+use crate::authentication::rate_limit::RateScope;
+use crate::models::models_hospital_admin::HospitalUpdate;
+use crate::routes::registry::register_route;
+use crate::services::service_hospital_registry::{registry_error, update_hospital};
+use crate::utils::api_error::ApiError;
+use crate::utils::response_format::NegotiatedResponse;
+
+// Route Handlers ***********************************************************************************
+// Update Hospital Handler
+#[patch("/hospitals/{hospital_id}")]
+/// Set the allowed exam types, the rate limit tier, the publish mode or the pause of a hospital -
+/// absent fields are kept, `"allowed_exam_types": null` allows all exam types again
+/// # Arguments
+/// * `body` - The settings to change
+/// # Returns
+/// * An HttpResponse with the hospital id, 404 if the hospital is not registered
+pub async fn update_hospital_handler(
+    req: HttpRequest,
+    path: web::Path<String>,
+    body: web::Json<HospitalUpdate>,
+    db_pool: web::Data<PgPool>,
+) -> Result<HttpResponse, ApiError> {
+    // Prep: Authenticate operator
+    authenticate_admin(&req).map_err(|e| ApiError::Unauthorized(e.to_string()))?;
+
+    if !update_hospital(&db_pool, &path, &body)
+        .await
+        .map_err(registry_error)?
+    {
+        return Err(ApiError::NotFound("Hospital not registered"));
+    }
+    info!(target: "audit", "hospital_updated hospital_id={} allowed_exam_types={:?} rate_limit_tier={} publish_mode={} paused={:?}",
+        path.as_str(),
+        body.allowed_exam_types,
+        body.rate_limit_tier.map_or("unchanged", |tier| tier.as_str()),
+        body.publish_mode.map_or("unchanged", |mode| mode.as_str()),
+        body.paused);
+    Ok(HttpResponse::Ok().negotiated(&json!({ "hospital_id": path.as_str() })))
+}
+
+// Route Registration ******************************************************************************
+register_route! {
+    scope: Admin, version: 1, path: "/hospitals/{hospital_id}",
+    auth: Operator, rate_limit: Some(RateScope::Admin),
+    service: update_hospital_handler
+}
+
+// TESTS *******************************************************************************************
+// DOCUMENTATION: Pass function only - no unit tests for route handlers
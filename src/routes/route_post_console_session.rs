@@ -0,0 +1,192 @@
+// Imports *****************************************************************************************
+// External Crates
+use actix_web::http::header;
+use actix_web::{post, web, HttpMessage, HttpRequest, HttpResponse};
+use log::{error, info, warn};
+use serde::Deserialize;
+use serde_json::json;
+use sqlx::PgPool;
+use std::sync::Arc;
+
+// Internal Modules
+use crate::audit::audit_event::AuditEvent;
+use crate::audit::audit_trail::record_audit;
+use crate::authentication::admin::authenticate_admin_key;
+use crate::authentication::console_session::{
+    check_origin, console_settings, open_session, refresh_session, revoke_session, session_cookie,
+    ConsoleSession, ConsoleSettings,
+};
+use crate::authentication::rate_limit::RateScope;
+use crate::config::Settings;
+use crate::routes::registry::register_route;
+use crate::utils::api_error::ApiError;
+use crate::utils::response_format::NegotiatedResponse;
+
+// Request Body ************************************************************************************
+/// Body of a console login
+/// # Arguments
+/// * `admin_key` - The admin key (ADMIN_API_KEY) typed by the operator
+#[derive(Deserialize)]
+pub struct ConsoleLogin {
+    admin_key: String,
+}
+
+// Route Handlers ***********************************************************************************
+// Console Login Handler
+#[post("/console/login")]
+/// Open an admin console session - from the console origin only
+/// # Arguments
+/// * `body` - The admin key
+/// * `settings` - The settings of the service, holding ADMIN_API_KEY
+/// # Returns
+/// * An HttpResponse with the session cookie and the CSRF token the console sends back in
+///   'x-csrf-token', 401 if the key is refused, 403 from another origin, 404 if the console is
+///   disabled
+pub async fn console_login_handler(
+    req: HttpRequest,
+    body: web::Json<ConsoleLogin>,
+    db_pool: web::Data<PgPool>,
+    settings: web::Data<Arc<Settings>>,
+) -> Result<HttpResponse, ApiError> {
+    // Prep: The console must be enabled and the login come from its origin
+    let console = enabled_console()?;
+    check_origin(req.headers().get(header::ORIGIN), &console.origin, true)
+        .map_err(|e| ApiError::Forbidden(e.to_string()))?;
+    let client_ip = client_ip(&req);
+
+    // STEP 1: Authenticate the operator - refusals count for the lockout of the admin scope
+    if let Err(e) = authenticate_admin_key(&body.admin_key, &settings.auth) {
+        warn!("Console login denied: {e}");
+        record_audit(AuditEvent::console_session("denied", "", &client_ip));
+        return Err(ApiError::Unauthorized(e.to_string()));
+    }
+
+    // STEP 2: Open the session and set its cookie
+    let (token, session) = open_session(&db_pool, console, &client_ip)
+        .await
+        .map_err(|e| {
+            error!("Console session not opened: {e}");
+            ApiError::Internal
+        })?;
+    info!(target: "audit", "console_login session_id={} client_ip={client_ip}", session.session_id);
+    record_audit(AuditEvent::console_session(
+        "login",
+        &session.session_id,
+        &client_ip,
+    ));
+    Ok(session_response(&token, &session))
+}
+
+// Console Refresh Handler
+#[post("/console/refresh")]
+/// Refresh the admin console session: new cookie and CSRF token, expiry pushed back up to
+/// ADMIN_SESSION_MAX_S after the login
+/// # Returns
+/// * An HttpResponse with the new session cookie and CSRF token, 401 without a live session
+pub async fn console_refresh_handler(
+    req: HttpRequest,
+    db_pool: web::Data<PgPool>,
+) -> Result<HttpResponse, ApiError> {
+    let console = enabled_console()?;
+    let session = current_session(&req)?;
+    let refreshed = refresh_session(&db_pool, console, &session)
+        .await
+        .map_err(|e| {
+            error!("Console session not refreshed: {e}");
+            ApiError::Internal
+        })?;
+    let Some((token, session)) = refreshed else {
+        return Err(ApiError::Unauthorized("Session expired".to_string()));
+    };
+    info!(target: "audit", "console_refresh session_id={}", session.session_id);
+    record_audit(AuditEvent::console_session(
+        "refresh",
+        &session.session_id,
+        &client_ip(&req),
+    ));
+    Ok(session_response(&token, &session))
+}
+
+// Console Logout Handler
+#[post("/console/logout")]
+/// Close the admin console session - its cookie is refused from then on and cleared
+/// # Returns
+/// * An HttpResponse clearing the session cookie, 401 without a live session
+pub async fn console_logout_handler(
+    req: HttpRequest,
+    db_pool: web::Data<PgPool>,
+) -> Result<HttpResponse, ApiError> {
+    enabled_console()?;
+    let session = current_session(&req)?;
+    revoke_session(&db_pool, &session.session_id)
+        .await
+        .map_err(|e| {
+            error!("Console session not revoked: {e}");
+            ApiError::Internal
+        })?;
+    info!(target: "audit", "console_logout session_id={}", session.session_id);
+    record_audit(AuditEvent::console_session(
+        "logout",
+        &session.session_id,
+        &client_ip(&req),
+    ));
+    Ok(HttpResponse::Ok()
+        .cookie(session_cookie("", 0))
+        .negotiated(&json!({ "session_id": session.session_id })))
+}
+
+// SUPPORT FUNCTIONS *******************************************************************************
+/// Settings of the console, 404 when it is disabled
+fn enabled_console() -> Result<&'static ConsoleSettings, ApiError> {
+    console_settings().ok_or(ApiError::NotFound("Admin console disabled"))
+}
+
+/// Session verified by `console_session_middleware`, 401 without one
+fn current_session(req: &HttpRequest) -> Result<ConsoleSession, ApiError> {
+    req.extensions()
+        .get::<ConsoleSession>()
+        .cloned()
+        .ok_or_else(|| ApiError::Unauthorized("No console session".to_string()))
+}
+
+/// Client address, as seen behind the load balancer
+fn client_ip(req: &HttpRequest) -> String {
+    req.connection_info()
+        .realip_remote_addr()
+        .unwrap_or("unknown")
+        .to_string()
+}
+
+/// Session cookie and the session as seen by the console
+fn session_response(token: &str, session: &ConsoleSession) -> HttpResponse {
+    let max_age_s = (session.expires_at - chrono::Utc::now())
+        .num_seconds()
+        .max(0);
+    HttpResponse::Ok()
+        .cookie(session_cookie(token, max_age_s))
+        .negotiated(&json!({
+            "session_id": session.session_id,
+            "csrf_token": session.csrf_token,
+            "expires_at": session.expires_at,
+        }))
+}
+
+// Route Registration ******************************************************************************
+register_route! {
+    scope: Internal, version: 1, path: "/console/login",
+    auth: ConsoleLogin, rate_limit: Some(RateScope::Admin),
+    service: console_login_handler
+}
+register_route! {
+    scope: Internal, version: 1, path: "/console/refresh",
+    auth: Operator, rate_limit: Some(RateScope::Admin),
+    service: console_refresh_handler
+}
+register_route! {
+    scope: Internal, version: 1, path: "/console/logout",
+    auth: Operator, rate_limit: Some(RateScope::Admin),
+    service: console_logout_handler
+}
+
+// TESTS *******************************************************************************************
+// DOCUMENTATION: Pass function only - no unit tests for route handlers
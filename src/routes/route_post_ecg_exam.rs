@@ -1,61 +1,344 @@
 // Imports *****************************************************************************************
 // External Crates
 use actix_web::HttpRequest;
-use actix_web::{post, web, Error, HttpResponse};
+use actix_web::{post, web, HttpResponse};
 use log::{error, info};
 use serde_json::json;
+use sqlx::PgPool;
 use std::sync::Arc;
 use validator::Validate;
 
 // Internal Modules
-use crate::authentication::auth::authenticate_hospital;
+use crate::authentication::auth::AuthenticatedHospital;
+use crate::authentication::rate_limit::RateScope;
+use crate::config::settings::{IngestSettings, Settings};
+use crate::models::models_deprecations::{sunset_header, DeprecatedFields};
 use crate::models::models_exams::PayloadEcg;
-use crate::services::service_ecg_exam::handler_ecg_exam;
-use google_cloud_pubsub::client::Client as PubSubClient;
-use google_cloud_storage::client::Client as GcsClient;
+use crate::models::models_validation_profiles::ECG_PROFILE;
+use crate::routes::registry::register_route;
+use crate::services::service_confirmation_webhook::check_webhook_url;
+use crate::services::service_downstream_feedback::is_saturated;
+use crate::services::service_ecg_exam::ecg_exam_id;
+use crate::services::service_hospital_groups::{consume_monthly_quota, release_monthly_quota};
+use crate::services::service_idempotency::{
+    check_idempotency, idempotency_key, IdempotencyCheck, IdempotencyStore, StoredResponse,
+};
+use crate::services::service_ingest_queue::{IngestJob, IngestQueue};
+use crate::services::service_rejection_digest::record_rejection;
+use crate::services::service_wasm_plugins::PluginRegistry;
+use crate::telemetry::trace_context::{current_trace, start_span};
+use crate::utils::api_error::ApiError;
+use crate::utils::deprecation_usage::record_deprecated_usage;
+use crate::utils::get_headers::{confirmation_webhook, is_two_phase, is_urgent};
+use crate::utils::ids::ReceiptId;
+use crate::utils::publish_backlog::{Admission, BACKLOG_RETRY_AFTER_S, PUBLISH_BACKLOG};
+use crate::utils::reason_code::ReasonCode;
+use crate::utils::request_id::request_id;
+use crate::utils::response_format::NegotiatedResponse;
+
+// Constants ***************************************************************************************
+/// Exam type key used for plugins and rejection digests
+const EXAM_TYPE: &str = "ecg_exam";
 
 // Route Handlers ***********************************************************************************
 // Health Check Handler
+#[utoipa::path(
+    post,
+    path = "/v1/ecg_exam",
+    tag = "exams",
+    request_body = PayloadEcg,
+    params(
+        ("Idempotency-Key" = Option<String>, Header, description = "Identifies the exam across \
+            retries, at most 255 characters - the SHA256 of the JSON payload when absent"),
+        ("exam_priority" = Option<String>, Header, description = "`urgent` exams are never \
+            deferred nor throttled"),
+        ("ingest_mode" = Option<String>, Header, description = "`two_phase`: answered 201 once the \
+            exam is spooled to durable storage"),
+        ("confirmation_webhook" = Option<String>, Header, description = "HTTPS URL notified once \
+            the exam is stored and published"),
+        ("on_behalf_of" = Option<String>, Header, description = "Clinic a hospital group submits \
+            for"),
+        ("Content-Encoding" = Option<String>, Header, description = "`gzip` or `zstd` body, \
+            limited to DECOMPRESSED_BODY_MAX_BYTES once decompressed"),
+        ("zstd_dictionary" = Option<u32>, Header, description = "Id of the pre-shared dictionary \
+            of `/v1/zstd_dictionaries` the zstd body was compressed with"),
+    ),
+    responses(
+        (status = 202, description = "Exam queued - follow it at `/v1/exam_status/{exam_id}`",
+            example = json!({"status": "ECG Exam Accepted", "receipt_id": "0192...", "exam_id": "ecg_exam/...",
+                "deferred": false, "confirmation_webhook": false, "warnings": []})),
+        (status = 201, description = "Two-phase exam spooled"),
+        ApiError
+    ),
+    security(("hospital_id" = [], "hospital_key" = []), ("bearer_token" = []))
+)]
 #[post("/ecg_exam")]
 /// Receive and process an ECG exam of a patient
 /// # Arguments
 /// * `payload` - A JSON object containing the data of the patient
+/// * `settings` - The settings of the service, holding CONFIRMATION_WEBHOOK_HOSTS
 /// # Returns
-/// * An HttpResponse containing a 200 OK status if the ECG exam is processed successfully
+/// * An HttpResponse containing a 202 Accepted status and the exam id once the ECG exam is queued,
+///   or 201 Created once it is also spooled ('ingest_mode: two_phase'); its processing can be
+///   followed at `/v1/exam_status/{exam_id}` or through the 'confirmation_webhook' - a retry of the
+///   same exam (same 'Idempotency-Key', or same payload) gets the original response
 pub async fn ecg_exam_handler(
     req: HttpRequest,
+    hospital: AuthenticatedHospital,
     payload: web::Json<PayloadEcg>,
-    gcs_client: web::Data<Arc<GcsClient>>,
-    pubsub_client: web::Data<Arc<PubSubClient>>,
-) -> Result<HttpResponse, Error> {
+    ingest_queue: web::Data<Arc<IngestQueue>>,
+    plugins: web::Data<Arc<PluginRegistry>>,
+    idempotency: web::Data<Arc<IdempotencyStore>>,
+    pool: web::Data<PgPool>,
+    settings: web::Data<Arc<Settings>>,
+) -> Result<HttpResponse, ApiError> {
+    submit_ecg_exam(
+        &req,
+        hospital,
+        payload.into_inner(),
+        &ingest_queue,
+        &plugins,
+        &idempotency,
+        &pool,
+        &settings.ingest,
+    )
+    .await
+}
+
+// SUPPORT FUNCTIONS *******************************************************************************
+/// ECG pipeline shared by the JSON, FHIR and stream routes: plugin, ownership check, validation,
+/// monthly quota, queueing
+/// # Arguments
+/// * `req` - The request, for its urgency and request id
+/// * `hospital` - The hospital authenticated by the middleware
+/// * `payload` - The ECG exam, as sent or mapped from FHIR
+/// * `ingest_queue` - The queue of the exams to store and publish
+/// * `plugins` - The transformation plugins of the hospitals
+/// * `idempotency` - The responses of the exams already accepted
+/// * `pool` - The shared database connection pool, counting the monthly quotas
+/// * `settings` - The intake settings, holding CONFIRMATION_WEBHOOK_HOSTS
+/// # Returns
+/// * An HttpResponse containing a 202 Accepted status and the exam id once the ECG exam is queued,
+///   201 Created for a two-phase exam once it is spooled, or the original response of a retried
+///   exam
+/// # Errors
+/// * Returns Validation if the payload is invalid, InvalidContent if the confirmation webhook is
+///   not allowed, QuotaExceeded if a monthly quota is used up, RateLimited if the ingest queue is
+///   full or the publish backlog is critical, StorageUnavailable if a two-phase exam could not be
+///   spooled
+pub(crate) async fn submit_ecg_exam(
+    req: &HttpRequest,
+    hospital: AuthenticatedHospital,
+    payload: PayloadEcg,
+    ingest_queue: &IngestQueue,
+    plugins: &PluginRegistry,
+    idempotency: &IdempotencyStore,
+    pool: &PgPool,
+    settings: &IngestSettings,
+) -> Result<HttpResponse, ApiError> {
     info!("Starting the route handler for the ECG exam processing");
+    let received_at = chrono::Utc::now();
+    // Non-urgent exams are deferred while the inference service is saturated or Pub/Sub is
+    // backlogged, and refused once the publish backlog holds too many exams
+    let admission = PUBLISH_BACKLOG.admission(is_urgent(req));
+    let deferred = admission == Admission::Defer || (!is_urgent(req) && is_saturated());
+    let request_id = request_id(req);
+    let two_phase = is_two_phase(req);
+    let webhook = confirmation_webhook(req);
 
-    // Prep: Authenticate hospital
-    // If authentication fails, an error response is returned, else processing continues
-    if let Err(e) = authenticate_hospital(req).await {
-        error!("Authentication error - ECG Exam: {}", e);
-        return Ok(HttpResponse::Unauthorized().json(json!({ "error": e.to_string() })));
+    // Prep: The hospital was authenticated by the middleware of the scope
+    let authenticated_hospital_id = hospital.hospital_id.clone();
+    let consent_scope = hospital.consent_scope;
+
+    // Prep: A retry of an exam already accepted gets its original response, nothing is re-processed;
+    // a retry racing the request still processing the exam waits for its response
+    let key = idempotency_key(req, Some(&payload));
+    let _in_flight = match check_idempotency(
+        idempotency,
+        &authenticated_hospital_id,
+        EXAM_TYPE,
+        key.as_ref(),
+    )
+    .await
+    {
+        Ok(IdempotencyCheck::Replayed(replayed)) => return Ok(replayed),
+        Ok(IdempotencyCheck::Process(claim)) => claim,
+        Err(e) => {
+            error!("Exam still in flight or key reused - ECG Exam refused");
+            record_rejection(
+                &authenticated_hospital_id,
+                EXAM_TYPE,
+                e.reason(),
+                &request_id,
+            );
+            return Err(e);
+        }
     };
 
-    // STEP 1: Validate the payload
-    if let Err(e) = payload.validate() {
+    // Prep: The publish backlog is critical - the exam is refused before anything is read or stored
+    if admission == Admission::Reject {
+        error!("Publish backlog critical - ECG Exam refused");
+        record_rejection(
+            &authenticated_hospital_id,
+            EXAM_TYPE,
+            ReasonCode::PublishBacklog,
+            &request_id,
+        );
+        return Err(ApiError::RateLimited {
+            retry_after_s: BACKLOG_RETRY_AFTER_S,
+            reason: ReasonCode::PublishBacklog,
+        });
+    }
+
+    // Prep: Only allowed HTTPS hosts are notified of the confirmation
+    if let Some(url) = &webhook {
+        if let Err(e) = check_webhook_url(url, settings) {
+            error!("Confirmation webhook refused - ECG Exam: {}", e);
+            record_rejection(
+                &authenticated_hospital_id,
+                EXAM_TYPE,
+                e.reason(),
+                &request_id,
+            );
+            return Err(e);
+        }
+    }
+
+    // STEP 1: Apply the experimental transformation plugin of the hospital, if any
+    let payload = match plugins
+        .apply(&authenticated_hospital_id, EXAM_TYPE, payload)
+        .await
+    {
+        Ok(payload) => payload,
+        Err(e) => {
+            error!("WASM plugin error - ECG Exam: {}", e);
+            record_rejection(
+                &authenticated_hospital_id,
+                EXAM_TYPE,
+                ReasonCode::PluginFailed,
+                &request_id,
+            );
+            return Err(ApiError::PluginFailed);
+        }
+    };
+
+    // STEP 2: Check the payload belongs to the authenticated hospital, then validate it - the
+    // decision is audited with the profile version applied
+    if let Err(e) = hospital.check_payload(&payload.hospital_id) {
+        error!("Authentication error - ECG Exam: {}", e);
+        record_rejection(
+            &authenticated_hospital_id,
+            EXAM_TYPE,
+            e.reason(),
+            &request_id,
+        );
+        return Err(e);
+    }
+    let profile = ECG_PROFILE.label();
+    let validation = {
+        let _span = start_span("validation");
+        payload.validate()
+    };
+    if let Err(e) = validation {
         error!("Validation error - ECG Exam: {}", e);
-        return Ok(HttpResponse::BadRequest().json(json!({ "error": "Invalid Input" })));
+        let error = ApiError::from(e);
+        let reason = error.reason();
+        record_rejection(&authenticated_hospital_id, EXAM_TYPE, reason, &request_id);
+        info!(target: "audit", "exam_validation exam_type=ecg_exam hospital_id={} profile={profile} decision=rejected reason={reason}", payload.hospital_id);
+        return Err(error);
     }
+    info!(target: "audit", "exam_validation exam_type=ecg_exam hospital_id={} profile={profile} decision=accepted", payload.hospital_id);
 
-    // STEP 2: Extract data from payload, process and log it, then return response
-    let data = payload.into_inner();
-    match handler_ecg_exam(data, &gcs_client, &pubsub_client).await {
-        Ok(_) => {
-            info!("End of the route handler for the ECG exam processing - Success");
-            Ok(HttpResponse::Ok().json(json!({ "status": "ECG Exam Processed Successfully" })))
+    // Deprecated fields are accepted until their sunset - usage is recorded and the hospital warned
+    let deprecated = payload.deprecated_fields_used();
+    record_deprecated_usage(&payload.hospital_id, &deprecated);
+    let warnings: Vec<String> = deprecated.iter().map(|d| d.warning()).collect();
+
+    // STEP 3: Count the exam against the monthly quotas - replays and refused exams are never
+    // counted, an exam that cannot be queued is given back
+    if let Err(e) = consume_monthly_quota(&hospital, pool, received_at).await {
+        error!("Monthly quota used up - ECG Exam refused");
+        record_rejection(
+            &authenticated_hospital_id,
+            EXAM_TYPE,
+            e.reason(),
+            &request_id,
+        );
+        return Err(e);
+    }
+
+    // STEP 4: Queue the exam for storage and publish (spooled first in two-phase mode), then
+    // return its id for status polling
+    let exam_id = ecg_exam_id(&payload, received_at);
+    let job = IngestJob {
+        exam_id: exam_id.clone(),
+        hospital_id: authenticated_hospital_id.clone(),
+        data: payload,
+        deferred,
+        received_at,
+        consent_scope,
+        trace: current_trace(),
+        idempotency_key: key.as_ref().map(|key| key.value.clone()),
+        two_phase,
+        confirmation_webhook: webhook.clone(),
+        spooled: false,
+    };
+    match ingest_queue.enqueue(job).await {
+        Ok(()) => {
+            let receipt_id = ReceiptId::generate();
+            info!("End of the route handler for the ECG exam processing - Accepted {exam_id} (receipt {receipt_id})");
+            let (status, message) = if two_phase {
+                (201, "ECG Exam Provisionally Accepted")
+            } else {
+                (202, "ECG Exam Accepted")
+            };
+            let body = json!({
+                "status": message,
+                "receipt_id": receipt_id,
+                "exam_id": exam_id,
+                "deferred": deferred,
+                "confirmation_webhook": webhook.is_some(),
+                "warnings": warnings,
+            });
+            if let Some(key) = &key {
+                let accepted = StoredResponse {
+                    status,
+                    body: body.clone(),
+                };
+                idempotency
+                    .remember(&authenticated_hospital_id, EXAM_TYPE, key, accepted)
+                    .await;
+            }
+            let mut response = if two_phase {
+                HttpResponse::Created()
+            } else {
+                HttpResponse::Accepted()
+            };
+            if let Some(sunset) = sunset_header(&deprecated) {
+                response.insert_header(("Sunset", sunset));
+            }
+            Ok(response.negotiated(&body))
         }
         Err(e) => {
-            error!("Error while processing ECG Exam: {}", e);
-            Ok(HttpResponse::InternalServerError().json(json!({ "error": "Processing Error" })))
+            error!("Error while queueing ECG Exam: {}", e);
+            release_monthly_quota(&hospital, pool, received_at).await;
+            record_rejection(
+                &authenticated_hospital_id,
+                EXAM_TYPE,
+                e.reason(),
+                &request_id,
+            );
+            Err(e)
         }
     }
 }
 
+// Route Registration ******************************************************************************
+register_route! {
+    scope: Hospital, version: 1, path: "/ecg_exam",
+    auth: Hospital, rate_limit: Some(RateScope::Ingest),
+    service: ecg_exam_handler
+}
+
 // TESTS *******************************************************************************************
 // DOCUMENTATION: Pass function only - no unit tests for route handlers
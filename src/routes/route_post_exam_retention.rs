@@ -0,0 +1,53 @@
+// Imports *****************************************************************************************
+// External Crates
+use actix_web::{post, web, HttpRequest, HttpResponse};
+use log::info;
+use serde_json::json;
+use std::sync::Arc;
+
+// Internal Modules
+use crate::authentication::admin::authenticate_admin;
+use crate::authentication::rate_limit::RateScope;
+use crate::config::Settings;
+use crate::routes::registry::register_route;
+use crate::services::service_exam_retention::{verify_certificate, DeletionCertificate};
+use crate::utils::api_error::ApiError;
+use crate::utils::response_format::NegotiatedResponse;
+
+// Route Handlers ***********************************************************************************
+// Certificate Verification Handler
+#[post("/exam_retention/verify")]
+/// Check a deletion certificate taken from the audit log against RETENTION_SIGNING_KEY
+/// # Arguments
+/// * `body` - The certificate, as written to the audit log
+/// * `settings` - The settings of the service, holding RETENTION_SIGNING_KEY
+/// # Returns
+/// * An HttpResponse with `valid`, 400 if the certificate was signed with another key or no key
+///   is configured
+pub async fn verify_certificate_handler(
+    req: HttpRequest,
+    body: web::Json<DeletionCertificate>,
+    settings: web::Data<Arc<Settings>>,
+) -> Result<HttpResponse, ApiError> {
+    // Prep: Authenticate operator
+    authenticate_admin(&req).map_err(|e| ApiError::Unauthorized(e.to_string()))?;
+
+    let valid = verify_certificate(&body, &settings.lifecycle)
+        .map_err(|e| ApiError::BadRequest(e.to_string()))?;
+    info!(target: "audit", "exam_retention_verify certificate_id={} object={} valid={valid}",
+        body.certificate_id, body.object);
+    Ok(HttpResponse::Ok().negotiated(&json!({
+        "certificate_id": body.certificate_id,
+        "valid": valid,
+    })))
+}
+
+// Route Registration ******************************************************************************
+register_route! {
+    scope: Internal, version: 1, path: "/exam_retention/verify",
+    auth: Operator, rate_limit: Some(RateScope::Admin),
+    service: verify_certificate_handler
+}
+
+// TESTS *******************************************************************************************
+// DOCUMENTATION: Pass function only - no unit tests for route handlers
@@ -0,0 +1,137 @@
+// Imports *****************************************************************************************
+// External Crates
+use actix_web::http::StatusCode;
+use actix_web::{post, web, HttpRequest, HttpResponse};
+use log::{error, info};
+use sqlx::PgPool;
+use std::sync::Arc;
+
+// Internal Modules
+use crate::authentication::auth::AuthenticatedHospital;
+use crate::authentication::rate_limit::RateScope;
+use crate::config::Settings;
+use crate::models::models_fhir::{FhirObservation, OperationOutcome, FHIR_JSON};
+use crate::models::models_validation_profiles::ECG_PROFILE;
+use crate::routes::registry::register_route;
+use crate::routes::route_post_ecg_exam::submit_ecg_exam;
+use crate::services::service_idempotency::IdempotencyStore;
+use crate::services::service_ingest_queue::IngestQueue;
+use crate::services::service_rejection_digest::record_rejection;
+use crate::services::service_wasm_plugins::PluginRegistry;
+use crate::utils::api_error::ApiError;
+use crate::utils::reason_code::ReasonCode;
+use crate::utils::request_id::request_id;
+
+// Constants ***************************************************************************************
+/// Exam type key used for rejection digests
+const EXAM_TYPE: &str = "ecg_exam";
+
+// Route Handlers ***********************************************************************************
+// FHIR Observation Handler
+#[utoipa::path(
+    post,
+    path = "/v1/fhir/observation",
+    tag = "exams",
+    request_body(
+        description = "FHIR R4 Observation, one `SampledData` component per lead - mapped to the \
+            ECG exam (PayloadEcg) and validated as such",
+        content_type = "application/fhir+json",
+        content = Object
+    ),
+    params(
+        ("Idempotency-Key" = Option<String>, Header, description = "Identifies the exam across \
+            retries, at most 255 characters - the SHA256 of the JSON payload when absent"),
+        ("exam_priority" = Option<String>, Header, description = "`urgent` exams are never \
+            deferred nor throttled"),
+        ("on_behalf_of" = Option<String>, Header, description = "Clinic a hospital group submits \
+            for"),
+        ("Content-Encoding" = Option<String>, Header, description = "`gzip` or `zstd` body, \
+            limited to DECOMPRESSED_BODY_MAX_BYTES once decompressed"),
+        ("zstd_dictionary" = Option<u32>, Header, description = "Id of the pre-shared dictionary \
+            of `/v1/zstd_dictionaries` the zstd body was compressed with"),
+    ),
+    responses(
+        (status = 202, description = "Exam queued - follow it at `/v1/exam_status/{exam_id}`"),
+        (status = 400, description = "FHIR OperationOutcome: the Observation cannot be mapped \
+            or is invalid", content_type = "application/fhir+json"),
+        ApiError
+    ),
+    security(("hospital_id" = [], "hospital_key" = []), ("bearer_token" = []))
+)]
+#[post("/fhir/observation")]
+/// Receive an ECG exam as a FHIR R4 Observation, one `SampledData` component per lead, and process
+/// it as an ECG exam
+/// # Arguments
+/// * `observation` - The Observation, sent as `application/fhir+json` or `application/json`
+/// * `settings` - The settings of the service, holding CONFIRMATION_WEBHOOK_HOSTS
+/// # Returns
+/// * An HttpResponse containing a 202 Accepted status and the exam id once the ECG exam is queued,
+///   or a 400 OperationOutcome if the Observation cannot be mapped or is invalid
+pub async fn fhir_observation_handler(
+    req: HttpRequest,
+    hospital: AuthenticatedHospital,
+    observation: web::Json<FhirObservation>,
+    ingest_queue: web::Data<Arc<IngestQueue>>,
+    plugins: web::Data<Arc<PluginRegistry>>,
+    idempotency: web::Data<Arc<IdempotencyStore>>,
+    pool: web::Data<PgPool>,
+    settings: web::Data<Arc<Settings>>,
+) -> Result<HttpResponse, ApiError> {
+    info!("Starting the route handler for the FHIR Observation");
+
+    // STEP 1: Map the Observation to the ECG payload
+    let payload = match observation.to_payload_ecg(&hospital.hospital_id) {
+        Ok(payload) => payload,
+        Err(issues) => {
+            error!(
+                "Validation error - FHIR Observation: {} issue(s)",
+                issues.len()
+            );
+            record_rejection(
+                &hospital.hospital_id,
+                EXAM_TYPE,
+                ReasonCode::FhirMapping,
+                &request_id(&req),
+            );
+            info!(target: "audit", "exam_validation exam_type=ecg_exam hospital_id={} profile={} decision=rejected reason={}", hospital.hospital_id, ECG_PROFILE.label(), ReasonCode::FhirMapping);
+            return Ok(operation_outcome(OperationOutcome::from_issues(issues)));
+        }
+    };
+
+    // STEP 2: The ECG pipeline - validation failures are reported as an OperationOutcome
+    let submitted = submit_ecg_exam(
+        &req,
+        hospital,
+        payload,
+        &ingest_queue,
+        &plugins,
+        &idempotency,
+        &pool,
+        &settings.ingest,
+    )
+    .await;
+    match submitted {
+        Err(ApiError::Validation { fields, .. }) => Ok(operation_outcome(
+            OperationOutcome::from_validation(&fields),
+        )),
+        response => response,
+    }
+}
+
+// SUPPORT FUNCTIONS *******************************************************************************
+/// 400 response carrying an OperationOutcome
+fn operation_outcome(outcome: OperationOutcome) -> HttpResponse {
+    HttpResponse::build(StatusCode::BAD_REQUEST)
+        .content_type(FHIR_JSON)
+        .json(outcome)
+}
+
+// Route Registration ******************************************************************************
+register_route! {
+    scope: Hospital, version: 1, path: "/fhir/observation",
+    auth: Hospital, rate_limit: Some(RateScope::Ingest),
+    service: fhir_observation_handler
+}
+
+// TESTS *******************************************************************************************
+// DOCUMENTATION: Pass function only - no unit tests for route handlers
@@ -0,0 +1,72 @@
+// Imports *****************************************************************************************
+// External Crates
+use actix_web::{post, web, HttpResponse};
+use log::info;
+use std::sync::Arc;
+use validator::Validate;
+
+// Internal Modules
+use crate::authentication::auth::AuthenticatedHospital;
+use crate::authentication::rate_limit::RateScope;
+use crate::config::Settings;
+use crate::models::models_hash_check::{check_hash, HashCheckRequest, HashCheckResult};
+use crate::routes::registry::register_route;
+use crate::utils::api_error::ApiError;
+use crate::utils::response_format::NegotiatedResponse;
+
+// Constants ***************************************************************************************
+/// Deployment environment where the self-check is not served - hospitals test in dev and staging
+const PRODUCTION_ENV: &str = "prod";
+
+// Route Handlers ***********************************************************************************
+// Hash Check Handler
+#[utoipa::path(
+    post,
+    path = "/v1/tools/hash_check",
+    tag = "tools",
+    request_body = HashCheckRequest,
+    responses(
+        (status = 200, description = "Outcome of the self-check", body = HashCheckResult),
+        ApiError
+    ),
+    security(("hospital_id" = [], "hospital_key" = []), ("bearer_token" = []))
+)]
+#[post("/tools/hash_check")]
+/// Self-check of the identifier hashing of a hospital during onboarding: the candidate hash of a
+/// synthetic test vector is compared with the agreed scheme (SHA256 of salt + identifier), and a
+/// mismatch is diagnosed - sandbox only (404 in prod), nothing of the test vector is stored or
+/// logged
+/// # Arguments
+/// * `body` - The test vector and the candidate hash
+/// # Returns
+/// * An HttpResponse with `matches`, the `expected_hash` and, on a mismatch, the `diagnosis` and a
+///   `hint`
+pub async fn hash_check_handler(
+    hospital: AuthenticatedHospital,
+    settings: web::Data<Arc<Settings>>,
+    body: web::Json<HashCheckRequest>,
+) -> Result<HttpResponse, ApiError> {
+    // Prep: The hospital was authenticated by the middleware of the scope
+    if settings.deploy_env == PRODUCTION_ENV {
+        return Err(ApiError::NotFound(
+            "Hash check is only available in the sandbox",
+        ));
+    }
+    body.validate()?;
+
+    let result = check_hash(&body);
+    let outcome = if result.matches { "match" } else { "mismatch" };
+    let diagnosis = result.diagnosis.map_or("none", |d| d.as_str());
+    info!(target: "audit", "hash_check hospital_id={} outcome={outcome} diagnosis={diagnosis}", hospital.hospital_id);
+    Ok(HttpResponse::Ok().negotiated(&result))
+}
+
+// Route Registration ******************************************************************************
+register_route! {
+    scope: Hospital, version: 1, path: "/tools/hash_check",
+    auth: Hospital, rate_limit: Some(RateScope::Ingest),
+    service: hash_check_handler
+}
+
+// TESTS *******************************************************************************************
+// DOCUMENTATION: Pass function only - no unit tests for route handlers
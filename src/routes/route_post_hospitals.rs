@@ -0,0 +1,119 @@
+// Imports *****************************************************************************************
+// External Crates
+use actix_web::{post, web, HttpRequest, HttpResponse};
+use log::info;
+use serde_json::json;
+use sqlx::PgPool;
+use validator::Validate;
+
+// Internal Modules
+use crate::authentication::admin::authenticate_admin;
+use crate::authentication::rate_limit::RateScope;
+use crate::models::models_hospital_admin::{HospitalCreate, KeyIssue, KeyRotation};
+use crate::routes::registry::register_route;
+use crate::services::service_hospital_registry::{
+    create_hospital, issue_key, registry_error, rotate_keys,
+};
+use crate::utils::api_error::ApiError;
+use crate::utils::response_format::NegotiatedResponse;
+
+// Route Handlers ***********************************************************************************
+// Create Hospital Handler
+#[post("/hospitals")]
+/// Register a hospital with its settings and issue its first key - the key is only returned in
+/// this response
+/// # Arguments
+/// * `body` - The hospital id, its consent, size and rate limit tiers, allowed exam types, quota
+///   and the expiry of the first key
+/// # Returns
+/// * An HttpResponse 201 with the key, 400 if the hospital is already registered
+pub async fn create_hospital_handler(
+    req: HttpRequest,
+    body: web::Json<HospitalCreate>,
+    db_pool: web::Data<PgPool>,
+) -> Result<HttpResponse, ApiError> {
+    // Prep: Authenticate operator
+    authenticate_admin(&req).map_err(|e| ApiError::Unauthorized(e.to_string()))?;
+    body.validate()?;
+
+    let key = create_hospital(&db_pool, &body)
+        .await
+        .map_err(registry_error)?
+        .ok_or_else(|| ApiError::BadRequest("Hospital already registered".to_string()))?;
+    info!(target: "audit", "hospital_created hospital_id={}", body.hospital_id);
+    Ok(HttpResponse::Created().negotiated(&key))
+}
+
+// Issue Key Handler
+#[post("/hospitals/{hospital_id}/keys")]
+/// Issue a new key to a hospital, next to its active keys - the key is only returned in this
+/// response
+/// # Arguments
+/// * `body` - The expiry of the key, if any
+/// # Returns
+/// * An HttpResponse 201 with the key, 400 if the hospital already has the most active keys, 404
+///   if the hospital is not registered
+pub async fn issue_key_handler(
+    req: HttpRequest,
+    path: web::Path<String>,
+    body: web::Json<KeyIssue>,
+    db_pool: web::Data<PgPool>,
+) -> Result<HttpResponse, ApiError> {
+    // Prep: Authenticate operator
+    authenticate_admin(&req).map_err(|e| ApiError::Unauthorized(e.to_string()))?;
+
+    let key = issue_key(&db_pool, &path, body.expires_at)
+        .await
+        .map_err(registry_error)?
+        .ok_or(ApiError::NotFound("Hospital not registered"))?;
+    Ok(HttpResponse::Created().negotiated(&key))
+}
+
+// Rotate Keys Handler
+#[post("/hospitals/{hospital_id}/keys/rotate")]
+/// Rotate the keys of a hospital: a new key is issued and the active ones keep working for the
+/// grace period - the new key is only returned in this response
+/// # Arguments
+/// * `body` - The grace period in seconds and the expiry of the new key
+/// # Returns
+/// * An HttpResponse 201 with the new key and the ids of the retiring keys, 404 if the hospital is
+///   not registered
+pub async fn rotate_keys_handler(
+    req: HttpRequest,
+    path: web::Path<String>,
+    body: web::Json<KeyRotation>,
+    db_pool: web::Data<PgPool>,
+) -> Result<HttpResponse, ApiError> {
+    // Prep: Authenticate operator
+    authenticate_admin(&req).map_err(|e| ApiError::Unauthorized(e.to_string()))?;
+
+    let (key, retiring) = rotate_keys(&db_pool, &path, &body)
+        .await
+        .map_err(registry_error)?
+        .ok_or(ApiError::NotFound("Hospital not registered"))?;
+    info!(target: "audit", "hospital_keys_rotated hospital_id={} retiring={}", path.as_str(), retiring.join(","));
+    Ok(HttpResponse::Created().negotiated(&json!({
+        "key": key,
+        "retiring_key_ids": retiring,
+    })))
+}
+
+// Route Registration ******************************************************************************
+register_route! {
+    scope: Admin, version: 1, path: "/hospitals",
+    auth: Operator, rate_limit: Some(RateScope::Admin),
+    service: create_hospital_handler
+}
+register_route! {
+    scope: Admin, version: 1, path: "/hospitals/{hospital_id}/keys",
+    auth: Operator, rate_limit: Some(RateScope::Admin),
+    service: issue_key_handler
+}
+register_route! {
+    scope: Admin, version: 1, path: "/hospitals/{hospital_id}/keys/rotate",
+    auth: Operator, rate_limit: Some(RateScope::Admin),
+    service: rotate_keys_handler
+}
+
+// TESTS *******************************************************************************************
+// DOCUMENTATION: Pass function only - no unit tests for route handlers
@@ -0,0 +1,66 @@
+// Imports *****************************************************************************************
+// External Crates
+use actix_web::{post, web, HttpRequest, HttpResponse};
+use log::{error, info};
+use serde::Deserialize;
+use std::sync::Arc;
+
+// Internal Modules
+use crate::authentication::admin::authenticate_admin;
+use crate::authentication::rate_limit::RateScope;
+use crate::config::Settings;
+use crate::routes::registry::register_route;
+use crate::services::service_id_case_migration::merge_mixed_case_paths;
+use crate::storage::exam_storage::ExamStorage;
+use crate::utils::api_error::ApiError;
+use crate::utils::response_format::NegotiatedResponse;
+
+// Request Body ************************************************************************************
+/// Body of the id case migration
+/// # Arguments
+/// * `dry_run` - Only report the objects that would be moved (default true)
+#[derive(Debug, Deserialize)]
+pub struct IdCaseMigrationRequest {
+    #[serde(default = "default_dry_run")]
+    dry_run: bool,
+}
+
+fn default_dry_run() -> bool {
+    true
+}
+
+// Route Handlers ***********************************************************************************
+// Id Case Migration Handler
+#[post("/id_case_migration")]
+/// Merge the exams stored under mixed-case hospital/patient ids into their lowercase path
+/// # Returns
+/// * An HttpResponse with the migration report - runs can be repeated safely
+pub async fn id_case_migration_handler(
+    req: HttpRequest,
+    body: web::Json<IdCaseMigrationRequest>,
+    storage: web::Data<Arc<dyn ExamStorage>>,
+    settings: web::Data<Arc<Settings>>,
+) -> Result<HttpResponse, ApiError> {
+    // Prep: Authenticate operator
+    authenticate_admin(&req).map_err(|e| ApiError::Unauthorized(e.to_string()))?;
+    let bucket = &settings.storage.bucket_name;
+
+    info!(target: "audit", "id_case_migration started bucket={bucket} dry_run={}", body.dry_run);
+    let report = merge_mixed_case_paths(storage.as_ref().as_ref(), bucket, body.dry_run)
+        .await
+        .map_err(|e| {
+            error!("Id case migration failed: {e}");
+            ApiError::StorageFailure
+        })?;
+    Ok(HttpResponse::Ok().negotiated(&report))
+}
+
+// Route Registration ******************************************************************************
+register_route! {
+    scope: Internal, version: 1, path: "/id_case_migration",
+    auth: Operator, rate_limit: Some(RateScope::Admin),
+    service: id_case_migration_handler
+}
+
+// TESTS *******************************************************************************************
+// DOCUMENTATION: Pass function only - no unit tests for route handlers
@@ -0,0 +1,55 @@
+// Imports *****************************************************************************************
+// External Crates
+use actix_web::{post, web, HttpRequest, HttpResponse};
+use log::{info, warn};
+use serde::Deserialize;
+use serde_json::json;
+
+// Internal Modules
+use crate::authentication::admin::authenticate_admin;
+use crate::authentication::rate_limit::RateScope;
+use crate::routes::registry::register_route;
+use crate::utils::api_error::ApiError;
+use crate::utils::drain_state::{DrainReason, DRAIN_STATE};
+use crate::utils::response_format::NegotiatedResponse;
+
+// Request Body ************************************************************************************
+/// Body of the maintenance toggle
+/// # Arguments
+/// * `enabled` - true to start draining the instance, false to take traffic again
+#[derive(Debug, Deserialize)]
+pub struct MaintenanceToggle {
+    enabled: bool,
+}
+
+// Route Handlers ***********************************************************************************
+// Maintenance Handler
+#[post("/maintenance")]
+/// Toggle maintenance mode - the health check returns 503 while it is enabled
+/// # Returns
+/// * An HttpResponse with the current draining reasons
+pub async fn maintenance_handler(
+    req: HttpRequest,
+    body: web::Json<MaintenanceToggle>,
+) -> Result<HttpResponse, ApiError> {
+    // Prep: Authenticate operator
+    if let Err(e) = authenticate_admin(&req) {
+        warn!("Maintenance toggle denied: {e}");
+        return Err(ApiError::Unauthorized(e.to_string()));
+    }
+
+    DRAIN_STATE.set(DrainReason::Maintenance, body.enabled);
+    info!("Maintenance mode set to {}", body.enabled);
+    let reasons: Vec<&str> = DRAIN_STATE.reasons().iter().map(|r| r.as_str()).collect();
+    Ok(HttpResponse::Ok().negotiated(&json!({ "draining": reasons })))
+}
+
+// Route Registration ******************************************************************************
+register_route! {
+    scope: Internal, version: 1, path: "/maintenance",
+    auth: Operator, rate_limit: Some(RateScope::Admin),
+    service: maintenance_handler
+}
+
+// TESTS *******************************************************************************************
+// DOCUMENTATION: Pass function only - no unit tests for route handlers
@@ -0,0 +1,71 @@
+// Imports *****************************************************************************************
+// External Crates
+use actix_web::{post, web, HttpRequest, HttpResponse};
+use log::info;
+use serde_json::json;
+use std::sync::Arc;
+
+// Internal Modules
+use crate::authentication::admin::authenticate_admin;
+use crate::authentication::rate_limit::RateScope;
+use crate::routes::registry::register_route;
+use crate::services::service_queue_admin::{QueueAdmin, QueueStore, Selection};
+use crate::utils::api_error::ApiError;
+use crate::utils::response_format::NegotiatedResponse;
+
+// Route Handlers ***********************************************************************************
+// Queue Requeue Handler
+#[post("/queues/{store}/requeue")]
+/// Queue items again: spooled exams go back to the ingest queue, publish dead letters are
+/// published again and removed
+/// # Returns
+/// * An HttpResponse with the outcome of each selected item
+pub async fn queue_requeue_handler(
+    req: HttpRequest,
+    store: web::Path<QueueStore>,
+    body: web::Json<Selection>,
+    queue_admin: web::Data<Arc<QueueAdmin>>,
+) -> Result<HttpResponse, ApiError> {
+    // Prep: Authenticate operator
+    authenticate_admin(&req).map_err(|e| ApiError::Unauthorized(e.to_string()))?;
+
+    let ids = queue_admin.select(*store, &body).await?;
+    info!(target: "audit", "queue_requeue store={} items={}", store.as_str(), ids.len());
+    let outcomes = queue_admin.requeue(*store, &ids).await;
+    Ok(HttpResponse::Ok().negotiated(&json!({ "store": *store, "items": outcomes })))
+}
+
+// Queue Discard Handler
+#[post("/queues/{store}/discard")]
+/// Remove items for good
+/// # Returns
+/// * An HttpResponse with the outcome of each selected item
+pub async fn queue_discard_handler(
+    req: HttpRequest,
+    store: web::Path<QueueStore>,
+    body: web::Json<Selection>,
+    queue_admin: web::Data<Arc<QueueAdmin>>,
+) -> Result<HttpResponse, ApiError> {
+    // Prep: Authenticate operator
+    authenticate_admin(&req).map_err(|e| ApiError::Unauthorized(e.to_string()))?;
+
+    let ids = queue_admin.select(*store, &body).await?;
+    info!(target: "audit", "queue_discard store={} items={}", store.as_str(), ids.len());
+    let outcomes = queue_admin.discard(*store, &ids).await;
+    Ok(HttpResponse::Ok().negotiated(&json!({ "store": *store, "items": outcomes })))
+}
+
+// Route Registration ******************************************************************************
+register_route! {
+    scope: Internal, version: 1, path: "/queues/{store}/requeue",
+    auth: Operator, rate_limit: Some(RateScope::Admin),
+    service: queue_requeue_handler
+}
+register_route! {
+    scope: Internal, version: 1, path: "/queues/{store}/discard",
+    auth: Operator, rate_limit: Some(RateScope::Admin),
+    service: queue_discard_handler
+}
+
+// TESTS *******************************************************************************************
+// DOCUMENTATION: Pass function only - no unit tests for route handlers
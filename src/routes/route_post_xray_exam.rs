@@ -1,55 +1,318 @@
 // Imports *****************************************************************************************
 // External Crates
-use actix_web::{post, web, Error, HttpResponse};
+use actix_web::HttpRequest;
+use actix_web::{post, web, HttpResponse};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
 use log::{error, info};
 use serde_json::json;
+use sqlx::PgPool;
 use std::sync::Arc;
 use validator::Validate;
 
 // Internal Modules
+use crate::authentication::auth::AuthenticatedHospital;
+use crate::authentication::rate_limit::RateScope;
+use crate::config::Settings;
+use crate::models::models_deprecations::{sunset_header, DeprecatedFields};
 use crate::models::models_exams::PayloadXray;
+use crate::models::models_validation_profiles::XRAY_PROFILE;
+use crate::publishers::publisher::Publisher;
+use crate::routes::registry::register_route;
+use crate::services::service_billing::BillingService;
+use crate::services::service_dead_letter::Delivery;
+use crate::services::service_downstream_feedback::is_saturated;
+use crate::services::service_hospital_groups::{consume_monthly_quota, release_monthly_quota};
+use crate::services::service_idempotency::{
+    check_idempotency, idempotency_key, IdempotencyCheck, IdempotencyStore, StoredResponse,
+};
+use crate::services::service_rejection_digest::record_rejection;
+use crate::services::service_scan::{scan_payload, Scanner};
+use crate::services::service_wasm_plugins::PluginRegistry;
 use crate::services::service_xray_exam::handler_xray_exam;
-use google_cloud_pubsub::client::Client as PubSubClient;
-use google_cloud_storage::client::Client as GcsClient;
+use crate::storage::exam_storage::ExamStorage;
+use crate::telemetry::trace_context::start_span;
+use crate::utils::api_error::ApiError;
+use crate::utils::deprecation_usage::record_deprecated_usage;
+use crate::utils::get_headers::is_urgent;
+use crate::utils::ids::ReceiptId;
+use crate::utils::publish_backlog::{Admission, BACKLOG_RETRY_AFTER_S, PUBLISH_BACKLOG};
+use crate::utils::reason_code::ReasonCode;
+use crate::utils::request_id::request_id;
+use crate::utils::response_format::NegotiatedResponse;
+
+// Constants ***************************************************************************************
+/// Exam type key used for plugins and rejection digests
+const EXAM_TYPE: &str = "xray_exam";
 
 // Route Handlers ***********************************************************************************
 // Health Check Handler
+#[utoipa::path(
+    post,
+    path = "/v1/xray_exam",
+    tag = "exams",
+    request_body = PayloadXray,
+    params(
+        ("Idempotency-Key" = Option<String>, Header, description = "Identifies the exam across \
+            retries, at most 255 characters - the SHA256 of the JSON payload when absent"),
+        ("exam_priority" = Option<String>, Header, description = "`urgent` exams are never \
+            deferred nor throttled"),
+        ("on_behalf_of" = Option<String>, Header, description = "Clinic a hospital group submits \
+            for"),
+        ("Content-Encoding" = Option<String>, Header, description = "`gzip` or `zstd` body, \
+            limited to DECOMPRESSED_BODY_MAX_BYTES once decompressed"),
+    ),
+    responses(
+        (status = 200, description = "Exam stored and notified - or stored only, notified in the \
+            background, for `deferred` exams and hospitals answered after storage",
+            example = json!({"status": "Xray Exam Processed Successfully", "receipt_id": "0192...", "deferred": false,
+                "published": true, "warnings": []})),
+        (status = 202, description = "Exam kept for replay - storage or Pub/Sub failed"),
+        ApiError
+    ),
+    security(("hospital_id" = [], "hospital_key" = []), ("bearer_token" = []))
+)]
 #[post("/xray_exam")]
 /// Receive and process an XRay exam of a patient
 /// # Arguments
 /// * `payload` - A JSON object containing the data of the patient
+/// * `settings` - The settings of the service: exam bucket and dead letters
 /// # Returns
-/// * An HttpResponse containing a 200 OK status if the XRay exam is processed successfully
+/// * An HttpResponse containing a 200 OK status if the XRay exam is processed successfully - a
+///   retry of the same exam (same 'Idempotency-Key', or same payload) gets the original response
 pub async fn xray_exam_handler(
+    req: HttpRequest,
+    hospital: AuthenticatedHospital,
     payload: web::Json<PayloadXray>,
-    gcs_client: web::Data<Arc<GcsClient>>,
-    pubsub_client: web::Data<Arc<PubSubClient>>,
-) -> Result<HttpResponse, Error> {
+    storage: web::Data<Arc<dyn ExamStorage>>,
+    publisher: web::Data<Arc<dyn Publisher>>,
+    billing: web::Data<Arc<BillingService>>,
+    plugins: web::Data<Arc<PluginRegistry>>,
+    scanner: web::Data<Arc<dyn Scanner>>,
+    idempotency: web::Data<Arc<IdempotencyStore>>,
+    pool: web::Data<PgPool>,
+    settings: web::Data<Arc<Settings>>,
+) -> Result<HttpResponse, ApiError> {
     info!("Starting the route handler for the Xray exam processing");
+    let received_at = chrono::Utc::now();
+    // Non-urgent exams are deferred while the inference service is saturated or Pub/Sub is
+    // backlogged, and refused once the publish backlog holds too many exams
+    let admission = PUBLISH_BACKLOG.admission(is_urgent(&req));
+    let deferred = admission == Admission::Defer || (!is_urgent(&req) && is_saturated());
+    let request_id = request_id(&req);
+
+    // Prep: The hospital was authenticated by the middleware of the scope
+    let authenticated_hospital_id = hospital.hospital_id.clone();
+    let consent_scope = hospital.consent_scope;
+
+    // Prep: A retry of an exam already accepted gets its original response, nothing is re-uploaded;
+    // a retry racing the request still processing the exam waits for its response
+    let key = idempotency_key(&req, Some(&*payload));
+    let _in_flight = match check_idempotency(
+        &idempotency,
+        &authenticated_hospital_id,
+        EXAM_TYPE,
+        key.as_ref(),
+    )
+    .await
+    {
+        Ok(IdempotencyCheck::Replayed(replayed)) => return Ok(replayed),
+        Ok(IdempotencyCheck::Process(claim)) => claim,
+        Err(e) => {
+            error!("Exam still in flight or key reused - XRay Exam refused");
+            record_rejection(
+                &authenticated_hospital_id,
+                EXAM_TYPE,
+                e.reason(),
+                &request_id,
+            );
+            return Err(e);
+        }
+    };
 
-    // Prep: Authenticate hospital
-    // TODO
+    // Prep: The publish backlog is critical - the exam is refused before anything is read or stored
+    if admission == Admission::Reject {
+        error!("Publish backlog critical - XRay Exam refused");
+        record_rejection(
+            &authenticated_hospital_id,
+            EXAM_TYPE,
+            ReasonCode::PublishBacklog,
+            &request_id,
+        );
+        return Err(ApiError::RateLimited {
+            retry_after_s: BACKLOG_RETRY_AFTER_S,
+            reason: ReasonCode::PublishBacklog,
+        });
+    }
 
-    // STEP 1: Validate the payload
-    if let Err(e) = payload.validate() {
+    // STEP 1: Apply the experimental transformation plugin of the hospital, if any
+    let payload = match plugins
+        .apply(&authenticated_hospital_id, EXAM_TYPE, payload.into_inner())
+        .await
+    {
+        Ok(payload) => payload,
+        Err(e) => {
+            error!("WASM plugin error - XRay Exam: {}", e);
+            record_rejection(
+                &authenticated_hospital_id,
+                EXAM_TYPE,
+                ReasonCode::PluginFailed,
+                &request_id,
+            );
+            return Err(ApiError::PluginFailed);
+        }
+    };
+
+    // STEP 2: Check the payload belongs to the authenticated hospital, then validate it - the
+    // decision is audited with the profile version applied
+    if let Err(e) = hospital.check_payload(&payload.hospital_id) {
+        error!("Authentication error - XRay Exam: {}", e);
+        record_rejection(
+            &authenticated_hospital_id,
+            EXAM_TYPE,
+            e.reason(),
+            &request_id,
+        );
+        return Err(e);
+    }
+    let profile = XRAY_PROFILE.label();
+    let validation = {
+        let _span = start_span("validation");
+        payload.validate()
+    };
+    if let Err(e) = validation {
         error!("Validation error - XRay Exam: {}", e);
-        return Ok(HttpResponse::BadRequest().json(json!({ "error": "Invalid Input" })));
+        let error = ApiError::from(e);
+        let reason = error.reason();
+        record_rejection(&authenticated_hospital_id, EXAM_TYPE, reason, &request_id);
+        info!(target: "audit", "exam_validation exam_type=xray_exam hospital_id={} profile={profile} decision=rejected reason={reason}", payload.hospital_id);
+        return Err(error);
     }
+    info!(target: "audit", "exam_validation exam_type=xray_exam hospital_id={} profile={profile} decision=accepted", payload.hospital_id);
 
-    // STEP 2: Extract data from payload, process and log it, then return response
-    let data = payload.into_inner();
-    match handler_xray_exam(data, &gcs_client, &pubsub_client).await {
-        Ok(_) => {
+    // STEP 3: Scan the decoded image for malware before anything is stored
+    if scanner.is_enabled() {
+        let image = STANDARD
+            .decode(&payload.image)
+            .map_err(|e| ApiError::BadRequest(format!("Invalid image encoding: {e}")))?;
+        let scanned = scan_payload(
+            scanner.get_ref().as_ref(),
+            &image,
+            EXAM_TYPE,
+            &payload.hospital_id,
+        )
+        .await;
+        if let Err(e) = scanned {
+            error!("Malware scan refused - XRay Exam: {}", e);
+            record_rejection(
+                &authenticated_hospital_id,
+                EXAM_TYPE,
+                e.reason(),
+                &request_id,
+            );
+            return Err(e);
+        }
+    }
+
+    // Deprecated fields are accepted until their sunset - usage is recorded and the hospital warned
+    let deprecated = payload.deprecated_fields_used();
+    record_deprecated_usage(&payload.hospital_id, &deprecated);
+    let warnings: Vec<String> = deprecated.iter().map(|d| d.warning()).collect();
+
+    // STEP 4: Count the exam against the monthly quotas - replays and refused exams are never
+    // counted, an exam that could not be stored nor kept for replay is given back
+    if let Err(e) = consume_monthly_quota(&hospital, &pool, received_at).await {
+        error!("Monthly quota used up - XRay Exam refused");
+        record_rejection(
+            &authenticated_hospital_id,
+            EXAM_TYPE,
+            e.reason(),
+            &request_id,
+        );
+        return Err(e);
+    }
+
+    // STEP 5: Process the payload and log it, then return response
+    let data = payload;
+    match handler_xray_exam(
+        data,
+        &storage,
+        &settings.storage,
+        &publisher,
+        &billing,
+        deferred,
+        received_at,
+        consent_scope,
+        key.as_ref().map(|key| key.value.clone()),
+        hospital.publish_mode,
+    )
+    .await
+    {
+        Ok(Delivery::DeadLettered) => {
+            // Not stored or not notified yet, but kept for replay: the hospital must not resend
+            info!("End of the route handler for the XRay exam processing - Dead-lettered");
+            let body = json!({
+                "status": "Xray Exam Accepted for Replay",
+                "receipt_id": ReceiptId::generate(),
+                "deferred": true,
+                "warnings": warnings,
+            });
+            if let Some(key) = &key {
+                let accepted = StoredResponse {
+                    status: 202,
+                    body: body.clone(),
+                };
+                idempotency
+                    .remember(&authenticated_hospital_id, EXAM_TYPE, key, accepted)
+                    .await;
+            }
+            Ok(HttpResponse::Accepted().negotiated(&body))
+        }
+        Ok(delivery) => {
             info!("End of the route handler for the XRay exam processing - Success");
-            Ok(HttpResponse::Ok().json(json!({ "status": "Xray Exam Processed Successfully" })))
+            let body = json!({
+                "status": "Xray Exam Processed Successfully",
+                "receipt_id": ReceiptId::generate(),
+                "deferred": deferred,
+                "published": delivery == Delivery::Published,
+                "warnings": warnings,
+            });
+            if let Some(key) = &key {
+                let processed = StoredResponse {
+                    status: 200,
+                    body: body.clone(),
+                };
+                idempotency
+                    .remember(&authenticated_hospital_id, EXAM_TYPE, key, processed)
+                    .await;
+            }
+            let mut response = HttpResponse::Ok();
+            if let Some(sunset) = sunset_header(&deprecated) {
+                response.insert_header(("Sunset", sunset));
+            }
+            Ok(response.negotiated(&body))
         }
         Err(e) => {
             error!("Error while processing XRay Exam: {}", e);
-            Ok(HttpResponse::InternalServerError().json(json!({ "error": "Processing Error" })))
+            release_monthly_quota(&hospital, &pool, received_at).await;
+            let error = ApiError::from(e);
+            record_rejection(
+                &authenticated_hospital_id,
+                EXAM_TYPE,
+                error.reason(),
+                &request_id,
+            );
+            Err(error)
         }
     }
 }
 
+// Route Registration ******************************************************************************
+register_route! {
+    scope: Hospital, version: 1, path: "/xray_exam",
+    auth: Hospital, rate_limit: Some(RateScope::Ingest),
+    service: xray_exam_handler
+}
+
 // TESTS *******************************************************************************************
 // DOCUMENTATION: Pass function only - no unit tests for route handlers
-
@@ -0,0 +1,446 @@
+// Imports *****************************************************************************************
+// External Crates
+use actix_multipart::Multipart;
+use actix_web::web::Bytes;
+use actix_web::{mime, post, web, HttpMessage, HttpRequest, HttpResponse};
+use futures_util::future::join;
+use futures_util::{stream, StreamExt};
+use log::{error, info};
+use serde_json::json;
+use sqlx::PgPool;
+use std::sync::Arc;
+use validator::Validate;
+
+// Internal Modules
+use crate::authentication::auth::AuthenticatedHospital;
+use crate::authentication::rate_limit::RateScope;
+use crate::config::Settings;
+use crate::models::models_exams::{XrayImageFormat, XrayUploadMetadata, XRAY_SIGNATURE_BYTES};
+use crate::models::models_validation_profiles::XRAY_UPLOAD_PROFILE;
+use crate::publishers::publisher::Publisher;
+use crate::routes::registry::register_route;
+use crate::services::service_billing::BillingService;
+use crate::services::service_dead_letter::Delivery;
+use crate::services::service_dicom::{deidentify_dicom, handler_dicom_exam, DeidentifiedDicom};
+use crate::services::service_downstream_feedback::is_saturated;
+use crate::services::service_hospital_groups::{consume_monthly_quota, release_monthly_quota};
+use crate::services::service_idempotency::{
+    check_idempotency, idempotency_key, IdempotencyCheck, IdempotencyStore, StoredResponse,
+};
+use crate::services::service_rejection_digest::record_rejection;
+use crate::services::service_scan::{scan_payload, Scanner};
+use crate::services::service_xray_exam::{handler_xray_upload, XrayUpload};
+use crate::storage::exam_storage::ExamStorage;
+use crate::telemetry::trace_context::start_span;
+use crate::utils::api_error::ApiError;
+use crate::utils::get_headers::is_urgent;
+use crate::utils::ids::ReceiptId;
+use crate::utils::publish_backlog::{Admission, BACKLOG_RETRY_AFTER_S, PUBLISH_BACKLOG};
+use crate::utils::reason_code::ReasonCode;
+use crate::utils::request_id::request_id;
+use crate::utils::response_format::NegotiatedResponse;
+use crate::utils::upload_stream::{
+    collect_chunks, multipart_error, payload_error, pump_chunks, read_head, upload_channel,
+    UploadChunks,
+};
+
+// Constants ***************************************************************************************
+/// Exam type key used for rejection digests
+const EXAM_TYPE: &str = "xray_exam";
+/// Largest metadata part of a multipart upload
+const METADATA_MAX_BYTES: usize = 16 * 1024;
+
+// Structs *****************************************************************************************
+/// Image of an upload whose signature was checked
+enum UploadImage {
+    /// PNG or JPEG: its leading bytes and the rest of the body, streamed to storage
+    Streamed(Bytes, UploadChunks),
+    /// DICOM: de-identified in memory before storage
+    Dicom(DeidentifiedDicom),
+}
+
+// Route Handlers ***********************************************************************************
+// XRay upload Handler
+#[utoipa::path(
+    post,
+    path = "/v1/xray_exam/upload",
+    tag = "exams",
+    request_body(
+        description = "`multipart/form-data` with a `metadata` JSON part (XrayUploadMetadata) \
+            then an `image` part, or the raw image with the metadata as query parameters - at most \
+            XRAY_UPLOAD_MAX_BYTES, never compressed",
+        content(
+            (XrayUploadMetadata = "multipart/form-data"),
+            (Vec<u8> = "image/png"),
+            (Vec<u8> = "image/jpeg"),
+            (Vec<u8> = "application/dicom")
+        )
+    ),
+    params(
+        XrayUploadMetadata,
+        ("Idempotency-Key" = Option<String>, Header, description = "Identifies the exam across \
+            retries, at most 255 characters - the streamed body is not hashed"),
+        ("exam_priority" = Option<String>, Header, description = "`urgent` exams are never \
+            deferred nor throttled"),
+        ("on_behalf_of" = Option<String>, Header, description = "Clinic a hospital group submits \
+            for"),
+    ),
+    responses(
+        (status = 200, description = "Exam stored and notified - or stored only, notified in the \
+            background, for `deferred` exams and hospitals answered after storage",
+            example = json!({"status": "Xray Exam Processed Successfully", "receipt_id": "0192...", "deferred": false,
+                "published": true})),
+        (status = 202, description = "Exam kept for replay - Pub/Sub failed"),
+        ApiError
+    ),
+    security(("hospital_id" = [], "hospital_key" = []), ("bearer_token" = []))
+)]
+#[post("/xray_exam/upload")]
+/// Receive a large XRay image as a stream, stored without being buffered in memory - DICOM files
+/// are buffered (within the upload limit) to be de-identified first, and every image is buffered
+/// when a malware scanner is configured, so nothing is stored before it is scanned
+/// - `multipart/form-data`: a `metadata` JSON part followed by an `image` part
+/// - `image/png`, `image/jpeg` or `application/dicom`: the raw image, with the metadata as query
+///   parameters
+/// # Arguments
+/// * `payload` - The body of the request, limited by XRAY_UPLOAD_MAX_BYTES
+/// * `settings` - The settings of the service: exam bucket, dead letters and DICOM salt
+/// # Returns
+/// * An HttpResponse containing a 200 OK status if the XRay exam is stored and notified - a retry
+///   with the same 'Idempotency-Key' gets the original response (the streamed body is not hashed)
+pub async fn xray_upload_handler(
+    req: HttpRequest,
+    hospital: AuthenticatedHospital,
+    payload: web::Payload,
+    storage: web::Data<Arc<dyn ExamStorage>>,
+    publisher: web::Data<Arc<dyn Publisher>>,
+    billing: web::Data<Arc<BillingService>>,
+    scanner: web::Data<Arc<dyn Scanner>>,
+    idempotency: web::Data<Arc<IdempotencyStore>>,
+    pool: web::Data<PgPool>,
+    settings: web::Data<Arc<Settings>>,
+) -> Result<HttpResponse, ApiError> {
+    info!("Starting the route handler for the Xray upload");
+    let received_at = chrono::Utc::now();
+    // Non-urgent exams are deferred while the inference service is saturated or Pub/Sub is
+    // backlogged, and refused once the publish backlog holds too many exams
+    let admission = PUBLISH_BACKLOG.admission(is_urgent(&req));
+    let deferred = admission == Admission::Defer || (!is_urgent(&req) && is_saturated());
+    let request_id = request_id(&req);
+    let reject = |reason| record_rejection(&hospital.hospital_id, EXAM_TYPE, reason, &request_id);
+
+    // Prep: A retry of an upload already accepted gets its original response, before its body
+    // is read; a retry racing the request still processing the upload waits for its response
+    let key = idempotency_key::<XrayUploadMetadata>(&req, None);
+    let _in_flight =
+        match check_idempotency(&idempotency, &hospital.hospital_id, EXAM_TYPE, key.as_ref()).await
+        {
+            Ok(IdempotencyCheck::Replayed(replayed)) => return Ok(replayed),
+            Ok(IdempotencyCheck::Process(claim)) => claim,
+            Err(e) => {
+                error!("Exam still in flight or key reused - XRay Exam refused");
+                reject(e.reason());
+                return Err(e);
+            }
+        };
+
+    // Prep: The publish backlog is critical - the exam is refused before anything is read or stored
+    if admission == Admission::Reject {
+        error!("Publish backlog critical - XRay Exam refused");
+        reject(ReasonCode::PublishBacklog);
+        return Err(ApiError::RateLimited {
+            retry_after_s: BACKLOG_RETRY_AFTER_S,
+            reason: ReasonCode::PublishBacklog,
+        });
+    }
+
+    // STEP 1: Read the metadata and the declared format - the multipart body must outlive its parts
+    let mut multipart = None;
+    let content_type = req.mime_type().ok().flatten();
+    let read = match content_type {
+        Some(mime) if mime.essence_str() == mime::MULTIPART_FORM_DATA.essence_str() => {
+            read_multipart(multipart.insert(Multipart::new(req.headers(), payload))).await
+        }
+        _ => read_raw(&req, content_type.as_ref(), payload),
+    };
+    let (metadata, format, chunks) = read.inspect_err(|e| {
+        error!("Invalid upload - XRay Exam: {}", e);
+        reject(e.reason());
+    })?;
+
+    // STEP 2: Check the metadata belongs to the authenticated hospital, then validate it - the
+    // decision is audited with the profile version applied
+    if let Err(e) = hospital.check_payload(&metadata.hospital_id) {
+        error!("Authentication error - XRay Exam: {}", e);
+        reject(e.reason());
+        return Err(e);
+    }
+    let profile = XRAY_UPLOAD_PROFILE.label();
+    let validation = {
+        let _span = start_span("validation");
+        metadata.validate()
+    };
+    let (head, chunks) = match validation {
+        Ok(()) => read_head(chunks, XRAY_SIGNATURE_BYTES).await?,
+        Err(e) => {
+            error!("Validation error - XRay Exam: {}", e);
+            let error = ApiError::from(e);
+            let reason = error.reason();
+            reject(reason);
+            info!(target: "audit", "exam_validation exam_type=xray_exam hospital_id={} profile={profile} decision=rejected reason={reason}", metadata.hospital_id);
+            return Err(error);
+        }
+    };
+    if !format.matches_signature(&head) {
+        error!("Validation error - XRay Exam: image does not match {format:?}");
+        reject(ReasonCode::ImageFormat);
+        info!(target: "audit", "exam_validation exam_type=xray_exam hospital_id={} profile={profile} decision=rejected reason={}", metadata.hospital_id, ReasonCode::ImageFormat);
+        return Err(ApiError::InvalidContent(
+            ReasonCode::ImageFormat,
+            format!(
+                "Image does not match its content type {}",
+                format.content_type()
+            ),
+        ));
+    }
+
+    // STEP 3: Scan the whole image for malware before anything is stored
+    let (head, chunks) = if scanner.is_enabled() {
+        let file = collect_chunks(head, chunks).await?;
+        let scanned = scan_payload(
+            scanner.get_ref().as_ref(),
+            &file,
+            EXAM_TYPE,
+            &metadata.hospital_id,
+        )
+        .await;
+        if let Err(e) = scanned {
+            error!("Malware scan refused - XRay Exam: {}", e);
+            reject(e.reason());
+            return Err(e);
+        }
+        (file, stream::empty().boxed_local())
+    } else {
+        (head, chunks)
+    };
+
+    // STEP 4: DICOM files carry the patient identity: they are de-identified before storage
+    let image = if format == XrayImageFormat::Dicom {
+        let file = collect_chunks(head, chunks).await?;
+        let salt = settings.ingest.dicom_pseudonym_salt.as_ref();
+        match deidentify_dicom(&file, &metadata.hospital_id, &metadata.patient_id, salt) {
+            Ok(dicom) => UploadImage::Dicom(dicom),
+            Err(e) => {
+                error!("Validation error - XRay Exam: invalid DICOM file: {}", e);
+                reject(ReasonCode::DicomInvalid);
+                info!(target: "audit", "exam_validation exam_type=xray_exam hospital_id={} profile={profile} decision=rejected reason={}", metadata.hospital_id, ReasonCode::DicomInvalid);
+                return Err(ApiError::InvalidContent(
+                    ReasonCode::DicomInvalid,
+                    format!("Invalid DICOM file: {e}"),
+                ));
+            }
+        }
+    } else {
+        UploadImage::Streamed(head, chunks)
+    };
+    info!(target: "audit", "exam_validation exam_type=xray_exam hospital_id={} profile={profile} decision=accepted", metadata.hospital_id);
+
+    // STEP 5: Count the exam against the monthly quotas - replays and refused exams are never
+    // counted, an upload interrupted or not stored is given back
+    if let Err(e) = consume_monthly_quota(&hospital, &pool, received_at).await {
+        error!("Monthly quota used up - XRay Exam refused");
+        reject(e.reason());
+        return Err(e);
+    }
+
+    // STEP 6: Store the image - streamed while it is received - then notify it
+    let upload = XrayUpload {
+        metadata,
+        format,
+        deferred,
+        received_at,
+        consent_scope: hospital.consent_scope,
+        image_width: None,
+        image_height: None,
+        dicom: None,
+        idempotency_key: key.as_ref().map(|key| key.value.clone()),
+        publish_mode: hospital.publish_mode,
+    };
+    let stored = match image {
+        UploadImage::Dicom(dicom) => {
+            handler_dicom_exam(
+                upload,
+                dicom,
+                &storage,
+                &settings.storage,
+                &publisher,
+                &billing,
+            )
+            .await
+        }
+        UploadImage::Streamed(head, chunks) => {
+            let (sender, image) = upload_channel();
+            let (stored, pumped) = join(
+                handler_xray_upload(
+                    upload,
+                    image,
+                    &storage,
+                    &settings.storage,
+                    &publisher,
+                    &billing,
+                ),
+                pump_chunks(head, chunks, sender),
+            )
+            .await;
+            if let Err(e) = pumped {
+                // The body was cut or interrupted: the upload was aborted, nothing was stored
+                error!("Upload interrupted - XRay Exam: {}", e);
+                release_monthly_quota(&hospital, &pool, received_at).await;
+                reject(e.reason());
+                return Err(e);
+            }
+            stored
+        }
+    };
+    drop(multipart);
+    match stored {
+        Ok(Delivery::DeadLettered) => {
+            // Stored but not notified yet, kept for replay: the hospital must not resend
+            info!("End of the route handler for the XRay upload - Dead-lettered");
+            let body = json!({
+                "status": "Xray Exam Accepted for Replay",
+                "receipt_id": ReceiptId::generate(),
+                "deferred": true,
+            });
+            if let Some(key) = &key {
+                let accepted = StoredResponse {
+                    status: 202,
+                    body: body.clone(),
+                };
+                idempotency
+                    .remember(&hospital.hospital_id, EXAM_TYPE, key, accepted)
+                    .await;
+            }
+            Ok(HttpResponse::Accepted().negotiated(&body))
+        }
+        Ok(delivery) => {
+            info!("End of the route handler for the XRay upload - Success");
+            let body = json!({
+                "status": "Xray Exam Processed Successfully",
+                "receipt_id": ReceiptId::generate(),
+                "deferred": deferred,
+                "published": delivery == Delivery::Published,
+            });
+            if let Some(key) = &key {
+                let processed = StoredResponse {
+                    status: 200,
+                    body: body.clone(),
+                };
+                idempotency
+                    .remember(&hospital.hospital_id, EXAM_TYPE, key, processed)
+                    .await;
+            }
+            Ok(HttpResponse::Ok().negotiated(&body))
+        }
+        Err(e) => {
+            error!("Error while processing XRay upload: {}", e);
+            release_monthly_quota(&hospital, &pool, received_at).await;
+            let error = ApiError::from(e);
+            reject(error.reason());
+            Err(error)
+        }
+    }
+}
+
+// SUPPORT FUNCTIONS *******************************************************************************
+/// Metadata and image of a multipart upload - the metadata part must come first, so it is checked
+/// before the image is read
+/// # Arguments
+/// * `multipart` - The multipart body
+/// # Returns
+/// * The metadata, the declared format of the image and the chunks of the image part
+/// # Errors
+/// * Returns BadRequest if a part is missing, out of order or malformed
+async fn read_multipart(
+    multipart: &mut Multipart,
+) -> Result<(XrayUploadMetadata, XrayImageFormat, UploadChunks), ApiError> {
+    // STEP 1: The metadata part, bounded
+    let mut field = next_part(multipart, "metadata").await?;
+    let mut raw = Vec::new();
+    while let Some(chunk) = field.next().await {
+        raw.extend_from_slice(&chunk.map_err(multipart_error)?);
+        if raw.len() > METADATA_MAX_BYTES {
+            return Err(ApiError::BadRequest(
+                "Metadata part is too large".to_string(),
+            ));
+        }
+    }
+    let metadata = serde_json::from_slice(&raw)
+        .map_err(|e| ApiError::BadRequest(format!("Invalid metadata part: {e}")))?;
+
+    // STEP 2: The image part, streamed
+    let field = next_part(multipart, "image").await?;
+    let format = field
+        .content_type()
+        .and_then(|mime| XrayImageFormat::from_content_type(mime.essence_str()))
+        .ok_or_else(unsupported_format)?;
+    let chunks = field
+        .map(|chunk| chunk.map_err(multipart_error))
+        .boxed_local();
+    Ok((metadata, format, chunks))
+}
+
+/// Next part of a multipart body, which must have the expected name
+async fn next_part(
+    multipart: &mut Multipart,
+    name: &str,
+) -> Result<actix_multipart::Field, ApiError> {
+    match multipart.next().await {
+        Some(Ok(field)) if field.name() == Some(name) => Ok(field),
+        Some(Err(e)) => Err(multipart_error(e)),
+        _ => Err(ApiError::BadRequest(format!(
+            "Expected a '{name}' part, in the order metadata then image"
+        ))),
+    }
+}
+
+/// Metadata and image of a raw upload: the metadata is sent as query parameters
+/// # Arguments
+/// * `req` - The request, for its query string
+/// * `content_type` - The content type of the body
+/// * `payload` - The body
+/// # Errors
+/// * Returns BadRequest if the query or the content type is not accepted
+fn read_raw(
+    req: &HttpRequest,
+    content_type: Option<&mime::Mime>,
+    payload: web::Payload,
+) -> Result<(XrayUploadMetadata, XrayImageFormat, UploadChunks), ApiError> {
+    let format = content_type
+        .and_then(|mime| XrayImageFormat::from_content_type(mime.essence_str()))
+        .ok_or_else(unsupported_format)?;
+    let metadata = web::Query::<XrayUploadMetadata>::from_query(req.query_string())
+        .map_err(|e| ApiError::BadRequest(format!("Invalid metadata parameters: {e}")))?
+        .into_inner();
+    let chunks = payload
+        .map(|chunk| chunk.map_err(payload_error))
+        .boxed_local();
+    Ok((metadata, format, chunks))
+}
+
+/// Error of an image sent with a content type that is not accepted
+fn unsupported_format() -> ApiError {
+    ApiError::BadRequest(
+        "Image must be sent as image/png, image/jpeg or application/dicom".to_string(),
+    )
+}
+
+// Route Registration ******************************************************************************
+register_route! {
+    scope: Hospital, version: 1, path: "/xray_exam/upload",
+    auth: Hospital, rate_limit: Some(RateScope::Ingest),
+    service: xray_upload_handler
+}
+
+// TESTS *******************************************************************************************
+// DOCUMENTATION: Pass function only - no unit tests for route handlers
@@ -1,2 +1,34 @@
+pub mod service_autoscale_hint;
+pub mod service_billing;
+pub mod service_compatibility;
+pub mod service_confirmation_webhook;
+pub mod service_dead_letter;
+pub mod service_dicom;
+pub mod service_downstream_feedback;
 pub mod service_ecg_exam;
-pub mod service_xray_exam;
\ No newline at end of file
+pub mod service_exam_export;
+pub mod service_exam_retention;
+pub mod service_hospital_groups;
+pub mod service_hospital_registry;
+pub mod service_id_case_migration;
+pub mod service_idempotency;
+pub mod service_ingest_queue;
+pub mod service_ingest_spool;
+pub mod service_message_format;
+pub mod service_migrations;
+pub mod service_parquet_schemas;
+#[cfg(feature = "grpc")]
+pub mod service_pubsub_router;
+pub mod service_queue_admin;
+pub mod service_readiness;
+pub mod service_registry_events;
+pub mod service_rejection_digest;
+pub mod service_research_sampling;
+pub mod service_scan;
+#[cfg(feature = "scanner")]
+pub mod service_scan_network;
+pub mod service_storage_gc;
+pub mod service_tenant_stats;
+pub mod service_wasm_plugins;
+pub mod service_xray_exam;
+pub mod service_zstd_dictionaries;
@@ -0,0 +1,240 @@
+// Imports *****************************************************************************************
+// External Crates
+use serde::Serialize;
+
+// Internal Modules
+use crate::services::service_ingest_queue::IngestQueue;
+use crate::telemetry::middleware::requests_in_flight;
+use crate::utils::drain_state::DRAIN_STATE;
+use crate::utils::publish_backlog::{BacklogState, PUBLISH_BACKLOG};
+
+// Constants ***************************************************************************************
+/// Saturation from which more instances are asked for
+const SCALE_OUT_AT: f64 = 0.75;
+/// Saturation up to which the instance could be removed
+const SCALE_IN_AT: f64 = 0.25;
+/// Memory of the container (cgroup v2), then of the cgroup v1 hierarchy
+const CGROUP_MEMORY: [(&str, &str); 2] = [
+    ("/sys/fs/cgroup/memory.current", "/sys/fs/cgroup/memory.max"),
+    (
+        "/sys/fs/cgroup/memory/memory.usage_in_bytes",
+        "/sys/fs/cgroup/memory/memory.limit_in_bytes",
+    ),
+];
+/// cgroup v1 reports "no limit" as a page-rounded i64::MAX - anything above is no limit
+const NO_MEMORY_LIMIT: u64 = 1 << 60;
+
+// Structs *****************************************************************************************
+/// Scaling recommendation of an instance
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ScaleHint {
+    /// Saturation from SCALE_OUT_AT (or the instance is draining): add instances
+    ScaleOut,
+    /// Keep the current instances
+    Hold,
+    /// Saturation up to SCALE_IN_AT: the instance could be removed
+    ScaleIn,
+}
+
+/// Saturation signals of the instance, flat so an external autoscaler reads any field by name
+/// (e.g. KEDA's `metrics-api` scaler with `valueLocation: saturation`)
+/// # Arguments
+/// * `saturation` - The highest of the pressures below, from 0 to 1
+/// * `hint` - The recommendation derived from the saturation
+/// * `draining` - The instance refuses new work (maintenance, shutdown)
+/// * `queue_depth` - Exams queued or being processed by the ingest workers
+/// * `queue_capacity` - Exams the ingest queue holds before refusing new ones
+/// * `requests_in_flight` - HTTP requests being handled
+/// * `publish_backlog` - Notifications held in memory (in flight or deferred)
+/// * `publish_pressure` - Share of PUBLISH_BACKLOG_REJECT_AT held in memory
+/// * `memory_used_bytes` - Memory of the container, or resident memory of the process
+/// * `memory_limit_bytes` - Memory limit of the container, or memory of the host
+/// * `memory_headroom` - Share of the memory limit still free, None when unknown
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct AutoscaleHint {
+    pub saturation: f64,
+    pub hint: ScaleHint,
+    pub draining: bool,
+    pub queue_depth: usize,
+    pub queue_capacity: usize,
+    pub requests_in_flight: usize,
+    pub publish_backlog: u64,
+    pub publish_pressure: f64,
+    pub memory_used_bytes: Option<u64>,
+    pub memory_limit_bytes: Option<u64>,
+    pub memory_headroom: Option<f64>,
+}
+
+// MAIN FUNCTIONS **********************************************************************************
+/// Current saturation signals of this instance - the ingest queue, the requests being handled,
+/// the publish backlog and the memory headroom - and the scaling they call for
+/// # Arguments
+/// * `ingest_queue` - The queue of the exams to store and publish
+pub fn autoscale_hint(ingest_queue: &IngestQueue) -> AutoscaleHint {
+    let (memory_used_bytes, memory_limit_bytes) = memory_usage();
+    hint_of(AutoscaleHint {
+        saturation: 0.0,
+        hint: ScaleHint::Hold,
+        draining: !DRAIN_STATE.reasons().is_empty(),
+        queue_depth: ingest_queue.in_flight(),
+        queue_capacity: ingest_queue.capacity(),
+        requests_in_flight: requests_in_flight(),
+        publish_backlog: PUBLISH_BACKLOG.count(BacklogState::InFlight)
+            + PUBLISH_BACKLOG.count(BacklogState::Deferred),
+        publish_pressure: PUBLISH_BACKLOG.pressure(),
+        memory_used_bytes,
+        memory_limit_bytes,
+        memory_headroom: None,
+    })
+}
+
+/// Resident memory of the process (VmRSS), None where /proc is not available
+pub fn resident_memory_bytes() -> Option<u64> {
+    std::fs::read_to_string("/proc/self/status")
+        .ok()
+        .as_deref()
+        .and_then(|text| kib_field(text, "VmRSS:"))
+}
+
+// SUPPORT FUNCTIONS *******************************************************************************
+/// Complete the signals with the memory headroom, the saturation and the recommendation
+fn hint_of(mut signals: AutoscaleHint) -> AutoscaleHint {
+    // STEP 1: Pressure of each signal, from 0 to 1
+    signals.memory_headroom = match (signals.memory_used_bytes, signals.memory_limit_bytes) {
+        (Some(used), Some(limit)) if limit > 0 => {
+            Some(round(1.0 - (used as f64 / limit as f64).min(1.0)))
+        }
+        _ => None,
+    };
+    let queue_pressure =
+        (signals.queue_depth as f64 / signals.queue_capacity.max(1) as f64).min(1.0);
+    let memory_pressure = signals
+        .memory_headroom
+        .map_or(0.0, |headroom| 1.0 - headroom);
+
+    // STEP 2: The instance is as saturated as its most saturated stage
+    signals.publish_pressure = round(signals.publish_pressure);
+    signals.saturation = round(
+        queue_pressure
+            .max(signals.publish_pressure)
+            .max(memory_pressure),
+    );
+    signals.hint = if signals.draining || signals.saturation >= SCALE_OUT_AT {
+        ScaleHint::ScaleOut
+    } else if signals.saturation <= SCALE_IN_AT {
+        ScaleHint::ScaleIn
+    } else {
+        ScaleHint::Hold
+    };
+    signals
+}
+
+/// Memory used and its limit: the ones of the container (cgroup v2, then v1), else the resident
+/// memory of the process - the memory of the host when the container has no limit
+fn memory_usage() -> (Option<u64>, Option<u64>) {
+    let read = |path: &str| std::fs::read_to_string(path).ok();
+    let host_memory = || {
+        read("/proc/meminfo")
+            .as_deref()
+            .and_then(|text| kib_field(text, "MemTotal:"))
+    };
+    for (current, max) in CGROUP_MEMORY {
+        if let Some(used) = read(current).and_then(|text| text.trim().parse().ok()) {
+            let limit = read(max).as_deref().and_then(memory_limit);
+            return (Some(used), limit.or_else(host_memory));
+        }
+    }
+    (resident_memory_bytes(), host_memory())
+}
+
+/// Memory limit of a cgroup file - None for `max` (v2) or the v1 "no limit" value
+fn memory_limit(text: &str) -> Option<u64> {
+    text.trim()
+        .parse::<u64>()
+        .ok()
+        .filter(|limit| *limit < NO_MEMORY_LIMIT)
+}
+
+/// Bytes of a `Name:   1234 kB` line of a /proc file
+fn kib_field(text: &str, name: &str) -> Option<u64> {
+    text.lines()
+        .find_map(|line| line.strip_prefix(name))
+        .and_then(|value| value.split_whitespace().next())
+        .and_then(|kib| kib.parse::<u64>().ok())
+        .map(|kib| kib * 1024)
+}
+
+/// Round a ratio to 3 decimals, for a compact body
+fn round(ratio: f64) -> f64 {
+    (ratio * 1000.0).round() / 1000.0
+}
+
+// TESTS *******************************************************************************************
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn signals(queue_depth: usize, publish_pressure: f64, used: Option<u64>) -> AutoscaleHint {
+        AutoscaleHint {
+            saturation: 0.0,
+            hint: ScaleHint::Hold,
+            draining: false,
+            queue_depth,
+            queue_capacity: 100,
+            requests_in_flight: 3,
+            publish_backlog: 0,
+            publish_pressure,
+            memory_used_bytes: used,
+            memory_limit_bytes: Some(1_000),
+            memory_headroom: None,
+        }
+    }
+
+    // Happy path: the most saturated stage drives the recommendation
+    #[test]
+    fn saturation_of_busiest_stage() {
+        let idle = hint_of(signals(10, 0.0, Some(100)));
+        assert_eq!(idle.saturation, 0.1);
+        assert_eq!(idle.memory_headroom, Some(0.9));
+        assert_eq!(idle.hint, ScaleHint::ScaleIn);
+
+        let queued = hint_of(signals(80, 0.1, Some(100)));
+        assert_eq!((queued.saturation, queued.hint), (0.8, ScaleHint::ScaleOut));
+        let backlogged = hint_of(signals(10, 0.5, Some(100)));
+        assert_eq!(
+            (backlogged.saturation, backlogged.hint),
+            (0.5, ScaleHint::Hold)
+        );
+        let memory_bound = hint_of(signals(10, 0.0, Some(900)));
+        assert_eq!(memory_bound.hint, ScaleHint::ScaleOut);
+    }
+
+    // Borderline: an overfull queue, unknown memory and a draining instance
+    #[test]
+    fn borderline_signals() {
+        let overfull = hint_of(signals(150, 0.0, None));
+        assert_eq!(overfull.saturation, 1.0);
+        assert_eq!(overfull.memory_headroom, None);
+        let draining = hint_of(AutoscaleHint {
+            draining: true,
+            ..signals(0, 0.0, None)
+        });
+        assert_eq!(draining.hint, ScaleHint::ScaleOut);
+        assert_eq!(
+            serde_json::to_value(&draining).unwrap()["hint"],
+            "scale_out"
+        );
+    }
+
+    // Happy path: memory read from the cgroup and /proc formats
+    #[test]
+    fn memory_files_parsed() {
+        assert_eq!(memory_limit("536870912\n"), Some(536_870_912));
+        assert_eq!(memory_limit("max\n"), None);
+        assert_eq!(memory_limit("9223372036854771712\n"), None);
+        let status = "Name:\tsentinela\nVmPeak:\t  2048 kB\nVmRSS:\t  1024 kB\n";
+        assert_eq!(kib_field(status, "VmRSS:"), Some(1_048_576));
+        assert_eq!(kib_field(status, "VmSwap:"), None);
+    }
+}
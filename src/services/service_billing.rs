@@ -0,0 +1,239 @@
+// Imports *****************************************************************************************
+// External Crates
+use chrono::{DateTime, Utc};
+use log::{info, warn};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+
+// Internal Modules
+use crate::authentication::gcp_identity::GcpIdentity;
+use crate::config::settings::SinkTarget;
+use crate::publishers::publisher::PubSubClients;
+use crate::services::service_hospital_groups::group_of;
+use crate::sinks::event_sink::{sink_of, EventSink};
+use crate::telemetry::metrics::record_exam_accepted;
+
+// Structs *****************************************************************************************
+/// Usage event emitted for every accepted exam
+/// # Arguments
+/// * `hospital_id` - The hospital id (SHA256 hash)
+/// * `exam_type` - The exam type key, e.g. `ecg_exam`
+/// * `bytes_stored` - Size of the objects written to storage for the exam
+/// * `timestamp` - When the exam was accepted
+#[derive(Debug, Clone, Serialize)]
+pub struct BillingEvent {
+    pub hospital_id: String,
+    pub exam_type: String,
+    pub bytes_stored: u64,
+    pub timestamp: DateTime<Utc>,
+}
+
+/// Aggregated usage of one hospital for one exam type in one month
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct BillingTotals {
+    pub exams: u64,
+    pub bytes_stored: u64,
+}
+
+/// Monthly aggregation key: (`YYYY-MM`, hospital_id, exam_type)
+type LedgerKey = (String, String, String);
+
+/// Billing subsystem: forwards events to the billing sink and keeps monthly totals
+pub struct BillingService {
+    sink: Box<dyn EventSink>,
+    ledger: Mutex<BTreeMap<LedgerKey, BillingTotals>>,
+}
+
+impl BillingService {
+    /// Create the billing service with an explicit sink
+    pub fn new(sink: Box<dyn EventSink>) -> Self {
+        Self {
+            sink,
+            ledger: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    /// Create the billing service on its configured sink (BILLING_SINK, BILLING_TOPIC,
+    /// BILLING_BIGQUERY_TABLE)
+    /// # Arguments
+    /// * `target` - The billing sink of the settings
+    /// * `pubsub` - The Pub/Sub clients used by the `pubsub` sink
+    /// * `identity` - The identity of the service, used by the `bigquery` sink
+    pub fn from_settings(
+        target: &SinkTarget,
+        pubsub: &PubSubClients,
+        identity: &GcpIdentity,
+    ) -> Self {
+        let sink = sink_of(target, pubsub, identity, "billing");
+        info!("Billing events sink: {}", sink.name());
+        Self::new(sink)
+    }
+
+    /// Record an accepted exam: update the monthly totals and emit the event
+    /// A failing sink never fails the exam - the event is logged for later reconciliation
+    /// # Arguments
+    /// * `event` - The billing event
+    pub async fn record(&self, event: BillingEvent) {
+        self.aggregate(&event);
+        record_exam_accepted(&event.hospital_id, &event.exam_type, event.bytes_stored);
+        let payload = match serde_json::to_value(&event) {
+            Ok(payload) => payload,
+            Err(e) => {
+                warn!("Could not serialize billing event: {e}");
+                return;
+            }
+        };
+        if let Err(e) = self.sink.emit(&payload).await {
+            warn!(target: "billing", "Billing event not delivered ({e}): {payload}");
+        }
+    }
+
+    /// Monthly totals per hospital and exam type
+    /// # Arguments
+    /// * `month` - The month as `YYYY-MM`
+    /// # Returns
+    /// * hospital_id -> exam_type -> totals
+    pub fn monthly_summary(
+        &self,
+        month: &str,
+    ) -> BTreeMap<String, BTreeMap<String, BillingTotals>> {
+        let mut summary: BTreeMap<String, BTreeMap<String, BillingTotals>> = BTreeMap::new();
+        if let Ok(ledger) = self.ledger.lock() {
+            for ((m, hospital_id, exam_type), totals) in ledger.iter() {
+                if m == month {
+                    summary
+                        .entry(hospital_id.clone())
+                        .or_default()
+                        .insert(exam_type.clone(), totals.clone());
+                }
+            }
+        }
+        summary
+    }
+
+    /// Monthly totals of the hospital groups: the totals of their clinics seen by this instance
+    /// # Arguments
+    /// * `month` - The month as `YYYY-MM`
+    /// # Returns
+    /// * group_id -> exam_type -> totals
+    pub fn monthly_group_summary(
+        &self,
+        month: &str,
+    ) -> BTreeMap<String, BTreeMap<String, BillingTotals>> {
+        let mut summary: BTreeMap<String, BTreeMap<String, BillingTotals>> = BTreeMap::new();
+        for (hospital_id, exam_types) in self.monthly_summary(month) {
+            let Some(group_id) = group_of(&hospital_id) else {
+                continue;
+            };
+            let group = summary.entry(group_id).or_default();
+            for (exam_type, totals) in exam_types {
+                let group_totals = group.entry(exam_type).or_default();
+                group_totals.exams += totals.exams;
+                group_totals.bytes_stored += totals.bytes_stored;
+            }
+        }
+        summary
+    }
+
+    /// Add an event to the monthly totals
+    fn aggregate(&self, event: &BillingEvent) {
+        let key = (
+            event.timestamp.format("%Y-%m").to_string(),
+            event.hospital_id.clone(),
+            event.exam_type.clone(),
+        );
+        if let Ok(mut ledger) = self.ledger.lock() {
+            let totals = ledger.entry(key).or_default();
+            totals.exams += 1;
+            totals.bytes_stored += event.bytes_stored;
+        }
+    }
+}
+
+// TESTS *******************************************************************************************
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::authentication::auth::AuthenticatedHospital;
+    use crate::models::models_consent::ConsentScope;
+    use crate::models::models_publish_modes::PublishMode;
+    use crate::models::models_rate_tiers::RateTier;
+    use crate::models::models_size_tiers::SizeTier;
+    use crate::services::service_hospital_groups::{record_group_member, HospitalGroup};
+    use crate::sinks::sink_log::LogSink;
+    use chrono::TimeZone;
+
+    fn event(hospital: &str, exam_type: &str, bytes: u64, month: u32) -> BillingEvent {
+        BillingEvent {
+            hospital_id: hospital.to_string(),
+            exam_type: exam_type.to_string(),
+            bytes_stored: bytes,
+            timestamp: Utc.with_ymd_and_hms(2025, month, 15, 12, 0, 0).unwrap(),
+        }
+    }
+
+    // Happy path: events of the same month, hospital and exam type are summed
+    #[tokio::test]
+    async fn billing_aggregates_per_month() {
+        let billing = BillingService::new(Box::new(LogSink::new("billing")));
+        billing.record(event("h1", "ecg_exam", 100, 3)).await;
+        billing.record(event("h1", "ecg_exam", 50, 3)).await;
+        billing.record(event("h1", "xray_exam", 10, 3)).await;
+        billing.record(event("h2", "ecg_exam", 7, 4)).await;
+
+        let march = billing.monthly_summary("2025-03");
+        assert_eq!(
+            march["h1"]["ecg_exam"],
+            BillingTotals {
+                exams: 2,
+                bytes_stored: 150
+            }
+        );
+        assert_eq!(march["h1"]["xray_exam"].exams, 1);
+        assert!(!march.contains_key("h2"));
+    }
+
+    // Clinics are rolled up under their group, the group keeps its own totals too
+    #[tokio::test]
+    async fn billing_rolls_up_groups() {
+        for clinic in ["bill-c1", "bill-c2"] {
+            record_group_member(&AuthenticatedHospital {
+                hospital_id: clinic.to_string(),
+                consent_scope: ConsentScope::Clinical,
+                size_tier: SizeTier::Standard,
+                monthly_quota: None,
+                group: Some(HospitalGroup {
+                    group_id: "bill-g1".to_string(),
+                    monthly_quota: Some(10),
+                }),
+                allowed_exam_types: None,
+                rate_tier: RateTier::Standard,
+                publish_mode: PublishMode::Inline,
+                paused: false,
+            });
+        }
+        let billing = BillingService::new(Box::new(LogSink::new("billing")));
+        billing.record(event("bill-c1", "ecg_exam", 100, 5)).await;
+        billing.record(event("bill-c2", "ecg_exam", 50, 5)).await;
+        billing.record(event("bill-h3", "ecg_exam", 7, 5)).await;
+
+        let groups = billing.monthly_group_summary("2025-05");
+        assert_eq!(groups.len(), 1);
+        assert_eq!(
+            groups["bill-g1"]["ecg_exam"],
+            BillingTotals {
+                exams: 2,
+                bytes_stored: 150
+            }
+        );
+        assert_eq!(billing.monthly_summary("2025-05").len(), 3);
+    }
+
+    // Borderline: unknown month is empty
+    #[test]
+    fn billing_empty_month() {
+        let billing = BillingService::new(Box::new(LogSink::new("billing")));
+        assert!(billing.monthly_summary("1999-01").is_empty());
+    }
+}
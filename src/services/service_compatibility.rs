@@ -0,0 +1,364 @@
+// Imports *****************************************************************************************
+// External Crates
+use chrono::{DateTime, Utc};
+use log::{error, info};
+use serde::Serialize;
+use sqlx::PgPool;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+// Internal Modules
+use crate::publishers::publisher::{Publisher, TopicSchema};
+use crate::services::service_migrations::{migration_status, unknown_migrations, MigrationStatus};
+
+// Constants ***************************************************************************************
+/// Why Redis has no version check, reported with its `not_configured` status
+const REDIS_NOT_APPLICABLE: &str = "Not applicable: Redis only holds the rate limit windows \
+    shared by the instances (RATE_LIMIT_REDIS_URL, `redis` feature), which expire with their \
+    window - lockouts, idempotency and status caches are held in memory per instance - so there \
+    is no Redis version nor durable key layout to check";
+
+// Structs *****************************************************************************************
+/// Compatibility check of the schemas the binary writes to
+/// # Arguments
+/// * `enforce` - Whether a mismatch fails readiness (COMPATIBILITY_CHECK, `enforce` by default,
+///   `warn` to only log it)
+/// * `interval` - Interval between checks (COMPATIBILITY_CHECK_INTERVAL_S)
+/// * `pubsub_schema` - Schema id the routed topics may be bound to (PUBSUB_SCHEMA, default
+///   `exam-notification-v{NOTIFICATION_SCHEMA_VERSION}`)
+#[derive(Debug, Clone, PartialEq)]
+pub struct CompatibilitySettings {
+    pub enforce: bool,
+    pub interval: Duration,
+    pub pubsub_schema: String,
+}
+
+/// Outcome of the compatibility check of one dependency
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CompatibilityStatus {
+    /// The dependency has the schema the binary expects
+    Compatible,
+    /// The schema differs - the binary must not write to it
+    Incompatible,
+    /// The dependency could not be read - the readiness probe reports it as down
+    Unknown,
+    /// The deployment does not use the dependency
+    NotConfigured,
+}
+
+/// Compatibility check of one dependency
+/// # Arguments
+/// * `dependency` - The dependency, e.g. `postgres`
+/// * `status` - The outcome
+/// * `diagnostic` - What differs and how to fix it, or why the check could not run
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CompatibilityCheck {
+    pub dependency: &'static str,
+    pub status: CompatibilityStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub diagnostic: Option<String>,
+}
+
+/// Compatibility of the dependencies with the binary
+/// # Arguments
+/// * `compatible` - Whether no dependency is incompatible
+/// * `enforced` - Whether an incompatibility fails readiness
+/// * `checked_at` - When the checks ran
+/// * `checks` - The check of each dependency
+#[derive(Debug, Clone, Serialize)]
+pub struct CompatibilityReport {
+    pub compatible: bool,
+    pub enforced: bool,
+    pub checked_at: DateTime<Utc>,
+    pub checks: Vec<CompatibilityCheck>,
+}
+
+impl CompatibilityReport {
+    /// Whether the report lets the instance serve traffic: compatible, or not enforced
+    pub fn allows_traffic(&self) -> bool {
+        self.compatible || !self.enforced
+    }
+}
+
+// Global variables ********************************************************************************
+/// Last compatibility check - None until the first check ran
+static COMPATIBILITY: Mutex<Option<CompatibilityReport>> = Mutex::new(None);
+
+// MAIN FUNCTIONS **********************************************************************************
+/// Check the dependencies before serving traffic and log every mismatch - an incompatible
+/// dependency is not fatal, readiness fails until it matches again
+/// # Arguments
+/// * `db_pool` - The Postgres pool
+/// * `publisher` - The notification backend
+/// * `settings` - The settings of the check
+pub async fn check_compatibility_at_startup(
+    db_pool: &PgPool,
+    publisher: &Arc<dyn Publisher>,
+    settings: &CompatibilitySettings,
+) {
+    let report = check_compatibility(db_pool, publisher, settings).await;
+    let statuses: Vec<String> = report
+        .checks
+        .iter()
+        .map(|check| format!("{}={:?}", check.dependency, check.status))
+        .collect();
+    info!(
+        "Compatibility at startup: compatible={} enforced={} ({})",
+        report.compatible,
+        report.enforced,
+        statuses.join(", ")
+    );
+}
+
+/// Check the dependencies every COMPATIBILITY_CHECK_INTERVAL_S - a migration applied by a newer
+/// binary fails the readiness of the older instances still running
+/// # Arguments
+/// * `db_pool` - The Postgres pool
+/// * `publisher` - The notification backend
+/// * `settings` - The settings of the check
+pub async fn run_compatibility_checks(
+    db_pool: PgPool,
+    publisher: Arc<dyn Publisher>,
+    settings: CompatibilitySettings,
+) {
+    loop {
+        tokio::time::sleep(settings.interval).await;
+        check_compatibility(&db_pool, &publisher, &settings).await;
+    }
+}
+
+/// Last compatibility check, for the readiness diagnostics
+pub fn compatibility_report() -> Option<CompatibilityReport> {
+    COMPATIBILITY.lock().ok().and_then(|report| report.clone())
+}
+
+/// Whether the instance may write to its dependencies: true until a check finds a mismatch, and
+/// always with COMPATIBILITY_CHECK=warn
+pub fn schemas_compatible() -> bool {
+    compatibility_report().is_none_or(|report| report.allows_traffic())
+}
+
+// SUPPORT FUNCTIONS *******************************************************************************
+/// Run every check and keep the report
+/// # Arguments
+/// * `db_pool` - The Postgres pool
+/// * `publisher` - The notification backend
+/// * `settings` - The settings of the check
+async fn check_compatibility(
+    db_pool: &PgPool,
+    publisher: &Arc<dyn Publisher>,
+    settings: &CompatibilitySettings,
+) -> CompatibilityReport {
+    // STEP 1: Read the schema of each dependency
+    let migrations = async {
+        let statuses = migration_status(db_pool).await?;
+        let unknown = unknown_migrations(db_pool).await?;
+        Ok::<_, anyhow::Error>(postgres_check(&statuses, &unknown))
+    };
+    let (postgres, topics) = tokio::join!(migrations, publisher.topic_schemas());
+    let postgres = postgres.unwrap_or_else(|e| unknown_check("postgres", &e));
+    let pubsub = match topics {
+        Ok(topics) => pubsub_check(&topics, &settings.pubsub_schema),
+        Err(e) => unknown_check("pubsub", &e),
+    };
+    let redis = CompatibilityCheck {
+        dependency: "redis",
+        status: CompatibilityStatus::NotConfigured,
+        diagnostic: Some(REDIS_NOT_APPLICABLE.to_string()),
+    };
+
+    // STEP 2: Keep the report for readiness
+    let checks = vec![postgres, pubsub, redis];
+    for check in checks
+        .iter()
+        .filter(|c| c.status == CompatibilityStatus::Incompatible)
+    {
+        error!(target: "alert", "schema_incompatible dependency={} enforced={} diagnostic={}",
+            check.dependency, settings.enforce, check.diagnostic.as_deref().unwrap_or_default());
+    }
+    let report = CompatibilityReport {
+        compatible: checks
+            .iter()
+            .all(|c| c.status != CompatibilityStatus::Incompatible),
+        enforced: settings.enforce,
+        checked_at: Utc::now(),
+        checks,
+    };
+    if let Ok(mut last) = COMPATIBILITY.lock() {
+        *last = Some(report.clone());
+    }
+    report
+}
+
+/// Compatibility of the database: every migration of the binary applied as embedded, and none
+/// the binary does not know
+/// # Arguments
+/// * `statuses` - The migrations of the binary and whether the database has them
+/// * `unknown` - The versions of the database the binary does not embed
+fn postgres_check(statuses: &[MigrationStatus], unknown: &[String]) -> CompatibilityCheck {
+    let pending: Vec<&str> = statuses
+        .iter()
+        .filter(|m| m.applied_at.is_none())
+        .map(|m| m.name)
+        .collect();
+    let modified: Vec<&str> = statuses
+        .iter()
+        .filter(|m| m.modified)
+        .map(|m| m.name)
+        .collect();
+    let mut problems = Vec::new();
+    if !unknown.is_empty() {
+        problems.push(format!(
+            "the database has migrations this binary does not know ({}) - deploy the binary \
+             that applied them",
+            unknown.join(", ")
+        ));
+    }
+    if !pending.is_empty() {
+        problems.push(format!(
+            "migrations not applied ({}) - run `admin migrate`",
+            pending.join(", ")
+        ));
+    }
+    if !modified.is_empty() {
+        problems.push(format!(
+            "migrations applied from a different file ({})",
+            modified.join(", ")
+        ));
+    }
+    schema_check("postgres", problems)
+}
+
+/// Compatibility of the Pub/Sub topics: a topic bound to the schema registry must validate
+/// against the schema of the notifications the binary writes
+/// # Arguments
+/// * `topics` - The schema bound to each routed topic
+/// * `expected` - The schema id of the notifications (PUBSUB_SCHEMA)
+fn pubsub_check(topics: &[TopicSchema], expected: &str) -> CompatibilityCheck {
+    let problems = topics
+        .iter()
+        .filter_map(|topic| {
+            let schema = topic.schema.as_deref()?;
+            // `projects/{project}/schemas/{id}` - the id is compared, the project may differ
+            let id = schema.rsplit('/').next().unwrap_or(schema);
+            (id != expected).then(|| {
+                format!(
+                    "topic {} ({}) validates against schema {schema}, the binary writes {expected}",
+                    topic.topic, topic.exam_type
+                )
+            })
+        })
+        .collect();
+    schema_check("pubsub", problems)
+}
+
+/// Check of a dependency from the mismatches found
+fn schema_check(dependency: &'static str, problems: Vec<String>) -> CompatibilityCheck {
+    if problems.is_empty() {
+        return CompatibilityCheck {
+            dependency,
+            status: CompatibilityStatus::Compatible,
+            diagnostic: None,
+        };
+    }
+    CompatibilityCheck {
+        dependency,
+        status: CompatibilityStatus::Incompatible,
+        diagnostic: Some(problems.join("; ")),
+    }
+}
+
+/// Check of a dependency whose schema could not be read
+fn unknown_check(dependency: &'static str, error: &anyhow::Error) -> CompatibilityCheck {
+    CompatibilityCheck {
+        dependency,
+        status: CompatibilityStatus::Unknown,
+        diagnostic: Some(error.to_string()),
+    }
+}
+
+// TESTS *******************************************************************************************
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::anyhow;
+
+    fn migration(name: &'static str, applied: bool, modified: bool) -> MigrationStatus {
+        MigrationStatus {
+            version: &name[..8],
+            name,
+            applied_at: applied.then(Utc::now),
+            modified,
+        }
+    }
+
+    fn topic(schema: Option<&str>) -> TopicSchema {
+        TopicSchema {
+            exam_type: "ecg_exam".to_string(),
+            topic: "projects/p/topics/dev-ecg-v1".to_string(),
+            schema: schema.map(str::to_string),
+        }
+    }
+
+    // Happy path: a database migrated by this binary and topics on its schema
+    #[test]
+    fn matching_schemas_compatible() {
+        let statuses = [
+            migration("20261017_hash_hospital_keys", true, false),
+            migration("20261018_hospital_size_tiers", true, false),
+        ];
+        assert_eq!(
+            postgres_check(&statuses, &[]).status,
+            CompatibilityStatus::Compatible
+        );
+        let topics = [
+            topic(Some("projects/p/schemas/exam-notification-v2")),
+            topic(None),
+        ];
+        assert_eq!(
+            pubsub_check(&topics, "exam-notification-v2").status,
+            CompatibilityStatus::Compatible
+        );
+    }
+
+    // Error handling: a newer schema, a pending migration and another topic schema are refused
+    #[test]
+    fn mismatches_diagnosed() {
+        let statuses = [
+            migration("20261017_hash_hospital_keys", true, true),
+            migration("20261018_hospital_size_tiers", false, false),
+        ];
+        let check = postgres_check(&statuses, &["20261099".to_string()]);
+        assert_eq!(check.status, CompatibilityStatus::Incompatible);
+        let diagnostic = check.diagnostic.unwrap();
+        assert!(diagnostic.contains("does not know (20261099)"));
+        assert!(diagnostic.contains("not applied (20261018_hospital_size_tiers)"));
+        assert!(diagnostic.contains("different file (20261017_hash_hospital_keys)"));
+
+        let check = pubsub_check(
+            &[topic(Some("projects/p/schemas/exam-notification-v3"))],
+            "exam-notification-v2",
+        );
+        assert_eq!(check.status, CompatibilityStatus::Incompatible);
+        assert!(check.diagnostic.unwrap().contains("exam-notification-v3"));
+    }
+
+    // Borderline: no check yet keeps the instance ready, a warn-only mismatch as well
+    #[test]
+    fn readiness_until_checked() {
+        assert!(schemas_compatible());
+        let mut report = CompatibilityReport {
+            compatible: false,
+            enforced: false,
+            checked_at: Utc::now(),
+            checks: Vec::new(),
+        };
+        assert!(report.allows_traffic());
+        report.enforced = true;
+        assert!(!report.allows_traffic());
+        let body = serde_json::to_value(unknown_check("postgres", &anyhow!("down"))).unwrap();
+        assert_eq!(body["status"], "unknown");
+        assert_eq!(body["diagnostic"], "down");
+    }
+}
@@ -0,0 +1,176 @@
+// Imports *****************************************************************************************
+// External Crates
+use chrono::Utc;
+use log::{info, warn};
+use reqwest::Url;
+use std::sync::LazyLock;
+
+// Internal Modules
+use crate::config::settings::{IngestSettings, Secret};
+use crate::services::service_ingest_queue::ExamStatus;
+use crate::utils::api_error::ApiError;
+use crate::utils::canonical_json::to_canonical_string;
+use crate::utils::external_call::{Dependency, ExternalCall};
+use crate::utils::hmac::{hex, hmac_sha256};
+use crate::utils::reason_code::ReasonCode;
+
+// Constants ***************************************************************************************
+/// Header carrying the HMAC-SHA256 of `{timestamp}.{body}`, as `sha256=<hex>`
+pub const SIGNATURE_HEADER: &str = "x-sentinela-signature";
+/// Header carrying the unix timestamp of the delivery, part of the signed content
+pub const TIMESTAMP_HEADER: &str = "x-sentinela-timestamp";
+/// Additional attempts of a delivery on timeouts and transient errors
+const WEBHOOK_RETRIES: u32 = 3;
+
+// Global variables ********************************************************************************
+/// HTTP client shared by the deliveries
+static HTTP: LazyLock<reqwest::Client> = LazyLock::new(reqwest::Client::new);
+
+// MAIN FUNCTIONS **********************************************************************************
+/// Check the confirmation webhook of a request: an HTTPS URL on one of the hosts allowed by
+/// CONFIRMATION_WEBHOOK_HOSTS - the gateway never calls arbitrary URLs
+/// # Arguments
+/// * `url` - The URL sent in the 'confirmation_webhook' header
+/// * `settings` - The intake settings, holding CONFIRMATION_WEBHOOK_HOSTS
+/// # Errors
+/// * Returns `ApiError::InvalidContent` (WEBHOOK_REFUSED) if the URL is malformed, not HTTPS or
+///   its host is not allowed
+pub fn check_webhook_url(url: &str, settings: &IngestSettings) -> Result<(), ApiError> {
+    check_webhook_url_against(url, &settings.confirmation_webhook_hosts)
+}
+
+/// Deliver the final status of an exam to its confirmation webhook - failures are audited, the
+/// status can still be polled
+/// # Arguments
+/// * `url` - The checked webhook URL
+/// * `exam_type` - The exam type key
+/// * `status` - The status of the exam once it left the gateway
+/// * `secret` - The key of the signatures (CONFIRMATION_WEBHOOK_SECRET)
+pub async fn notify_confirmation(
+    url: &str,
+    exam_type: &str,
+    status: &ExamStatus,
+    secret: Option<&Secret>,
+) {
+    // STEP 1: Body and signature (CONFIRMATION_WEBHOOK_SECRET, unsigned if not set)
+    // Canonical JSON (RFC 8785): a receiver that re-serializes the parsed body checks the same bytes
+    let body = match to_canonical_string(status) {
+        Ok(body) => body,
+        Err(e) => {
+            warn!("Could not serialize the status of {}: {e}", status.exam_id);
+            return;
+        }
+    };
+    let timestamp = Utc::now().timestamp().to_string();
+    let signature = secret.map(|secret| sign(secret.expose(), &timestamp, &body));
+
+    // STEP 2: Deliver, retrying transient failures
+    let delivered = ExternalCall::new(Dependency::Webhook, "confirm_exam")
+        .retries(WEBHOOK_RETRIES)
+        .run(|| async {
+            let mut request = HTTP
+                .post(url)
+                .header("content-type", "application/json")
+                .header(TIMESTAMP_HEADER, &timestamp)
+                .body(body.clone());
+            if let Some(signature) = &signature {
+                request = request.header(SIGNATURE_HEADER, signature);
+            }
+            let response = request.send().await.map_err(|e| e.to_string())?;
+            let code = response.status();
+            if !code.is_success() {
+                return Err(format!("Webhook answered {code}"));
+            }
+            Ok(())
+        })
+        .await;
+
+    // STEP 3: Audit the delivery
+    let outcome = match &delivered {
+        Ok(()) => "delivered",
+        Err(e) => {
+            warn!("Confirmation webhook of {} failed: {e}", status.exam_id);
+            "failed"
+        }
+    };
+    info!(target: "audit", "exam_confirmation exam_type={exam_type} exam_id={} hospital_id={} state={} outcome={outcome}",
+        status.exam_id, status.hospital_id, status.state.as_str());
+}
+
+// SUPPORT FUNCTIONS *******************************************************************************
+/// Check a webhook URL against the allowed hosts
+/// # Arguments
+/// * `url` - The webhook URL
+/// * `allowed` - The allowed hosts - none if empty
+fn check_webhook_url_against(url: &str, allowed: &[String]) -> Result<(), ApiError> {
+    let refused = |message: &str| {
+        Err(ApiError::InvalidContent(
+            ReasonCode::WebhookRefused,
+            message.to_string(),
+        ))
+    };
+    let Ok(parsed) = Url::parse(url) else {
+        return refused("confirmation_webhook is not a valid URL");
+    };
+    if parsed.scheme() != "https" {
+        return refused("confirmation_webhook must use HTTPS");
+    }
+    let host = parsed.host_str().unwrap_or_default();
+    if !allowed
+        .iter()
+        .any(|allowed| allowed.eq_ignore_ascii_case(host))
+    {
+        return refused("confirmation_webhook host is not allowed");
+    }
+    Ok(())
+}
+
+/// Signature of a delivery: `sha256=` and the hex HMAC-SHA256 of `{timestamp}.{body}`
+fn sign(secret: &str, timestamp: &str, body: &str) -> String {
+    let mac = hmac_sha256(secret.as_bytes(), format!("{timestamp}.{body}").as_bytes());
+    format!("sha256={}", hex(&mac))
+}
+
+// TESTS *******************************************************************************************
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Happy path: HTTPS URLs on an allowed host, whatever their case
+    #[test]
+    fn webhook_url_allowed() {
+        let allowed = [
+            "hooks.hospital.example".to_string(),
+            "Other.example".to_string(),
+        ];
+        assert!(
+            check_webhook_url_against("https://hooks.hospital.example/exams", &allowed).is_ok()
+        );
+        assert!(check_webhook_url_against("https://other.example:8443/cb?x=1", &allowed).is_ok());
+    }
+
+    // Error handling: plain HTTP, unknown hosts, malformed URLs and no allowed host at all
+    #[test]
+    fn webhook_url_refused() {
+        let allowed = ["hooks.hospital.example".to_string()];
+        for url in [
+            "http://hooks.hospital.example/exams",
+            "https://evil.example/exams",
+            "https://hooks.hospital.example.evil.example/",
+            "not a url",
+        ] {
+            let error = check_webhook_url_against(url, &allowed).unwrap_err();
+            assert_eq!(error.reason(), ReasonCode::WebhookRefused, "{url}");
+        }
+        assert!(check_webhook_url_against("https://hooks.hospital.example/", &[]).is_err());
+    }
+
+    // The signature covers the timestamp, so a captured delivery cannot be replayed later
+    #[test]
+    fn signature_covers_timestamp() {
+        let signature = sign("secret", "1700000000", "{}");
+        assert!(signature.starts_with("sha256="));
+        assert_eq!(signature.len(), "sha256=".len() + 64);
+        assert_ne!(signature, sign("secret", "1700000001", "{}"));
+    }
+}
@@ -0,0 +1,397 @@
+// Imports *****************************************************************************************
+// External Crates
+use actix_web::web;
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
+use log::{error, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+// Internal Modules
+use crate::audit::audit_event::{AuditAction, AuditEvent};
+use crate::audit::audit_trail::record_audit;
+use crate::config::settings::StorageSettings;
+use crate::models::models_consent::ConsentScope;
+use crate::publishers::publisher::{NotificationMessage, Publisher};
+use crate::services::service_message_format::{
+    encode_message, tag_exam_message, AVRO_FALLBACK_ATTRIBUTE, ENCODING_ATTRIBUTE,
+    INFERENCE_ECG_AVRO, INFERENCE_XRAY_AVRO, JSON_FALLBACK_ATTRIBUTE, SCHEMA_ATTRIBUTE,
+};
+use crate::storage::exam_storage::{ExamStorage, ObjectPut};
+use crate::telemetry::metrics::{record_exam_dead_lettered, record_message_published};
+use crate::utils::api_error::ApiError;
+use crate::utils::reason_code::ReasonCode;
+use crate::utils::storage_diagnostics::classify_storage_fault;
+
+// Structs *****************************************************************************************
+/// How an exam left the gateway
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Delivery {
+    /// Stored and notified
+    Published,
+    /// Stored, notification scheduled once downstream recovers
+    Deferred,
+    /// Stored and answered, notification published in the background (after-storage publish mode)
+    Stored,
+    /// Storage or notification failed after all retries - kept for replay
+    DeadLettered,
+}
+
+/// Step of the pipeline that failed for a dead-lettered exam
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FailedStage {
+    /// The exam was not stored: `payload` holds what should have been written
+    Storage,
+    /// The exam is stored, its notification was not sent: `payload` and `attributes` hold the
+    /// message that should have been published
+    Publish,
+}
+
+impl FailedStage {
+    /// Stable lowercase name, used in object names and audit records
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FailedStage::Storage => "storage",
+            FailedStage::Publish => "publish",
+        }
+    }
+
+    /// Error returned to the hospital when the exam could not even be dead-lettered
+    pub fn api_error(&self) -> ApiError {
+        match self {
+            FailedStage::Storage => ApiError::StorageFailure,
+            FailedStage::Publish => ApiError::PublishFailure,
+        }
+    }
+}
+
+/// Structured record of an exam that could not be stored or notified, sufficient to replay it
+/// # Arguments
+/// * `exam_type` - The exam type key
+/// * `exam_id` - The exam identifier (object name without extension)
+/// * `hospital_id` - The hospital that sent the exam
+/// * `stage` - The step that failed
+/// * `error` - The last error, after all retries
+/// * `reason` - The reason code of the error, e.g. GCS_TIMEOUT (INTERNAL for older records)
+/// * `failed_at` - When the exam was dead-lettered
+/// * `payload` - The serialized exam (storage) or notification (publish)
+/// * `attributes` - The notification attributes (publish only)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeadLetterRecord {
+    pub exam_type: String,
+    pub exam_id: String,
+    pub hospital_id: String,
+    pub stage: FailedStage,
+    pub error: String,
+    #[serde(default)]
+    pub reason: ReasonCode,
+    pub failed_at: DateTime<Utc>,
+    pub payload: serde_json::Value,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub attributes: HashMap<String, String>,
+}
+
+impl DeadLetterRecord {
+    /// Name of the record, unique per exam and failed stage
+    pub fn file_name(&self) -> String {
+        format!(
+            "{}/{}/{}.json",
+            self.stage.as_str(),
+            self.exam_id,
+            self.failed_at.format("%Y%m%dT%H%M%S%.fZ")
+        )
+    }
+
+    /// Error returned to the hospital when the exam could not even be dead-lettered - a storage
+    /// fault of the deployment (permissions, missing bucket, quota) is reported as a neutral 503,
+    /// its diagnostic is for the operators only
+    pub fn lost_error(&self) -> ApiError {
+        if self.stage == FailedStage::Storage && classify_storage_fault(&self.error).is_some() {
+            ApiError::StorageUnavailable
+        } else {
+            self.stage.api_error()
+        }
+    }
+}
+
+// MAIN FUNCTIONS **********************************************************************************
+/// Keep an exam that exhausted its retries: in DEAD_LETTER_BUCKET if set and reachable, otherwise
+/// in the local DEAD_LETTER_DIR
+/// # Arguments
+/// * `record` - The dead-letter record
+/// * `storage` - The exam storage backend, holding the dead-letter bucket
+/// * `settings` - The storage settings (DEAD_LETTER_BUCKET, DEAD_LETTER_DIR)
+/// # Returns
+/// * The location the record was written to
+/// # Errors
+/// * Returns an error if the record could be written nowhere - the exam is then lost; the error
+///   carries the `ApiError` of the failed stage
+pub async fn dead_letter(
+    record: &DeadLetterRecord,
+    storage: &Arc<dyn ExamStorage>,
+    settings: &StorageSettings,
+) -> Result<String> {
+    let body = serde_json::to_vec(record)?;
+    let name = record.file_name();
+
+    // STEP 1: Dead-letter bucket - usually in another location than the ingest bucket
+    if let Some(bucket) = settings.dead_letter_bucket.as_deref() {
+        let object = ObjectPut {
+            bucket,
+            name: &name,
+            content_type: Some("application/json"),
+            operation: "upload_dead_letter",
+            retries: 1,
+        };
+        match storage.put_object(&object, body.clone()).await {
+            Ok(_) => return Ok(audit(record, storage.location(bucket, &name))),
+            Err(e) => warn!("Dead-letter bucket unavailable, spilling locally: {e}"),
+        }
+    }
+
+    // STEP 2: Local spill directory
+    let path = PathBuf::from(&settings.dead_letter_dir).join(&name);
+    let spill_path = path.clone();
+    web::block(move || -> std::io::Result<()> {
+        if let Some(parent) = spill_path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(&spill_path, body)
+    })
+    .await
+    .map_err(|e| {
+        anyhow::Error::new(record.lost_error())
+            .context(format!("Dead-letter spill could not be scheduled: {e}"))
+    })?
+    .map_err(|e| {
+        error!(target: "audit", "exam_lost exam_type={} exam_id={} stage={} reason={} error={}",
+            record.exam_type, record.exam_id, record.stage.as_str(), record.reason, record.error);
+        record_audit(AuditEvent::exam(
+            AuditAction::ExamLost,
+            &record.exam_type,
+            &record.exam_id,
+            &record.hospital_id,
+        ));
+        anyhow::Error::new(record.lost_error()).context(format!(
+            "Dead-letter spill to {} failed: {e}",
+            path.display()
+        ))
+    })?;
+    Ok(audit(record, path.display().to_string()))
+}
+
+/// Publish again the notification of a publish dead letter, in the current format of its topic -
+/// the recorded consent is checked against the current route, which may require more since
+/// # Arguments
+/// * `record` - The dead letter of a stored exam whose notification was not published
+/// * `publisher` - The notification backend
+/// # Returns
+/// * The Pub/Sub message id
+/// # Errors
+/// * Returns an error for a storage dead letter (the exam is not stored), a withdrawn consent or a
+///   failed publish - the record is then kept
+pub async fn republish(
+    record: &DeadLetterRecord,
+    publisher: &Arc<dyn Publisher>,
+) -> Result<String> {
+    // STEP 1: Only stored exams have a notification to publish
+    if record.stage != FailedStage::Publish {
+        return Err(anyhow!(
+            "Exam {} was not stored - its dead letter cannot be published",
+            record.exam_id
+        ));
+    }
+    let consent_scope = record
+        .attributes
+        .get("consent_scope")
+        .and_then(|scope| scope.parse::<ConsentScope>().ok())
+        .unwrap_or(ConsentScope::Clinical);
+    publisher.check_consent(&record.exam_type, consent_scope)?;
+    let topic_name = publisher.topic_name(&record.exam_type)?;
+
+    // STEP 2: Encode the notification again - the topic may have changed format since
+    let schema = match record.exam_type.as_str() {
+        "ecg_exam" => INFERENCE_ECG_AVRO,
+        _ => INFERENCE_XRAY_AVRO,
+    };
+    let mut attributes = record.attributes.clone();
+    for stale in [
+        ENCODING_ATTRIBUTE,
+        SCHEMA_ATTRIBUTE,
+        JSON_FALLBACK_ATTRIBUTE,
+        AVRO_FALLBACK_ATTRIBUTE,
+    ] {
+        attributes.remove(stale);
+    }
+    let (payload, format) = encode_message(topic_name, schema, &record.payload, &mut attributes);
+    let mut message = NotificationMessage {
+        data: payload,
+        attributes,
+        ..Default::default()
+    };
+    tag_exam_message(&mut message, &record.payload);
+
+    // STEP 3: Publish
+    let message_id = publisher.publish(&record.exam_type, &message).await?;
+    record_message_published(topic_name, format.as_str());
+    record_audit(
+        AuditEvent::exam(
+            AuditAction::ExamPublished,
+            &record.exam_type,
+            &record.exam_id,
+            &record.hospital_id,
+        )
+        .message_id(message_id.clone()),
+    );
+    Ok(message_id)
+}
+
+// SUPPORT FUNCTIONS *******************************************************************************
+/// Audit and count a dead-lettered exam and return its location
+fn audit(record: &DeadLetterRecord, location: String) -> String {
+    record_exam_dead_lettered(&record.exam_type, record.stage.as_str(), record.reason);
+    error!(target: "audit", "exam_dead_lettered exam_type={} exam_id={} hospital_id={} stage={} reason={} location={location} error={}",
+        record.exam_type, record.exam_id, record.hospital_id, record.stage.as_str(), record.reason, record.error);
+    record_audit(
+        AuditEvent::exam(
+            AuditAction::ExamDeadLettered,
+            &record.exam_type,
+            &record.exam_id,
+            &record.hospital_id,
+        )
+        .object_path(location.clone()),
+    );
+    location
+}
+
+// TESTS *******************************************************************************************
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(stage: FailedStage) -> DeadLetterRecord {
+        DeadLetterRecord {
+            exam_type: "ecg_exam".to_string(),
+            exam_id: "ecg_exam/h/p/2025-01-01T000000.000Z".to_string(),
+            hospital_id: "h".to_string(),
+            stage,
+            error: "gcs.upload_object failed (Transient): 503".to_string(),
+            reason: ReasonCode::GcsError,
+            failed_at: Utc::now(),
+            payload: serde_json::json!({"patient_id": "p"}),
+            attributes: HashMap::new(),
+        }
+    }
+
+    // Records of the same exam are grouped per failed stage and never overwrite each other
+    #[test]
+    fn dead_letter_file_name() {
+        let storage = record(FailedStage::Storage);
+        let name = storage.file_name();
+        assert!(name.starts_with("storage/ecg_exam/h/p/2025-01-01T000000.000Z/"));
+        assert!(name.ends_with(".json"));
+        assert!(record(FailedStage::Publish)
+            .file_name()
+            .starts_with("publish/"));
+    }
+
+    // Records round-trip, so they can be replayed
+    #[test]
+    fn dead_letter_record_round_trip() {
+        let json = serde_json::to_string(&record(FailedStage::Publish)).unwrap();
+        let back: DeadLetterRecord = serde_json::from_str(&json).unwrap();
+        assert_eq!(back.stage, FailedStage::Publish);
+        assert_eq!(back.payload["patient_id"], "p");
+        assert_eq!(back.reason, ReasonCode::GcsError);
+
+        // Records written before the reason codes are still read
+        let mut json: serde_json::Value = serde_json::from_str(&json).unwrap();
+        json.as_object_mut().unwrap().remove("reason");
+        let back: DeadLetterRecord = serde_json::from_value(json).unwrap();
+        assert_eq!(back.reason, ReasonCode::Internal);
+    }
+
+    // A lost exam surfaces as the failure of its stage
+    #[test]
+    fn lost_exam_error() {
+        assert_eq!(FailedStage::Storage.api_error(), ApiError::StorageFailure);
+        assert_eq!(FailedStage::Publish.api_error(), ApiError::PublishFailure);
+    }
+
+    // A lost exam of a misconfigured bucket gets a neutral 503, any other the failure of its stage
+    #[test]
+    fn lost_exam_storage_fault() {
+        let mut lost = record(FailedStage::Storage);
+        lost.error = "gcs.upload_object failed (Permanent): 403 permission denied".to_string();
+        assert_eq!(lost.lost_error(), ApiError::StorageUnavailable);
+        lost.error = "gcs.upload_object failed (Timeout): timed out after 30s".to_string();
+        assert_eq!(lost.lost_error(), ApiError::StorageFailure);
+        lost.stage = FailedStage::Publish;
+        lost.error = "pubsub.publish failed (Permanent): 403 permission denied".to_string();
+        assert_eq!(lost.lost_error(), ApiError::PublishFailure);
+    }
+
+    // Borderline: records keep their shape, so the replay tooling reads those of older releases
+    #[test]
+    fn dead_letter_record_contract() {
+        let mut record = record(FailedStage::Publish);
+        record.failed_at = "2026-10-01T12:00:00Z".parse().unwrap();
+        record
+            .attributes
+            .insert("consent_scope".to_string(), "clinical".to_string());
+        insta::assert_json_snapshot!(record, @r#"
+        {
+          "exam_type": "ecg_exam",
+          "exam_id": "ecg_exam/h/p/2025-01-01T000000.000Z",
+          "hospital_id": "h",
+          "stage": "publish",
+          "error": "gcs.upload_object failed (Transient): 503",
+          "reason": "GCS_ERROR",
+          "failed_at": "2026-10-01T12:00:00Z",
+          "payload": {
+            "patient_id": "p"
+          },
+          "attributes": {
+            "consent_scope": "clinical"
+          }
+        }
+        "#);
+    }
+
+    // Happy path: a publish dead letter is published again, with the consent of its topic
+    #[actix_web::test]
+    async fn publish_dead_letter_republished() {
+        use crate::models::models_topics::TopicName;
+        use crate::models::models_topics::TopicRoute;
+        use crate::publishers::publisher_log::LogPublisher;
+
+        let route = TopicRoute {
+            topic: TopicName::parse_for_env("dev-ecg-v1", "dev").unwrap(),
+            project: None,
+            required_consent: ConsentScope::Research,
+        };
+        let publisher: Arc<dyn Publisher> = Arc::new(LogPublisher::new(
+            HashMap::from([("ecg_exam".to_string(), route)]),
+            None,
+        ));
+
+        let mut research = record(FailedStage::Publish);
+        research
+            .attributes
+            .insert("consent_scope".to_string(), "research".to_string());
+        assert!(republish(&research, &publisher).await.is_ok());
+
+        // Error handling: a clinical-only consent no longer reaches the topic, and an exam that
+        // was not stored has nothing to publish
+        assert!(republish(&record(FailedStage::Publish), &publisher)
+            .await
+            .is_err());
+        let mut storage = research.clone();
+        storage.stage = FailedStage::Storage;
+        let error = republish(&storage, &publisher).await.unwrap_err();
+        assert!(error.to_string().contains("was not stored"));
+    }
+}
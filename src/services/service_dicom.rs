@@ -0,0 +1,329 @@
+// Imports *****************************************************************************************
+// External Crates
+use actix_web::web::Bytes;
+use anyhow::Result;
+use futures_util::stream;
+use log::info;
+use sha2::{Digest, Sha256};
+use std::future::ready;
+use std::sync::Arc;
+
+// Internal Modules
+use crate::config::settings::{Secret, StorageSettings};
+use crate::models::models_dicom::{
+    encode_element, DicomFile, DicomMetadata, Tag, ACQUISITION_DATE, ACQUISITION_DATETIME,
+    ACQUISITION_TIME, COLUMNS, DEIDENTIFICATION_METHOD, FILE_META_GROUP_LENGTH,
+    MEDIA_STORAGE_SOP_INSTANCE_UID, MODALITY, PATIENT_ID, PATIENT_IDENTITY_REMOVED, PIXEL_DATA,
+    ROWS, SERIES_INSTANCE_UID, SOP_CLASS_UID, SOP_INSTANCE_UID, STUDY_DATE, STUDY_INSTANCE_UID,
+    STUDY_TIME,
+};
+use crate::publishers::publisher::Publisher;
+use crate::services::service_billing::BillingService;
+use crate::services::service_dead_letter::Delivery;
+use crate::services::service_xray_exam::{handler_xray_upload, XrayUpload};
+use crate::storage::exam_storage::ExamStorage;
+
+// Constants ***************************************************************************************
+/// File meta information kept as sent: version, SOP class, transfer syntax, implementation
+const KEPT_META_TAGS: [Tag; 5] = [
+    (0x0002, 0x0001),
+    (0x0002, 0x0002),
+    (0x0002, 0x0010),
+    (0x0002, 0x0012),
+    (0x0002, 0x0013),
+];
+/// Dataset elements kept as sent - everything else (patient, physicians, institution, device,
+/// dates, private tags and sequences) is removed
+const KEPT_TAGS: [Tag; 9] = [
+    (0x0008, 0x0008), // Image Type
+    SOP_CLASS_UID,
+    MODALITY,
+    (0x0018, 0x0015), // Body Part Examined
+    (0x0018, 0x5101), // View Position
+    (0x0020, 0x0011), // Series Number
+    (0x0020, 0x0013), // Instance Number
+    (0x0020, 0x0020), // Patient Orientation
+    PIXEL_DATA,
+];
+/// Groups kept whole: image pixel description and presentation (0028) carry no identity
+const KEPT_GROUPS: [u16; 1] = [0x0028];
+/// UIDs replaced by a pseudonym - stable per hospital, so studies and series still group
+const PSEUDONYMIZED_UIDS: [Tag; 3] = [STUDY_INSTANCE_UID, SERIES_INSTANCE_UID, SOP_INSTANCE_UID];
+/// Recorded in the De-identification Method of every stored file
+const DEIDENTIFICATION_METHOD_NAME: &str = "sentinela allowlist v1";
+
+// Structs *****************************************************************************************
+/// De-identified DICOM file, ready for storage
+/// # Arguments
+/// * `bytes` - The rewritten file
+/// * `metadata` - The metadata extracted from it, UIDs pseudonymized
+/// * `image_width` / `image_height` - The Columns and Rows of the image, if present
+#[derive(Debug, Clone)]
+pub struct DeidentifiedDicom {
+    pub bytes: Vec<u8>,
+    pub metadata: DicomMetadata,
+    pub image_width: Option<u32>,
+    pub image_height: Option<u32>,
+}
+
+// MAIN FUNCTIONS **********************************************************************************
+/// Handles a DICOM X-ray: the de-identified file is stored and notified like a streamed upload,
+/// with its metadata in the sidecar and the notification
+/// # Arguments
+/// * `upload` - The validated metadata of the upload
+/// * `dicom` - The de-identified file
+/// * `storage` - The exam storage backend
+/// * `settings` - The storage settings: exam bucket and dead letters
+/// * `publisher` - The notification backend, routed per exam type
+/// * `billing` - An Arc reference to the billing service recording usage
+/// # Returns
+/// * How the exam left the gateway
+/// # Errors
+/// * Returns an error if the file could not be stored
+pub async fn handler_dicom_exam(
+    mut upload: XrayUpload,
+    dicom: DeidentifiedDicom,
+    storage: &Arc<dyn ExamStorage>,
+    settings: &StorageSettings,
+    publisher: &Arc<dyn Publisher>,
+    billing: &Arc<BillingService>,
+) -> Result<Delivery> {
+    info!("Handling DICOM X-ray - storing the de-identified file");
+    upload.image_width = dicom.image_width;
+    upload.image_height = dicom.image_height;
+    upload.dicom = Some(dicom.metadata);
+    let file = stream::once(ready(Ok::<_, std::io::Error>(Bytes::from(dicom.bytes))));
+    handler_xray_upload(upload, file, storage, settings, publisher, billing).await
+}
+
+/// De-identify a DICOM file: only the allow-listed elements are kept, the UIDs are pseudonymized,
+/// the patient is only known by its gateway id
+/// # Arguments
+/// * `file` - The DICOM Part 10 file as uploaded
+/// * `hospital_id` - The hospital sending the file, part of the UID pseudonyms
+/// * `patient_id` - The gateway patient id (a SHA256 hash), written as Patient ID
+/// * `salt` - The salt of the UID pseudonyms (DICOM_PSEUDONYM_SALT)
+/// # Returns
+/// * The rewritten file and its metadata
+/// # Errors
+/// * Returns an error if the file is not a little endian DICOM Part 10 file
+pub fn deidentify_dicom(
+    file: &[u8],
+    hospital_id: &str,
+    patient_id: &str,
+    salt: Option<&Secret>,
+) -> Result<DeidentifiedDicom> {
+    // STEP 1: Parse the file and extract its metadata
+    let parsed = DicomFile::parse(file)?;
+    let explicit = parsed.explicit_vr();
+    let pseudonym = |tag| {
+        parsed
+            .string(tag)
+            .map(|uid| pseudonymize_uid(hospital_id, &uid, salt))
+    };
+    let metadata = DicomMetadata {
+        modality: parsed.string(MODALITY),
+        sop_class_uid: parsed.string(SOP_CLASS_UID),
+        study_instance_uid: pseudonym(STUDY_INSTANCE_UID),
+        series_instance_uid: pseudonym(SERIES_INSTANCE_UID),
+        sop_instance_uid: pseudonym(SOP_INSTANCE_UID),
+        acquisition_datetime: acquisition_datetime(&parsed),
+        transfer_syntax_uid: parsed.transfer_syntax_uid.clone(),
+    };
+
+    // STEP 2: Dataset - allow-listed elements as sent, pseudonymized UIDs, de-identification marks
+    let mut dataset: Vec<(Tag, Vec<u8>)> = Vec::new();
+    for element in &parsed.elements {
+        if PSEUDONYMIZED_UIDS.contains(&element.tag) {
+            if let Some(uid) = pseudonym(element.tag) {
+                dataset.push((
+                    element.tag,
+                    encode_element(element.tag, b"UI", uid.as_bytes(), explicit),
+                ));
+            }
+        } else if KEPT_TAGS.contains(&element.tag) || KEPT_GROUPS.contains(&element.tag.0) {
+            dataset.push((element.tag, file[element.raw.clone()].to_vec()));
+        }
+    }
+    let patient = patient_pseudonym(patient_id);
+    dataset.push((
+        PATIENT_ID,
+        encode_element(PATIENT_ID, b"LO", patient.as_bytes(), explicit),
+    ));
+    dataset.push((
+        PATIENT_IDENTITY_REMOVED,
+        encode_element(PATIENT_IDENTITY_REMOVED, b"CS", b"YES", explicit),
+    ));
+    dataset.push((
+        DEIDENTIFICATION_METHOD,
+        encode_element(
+            DEIDENTIFICATION_METHOD,
+            b"LO",
+            DEIDENTIFICATION_METHOD_NAME.as_bytes(),
+            explicit,
+        ),
+    ));
+    dataset.sort_by_key(|(tag, _)| *tag);
+
+    // STEP 3: File meta information - its group length is recomputed
+    let mut meta: Vec<u8> = Vec::new();
+    for element in &parsed.meta {
+        if element.tag == MEDIA_STORAGE_SOP_INSTANCE_UID {
+            if let Some(uid) = &metadata.sop_instance_uid {
+                meta.extend(encode_element(element.tag, b"UI", uid.as_bytes(), true));
+            }
+        } else if KEPT_META_TAGS.contains(&element.tag) {
+            meta.extend_from_slice(&file[element.raw.clone()]);
+        }
+    }
+
+    // STEP 4: Assemble - a blank preamble, the prefix, the meta information and the dataset
+    let mut bytes = vec![0u8; 128];
+    bytes.extend_from_slice(b"DICM");
+    bytes.extend(encode_element(
+        FILE_META_GROUP_LENGTH,
+        b"UL",
+        &(meta.len() as u32).to_le_bytes(),
+        true,
+    ));
+    bytes.extend(meta);
+    for (_, element) in dataset {
+        bytes.extend(element);
+    }
+    Ok(DeidentifiedDicom {
+        image_width: parsed.u16_value(COLUMNS).map(u32::from),
+        image_height: parsed.u16_value(ROWS).map(u32::from),
+        bytes,
+        metadata,
+    })
+}
+
+// SUPPORT FUNCTIONS *******************************************************************************
+/// Pseudonym of a UID: a `2.25` UUID-derived UID from the salted hash of the hospital and the UID
+/// - the salt (DICOM_PSEUDONYM_SALT) keeps the pseudonyms from being recomputed from the UIDs
+/// # Arguments
+/// * `hospital_id` - The hospital the UID was issued by
+/// * `uid` - The original UID
+/// * `salt` - The salt of the pseudonyms (DICOM_PSEUDONYM_SALT)
+fn pseudonymize_uid(hospital_id: &str, uid: &str, salt: Option<&Secret>) -> String {
+    let salt = salt.map_or("", Secret::expose);
+    let digest = Sha256::new()
+        .chain_update(salt.as_bytes())
+        .chain_update(hospital_id.as_bytes())
+        .chain_update(b"/")
+        .chain_update(uid.as_bytes())
+        .finalize();
+    let mut head = [0u8; 16];
+    head.copy_from_slice(&digest[..16]);
+    format!("2.25.{}", u128::from_be_bytes(head))
+}
+
+/// Patient ID written in the file: the gateway id, hashed if it exceeds the 64 characters of a LO
+fn patient_pseudonym(patient_id: &str) -> String {
+    if patient_id.len() <= 64 {
+        patient_id.to_string()
+    } else {
+        format!("{:x}", Sha256::digest(patient_id.as_bytes()))
+    }
+}
+
+/// When the image was acquired: Acquisition DateTime, else Acquisition Date and Time, else the
+/// study date and time
+fn acquisition_datetime(file: &DicomFile) -> Option<String> {
+    file.string(ACQUISITION_DATETIME).or_else(|| {
+        [
+            (ACQUISITION_DATE, ACQUISITION_TIME),
+            (STUDY_DATE, STUDY_TIME),
+        ]
+        .into_iter()
+        .find_map(|(date, time)| {
+            file.string(date)
+                .map(|date| date + file.string(time).unwrap_or_default().as_str())
+        })
+    })
+}
+
+// TESTS *******************************************************************************************
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::models_dicom::tests::dicom_file;
+
+    const PATIENT_NAME: Tag = (0x0010, 0x0010);
+    const INSTITUTION_NAME: Tag = (0x0008, 0x0080);
+
+    fn sample(transfer_syntax: &str) -> Vec<u8> {
+        dicom_file(
+            transfer_syntax,
+            &[
+                (SOP_CLASS_UID, b"UI", b"1.2.840.10008.5.1.4.1.1.1.1"),
+                (SOP_INSTANCE_UID, b"UI", b"1.2.3.4.5"),
+                (ACQUISITION_DATE, b"DA", b"20260101"),
+                (ACQUISITION_TIME, b"TM", b"101500"),
+                (MODALITY, b"CS", b"DX"),
+                (INSTITUTION_NAME, b"LO", b"General Hospital"),
+                (PATIENT_NAME, b"PN", b"DOE^JOHN"),
+                (PATIENT_ID, b"LO", b"MRN-1234"),
+                ((0x0011, 0x0010), b"LO", b"PRIVATE CREATOR"),
+                (STUDY_INSTANCE_UID, b"UI", b"1.2.3"),
+                (SERIES_INSTANCE_UID, b"UI", b"1.2.3.4"),
+                (ROWS, b"US", &512u16.to_le_bytes()),
+                (COLUMNS, b"US", &256u16.to_le_bytes()),
+                (PIXEL_DATA, b"OW", &[7u8; 16]),
+            ],
+        )
+    }
+
+    // Happy path: identity removed, UIDs pseudonymized, image kept - in both syntaxes
+    #[test]
+    fn deidentify_happy_path() {
+        for syntax in ["1.2.840.10008.1.2.1", "1.2.840.10008.1.2"] {
+            let dicom = deidentify_dicom(&sample(syntax), "hospital", "abc123", None).unwrap();
+            let file = DicomFile::parse(&dicom.bytes).unwrap();
+            assert_eq!(file.string(PATIENT_NAME), None);
+            assert_eq!(file.string(INSTITUTION_NAME), None);
+            assert!(file.element((0x0011, 0x0010)).is_none());
+            assert_eq!(file.string(PATIENT_ID).as_deref(), Some("abc123"));
+            assert_eq!(
+                file.string(PATIENT_IDENTITY_REMOVED).as_deref(),
+                Some("YES")
+            );
+            assert_eq!(file.string(MODALITY).as_deref(), Some("DX"));
+            assert_eq!(
+                file.string(STUDY_INSTANCE_UID),
+                dicom.metadata.study_instance_uid
+            );
+            let pixels = file.element(PIXEL_DATA).unwrap();
+            assert_eq!(&dicom.bytes[pixels.value.clone()], &[7u8; 16]);
+
+            assert_eq!(dicom.metadata.modality.as_deref(), Some("DX"));
+            assert_eq!(
+                dicom.metadata.acquisition_datetime.as_deref(),
+                Some("20260101101500")
+            );
+            assert_eq!(
+                (dicom.image_width, dicom.image_height),
+                (Some(256), Some(512))
+            );
+            assert_eq!(dicom.metadata.transfer_syntax_uid, syntax);
+        }
+    }
+
+    // Pseudonyms are stable per hospital, never the original UID
+    #[test]
+    fn uid_pseudonyms() {
+        let uid = pseudonymize_uid("hospital", "1.2.3", None);
+        assert_eq!(uid, pseudonymize_uid("hospital", "1.2.3", None));
+        assert_ne!(uid, pseudonymize_uid("other", "1.2.3", None));
+        let salt = Secret("salt".to_string());
+        assert_ne!(uid, pseudonymize_uid("hospital", "1.2.3", Some(&salt)));
+        assert!(uid.starts_with("2.25.") && uid.len() <= 64);
+        assert_eq!(patient_pseudonym(&"a".repeat(100)).len(), 64);
+    }
+
+    // Error handling: files that are not DICOM are refused
+    #[test]
+    fn deidentify_rejects_non_dicom() {
+        assert!(deidentify_dicom(b"\x89PNG", "hospital", "abc123", None).is_err());
+    }
+}
@@ -0,0 +1,199 @@
+// Imports *****************************************************************************************
+// External Crates
+#[cfg(feature = "grpc")]
+use google_cloud_pubsub::client::Client as PubSubClient;
+#[cfg(feature = "grpc")]
+use log::info;
+use log::warn;
+use serde::Deserialize;
+use std::sync::atomic::{AtomicI64, AtomicU64, Ordering};
+#[cfg(feature = "grpc")]
+use std::sync::Arc;
+use std::sync::OnceLock;
+use std::time::Duration;
+
+// Internal Modules
+use crate::config::settings::{DownstreamSettings, DEFAULT_MAX_LAG_S, DEFAULT_MAX_QUEUE_DEPTH};
+use crate::utils::drain_state::{DrainReason, DRAIN_STATE};
+#[cfg(feature = "grpc")]
+use crate::utils::external_call::{Dependency, ExternalCall};
+use crate::utils::publish_backlog::PUBLISH_BACKLOG;
+
+// Constants ***************************************************************************************
+/// Feedback older than this is ignored - a silent downstream is not treated as saturated
+const FEEDBACK_STALE_AFTER_S: i64 = 120;
+/// Interval between two pulls of the feedback subscription
+const PULL_INTERVAL: Duration = Duration::from_secs(5);
+/// Longest time a deferred publish waits for downstream to recover before publishing anyway
+pub const MAX_DEFERRAL: Duration = Duration::from_secs(600);
+
+// Global variables ********************************************************************************
+/// Last queue depth reported by the inference service
+static QUEUE_DEPTH: AtomicU64 = AtomicU64::new(0);
+/// Last processing lag reported by the inference service, in milliseconds
+static LAG_MS: AtomicU64 = AtomicU64::new(0);
+/// Unix timestamp (seconds) of the last feedback, 0 if none was received
+static UPDATED_AT: AtomicI64 = AtomicI64::new(0);
+/// Saturation thresholds, set once at startup
+static DOWNSTREAM_SETTINGS: OnceLock<DownstreamSettings> = OnceLock::new();
+
+// Structs *****************************************************************************************
+/// Feedback message published by the inference service
+/// # Arguments
+/// * `queue_depth` - Number of exams waiting to be processed downstream
+/// * `processing_lag_s` - Age in seconds of the oldest exam waiting downstream
+#[derive(Debug, Deserialize)]
+#[cfg_attr(not(feature = "grpc"), allow(dead_code))]
+pub struct DownstreamFeedback {
+    pub queue_depth: u64,
+    pub processing_lag_s: f64,
+}
+
+// MAIN FUNCTIONS **********************************************************************************
+/// Set the saturation thresholds - called once at startup, the defaults apply until then
+/// # Arguments
+/// * `downstream` - The downstream settings (DOWNSTREAM_MAX_QUEUE_DEPTH, DOWNSTREAM_MAX_LAG_S)
+pub fn init_downstream_settings(downstream: DownstreamSettings) {
+    if DOWNSTREAM_SETTINGS.set(downstream).is_err() {
+        warn!("Downstream settings already initialized");
+    }
+}
+
+/// Pull the downstream feedback subscription forever, updating the saturation state
+/// Does nothing when DOWNSTREAM_FEEDBACK_SUBSCRIPTION is not set
+/// # Arguments
+/// * `subscription_name` - The feedback subscription (DOWNSTREAM_FEEDBACK_SUBSCRIPTION)
+/// * `pubsub_client` - An Arc reference to the PubSub client
+#[cfg(feature = "grpc")]
+pub async fn listen_downstream_feedback(
+    subscription_name: Option<String>,
+    pubsub_client: Arc<PubSubClient>,
+) {
+    let Some(subscription_name) = subscription_name else {
+        info!("DOWNSTREAM_FEEDBACK_SUBSCRIPTION not set - downstream feedback disabled");
+        return;
+    };
+    info!("Listening to downstream feedback on {subscription_name}");
+    let subscription = pubsub_client.subscription(&subscription_name);
+
+    loop {
+        let pulled = ExternalCall::new(Dependency::PubSub, "pull_feedback")
+            .run(|| subscription.pull(10, None))
+            .await;
+        match pulled {
+            Ok(messages) => {
+                for message in messages {
+                    match serde_json::from_slice::<DownstreamFeedback>(&message.message.data) {
+                        Ok(feedback) => record_feedback(&feedback, chrono::Utc::now().timestamp()),
+                        Err(e) => warn!("Ignoring malformed downstream feedback: {e}"),
+                    }
+                    if let Err(e) = message.ack().await {
+                        warn!("Could not ack downstream feedback: {e}");
+                    }
+                }
+            }
+            Err(e) => warn!("Could not pull downstream feedback: {e}"),
+        }
+        tokio::time::sleep(PULL_INTERVAL).await;
+    }
+}
+
+/// Whether the inference service currently reports saturation
+/// # Returns
+/// * true if recent feedback exceeds DOWNSTREAM_MAX_QUEUE_DEPTH or DOWNSTREAM_MAX_LAG_S
+pub fn is_saturated() -> bool {
+    let (max_depth, max_lag_s) = DOWNSTREAM_SETTINGS
+        .get()
+        .map_or((DEFAULT_MAX_QUEUE_DEPTH, DEFAULT_MAX_LAG_S), |downstream| {
+            (downstream.max_queue_depth, downstream.max_lag_s)
+        });
+    saturated_at(chrono::Utc::now().timestamp(), max_depth, max_lag_s)
+}
+
+/// Wait until downstream is no longer saturated and the publish backlog has drained, or
+/// MAX_DEFERRAL elapsed - a shutdown ends the wait, the deferred exams publish before the stop
+pub async fn wait_until_unsaturated() {
+    let deadline = tokio::time::Instant::now() + MAX_DEFERRAL;
+    while (is_saturated() || PUBLISH_BACKLOG.is_backlogged())
+        && tokio::time::Instant::now() < deadline
+        && !DRAIN_STATE.is_set(DrainReason::ShutdownInitiated)
+    {
+        tokio::time::sleep(PULL_INTERVAL).await;
+    }
+}
+
+// SUPPORT FUNCTIONS *******************************************************************************
+/// Store a feedback message as the current downstream state
+/// # Arguments
+/// * `feedback` - The feedback message
+/// * `now` - The current unix timestamp in seconds
+#[cfg_attr(not(feature = "grpc"), allow(dead_code))]
+fn record_feedback(feedback: &DownstreamFeedback, now: i64) {
+    QUEUE_DEPTH.store(feedback.queue_depth, Ordering::Relaxed);
+    LAG_MS.store(
+        (feedback.processing_lag_s.max(0.0) * 1000.0) as u64,
+        Ordering::Relaxed,
+    );
+    UPDATED_AT.store(now, Ordering::Relaxed);
+}
+
+/// Evaluate the saturation state against thresholds
+/// # Arguments
+/// * `now` - The current unix timestamp in seconds
+/// * `max_depth` - Queue depth threshold
+/// * `max_lag_s` - Processing lag threshold in seconds
+fn saturated_at(now: i64, max_depth: u64, max_lag_s: f64) -> bool {
+    let updated_at = UPDATED_AT.load(Ordering::Relaxed);
+    if updated_at == 0 || now - updated_at > FEEDBACK_STALE_AFTER_S {
+        return false;
+    }
+    let lag_s = LAG_MS.load(Ordering::Relaxed) as f64 / 1000.0;
+    QUEUE_DEPTH.load(Ordering::Relaxed) > max_depth || lag_s > max_lag_s
+}
+
+// TESTS *******************************************************************************************
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Thresholds, staleness and recovery in one test - the state is process-global
+    #[test]
+    fn saturation_follows_feedback() {
+        let now = 1_000_000;
+        record_feedback(
+            &DownstreamFeedback {
+                queue_depth: 5_000,
+                processing_lag_s: 1.0,
+            },
+            now,
+        );
+        assert!(saturated_at(now, 1_000, 60.0));
+        // Stale feedback is ignored
+        assert!(!saturated_at(now + FEEDBACK_STALE_AFTER_S + 1, 1_000, 60.0));
+
+        record_feedback(
+            &DownstreamFeedback {
+                queue_depth: 10,
+                processing_lag_s: 90.0,
+            },
+            now,
+        );
+        assert!(saturated_at(now, 1_000, 60.0));
+
+        record_feedback(
+            &DownstreamFeedback {
+                queue_depth: 10,
+                processing_lag_s: 2.0,
+            },
+            now,
+        );
+        assert!(!saturated_at(now, 1_000, 60.0));
+    }
+
+    #[test]
+    fn feedback_deserializes() {
+        let f: DownstreamFeedback =
+            serde_json::from_str(r#"{"queue_depth": 3, "processing_lag_s": 0.5}"#).unwrap();
+        assert_eq!(f.queue_depth, 3);
+    }
+}
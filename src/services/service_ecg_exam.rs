@@ -1,78 +1,232 @@
 // Imports *****************************************************************************************
 // External Crates
 use anyhow::Result;
-use chrono;
-use google_cloud_googleapis::pubsub::v1::PubsubMessage;
-use google_cloud_pubsub::client::Client as PubSubClient;
-use google_cloud_storage::client::Client as GcsClient;
-use google_cloud_storage::http::objects::upload::{Media, UploadObjectRequest, UploadType};
+use chrono::{DateTime, Utc};
 use log::{error, info};
-use polars::io::json::JsonReader;
 use polars::io::parquet::{ParquetWriter, ZstdLevel};
 use polars::prelude::*;
 use serde::Serialize;
-use std::borrow::Cow;
 use std::collections::HashMap;
-use std::io::Cursor;
 use std::sync::Arc;
 
 // Internal Modules
+use crate::audit::audit_event::{AuditAction, AuditEvent};
+use crate::audit::audit_trail::record_audit;
+use crate::config::settings::StorageSettings;
+use crate::models::models_consent::ConsentScope;
+use crate::models::models_ecg_quality::{ECG_DEFAULT_DURATION_S, ECG_DEFAULT_SAMPLING_RATE_HZ};
 use crate::models::models_exams::PayloadEcg;
+use crate::models::models_validation_profiles::ECG_PROFILE;
+use crate::publishers::publisher::{NotificationMessage, Publisher};
+use crate::services::service_billing::{BillingEvent, BillingService};
+use crate::services::service_dead_letter::{dead_letter, DeadLetterRecord, Delivery, FailedStage};
+use crate::services::service_downstream_feedback::wait_until_unsaturated;
+use crate::services::service_idempotency::tag_message;
+use crate::services::service_message_format::{
+    encode_message, tag_exam_message, INFERENCE_ECG_AVRO,
+};
+use crate::services::service_research_sampling::{offer_research_sample, ResearchSample};
+use crate::storage::exam_storage::{ExamStorage, ObjectPut};
+use crate::telemetry::metrics::record_message_published;
+use crate::telemetry::trace_context::{in_current_trace, trace_attributes};
+use crate::utils::external_call::INGEST_RETRIES;
+use crate::utils::ids::ExamId;
+use crate::utils::publish_backlog::{BacklogState, PUBLISH_BACKLOG};
+use crate::utils::reason_code::ReasonCode;
+use crate::utils::stage_metrics::{latency_attributes, observe_stage_between, Stage};
+
+// Constants ***************************************************************************************
+/// Exam type key used for storage prefixes and PubSub routing
+const EXAM_TYPE: &str = "ecg_exam";
+/// Column numbering the samples of the stored ECG, one row per sample
+pub const ECG_SAMPLE_INDEX_COLUMN: &str = "sample_index";
+/// Version of the Parquet layout of `ecg_exam_frame` - bumped on any change of its columns or types
+pub const ECG_PARQUET_SCHEMA_VERSION: u32 = 1;
+/// Float32 columns of the stored ECG leads, in the standard 12-lead order
+pub const ECG_LEAD_COLUMNS: [&str; 12] = [
+    "lead_i", "lead_ii", "lead_iii", "lead_avr", "lead_avl", "lead_avf", "lead_v1", "lead_v2",
+    "lead_v3", "lead_v4", "lead_v5", "lead_v6",
+];
 
 // Services ****************************************************************************************
 // Follow service protocol for handling ECG exam data
 /// Handles the processing of an ECG exam data from processing to storage and PubSub
 /// # Arguments
 /// * `data` - A Payload struct containing the validated data of the ECG exam
-/// * `gcs_client` - An Arc reference to the GCS client for storage operations
-/// * `pubsub_client` - An Arc reference to the PubSub client for message publishing
+/// * `exam_id` - The id handed to the hospital when the exam was accepted
+/// * `storage` - The exam storage backend
+/// * `settings` - The storage settings: exam bucket and dead letters
+/// * `publisher` - The notification backend, routed per exam type
+/// * `billing` - An Arc reference to the billing service recording usage
+/// * `deferred` - Publish in the background once downstream is no longer saturated
+/// * `received_at` - When the gateway received the exam, for end-to-end latency
+/// * `consent_scope` - The data-sharing consent of the hospital
+/// * `idempotency_key` - The idempotency key of the request, passed on to the consumers
 /// # Returns
-/// * A Result indicating success or failure of the operation
+/// * How the exam left the gateway - exams failing storage or publish after all retries are
+///   dead-lettered for replay
 /// # Errors
-/// * Returns an error if any step in the processing fails
+/// * Returns an error if any step in the processing fails and the exam could not be dead-lettered
 pub async fn handler_ecg_exam(
     data: PayloadEcg,
-    gcs_client: &Arc<GcsClient>,
-    pubsub_client: &Arc<PubSubClient>,
-) -> Result<()> {
+    exam_id: &ExamId,
+    storage: &Arc<dyn ExamStorage>,
+    settings: &StorageSettings,
+    publisher: &Arc<dyn Publisher>,
+    billing: &Arc<BillingService>,
+    deferred: bool,
+    received_at: DateTime<Utc>,
+    consent_scope: ConsentScope,
+    idempotency_key: Option<String>,
+) -> Result<Delivery> {
     info!("Handling ECG payload - pre-processing the data");
-    // STEP 1: Pre-process the data
-    let prep_data = preprocess_ecg_data(data.clone())?;
+    let started_at = Utc::now();
+    observe_stage_between(EXAM_TYPE, Stage::Intake, received_at, started_at);
+
+    // STEP 1: Pre-process the notification - the stored exam is written from the payload itself
+    let topic = publisher.topic_name(EXAM_TYPE)?;
+    let object_path = storage.location(
+        &settings.bucket_name,
+        &storage.object_name(exam_id, "parquet"),
+    );
+    let pubsub_data = preprocess_ecg_data(
+        &data,
+        exam_id,
+        deferred,
+        topic,
+        &object_path,
+        consent_scope,
+        received_at,
+    )?;
+    let preprocessed_at = Utc::now();
+    observe_stage_between(EXAM_TYPE, Stage::Preprocess, started_at, preprocessed_at);
 
     // STEP 2: Save ECG exam data to persistent storage
-    let parquet = prep_data
-        .get("parquet")
-        .ok_or_else(|| anyhow::anyhow!("Missing 'parquet' entry in prep_data"))?
-        .clone();
-    save_ecg_exam_data(parquet, gcs_client).await?;
+    let hospital_id = data.hospital_id.to_string();
+    let timestamp = exam_timestamp(received_at);
+    let bytes_stored = match save_ecg_exam_data(
+        &data,
+        exam_id,
+        &timestamp,
+        consent_scope,
+        storage,
+        settings,
+    )
+    .await
+    {
+        Ok(bytes_stored) => bytes_stored,
+        Err(e) => {
+            // Storage is down: keep the whole exam so it can be replayed
+            let record = DeadLetterRecord {
+                exam_type: EXAM_TYPE.to_string(),
+                exam_id: exam_id.to_string(),
+                hospital_id,
+                stage: FailedStage::Storage,
+                error: e.to_string(),
+                reason: ReasonCode::of_error(&e),
+                failed_at: Utc::now(),
+                payload: ecg_exam_record(&data, &timestamp, consent_scope)?,
+                attributes: HashMap::new(),
+            };
+            dead_letter(&record, storage, settings).await?;
+            return Ok(Delivery::DeadLettered);
+        }
+    };
+    let stored_at = Utc::now();
+    observe_stage_between(EXAM_TYPE, Stage::Storage, preprocessed_at, stored_at);
 
     info!("Handling ECG payload - pre-processing the data - done - parquet saved");
 
+    // STEP 2b: Record the usage for billing (never fails the exam)
+    billing
+        .record(BillingEvent {
+            hospital_id,
+            exam_type: EXAM_TYPE.to_string(),
+            bytes_stored,
+            timestamp: stored_at,
+        })
+        .await;
+
+    // STEP 2c: Offer the stored exam to the research mirror (background, consented hospitals only)
+    // - its record is only built if the exam is sampled
+    offer_research_sample(
+        ResearchSample {
+            exam_type: EXAM_TYPE,
+            exam_id: exam_id.to_string(),
+            hospital_id: data.hospital_id.to_string(),
+            patient_id: data.patient_id.to_string(),
+            consent_scope,
+            image: None,
+        },
+        || ecg_exam_record(&data, &timestamp, consent_scope),
+        storage,
+    );
+
     // STEP 3: Send to PubSub for further processing
-    let pubsub_data = prep_data
-        .get("pubsub")
-        .ok_or_else(|| anyhow::anyhow!("Missing 'pubsub' entry in prep_data"))?
-        .clone();
-    send_to_pubsub(pubsub_data.clone(), pubsub_client).await?;
+    if deferred {
+        // Downstream is saturated or Pub/Sub is backlogged: the exam is safely stored, notify once
+        // it recovers - the waiting notification counts in the backlog
+        let publisher = publisher.clone();
+        let storage = storage.clone();
+        let settings = settings.clone();
+        let waiting = PUBLISH_BACKLOG.track(BacklogState::Deferred);
+        actix_web::rt::spawn(in_current_trace(async move {
+            wait_until_unsaturated().await;
+            drop(waiting);
+            observe_stage_between(EXAM_TYPE, Stage::Deferral, stored_at, Utc::now());
+            if let Err(e) = send_to_pubsub(
+                pubsub_data,
+                &publisher,
+                &storage,
+                &settings,
+                received_at,
+                stored_at,
+                consent_scope,
+                idempotency_key.as_deref(),
+            )
+            .await
+            {
+                error!("Deferred ECG publish failed: {e}");
+            }
+        }));
+        info!("ECG exam stored - publish deferred until downstream recovers");
+        return Ok(Delivery::Deferred);
+    }
+    let delivery = send_to_pubsub(
+        pubsub_data.clone(),
+        publisher,
+        storage,
+        settings,
+        received_at,
+        stored_at,
+        consent_scope,
+        idempotency_key.as_deref(),
+    )
+    .await?;
 
     info!("Handling ECG payload - pre-processing the data - done - parquet saved - pubsub sent");
 
     // STEP 4: Return success response
     info!("ECG exam successfully processed");
-    Ok(())
+    Ok(delivery)
 }
 
 // Support Functions & Structs *********************************************************************
-/// Struct to represent the ECG exam data in a format suitable for Parquet storage
+/// Struct to represent the ECG exam as a single JSON record - dead letters and research samples
 /// # Arguments
 /// * `timestamp` - A string representing the timestamp of the ECG exam
+/// * `consent_scope` - The data-sharing consent of the hospital, kept with the exam
+/// * `validation_profile_id` / `validation_profile_version` - The profile the exam passed
 /// * data - A Payload struct containing the data of the ECG exam
 #[derive(serde::Serialize, Debug)]
-struct EcgExamParquet {
+struct EcgExamParquet<'a> {
     exam_type: String,
     timestamp: String,
+    consent_scope: ConsentScope,
+    validation_profile_id: String,
+    validation_profile_version: u32,
     #[serde(flatten)]
-    data: PayloadEcg,
+    data: &'a PayloadEcg,
 }
 
 /// Struct to represent the ECG exam data in a format suitable for PubSub
@@ -82,153 +236,333 @@ struct EcgExamParquet {
 /// * `timestamp` - A string representing the timestamp of the ECG exam
 /// * `patient_id` - A string representing the patient id (SHA256 hash)
 /// * `hospital_id` - A string representing the hospital id (SHA256 hash)
+/// * `exam_id` - A string identifying the stored exam (object name without extension)
+/// * `deferred` - Whether the publish was delayed because downstream was saturated
+/// * `consent_scope` - The data-sharing consent of the hospital
+/// * `object_path` - The location of the stored Parquet file, e.g. `gs://bucket/ecg_exam/...`
 #[derive(Serialize, Debug)]
 struct EcgExamPubSub {
     topic: String,
+    exam_id: String,
     exam_type: String,
     timestamp: String,
     patient_id: String,
     hospital_id: String,
+    deferred: bool,
+    consent_scope: ConsentScope,
+    object_path: String,
+}
+
+/// Identifier of a new ECG exam: the object name of its Parquet file, without extension
+/// # Arguments
+/// * `data` - The payload of the exam
+/// * `received_at` - When the gateway received the exam
+/// # Returns
+/// * `ecg_exam/{hospital_id}/{patient_id}/{uuid}`, handed to the hospital before the exam is
+///   processed
+pub fn ecg_exam_id(data: &PayloadEcg, received_at: DateTime<Utc>) -> ExamId {
+    ExamId::generate(EXAM_TYPE, &data.hospital_id, &data.patient_id, received_at)
+}
+
+/// Timestamp of an exam as used in its object name and Parquet file
+fn exam_timestamp(received_at: DateTime<Utc>) -> String {
+    received_at.format("%Y-%m-%dT%H%M%S%.fZ").to_string()
 }
 
-/// Pre-process the ECG data for storage and PubSub
+/// Pre-process the ECG notification for PubSub
 /// # Arguments
 /// * `data` - A Payload struct containing the validated data of the ECG exam
+/// * `exam_id` - The id of the exam
+/// * `deferred` - Whether the PubSub message will be published in the background
+/// * `topic` - The PubSub topic routed for ECG exams
+/// * `object_path` - The location the Parquet file is stored at
+/// * `consent_scope` - The data-sharing consent of the hospital
+/// * `received_at` - When the gateway received the exam, the timestamp of the exam
 /// # Returns
-/// * A HashMap containing two entries: one for Parquet storage and one for PubSub
+/// * The PubSub notification of the exam
 /// # Errors
 /// * Returns an error if serialization fails
-fn preprocess_ecg_data(data: PayloadEcg) -> Result<HashMap<String, serde_json::Value>> {
-    // STEP 1: Get name variables
-    let utc_timestamp = chrono::Utc::now();
-    let utc_timestamp_string = utc_timestamp.format("%Y-%m-%dT%H%M%S%.fZ").to_string();
-
-    // STEP 2: Create the ECG exam data structure for Parquet storage
-    let ecg_exam_parquet = EcgExamParquet {
-        exam_type: "ECG Exam".to_string(),
-        timestamp: utc_timestamp_string.clone(),
-        data: data.clone(),
-    };
-
-    // STEP 3: Create the ECG exam data structure for PubSub
+fn preprocess_ecg_data(
+    data: &PayloadEcg,
+    exam_id: &ExamId,
+    deferred: bool,
+    topic: &str,
+    object_path: &str,
+    consent_scope: ConsentScope,
+    received_at: DateTime<Utc>,
+) -> Result<serde_json::Value> {
+    // STEP 1: Create the ECG exam data structure for PubSub - it carries no samples
     let ecg_exam_pubsub = EcgExamPubSub {
-        topic: "topic-ecg-dev".to_string(), // TODO: after PoC: discuss name for dev/prod
+        topic: topic.to_string(),
+        exam_id: exam_id.to_string(),
         exam_type: "ECG Exam".to_string(),
-        timestamp: utc_timestamp_string,
-        patient_id: data.patient_id.clone(),
-        hospital_id: data.hospital_id.clone(),
+        timestamp: exam_timestamp(received_at),
+        patient_id: data.patient_id.to_string(),
+        hospital_id: data.hospital_id.to_string(),
+        deferred,
+        consent_scope,
+        object_path: object_path.to_string(),
     };
 
-    // STEP 4: Convert the structures to HashMap for further processing
-    let mut map = HashMap::new();
-    map.insert(
-        "parquet".to_string(),
-        serde_json::to_value(ecg_exam_parquet)?,
-    );
-    map.insert("pubsub".to_string(), serde_json::to_value(ecg_exam_pubsub)?);
+    // STEP 2: Convert the structure to JSON for further processing
+    Ok(serde_json::to_value(ecg_exam_pubsub)?)
+}
 
-    Ok(map)
+/// Whole ECG exam as one JSON record, samples included - only built for the exams dead-lettered
+/// or sampled for research, never on the path of every exam
+/// # Arguments
+/// * `data` - A Payload struct containing the validated data of the ECG exam
+/// * `timestamp` - The timestamp of the exam
+/// * `consent_scope` - The data-sharing consent of the hospital, kept with the exam
+/// # Returns
+/// * The record, without the deprecated hospital key - it is never persisted
+/// # Errors
+/// * Returns an error if serialization fails
+fn ecg_exam_record(
+    data: &PayloadEcg,
+    timestamp: &str,
+    consent_scope: ConsentScope,
+) -> Result<serde_json::Value> {
+    let mut record = serde_json::to_value(EcgExamParquet {
+        exam_type: "ECG Exam".to_string(),
+        timestamp: timestamp.to_string(),
+        consent_scope,
+        validation_profile_id: ECG_PROFILE.id.to_string(),
+        validation_profile_version: ECG_PROFILE.version,
+        data,
+    })?;
+    if let Some(record) = record.as_object_mut() {
+        record.remove("hospital_key");
+    }
+    Ok(record)
 }
 
-/// Save the ECG exam data to persistent storage as Parquet in GCP Cloud Storage
+/// Save the ECG exam data to persistent storage as Parquet, in the columnar layout of
+/// `ecg_exam_frame`
 /// # Arguments
-/// * `data` - A serde_json::Value containing the ECG exam data for Parquet storage
-/// * `gcs_client` - An Arc reference to the GCS client for storage operations
+/// * `data` - A Payload struct containing the validated data of the ECG exam
+/// * `exam_id` - The id of the exam, naming its Parquet file
+/// * `timestamp` - The timestamp of the exam
+/// * `consent_scope` - The data-sharing consent of the hospital, kept with the exam
+/// * `storage` - The exam storage backend
+/// * `settings` - The storage settings, naming the exam bucket
 /// # Returns
-/// * A Result indicating success or failure of the operation
+/// * A Result containing the number of bytes stored
 /// # Errors
 /// * Returns an error if any step in the saving process fails
-async fn save_ecg_exam_data(data: serde_json::Value, gcs_client: &Arc<GcsClient>) -> Result<()> {
-    // Save ECG exam data to persistent storage as parquet -> GCP Cloud Storage
+async fn save_ecg_exam_data(
+    data: &PayloadEcg,
+    exam_id: &ExamId,
+    timestamp: &str,
+    consent_scope: ConsentScope,
+    storage: &Arc<dyn ExamStorage>,
+    settings: &StorageSettings,
+) -> Result<u64> {
+    // Save ECG exam data to persistent storage as parquet
     // STEP 1: create the unique file name
-    let bucket_name = std::env::var("BUCKET_NAME")?;
-    let exam_type = "ecg_exam";
-    let hospital_id = data
-        .get("hospital_id")
-        .and_then(|v| v.as_str())
-        .ok_or_else(|| anyhow::anyhow!("hospital_id was not set"))?;
-    let patient_id = data
-        .get("patient_id")
-        .and_then(|v| v.as_str())
-        .ok_or_else(|| anyhow::anyhow!("patient_id was not set"))?;
-    let timestamp = data
-        .get("timestamp")
-        .and_then(|v| v.as_str())
-        .ok_or_else(|| anyhow::anyhow!("timestamp was not set"))?;
-    let object_name = format!("{exam_type}/{hospital_id}/{patient_id}/{timestamp}.parquet");
-
-    // STEP 2: Convert the data to Parquet format
-    // Convert to json string
-    let json = serde_json::to_string(&vec![data])?;
-    // read into a polars DataFrame
-    let mut df = JsonReader::new(Cursor::new(json))
-        .infer_schema_len(None)
-        .finish()?;
-    // Write the DataFrame to Parquet buffer
+    let bucket_name = &settings.bucket_name;
+    let exam_type = EXAM_TYPE;
+    let hospital_id = &data.hospital_id;
+    let object_name = storage.object_name(exam_id, "parquet");
+
+    // STEP 2: Build the columnar DataFrame and write it as Parquet
+    let mut df = ecg_exam_frame(data, timestamp, consent_scope)?;
     let mut buffer = Vec::new();
     ParquetWriter::new(&mut buffer)
         .with_compression(ParquetCompression::Zstd(Some(ZstdLevel::try_new(1)?)))
         .finish(&mut df)?;
 
-    // STEP 3: Upload the Parquet file to GCP Cloud Storage
-    let media = Media::new(Cow::Owned(object_name.clone()));
-    let upload_type = UploadType::Simple(media);
-
-    gcs_client
-        .upload_object(
-            &UploadObjectRequest {
-                bucket: bucket_name,
-                ..Default::default()
-            },
-            buffer,
-            &upload_type,
-        )
-        .await?;
+    // STEP 3: Upload the Parquet file to the exam storage, and record it in the audit trail
+    let event = AuditEvent::exam(AuditAction::ExamStored, exam_type, exam_id, hospital_id)
+        .payload(&buffer)
+        .object_path(storage.location(bucket_name, &object_name));
+    let object = ObjectPut {
+        bucket: bucket_name,
+        name: &object_name,
+        content_type: None,
+        operation: "upload_object",
+        retries: INGEST_RETRIES,
+    };
+    let written = storage.put_object(&object, buffer).await?;
+    record_audit(event);
+    Ok(written)
+}
+
+/// Columnar DataFrame of an ECG exam: one row per sample, a UInt32 `sample_index`, one Float32
+/// column per lead (ECG_LEAD_COLUMNS) and the exam metadata, with the effective sampling rate and
+/// duration, repeated on every row - Parquet
+/// dictionary-encodes the repeated values, so they cost a few bytes per file
+/// The schema is explicit: nothing is inferred and the deprecated hospital key is never stored
+/// # Arguments
+/// * `data` - A Payload struct containing the validated data of the ECG exam
+/// * `timestamp` - The timestamp naming the exam
+/// * `consent_scope` - The data-sharing consent of the hospital
+/// # Returns
+/// * A Result containing the DataFrame of the exam
+/// # Errors
+/// * Returns an error if the leads do not all have the same number of samples
+pub fn ecg_exam_frame(
+    data: &PayloadEcg,
+    timestamp: &str,
+    consent_scope: ConsentScope,
+) -> Result<DataFrame> {
+    // STEP 1: Check that the leads line up
+    let leads = ecg_leads(data);
+    let samples = leads[0].len();
+    if let Some(i) = leads.iter().position(|lead| lead.len() != samples) {
+        return Err(anyhow::anyhow!(
+            "{} has {} samples, expected {samples}",
+            ECG_LEAD_COLUMNS[i],
+            leads[i].len()
+        ));
+    }
+
+    // STEP 2: Sample index and typed lead columns
+    let mut columns = Vec::with_capacity(ECG_LEAD_COLUMNS.len() + 8);
+    columns.push(Series::new(
+        ECG_SAMPLE_INDEX_COLUMN,
+        (0..samples as u32).collect::<Vec<u32>>(),
+    ));
+    for (name, lead) in ECG_LEAD_COLUMNS.iter().zip(leads) {
+        columns.push(Series::new(name, lead));
+    }
 
-    Ok(())
+    // STEP 3: Metadata columns
+    let constant = |name: &str, value: &str| Series::new(name, vec![value; samples]);
+    columns.push(constant("exam_type", "ECG Exam"));
+    columns.push(constant("timestamp", timestamp));
+    columns.push(constant("hospital_id", &data.hospital_id));
+    columns.push(constant("patient_id", &data.patient_id));
+    columns.push(constant("consent_scope", consent_scope.as_str()));
+    columns.push(constant("validation_profile_id", ECG_PROFILE.id));
+    columns.push(Series::new(
+        "validation_profile_version",
+        vec![ECG_PROFILE.version; samples],
+    ));
+    columns.push(Series::new(
+        "sampling_rate_hz",
+        vec![
+            data.sampling_rate_hz
+                .unwrap_or(ECG_DEFAULT_SAMPLING_RATE_HZ);
+            samples
+        ],
+    ));
+    columns.push(Series::new(
+        "duration_s",
+        vec![data.duration_s.unwrap_or(ECG_DEFAULT_DURATION_S); samples],
+    ));
+
+    Ok(DataFrame::new(columns)?)
+}
+
+/// Leads of an ECG payload, in the order of ECG_LEAD_COLUMNS
+fn ecg_leads(data: &PayloadEcg) -> [&[f32]; 12] {
+    [
+        &data.lead_i,
+        &data.lead_ii,
+        &data.lead_iii,
+        &data.lead_avr,
+        &data.lead_avl,
+        &data.lead_avf,
+        &data.lead_v1,
+        &data.lead_v2,
+        &data.lead_v3,
+        &data.lead_v4,
+        &data.lead_v5,
+        &data.lead_v6,
+    ]
 }
 
 /// Send the ECG exam data to PubSub for further processing
 /// # Arguments
 /// * `data` - A serde_json::Value containing the ECG exam data for PubSub
-/// * `pubsub_client` - An Arc reference to the PubSub client for message publishing
+/// * `publisher` - The notification backend, routed per exam type
+/// * `storage` - The exam storage backend, to dead-letter an unpublished message
+/// * `settings` - The storage settings, locating the dead letters
+/// * `received_at` - When the gateway received the exam
+/// * `stored_at` - When the exam was written to storage
+/// * `consent_scope` - The data-sharing consent of the hospital
+/// * `idempotency_key` - The idempotency key of the exam, set as attribute
 /// # Returns
-/// * A Result indicating success or failure of the operation
+/// * Published, or DeadLettered if the publish failed after all retries
 /// # Errors
-/// * Returns an error if any step in the sending process fails
-async fn send_to_pubsub(data: serde_json::Value, pubsub_client: &Arc<PubSubClient>) -> Result<()> {
-    // STEP 1: Extract the topic and message from the data
-    let topic_name = data
-        .get("topic")
-        .and_then(|v| v.as_str())
-        .ok_or_else(|| anyhow::anyhow!("topic was not set"))?;
-
-    // STEP 2: Create the PubSub message as JSON string
-    let payload = serde_json::to_string(&data)?;
-
-    // STEP 3: Get the topic and create a publisher
-    let topic = pubsub_client.topic(topic_name);
-    let publisher = topic.new_publisher(None);
-
-    // STEP 4: Create the PubSub message and publish it
-    // let mut message = google_cloud_pubsub::publisher::PubsubMessage::default();
-    let message = PubsubMessage {
-        data: payload.clone().into_bytes(),
-        attributes: Default::default(),
-        message_id: "".to_string(),
-        publish_time: None,
+/// * Returns an error if the routed destination is not covered by the consent, or if any step
+///   in the sending process fails and the message could not be dead-lettered
+async fn send_to_pubsub(
+    data: serde_json::Value,
+    publisher: &Arc<dyn Publisher>,
+    storage: &Arc<dyn ExamStorage>,
+    settings: &StorageSettings,
+    received_at: DateTime<Utc>,
+    stored_at: DateTime<Utc>,
+    consent_scope: ConsentScope,
+    idempotency_key: Option<&str>,
+) -> Result<Delivery> {
+    // STEP 1: Resolve the routed topic (possibly in a partner project) - only if consented
+    publisher.check_consent(EXAM_TYPE, consent_scope)?;
+    let topic_name = publisher.topic_name(EXAM_TYPE)?;
+    // The notification counts in the backlog until acked or dead-lettered
+    let _in_flight = PUBLISH_BACKLOG.track(BacklogState::InFlight);
+
+    // STEP 2: Create the PubSub message in the format of the topic (JSON, Avro or both)
+    // Latency timestamps travel as attributes so consumers can compute end-to-end latency
+    let published_at = Utc::now();
+    let mut attributes = latency_attributes(received_at, stored_at, published_at);
+    attributes.insert(
+        "consent_scope".to_string(),
+        consent_scope.as_str().to_string(),
+    );
+    // The trace joins the consumers' spans to the gateway's
+    attributes.extend(trace_attributes());
+    let (payload, format) = encode_message(topic_name, INFERENCE_ECG_AVRO, &data, &mut attributes);
+    let mut message = NotificationMessage {
+        data: payload,
+        attributes,
         ordering_key: "".to_string(),
     };
-
-    // STEP 5: Publish the message
-    let awaiter = publisher.publish(message).await;
-    let result = awaiter.get().await;
+    // Retries of the same exam share the key, so the consumers can dedupe them
+    tag_message(&mut message, idempotency_key);
+    // Consumers filter on the routing attributes, and read the exams of a patient in order
+    tag_exam_message(&mut message, &data);
+
+    // STEP 3: Publish the message
+    let result = publisher.publish(EXAM_TYPE, &message).await;
+    let done_at = Utc::now();
+    observe_stage_between(EXAM_TYPE, Stage::Publish, published_at, done_at);
+    observe_stage_between(EXAM_TYPE, Stage::Total, received_at, done_at);
     match result {
-        Ok(message_id) => info!("✅ Published with message ID: {:?}", message_id),
-        Err(e) => error!("❌ Failed to publish: {:?}", e),
+        Ok(message_id) => {
+            info!("✅ Published with message ID: {:?}", message_id);
+            record_message_published(topic_name, format.as_str());
+            record_audit(
+                AuditEvent::exam(
+                    AuditAction::ExamPublished,
+                    EXAM_TYPE,
+                    data["exam_id"].as_str().unwrap_or_default(),
+                    data["hospital_id"].as_str().unwrap_or_default(),
+                )
+                .message_id(message_id),
+            );
+            Ok(Delivery::Published)
+        }
+        Err(e) => {
+            error!("❌ Failed to publish: {:?}", e);
+            // The exam is stored: keep the notification so it can be published later
+            let record = DeadLetterRecord {
+                exam_type: EXAM_TYPE.to_string(),
+                exam_id: data["exam_id"].as_str().unwrap_or_default().to_string(),
+                hospital_id: data["hospital_id"].as_str().unwrap_or_default().to_string(),
+                stage: FailedStage::Publish,
+                error: e.to_string(),
+                reason: ReasonCode::of_error(&e),
+                failed_at: Utc::now(),
+                payload: data,
+                attributes: message.attributes,
+            };
+            dead_letter(&record, storage, settings).await?;
+            Ok(Delivery::DeadLettered)
+        }
     }
-
-    Ok(())
 }
 
 // TESTS *******************************************************************************************
@@ -236,10 +570,13 @@ async fn send_to_pubsub(data: serde_json::Value, pubsub_client: &Arc<PubSubClien
 mod tests {
     use super::*;
     use crate::models::models_exams::{PayloadEcg, ECG_LEAD_LENGTH};
+    use crate::utils::schema_compat::{
+        avro_incompatibilities, proto_incompatibilities, ARCHIVE_EXAM_PROTO, INFERENCE_ECG_AVRO,
+    };
     use validator::Validate;
 
     fn hex64(c: char) -> String {
-        std::iter::repeat(c).take(64).collect()
+        std::iter::repeat_n(c, 64).collect()
     }
     fn lead_ok() -> Vec<f32> {
         let mut v = vec![0.0; ECG_LEAD_LENGTH];
@@ -248,9 +585,9 @@ mod tests {
     }
     fn valid_payload() -> PayloadEcg {
         PayloadEcg {
-            patient_id: hex64('a'),
-            hospital_id: hex64('b'),
-            hospital_key: hex64('c'),
+            patient_id: hex64('a').into(),
+            hospital_id: hex64('b').into(),
+            hospital_key: Some(hex64('c')),
             lead_i: lead_ok(),
             lead_ii: lead_ok(),
             lead_iii: lead_ok(),
@@ -263,20 +600,32 @@ mod tests {
             lead_v4: lead_ok(),
             lead_v5: lead_ok(),
             lead_v6: lead_ok(),
+            sampling_rate_hz: None,
+            duration_s: None,
         }
     }
 
-    // Happy path: returns both entries with expected fields
+    // Happy path: the notification and the record carry the expected fields
     #[test]
     fn preprocess_happy_path() {
         let p = valid_payload();
         assert!(p.validate().is_ok());
 
-        let map = preprocess_ecg_data(p.clone()).expect("preprocess ok");
-        assert!(map.contains_key("parquet"));
-        assert!(map.contains_key("pubsub"));
+        let received_at = Utc::now();
+        let exam_id = ecg_exam_id(&p, received_at);
+        let pubsub = preprocess_ecg_data(
+            &p,
+            &exam_id,
+            false,
+            "dev-ecg-v1",
+            "gs://b/ecg_exam/h/p/t.parquet",
+            ConsentScope::Research,
+            received_at,
+        )
+        .expect("preprocess ok");
+        let parquet =
+            ecg_exam_record(&p, &exam_timestamp(received_at), ConsentScope::Research).unwrap();
 
-        let parquet = map.get("parquet").unwrap();
         assert_eq!(
             parquet.get("exam_type").unwrap().as_str().unwrap(),
             "ECG Exam"
@@ -284,49 +633,164 @@ mod tests {
         let ts = parquet.get("timestamp").unwrap().as_str().unwrap();
         assert!(ts.ends_with('Z'));
 
-        // payload flattened under parquet
+        // payload flattened in the record, without the deprecated hospital key
         assert_eq!(
             parquet.get("patient_id").unwrap().as_str().unwrap(),
-            p.patient_id
+            &*p.patient_id
         );
         assert_eq!(
             parquet.get("hospital_id").unwrap().as_str().unwrap(),
-            p.hospital_id
+            &*p.hospital_id
         );
+        assert!(p.hospital_key.is_some());
+        assert!(parquet.get("hospital_key").is_none());
+        assert!(pubsub.get("lead_i").is_none());
 
-        let pubsub = map.get("pubsub").unwrap();
-        assert_eq!(
-            pubsub.get("topic").unwrap().as_str().unwrap(),
-            "topic-ecg-dev"
-        );
+        assert_eq!(pubsub.get("topic").unwrap().as_str().unwrap(), "dev-ecg-v1");
         assert_eq!(
             pubsub.get("exam_type").unwrap().as_str().unwrap(),
             "ECG Exam"
         );
+        assert_eq!(pubsub.get("deferred").unwrap().as_bool(), Some(false));
+        // consent travels with both the stored exam and the notification
+        assert_eq!(pubsub.get("consent_scope").unwrap(), "research");
+        assert_eq!(parquet.get("consent_scope").unwrap(), "research");
+        assert_eq!(parquet.get("validation_profile_id").unwrap(), "ecg_exam");
+        // exam id matches the object name used for storage (without extension)
+        let published_id = pubsub.get("exam_id").unwrap().as_str().unwrap();
+        assert_eq!(published_id, exam_id.as_str());
+        assert!(published_id.starts_with(&format!("ecg_exam/{}/{}/", p.hospital_id, p.patient_id)));
         assert_eq!(
             pubsub.get("patient_id").unwrap().as_str().unwrap(),
-            p.patient_id
+            &*p.patient_id
         );
         assert_eq!(
             pubsub.get("hospital_id").unwrap().as_str().unwrap(),
-            p.hospital_id
+            &*p.hospital_id
+        );
+    }
+
+    // The exam id handed to the hospital before processing names the stored object
+    #[test]
+    fn exam_id_known_before_processing() {
+        let p = valid_payload();
+        let received_at = Utc::now();
+        let exam_id = ecg_exam_id(&p, received_at);
+        let pubsub = preprocess_ecg_data(
+            &p,
+            &exam_id,
+            false,
+            "dev-ecg-v1",
+            "gs://b/ecg_exam/h/p/t.parquet",
+            ConsentScope::Clinical,
+            received_at,
+        )
+        .unwrap();
+        assert_eq!(pubsub["exam_id"], exam_id.as_str());
+        assert_ne!(ecg_exam_id(&p, received_at), exam_id);
+    }
+
+    // The published message stays readable by the downstream consumers (vendored schemas)
+    #[test]
+    fn pubsub_matches_consumer_schemas() {
+        let message = &preprocess_ecg_data(
+            &valid_payload(),
+            &ExamId::from("ecg_exam/h/p/t".to_string()),
+            true,
+            "dev-ecg-v1",
+            "gs://b/ecg_exam/h/p/t.parquet",
+            ConsentScope::Research,
+            Utc::now(),
+        )
+        .unwrap();
+        assert_eq!(
+            avro_incompatibilities(INFERENCE_ECG_AVRO, message),
+            Vec::<String>::new()
+        );
+        assert_eq!(
+            proto_incompatibilities(ARCHIVE_EXAM_PROTO, "ExamNotification", message),
+            Vec::<String>::new()
         );
     }
 
     // Borderline‑ok: timestamp format parses with your custom fmt
     #[test]
     fn preprocess_timestamp_format() {
-        let map = preprocess_ecg_data(valid_payload()).unwrap();
-        let ts = map
-            .get("parquet")
-            .unwrap()
-            .get("timestamp")
-            .unwrap()
-            .as_str()
-            .unwrap();
+        let record = ecg_exam_record(
+            &valid_payload(),
+            &exam_timestamp(Utc::now()),
+            ConsentScope::Clinical,
+        )
+        .unwrap();
+        let ts = record.get("timestamp").unwrap().as_str().unwrap();
         assert!(ts.ends_with('Z'));
         let ts_no_z = &ts[..ts.len() - 1];
         let fmt = "%Y-%m-%dT%H%M%S%.f";
         chrono::NaiveDateTime::parse_from_str(ts_no_z, fmt).expect("timestamp matches custom fmt");
     }
+
+    // Happy path: one typed row per sample, metadata repeated, no hospital key
+    #[test]
+    fn ecg_frame_columnar_layout() {
+        let df = ecg_exam_frame(
+            &valid_payload(),
+            "2025-01-01T000000.0Z",
+            ConsentScope::Research,
+        )
+        .unwrap();
+        assert_eq!(df.height(), ECG_LEAD_LENGTH);
+        assert_eq!(
+            df.column(ECG_SAMPLE_INDEX_COLUMN).unwrap().dtype(),
+            &DataType::UInt32
+        );
+        for lead in ECG_LEAD_COLUMNS {
+            assert_eq!(df.column(lead).unwrap().dtype(), &DataType::Float32);
+        }
+        let lead_i = df.column("lead_i").unwrap().f32().unwrap();
+        assert_eq!(lead_i.get(0), Some(0.5));
+        assert_eq!(lead_i.get(1), Some(0.0));
+        let consent = df.column("consent_scope").unwrap().str().unwrap();
+        assert_eq!(consent.get(ECG_LEAD_LENGTH - 1), Some("research"));
+        assert!(df.column("hospital_key").is_err());
+        let rate = df.column("sampling_rate_hz").unwrap().f32().unwrap();
+        assert_eq!(rate.get(0), Some(ECG_DEFAULT_SAMPLING_RATE_HZ));
+    }
+
+    // Error handling: leads of different lengths cannot share the sample index
+    #[test]
+    fn ecg_frame_rejects_ragged_leads() {
+        let mut payload = valid_payload();
+        payload.lead_v3.pop();
+        let error = ecg_exam_frame(&payload, "ts", ConsentScope::Clinical).unwrap_err();
+        assert!(error.to_string().contains("lead_v3"));
+    }
+
+    // Borderline: the notification read by the consumers keeps its field names, order and types
+    #[test]
+    fn pubsub_contract() {
+        let notification = EcgExamPubSub {
+            topic: "projects/sentinela/topics/ecg".to_string(),
+            exam_id: "ecg_exam/h1/p1/2026-10-01T120000.000Z".to_string(),
+            exam_type: "ECG Exam".to_string(),
+            timestamp: "2026-10-01T120000.000Z".to_string(),
+            patient_id: "p1".to_string(),
+            hospital_id: "h1".to_string(),
+            deferred: false,
+            consent_scope: ConsentScope::Research,
+            object_path: "gs://exams/ecg_exam/h1/p1/2026-10-01T120000.000Z.parquet".to_string(),
+        };
+        insta::assert_json_snapshot!(notification, @r#"
+        {
+          "topic": "projects/sentinela/topics/ecg",
+          "exam_id": "ecg_exam/h1/p1/2026-10-01T120000.000Z",
+          "exam_type": "ECG Exam",
+          "timestamp": "2026-10-01T120000.000Z",
+          "patient_id": "p1",
+          "hospital_id": "h1",
+          "deferred": false,
+          "consent_scope": "research",
+          "object_path": "gs://exams/ecg_exam/h1/p1/2026-10-01T120000.000Z.parquet"
+        }
+        "#);
+    }
 }
@@ -0,0 +1,409 @@
+// Imports *****************************************************************************************
+// External Crates
+use actix_web::web::Bytes;
+use anyhow::{anyhow, Result};
+use futures_util::{Stream, StreamExt};
+use log::{info, warn};
+use polars::io::json::{JsonFormat, JsonWriter};
+use polars::io::parquet::ParquetReader;
+use polars::prelude::*;
+use std::io::Cursor;
+use std::sync::Arc;
+
+// Internal Modules
+use crate::services::service_ecg_exam::{ECG_LEAD_COLUMNS, ECG_SAMPLE_INDEX_COLUMN};
+use crate::storage::exam_storage::ExamStorage;
+
+// Constants ***************************************************************************************
+/// Exam types that can be exported, matching the GCS prefixes used by the exam services
+pub const EXPORTABLE_EXAM_TYPES: [&str; 2] = ["ecg_exam", "xray_exam"];
+
+/// Fields that are never returned by the export, even to support staff
+const REDACTED_FIELDS: [&str; 1] = ["hospital_key"];
+
+/// Chunks buffered between the storage download and the client - a slow client pauses the download
+const STREAM_BUFFER_CHUNKS: usize = 4;
+
+// MAIN FUNCTIONS **********************************************************************************
+/// Fetch the stored Parquet object of a single exam from the exam storage
+/// # Arguments
+/// * `exam_id` - The exam id, i.e. the object name without the `.parquet` extension
+/// * `bucket` - The exam bucket
+/// * `storage` - The exam storage backend
+/// # Returns
+/// * A Result containing the raw Parquet bytes
+/// # Errors
+/// * Returns an error if the exam id is invalid or the download fails
+pub async fn fetch_exam_parquet(
+    exam_id: &str,
+    bucket: &str,
+    storage: &dyn ExamStorage,
+) -> Result<Vec<u8>> {
+    // STEP 1: Validate the exam id and locate the object
+    let name = exam_object(exam_id)?;
+
+    // STEP 2: Download the object
+    info!("Exporting exam - downloading object {name}");
+    storage.get_object(bucket, &name).await
+}
+
+/// Size in bytes of the stored Parquet object of a single exam
+/// # Arguments
+/// * `exam_id` - The exam id, i.e. the object name without the `.parquet` extension
+/// * `bucket` - The exam bucket
+/// * `storage` - The exam storage backend
+/// # Errors
+/// * Returns an error if the exam id is invalid or the object does not exist
+pub async fn exam_parquet_size(
+    exam_id: &str,
+    bucket: &str,
+    storage: &dyn ExamStorage,
+) -> Result<u64> {
+    let name = exam_object(exam_id)?;
+    Ok(storage.object_info(bucket, &name).await?.size)
+}
+
+/// Stream the stored Parquet object of a single exam (or a byte range of it) without buffering it
+/// The download runs in a background task feeding a bounded channel, so it only advances as fast
+/// as the client reads
+/// # Arguments
+/// * `exam_id` - The exam id, i.e. the object name without the `.parquet` extension
+/// * `bucket` - The exam bucket
+/// * `storage` - The exam storage backend
+/// * `range` - Inclusive byte range to stream, None for the whole object
+/// # Returns
+/// * A stream of chunks, ending with an error if the download fails midway
+/// # Errors
+/// * Returns an error if the exam id is invalid
+pub fn stream_exam_parquet(
+    exam_id: &str,
+    bucket: &str,
+    storage: Arc<dyn ExamStorage>,
+    range: Option<(u64, u64)>,
+) -> Result<impl Stream<Item = std::io::Result<Bytes>>> {
+    let name = exam_object(exam_id)?;
+    let bucket = bucket.to_string();
+    let (tx, rx) = tokio::sync::mpsc::channel(STREAM_BUFFER_CHUNKS);
+
+    actix_web::rt::spawn(async move {
+        let mut chunks = match storage.get_stream(&bucket, &name, range).await {
+            Ok(chunks) => chunks,
+            Err(e) => {
+                let _ = tx.send(Err(std::io::Error::other(e.to_string()))).await;
+                return;
+            }
+        };
+        while let Some(chunk) = chunks.next().await {
+            let chunk = chunk.map_err(|e| {
+                warn!("Exam stream of {name} aborted midway: {e}");
+                e
+            });
+            let failed = chunk.is_err();
+            // A closed channel means the client went away - stop downloading
+            if tx.send(chunk).await.is_err() || failed {
+                return;
+            }
+        }
+    });
+
+    Ok(futures_util::stream::unfold(rx, |mut rx| async move {
+        rx.recv().await.map(|chunk| (chunk, rx))
+    }))
+}
+
+/// Resolve a `Range` request header against the object size (a single range only)
+/// # Arguments
+/// * `header` - The value of the Range header, e.g. `bytes=0-1023`, `bytes=1024-`, `bytes=-512`
+/// * `size` - The object size in bytes
+/// # Returns
+/// * Ok(Some((from, to))) for a satisfiable range (inclusive), Ok(None) to serve the whole object
+///   (unsupported units or multiple ranges)
+/// # Errors
+/// * Returns an error if the range is malformed or not satisfiable (416)
+pub fn resolve_range(header: &str, size: u64) -> Result<Option<(u64, u64)>> {
+    let Some(spec) = header.trim().strip_prefix("bytes=") else {
+        return Ok(None);
+    };
+    if spec.contains(',') {
+        return Ok(None);
+    }
+    let (from, to) = spec
+        .split_once('-')
+        .ok_or_else(|| anyhow!("Malformed range '{header}'"))?;
+    let parse = |v: &str| {
+        v.trim()
+            .parse::<u64>()
+            .map_err(|_| anyhow!("Malformed range '{header}'"))
+    };
+    let (from, to) = match (from.trim().is_empty(), to.trim().is_empty()) {
+        // bytes=-n: the last n bytes
+        (true, false) => {
+            let suffix = parse(to)?;
+            if suffix == 0 {
+                return Err(anyhow!("Unsatisfiable range '{header}'"));
+            }
+            (size.saturating_sub(suffix), size.saturating_sub(1))
+        }
+        // bytes=n-: from n to the end
+        (false, true) => (parse(from)?, size.saturating_sub(1)),
+        (false, false) => (parse(from)?, parse(to)?.min(size.saturating_sub(1))),
+        (true, true) => return Err(anyhow!("Malformed range '{header}'")),
+    };
+    if size == 0 || from >= size || from > to {
+        return Err(anyhow!("Unsatisfiable range '{header}'"));
+    }
+    Ok(Some((from, to)))
+}
+
+/// Convert the stored Parquet bytes of an exam back to its canonical JSON payload
+/// # Arguments
+/// * `parquet` - The raw Parquet bytes as stored by the exam services
+/// * `downsample` - Keep only every n-th sample of each lead (1 keeps all samples)
+/// # Returns
+/// * A Result containing the exam as a JSON object
+/// # Errors
+/// * Returns an error if the Parquet cannot be read or does not contain exactly one exam
+pub fn parquet_to_json(parquet: Vec<u8>, downsample: usize) -> Result<serde_json::Value> {
+    if downsample == 0 {
+        return Err(anyhow!("downsample factor must be at least 1"));
+    }
+
+    // STEP 1: Read the Parquet into a DataFrame - ECG exams stored one row per sample are folded
+    // back into a single exam row
+    let df = ParquetReader::new(Cursor::new(parquet)).finish()?;
+    let mut df = if df.get_column_names().contains(&ECG_SAMPLE_INDEX_COLUMN) {
+        fold_ecg_samples(&df)?
+    } else {
+        df
+    };
+    if df.height() != 1 {
+        return Err(anyhow!("expected a single exam row, found {}", df.height()));
+    }
+
+    // STEP 2: Write the DataFrame as JSON rows
+    let mut buffer = Vec::new();
+    JsonWriter::new(&mut buffer)
+        .with_json_format(JsonFormat::Json)
+        .finish(&mut df)?;
+    let mut rows: Vec<serde_json::Map<String, serde_json::Value>> =
+        serde_json::from_slice(&buffer)?;
+    let mut exam = rows
+        .pop()
+        .ok_or_else(|| anyhow!("exam row missing after conversion"))?;
+
+    // STEP 3: Redact secrets and downsample the leads
+    for field in REDACTED_FIELDS {
+        exam.remove(field);
+    }
+    for (key, value) in exam.iter_mut() {
+        if key.starts_with("lead_") {
+            if let serde_json::Value::Array(samples) = value {
+                *samples = samples.iter().step_by(downsample).cloned().collect();
+            }
+        }
+    }
+    // The downsampled leads keep their duration, not their sampling rate
+    if let Some(rate) = exam.get("sampling_rate_hz").and_then(|rate| rate.as_f64()) {
+        exam.insert(
+            "sampling_rate_hz".to_string(),
+            serde_json::json!(rate / downsample as f64),
+        );
+    }
+
+    Ok(serde_json::Value::Object(exam))
+}
+
+// SUPPORT FUNCTIONS *******************************************************************************
+/// Fold a columnar ECG exam (one row per sample) into the single-row layout of the export: each
+/// lead becomes a list of its samples, ordered by sample index, and the metadata is taken from
+/// the first row
+/// # Arguments
+/// * `df` - The DataFrame of the stored exam
+/// # Errors
+/// * Returns an error if the exam has no samples or a lead is not a complete Float32 column
+fn fold_ecg_samples(df: &DataFrame) -> Result<DataFrame> {
+    if df.height() == 0 {
+        return Err(anyhow!("ECG exam without samples"));
+    }
+    let df = df.sort([ECG_SAMPLE_INDEX_COLUMN], SortMultipleOptions::default())?;
+    let mut columns = Vec::with_capacity(df.width());
+    for column in df.get_columns() {
+        let name = column.name();
+        if name == ECG_SAMPLE_INDEX_COLUMN {
+            continue;
+        }
+        if ECG_LEAD_COLUMNS.contains(&name) {
+            let samples: Vec<f32> = column
+                .f32()?
+                .into_iter()
+                .collect::<Option<_>>()
+                .ok_or_else(|| anyhow!("{name} has missing samples"))?;
+            columns.push(Series::new(name, [Series::new("", samples)]));
+        } else {
+            columns.push(column.head(Some(1)));
+        }
+    }
+    Ok(DataFrame::new(columns)?)
+}
+
+/// Locate the stored Parquet object of an exam
+/// # Arguments
+/// * `exam_id` - The exam id to validate
+/// # Returns
+/// * The object name
+/// # Errors
+/// * Returns an error if the exam id is invalid
+fn exam_object(exam_id: &str) -> Result<String> {
+    validate_exam_id(exam_id)?;
+    Ok(format!("{exam_id}.parquet"))
+}
+
+/// Validate an exam id of the form `{exam_type}/{hospital_id}/{patient_id}/{uuid}` (`{timestamp}`
+/// for the exams stored before the UUIDs)
+/// # Arguments
+/// * `exam_id` - The exam id to validate
+/// # Returns
+/// * Ok(()) if the exam id is well-formed, Err otherwise
+fn validate_exam_id(exam_id: &str) -> Result<()> {
+    let parts: Vec<&str> = exam_id.split('/').collect();
+    if parts.len() != 4 || parts.iter().any(|p| p.is_empty() || *p == "..") {
+        return Err(anyhow!("Invalid exam id"));
+    }
+    if !EXPORTABLE_EXAM_TYPES.contains(&parts[0]) {
+        return Err(anyhow!("Unknown exam type in exam id"));
+    }
+    Ok(())
+}
+
+// TESTS *******************************************************************************************
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::models_consent::ConsentScope;
+    use crate::models::models_exams::PayloadEcg;
+    use crate::services::service_ecg_exam::ecg_exam_frame;
+    use polars::io::parquet::ParquetWriter;
+
+    /// Build a single-row Parquet file shaped like the stored ECG exams
+    fn exam_parquet() -> Vec<u8> {
+        let lead: Vec<f32> = (0..10).map(|i| i as f32 / 10.0).collect();
+        let json = serde_json::json!([{
+            "exam_type": "ECG Exam",
+            "timestamp": "2025-01-01T000000.0Z",
+            "patient_id": "p",
+            "hospital_id": "h",
+            "hospital_key": "secret",
+            "lead_i": lead,
+        }])
+        .to_string();
+        let mut df = polars::io::json::JsonReader::new(Cursor::new(json))
+            .finish()
+            .unwrap();
+        let mut buffer = Vec::new();
+        ParquetWriter::new(&mut buffer).finish(&mut df).unwrap();
+        buffer
+    }
+
+    /// Build a Parquet file in the columnar ECG layout, one row per sample
+    fn columnar_exam_parquet() -> Vec<u8> {
+        let lead: Vec<f32> = (0..10).map(|i| i as f32 / 10.0).collect();
+        let payload = PayloadEcg {
+            patient_id: "p".into(),
+            hospital_id: "h".into(),
+            hospital_key: Some("secret".to_string()),
+            lead_i: lead.clone(),
+            lead_ii: lead.clone(),
+            lead_iii: lead.clone(),
+            lead_avr: lead.clone(),
+            lead_avl: lead.clone(),
+            lead_avf: lead.clone(),
+            lead_v1: lead.clone(),
+            lead_v2: lead.clone(),
+            lead_v3: lead.clone(),
+            lead_v4: lead.clone(),
+            lead_v5: lead.clone(),
+            lead_v6: lead,
+            sampling_rate_hz: Some(1000.0),
+            duration_s: Some(0.01),
+        };
+        let mut df =
+            ecg_exam_frame(&payload, "2025-01-01T000000.0Z", ConsentScope::Clinical).unwrap();
+        let mut buffer = Vec::new();
+        ParquetWriter::new(&mut buffer).finish(&mut df).unwrap();
+        buffer
+    }
+
+    // Happy path: round-trip keeps the payload and strips the hospital key
+    #[test]
+    fn export_happy_path() {
+        let exam = parquet_to_json(exam_parquet(), 1).unwrap();
+        assert_eq!(exam["patient_id"], "p");
+        assert_eq!(exam["exam_type"], "ECG Exam");
+        assert_eq!(exam["lead_i"].as_array().unwrap().len(), 10);
+        assert!(exam.get("hospital_key").is_none());
+    }
+
+    // Borderline-ok: downsampling keeps every n-th sample starting at the first one
+    #[test]
+    fn export_downsampled_leads() {
+        let exam = parquet_to_json(exam_parquet(), 4).unwrap();
+        let lead = exam["lead_i"].as_array().unwrap();
+        assert_eq!(lead.len(), 3);
+        assert!((lead[1].as_f64().unwrap() - 0.4).abs() < 1e-6);
+    }
+
+    // Happy path: the columnar layout is folded back into the same exam shape
+    #[test]
+    fn export_columnar_layout() {
+        let exam = parquet_to_json(columnar_exam_parquet(), 4).unwrap();
+        assert_eq!(exam["patient_id"], "p");
+        assert_eq!(exam["consent_scope"], "clinical");
+        assert!(exam.get(ECG_SAMPLE_INDEX_COLUMN).is_none());
+        assert!(exam.get("hospital_key").is_none());
+        let lead = exam["lead_v6"].as_array().unwrap();
+        assert_eq!(lead.len(), 3);
+        assert!((lead[2].as_f64().unwrap() - 0.8).abs() < 1e-6);
+        assert_eq!(exam["sampling_rate_hz"], 250.0);
+        assert!((exam["duration_s"].as_f64().unwrap() - 0.01).abs() < 1e-6);
+    }
+
+    // Error handling: zero downsample factor and malformed exam ids
+    #[test]
+    fn export_error_cases() {
+        assert!(parquet_to_json(exam_parquet(), 0).is_err());
+        assert!(validate_exam_id("ecg_exam/h/p/ts").is_ok());
+        assert!(validate_exam_id("ecg_exam/h/p").is_err());
+        assert!(validate_exam_id("ecg_exam/../p/ts").is_err());
+        assert!(validate_exam_id("audit/h/p/ts").is_err());
+    }
+
+    // Happy path: the three single-range forms
+    #[test]
+    fn range_forms() {
+        assert_eq!(resolve_range("bytes=0-99", 1000).unwrap(), Some((0, 99)));
+        assert_eq!(resolve_range("bytes=900-", 1000).unwrap(), Some((900, 999)));
+        assert_eq!(resolve_range("bytes=-100", 1000).unwrap(), Some((900, 999)));
+        // An end past the object is clamped
+        assert_eq!(
+            resolve_range("bytes=990-5000", 1000).unwrap(),
+            Some((990, 999))
+        );
+    }
+
+    // Borderline-ok: unsupported units and multiple ranges fall back to the whole object
+    #[test]
+    fn range_fallback_to_full() {
+        assert_eq!(resolve_range("items=0-1", 1000).unwrap(), None);
+        assert_eq!(resolve_range("bytes=0-1,5-6", 1000).unwrap(), None);
+    }
+
+    // Error handling: malformed and unsatisfiable ranges
+    #[test]
+    fn range_errors() {
+        assert!(resolve_range("bytes=1000-", 1000).is_err());
+        assert!(resolve_range("bytes=5-2", 1000).is_err());
+        assert!(resolve_range("bytes=-0", 1000).is_err());
+        assert!(resolve_range("bytes=a-b", 1000).is_err());
+        assert!(resolve_range("bytes=-", 1000).is_err());
+    }
+}
@@ -0,0 +1,591 @@
+// Imports *****************************************************************************************
+// External Crates
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
+use log::{error, info, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use subtle::ConstantTimeEq;
+use uuid::Uuid;
+
+// Internal Modules
+use crate::audit::audit_event::{AuditAction, AuditEvent};
+use crate::audit::audit_trail::record_audit;
+use crate::config::settings::LifecycleSettings;
+use crate::models::models_hospital_admin::ExamType;
+use crate::storage::exam_storage::{ExamStorage, StoredObject};
+use crate::utils::canonical_json::to_canonical_string;
+use crate::utils::hmac::{hex, hmac_sha256};
+
+// Constants ***************************************************************************************
+/// Issuer written in every certificate
+const CERTIFICATE_ISSUER: &str = "sentinela-exam-receiver";
+
+// Structs *****************************************************************************************
+/// Retention policy of an exam type, or of the exams of one hospital of that type
+/// # Arguments
+/// * `exam_type` - The exam type key, first segment of the object names
+/// * `hospital_id` - The hospital the policy is restricted to, None for every hospital
+/// * `retention_days` - Exams older than this are deleted
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct RetentionPolicy {
+    pub exam_type: String,
+    pub hospital_id: Option<String>,
+    pub retention_days: i64,
+}
+
+impl RetentionPolicy {
+    /// Key of the policy as configured, e.g. `xray_exam` or `xray_exam/{hospital_id}`
+    pub fn key(&self) -> String {
+        match &self.hospital_id {
+            Some(hospital_id) => format!("{}/{hospital_id}", self.exam_type),
+            None => self.exam_type.clone(),
+        }
+    }
+}
+
+/// Evidence of the deletion of one stored object, signed with RETENTION_SIGNING_KEY
+/// # Arguments
+/// * `certificate_id` - UUIDv7 of the certificate
+/// * `issuer` - The service that deleted the object
+/// * `bucket` - The bucket of the object
+/// * `object` - The object name
+/// * `generation` - The GCS generation deleted, None for the backends without generations
+/// * `size` - The size of the object in bytes
+/// * `md5_hash` - The base64 MD5 of the object, as reported by GCS
+/// * `crc32c` - The base64 CRC32C of the object, as reported by GCS
+/// * `created_at` - When the object was written
+/// * `deleted_at` - When the deletion was confirmed
+/// * `exam_type` - The exam type key
+/// * `exam_id` - The exam the object belongs to (object name without extension)
+/// * `hospital_id` - The hospital that sent the exam
+/// * `policy` - The key of the retention policy applied
+/// * `retention_days` - The retention of the policy
+/// * `key_id` - The id of the signing key (RETENTION_SIGNING_KEY_ID)
+/// * `signature` - `sha256=<hex>` HMAC of the canonical JSON (RFC 8785) of the certificate with an
+///   empty signature
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DeletionCertificate {
+    pub certificate_id: String,
+    pub issuer: String,
+    pub bucket: String,
+    pub object: String,
+    pub generation: Option<i64>,
+    pub size: u64,
+    pub md5_hash: Option<String>,
+    pub crc32c: Option<String>,
+    pub created_at: Option<DateTime<Utc>>,
+    pub deleted_at: DateTime<Utc>,
+    pub exam_type: String,
+    pub exam_id: String,
+    pub hospital_id: String,
+    pub policy: String,
+    pub retention_days: i64,
+    pub key_id: String,
+    pub signature: String,
+}
+
+/// Deletion metrics of one policy since startup
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct RetentionStats {
+    pub runs: u64,
+    pub scanned: u64,
+    pub expired: u64,
+    pub held: u64,
+    pub deleted: u64,
+    pub deleted_bytes: u64,
+    pub failures: u64,
+}
+
+/// Signing key of the certificates
+struct SigningKey {
+    key_id: String,
+    secret: String,
+}
+
+// Global variables ********************************************************************************
+/// Retention metrics per policy key
+static RETENTION_STATS: Mutex<BTreeMap<String, RetentionStats>> = Mutex::new(BTreeMap::new());
+
+// MAIN FUNCTIONS **********************************************************************************
+/// Enforce the exam retention policies forever
+/// - Disabled unless EXAM_RETENTION_DAYS is set: exams are never deleted by default
+/// - EXAM_RETENTION_DRY_RUN=true only reports what would be deleted; otherwise
+///   RETENTION_SIGNING_KEY is required, so no exam is deleted without its certificate
+/// # Arguments
+/// * `lifecycle` - The retention settings (EXAM_RETENTION_* and RETENTION_SIGNING_*)
+/// * `bucket` - The exam bucket
+/// * `storage` - The exam storage backend
+pub async fn run_exam_retention(
+    lifecycle: LifecycleSettings,
+    bucket: String,
+    storage: Arc<dyn ExamStorage>,
+) {
+    // STEP 1: Policies and signing key
+    let Some(policies) = lifecycle.retention else {
+        info!("EXAM_RETENTION_DAYS not set - exam retention disabled");
+        return;
+    };
+    let dry_run = lifecycle.retention_dry_run;
+    // A dry run never deletes, even with a signing key
+    let key = lifecycle
+        .retention_signing_key
+        .filter(|_| !dry_run)
+        .map(|secret| SigningKey {
+            key_id: lifecycle.retention_signing_key_id,
+            secret: secret.expose().to_string(),
+        });
+    if key.is_none() && !dry_run {
+        error!("Exam retention disabled - RETENTION_SIGNING_KEY is required to delete exams");
+        return;
+    }
+    let interval = lifecycle.retention_interval_s;
+    info!("Exam retention every {interval}s (dry run: {dry_run}): {policies:?}");
+
+    // STEP 2: Sweep the prefixes of the policies on every run
+    loop {
+        for prefix in policy_prefixes(&policies) {
+            if let Err(e) =
+                enforce_prefix(storage.as_ref(), &bucket, &prefix, &policies, key.as_ref()).await
+            {
+                warn!("Exam retention of '{prefix}' failed: {e}");
+            }
+        }
+        tokio::time::sleep(Duration::from_secs(interval)).await;
+    }
+}
+
+/// Snapshot of the retention metrics, keyed by policy
+pub fn retention_stats() -> BTreeMap<String, RetentionStats> {
+    RETENTION_STATS
+        .lock()
+        .map(|stats| stats.clone())
+        .unwrap_or_default()
+}
+
+/// Check the signature of a deletion certificate against RETENTION_SIGNING_KEY
+/// # Arguments
+/// * `certificate` - The certificate, as written to the audit log
+/// * `lifecycle` - The retention settings, holding RETENTION_SIGNING_KEY and its id
+/// # Returns
+/// * `Ok(true)` if the certificate was signed with the key and is unchanged
+/// # Errors
+/// * Returns an error if no signing key is configured, or it is not the one of the certificate
+pub fn verify_certificate(
+    certificate: &DeletionCertificate,
+    lifecycle: &LifecycleSettings,
+) -> Result<bool> {
+    let secret = lifecycle
+        .retention_signing_key
+        .as_ref()
+        .ok_or_else(|| anyhow!("RETENTION_SIGNING_KEY not set"))?;
+    let key_id = &lifecycle.retention_signing_key_id;
+    if &certificate.key_id != key_id {
+        return Err(anyhow!(
+            "Certificate signed with key '{}', configured key is '{key_id}'",
+            certificate.key_id
+        ));
+    }
+    signature_matches(certificate, secret.expose())
+}
+
+// SUPPORT FUNCTIONS *******************************************************************************
+/// Delete the expired exams of one prefix, each with its signed certificate
+/// # Arguments
+/// * `storage` - The exam storage backend
+/// * `bucket` - The bucket name
+/// * `prefix` - The prefix swept, `{exam_type}/` or `{exam_type}/{hospital_id}/`
+/// * `policies` - Every retention policy - the most specific one applies to each object
+/// * `key` - The signing key, None for a dry run
+/// # Errors
+/// * Returns an error if the listing fails - failed deletions are counted and retried next run
+async fn enforce_prefix(
+    storage: &dyn ExamStorage,
+    bucket: &str,
+    prefix: &str,
+    policies: &[RetentionPolicy],
+    key: Option<&SigningKey>,
+) -> Result<()> {
+    let now = Utc::now();
+    let mut stats: BTreeMap<String, RetentionStats> = BTreeMap::new();
+    let mut page_token = None;
+    let listed = loop {
+        // STEP 1: List one page of the prefix
+        let page = match storage
+            .list_objects(bucket, prefix, page_token.take())
+            .await
+        {
+            Ok(page) => page,
+            Err(e) => break Err(e),
+        };
+
+        // STEP 2: Delete (or report) the expired objects not under a hold
+        for object in page.objects {
+            let Some(exam) = exam_of_object(&object.name) else {
+                continue;
+            };
+            let Some(policy) = policy_for(policies, exam.0, exam.1) else {
+                continue;
+            };
+            let policy_stats = stats.entry(policy.key()).or_default();
+            policy_stats.scanned += 1;
+            if !is_expired(object.created_at, policy.retention_days, now) {
+                continue;
+            }
+            policy_stats.expired += 1;
+            if object.held {
+                policy_stats.held += 1;
+                continue;
+            }
+            let Some(key) = key else {
+                info!(target: "audit", "exam_retention dry_run bucket={bucket} object={} size={} policy={}", object.name, object.size, policy.key());
+                continue;
+            };
+            // Deleting the listed generation never removes an object rewritten since the listing,
+            // nor leaves a noncurrent version behind in a versioned bucket
+            match storage
+                .delete_object(bucket, &object.name, object.generation)
+                .await
+            {
+                Ok(()) => {
+                    policy_stats.deleted += 1;
+                    policy_stats.deleted_bytes += object.size;
+                    let certificate = certify(bucket, &object, exam, policy, key);
+                    record_certificate(&certificate, storage.location(bucket, &object.name));
+                }
+                Err(e) => {
+                    policy_stats.failures += 1;
+                    warn!("exam_retention could not delete {}: {e}", object.name);
+                }
+            }
+        }
+
+        page_token = page.next_page_token;
+        if page_token.is_none() {
+            break Ok(());
+        }
+    };
+
+    // STEP 3: Metrics of the run, even when the listing stopped half way
+    for (policy, run) in &stats {
+        info!(
+            "Exam retention of '{policy}': {} scanned, {} expired, {} held, {} deleted",
+            run.scanned, run.expired, run.held, run.deleted
+        );
+        record_stats(policy, run);
+    }
+    listed
+}
+
+/// Signed certificate of a deleted object
+/// # Arguments
+/// * `bucket` - The bucket of the object
+/// * `object` - The object, as listed before its deletion
+/// * `exam` - The exam type, hospital and exam id of the object
+/// * `policy` - The retention policy applied
+/// * `key` - The signing key
+fn certify(
+    bucket: &str,
+    object: &StoredObject,
+    (exam_type, hospital_id, exam_id): (&str, &str, &str),
+    policy: &RetentionPolicy,
+    key: &SigningKey,
+) -> DeletionCertificate {
+    let mut certificate = DeletionCertificate {
+        certificate_id: Uuid::now_v7().to_string(),
+        issuer: CERTIFICATE_ISSUER.to_string(),
+        bucket: bucket.to_string(),
+        object: object.name.clone(),
+        generation: object.generation,
+        size: object.size,
+        md5_hash: object.md5_hash.clone(),
+        crc32c: object.crc32c.clone(),
+        created_at: object.created_at,
+        deleted_at: Utc::now(),
+        exam_type: exam_type.to_string(),
+        exam_id: exam_id.to_string(),
+        hospital_id: hospital_id.to_string(),
+        policy: policy.key(),
+        retention_days: policy.retention_days,
+        key_id: key.key_id.clone(),
+        signature: String::new(),
+    };
+    certificate.signature = sign(&certificate, &key.secret);
+    certificate
+}
+
+/// Write a certificate to the audit log and to the audit trail (`exam_expired`)
+/// # Arguments
+/// * `certificate` - The signed certificate
+/// * `location` - The location of the deleted object in the storage backend
+fn record_certificate(certificate: &DeletionCertificate, location: String) {
+    let json = match serde_json::to_string(certificate) {
+        Ok(json) => json,
+        Err(e) => {
+            error!(
+                "Deletion certificate of {} not serialized: {e}",
+                certificate.object
+            );
+            return;
+        }
+    };
+    info!(target: "audit", "exam_retention deleted object={} certificate={json}", certificate.object);
+    record_audit(
+        AuditEvent::exam(
+            AuditAction::ExamExpired,
+            &certificate.exam_type,
+            &certificate.exam_id,
+            &certificate.hospital_id,
+        )
+        .object_path(location)
+        .certificate(json),
+    );
+}
+
+/// `sha256=<hex>` HMAC of the canonical JSON of a certificate with an empty signature - an auditor
+/// recomputes it from the logged certificate with any RFC 8785 implementation
+fn sign(certificate: &DeletionCertificate, secret: &str) -> String {
+    let unsigned = DeletionCertificate {
+        signature: String::new(),
+        ..certificate.clone()
+    };
+    // Serializing a struct of strings, numbers and timestamps cannot fail
+    let content = to_canonical_string(&unsigned).unwrap_or_default();
+    format!(
+        "sha256={}",
+        hex(&hmac_sha256(secret.as_bytes(), content.as_bytes()))
+    )
+}
+
+/// Compare the signature of a certificate with the expected one, in constant time
+fn signature_matches(certificate: &DeletionCertificate, secret: &str) -> Result<bool> {
+    let expected = sign(certificate, secret);
+    Ok(bool::from(
+        certificate.signature.as_bytes().ct_eq(expected.as_bytes()),
+    ))
+}
+
+/// Exam type, hospital and exam id of a stored object, None for objects outside the exam layout
+/// `{exam_type}/{hospital_id}/{patient_id}/{uuid}.{extension}`
+fn exam_of_object(name: &str) -> Option<(&str, &str, &str)> {
+    let mut segments = name.split('/');
+    let (exam_type, hospital_id) = (segments.next()?, segments.next()?);
+    let (_patient_id, file) = (segments.next()?, segments.next()?);
+    if segments.next().is_some() || file.is_empty() {
+        return None;
+    }
+    let exam_id = name.rsplit_once('.').map_or(name, |(exam_id, _)| exam_id);
+    Some((exam_type, hospital_id, exam_id))
+}
+
+/// Policy of the exams of a hospital: its own, else the one of the exam type
+fn policy_for<'a>(
+    policies: &'a [RetentionPolicy],
+    exam_type: &str,
+    hospital_id: &str,
+) -> Option<&'a RetentionPolicy> {
+    let of_type = || policies.iter().filter(move |p| p.exam_type == exam_type);
+    of_type()
+        .find(|p| p.hospital_id.as_deref() == Some(hospital_id))
+        .or_else(|| of_type().find(|p| p.hospital_id.is_none()))
+}
+
+/// Prefixes to sweep: the whole exam type when it has a policy for every hospital, else only the
+/// hospitals with their own policy
+fn policy_prefixes(policies: &[RetentionPolicy]) -> Vec<String> {
+    let mut prefixes: Vec<String> = policies
+        .iter()
+        .filter(|p| {
+            p.hospital_id.is_none()
+                || !policies
+                    .iter()
+                    .any(|q| q.exam_type == p.exam_type && q.hospital_id.is_none())
+        })
+        .map(|p| format!("{}/", p.key()))
+        .collect();
+    prefixes.sort();
+    prefixes.dedup();
+    prefixes
+}
+
+/// Whether an exam is older than its retention - objects of unknown age are kept
+/// # Arguments
+/// * `created_at` - When the object was written
+/// * `retention_days` - The retention of the policy
+/// * `now` - The current instant
+fn is_expired(created_at: Option<DateTime<Utc>>, retention_days: i64, now: DateTime<Utc>) -> bool {
+    created_at.is_some_and(|at| now - at > chrono::Duration::days(retention_days))
+}
+
+/// Add the result of one run to the metrics of the policy
+fn record_stats(policy: &str, run: &RetentionStats) {
+    if let Ok(mut stats) = RETENTION_STATS.lock() {
+        let total = stats.entry(policy.to_string()).or_default();
+        total.runs += 1;
+        total.scanned += run.scanned;
+        total.expired += run.expired;
+        total.held += run.held;
+        total.deleted += run.deleted;
+        total.deleted_bytes += run.deleted_bytes;
+        total.failures += run.failures;
+    }
+}
+
+/// Parse retention policies of the form `exam_type=days,exam_type/hospital_id=days`
+/// # Arguments
+/// * `raw` - The policy list
+/// # Errors
+/// * Returns an error for malformed entries, unknown exam types, retentions under one day or a
+///   policy set twice
+pub(crate) fn parse_policies(raw: &str) -> Result<Vec<RetentionPolicy>> {
+    let known = [ExamType::EcgExam.as_str(), ExamType::XrayExam.as_str()];
+    let mut policies: Vec<RetentionPolicy> = Vec::new();
+    for entry in raw.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+        let (key, days) = entry
+            .split_once('=')
+            .ok_or_else(|| anyhow!("Invalid exam retention '{entry}': expected exam_type=days"))?;
+        let days: i64 = days
+            .trim()
+            .parse()
+            .map_err(|_| anyhow!("Invalid exam retention days in '{entry}'"))?;
+        let (exam_type, hospital_id) = match key.trim().split_once('/') {
+            Some((exam_type, hospital_id)) => (exam_type, Some(hospital_id)),
+            None => (key.trim(), None),
+        };
+        if !known.contains(&exam_type)
+            || hospital_id.is_some_and(|h| h.is_empty() || h.contains('/'))
+        {
+            return Err(anyhow!("Invalid exam retention '{entry}'"));
+        }
+        if days <= 0 {
+            return Err(anyhow!(
+                "Invalid exam retention '{entry}': at least one day"
+            ));
+        }
+        let policy = RetentionPolicy {
+            exam_type: exam_type.to_string(),
+            hospital_id: hospital_id.map(str::to_string),
+            retention_days: days,
+        };
+        if policies.iter().any(|p| p.key() == policy.key()) {
+            return Err(anyhow!("Exam retention '{}' set twice", policy.key()));
+        }
+        policies.push(policy);
+    }
+    Ok(policies)
+}
+
+// TESTS *******************************************************************************************
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::settings::DEFAULT_RETENTION_KEY_ID;
+
+    fn certificate() -> DeletionCertificate {
+        DeletionCertificate {
+            certificate_id: "0192f0c1-0000-7000-8000-000000000000".to_string(),
+            issuer: CERTIFICATE_ISSUER.to_string(),
+            bucket: "exams".to_string(),
+            object: "ecg_exam/h1/p1/0192f0c1.parquet".to_string(),
+            generation: Some(1_700_000_000_000_000),
+            size: 2048,
+            md5_hash: Some("1B2M2Y8AsgTpgAmY7PhCfg==".to_string()),
+            crc32c: None,
+            created_at: Some("2016-10-01T12:00:00Z".parse().unwrap()),
+            deleted_at: "2026-10-02T12:00:00Z".parse().unwrap(),
+            exam_type: "ecg_exam".to_string(),
+            exam_id: "ecg_exam/h1/p1/0192f0c1".to_string(),
+            hospital_id: "h1".to_string(),
+            policy: "ecg_exam".to_string(),
+            retention_days: 3650,
+            key_id: DEFAULT_RETENTION_KEY_ID.to_string(),
+            signature: String::new(),
+        }
+    }
+
+    // Happy path: exam type and hospital policies parse
+    #[test]
+    fn policies_parsed() {
+        let policies = parse_policies("ecg_exam=3650, xray_exam=3650,xray_exam/h1=30").unwrap();
+        assert_eq!(policies.len(), 3);
+        assert_eq!(
+            policies[2],
+            RetentionPolicy {
+                exam_type: "xray_exam".to_string(),
+                hospital_id: Some("h1".to_string()),
+                retention_days: 30,
+            }
+        );
+        assert_eq!(policies[2].key(), "xray_exam/h1");
+    }
+
+    // Error handling: unknown exam types, bad retentions and duplicates refuse the whole list
+    #[test]
+    fn policies_invalid() {
+        assert!(parse_policies("staging=30").is_err());
+        assert!(parse_policies("ecg_exam").is_err());
+        assert!(parse_policies("ecg_exam=0").is_err());
+        assert!(parse_policies("ecg_exam/=30").is_err());
+        assert!(parse_policies("ecg_exam/h1/p1=30").is_err());
+        assert!(parse_policies("ecg_exam=30,ecg_exam=40").is_err());
+    }
+
+    // Happy path: the hospital policy wins over the exam type one
+    #[test]
+    fn most_specific_policy_applies() {
+        let policies = parse_policies("xray_exam=3650,xray_exam/h1=30,ecg_exam/h2=90").unwrap();
+        assert_eq!(
+            policy_for(&policies, "xray_exam", "h1")
+                .unwrap()
+                .retention_days,
+            30
+        );
+        assert_eq!(
+            policy_for(&policies, "xray_exam", "h2")
+                .unwrap()
+                .retention_days,
+            3650
+        );
+        assert!(policy_for(&policies, "ecg_exam", "h1").is_none());
+        assert_eq!(
+            policy_prefixes(&policies),
+            vec!["ecg_exam/h2/".to_string(), "xray_exam/".to_string()]
+        );
+    }
+
+    // Borderline: only objects of the exam layout are considered
+    #[test]
+    fn exam_objects_only() {
+        assert_eq!(
+            exam_of_object("xray_exam/h1/p1/0192f0c1.png"),
+            Some(("xray_exam", "h1", "xray_exam/h1/p1/0192f0c1"))
+        );
+        assert!(exam_of_object("xray_exam/h1/p1").is_none());
+        assert!(exam_of_object("xray_exam/h1/p1/").is_none());
+        assert!(exam_of_object("xray_exam/h1/p1/x/y.png").is_none());
+    }
+
+    #[test]
+    fn expiry_follows_retention() {
+        let now: DateTime<Utc> = "2026-10-02T12:00:00Z".parse().unwrap();
+        assert!(is_expired(Some(now - chrono::Duration::days(31)), 30, now));
+        assert!(!is_expired(Some(now - chrono::Duration::days(29)), 30, now));
+        // Unknown age is kept
+        assert!(!is_expired(None, 30, now));
+    }
+
+    // Happy path: a signed certificate verifies, a changed one or another key does not
+    #[test]
+    fn certificate_signature() {
+        let mut signed = certificate();
+        signed.signature = sign(&signed, "secret");
+        assert!(signed.signature.starts_with("sha256="));
+        assert!(signature_matches(&signed, "secret").unwrap());
+        assert!(!signature_matches(&signed, "other").unwrap());
+        let mut changed = signed.clone();
+        changed.size += 1;
+        assert!(!signature_matches(&changed, "secret").unwrap());
+    }
+}
@@ -0,0 +1,457 @@
+// Imports *****************************************************************************************
+// External Crates
+use actix_web::web;
+use anyhow::{anyhow, Result};
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use chrono::{DateTime, Duration, Utc};
+use log::{error, info};
+use sqlx::postgres::PgRow;
+use sqlx::{PgPool, Row};
+
+// Internal Modules
+use crate::authentication::auth::forget_hospital;
+use crate::models::models_hospital_admin::{
+    key_status, ExamType, HospitalCreate, HospitalKey, HospitalUpdate, IssuedKey, KeyRotation,
+};
+use crate::telemetry::metrics::record_registry_invalidation;
+use crate::utils::api_error::ApiError;
+use crate::utils::external_call::{Dependency, ExternalCall};
+
+// Constants ***************************************************************************************
+/// Active keys per hospital - the authentication verifies at most this many keys, newest first
+pub const MAX_ACTIVE_KEYS: i64 = 3;
+/// Seconds the current keys keep working after a rotation, when the rotation does not say
+const DEFAULT_ROTATION_GRACE_S: u64 = 86_400;
+/// Longest grace period of a rotation - 30 days
+const MAX_ROTATION_GRACE_S: u64 = 2_592_000;
+/// bcrypt cost of the stored key hashes
+const KEY_HASH_COST: u32 = 12;
+/// Random bytes of a key
+const KEY_BYTES: usize = 32;
+/// Characters of the key kept in the registry, to recognise it in listings
+const KEY_PREFIX_LEN: usize = 8;
+/// Prefix of the keys generated by the gateway
+const KEY_MARKER: &str = "sk_";
+
+// MAIN FUNCTIONS **********************************************************************************
+/// Register a hospital with its first key
+/// # Arguments
+/// * `pool` - The shared database connection pool
+/// * `create` - The hospital and its settings
+/// # Returns
+/// * The first key of the hospital, None if the hospital is already registered
+/// # Errors
+/// * Returns an error if the key cannot be generated or the registry cannot be written
+pub async fn create_hospital(pool: &PgPool, create: &HospitalCreate) -> Result<Option<IssuedKey>> {
+    let key = GeneratedKey::new().await?;
+    let allowed_exam_types = create.allowed_exam_types.as_deref().map(exam_type_names);
+    let row = ExternalCall::new(Dependency::Postgres, "create_hospital")
+        .run(|| {
+            sqlx::query(
+                r#"
+                WITH hospital AS (
+                    INSERT INTO hospital_credentials
+                        (hospital_id, consent_scope, size_tier, rate_limit_tier,
+                         allowed_exam_types, monthly_exam_quota, publish_mode)
+                    VALUES ($1, $2, $3, $4, $5, $6, $7)
+                    ON CONFLICT (hospital_id) DO NOTHING
+                    RETURNING hospital_id
+                )
+                INSERT INTO hospital_keys (key_id, hospital_id, key_hash, key_prefix, expires_at)
+                SELECT $8, hospital_id, $9, $10, to_timestamp($11::float8) FROM hospital
+                RETURNING key_id
+                "#,
+            )
+            .bind(&create.hospital_id)
+            .bind(create.consent_scope.map(|scope| scope.as_str()))
+            .bind(create.size_tier.map(|tier| tier.as_str()))
+            .bind(create.rate_limit_tier.map(|tier| tier.as_str()))
+            .bind(allowed_exam_types.as_ref())
+            .bind(
+                create
+                    .monthly_exam_quota
+                    .map(|quota| quota.min(i64::MAX as u64) as i64),
+            )
+            .bind(create.publish_mode.map(|mode| mode.as_str()))
+            .bind(&key.key_id)
+            .bind(&key.key_hash)
+            .bind(key.prefix())
+            .bind(create.key_expires_at.map(epoch_seconds))
+            .fetch_optional(pool)
+        })
+        .await?;
+    Ok(row.map(|_| key.issued(&create.hospital_id, create.key_expires_at)))
+}
+
+/// Change the allowed exam types, rate limit tier, publish mode or pause of a hospital - effective
+/// at once on this instance, on the others once they receive the registry notification
+/// # Arguments
+/// * `pool` - The shared database connection pool
+/// * `hospital_id` - The hospital
+/// * `update` - The settings to change
+/// # Returns
+/// * false if the hospital is not registered
+/// # Errors
+/// * Returns an error if the registry cannot be written
+pub async fn update_hospital(
+    pool: &PgPool,
+    hospital_id: &str,
+    update: &HospitalUpdate,
+) -> Result<bool> {
+    let allowed_exam_types = update
+        .allowed_exam_types
+        .as_ref()
+        .map(|types| types.as_deref().map(exam_type_names));
+    let result = ExternalCall::new(Dependency::Postgres, "update_hospital")
+        .run(|| {
+            sqlx::query(
+                r#"
+                UPDATE hospital_credentials
+                SET allowed_exam_types = CASE WHEN $2 THEN $3 ELSE allowed_exam_types END,
+                    rate_limit_tier = COALESCE($4, rate_limit_tier),
+                    publish_mode = COALESCE($5, publish_mode),
+                    paused_at = CASE WHEN $6 IS NULL THEN paused_at
+                                     WHEN $6 THEN COALESCE(paused_at, now()) END
+                WHERE hospital_id = $1
+                "#,
+            )
+            .bind(hospital_id)
+            .bind(allowed_exam_types.is_some())
+            .bind(allowed_exam_types.clone().flatten())
+            .bind(update.rate_limit_tier.map(|tier| tier.as_str()))
+            .bind(update.publish_mode.map(|mode| mode.as_str()))
+            .bind(update.paused)
+            .execute(pool)
+        })
+        .await?;
+    forget_hospital(hospital_id);
+    record_registry_invalidation("local");
+    Ok(result.rows_affected() > 0)
+}
+
+/// Issue a new key to a hospital, next to its active keys
+/// # Arguments
+/// * `pool` - The shared database connection pool
+/// * `hospital_id` - The hospital
+/// * `expires_at` - The expiry of the key, None if never
+/// # Returns
+/// * The key, None if the hospital is not registered
+/// # Errors
+/// * Returns `ApiError::BadRequest` if the hospital already has MAX_ACTIVE_KEYS active keys, an
+///   error if the registry cannot be written
+pub async fn issue_key(
+    pool: &PgPool,
+    hospital_id: &str,
+    expires_at: Option<DateTime<Utc>>,
+) -> Result<Option<IssuedKey>> {
+    // STEP 1: The hospital must exist and have room for another key
+    let Some(active) = active_keys(pool, hospital_id).await? else {
+        return Ok(None);
+    };
+    if active >= MAX_ACTIVE_KEYS {
+        return Err(anyhow::Error::new(ApiError::BadRequest(format!(
+            "Hospital already has {MAX_ACTIVE_KEYS} active keys - rotate or revoke one first"
+        ))));
+    }
+
+    // STEP 2: Store the hash of a new key
+    let key = GeneratedKey::new().await?;
+    ExternalCall::new(Dependency::Postgres, "issue_hospital_key")
+        .run(|| {
+            sqlx::query(
+                r#"
+                INSERT INTO hospital_keys (key_id, hospital_id, key_hash, key_prefix, expires_at)
+                VALUES ($1, $2, $3, $4, to_timestamp($5::float8))
+                "#,
+            )
+            .bind(&key.key_id)
+            .bind(hospital_id)
+            .bind(&key.key_hash)
+            .bind(key.prefix())
+            .bind(expires_at.map(epoch_seconds))
+            .execute(pool)
+        })
+        .await?;
+    Ok(Some(key.issued(hospital_id, expires_at)))
+}
+
+/// Rotate the keys of a hospital: issue a new key and let the active ones expire after the grace
+/// period - keys already expiring sooner keep their expiry
+/// # Arguments
+/// * `pool` - The shared database connection pool
+/// * `hospital_id` - The hospital
+/// * `rotation` - The grace period and the expiry of the new key
+/// # Returns
+/// * The new key and the ids of the retiring keys, None if the hospital is not registered
+/// # Errors
+/// * Returns an error if the registry cannot be written
+pub async fn rotate_keys(
+    pool: &PgPool,
+    hospital_id: &str,
+    rotation: &KeyRotation,
+) -> Result<Option<(IssuedKey, Vec<String>)>> {
+    let key = GeneratedKey::new().await?;
+    let grace = rotation
+        .grace_s
+        .unwrap_or(DEFAULT_ROTATION_GRACE_S)
+        .min(MAX_ROTATION_GRACE_S);
+    let retire_at = Utc::now() + Duration::seconds(grace as i64);
+    let row = ExternalCall::new(Dependency::Postgres, "rotate_hospital_keys")
+        .run(|| {
+            sqlx::query(
+                r#"
+                WITH retiring AS (
+                    UPDATE hospital_keys
+                    SET expires_at = LEAST(COALESCE(expires_at, 'infinity'), to_timestamp($6::float8))
+                    WHERE hospital_id = $2 AND revoked_at IS NULL
+                      AND (expires_at IS NULL OR expires_at > now())
+                    RETURNING key_id
+                ), issued AS (
+                    INSERT INTO hospital_keys (key_id, hospital_id, key_hash, key_prefix, expires_at)
+                    SELECT $1, hospital_id, $3, $4, to_timestamp($5::float8)
+                    FROM hospital_credentials WHERE hospital_id = $2
+                    RETURNING key_id
+                )
+                SELECT ARRAY(SELECT key_id FROM retiring) AS retiring
+                FROM issued
+                "#,
+            )
+            .bind(&key.key_id)
+            .bind(hospital_id)
+            .bind(&key.key_hash)
+            .bind(key.prefix())
+            .bind(rotation.expires_at.map(epoch_seconds))
+            .bind(epoch_seconds(retire_at))
+            .fetch_optional(pool)
+        })
+        .await?;
+    let Some(row) = row else {
+        return Ok(None);
+    };
+    let retiring: Vec<String> = row.try_get("retiring")?;
+    forget_hospital(hospital_id);
+    record_registry_invalidation("local");
+    Ok(Some((
+        key.issued(hospital_id, rotation.expires_at),
+        retiring,
+    )))
+}
+
+/// Revoke a key of a hospital - refused at once on this instance, on the others once they receive
+/// the registry notification
+/// # Arguments
+/// * `pool` - The shared database connection pool
+/// * `hospital_id` - The hospital
+/// * `key_id` - The key
+/// # Returns
+/// * false if the hospital has no such key not yet revoked
+/// # Errors
+/// * Returns an error if the registry cannot be written
+pub async fn revoke_key(pool: &PgPool, hospital_id: &str, key_id: &str) -> Result<bool> {
+    let result = ExternalCall::new(Dependency::Postgres, "revoke_hospital_key")
+        .run(|| {
+            sqlx::query(
+                r#"
+                UPDATE hospital_keys SET revoked_at = now()
+                WHERE hospital_id = $1 AND key_id = $2 AND revoked_at IS NULL
+                "#,
+            )
+            .bind(hospital_id)
+            .bind(key_id)
+            .execute(pool)
+        })
+        .await?;
+    forget_hospital(hospital_id);
+    record_registry_invalidation("local");
+    Ok(result.rows_affected() > 0)
+}
+
+/// Keys of a hospital, newest first
+/// # Arguments
+/// * `pool` - The shared database connection pool
+/// * `hospital_id` - The hospital
+/// # Returns
+/// * The keys, None if the hospital is not registered
+/// # Errors
+/// * Returns an error if the registry cannot be read
+pub async fn list_keys(pool: &PgPool, hospital_id: &str) -> Result<Option<Vec<HospitalKey>>> {
+    if active_keys(pool, hospital_id).await?.is_none() {
+        return Ok(None);
+    }
+    let rows = ExternalCall::new(Dependency::Postgres, "list_hospital_keys")
+        .run(|| {
+            sqlx::query(
+                r#"
+                SELECT key_id, key_prefix,
+                       (extract(epoch FROM created_at) * 1000000)::bigint AS created_at_us,
+                       (extract(epoch FROM expires_at) * 1000000)::bigint AS expires_at_us,
+                       (extract(epoch FROM revoked_at) * 1000000)::bigint AS revoked_at_us
+                FROM hospital_keys
+                WHERE hospital_id = $1
+                ORDER BY created_at DESC
+                "#,
+            )
+            .bind(hospital_id)
+            .fetch_all(pool)
+        })
+        .await?;
+    let now = Utc::now();
+    rows.iter()
+        .map(|row| key_from_row(row, now))
+        .collect::<Result<_>>()
+        .map(Some)
+}
+
+/// Response error of a failed registry change - refusals keep their status, anything else is
+/// logged and hidden behind a 500
+/// # Arguments
+/// * `e` - The error of a registry function
+pub fn registry_error(e: anyhow::Error) -> ApiError {
+    e.downcast::<ApiError>().unwrap_or_else(|e| {
+        error!("Hospital registry failure: {e}");
+        ApiError::Internal
+    })
+}
+
+// SUPPORTING FUNCTIONS ****************************************************************************
+/// Key generated for a hospital, before it is stored
+struct GeneratedKey {
+    key_id: String,
+    hospital_key: String,
+    key_hash: String,
+}
+
+impl GeneratedKey {
+    /// Generate a key and its bcrypt hash, off the async workers (bcrypt is slow by design)
+    /// # Errors
+    /// * Returns an error if the system random source fails
+    async fn new() -> Result<Self> {
+        let mut id = [0u8; 8];
+        let mut secret = [0u8; KEY_BYTES];
+        getrandom::getrandom(&mut id)
+            .and_then(|_| getrandom::getrandom(&mut secret))
+            .map_err(|e| anyhow!("Key not generated: {e}"))?;
+        let key_id = format!(
+            "hk_{}",
+            id.iter().map(|b| format!("{b:02x}")).collect::<String>()
+        );
+        let hospital_key = format!("{KEY_MARKER}{}", URL_SAFE_NO_PAD.encode(secret));
+        let to_hash = hospital_key.clone();
+        let key_hash = web::block(move || bcrypt::hash(to_hash, KEY_HASH_COST))
+            .await
+            .map_err(|e| anyhow!("Key hashing not scheduled: {e}"))??;
+        Ok(Self {
+            key_id,
+            hospital_key,
+            key_hash,
+        })
+    }
+
+    /// First characters of the key, kept to recognise it
+    fn prefix(&self) -> String {
+        self.hospital_key
+            .chars()
+            .take(KEY_MARKER.len() + KEY_PREFIX_LEN)
+            .collect()
+    }
+
+    /// The key as returned, once, to the operator
+    fn issued(self, hospital_id: &str, expires_at: Option<DateTime<Utc>>) -> IssuedKey {
+        info!(target: "audit", "hospital_key_issued hospital_id={hospital_id} key_id={}", self.key_id);
+        IssuedKey {
+            hospital_id: hospital_id.to_string(),
+            key_id: self.key_id,
+            hospital_key: self.hospital_key,
+            expires_at,
+        }
+    }
+}
+
+/// Active keys of a hospital, None if the hospital is not registered
+async fn active_keys(pool: &PgPool, hospital_id: &str) -> Result<Option<i64>> {
+    let row = ExternalCall::new(Dependency::Postgres, "count_hospital_keys")
+        .run(|| {
+            sqlx::query(
+                r#"
+                SELECT count(k.key_id) AS active
+                FROM hospital_credentials c
+                LEFT JOIN hospital_keys k ON k.hospital_id = c.hospital_id
+                    AND k.revoked_at IS NULL AND (k.expires_at IS NULL OR k.expires_at > now())
+                WHERE c.hospital_id = $1
+                GROUP BY c.hospital_id
+                "#,
+            )
+            .bind(hospital_id)
+            .fetch_optional(pool)
+        })
+        .await?;
+    row.map(|row| row.try_get("active"))
+        .transpose()
+        .map_err(Into::into)
+}
+
+/// Key of a row of `hospital_keys`
+fn key_from_row(row: &PgRow, now: DateTime<Utc>) -> Result<HospitalKey> {
+    let created_at: i64 = row.try_get("created_at_us")?;
+    let expires_at = optional_instant(row.try_get("expires_at_us")?)?;
+    let revoked_at = optional_instant(row.try_get("revoked_at_us")?)?;
+    Ok(HospitalKey {
+        key_id: row.try_get("key_id")?,
+        key_prefix: row.try_get("key_prefix")?,
+        created_at: optional_instant(Some(created_at))?.unwrap_or(now),
+        expires_at,
+        revoked_at,
+        status: key_status(expires_at, revoked_at, now),
+    })
+}
+
+/// Instant of microseconds since the epoch
+fn optional_instant(micros: Option<i64>) -> Result<Option<DateTime<Utc>>> {
+    micros
+        .map(|micros| {
+            DateTime::from_timestamp_micros(micros)
+                .ok_or_else(|| anyhow!("Key timestamp out of range: {micros}"))
+        })
+        .transpose()
+}
+
+/// Names of exam types, as stored in the registry
+fn exam_type_names(types: &[ExamType]) -> Vec<String> {
+    types.iter().map(|t| t.as_str().to_string()).collect()
+}
+
+/// Seconds since the epoch, with microsecond precision - timestamps cross to Postgres as numbers
+fn epoch_seconds(at: DateTime<Utc>) -> f64 {
+    at.timestamp_micros() as f64 / 1_000_000.0
+}
+
+// TESTS *******************************************************************************************
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Happy path: keys are random, marked, and verify against their stored hash
+    #[actix_web::test]
+    async fn key_generated_and_hashed() {
+        let key = GeneratedKey::new().await.unwrap();
+        let other = GeneratedKey::new().await.unwrap();
+        assert_ne!(key.hospital_key, other.hospital_key);
+        assert_ne!(key.key_id, other.key_id);
+        assert!(key.hospital_key.starts_with(KEY_MARKER));
+        assert_eq!(key.key_id.len(), 19);
+        assert!(bcrypt::verify(&key.hospital_key, &key.key_hash).unwrap());
+        assert!(!key.key_hash.contains(&key.hospital_key));
+        assert_eq!(key.prefix().len(), KEY_MARKER.len() + KEY_PREFIX_LEN);
+        assert!(key.hospital_key.starts_with(&key.prefix()));
+    }
+
+    // Happy path: exam types are stored by name
+    #[test]
+    fn exam_types_named() {
+        assert_eq!(
+            exam_type_names(&[ExamType::XrayExam, ExamType::EcgExam]),
+            ["xray_exam", "ecg_exam"]
+        );
+    }
+}
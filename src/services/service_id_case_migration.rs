@@ -0,0 +1,173 @@
+// Imports *****************************************************************************************
+// External Crates
+use anyhow::Result;
+use log::{info, warn};
+use serde::Serialize;
+
+// Internal Modules
+use crate::models::models_ids::canonical_hex;
+use crate::storage::exam_storage::{ExamStorage, StorageError, StoredObject};
+
+// Constants ***************************************************************************************
+/// Prefixes of the stored exams: `{exam_type}/{hospital_id}/{patient_id}/{uuid}.{ext}`
+const EXAM_PREFIXES: [&str; 2] = ["ecg_exam/", "xray_exam/"];
+
+// Structs *****************************************************************************************
+/// Outcome of one migration run
+/// # Arguments
+/// * `dry_run` - Whether objects were only reported
+/// * `scanned` - Objects listed under the exam prefixes
+/// * `mixed_case` - Objects whose hospital or patient id is not lowercase hex
+/// * `merged` - Objects moved to their canonical path
+/// * `conflicts` - Objects left in place because their canonical path is already taken
+/// * `failures` - Objects that could not be moved, retried on the next run
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct IdCaseMigrationReport {
+    pub dry_run: bool,
+    pub scanned: u64,
+    pub mixed_case: u64,
+    pub merged: u64,
+    pub conflicts: u64,
+    pub failures: u64,
+}
+
+// MAIN FUNCTIONS **********************************************************************************
+/// Move the exams stored under mixed-case ids to their canonical lowercase path, so every patient
+/// has a single path - the stored content is not rewritten
+/// # Arguments
+/// * `storage` - The exam storage backend
+/// * `bucket` - The exam bucket
+/// * `dry_run` - Only report the objects that would be moved
+/// # Returns
+/// * The migration report; every move and conflict is audited
+/// # Errors
+/// * Returns an error if a listing fails - the run can be repeated safely
+pub async fn merge_mixed_case_paths(
+    storage: &dyn ExamStorage,
+    bucket: &str,
+    dry_run: bool,
+) -> Result<IdCaseMigrationReport> {
+    let mut report = IdCaseMigrationReport {
+        dry_run,
+        ..Default::default()
+    };
+    for prefix in EXAM_PREFIXES {
+        let mut page_token = None;
+        loop {
+            // STEP 1: List one page of the exam prefix
+            let page = storage
+                .list_objects(bucket, prefix, page_token.take())
+                .await?;
+
+            // STEP 2: Move (or report) the objects stored under a mixed-case id
+            for object in page.objects {
+                report.scanned += 1;
+                let Some(canonical) = canonical_object_name(&object.name) else {
+                    continue;
+                };
+                report.mixed_case += 1;
+                if dry_run {
+                    info!(target: "audit", "id_case_migration dry_run bucket={bucket} object={} canonical={canonical}", object.name);
+                    continue;
+                }
+                move_object(storage, bucket, &object, &canonical, &mut report).await;
+            }
+
+            page_token = page.next_page_token;
+            if page_token.is_none() {
+                break;
+            }
+        }
+    }
+    info!("Id case migration: {report:?}");
+    Ok(report)
+}
+
+// SUPPORT FUNCTIONS *******************************************************************************
+/// Copy an object to its canonical name, unless that name is taken, then delete the original
+/// # Arguments
+/// * `storage` - The exam storage backend
+/// * `bucket` - The exam bucket
+/// * `object` - The object stored under a mixed-case id
+/// * `canonical` - Its canonical name
+/// * `report` - The report of the run
+async fn move_object(
+    storage: &dyn ExamStorage,
+    bucket: &str,
+    object: &StoredObject,
+    canonical: &str,
+    report: &mut IdCaseMigrationReport,
+) {
+    // STEP 1: Copy - only creates the canonical object if it does not exist yet
+    if let Err(e) = storage.copy_object(bucket, object, canonical).await {
+        if StorageError::is_conflict(&e) {
+            report.conflicts += 1;
+            warn!(target: "audit", "id_case_migration conflict bucket={bucket} object={} canonical={canonical}", object.name);
+        } else {
+            report.failures += 1;
+            warn!("id_case_migration could not copy {}: {e}", object.name);
+        }
+        return;
+    }
+
+    // STEP 2: Delete the original - pinned to the generation that was copied
+    match storage
+        .delete_object(bucket, &object.name, object.generation)
+        .await
+    {
+        Ok(()) => {
+            report.merged += 1;
+            info!(target: "audit", "id_case_migration merged bucket={bucket} object={} canonical={canonical}", object.name);
+        }
+        Err(e) => {
+            // The canonical copy exists: the next run reports the original as a conflict
+            report.failures += 1;
+            warn!("id_case_migration could not delete {}: {e}", object.name);
+        }
+    }
+}
+
+/// Canonical name of a stored exam object, if its hospital or patient id is not canonical
+/// # Arguments
+/// * `name` - The object name, `{exam_type}/{hospital_id}/{patient_id}/{file}`
+/// # Returns
+/// * The name with lowercase hex ids, or None if it is already canonical or not an exam object
+fn canonical_object_name(name: &str) -> Option<String> {
+    let mut segments: Vec<String> = name.split('/').map(str::to_string).collect();
+    if segments.len() < 4 {
+        return None;
+    }
+    segments[1] = canonical_hex(&segments[1]);
+    segments[2] = canonical_hex(&segments[2]);
+    let canonical = segments.join("/");
+    (canonical != name).then_some(canonical)
+}
+
+// TESTS *******************************************************************************************
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Happy path: mixed-case ids map to the lowercase path, the file name is kept
+    #[test]
+    fn canonical_name_of_mixed_case() {
+        assert_eq!(
+            canonical_object_name("ecg_exam/ABC1/Def2/2025-01-01T000000.000Z.parquet"),
+            Some("ecg_exam/abc1/def2/2025-01-01T000000.000Z.parquet".to_string())
+        );
+    }
+
+    // Canonical, non-hex and non-exam names are left alone
+    #[test]
+    fn canonical_name_unchanged() {
+        assert_eq!(
+            canonical_object_name("ecg_exam/abc1/def2/2025-01-01T000000.000Z.parquet"),
+            None
+        );
+        assert_eq!(
+            canonical_object_name("ecg_exam/abc1/Patient-X/t.parquet"),
+            None
+        );
+        assert_eq!(canonical_object_name("ecg_exam/ABC1"), None);
+    }
+}
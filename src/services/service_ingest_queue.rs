@@ -0,0 +1,664 @@
+// Imports *****************************************************************************************
+// External Crates
+use chrono::{DateTime, Utc};
+use log::{error, info, warn};
+use moka::future::Cache;
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::{mpsc, Mutex};
+use utoipa::ToSchema;
+
+// Internal Modules
+use crate::config::settings::{IngestSettings, Settings};
+use crate::models::models_consent::ConsentScope;
+use crate::models::models_exams::PayloadEcg;
+use crate::publishers::publisher::Publisher;
+use crate::services::service_billing::BillingService;
+use crate::services::service_confirmation_webhook::notify_confirmation;
+use crate::services::service_dead_letter::Delivery;
+use crate::services::service_ecg_exam::handler_ecg_exam;
+use crate::services::service_ingest_spool::IngestSpool;
+use crate::storage::exam_storage::ExamStorage;
+use crate::telemetry::trace_context::{scope_trace, TraceContext};
+use crate::utils::api_error::ApiError;
+use crate::utils::ids::ExamId;
+use crate::utils::publish_backlog::{BacklogState, PUBLISH_BACKLOG};
+use crate::utils::reason_code::ReasonCode;
+
+// Constants ***************************************************************************************
+/// Most exam statuses kept in memory
+const STATUS_CAPACITY: u64 = 100_000;
+/// Seconds a hospital is asked to wait before retrying when the queue is full
+const QUEUE_FULL_RETRY_AFTER_S: u64 = 5;
+/// Interval at which a shutdown drain checks the exams and publishes still in flight
+const DRAIN_POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+// Structs *****************************************************************************************
+/// Processing state of an exam accepted with 202, or provisionally accepted with 201
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum ExamState {
+    /// Waiting for a worker
+    Queued,
+    /// Being stored and published
+    Processing,
+    /// Stored, and published or scheduled for a deferred publish
+    Committed,
+    /// Storage or publish kept failing - the exam was kept for replay, no need to send it again
+    #[serde(rename = "dead_lettered")]
+    DeadLettered,
+    /// Processing failed - the hospital must send the exam again
+    Failed,
+}
+
+impl ExamState {
+    /// Stable lowercase name, as serialized
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ExamState::Queued => "queued",
+            ExamState::Processing => "processing",
+            ExamState::Committed => "committed",
+            ExamState::DeadLettered => "dead_lettered",
+            ExamState::Failed => "failed",
+        }
+    }
+}
+
+/// Status of an exam, as returned to the hospital that sent it
+/// # Arguments
+/// * `exam_id` - The exam identifier returned with the 202
+/// * `state` - The processing state
+/// * `updated_at` - When the state last changed
+/// * `hospital_id` - The hospital that sent the exam, the only one allowed to read the status
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct ExamStatus {
+    pub exam_id: String,
+    pub state: ExamState,
+    pub updated_at: DateTime<Utc>,
+    #[serde(skip)]
+    pub hospital_id: String,
+}
+
+/// Exam accepted by the route, waiting to be processed - two-phase exams are spooled as JSON
+/// # Arguments
+/// * `exam_id` - The exam identifier returned with the 202 (or 201)
+/// * `hospital_id` - The authenticated hospital, owner of the status
+/// * `data` - The validated payload
+/// * `deferred` - Publish in the background once downstream is no longer saturated
+/// * `received_at` - When the gateway received the exam
+/// * `consent_scope` - The data-sharing consent of the hospital
+/// * `trace` - The trace of the request, continued by the worker
+/// * `idempotency_key` - The idempotency key of the request, passed on to the consumers
+/// * `two_phase` - Provisionally accepted: spooled before the 201, removed once confirmed
+/// * `confirmation_webhook` - The checked URL notified of the final state, if any
+/// * `spooled` - In the spool (two-phase, spilled at shutdown or recovered): removed from it once
+///   the exam left the gateway
+#[derive(Serialize, Deserialize)]
+pub struct IngestJob {
+    pub exam_id: ExamId,
+    pub hospital_id: String,
+    pub data: PayloadEcg,
+    pub deferred: bool,
+    pub received_at: DateTime<Utc>,
+    pub consent_scope: ConsentScope,
+    #[serde(skip)]
+    pub trace: Option<TraceContext>,
+    pub idempotency_key: Option<String>,
+    #[serde(default)]
+    pub two_phase: bool,
+    #[serde(default)]
+    pub confirmation_webhook: Option<String>,
+    #[serde(skip)]
+    pub spooled: bool,
+}
+
+/// Bounded work queue decoupling the HTTP response from storage and publish
+pub struct IngestQueue {
+    sender: mpsc::Sender<IngestJob>,
+    receiver: Arc<Mutex<mpsc::Receiver<IngestJob>>>,
+    statuses: Cache<String, ExamStatus>,
+    spool: IngestSpool,
+    /// Exams queued or being processed
+    in_flight: AtomicUsize,
+    /// Set once a shutdown drain ran out of time: received exams are spilled, not processed
+    spilling: AtomicBool,
+}
+
+impl IngestQueue {
+    /// Create the queue and spawn its workers - two-phase exams left in the spool by a previous run
+    /// are queued again
+    /// # Arguments
+    /// * `settings` - The settings of the service: intake (capacity, workers, status TTL, spool
+    ///   directory and webhooks) and storage
+    /// * `storage` - The exam storage backend
+    /// * `publisher` - The notification backend
+    /// * `billing` - The billing service recording usage
+    pub fn start(
+        settings: Arc<Settings>,
+        storage: Arc<dyn ExamStorage>,
+        publisher: Arc<dyn Publisher>,
+        billing: Arc<BillingService>,
+    ) -> Arc<Self> {
+        let ingest = &settings.ingest;
+        let capacity = ingest.queue_capacity;
+        let workers = ingest.workers;
+        let queue = Arc::new(Self::new(
+            capacity,
+            Duration::from_secs(ingest.status_ttl_s),
+            IngestSpool::new(&ingest.spool_dir),
+        ));
+
+        // Workers share the receiver: each job is processed exactly once
+        for _ in 0..workers {
+            actix_web::rt::spawn(run_worker(
+                queue.clone(),
+                storage.clone(),
+                settings.clone(),
+                publisher.clone(),
+                billing.clone(),
+            ));
+        }
+        info!("Ingest queue started: {workers} workers, capacity {capacity}");
+        actix_web::rt::spawn(requeue_spooled(queue.clone()));
+        queue
+    }
+
+    /// Create a queue without workers
+    fn new(capacity: usize, status_ttl: Duration, spool: IngestSpool) -> Self {
+        let (sender, receiver) = mpsc::channel(capacity);
+        let statuses = Cache::builder()
+            .max_capacity(STATUS_CAPACITY)
+            .time_to_live(status_ttl)
+            .build();
+        Self {
+            sender,
+            receiver: Arc::new(Mutex::new(receiver)),
+            statuses,
+            spool,
+            in_flight: AtomicUsize::new(0),
+            spilling: AtomicBool::new(false),
+        }
+    }
+
+    /// Enqueue an exam without waiting - a two-phase exam is spooled first, so it survives a
+    /// restart once acknowledged
+    /// # Arguments
+    /// * `job` - The exam to process
+    /// # Errors
+    /// * Returns RateLimited if the queue is full - the hospital should retry later - or
+    ///   StorageUnavailable if a two-phase exam could not be spooled
+    pub async fn enqueue(&self, mut job: IngestJob) -> Result<(), ApiError> {
+        let exam_id = job.exam_id.clone();
+        let two_phase = job.two_phase;
+        if two_phase {
+            if let Err(e) = self.spool.write(&job).await {
+                error!("Could not spool ECG Exam {exam_id}: {e}");
+                return Err(ApiError::StorageUnavailable);
+            }
+            job.spooled = true;
+        }
+        let submitted = self.submit(job).await;
+        if submitted.is_err() && two_phase {
+            if let Err(e) = self.spool.remove(&exam_id).await {
+                warn!("Could not unspool refused ECG Exam {exam_id}: {e}");
+            }
+        }
+        submitted
+    }
+
+    /// Queue again an exam an operator took back from the spool - it stays spooled until it
+    /// leaves the gateway, even if the queue refuses it
+    /// # Arguments
+    /// * `job` - The spooled exam
+    /// # Errors
+    /// * Returns RateLimited if the queue is full
+    pub async fn requeue(&self, mut job: IngestJob) -> Result<(), ApiError> {
+        job.spooled = true;
+        self.submit(job).await
+    }
+
+    /// Whether an exam is waiting for a worker or being processed by this instance
+    /// # Arguments
+    /// * `exam_id` - The exam identifier
+    pub async fn is_active(&self, exam_id: &str) -> bool {
+        self.statuses
+            .get(exam_id)
+            .await
+            .is_some_and(|status| matches!(status.state, ExamState::Queued | ExamState::Processing))
+    }
+
+    /// The spool of the two-phase exams
+    pub fn spool(&self) -> &IngestSpool {
+        &self.spool
+    }
+
+    /// Status of an exam, if it was sent by the given hospital and is still tracked
+    /// # Arguments
+    /// * `exam_id` - The exam identifier returned with the 202
+    /// * `hospital_id` - The authenticated hospital
+    pub async fn status(&self, exam_id: &str, hospital_id: &str) -> Option<ExamStatus> {
+        self.statuses
+            .get(exam_id)
+            .await
+            .filter(|status| status.hospital_id == hospital_id)
+    }
+
+    /// Drain the queue at shutdown, once the server stopped taking exams: the workers and the
+    /// publishes of this instance get until the deadline, then the exams still waiting for a
+    /// worker are spilled to the spool and queued again at the next start
+    /// # Arguments
+    /// * `deadline` - How long the in-flight exams and publishes may take (SHUTDOWN_DEADLINE_S)
+    /// # Returns
+    /// * The exams still being processed when the deadline passed - only the two-phase ones
+    ///   among them survive the stop
+    pub async fn drain(&self, deadline: Duration) -> usize {
+        // STEP 1: Let the workers and the publishes finish
+        let started = Instant::now();
+        while started.elapsed() < deadline && (self.in_flight() > 0 || publishes_pending() > 0) {
+            tokio::time::sleep(DRAIN_POLL_INTERVAL).await;
+        }
+
+        // STEP 2: Spill the exams nobody picked up - an idle worker holding the receiver spills
+        // what it receives from now on
+        self.spilling.store(true, Ordering::SeqCst);
+        let mut spilled = 0;
+        if let Ok(mut receiver) = self.receiver.try_lock() {
+            while let Ok(job) = receiver.try_recv() {
+                spilled += usize::from(self.spill(job).await);
+            }
+        }
+
+        // STEP 3: Audit what the stop leaves behind
+        let unfinished = self.in_flight();
+        info!(target: "audit", "ingest_drained elapsed_ms={} spilled={spilled} unfinished={unfinished} publishes_pending={}",
+            started.elapsed().as_millis(), publishes_pending());
+        unfinished
+    }
+
+    /// Exams queued or being processed
+    pub fn in_flight(&self) -> usize {
+        self.in_flight.load(Ordering::SeqCst)
+    }
+
+    /// Exams the queue holds before refusing new ones (INGEST_QUEUE_CAPACITY)
+    pub fn capacity(&self) -> usize {
+        self.sender.max_capacity()
+    }
+
+    /// Write an exam nobody will process before the stop to the spool
+    /// # Returns
+    /// * Whether the exam is in the spool
+    async fn spill(&self, mut job: IngestJob) -> bool {
+        let spilled = job.spooled || {
+            job.spooled = true;
+            match self.spool.write(&job).await {
+                Ok(()) => true,
+                Err(e) => {
+                    error!("Could not spill ECG Exam {} at shutdown: {e}", job.exam_id);
+                    false
+                }
+            }
+        };
+        if spilled {
+            info!(target: "audit", "exam_spilled exam_type=ecg_exam exam_id={} hospital_id={}",
+                job.exam_id, job.hospital_id);
+        }
+        self.in_flight.fetch_sub(1, Ordering::SeqCst);
+        spilled
+    }
+
+    /// Hand an exam to the workers without waiting
+    /// # Errors
+    /// * Returns RateLimited if the queue is full - the exam is then not tracked
+    async fn submit(&self, job: IngestJob) -> Result<(), ApiError> {
+        let exam_id = job.exam_id.clone();
+        // The status and count are recorded first: a fast worker may already update them
+        self.set_state(&exam_id, &job.hospital_id, ExamState::Queued)
+            .await;
+        self.in_flight.fetch_add(1, Ordering::SeqCst);
+        if let Err(e) = self.sender.try_send(job) {
+            error!("Ingest queue unavailable: {e}");
+            self.in_flight.fetch_sub(1, Ordering::SeqCst);
+            self.statuses.invalidate(exam_id.as_str()).await;
+            return Err(ApiError::RateLimited {
+                retry_after_s: QUEUE_FULL_RETRY_AFTER_S,
+                reason: ReasonCode::QueueFull,
+            });
+        }
+        Ok(())
+    }
+
+    /// Record the state of an exam
+    async fn set_state(&self, exam_id: &str, hospital_id: &str, state: ExamState) {
+        self.statuses
+            .insert(
+                exam_id.to_string(),
+                ExamStatus {
+                    exam_id: exam_id.to_string(),
+                    state,
+                    updated_at: Utc::now(),
+                    hospital_id: hospital_id.to_string(),
+                },
+            )
+            .await;
+    }
+}
+
+// SUPPORT FUNCTIONS *******************************************************************************
+/// Process queued exams until the queue is closed - or spill them once a shutdown drain ran out
+/// of time
+async fn run_worker(
+    queue: Arc<IngestQueue>,
+    storage: Arc<dyn ExamStorage>,
+    settings: Arc<Settings>,
+    publisher: Arc<dyn Publisher>,
+    billing: Arc<BillingService>,
+) {
+    loop {
+        // The lock is only held while waiting for the next job
+        let Some(job) = queue.receiver.lock().await.recv().await else {
+            return;
+        };
+        if queue.spilling.load(Ordering::SeqCst) {
+            queue.spill(job).await;
+            continue;
+        }
+        let hospital_id = job.hospital_id.clone();
+        let exam_id = job.exam_id.clone();
+        queue
+            .set_state(&exam_id, &hospital_id, ExamState::Processing)
+            .await;
+        let trace = job.trace.unwrap_or_else(TraceContext::new_root);
+        let processing = handler_ecg_exam(
+            job.data,
+            &exam_id,
+            &storage,
+            &settings.storage,
+            &publisher,
+            &billing,
+            job.deferred,
+            job.received_at,
+            job.consent_scope,
+            job.idempotency_key,
+        );
+        let state = match scope_trace(trace, processing).await {
+            Ok(Delivery::DeadLettered) => ExamState::DeadLettered,
+            Ok(_) => ExamState::Committed,
+            Err(e) => {
+                error!("Error while processing queued ECG Exam {exam_id}: {e}");
+                ExamState::Failed
+            }
+        };
+        queue.set_state(&exam_id, &hospital_id, state).await;
+        confirm(
+            &queue,
+            &exam_id,
+            &hospital_id,
+            state,
+            job.spooled,
+            job.confirmation_webhook,
+            &settings.ingest,
+        )
+        .await;
+        queue.in_flight.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Second phase of an exam that left the gateway: unspool it unless it failed - a failed
+/// spooled exam stays spooled and is retried at the next start - then notify its webhook
+async fn confirm(
+    queue: &IngestQueue,
+    exam_id: &str,
+    hospital_id: &str,
+    state: ExamState,
+    spooled: bool,
+    confirmation_webhook: Option<String>,
+    settings: &IngestSettings,
+) {
+    if spooled && state != ExamState::Failed {
+        if let Err(e) = queue.spool.remove(exam_id).await {
+            warn!("Could not unspool confirmed ECG Exam {exam_id}: {e}");
+        }
+    }
+    let Some(url) = confirmation_webhook else {
+        return;
+    };
+    if let Some(status) = queue.status(exam_id, hospital_id).await {
+        // Deliveries retry on their own task, the worker moves on to the next exam
+        let secret = settings.confirmation_webhook_secret.clone();
+        actix_web::rt::spawn(async move {
+            notify_confirmation(&url, "ecg_exam", &status, secret.as_ref()).await;
+        });
+    }
+}
+
+/// Queue the exams left in the spool by a previous run, waiting for room in the queue
+async fn requeue_spooled(queue: Arc<IngestQueue>) {
+    for mut job in queue.spool.recover().await {
+        job.spooled = true;
+        info!(target: "audit", "exam_requeued exam_type=ecg_exam exam_id={} hospital_id={}",
+            job.exam_id, job.hospital_id);
+        queue
+            .set_state(&job.exam_id, &job.hospital_id, ExamState::Queued)
+            .await;
+        queue.in_flight.fetch_add(1, Ordering::SeqCst);
+        if queue.sender.send(job).await.is_err() {
+            queue.in_flight.fetch_sub(1, Ordering::SeqCst);
+            return;
+        }
+    }
+}
+
+/// Publishes of this instance waiting for their ack or deferred
+fn publishes_pending() -> u64 {
+    PUBLISH_BACKLOG.count(BacklogState::InFlight) + PUBLISH_BACKLOG.count(BacklogState::Deferred)
+}
+
+// TESTS *******************************************************************************************
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    pub(crate) fn job(exam_id: &str, hospital_id: &str) -> IngestJob {
+        IngestJob {
+            exam_id: exam_id.to_string().into(),
+            hospital_id: hospital_id.to_string(),
+            data: PayloadEcg {
+                patient_id: Default::default(),
+                hospital_id: hospital_id.into(),
+                hospital_key: None,
+                lead_i: vec![],
+                lead_ii: vec![],
+                lead_iii: vec![],
+                lead_avr: vec![],
+                lead_avl: vec![],
+                lead_avf: vec![],
+                lead_v1: vec![],
+                lead_v2: vec![],
+                lead_v3: vec![],
+                lead_v4: vec![],
+                lead_v5: vec![],
+                lead_v6: vec![],
+                sampling_rate_hz: None,
+                duration_s: None,
+            },
+            deferred: false,
+            received_at: Utc::now(),
+            consent_scope: ConsentScope::Clinical,
+            trace: None,
+            idempotency_key: None,
+            two_phase: false,
+            confirmation_webhook: None,
+            spooled: false,
+        }
+    }
+
+    fn queue(capacity: usize, name: &str) -> IngestQueue {
+        let dir =
+            std::env::temp_dir().join(format!("sentinela_queue_{name}_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        IngestQueue::new(capacity, Duration::from_secs(60), IngestSpool::new(dir))
+    }
+
+    // Happy path: an enqueued exam is queued and visible to its hospital only
+    #[tokio::test]
+    async fn enqueued_exam_status() {
+        let queue = queue(2, "enqueued");
+        queue.enqueue(job("e1", "h1")).await.unwrap();
+        let status = queue.status("e1", "h1").await.unwrap();
+        assert_eq!(status.state, ExamState::Queued);
+        assert!(queue.status("e1", "h2").await.is_none());
+        assert!(queue.status("e2", "h1").await.is_none());
+        assert_eq!(
+            queue.receiver.lock().await.recv().await.unwrap().exam_id,
+            "e1"
+        );
+    }
+
+    // Error handling: a full queue rejects the exam and does not track it
+    #[tokio::test]
+    async fn full_queue_rejects() {
+        let queue = queue(1, "full");
+        queue.enqueue(job("e1", "h1")).await.unwrap();
+        let error = queue.enqueue(job("e2", "h1")).await.unwrap_err();
+        assert_eq!(error.reason(), ReasonCode::QueueFull);
+        assert!(queue.status("e2", "h1").await.is_none());
+    }
+
+    // Two-phase exams are spooled while queued; a refused one is not left in the spool
+    #[tokio::test]
+    async fn two_phase_exams_spooled() {
+        let queue = queue(1, "two_phase");
+        let mut first = job("e1", "h1");
+        first.two_phase = true;
+        queue.enqueue(first).await.unwrap();
+        assert_eq!(queue.spool.recover().await.len(), 1);
+
+        let mut second = job("e2", "h1");
+        second.two_phase = true;
+        assert!(queue.enqueue(second).await.is_err());
+        assert_eq!(queue.spool.recover().await.len(), 1);
+
+        let queued = queue.receiver.lock().await.recv().await.unwrap();
+        confirm(
+            &queue,
+            &queued.exam_id,
+            "h1",
+            ExamState::Committed,
+            queued.spooled,
+            None,
+            &crate::config::settings::test_settings().ingest,
+        )
+        .await;
+        assert!(queue.spool.recover().await.is_empty());
+    }
+
+    // Exams nobody processed before the shutdown deadline are spilled to the spool, once
+    #[tokio::test]
+    async fn drain_spills_queued_exams() {
+        let queue = queue(4, "drain");
+        let mut two_phase = job("e1", "h1");
+        two_phase.two_phase = true;
+        queue.enqueue(two_phase).await.unwrap();
+        queue.enqueue(job("e2", "h1")).await.unwrap();
+        assert_eq!(queue.in_flight(), 2);
+
+        assert_eq!(queue.drain(Duration::ZERO).await, 0);
+        assert_eq!(queue.in_flight(), 0);
+        let spilled = queue.spool.recover().await;
+        assert_eq!(spilled.len(), 2);
+        assert!(spilled
+            .iter()
+            .any(|job| job.exam_id == "e2" && !job.two_phase));
+    }
+
+    // Borderline: an exam taken back from the spool stays spooled when the queue refuses it
+    #[tokio::test]
+    async fn requeued_exam_stays_spooled() {
+        let queue = queue(1, "requeue");
+        queue.enqueue(job("e1", "h1")).await.unwrap();
+        assert!(queue.is_active("e1").await);
+        let mut spooled = job("e2", "h1");
+        spooled.two_phase = true;
+        queue.spool.write(&spooled).await.unwrap();
+
+        let error = queue.requeue(spooled).await.unwrap_err();
+        assert_eq!(error.reason(), ReasonCode::QueueFull);
+        assert!(!queue.is_active("e2").await);
+        assert_eq!(queue.spool.recover().await.len(), 1);
+
+        queue.receiver.lock().await.recv().await.unwrap();
+        let spooled = queue.spool.recover().await.pop().unwrap();
+        queue.requeue(spooled).await.unwrap();
+        assert!(queue.is_active("e2").await);
+        assert!(queue.receiver.lock().await.recv().await.unwrap().spooled);
+    }
+
+    #[test]
+    fn state_serializes_lowercase() {
+        assert_eq!(
+            serde_json::to_value(ExamState::Committed).unwrap(),
+            "committed"
+        );
+    }
+
+    // Borderline: spooled exams and exam statuses keep their shape across releases - a spool
+    // written by the previous version is read again after a restart
+    #[test]
+    fn spool_and_status_contracts() {
+        let exam_id = "ecg_exam/h1/p1/2026-10-01T120000.000Z";
+        let at: DateTime<Utc> = "2026-10-01T12:00:00Z".parse().unwrap();
+        let mut spooled = job(exam_id, "h1");
+        spooled.received_at = at;
+        spooled.idempotency_key = Some("key-1".to_string());
+        spooled.two_phase = true;
+        spooled.confirmation_webhook = Some("https://hospital.example/exams".to_string());
+        insta::assert_json_snapshot!(spooled, @r#"
+        {
+          "exam_id": "ecg_exam/h1/p1/2026-10-01T120000.000Z",
+          "hospital_id": "h1",
+          "data": {
+            "patient_id": "",
+            "hospital_id": "h1",
+            "lead_i": [],
+            "lead_ii": [],
+            "lead_iii": [],
+            "lead_avr": [],
+            "lead_avl": [],
+            "lead_avf": [],
+            "lead_v1": [],
+            "lead_v2": [],
+            "lead_v3": [],
+            "lead_v4": [],
+            "lead_v5": [],
+            "lead_v6": []
+          },
+          "deferred": false,
+          "received_at": "2026-10-01T12:00:00Z",
+          "consent_scope": "clinical",
+          "idempotency_key": "key-1",
+          "two_phase": true,
+          "confirmation_webhook": "https://hospital.example/exams"
+        }
+        "#);
+        let json = serde_json::to_value(&spooled).unwrap();
+        let back: IngestJob = serde_json::from_value(json.clone()).unwrap();
+        assert_eq!(serde_json::to_value(&back).unwrap(), json);
+
+        let status = ExamStatus {
+            exam_id: exam_id.to_string(),
+            state: ExamState::DeadLettered,
+            updated_at: at,
+            hospital_id: "h1".to_string(),
+        };
+        insta::assert_json_snapshot!(status, @r#"
+        {
+          "exam_id": "ecg_exam/h1/p1/2026-10-01T120000.000Z",
+          "state": "dead_lettered",
+          "updated_at": "2026-10-01T12:00:00Z"
+        }
+        "#);
+    }
+}
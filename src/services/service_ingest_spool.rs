@@ -0,0 +1,229 @@
+// Imports *****************************************************************************************
+// External Crates
+use actix_web::web;
+use anyhow::{anyhow, Result};
+use log::{info, warn};
+use sha2::{Digest, Sha256};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+// Internal Modules
+use crate::services::service_ingest_queue::IngestJob;
+
+// Structs *****************************************************************************************
+/// Durable copy of the two-phase exams between their provisional accept and their confirmation:
+/// an exam is written before the 201 is returned and removed once stored and published (or
+/// dead-lettered), so a restart replays the exams it had not finished
+/// INGEST_SPOOL_DIR must be on a disk that outlives the instance for the guarantee to hold
+#[derive(Debug, Clone)]
+pub struct IngestSpool {
+    dir: PathBuf,
+}
+
+/// Exam of the spool with the id of its file
+/// # Arguments
+/// * `id` - The file name without extension: the SHA256 of the exam id
+/// * `job` - The spooled exam
+pub struct SpoolEntry {
+    pub id: String,
+    pub job: IngestJob,
+}
+
+impl IngestSpool {
+    /// Spool in a given directory
+    /// # Arguments
+    /// * `dir` - The spool directory, created on the first write
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    /// Write an exam to the spool - the file is synced before it becomes visible
+    /// # Arguments
+    /// * `job` - The exam, as queued
+    /// # Errors
+    /// * Returns an error if the exam could not be made durable
+    pub async fn write(&self, job: &IngestJob) -> Result<()> {
+        let body = serde_json::to_vec(job)?;
+        let path = self.path(&job.exam_id);
+        let dir = self.dir.clone();
+        web::block(move || -> std::io::Result<()> {
+            std::fs::create_dir_all(&dir)?;
+            let partial = path.with_extension("partial");
+            let mut file = std::fs::File::create(&partial)?;
+            file.write_all(&body)?;
+            file.sync_all()?;
+            std::fs::rename(&partial, &path)
+        })
+        .await
+        .map_err(|e| anyhow!("Spool write could not be scheduled: {e}"))??;
+        Ok(())
+    }
+
+    /// Remove a confirmed exam from the spool
+    /// # Arguments
+    /// * `exam_id` - The exam identifier
+    /// # Errors
+    /// * Returns an error if the spooled file exists and could not be removed
+    pub async fn remove(&self, exam_id: &str) -> Result<()> {
+        let path = self.path(exam_id);
+        web::block(move || match std::fs::remove_file(&path) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        })
+        .await
+        .map_err(|e| anyhow!("Spool removal could not be scheduled: {e}"))??;
+        Ok(())
+    }
+
+    /// Exams left in the spool by a previous run - unreadable files are logged and kept for the
+    /// operators
+    pub async fn recover(&self) -> Vec<IngestJob> {
+        let jobs: Vec<IngestJob> = self.entries().await.into_iter().map(|e| e.job).collect();
+        if !jobs.is_empty() {
+            info!("Recovered {} spooled exams", jobs.len());
+        }
+        jobs
+    }
+
+    /// Every readable exam of the spool with its file id, oldest first
+    pub async fn entries(&self) -> Vec<SpoolEntry> {
+        let dir = self.dir.clone();
+        web::block(move || read_spool(&dir))
+            .await
+            .unwrap_or_else(|e| {
+                warn!("Spool listing could not be scheduled: {e}");
+                Vec::new()
+            })
+    }
+
+    /// Remove the file of a spooled exam by its id
+    /// # Arguments
+    /// * `id` - The file id, as listed by `entries`
+    /// # Returns
+    /// * Whether the file existed
+    /// # Errors
+    /// * Returns an error if the id is not a file id or the file could not be removed
+    pub async fn discard(&self, id: &str) -> Result<bool> {
+        if id.len() != 64 || !id.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return Err(anyhow!("Not a spool file id: {id}"));
+        }
+        let path = self.dir.join(format!("{id}.json"));
+        let removed = web::block(move || match std::fs::remove_file(&path) {
+            Ok(()) => Ok(true),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(false),
+            Err(e) => Err(e),
+        })
+        .await
+        .map_err(|e| anyhow!("Spool removal could not be scheduled: {e}"))??;
+        Ok(removed)
+    }
+
+    /// File of an exam - named after the hash of its id, so patient ids never reach a path
+    fn path(&self, exam_id: &str) -> PathBuf {
+        self.dir
+            .join(format!("{:x}.json", Sha256::digest(exam_id.as_bytes())))
+    }
+}
+
+// SUPPORT FUNCTIONS *******************************************************************************
+/// Read every spooled exam of a directory, oldest first
+fn read_spool(dir: &Path) -> Vec<SpoolEntry> {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let mut spooled: Vec<SpoolEntry> = entries
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+        .filter_map(|path| {
+            let entry = std::fs::read(&path)
+                .map_err(|e| e.to_string())
+                .and_then(|bytes| {
+                    let job = serde_json::from_slice(&bytes).map_err(|e| e.to_string())?;
+                    Ok(SpoolEntry {
+                        id: path
+                            .file_stem()
+                            .map(|stem| stem.to_string_lossy().into_owned())
+                            .unwrap_or_default(),
+                        job,
+                    })
+                });
+            entry
+                .map_err(|e| warn!("Unreadable spooled exam {}: {e}", path.display()))
+                .ok()
+        })
+        .collect();
+    spooled.sort_by_key(|entry| entry.job.received_at);
+    spooled
+}
+
+// TESTS *******************************************************************************************
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::service_ingest_queue::tests::job;
+
+    fn temp_spool(name: &str) -> IngestSpool {
+        let dir =
+            std::env::temp_dir().join(format!("sentinela_spool_{name}_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        IngestSpool::new(dir)
+    }
+
+    // Happy path: spooled exams are recovered until they are removed
+    #[tokio::test]
+    async fn spool_round_trip() {
+        let spool = temp_spool("round_trip");
+        let mut first = job("ecg_exam/h/p/1", "h");
+        first.two_phase = true;
+        first.confirmation_webhook = Some("https://hooks.example/cb".to_string());
+        spool.write(&first).await.unwrap();
+        spool.write(&job("ecg_exam/h/p/2", "h")).await.unwrap();
+
+        let recovered = spool.recover().await;
+        assert_eq!(recovered.len(), 2);
+        let first = recovered
+            .iter()
+            .find(|job| job.exam_id == "ecg_exam/h/p/1")
+            .unwrap();
+        assert!(first.two_phase);
+        assert_eq!(
+            first.confirmation_webhook.as_deref(),
+            Some("https://hooks.example/cb")
+        );
+
+        spool.remove("ecg_exam/h/p/1").await.unwrap();
+        spool.remove("ecg_exam/h/p/1").await.unwrap();
+        assert_eq!(spool.recover().await.len(), 1);
+    }
+
+    // Happy path: operators discard a spooled exam by the id of its file
+    #[tokio::test]
+    async fn spool_entries_discarded_by_id() {
+        let spool = temp_spool("discard");
+        spool.write(&job("ecg_exam/h/p/1", "h")).await.unwrap();
+        let entries = spool.entries().await;
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].job.exam_id, "ecg_exam/h/p/1");
+
+        assert!(spool.discard(&entries[0].id).await.unwrap());
+        assert!(!spool.discard(&entries[0].id).await.unwrap());
+        assert!(spool.discard("../../etc/passwd").await.is_err());
+        assert!(spool.entries().await.is_empty());
+    }
+
+    // Borderline: no spool directory yet, and partial or foreign files are ignored
+    #[tokio::test]
+    async fn spool_recovery_ignores_leftovers() {
+        let spool = temp_spool("leftovers");
+        assert!(spool.recover().await.is_empty());
+        spool.write(&job("ecg_exam/h/p/1", "h")).await.unwrap();
+        std::fs::write(spool.dir.join("x.partial"), b"{").unwrap();
+        std::fs::write(spool.dir.join("y.json"), b"not json").unwrap();
+        assert_eq!(spool.recover().await.len(), 1);
+        assert!(!spool
+            .path("ecg_exam/h/p/1")
+            .to_string_lossy()
+            .contains("ecg_exam"));
+    }
+}
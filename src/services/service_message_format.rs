@@ -0,0 +1,643 @@
+// Imports *****************************************************************************************
+// External Crates
+use anyhow::{anyhow, Result};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+#[cfg(feature = "grpc")]
+use google_cloud_pubsub::client::Client as PubSubClient;
+use log::{error, info, warn};
+#[cfg(feature = "grpc")]
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+use std::str::FromStr;
+#[cfg(feature = "grpc")]
+use std::sync::Arc;
+use std::sync::OnceLock;
+#[cfg(feature = "grpc")]
+use std::time::Duration;
+
+// Internal Modules
+use crate::publishers::publisher::NotificationMessage;
+#[cfg(feature = "grpc")]
+use crate::telemetry::metrics::record_consumer_reads;
+use crate::telemetry::metrics::record_fallback_omitted;
+#[cfg(feature = "grpc")]
+use crate::utils::external_call::{Dependency, ExternalCall};
+
+// Constants ***************************************************************************************
+/// Schema of the ECG notification read by the inference service (Python, Avro)
+pub const INFERENCE_ECG_AVRO: &str = include_str!(concat!(
+    env!("CARGO_MANIFEST_DIR"),
+    "/schemas/consumers/inference_service/ecg_exam.avsc"
+));
+/// Schema of the X-ray notification read by the inference service (Python, Avro)
+pub const INFERENCE_XRAY_AVRO: &str = include_str!(concat!(
+    env!("CARGO_MANIFEST_DIR"),
+    "/schemas/consumers/inference_service/xray_exam.avsc"
+));
+/// Pub/Sub attribute naming the encoding of the message body, `json` or `avro`
+pub const ENCODING_ATTRIBUTE: &str = "encoding";
+/// Pub/Sub attribute naming the Avro schema (full name) of the body or of the fallback
+pub const SCHEMA_ATTRIBUTE: &str = "avro_schema";
+/// Pub/Sub attribute carrying the JSON message when the body is Avro
+pub const JSON_FALLBACK_ATTRIBUTE: &str = "json_fallback";
+/// Pub/Sub attribute carrying the base64 Avro message when the body is JSON
+pub const AVRO_FALLBACK_ATTRIBUTE: &str = "avro_fallback";
+/// Version of the exam notification, bumped whenever a field is added or changed
+/// * `1` - Initial notification
+/// * `2` - Adds `object_path`, the GCS location of the stored exam
+pub const NOTIFICATION_SCHEMA_VERSION: &str = "2";
+/// Pub/Sub attribute carrying the version of the exam notification
+pub const SCHEMA_VERSION_ATTRIBUTE: &str = "schema_version";
+/// Fields of the exam notification copied into Pub/Sub attributes, so the subscriptions can
+/// filter on them without decoding the body
+const ROUTING_ATTRIBUTES: [&str; 3] = ["hospital_id", "exam_type", "timestamp"];
+/// Largest attribute value accepted by Pub/Sub - a larger fallback is left out
+const MAX_ATTRIBUTE_BYTES: usize = 1024;
+/// Interval between two pulls of the format report subscription
+#[cfg(feature = "grpc")]
+const PULL_INTERVAL: Duration = Duration::from_secs(30);
+
+// Structs *****************************************************************************************
+/// Encoding of the messages published to a topic while the consumers migrate from JSON to Avro
+/// * `Json` - JSON body only (default)
+/// * `JsonWithAvro` - JSON body, Avro copy in the `avro_fallback` attribute
+/// * `AvroWithJson` - Avro body, JSON copy in the `json_fallback` attribute
+/// * `Avro` - Avro body only, once every consumer reads Avro
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MessageFormat {
+    Json,
+    JsonWithAvro,
+    AvroWithJson,
+    Avro,
+}
+
+impl MessageFormat {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            MessageFormat::Json => "json",
+            MessageFormat::JsonWithAvro => "json+avro",
+            MessageFormat::AvroWithJson => "avro+json",
+            MessageFormat::Avro => "avro",
+        }
+    }
+}
+
+impl FromStr for MessageFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(raw: &str) -> Result<Self> {
+        match raw.trim() {
+            "json" => Ok(MessageFormat::Json),
+            "json+avro" => Ok(MessageFormat::JsonWithAvro),
+            "avro+json" => Ok(MessageFormat::AvroWithJson),
+            "avro" => Ok(MessageFormat::Avro),
+            other => Err(anyhow!(
+                "Unknown message format '{other}' (json, json+avro, avro+json, avro)"
+            )),
+        }
+    }
+}
+
+/// Message format of each topic, from PUBSUB_MESSAGE_FORMATS - topics not listed stay JSON
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MessageFormats {
+    topics: HashMap<String, MessageFormat>,
+}
+
+impl MessageFormats {
+    /// Parse a list of the form `prod-ecg-v1=avro+json,prod-xray-v1=json+avro`
+    /// # Arguments
+    /// * `raw` - The list
+    /// # Errors
+    /// * Returns an error if an entry is malformed or names an unknown format
+    pub(crate) fn parse(raw: &str) -> Result<Self> {
+        let mut topics = HashMap::new();
+        for entry in raw.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+            let (topic, format) = entry.split_once('=').ok_or_else(|| {
+                anyhow!("Invalid message format '{entry}': expected topic=format")
+            })?;
+            let format = format
+                .parse()
+                .map_err(|e| anyhow!("Invalid message format '{entry}': {e}"))?;
+            if topics.insert(topic.trim().to_string(), format).is_some() {
+                return Err(anyhow!("Duplicate message format for '{}'", topic.trim()));
+            }
+        }
+        Ok(Self { topics })
+    }
+
+    /// The format of a topic
+    pub fn get(&self, topic: &str) -> MessageFormat {
+        self.topics
+            .get(topic)
+            .copied()
+            .unwrap_or(MessageFormat::Json)
+    }
+}
+
+/// Report published by a consumer on the messages it read since its previous report
+/// # Arguments
+/// * `consumer` - The consumer name, e.g. `inference_service`
+/// * `topic` - The topic read
+/// * `body_reads` - Messages decoded from their body
+/// * `fallback_reads` - Messages decoded from their fallback attribute - the consumer still needs
+///   the fallback
+#[derive(Debug, Deserialize)]
+#[cfg(feature = "grpc")]
+pub struct ConsumerFormatReport {
+    pub consumer: String,
+    pub topic: String,
+    #[serde(default)]
+    pub body_reads: u64,
+    #[serde(default)]
+    pub fallback_reads: u64,
+}
+
+// Global variables ********************************************************************************
+/// Message formats of the topics - JSON everywhere until initialized
+static MESSAGE_FORMATS: OnceLock<MessageFormats> = OnceLock::new();
+
+// MAIN FUNCTIONS **********************************************************************************
+/// Set the message formats of the topics - called once at startup
+/// # Arguments
+/// * `formats` - The formats from PUBSUB_MESSAGE_FORMATS
+pub fn init_message_formats(formats: MessageFormats) {
+    for (topic, format) in &formats.topics {
+        info!(
+            "Pub/Sub messages of {topic} published as {}",
+            format.as_str()
+        );
+    }
+    if MESSAGE_FORMATS.set(formats).is_err() {
+        warn!("Message formats already initialized");
+    }
+}
+
+/// Encode a notification in the format of its topic, adding the encoding and fallback attributes
+/// - A message that cannot be encoded in Avro is published as JSON, never dropped
+/// - A fallback larger than an attribute allows is left out and counted
+/// # Arguments
+/// * `topic` - The topic the message is published to
+/// * `schema` - The Avro schema (`.avsc`) of the topic's consumers
+/// * `data` - The JSON notification
+/// * `attributes` - The attributes of the message
+/// # Returns
+/// * The message body, and the format actually published
+pub fn encode_message(
+    topic: &str,
+    schema: &str,
+    data: &Value,
+    attributes: &mut HashMap<String, String>,
+) -> (Vec<u8>, MessageFormat) {
+    let format = MESSAGE_FORMATS
+        .get()
+        .map(|formats| formats.get(topic))
+        .unwrap_or(MessageFormat::Json);
+    let json = data.to_string();
+    if format == MessageFormat::Json {
+        return (json.into_bytes(), format);
+    }
+
+    // STEP 1: Encode the Avro copy - on failure the topic falls back to JSON for this message
+    let (avro, schema_name) = match encode_avro(schema, data) {
+        Ok(encoded) => encoded,
+        Err(e) => {
+            error!("❌ Avro encoding failed for {topic}, publishing JSON: {e}");
+            attributes.insert(ENCODING_ATTRIBUTE.to_string(), "json".to_string());
+            return (json.into_bytes(), MessageFormat::Json);
+        }
+    };
+    attributes.insert(SCHEMA_ATTRIBUTE.to_string(), schema_name);
+
+    // STEP 2: Pick the body and the fallback attribute
+    let (body, encoding, fallback) = match format {
+        MessageFormat::JsonWithAvro => (
+            json.into_bytes(),
+            "json",
+            Some((AVRO_FALLBACK_ATTRIBUTE, BASE64.encode(&avro))),
+        ),
+        MessageFormat::AvroWithJson => (avro, "avro", Some((JSON_FALLBACK_ATTRIBUTE, json))),
+        _ => (avro, "avro", None),
+    };
+    attributes.insert(ENCODING_ATTRIBUTE.to_string(), encoding.to_string());
+
+    // STEP 3: Attach the fallback if it fits in an attribute
+    let Some((name, value)) = fallback else {
+        return (body, format);
+    };
+    if value.len() > MAX_ATTRIBUTE_BYTES {
+        warn!(
+            "{name} of {} bytes left out of a {topic} message (limit {MAX_ATTRIBUTE_BYTES})",
+            value.len()
+        );
+        record_fallback_omitted(topic);
+        let published = match encoding {
+            "avro" => MessageFormat::Avro,
+            _ => MessageFormat::Json,
+        };
+        return (body, published);
+    }
+    attributes.insert(name.to_string(), value);
+    (body, format)
+}
+
+/// Copy the routing fields of an exam notification into the attributes of its Pub/Sub message,
+/// with the notification version, and order the messages of a patient by the patient hash
+/// # Arguments
+/// * `message` - The message to publish
+/// * `data` - The JSON notification of the exam
+pub fn tag_exam_message(message: &mut NotificationMessage, data: &Value) {
+    for name in ROUTING_ATTRIBUTES {
+        if let Some(value) = data[name].as_str() {
+            message
+                .attributes
+                .insert(name.to_string(), value.to_string());
+        }
+    }
+    message.attributes.insert(
+        SCHEMA_VERSION_ATTRIBUTE.to_string(),
+        NOTIFICATION_SCHEMA_VERSION.to_string(),
+    );
+    if let Some(patient_id) = data["patient_id"].as_str() {
+        message.ordering_key = patient_id.to_string();
+    }
+}
+
+/// Pull the format report subscription forever, counting the reads of each consumer
+/// Does nothing when FORMAT_REPORT_SUBSCRIPTION is not set
+/// # Arguments
+/// * `subscription_name` - The format report subscription (FORMAT_REPORT_SUBSCRIPTION)
+/// * `pubsub_client` - An Arc reference to the PubSub client
+#[cfg(feature = "grpc")]
+pub async fn listen_format_reports(
+    subscription_name: Option<String>,
+    pubsub_client: Arc<PubSubClient>,
+) {
+    let Some(subscription_name) = subscription_name else {
+        info!("FORMAT_REPORT_SUBSCRIPTION not set - consumer format reports disabled");
+        return;
+    };
+    info!("Listening to consumer format reports on {subscription_name}");
+    let subscription = pubsub_client.subscription(&subscription_name);
+
+    loop {
+        let pulled = ExternalCall::new(Dependency::PubSub, "pull_format_reports")
+            .run(|| subscription.pull(10, None))
+            .await;
+        match pulled {
+            Ok(messages) => {
+                for message in messages {
+                    match serde_json::from_slice::<ConsumerFormatReport>(&message.message.data) {
+                        Ok(report) => record_report(&report),
+                        Err(e) => warn!("Ignoring malformed consumer format report: {e}"),
+                    }
+                    if let Err(e) = message.ack().await {
+                        warn!("Could not ack consumer format report: {e}");
+                    }
+                }
+            }
+            Err(e) => warn!("Could not pull consumer format reports: {e}"),
+        }
+        tokio::time::sleep(PULL_INTERVAL).await;
+    }
+}
+
+// SUPPORT FUNCTIONS *******************************************************************************
+/// Count the reads of a consumer report
+/// # Arguments
+/// * `report` - The report of the consumer
+#[cfg(feature = "grpc")]
+fn record_report(report: &ConsumerFormatReport) {
+    record_consumer_reads(&report.consumer, &report.topic, "body", report.body_reads);
+    record_consumer_reads(
+        &report.consumer,
+        &report.topic,
+        "fallback",
+        report.fallback_reads,
+    );
+    if report.fallback_reads > 0 {
+        info!(
+            "Consumer {} still reads {} from the fallback ({} messages)",
+            report.consumer, report.topic, report.fallback_reads
+        );
+    }
+}
+
+/// Encode a JSON message with an Avro record schema (binary encoding, no container header)
+/// # Arguments
+/// * `schema` - The `.avsc` file of the consumer
+/// * `message` - The JSON message
+/// # Returns
+/// * The Avro bytes, and the full name of the record schema
+/// # Errors
+/// * Returns an error if the schema is invalid or the message does not match it
+fn encode_avro(schema: &str, message: &Value) -> Result<(Vec<u8>, String)> {
+    let schema: Value = serde_json::from_str(schema)?;
+    let name = schema["name"]
+        .as_str()
+        .ok_or_else(|| anyhow!("Avro schema without name"))?;
+    let full_name = match schema["namespace"].as_str() {
+        Some(namespace) => format!("{namespace}.{name}"),
+        None => name.to_string(),
+    };
+    let mut out = Vec::new();
+    encode_value(&schema, message, &mut out)?;
+    Ok((out, full_name))
+}
+
+/// Encode a value with its schema
+/// # Arguments
+/// * `schema` - A primitive name, a union (array) or a complex type (object)
+/// * `value` - The JSON value
+/// * `out` - The buffer the bytes are appended to
+fn encode_value(schema: &Value, value: &Value, out: &mut Vec<u8>) -> Result<()> {
+    match schema {
+        Value::String(kind) => encode_primitive(kind, value, out),
+        Value::Array(branches) => {
+            // The first branch the value matches is written, preceded by its index
+            for (index, branch) in branches.iter().enumerate() {
+                let mut encoded = Vec::new();
+                if encode_value(branch, value, &mut encoded).is_ok() {
+                    write_long(index as i64, out);
+                    out.extend(encoded);
+                    return Ok(());
+                }
+            }
+            Err(anyhow!("{value} matches no branch of {schema}"))
+        }
+        Value::Object(object) => match object.get("type").and_then(Value::as_str) {
+            Some("record") => encode_record(object, value, out),
+            Some("enum") => {
+                let symbol = value
+                    .as_str()
+                    .ok_or_else(|| anyhow!("{value} is not an enum symbol"))?;
+                let index = object["symbols"]
+                    .as_array()
+                    .and_then(|symbols| symbols.iter().position(|s| s == symbol))
+                    .ok_or_else(|| anyhow!("'{symbol}' is not a symbol of {schema}"))?;
+                write_long(index as i64, out);
+                Ok(())
+            }
+            Some("array") => {
+                let items = value
+                    .as_array()
+                    .ok_or_else(|| anyhow!("{value} is not an array"))?;
+                if !items.is_empty() {
+                    write_long(items.len() as i64, out);
+                    for item in items {
+                        encode_value(&object["items"], item, out)?;
+                    }
+                }
+                write_long(0, out);
+                Ok(())
+            }
+            // A primitive with attributes, e.g. a logical type
+            Some(kind) => encode_primitive(kind, value, out),
+            None => Err(anyhow!("Avro type without name: {schema}")),
+        },
+        _ => Err(anyhow!("Unsupported Avro schema: {schema}")),
+    }
+}
+
+/// Encode the fields of a record in schema order - a missing field takes its default
+/// # Arguments
+/// * `schema` - The record schema
+/// * `value` - The JSON object
+/// * `out` - The buffer the bytes are appended to
+fn encode_record(schema: &Map<String, Value>, value: &Value, out: &mut Vec<u8>) -> Result<()> {
+    let object = value
+        .as_object()
+        .ok_or_else(|| anyhow!("{value} is not a record"))?;
+    let fields = schema
+        .get("fields")
+        .and_then(Value::as_array)
+        .ok_or_else(|| anyhow!("Avro record without fields"))?;
+    for field in fields {
+        let name = field["name"].as_str().unwrap_or_default();
+        let field_value = object
+            .get(name)
+            .or_else(|| field.get("default"))
+            .ok_or_else(|| anyhow!("Missing field '{name}' without default"))?;
+        encode_value(&field["type"], field_value, out)
+            .map_err(|e| anyhow!("Field '{name}': {e}"))?;
+    }
+    Ok(())
+}
+
+/// Encode a primitive value
+/// # Arguments
+/// * `kind` - The primitive type name
+/// * `value` - The JSON value
+/// * `out` - The buffer the bytes are appended to
+fn encode_primitive(kind: &str, value: &Value, out: &mut Vec<u8>) -> Result<()> {
+    let mismatch = || anyhow!("{value} is not a {kind}");
+    match kind {
+        "null" => value.is_null().then_some(()).ok_or_else(mismatch),
+        "boolean" => {
+            out.push(u8::from(value.as_bool().ok_or_else(mismatch)?));
+            Ok(())
+        }
+        "int" | "long" => {
+            write_long(value.as_i64().ok_or_else(mismatch)?, out);
+            Ok(())
+        }
+        "float" => {
+            out.extend((value.as_f64().ok_or_else(mismatch)? as f32).to_le_bytes());
+            Ok(())
+        }
+        "double" => {
+            out.extend(value.as_f64().ok_or_else(mismatch)?.to_le_bytes());
+            Ok(())
+        }
+        "string" | "bytes" => {
+            let bytes = value.as_str().ok_or_else(mismatch)?.as_bytes();
+            write_long(bytes.len() as i64, out);
+            out.extend(bytes);
+            Ok(())
+        }
+        other => Err(anyhow!("Unsupported Avro type '{other}'")),
+    }
+}
+
+/// Write a long as a zig-zag variable-length integer
+fn write_long(n: i64, out: &mut Vec<u8>) {
+    let mut zigzag = ((n << 1) ^ (n >> 63)) as u64;
+    while zigzag >= 0x80 {
+        out.push((zigzag as u8 & 0x7f) | 0x80);
+        zigzag >>= 7;
+    }
+    out.push(zigzag as u8);
+}
+
+// TESTS *******************************************************************************************
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn ecg_notification() -> Value {
+        json!({
+            "topic": "t",
+            "exam_id": "e",
+            "exam_type": "ecg_exam",
+            "timestamp": "2026",
+            "patient_id": "p",
+            "hospital_id": "h",
+            "deferred": true,
+            "consent_scope": "research",
+            "object_path": "gs://b/ecg_exam/h/p/2026.parquet"
+        })
+    }
+
+    // Happy path: zig-zag varints as in the Avro specification
+    #[test]
+    fn longs_zigzag_encoded() {
+        for (n, expected) in [
+            (0, vec![0x00]),
+            (-1, vec![0x01]),
+            (1, vec![0x02]),
+            (-64, vec![0x7f]),
+            (64, vec![0x80, 0x01]),
+        ] {
+            let mut out = Vec::new();
+            write_long(n, &mut out);
+            assert_eq!(out, expected, "{n}");
+        }
+    }
+
+    // Happy path: the ECG notification encodes field by field in schema order
+    #[test]
+    fn ecg_notification_encoded() {
+        let (bytes, name) = encode_avro(INFERENCE_ECG_AVRO, &ecg_notification()).unwrap();
+        assert_eq!(name, "sentinela.inference.EcgExamNotification");
+        let mut expected = vec![2, b't', 2, b'e', 16];
+        expected.extend(b"ecg_exam");
+        expected.extend([8, b'2', b'0', b'2', b'6', 2, b'p', 2, b'h', 1, 2, 64]);
+        expected.extend(b"gs://b/ecg_exam/h/p/2026.parquet");
+        assert_eq!(bytes, expected);
+    }
+
+    // Happy path: routing fields and version become attributes, the patient hash the ordering key
+    #[test]
+    fn exam_message_tagged() {
+        let mut message = NotificationMessage::default();
+        tag_exam_message(&mut message, &ecg_notification());
+        assert_eq!(message.ordering_key, "p");
+        assert_eq!(message.attributes["hospital_id"], "h");
+        assert_eq!(message.attributes["exam_type"], "ecg_exam");
+        assert_eq!(message.attributes["timestamp"], "2026");
+        assert_eq!(
+            message.attributes[SCHEMA_VERSION_ATTRIBUTE],
+            NOTIFICATION_SCHEMA_VERSION
+        );
+        assert!(!message.attributes.contains_key("patient_id"));
+    }
+
+    // Happy path: absent nullable DICOM fields take their null branch
+    #[test]
+    fn xray_nullable_fields_encoded() {
+        let mut message = ecg_notification();
+        message["exam_type"] = json!("xray_exam");
+        message["image_object"] = json!("xray_exam/h/p/2026.dcm");
+        message["modality"] = json!("CR");
+        let (bytes, name) = encode_avro(INFERENCE_XRAY_AVRO, &message).unwrap();
+        assert_eq!(name, "sentinela.inference.XrayExamNotification");
+        // "CR" is written as the string branch (index 1), the other DICOM fields as null (index 0)
+        let cr = [2, 4, b'C', b'R'];
+        assert!(bytes.windows(cr.len()).any(|w| w == cr));
+    }
+
+    // Sad path: a message missing a required field or with an unknown symbol is refused
+    #[test]
+    fn mismatching_message_refused() {
+        let mut missing = ecg_notification();
+        missing.as_object_mut().unwrap().remove("patient_id");
+        assert!(encode_avro(INFERENCE_ECG_AVRO, &missing).is_err());
+        let mut unknown = ecg_notification();
+        unknown["consent_scope"] = json!("marketing");
+        assert!(encode_avro(INFERENCE_ECG_AVRO, &unknown).is_err());
+    }
+
+    // Happy path: formats per topic, JSON by default; sad path: unknown format
+    #[test]
+    fn formats_parsed() {
+        let formats =
+            MessageFormats::parse("prod-ecg-v1=avro+json, prod-xray-v1=json+avro").unwrap();
+        assert_eq!(formats.get("prod-ecg-v1"), MessageFormat::AvroWithJson);
+        assert_eq!(formats.get("prod-xray-v1"), MessageFormat::JsonWithAvro);
+        assert_eq!(formats.get("prod-other-v1"), MessageFormat::Json);
+        assert!(MessageFormats::parse("prod-ecg-v1=xml").is_err());
+        assert!(MessageFormats::parse("prod-ecg-v1").is_err());
+        assert!(MessageFormats::parse("a=avro,a=json").is_err());
+    }
+
+    // Happy path: topics not initialized stay JSON without any format attribute
+    #[test]
+    fn json_topic_unchanged() {
+        let mut attributes = HashMap::new();
+        let data = ecg_notification();
+        let (body, format) = encode_message(
+            "format-test-none",
+            INFERENCE_ECG_AVRO,
+            &data,
+            &mut attributes,
+        );
+        assert_eq!(format, MessageFormat::Json);
+        assert_eq!(body, data.to_string().into_bytes());
+        assert!(attributes.is_empty());
+    }
+
+    // Dual formats in one test - the formats are process-global
+    #[test]
+    fn dual_formats_carry_fallback() {
+        init_message_formats(
+            MessageFormats::parse(
+                "format-test-avro=avro+json,format-test-json=json+avro,format-test-big=avro+json",
+            )
+            .unwrap(),
+        );
+        let data = ecg_notification();
+        let (avro, _) = encode_avro(INFERENCE_ECG_AVRO, &data).unwrap();
+
+        let mut attributes = HashMap::new();
+        let (body, format) = encode_message(
+            "format-test-avro",
+            INFERENCE_ECG_AVRO,
+            &data,
+            &mut attributes,
+        );
+        assert_eq!((body, format), (avro.clone(), MessageFormat::AvroWithJson));
+        assert_eq!(attributes[ENCODING_ATTRIBUTE], "avro");
+        assert_eq!(attributes[JSON_FALLBACK_ATTRIBUTE], data.to_string());
+        assert_eq!(
+            attributes[SCHEMA_ATTRIBUTE],
+            "sentinela.inference.EcgExamNotification"
+        );
+
+        let mut attributes = HashMap::new();
+        let (body, _) = encode_message(
+            "format-test-json",
+            INFERENCE_ECG_AVRO,
+            &data,
+            &mut attributes,
+        );
+        assert_eq!(body, data.to_string().into_bytes());
+        assert_eq!(attributes[ENCODING_ATTRIBUTE], "json");
+        assert_eq!(
+            BASE64.decode(&attributes[AVRO_FALLBACK_ATTRIBUTE]).unwrap(),
+            avro
+        );
+
+        // Borderline: a fallback over the attribute limit is left out, the body is still Avro
+        let mut big = data.clone();
+        big["exam_id"] = json!("e".repeat(MAX_ATTRIBUTE_BYTES));
+        let mut attributes = HashMap::new();
+        let (_, format) =
+            encode_message("format-test-big", INFERENCE_ECG_AVRO, &big, &mut attributes);
+        assert_eq!(format, MessageFormat::Avro);
+        assert_eq!(attributes[ENCODING_ATTRIBUTE], "avro");
+        assert!(!attributes.contains_key(JSON_FALLBACK_ATTRIBUTE));
+    }
+}
@@ -0,0 +1,355 @@
+// Imports *****************************************************************************************
+// External Crates
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
+use log::{info, warn};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use sqlx::pool::PoolConnection;
+use sqlx::{Connection, PgConnection, PgPool, Postgres, Row};
+use std::collections::HashMap;
+
+// Internal Modules
+
+// Constants ***************************************************************************************
+/// Migrations of the `migrations` directory, oldest first - embedded in the binary, so `admin
+/// migrate` applies the schema the binary was built for; a new file must be added here
+const MIGRATIONS: &[(&str, &str)] = &[
+    (
+        "20261017_hash_hospital_keys",
+        include_str!("../../migrations/20261017_hash_hospital_keys.sql"),
+    ),
+    (
+        "20261018_hospital_size_tiers",
+        include_str!("../../migrations/20261018_hospital_size_tiers.sql"),
+    ),
+    (
+        "20261019_hospital_groups",
+        include_str!("../../migrations/20261019_hospital_groups.sql"),
+    ),
+    (
+        "20261020_idempotency_keys",
+        include_str!("../../migrations/20261020_idempotency_keys.sql"),
+    ),
+    (
+        "20261021_audit_events",
+        include_str!("../../migrations/20261021_audit_events.sql"),
+    ),
+    (
+        "20261022_hospital_keys",
+        include_str!("../../migrations/20261022_hospital_keys.sql"),
+    ),
+    (
+        "20261023_hospital_registry_notify",
+        include_str!("../../migrations/20261023_hospital_registry_notify.sql"),
+    ),
+    (
+        "20261024_hospital_publish_mode",
+        include_str!("../../migrations/20261024_hospital_publish_mode.sql"),
+    ),
+    (
+        "20261025_admin_console_sessions",
+        include_str!("../../migrations/20261025_admin_console_sessions.sql"),
+    ),
+    (
+        "20261026_exam_retention_certificates",
+        include_str!("../../migrations/20261026_exam_retention_certificates.sql"),
+    ),
+    (
+        "20261027_hospital_pause",
+        include_str!("../../migrations/20261027_hospital_pause.sql"),
+    ),
+    (
+        "20261028_idempotency_in_flight",
+        include_str!("../../migrations/20261028_idempotency_in_flight.sql"),
+    ),
+    (
+        "20261029_idempotency_payload_hash",
+        include_str!("../../migrations/20261029_idempotency_payload_hash.sql"),
+    ),
+];
+/// Key of the Postgres advisory lock held while migrating, so two operators never apply the same
+/// migration twice
+const MIGRATION_LOCK_KEY: i64 = 0x5e17_1e1a_0001;
+
+// Structs *****************************************************************************************
+/// Migration of the binary and whether the database has it
+/// # Arguments
+/// * `version` - The version, prefix of the file name (e.g. `20261027`)
+/// * `name` - The file name, without `.sql`
+/// * `applied_at` - When it was applied or baselined, None if pending
+/// * `modified` - Applied from a file different from the embedded one
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct MigrationStatus {
+    pub version: &'static str,
+    pub name: &'static str,
+    pub applied_at: Option<DateTime<Utc>>,
+    pub modified: bool,
+}
+
+// MAIN FUNCTIONS **********************************************************************************
+/// Migrations of the binary and whether the database has them
+/// # Arguments
+/// * `pool` - The shared database connection pool
+/// # Errors
+/// * Returns an error if the `schema_migrations` table cannot be created or read
+pub async fn migration_status(pool: &PgPool) -> Result<Vec<MigrationStatus>> {
+    let mut conn = pool.acquire().await?;
+    status(&mut conn).await
+}
+
+/// Versions recorded in the database that the binary does not embed - a newer binary migrated it
+/// # Arguments
+/// * `pool` - The shared database connection pool
+/// # Errors
+/// * Returns an error if the `schema_migrations` table cannot be created or read
+pub async fn unknown_migrations(pool: &PgPool) -> Result<Vec<String>> {
+    let mut conn = pool.acquire().await?;
+    status(&mut conn).await?;
+    let recorded: Vec<String> =
+        sqlx::query_scalar("SELECT version FROM schema_migrations ORDER BY version")
+            .fetch_all(&mut *conn)
+            .await?;
+    Ok(recorded
+        .into_iter()
+        .filter(|version| {
+            !MIGRATIONS
+                .iter()
+                .any(|(name, _)| migration_version(name) == version)
+        })
+        .collect())
+}
+
+/// Apply the pending migrations in order, each file in one transaction with its record in
+/// `schema_migrations` - under an advisory lock, and stopping at the first failure
+/// # Arguments
+/// * `pool` - The shared database connection pool
+/// * `dry_run` - Only list the pending migrations
+/// # Returns
+/// * The names of the migrations applied, or pending in a dry run
+/// # Errors
+/// * Returns an error if a migration fails - the ones before it stay applied, the failed one is
+///   neither applied nor recorded
+pub async fn apply_migrations(pool: &PgPool, dry_run: bool) -> Result<Vec<&'static str>> {
+    let mut conn = pool.acquire().await?;
+    sqlx::query("SELECT pg_advisory_lock($1)")
+        .bind(MIGRATION_LOCK_KEY)
+        .execute(&mut *conn)
+        .await?;
+    let applied = apply_pending(&mut conn, dry_run).await;
+    // Session lock: released explicitly, the connection goes back to the pool
+    if let Err(e) = sqlx::query("SELECT pg_advisory_unlock($1)")
+        .bind(MIGRATION_LOCK_KEY)
+        .execute(&mut *conn)
+        .await
+    {
+        warn!("Migration lock not released: {e}");
+        conn.close_on_drop();
+    }
+    applied
+}
+
+/// Record the migrations up to a version as applied, without running them - for a database whose
+/// schema was set up by hand before `admin migrate`
+/// # Arguments
+/// * `pool` - The shared database connection pool
+/// * `version` - The last version already in the database
+/// # Returns
+/// * The names of the migrations recorded
+/// # Errors
+/// * Returns an error for a version the binary does not know, or if the table cannot be written
+pub async fn baseline_migrations(pool: &PgPool, version: &str) -> Result<Vec<&'static str>> {
+    if !MIGRATIONS
+        .iter()
+        .any(|(name, _)| migration_version(name) == version)
+    {
+        return Err(anyhow!("Unknown migration version {version}"));
+    }
+    let mut conn = pool.acquire().await?;
+    let applied = status(&mut conn).await?;
+    let mut recorded = Vec::new();
+    for migration in applied.iter().filter(|m| m.applied_at.is_none()) {
+        if migration.version > version {
+            continue;
+        }
+        record_migration(&mut conn, migration.name).await?;
+        info!(target: "audit", "migration_baselined name={}", migration.name);
+        recorded.push(migration.name);
+    }
+    Ok(recorded)
+}
+
+// SUPPORT FUNCTIONS *******************************************************************************
+/// Apply the pending migrations on the locked connection
+async fn apply_pending(
+    conn: &mut PoolConnection<Postgres>,
+    dry_run: bool,
+) -> Result<Vec<&'static str>> {
+    let pending: Vec<&'static str> = status(conn)
+        .await?
+        .into_iter()
+        .filter(|migration| migration.applied_at.is_none())
+        .map(|migration| migration.name)
+        .collect();
+    if dry_run {
+        return Ok(pending);
+    }
+    for name in &pending {
+        // The SQL and its record commit together - a failure in between applies neither, so the
+        // next run does not apply the file a second time
+        let body = transaction_body(migration_sql(name));
+        let mut transaction = conn.begin().await?;
+        sqlx::raw_sql(&body)
+            .execute(&mut *transaction)
+            .await
+            .map_err(|e| anyhow!("Migration {name} failed: {e}"))?;
+        record_migration(&mut transaction, name).await?;
+        transaction.commit().await?;
+        info!(target: "audit", "migration_applied name={name}");
+    }
+    Ok(pending)
+}
+
+/// Migrations of the binary against the `schema_migrations` table, created if missing
+async fn status(conn: &mut PoolConnection<Postgres>) -> Result<Vec<MigrationStatus>> {
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS schema_migrations (
+            version    TEXT PRIMARY KEY,
+            name       TEXT NOT NULL,
+            checksum   TEXT NOT NULL,
+            applied_at TIMESTAMPTZ NOT NULL DEFAULT now()
+        )
+        "#,
+    )
+    .execute(&mut **conn)
+    .await?;
+    let rows = sqlx::query(
+        r#"
+        SELECT version, checksum,
+               (extract(epoch FROM applied_at) * 1000000)::bigint AS applied_at_us
+        FROM schema_migrations
+        "#,
+    )
+    .fetch_all(&mut **conn)
+    .await?;
+    let mut applied = HashMap::new();
+    for row in rows {
+        let version: String = row.try_get("version")?;
+        let checksum: String = row.try_get("checksum")?;
+        let micros: i64 = row.try_get("applied_at_us")?;
+        applied.insert(version, (checksum, DateTime::from_timestamp_micros(micros)));
+    }
+    Ok(MIGRATIONS
+        .iter()
+        .map(|&(name, sql)| {
+            let version = migration_version(name);
+            let recorded = applied.get(version);
+            MigrationStatus {
+                version,
+                name,
+                applied_at: recorded.and_then(|(_, at)| *at),
+                modified: recorded.is_some_and(|(checksum, _)| *checksum != checksum_of(sql)),
+            }
+        })
+        .collect())
+}
+
+/// Record a migration as applied
+async fn record_migration(conn: &mut PgConnection, name: &str) -> Result<()> {
+    sqlx::query(
+        r#"
+        INSERT INTO schema_migrations (version, name, checksum) VALUES ($1, $2, $3)
+        ON CONFLICT (version) DO NOTHING
+        "#,
+    )
+    .bind(migration_version(name))
+    .bind(name)
+    .bind(checksum_of(migration_sql(name)))
+    .execute(conn)
+    .await?;
+    Ok(())
+}
+
+/// Version of a migration: the prefix of its name, before the first `_`
+fn migration_version(name: &str) -> &str {
+    name.split_once('_').map_or(name, |(version, _)| version)
+}
+
+/// SQL of an embedded migration
+fn migration_sql(name: &str) -> &'static str {
+    MIGRATIONS
+        .iter()
+        .find(|(migration, _)| *migration == name)
+        .map_or("", |&(_, sql)| sql)
+}
+
+/// SQL of a migration without its own `BEGIN;` and `COMMIT;` lines, to run in the transaction that
+/// records it - the checksum stays the one of the file as written
+fn transaction_body(sql: &str) -> String {
+    sql.lines()
+        .filter(|line| {
+            let statement = line.trim();
+            !statement.eq_ignore_ascii_case("BEGIN;") && !statement.eq_ignore_ascii_case("COMMIT;")
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Hex SHA256 of the SQL of a migration
+fn checksum_of(sql: &str) -> String {
+    format!("{:x}", Sha256::digest(sql.as_bytes()))
+}
+
+// TESTS *******************************************************************************************
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Happy path: every file of the migrations directory is embedded, in order
+    #[test]
+    fn every_migration_embedded() {
+        let dir = concat!(env!("CARGO_MANIFEST_DIR"), "/migrations");
+        let mut files: Vec<String> = std::fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .filter_map(|file| file.strip_suffix(".sql").map(str::to_string))
+            .collect();
+        files.sort();
+        let embedded: Vec<&str> = MIGRATIONS.iter().map(|(name, _)| *name).collect();
+        assert_eq!(files, embedded);
+    }
+
+    // Borderline: versions are unique, so each one is recorded once
+    #[test]
+    fn versions_unique() {
+        let mut versions: Vec<&str> = MIGRATIONS
+            .iter()
+            .map(|(name, _)| migration_version(name))
+            .collect();
+        versions.dedup();
+        assert_eq!(versions.len(), MIGRATIONS.len());
+        assert_eq!(migration_version("20261027_hospital_pause"), "20261027");
+        assert_eq!(migration_sql("missing"), "");
+    }
+
+    // Happy path: the transaction control of the files is left out, the PL/pgSQL blocks are kept
+    #[test]
+    fn transaction_body_unwrapped() {
+        for (name, sql) in MIGRATIONS {
+            let body = transaction_body(sql);
+            let statements: Vec<String> = body
+                .lines()
+                .map(|line| line.trim().to_ascii_uppercase())
+                .collect();
+            assert!(!statements.contains(&"BEGIN;".to_string()), "{name}");
+            assert!(!statements.contains(&"COMMIT;".to_string()), "{name}");
+        }
+        let audit = transaction_body(migration_sql("20261021_audit_events"));
+        assert!(audit.lines().any(|line| line.trim() == "BEGIN"));
+        assert_eq!(
+            transaction_body("BEGIN;\nALTER TABLE t ADD COLUMN c INT;\n  commit;"),
+            "ALTER TABLE t ADD COLUMN c INT;"
+        );
+    }
+}
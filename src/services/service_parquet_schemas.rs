@@ -0,0 +1,263 @@
+// Imports *****************************************************************************************
+// External Crates
+use anyhow::Result;
+use log::{info, warn};
+use polars::io::parquet::{ParquetReader, ParquetWriter, ZstdLevel};
+use polars::prelude::*;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::io::Cursor;
+use std::sync::Arc;
+
+// Internal Modules
+use crate::models::models_consent::ConsentScope;
+use crate::models::models_exams::PayloadEcg;
+use crate::services::service_ecg_exam::{ecg_exam_frame, ECG_PARQUET_SCHEMA_VERSION};
+use crate::services::service_xray_exam::{sidecar_schema_samples, XRAY_PARQUET_SCHEMA_VERSION};
+use crate::storage::exam_storage::{ExamStorage, ObjectPut};
+use crate::utils::external_call::INGEST_RETRIES;
+
+// Constants ***************************************************************************************
+/// Prefix of the published schema documents in the exam bucket - outside of every exam type
+const SCHEMA_PREFIX: &str = "schemas/parquet";
+/// Compression of the stored Parquet files, as set by the writers
+const PARQUET_COMPRESSION: &str = "zstd(1)";
+
+// Structs *****************************************************************************************
+/// Column of a stored Parquet file
+/// # Arguments
+/// * `name` - The column name
+/// * `data_type` - The type of the column, as written (e.g. `Float32`, `String`, `UInt32`)
+/// * `optional` - Absent from the file, or null-typed, when the exam does not have the value
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ParquetColumn {
+    pub name: String,
+    pub data_type: String,
+    pub optional: bool,
+}
+
+/// Machine-readable description of the Parquet files of an exam type, derived from the writer
+/// # Arguments
+/// * `exam_type` - The exam type key, first segment of the object names
+/// * `schema_version` - The version of the layout, bumped by the writer on any change
+/// * `object_name` - The pattern of the object names
+/// * `rows` - What a row of the file is
+/// * `compression` - The compression of the column chunks
+/// * `columns` - The columns, in file order
+/// * `metadata_keys` - The keys of the Parquet key-value metadata written with the file
+/// * `fingerprint` - Hex SHA256 of the column names and types, to match a file to its version
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ParquetSchema {
+    pub exam_type: &'static str,
+    pub schema_version: u32,
+    pub object_name: String,
+    pub rows: &'static str,
+    pub compression: &'static str,
+    pub columns: Vec<ParquetColumn>,
+    pub metadata_keys: Vec<String>,
+    pub fingerprint: String,
+}
+
+// MAIN FUNCTIONS **********************************************************************************
+/// Describe the Parquet files currently written, per exam type - the sample exams go through the
+/// writer code, so the description cannot drift from it
+/// # Returns
+/// * The schema of every exam type stored as Parquet
+/// # Errors
+/// * Returns an error if a sample exam cannot be written
+pub fn parquet_schemas() -> Result<Vec<ParquetSchema>> {
+    // STEP 1: ECG exams - one frame of the columnar writer
+    let ecg = ecg_exam_frame(
+        &sample_ecg(),
+        "2026-10-01T120000.000Z",
+        ConsentScope::Clinical,
+    )?;
+    let ecg = describe(
+        "ecg_exam",
+        ECG_PARQUET_SCHEMA_VERSION,
+        "one row per sample, the exam metadata repeated on every row",
+        &ecg,
+        &ecg,
+    )?;
+
+    // STEP 2: XRay exams - the metadata sidecars, from the smallest to the largest
+    let (smallest, largest) = sidecar_schema_samples()?;
+    let xray = describe(
+        "xray_exam",
+        XRAY_PARQUET_SCHEMA_VERSION,
+        "one row per exam, the metadata sidecar of the image stored next to it",
+        &smallest,
+        &largest,
+    )?;
+    Ok(vec![ecg, xray])
+}
+
+/// Publish the schema of every exam type to the exam bucket, under
+/// `schemas/parquet/{exam_type}/v{schema_version}.json` and `.../latest.json` - the versioned
+/// documents are kept, so the bucket holds the schema of every version ever written
+/// # Arguments
+/// * `bucket_name` - The exam bucket
+/// * `storage` - The exam storage backend
+pub async fn publish_parquet_schemas(bucket_name: String, storage: Arc<dyn ExamStorage>) {
+    let published = async {
+        for schema in parquet_schemas()? {
+            let document = serde_json::to_vec_pretty(&schema)?;
+            let versioned = format!("v{}", schema.schema_version);
+            for version in [versioned.as_str(), "latest"] {
+                let name = format!("{SCHEMA_PREFIX}/{}/{version}.json", schema.exam_type);
+                let object = ObjectPut {
+                    bucket: &bucket_name,
+                    name: &name,
+                    content_type: Some("application/json"),
+                    operation: "upload_parquet_schema",
+                    retries: INGEST_RETRIES,
+                };
+                storage.put_object(&object, document.clone()).await?;
+            }
+            info!(
+                "Parquet schema of {} v{} published",
+                schema.exam_type, schema.schema_version
+            );
+        }
+        anyhow::Ok(())
+    };
+    if let Err(e) = published.await {
+        warn!("Parquet schemas not published: {e}");
+    }
+}
+
+// SUPPORT FUNCTIONS *******************************************************************************
+/// Describe the files of an exam type from the frames of its smallest and largest sample: the
+/// columns of the largest, optional when the smallest lacks them or has them null-typed
+fn describe(
+    exam_type: &'static str,
+    schema_version: u32,
+    rows: &'static str,
+    smallest: &DataFrame,
+    largest: &DataFrame,
+) -> Result<ParquetSchema> {
+    let smallest = smallest.schema();
+    let columns: Vec<ParquetColumn> = largest
+        .schema()
+        .iter()
+        .map(|(name, data_type)| ParquetColumn {
+            name: name.to_string(),
+            data_type: format!("{data_type:?}"),
+            optional: !smallest
+                .get(name.as_str())
+                .is_some_and(|data_type| data_type != &DataType::Null),
+        })
+        .collect();
+    let fingerprint = columns
+        .iter()
+        .fold(Sha256::new(), |hasher, column| {
+            hasher.chain_update(format!("{}:{};", column.name, column.data_type))
+        })
+        .finalize();
+    Ok(ParquetSchema {
+        exam_type,
+        schema_version,
+        object_name: format!("{exam_type}/{{hospital_id}}/{{patient_id}}/{{uuid}}.parquet"),
+        rows,
+        compression: PARQUET_COMPRESSION,
+        metadata_keys: metadata_keys(largest)?,
+        columns,
+        fingerprint: format!("{fingerprint:x}"),
+    })
+}
+
+/// Keys of the key-value metadata the writer adds to a file, read back from a written sample
+fn metadata_keys(df: &DataFrame) -> Result<Vec<String>> {
+    let mut df = df.clone();
+    let mut buffer = Vec::new();
+    ParquetWriter::new(&mut buffer)
+        .with_compression(ParquetCompression::Zstd(Some(ZstdLevel::try_new(1)?)))
+        .finish(&mut df)?;
+    let mut reader = ParquetReader::new(Cursor::new(buffer));
+    let metadata = reader.get_metadata()?;
+    Ok(metadata
+        .key_value_metadata
+        .iter()
+        .flatten()
+        .map(|kv| kv.key.clone())
+        .collect())
+}
+
+/// ECG exam of two samples with every optional field set
+fn sample_ecg() -> PayloadEcg {
+    let lead = vec![0.0_f32, 0.1];
+    PayloadEcg {
+        patient_id: "0".repeat(64).into(),
+        hospital_id: "0".repeat(64).into(),
+        hospital_key: None,
+        lead_i: lead.clone(),
+        lead_ii: lead.clone(),
+        lead_iii: lead.clone(),
+        lead_avr: lead.clone(),
+        lead_avl: lead.clone(),
+        lead_avf: lead.clone(),
+        lead_v1: lead.clone(),
+        lead_v2: lead.clone(),
+        lead_v3: lead.clone(),
+        lead_v4: lead.clone(),
+        lead_v5: lead.clone(),
+        lead_v6: lead,
+        sampling_rate_hz: Some(500.0),
+        duration_s: Some(10.0),
+    }
+}
+
+// TESTS *******************************************************************************************
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::service_ecg_exam::{ECG_LEAD_COLUMNS, ECG_SAMPLE_INDEX_COLUMN};
+
+    fn schema(exam_type: &str) -> ParquetSchema {
+        parquet_schemas()
+            .unwrap()
+            .into_iter()
+            .find(|schema| schema.exam_type == exam_type)
+            .unwrap()
+    }
+
+    // Happy path: the ECG columns are those of the writer, none optional
+    #[test]
+    fn ecg_schema_from_writer() {
+        let ecg = schema("ecg_exam");
+        assert_eq!(ecg.schema_version, ECG_PARQUET_SCHEMA_VERSION);
+        assert_eq!(ecg.columns[0].name, ECG_SAMPLE_INDEX_COLUMN);
+        let leads: Vec<&str> = ecg.columns[1..13].iter().map(|c| c.name.as_str()).collect();
+        assert_eq!(leads, ECG_LEAD_COLUMNS);
+        assert_eq!(ecg.columns[1].data_type, "Float32");
+        assert!(ecg.columns.iter().all(|column| !column.optional));
+        assert!(ecg
+            .columns
+            .iter()
+            .any(|column| column.name == "consent_scope"));
+    }
+
+    // Borderline: sidecar columns only some exams have are optional
+    #[test]
+    fn xray_optional_columns() {
+        let xray = schema("xray_exam");
+        let optional = |name: &str| {
+            xray.columns
+                .iter()
+                .find(|column| column.name == name)
+                .unwrap()
+                .optional
+        };
+        assert!(!optional("image_object"));
+        assert!(optional("image_width"));
+        assert!(optional("view_position"));
+        assert!(optional("transfer_syntax_uid"));
+        assert_eq!(xray.fingerprint.len(), 64);
+    }
+
+    // Happy path: the description is stable, so a published version is never rewritten differently
+    #[test]
+    fn schemas_stable() {
+        assert_eq!(parquet_schemas().unwrap(), parquet_schemas().unwrap());
+    }
+}
@@ -0,0 +1,264 @@
+// Imports *****************************************************************************************
+// External Crates
+use anyhow::{anyhow, Result};
+use google_cloud_gax::conn::{ConnectionManager, ConnectionOptions};
+use google_cloud_googleapis::pubsub::v1::publisher_client::PublisherClient;
+use google_cloud_googleapis::pubsub::v1::GetTopicRequest;
+use google_cloud_pubsub::apiv1::conn_pool::AUDIENCE;
+use google_cloud_pubsub::client::google_cloud_auth::credentials::CredentialsFile;
+use google_cloud_pubsub::client::{Client as PubSubClient, ClientConfig as PubSubClientConfig};
+use google_cloud_pubsub::topic::Topic;
+use log::info;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+// Internal Modules
+use crate::authentication::gcp_identity::{pubsub_client_config, GcpIdentity};
+use crate::config::settings::PublishSettings;
+use crate::models::models_consent::ConsentScope;
+use crate::models::models_topics::{check_route_consent, route_of, TopicRoute};
+use crate::publishers::publisher::TopicSchema;
+use crate::utils::external_call::{Dependency, ExternalCall};
+
+// Structs *****************************************************************************************
+/// Topic admin of one Pub/Sub project: the gRPC publisher service, which the client library only
+/// uses internally - it reads the configuration (schema settings) of the topics
+pub struct TopicAdmin {
+    connections: ConnectionManager,
+}
+
+/// Exam-type routing table of Pub/Sub topics, with one client and topic admin per partner project
+pub struct PubSubRouter {
+    default_client: Arc<PubSubClient>,
+    default_admin: Arc<TopicAdmin>,
+    project_clients: HashMap<String, (Arc<PubSubClient>, TopicAdmin)>,
+    routes: HashMap<String, TopicRoute>,
+}
+
+impl TopicAdmin {
+    /// Connect the topic admin with the configuration of a project's client
+    /// # Arguments
+    /// * `config` - The configuration the client of the project is created with
+    /// # Errors
+    /// * Returns an error if the connection cannot be set up
+    pub async fn new(config: &PubSubClientConfig) -> Result<Self> {
+        let connections = ConnectionManager::new(
+            config.pool_size.unwrap_or(1).max(1),
+            config.endpoint.as_str(),
+            AUDIENCE,
+            &config.environment,
+            &ConnectionOptions::default(),
+        )
+        .await?;
+        Ok(Self { connections })
+    }
+
+    /// Schema of the registry a topic is bound to
+    /// # Arguments
+    /// * `topic` - The fully qualified topic name
+    /// # Returns
+    /// * The schema name, None for a topic without schema
+    /// # Errors
+    /// * Returns an error if the topic configuration cannot be read
+    pub async fn schema_of(&self, topic: &str) -> Result<Option<String>> {
+        let config = ExternalCall::new(Dependency::PubSub, "get_topic")
+            .run(|| {
+                let mut client = PublisherClient::new(self.connections.conn());
+                let request = GetTopicRequest {
+                    topic: topic.to_string(),
+                };
+                async move { client.get_topic(request).await }
+            })
+            .await?
+            .into_inner();
+        Ok(config
+            .schema_settings
+            .map(|settings| settings.schema)
+            .filter(|schema| !schema.is_empty()))
+    }
+}
+
+impl PubSubRouter {
+    /// Build the router from PUBSUB_ROUTES and PUBSUB_PROJECT_CREDENTIALS and validate that every
+    /// routed topic exists and is reachable with the credentials of its project
+    /// # Arguments
+    /// * `settings` - The publish settings, whose routes were checked against DEPLOY_ENV
+    /// * `default_client` - The PubSub client of the service's own project
+    /// * `default_admin` - The topic admin of the service's own project
+    /// # Errors
+    /// * Returns an error listing the misconfigured route if validation fails
+    pub async fn new(
+        settings: &PublishSettings,
+        default_client: Arc<PubSubClient>,
+        default_admin: Arc<TopicAdmin>,
+    ) -> Result<Self> {
+        // STEP 1: The routing table and the partner credentials
+        let routes = settings.routes.clone();
+        let credentials = &settings.project_credentials;
+
+        // STEP 2: Create one client per partner project
+        let mut project_clients = HashMap::new();
+        for project in routes.values().filter_map(|r| r.project.clone()) {
+            if project_clients.contains_key(&project) {
+                continue;
+            }
+            let path = credentials.get(&project).ok_or_else(|| {
+                anyhow!("Pub/Sub project '{project}' is routed but has no entry in PUBSUB_PROJECT_CREDENTIALS")
+            })?;
+            let client = init_project_client(&project, path).await?;
+            project_clients.insert(project, client);
+        }
+
+        // STEP 3: Validate every route against its project
+        let router = Self {
+            default_client,
+            default_admin,
+            project_clients,
+            routes,
+        };
+        router.check_topics().await?;
+        for (exam_type, route) in &router.routes {
+            info!(
+                "Pub/Sub route {exam_type} -> {} (project: {}, consent: {})",
+                route.topic,
+                route.project.as_deref().unwrap_or("default"),
+                route.required_consent.as_str()
+            );
+        }
+        Ok(router)
+    }
+
+    /// Check that every routed topic exists and is reachable with the client of its project -
+    /// run at startup and by the readiness probe
+    /// # Errors
+    /// * Returns an error naming the first unreachable or missing topic
+    pub async fn check_topics(&self) -> Result<()> {
+        for exam_type in self.routes.keys() {
+            let topic = self.topic(exam_type)?;
+            let exists = ExternalCall::new(Dependency::PubSub, "topic_exists")
+                .run(|| topic.exists(None))
+                .await
+                .map_err(|e| {
+                    anyhow!(
+                        "Pub/Sub route '{exam_type}' ({}) is unreachable: {e}",
+                        topic.fully_qualified_name()
+                    )
+                })?;
+            if !exists {
+                return Err(anyhow!(
+                    "Pub/Sub route '{exam_type}': topic {} does not exist",
+                    topic.fully_qualified_name()
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Schema of the registry bound to every routed topic, read through the topic admin of its
+    /// project
+    /// # Errors
+    /// * Returns an error naming the first topic whose configuration cannot be read
+    pub async fn topic_schemas(&self) -> Result<Vec<TopicSchema>> {
+        let mut schemas = Vec::new();
+        for exam_type in self.routes.keys() {
+            let topic = self.topic(exam_type)?;
+            let admin = self.topic_admin(exam_type)?;
+            let schema = admin
+                .schema_of(topic.fully_qualified_name())
+                .await
+                .map_err(|e| {
+                    anyhow!(
+                        "Pub/Sub route '{exam_type}': configuration of {} unreadable: {e}",
+                        topic.fully_qualified_name()
+                    )
+                })?;
+            schemas.push(TopicSchema {
+                exam_type: exam_type.clone(),
+                topic: topic.fully_qualified_name().to_string(),
+                schema,
+            });
+        }
+        schemas.sort_by(|a, b| a.exam_type.cmp(&b.exam_type));
+        Ok(schemas)
+    }
+
+    /// The routed topic id of an exam type
+    /// # Errors
+    /// * Returns an error if the exam type has no route
+    pub fn topic_name(&self, exam_type: &str) -> Result<&str> {
+        route_of(&self.routes, exam_type).map(|r| r.topic.as_str())
+    }
+
+    /// Check that the consent of a hospital covers the destination of an exam type
+    /// # Arguments
+    /// * `exam_type` - The exam type key
+    /// * `consent_scope` - The consent of the hospital the exam comes from
+    /// # Errors
+    /// * Returns an error if the exam type has no route or the route needs a wider consent
+    pub fn check_consent(&self, exam_type: &str, consent_scope: ConsentScope) -> Result<()> {
+        check_route_consent(&self.routes, exam_type, consent_scope)
+    }
+
+    /// The topic handle of an exam type, bound to the client of the owning project
+    /// # Errors
+    /// * Returns an error if the exam type has no route
+    pub fn topic(&self, exam_type: &str) -> Result<Topic> {
+        let route = route_of(&self.routes, exam_type)?;
+        match &route.project {
+            None => Ok(self.default_client.topic(route.topic.as_str())),
+            Some(project) => {
+                let (client, _) = self
+                    .project_clients
+                    .get(project)
+                    .ok_or_else(|| anyhow!("No Pub/Sub client for project '{project}'"))?;
+                Ok(client.topic(&format!("projects/{project}/topics/{}", route.topic)))
+            }
+        }
+    }
+
+    /// The topic admin of the project owning the routed topic of an exam type
+    /// # Errors
+    /// * Returns an error if the exam type has no route
+    fn topic_admin(&self, exam_type: &str) -> Result<&TopicAdmin> {
+        let route = route_of(&self.routes, exam_type)?;
+        match &route.project {
+            None => Ok(&self.default_admin),
+            Some(project) => self
+                .project_clients
+                .get(project)
+                .map(|(_, admin)| admin)
+                .ok_or_else(|| anyhow!("No Pub/Sub topic admin for project '{project}'")),
+        }
+    }
+}
+
+// SUPPORT FUNCTIONS *******************************************************************************
+/// Create a PubSub client and topic admin for a partner project with its own identity
+/// # Arguments
+/// * `project` - The partner project id
+/// * `credentials` - `impersonate:<service account email>`, or the path to a credentials file
+///   (service account or external account for workload identity federation)
+async fn init_project_client(
+    project: &str,
+    credentials: &str,
+) -> Result<(Arc<PubSubClient>, TopicAdmin)> {
+    if let Some(account) = credentials.strip_prefix("impersonate:") {
+        let identity = GcpIdentity::Impersonated(account.to_string());
+        let config = pubsub_client_config(&identity, Some(project))
+            .await
+            .map_err(|e| anyhow!("Cannot authenticate to Pub/Sub project '{project}': {e}"))?;
+        let admin = TopicAdmin::new(&config).await?;
+        return Ok((Arc::new(PubSubClient::new(config).await?), admin));
+    }
+    let credentials_path = credentials;
+    let credentials = CredentialsFile::new_from_file(credentials_path.to_string())
+        .await
+        .map_err(|e| anyhow!("Cannot read credentials for Pub/Sub project '{project}': {e}"))?;
+    let mut config = PubSubClientConfig::default()
+        .with_credentials(credentials)
+        .await
+        .map_err(|e| anyhow!("Cannot authenticate to Pub/Sub project '{project}': {e}"))?;
+    config.project_id = Some(project.to_string());
+    let admin = TopicAdmin::new(&config).await?;
+    Ok((Arc::new(PubSubClient::new(config).await?), admin))
+}
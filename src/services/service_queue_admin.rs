@@ -0,0 +1,835 @@
+// Imports *****************************************************************************************
+// External Crates
+use actix_web::web;
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+
+// Internal Modules
+use crate::audit::audit_event::{AuditAction, AuditEvent};
+use crate::audit::audit_trail::record_audit;
+use crate::config::settings::StorageSettings;
+use crate::publishers::publisher::Publisher;
+use crate::services::service_dead_letter::{republish, DeadLetterRecord, FailedStage};
+use crate::services::service_ingest_queue::IngestQueue;
+use crate::services::service_ingest_spool::SpoolEntry;
+use crate::storage::exam_storage::{ExamStorage, StorageError};
+use crate::utils::api_error::ApiError;
+use crate::utils::publish_backlog::{BacklogState, PUBLISH_BACKLOG};
+use crate::utils::reason_code::ReasonCode;
+
+// Constants ***************************************************************************************
+/// Items listed when the filter sets no limit
+const DEFAULT_LIST_LIMIT: usize = 100;
+/// Most items listed, or acted on by one bulk request
+const MAX_ITEMS: usize = 500;
+
+// Structs *****************************************************************************************
+/// Store of the exams that left the normal pipeline
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum QueueStore {
+    /// Exams whose storage or publish kept failing: DEAD_LETTER_DIR of this instance, and
+    /// DEAD_LETTER_BUCKET of the storage backend
+    DeadLetter,
+    /// Two-phase exams waiting for their confirmation, and exams spilled at shutdown
+    Spool,
+}
+
+impl QueueStore {
+    /// Stable snake_case name, as in the paths and audit records
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            QueueStore::DeadLetter => "dead_letter",
+            QueueStore::Spool => "spool",
+        }
+    }
+}
+
+/// Filters of a listing - an item matches every filter that is set
+/// # Arguments
+/// * `exam_type` - The exam type key
+/// * `hospital_id` - The hospital that sent the exam
+/// * `stage` - The failed stage (dead letters only)
+/// * `reason` - The reason code of the failure (dead letters only)
+/// * `before` - Only items failed (dead letters) or received (spool) before this instant
+/// * `limit` - Most items returned (default 100, at most 500)
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ItemFilter {
+    pub exam_type: Option<String>,
+    pub hospital_id: Option<String>,
+    pub stage: Option<FailedStage>,
+    pub reason: Option<ReasonCode>,
+    pub before: Option<DateTime<Utc>>,
+    pub limit: Option<usize>,
+}
+
+impl ItemFilter {
+    /// Whether an item matches the filter
+    fn matches(&self, item: &QueueItem) -> bool {
+        self.exam_type.as_ref().is_none_or(|t| *t == item.exam_type)
+            && self
+                .hospital_id
+                .as_ref()
+                .is_none_or(|h| *h == item.hospital_id)
+            && self.stage.is_none_or(|stage| item.stage == Some(stage))
+            && self.reason.is_none_or(|reason| item.reason == Some(reason))
+            && self.before.is_none_or(|before| item.at < before)
+    }
+
+    /// Longest dead-letter name prefix covering the filter - records are named
+    /// `<stage>/<exam_type>/<hospital_id>/...`
+    fn prefix(&self) -> String {
+        let mut prefix = String::new();
+        let segments = [
+            self.stage.map(|stage| stage.as_str().to_string()),
+            self.exam_type.clone(),
+            self.hospital_id.clone(),
+        ];
+        for segment in segments {
+            match segment {
+                Some(segment) if is_plain_segment(&segment) => {
+                    prefix.push_str(&segment);
+                    prefix.push('/');
+                }
+                _ => break,
+            }
+        }
+        prefix
+    }
+
+    /// Number of items returned
+    fn limit(&self) -> usize {
+        self.limit.unwrap_or(DEFAULT_LIST_LIMIT).clamp(1, MAX_ITEMS)
+    }
+}
+
+/// Items a bulk action applies to: explicit ids, or the items of a listing
+/// # Arguments
+/// * `ids` - The item ids, as listed
+/// * `filter` - The filter of a listing, when no ids are given
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Selection {
+    #[serde(default)]
+    pub ids: Vec<String>,
+    pub filter: Option<ItemFilter>,
+}
+
+/// Metadata of the payload of an item - never its values, which hold patient data
+/// # Arguments
+/// * `size_bytes` - The size of the serialized payload
+/// * `sha256` - The hex SHA256 of the serialized payload
+/// * `fields` - The top-level fields of the payload
+/// * `attributes` - The names of the notification attributes (publish dead letters)
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PayloadSummary {
+    pub size_bytes: u64,
+    pub sha256: String,
+    pub fields: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub attributes: Vec<String>,
+}
+
+/// Exam held in a store, as shown to the operators
+/// # Arguments
+/// * `store` - The store holding the exam
+/// * `id` - The item id: `local/<name>` or `bucket/<name>` for dead letters, the file id for the
+///   spool
+/// * `at` - When the exam was dead-lettered, or received for the spool
+/// * `stage`, `reason`, `error` - The failure of a dead letter
+/// * `location` - Where the item is kept
+/// * `payload` - The metadata of its payload
+#[derive(Debug, Clone, Serialize)]
+pub struct QueueItem {
+    pub store: QueueStore,
+    pub id: String,
+    pub exam_type: String,
+    pub exam_id: String,
+    pub hospital_id: String,
+    pub at: DateTime<Utc>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stage: Option<FailedStage>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<ReasonCode>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    pub location: String,
+    pub payload: PayloadSummary,
+}
+
+/// Outcome of a bulk action on one item
+/// # Arguments
+/// * `outcome` - `requeued`, `discarded`, `not_found`, `refused` (the action does not apply to the
+///   item) or `failed` (the item is kept, `detail` tells why)
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ItemOutcome {
+    pub id: String,
+    pub outcome: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub exam_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+}
+
+/// Exams held out of the normal pipeline
+/// # Arguments
+/// * `dead_letter_local` - Dead letters spilled to DEAD_LETTER_DIR of this instance
+/// * `dead_letter_bucket` - The dead-letter bucket also administered, if any
+/// * `spool` - Exams in the spool of this instance
+/// * `ingest_in_flight` - Exams queued or being processed by this instance
+/// * `outbox_in_flight`, `outbox_deferred` - Notifications of this instance waiting for their ack,
+///   or for downstream to recover - they are held in memory, not in a store
+#[derive(Debug, Clone, Serialize)]
+pub struct QueueSummary {
+    pub dead_letter_local: usize,
+    pub dead_letter_bucket: Option<String>,
+    pub spool: usize,
+    pub ingest_in_flight: usize,
+    pub outbox_in_flight: u64,
+    pub outbox_deferred: u64,
+}
+
+/// Operator access to the dead letters and the spool: listing, peek, requeue and discard - every
+/// requeue and discard is audited
+pub struct QueueAdmin {
+    dead_letter_dir: PathBuf,
+    dead_letter_bucket: Option<String>,
+    storage: Arc<dyn ExamStorage>,
+    publisher: Arc<dyn Publisher>,
+    ingest_queue: Option<Arc<IngestQueue>>,
+}
+
+impl QueueAdmin {
+    /// Administer DEAD_LETTER_DIR, DEAD_LETTER_BUCKET and the ingest spool
+    /// # Arguments
+    /// * `settings` - The storage settings, locating the dead letters
+    /// * `storage` - The exam storage backend, holding the dead-letter bucket
+    /// * `publisher` - The notification backend, publishing the requeued dead letters
+    /// * `ingest_queue` - The ingest queue, holding the spool
+    pub fn new(
+        settings: &StorageSettings,
+        storage: &Arc<dyn ExamStorage>,
+        publisher: Arc<dyn Publisher>,
+        ingest_queue: Arc<IngestQueue>,
+    ) -> Self {
+        Self {
+            ingest_queue: Some(ingest_queue),
+            ..Self::dead_letters_only(settings, storage, publisher)
+        }
+    }
+
+    /// Administer DEAD_LETTER_DIR and DEAD_LETTER_BUCKET only - for the operator CLI, which runs no
+    /// ingest queue: the spool of an instance is only administered through its API
+    /// # Arguments
+    /// * `settings` - The storage settings, locating the dead letters
+    /// * `storage` - The exam storage backend, holding the dead-letter bucket
+    /// * `publisher` - The notification backend, publishing the requeued dead letters
+    pub fn dead_letters_only(
+        settings: &StorageSettings,
+        storage: &Arc<dyn ExamStorage>,
+        publisher: Arc<dyn Publisher>,
+    ) -> Self {
+        Self {
+            dead_letter_dir: PathBuf::from(&settings.dead_letter_dir),
+            dead_letter_bucket: settings.dead_letter_bucket.clone(),
+            storage: storage.clone(),
+            publisher,
+            ingest_queue: None,
+        }
+    }
+
+    /// Number of exams held by each store of this instance
+    pub async fn summary(&self) -> QueueSummary {
+        let dir = self.dead_letter_dir.clone();
+        let dead_letter_local = web::block(move || local_record_names(&dir, "").len())
+            .await
+            .unwrap_or_default();
+        let (spool, ingest_in_flight) = match &self.ingest_queue {
+            Some(queue) => (queue.spool().entries().await.len(), queue.in_flight()),
+            None => (0, 0),
+        };
+        QueueSummary {
+            dead_letter_local,
+            dead_letter_bucket: self.dead_letter_bucket.clone(),
+            spool,
+            ingest_in_flight,
+            outbox_in_flight: PUBLISH_BACKLOG.count(BacklogState::InFlight),
+            outbox_deferred: PUBLISH_BACKLOG.count(BacklogState::Deferred),
+        }
+    }
+
+    /// Items of a store matching a filter, oldest first
+    /// # Arguments
+    /// * `store` - The store
+    /// * `filter` - The filter of the listing
+    /// # Errors
+    /// * Returns an error if the dead-letter bucket cannot be listed
+    pub async fn list(&self, store: QueueStore, filter: &ItemFilter) -> Result<Vec<QueueItem>> {
+        let mut items = match store {
+            QueueStore::Spool => self
+                .spool_entries()
+                .await
+                .iter()
+                .map(|entry| self.spool_item(entry))
+                .filter(|item| filter.matches(item))
+                .take(filter.limit())
+                .collect(),
+            QueueStore::DeadLetter => self.dead_letters(filter).await?,
+        };
+        items.sort_by_key(|item| item.at);
+        Ok(items)
+    }
+
+    /// One item of a store
+    /// # Arguments
+    /// * `store` - The store
+    /// * `id` - The item id, as listed
+    /// # Returns
+    /// * The item, or None if the store does not hold it
+    /// # Errors
+    /// * Returns an error if the dead-letter bucket cannot be read
+    pub async fn peek(&self, store: QueueStore, id: &str) -> Result<Option<QueueItem>> {
+        match store {
+            QueueStore::Spool => Ok(self.spool_entry(id).await.map(|e| self.spool_item(&e))),
+            QueueStore::DeadLetter => Ok(self.read_record(id).await?.map(|(_, item)| item)),
+        }
+    }
+
+    /// Ids of the items a bulk action applies to
+    /// # Arguments
+    /// * `store` - The store
+    /// * `selection` - Explicit ids, or a filter
+    /// # Errors
+    /// * Returns BadRequest unless exactly one of ids and filter is given, or for more than 500
+    ///   ids, and StorageFailure if the listing failed
+    pub async fn select(
+        &self,
+        store: QueueStore,
+        selection: &Selection,
+    ) -> Result<Vec<String>, ApiError> {
+        match (&selection.filter, selection.ids.len()) {
+            (None, 1..=MAX_ITEMS) => Ok(selection.ids.clone()),
+            (Some(filter), 0) => {
+                let items = self.list(store, filter).await.map_err(|e| {
+                    warn!("Listing of the {} store failed: {e}", store.as_str());
+                    ApiError::StorageFailure
+                })?;
+                Ok(items.into_iter().map(|item| item.id).collect())
+            }
+            _ => Err(ApiError::BadRequest(format!(
+                "Select the items with either 1 to {MAX_ITEMS} ids or a filter"
+            ))),
+        }
+    }
+
+    /// Queue items again: spooled exams go back to the ingest queue, publish dead letters are
+    /// published again then removed - storage dead letters are refused, the exam was not stored
+    /// # Arguments
+    /// * `store` - The store
+    /// * `ids` - The item ids
+    /// # Returns
+    /// * The outcome of each item
+    pub async fn requeue(&self, store: QueueStore, ids: &[String]) -> Vec<ItemOutcome> {
+        let mut outcomes = Vec::with_capacity(ids.len());
+        for id in ids {
+            let outcome = match store {
+                QueueStore::Spool => self.requeue_spooled(id).await,
+                QueueStore::DeadLetter => self.requeue_dead_letter(id).await,
+            };
+            outcomes.push(outcome);
+        }
+        outcomes
+    }
+
+    /// Remove items for good - a spooled exam being processed is refused
+    /// # Arguments
+    /// * `store` - The store
+    /// * `ids` - The item ids
+    /// # Returns
+    /// * The outcome of each item
+    pub async fn discard(&self, store: QueueStore, ids: &[String]) -> Vec<ItemOutcome> {
+        let mut outcomes = Vec::with_capacity(ids.len());
+        for id in ids {
+            let outcome = match store {
+                QueueStore::Spool => self.discard_spooled(id).await,
+                QueueStore::DeadLetter => self.discard_dead_letter(id).await,
+            };
+            outcomes.push(outcome);
+        }
+        outcomes
+    }
+
+    /// Queue a spooled exam again, unless this instance is already processing it
+    async fn requeue_spooled(&self, id: &str) -> ItemOutcome {
+        let (Some(queue), Some(entry)) = (&self.ingest_queue, self.spool_entry(id).await) else {
+            return outcome(id, "not_found", None, None);
+        };
+        let job = entry.job;
+        let exam_id = Some(job.exam_id.to_string());
+        if queue.is_active(&job.exam_id).await {
+            let detail = Some("Already queued or being processed".to_string());
+            return outcome(id, "refused", exam_id, detail);
+        }
+        let hospital_id = job.hospital_id.clone();
+        match queue.requeue(job).await {
+            Ok(()) => {
+                let exam = exam_id.as_deref().unwrap_or_default();
+                audit(
+                    AuditAction::ExamRequeued,
+                    QueueStore::Spool,
+                    "ecg_exam",
+                    exam,
+                    &hospital_id,
+                );
+                outcome(id, "requeued", exam_id, None)
+            }
+            Err(e) => outcome(id, "failed", exam_id, Some(e.to_string())),
+        }
+    }
+
+    /// Publish a dead letter again and remove it
+    async fn requeue_dead_letter(&self, id: &str) -> ItemOutcome {
+        let record = match self.read_record(id).await {
+            Ok(Some((record, _))) => record,
+            Ok(None) => return outcome(id, "not_found", None, None),
+            Err(e) => return outcome(id, "failed", None, Some(e.to_string())),
+        };
+        let exam_id = Some(record.exam_id.clone());
+        if record.stage != FailedStage::Publish {
+            let detail = Some("The exam was not stored - replay it from the record".to_string());
+            return outcome(id, "refused", exam_id, detail);
+        }
+        if let Err(e) = republish(&record, &self.publisher).await {
+            return outcome(id, "failed", exam_id, Some(e.to_string()));
+        }
+        audit(
+            AuditAction::ExamRequeued,
+            QueueStore::DeadLetter,
+            &record.exam_type,
+            &record.exam_id,
+            &record.hospital_id,
+        );
+        // Published: a record left behind would publish the exam twice if requeued again
+        match self.remove_record(id).await {
+            Ok(_) => outcome(id, "requeued", exam_id, None),
+            Err(e) => {
+                let detail = Some(format!("Published, but the record was not removed: {e}"));
+                outcome(id, "requeued", exam_id, detail)
+            }
+        }
+    }
+
+    /// Remove a spooled exam, unless this instance is processing it
+    async fn discard_spooled(&self, id: &str) -> ItemOutcome {
+        let (Some(queue), Some(entry)) = (&self.ingest_queue, self.spool_entry(id).await) else {
+            return outcome(id, "not_found", None, None);
+        };
+        let exam_id = Some(entry.job.exam_id.to_string());
+        if queue.is_active(&entry.job.exam_id).await {
+            let detail = Some("Queued or being processed".to_string());
+            return outcome(id, "refused", exam_id, detail);
+        }
+        match queue.spool().discard(id).await {
+            Ok(true) => {
+                audit(
+                    AuditAction::ExamDiscarded,
+                    QueueStore::Spool,
+                    "ecg_exam",
+                    &entry.job.exam_id,
+                    &entry.job.hospital_id,
+                );
+                outcome(id, "discarded", exam_id, None)
+            }
+            Ok(false) => outcome(id, "not_found", exam_id, None),
+            Err(e) => outcome(id, "failed", exam_id, Some(e.to_string())),
+        }
+    }
+
+    /// Remove a dead letter
+    async fn discard_dead_letter(&self, id: &str) -> ItemOutcome {
+        let record = match self.read_record(id).await {
+            Ok(Some((record, _))) => record,
+            Ok(None) => return outcome(id, "not_found", None, None),
+            Err(e) => return outcome(id, "failed", None, Some(e.to_string())),
+        };
+        let exam_id = Some(record.exam_id.clone());
+        match self.remove_record(id).await {
+            Ok(true) => {
+                audit(
+                    AuditAction::ExamDiscarded,
+                    QueueStore::DeadLetter,
+                    &record.exam_type,
+                    &record.exam_id,
+                    &record.hospital_id,
+                );
+                outcome(id, "discarded", exam_id, None)
+            }
+            Ok(false) => outcome(id, "not_found", exam_id, None),
+            Err(e) => outcome(id, "failed", exam_id, Some(e.to_string())),
+        }
+    }
+
+    /// Dead letters matching a filter: the local ones, then those of the bucket
+    async fn dead_letters(&self, filter: &ItemFilter) -> Result<Vec<QueueItem>> {
+        let limit = filter.limit();
+        let prefix = filter.prefix();
+
+        // STEP 1: Local spill directory
+        let dir = self.dead_letter_dir.clone();
+        let local_prefix = prefix.clone();
+        let local = web::block(move || {
+            local_record_names(&dir, &local_prefix)
+                .into_iter()
+                .filter_map(|name| Some((std::fs::read(dir.join(&name)).ok()?, name)))
+                .collect::<Vec<_>>()
+        })
+        .await
+        .map_err(|e| anyhow!("Dead-letter listing could not be scheduled: {e}"))?;
+        let mut items: Vec<QueueItem> = local
+            .into_iter()
+            .filter_map(|(bytes, name)| {
+                let location = self.dead_letter_dir.join(&name).display().to_string();
+                record_item(&format!("local/{name}"), location, &bytes).map(|(_, item)| item)
+            })
+            .filter(|item| filter.matches(item))
+            .take(limit)
+            .collect();
+
+        // STEP 2: Dead-letter bucket, one page at a time until the limit
+        let Some(bucket) = &self.dead_letter_bucket else {
+            return Ok(items);
+        };
+        let mut page_token = None;
+        while items.len() < limit {
+            let page = self
+                .storage
+                .list_objects(bucket, &prefix, page_token.take())
+                .await?;
+            for object in page.objects {
+                if items.len() >= limit {
+                    break;
+                }
+                let id = format!("bucket/{}", object.name);
+                if let Some((_, item)) = self.read_record(&id).await? {
+                    if filter.matches(&item) {
+                        items.push(item);
+                    }
+                }
+            }
+            page_token = page.next_page_token;
+            if page_token.is_none() {
+                break;
+            }
+        }
+        Ok(items)
+    }
+
+    /// Read a dead letter by its id
+    /// # Returns
+    /// * The record and its item, or None if it does not exist or cannot be parsed
+    async fn read_record(&self, id: &str) -> Result<Option<(DeadLetterRecord, QueueItem)>> {
+        let bytes = match record_source(id) {
+            Some(("local", name)) => {
+                let path = self.dead_letter_dir.join(name);
+                let bytes = web::block(move || std::fs::read(path))
+                    .await
+                    .map_err(|e| anyhow!("Dead-letter read could not be scheduled: {e}"))?;
+                match bytes {
+                    Ok(bytes) => {
+                        Some((bytes, self.dead_letter_dir.join(name).display().to_string()))
+                    }
+                    Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+                    Err(e) => return Err(e.into()),
+                }
+            }
+            Some(("bucket", name)) => match &self.dead_letter_bucket {
+                Some(bucket) => match self.storage.get_object(bucket, name).await {
+                    Ok(bytes) => Some((bytes, self.storage.location(bucket, name))),
+                    Err(e) if StorageError::is_not_found(&e) => None,
+                    Err(e) => return Err(e),
+                },
+                None => None,
+            },
+            _ => None,
+        };
+        Ok(bytes.and_then(|(bytes, location)| record_item(id, location, &bytes)))
+    }
+
+    /// Remove a dead letter by its id
+    /// # Returns
+    /// * Whether the record existed
+    async fn remove_record(&self, id: &str) -> Result<bool> {
+        match record_source(id) {
+            Some(("local", name)) => {
+                let path = self.dead_letter_dir.join(name);
+                let removed = web::block(move || match std::fs::remove_file(&path) {
+                    Ok(()) => Ok(true),
+                    Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(false),
+                    Err(e) => Err(e),
+                })
+                .await
+                .map_err(|e| anyhow!("Dead-letter removal could not be scheduled: {e}"))??;
+                Ok(removed)
+            }
+            Some(("bucket", name)) => match &self.dead_letter_bucket {
+                Some(bucket) => match self.storage.delete_object(bucket, name, None).await {
+                    Ok(()) => Ok(true),
+                    Err(e) if StorageError::is_not_found(&e) => Ok(false),
+                    Err(e) => Err(e),
+                },
+                None => Ok(false),
+            },
+            _ => Ok(false),
+        }
+    }
+
+    /// Spooled exams, none without an ingest queue
+    async fn spool_entries(&self) -> Vec<SpoolEntry> {
+        match &self.ingest_queue {
+            Some(queue) => queue.spool().entries().await,
+            None => Vec::new(),
+        }
+    }
+
+    /// Spooled exam of a file id
+    async fn spool_entry(&self, id: &str) -> Option<SpoolEntry> {
+        self.spool_entries()
+            .await
+            .into_iter()
+            .find(|entry| entry.id == id)
+    }
+
+    /// Item of a spooled exam
+    fn spool_item(&self, entry: &SpoolEntry) -> QueueItem {
+        let payload = serde_json::to_value(&entry.job.data).unwrap_or_default();
+        QueueItem {
+            store: QueueStore::Spool,
+            id: entry.id.clone(),
+            exam_type: "ecg_exam".to_string(),
+            exam_id: entry.job.exam_id.to_string(),
+            hospital_id: entry.job.hospital_id.clone(),
+            at: entry.job.received_at,
+            stage: None,
+            reason: None,
+            error: None,
+            location: format!("spool/{}.json", entry.id),
+            payload: summarize(&payload, Vec::new()),
+        }
+    }
+}
+
+// SUPPORT FUNCTIONS *******************************************************************************
+/// Item of a dead-letter record
+/// # Returns
+/// * The record and its item, or None if the bytes are not a record
+fn record_item(id: &str, location: String, bytes: &[u8]) -> Option<(DeadLetterRecord, QueueItem)> {
+    let record: DeadLetterRecord = serde_json::from_slice(bytes)
+        .map_err(|e| warn!("Unreadable dead letter {location}: {e}"))
+        .ok()?;
+    let mut attributes: Vec<String> = record.attributes.keys().cloned().collect();
+    attributes.sort_unstable();
+    let item = QueueItem {
+        store: QueueStore::DeadLetter,
+        id: id.to_string(),
+        exam_type: record.exam_type.clone(),
+        exam_id: record.exam_id.clone(),
+        hospital_id: record.hospital_id.clone(),
+        at: record.failed_at,
+        stage: Some(record.stage),
+        reason: Some(record.reason),
+        error: Some(record.error.clone()),
+        location,
+        payload: summarize(&record.payload, attributes),
+    };
+    Some((record, item))
+}
+
+/// Metadata of a payload: size, hash and top-level fields
+fn summarize(payload: &serde_json::Value, attributes: Vec<String>) -> PayloadSummary {
+    let serialized = payload.to_string();
+    let mut fields: Vec<String> = payload
+        .as_object()
+        .map(|object| object.keys().cloned().collect())
+        .unwrap_or_default();
+    fields.sort_unstable();
+    PayloadSummary {
+        size_bytes: serialized.len() as u64,
+        sha256: format!("{:x}", Sha256::digest(serialized.as_bytes())),
+        fields,
+        attributes,
+    }
+}
+
+/// Source and name of a dead-letter id (`local/<name>` or `bucket/<name>`) - names leaving the
+/// store (absolute, `..`) are refused
+fn record_source(id: &str) -> Option<(&str, &str)> {
+    let (source, name) = id.split_once('/')?;
+    let relative = Path::new(name)
+        .components()
+        .all(|component| matches!(component, Component::Normal(_)));
+    (relative && name.ends_with(".json")).then_some((source, name))
+}
+
+/// Whether a filter value can be used as one segment of a record name
+fn is_plain_segment(segment: &str) -> bool {
+    !segment.is_empty() && segment != "." && segment != ".." && !segment.contains(['/', '\\'])
+}
+
+/// Names of the dead-letter records under a prefix of the spill directory, relative to it
+fn local_record_names(dir: &Path, prefix: &str) -> Vec<String> {
+    let mut names = Vec::new();
+    let mut pending = vec![dir.join(prefix)];
+    while let Some(current) = pending.pop() {
+        let Ok(entries) = std::fs::read_dir(&current) else {
+            continue;
+        };
+        for path in entries.flatten().map(|entry| entry.path()) {
+            if path.is_dir() {
+                pending.push(path);
+            } else if path.extension().is_some_and(|ext| ext == "json") {
+                if let Ok(name) = path.strip_prefix(dir) {
+                    names.push(name.to_string_lossy().replace('\\', "/"));
+                }
+            }
+        }
+    }
+    names.sort_unstable();
+    names
+}
+
+/// Outcome of an action on one item
+fn outcome(
+    id: &str,
+    outcome: &'static str,
+    exam_id: Option<String>,
+    detail: Option<String>,
+) -> ItemOutcome {
+    ItemOutcome {
+        id: id.to_string(),
+        outcome,
+        exam_id,
+        detail,
+    }
+}
+
+/// Audit an operator action on an exam
+fn audit(
+    action: AuditAction,
+    store: QueueStore,
+    exam_type: &str,
+    exam_id: &str,
+    hospital_id: &str,
+) {
+    info!(target: "audit", "{} store={} exam_type={exam_type} exam_id={exam_id} hospital_id={hospital_id}",
+        action.as_str(), store.as_str());
+    record_audit(AuditEvent::exam(action, exam_type, exam_id, hospital_id));
+}
+
+// TESTS *******************************************************************************************
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn record(stage: FailedStage, hospital_id: &str) -> DeadLetterRecord {
+        DeadLetterRecord {
+            exam_type: "ecg_exam".to_string(),
+            exam_id: format!("ecg_exam/{hospital_id}/p/2026-10-01T120000.000Z"),
+            hospital_id: hospital_id.to_string(),
+            stage,
+            error: "pubsub.publish failed (Timeout): timed out after 30s".to_string(),
+            reason: ReasonCode::PubSubTimeout,
+            failed_at: "2026-10-01T12:00:00Z".parse().unwrap(),
+            payload: serde_json::json!({"patient_id": "p", "exam_id": "e"}),
+            attributes: HashMap::from([("consent_scope".to_string(), "research".to_string())]),
+        }
+    }
+
+    // Happy path: records are listed from their names, with the metadata of their payload only
+    #[test]
+    fn local_records_listed() {
+        let dir = std::env::temp_dir().join(format!("sentinela_dl_admin_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        for record in [
+            record(FailedStage::Publish, "h1"),
+            record(FailedStage::Storage, "h2"),
+        ] {
+            let path = dir.join(record.file_name());
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(&path, serde_json::to_vec(&record).unwrap()).unwrap();
+        }
+        let names = local_record_names(&dir, "");
+        assert_eq!(names.len(), 2);
+        assert!(names[0].starts_with("publish/ecg_exam/h1/"));
+        assert_eq!(local_record_names(&dir, "storage/ecg_exam/h2/").len(), 1);
+        assert!(local_record_names(&dir, "storage/xray_exam/").is_empty());
+
+        let bytes = std::fs::read(dir.join(&names[0])).unwrap();
+        let (_, item) = record_item(&format!("local/{}", names[0]), String::new(), &bytes).unwrap();
+        assert_eq!(item.stage, Some(FailedStage::Publish));
+        assert_eq!(item.payload.fields, ["exam_id", "patient_id"]);
+        assert_eq!(item.payload.attributes, ["consent_scope"]);
+        let json = serde_json::to_string(&item).unwrap();
+        assert!(!json.contains("\"p\""));
+    }
+
+    // Borderline: filters narrow the name prefix as far as they go, and match every field
+    #[test]
+    fn filters_and_prefixes() {
+        let filter = ItemFilter {
+            stage: Some(FailedStage::Publish),
+            hospital_id: Some("h1".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(filter.prefix(), "publish/");
+        let filter = ItemFilter {
+            exam_type: Some("ecg_exam".to_string()),
+            ..filter
+        };
+        assert_eq!(filter.prefix(), "publish/ecg_exam/h1/");
+        let escaping = ItemFilter {
+            stage: Some(FailedStage::Storage),
+            exam_type: Some("..".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(escaping.prefix(), "storage/");
+
+        let bytes = serde_json::to_vec(&record(FailedStage::Publish, "h1")).unwrap();
+        let (_, item) = record_item("local/x.json", String::new(), &bytes).unwrap();
+        assert!(filter.matches(&item));
+        let before = ItemFilter {
+            before: Some("2026-10-01T12:00:00Z".parse().unwrap()),
+            ..Default::default()
+        };
+        assert!(!before.matches(&item));
+        let reason = ItemFilter {
+            reason: Some(ReasonCode::GcsError),
+            ..Default::default()
+        };
+        assert!(!reason.matches(&item));
+        assert_eq!(ItemFilter::default().limit(), DEFAULT_LIST_LIMIT);
+    }
+
+    // Error handling: ids naming a file out of the store are refused
+    #[test]
+    fn record_ids_stay_in_store() {
+        assert_eq!(
+            record_source("local/publish/ecg_exam/h/p/t/1.json"),
+            Some(("local", "publish/ecg_exam/h/p/t/1.json"))
+        );
+        for refused in [
+            "local/../secrets.json",
+            "local//etc/passwd.json",
+            "bucket/publish/x.txt",
+            "nothing",
+        ] {
+            assert_eq!(record_source(refused), None, "{refused}");
+        }
+    }
+}
@@ -0,0 +1,247 @@
+// Imports *****************************************************************************************
+// External Crates
+use chrono::{DateTime, Utc};
+use log::{info, warn};
+use serde::Serialize;
+use sqlx::PgPool;
+use std::future::Future;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+
+// Internal Modules
+use crate::publishers::publisher::Publisher;
+use crate::storage::exam_storage::{ExamStorage, ObjectPut};
+use crate::utils::external_call::{Dependency, ExternalCall};
+
+// Constants ***************************************************************************************
+/// Object written by the storage check, overwritten by every probe
+const PROBE_OBJECT: &str = "_readiness/probe";
+/// How long a probe is reused, so frequent load balancer checks do not load the dependencies
+const PROBE_CACHE_TTL: Duration = Duration::from_secs(5);
+
+// Structs *****************************************************************************************
+/// Outcome of the check of one dependency
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CheckStatus {
+    /// The dependency answered
+    Up,
+    /// The dependency failed or timed out
+    Down,
+    /// The deployment does not use the dependency - never blocks readiness
+    NotConfigured,
+}
+
+/// Check of one dependency
+/// # Arguments
+/// * `dependency` - The dependency, e.g. `storage`
+/// * `status` - The outcome
+/// * `latency_ms` - How long the check took
+/// * `error` - The error of a failed check
+#[derive(Debug, Clone, Serialize)]
+pub struct DependencyCheck {
+    pub dependency: &'static str,
+    pub status: CheckStatus,
+    pub latency_ms: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Checks of every dependency the instance needs to accept exams
+/// # Arguments
+/// * `ready` - Whether no dependency is down
+/// * `checked_at` - When the checks ran
+/// * `checks` - The check of each dependency
+#[derive(Debug, Clone, Serialize)]
+pub struct DependencyReport {
+    pub ready: bool,
+    pub checked_at: DateTime<Utc>,
+    pub checks: Vec<DependencyCheck>,
+}
+
+/// Readiness probe of the dependencies: exam storage (GCS, S3 or local), the notification
+/// backend (every routed Pub/Sub topic) and Postgres - Redis only shares the rate limit windows,
+/// which are counted per instance while it is unreachable, so it is reported as not configured
+/// and never blocks readiness
+pub struct ReadinessProbe {
+    storage: Arc<dyn ExamStorage>,
+    publisher: Arc<dyn Publisher>,
+    db_pool: PgPool,
+    bucket: String,
+    last: Mutex<Option<(Instant, DependencyReport)>>,
+}
+
+impl ReadinessProbe {
+    /// Create the probe
+    /// # Arguments
+    /// * `storage` - The exam storage backend
+    /// * `publisher` - The notification backend
+    /// * `db_pool` - The Postgres pool
+    /// * `bucket` - The bucket of the exams, where the storage check writes its object
+    pub fn new(
+        storage: Arc<dyn ExamStorage>,
+        publisher: Arc<dyn Publisher>,
+        db_pool: PgPool,
+        bucket: String,
+    ) -> Self {
+        Self {
+            storage,
+            publisher,
+            db_pool,
+            bucket,
+            last: Mutex::new(None),
+        }
+    }
+
+    /// Check the dependencies - a report younger than PROBE_CACHE_TTL is reused
+    /// # Returns
+    /// * The report of the dependencies
+    pub async fn check(&self) -> DependencyReport {
+        // The lock is held while checking: concurrent probes share one round of checks
+        let mut last = self.last.lock().await;
+        if let Some((at, report)) = last.as_ref() {
+            if at.elapsed() < PROBE_CACHE_TTL {
+                return report.clone();
+            }
+        }
+        let report = self.run().await;
+        *last = Some((Instant::now(), report.clone()));
+        report
+    }
+
+    /// Check the dependencies at startup and log the outcome - a dependency down is not fatal,
+    /// readiness stays 503 until it recovers
+    pub async fn check_at_startup(&self) {
+        let report = self.check().await;
+        let statuses: Vec<String> = report
+            .checks
+            .iter()
+            .map(|check| format!("{}={:?}", check.dependency, check.status))
+            .collect();
+        info!(
+            "Readiness at startup: ready={} ({})",
+            report.ready,
+            statuses.join(", ")
+        );
+    }
+
+    /// Run every check concurrently
+    async fn run(&self) -> DependencyReport {
+        let object = ObjectPut {
+            bucket: &self.bucket,
+            name: PROBE_OBJECT,
+            content_type: Some("text/plain"),
+            operation: "readiness_probe",
+            retries: 0,
+        };
+        let postgres_call = ExternalCall::new(Dependency::Postgres, "readiness_probe");
+        let (storage, publisher, postgres) = tokio::join!(
+            timed("storage", self.storage.put_object(&object, b"ok".to_vec())),
+            timed("pubsub", self.publisher.probe()),
+            timed(
+                "postgres",
+                postgres_call.run(|| sqlx::query("SELECT 1").execute(&self.db_pool)),
+            ),
+        );
+        let redis = DependencyCheck {
+            dependency: "redis",
+            status: CheckStatus::NotConfigured,
+            latency_ms: 0,
+            error: None,
+        };
+        let checks = vec![storage, publisher, postgres, redis];
+        for check in checks.iter().filter(|c| c.status == CheckStatus::Down) {
+            warn!(
+                "Readiness: {} is down: {}",
+                check.dependency,
+                check.error.as_deref().unwrap_or_default()
+            );
+        }
+        DependencyReport {
+            ready: checks.iter().all(|c| c.status != CheckStatus::Down),
+            checked_at: Utc::now(),
+            checks,
+        }
+    }
+}
+
+// SUPPORT FUNCTIONS *******************************************************************************
+/// Run the check of a dependency and time it
+/// # Arguments
+/// * `dependency` - The dependency name
+/// * `check` - The check, failing with the error to report
+async fn timed<T>(
+    dependency: &'static str,
+    check: impl Future<Output = anyhow::Result<T>>,
+) -> DependencyCheck {
+    let started = Instant::now();
+    let result = check.await;
+    let latency_ms = started.elapsed().as_millis() as u64;
+    match result {
+        Ok(_) => DependencyCheck {
+            dependency,
+            status: CheckStatus::Up,
+            latency_ms,
+            error: None,
+        },
+        Err(e) => DependencyCheck {
+            dependency,
+            status: CheckStatus::Down,
+            latency_ms,
+            error: Some(e.to_string()),
+        },
+    }
+}
+
+// TESTS *******************************************************************************************
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::publishers::publisher_log::LogPublisher;
+    use crate::storage::storage_local::LocalStorage;
+    use std::collections::HashMap;
+
+    pub(crate) fn probe(name: &str) -> ReadinessProbe {
+        let dir =
+            std::env::temp_dir().join(format!("sentinela_readiness_{name}_{}", std::process::id()));
+        let db_pool = sqlx::postgres::PgPoolOptions::new()
+            .acquire_timeout(Duration::from_millis(100))
+            .connect_lazy("postgres://invalid")
+            .unwrap();
+        ReadinessProbe::new(
+            Arc::new(LocalStorage::new(&dir.to_string_lossy())),
+            Arc::new(LogPublisher::new(HashMap::new(), None)),
+            db_pool,
+            "bucket".to_string(),
+        )
+    }
+
+    // Error handling: an unreachable database fails readiness, the other dependencies still report
+    #[tokio::test]
+    async fn database_down_not_ready() {
+        let report = probe("db_down").check().await;
+        assert!(!report.ready);
+        let status = |name: &str| {
+            report
+                .checks
+                .iter()
+                .find(|check| check.dependency == name)
+                .unwrap()
+                .status
+        };
+        assert_eq!(status("storage"), CheckStatus::Up);
+        assert_eq!(status("pubsub"), CheckStatus::Up);
+        assert_eq!(status("postgres"), CheckStatus::Down);
+        assert_eq!(status("redis"), CheckStatus::NotConfigured);
+    }
+
+    // Borderline: a recent report is reused rather than checked again
+    #[tokio::test]
+    async fn report_cached() {
+        let probe = probe("cached");
+        let first = probe.check().await;
+        let second = probe.check().await;
+        assert_eq!(first.checked_at, second.checked_at);
+    }
+}
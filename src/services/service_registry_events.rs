@@ -0,0 +1,62 @@
+// Imports *****************************************************************************************
+// External Crates
+use log::{info, warn};
+use sqlx::postgres::PgListener;
+use sqlx::PgPool;
+use std::time::Duration;
+
+// Internal Modules
+use crate::authentication::auth::{forget_hospital, forget_validated_credentials};
+use crate::telemetry::metrics::record_registry_invalidation;
+
+// Constants ***************************************************************************************
+/// Postgres channel notified by the registry triggers, with the changed hospital id as payload
+const REGISTRY_CHANNEL: &str = "hospital_registry";
+/// Wait before listening again after the listener failed
+const LISTEN_RETRY_DELAY: Duration = Duration::from_secs(5);
+
+// MAIN FUNCTIONS **********************************************************************************
+/// Listen to the changes of the hospital registry (Postgres LISTEN/NOTIFY) and forget the cached
+/// entries of the changed hospitals, so admin changes reach every instance within seconds
+/// - While the listener is down, the cached entries still expire after AUTH_CACHE_TTL_S
+/// # Arguments
+/// * `pool` - The shared database connection pool
+pub fn start_registry_listener(pool: PgPool) {
+    actix_web::rt::spawn(async move {
+        loop {
+            if let Err(e) = listen(&pool).await {
+                warn!("Hospital registry listener failed, retrying: {e}");
+            }
+            // Notifications may have been missed while the listener was down
+            forget_validated_credentials();
+            record_registry_invalidation("reconnect");
+            tokio::time::sleep(LISTEN_RETRY_DELAY).await;
+        }
+    });
+}
+
+// SUPPORTING FUNCTIONS ****************************************************************************
+/// Forget the hospitals notified on the registry channel, until the connection fails
+/// # Arguments
+/// * `pool` - The shared database connection pool
+/// # Errors
+/// * Returns an error once the listening connection fails or is lost
+async fn listen(pool: &PgPool) -> sqlx::Result<()> {
+    let mut listener = PgListener::connect_with(pool).await?;
+    listener.listen(REGISTRY_CHANNEL).await?;
+    info!("Hospital registry: listening to {REGISTRY_CHANNEL}");
+    // try_recv returns None when the connection is lost - the loop forgets everything, then
+    // listens again
+    while let Some(notification) = listener.try_recv().await? {
+        let hospital_id = notification.payload();
+        if hospital_id.is_empty() {
+            continue;
+        }
+        forget_hospital(hospital_id);
+        record_registry_invalidation("notification");
+    }
+    Ok(())
+}
+
+// TESTS *******************************************************************************************
+// DOCUMENTATION: Thin wrapper over the Postgres listener - forget_hospital is tested in auth
@@ -0,0 +1,243 @@
+// Imports *****************************************************************************************
+// External Crates
+use chrono::{DateTime, Utc};
+use log::{info, warn};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+use std::time::Duration;
+
+// Internal Modules
+use crate::authentication::gcp_identity::GcpIdentity;
+use crate::config::settings::EventSettings;
+use crate::models::models_ids::canonical_hex;
+use crate::publishers::publisher::PubSubClients;
+use crate::services::service_hospital_groups::group_of;
+use crate::sinks::event_sink::sink_of;
+use crate::telemetry::metrics::record_exam_rejected;
+use crate::utils::reason_code::ReasonCode;
+
+// Constants ***************************************************************************************
+/// Request ids kept per hospital and exam type in one window
+const MAX_SAMPLE_REQUEST_IDS: usize = 5;
+/// Distinct (hospital, exam type) pairs tracked per window - unauthenticated callers can send any
+/// hospital id, further pairs are counted under OVERFLOW_HOSPITAL
+const MAX_TRACKED_PAIRS: usize = 1_000;
+const OVERFLOW_HOSPITAL: &str = "other";
+
+// Structs *****************************************************************************************
+/// Rejections of one hospital for one exam type over one window
+/// # Arguments
+/// * `kind` - Always `rejection_digest`
+/// * `window_start` / `window_end` - The aggregation window
+/// * `hospital_id` - The hospital id as sent by the caller
+/// * `group_id` - The hospital group of a clinic, to roll up the digests per group
+/// * `exam_type` - The exam type key
+/// * `total` - Rejections in the window
+/// * `reason_counts` - Rejections per ReasonCode, as labelled in the metrics and responses
+/// * `sample_request_ids` - The first request ids of the window, to find the requests in the logs
+/// * `summary` - One-line human-readable description
+#[derive(Debug, Clone, Serialize)]
+pub struct RejectionDigest {
+    pub kind: &'static str,
+    pub window_start: DateTime<Utc>,
+    pub window_end: DateTime<Utc>,
+    pub hospital_id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub group_id: Option<String>,
+    pub exam_type: String,
+    pub total: u64,
+    pub reason_counts: BTreeMap<&'static str, u64>,
+    pub sample_request_ids: Vec<String>,
+    pub summary: String,
+}
+
+/// Rejections accumulated for one (hospital, exam type) in the current window
+#[derive(Debug, Default)]
+struct RejectionWindow {
+    reason_counts: BTreeMap<&'static str, u64>,
+    sample_request_ids: Vec<String>,
+}
+
+/// Rejections of the current window and when it started
+struct DigestState {
+    window_start: Option<DateTime<Utc>>,
+    windows: BTreeMap<(String, &'static str), RejectionWindow>,
+}
+
+// Global variables ********************************************************************************
+static DIGEST_STATE: Mutex<DigestState> = Mutex::new(DigestState {
+    window_start: None,
+    windows: BTreeMap::new(),
+});
+
+// MAIN FUNCTIONS **********************************************************************************
+/// Record a refused exam for the next digest
+/// # Arguments
+/// * `hospital_id` - The hospital id sent by the caller (possibly unauthenticated)
+/// * `exam_type` - The exam type key
+/// * `reason` - Why the exam was refused, as told to the hospital
+/// * `request_id` - The id of the request
+pub fn record_rejection(
+    hospital_id: &str,
+    exam_type: &'static str,
+    reason: ReasonCode,
+    request_id: &str,
+) {
+    record_exam_rejected(exam_type, reason);
+    if let Ok(mut state) = DIGEST_STATE.lock() {
+        state.window_start.get_or_insert_with(Utc::now);
+        let mut key = (hospital_id.to_string(), exam_type);
+        if !state.windows.contains_key(&key) && state.windows.len() >= MAX_TRACKED_PAIRS {
+            key.0 = OVERFLOW_HOSPITAL.to_string();
+        }
+        let window = state.windows.entry(key).or_default();
+        *window.reason_counts.entry(reason.as_str()).or_insert(0) += 1;
+        if window.sample_request_ids.len() < MAX_SAMPLE_REQUEST_IDS {
+            window.sample_request_ids.push(request_id.to_string());
+        }
+    }
+}
+
+/// Publish the rejection digests every REJECTION_DIGEST_INTERVAL_S seconds to
+/// REJECTION_DIGEST_SINK (`pubsub` default on REJECTION_DIGEST_TOPIC, `bigquery` into
+/// REJECTION_DIGEST_BIGQUERY_TABLE, or `log`)
+/// # Arguments
+/// * `events` - The event sinks of the settings
+/// * `pubsub` - The Pub/Sub clients used by the `pubsub` sink
+/// * `identity` - The identity of the service, used by the `bigquery` sink
+pub async fn run_rejection_digest(
+    events: EventSettings,
+    pubsub: PubSubClients,
+    identity: GcpIdentity,
+) {
+    let sink = sink_of(&events.rejection_digest, &pubsub, &identity, "rejections");
+    let interval = events.rejection_digest_interval_s;
+    info!(
+        "Rejection digests every {interval}s to sink: {}",
+        sink.name()
+    );
+
+    loop {
+        tokio::time::sleep(Duration::from_secs(interval)).await;
+        for digest in take_digests(Utc::now()) {
+            let Ok(event) = serde_json::to_value(&digest) else {
+                continue;
+            };
+            if let Err(e) = sink.emit(&event).await {
+                warn!(target: "rejections", "Rejection digest not delivered ({e}): {event}");
+            }
+        }
+    }
+}
+
+// SUPPORT FUNCTIONS *******************************************************************************
+/// Close the current window and return its digests
+/// # Arguments
+/// * `window_end` - When the window closes
+fn take_digests(window_end: DateTime<Utc>) -> Vec<RejectionDigest> {
+    let Ok(mut state) = DIGEST_STATE.lock() else {
+        return Vec::new();
+    };
+    let window_start = state.window_start.take().unwrap_or(window_end);
+    std::mem::take(&mut state.windows)
+        .into_iter()
+        .map(|((hospital_id, exam_type), window)| {
+            let total = window.reason_counts.values().sum();
+            let reasons = window
+                .reason_counts
+                .iter()
+                .map(|(reason, count)| format!("{reason}={count}"))
+                .collect::<Vec<_>>()
+                .join(", ");
+            RejectionDigest {
+                kind: "rejection_digest",
+                window_start,
+                window_end,
+                summary: format!(
+                    "{total} {exam_type} rejected for hospital {hospital_id} between {} and {}: {reasons}",
+                    window_start.format("%H:%M"),
+                    window_end.format("%H:%M UTC")
+                ),
+                group_id: group_of(&canonical_hex(&hospital_id)),
+                hospital_id,
+                exam_type: exam_type.to_string(),
+                total,
+                reason_counts: window.reason_counts,
+                sample_request_ids: window.sample_request_ids,
+            }
+        })
+        .collect()
+}
+
+// TESTS *******************************************************************************************
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Happy path: rejections are aggregated per hospital and reason, then the window is reset
+    #[test]
+    fn digest_aggregates_and_resets() {
+        for i in 0..7 {
+            record_rejection(
+                "digest-h1",
+                "ecg_exam",
+                ReasonCode::LeadLength,
+                &format!("r{i}"),
+            );
+        }
+        record_rejection("digest-h1", "ecg_exam", ReasonCode::AuthBadKey, "r7");
+
+        let digests = take_digests(Utc::now());
+        let digest = digests
+            .iter()
+            .find(|d| d.hospital_id == "digest-h1")
+            .expect("digest of digest-h1");
+        assert_eq!(digest.total, 8);
+        assert_eq!(digest.reason_counts["LEAD_LENGTH"], 7);
+        assert_eq!(digest.reason_counts["AUTH_BAD_KEY"], 1);
+        assert_eq!(digest.sample_request_ids.len(), MAX_SAMPLE_REQUEST_IDS);
+        assert!(digest.summary.contains("AUTH_BAD_KEY=1, LEAD_LENGTH=7"));
+
+        assert!(take_digests(Utc::now())
+            .iter()
+            .all(|d| d.hospital_id != "digest-h1"));
+    }
+
+    // Borderline: the digest read by the data-quality dashboards keeps its shape
+    #[test]
+    fn digest_contract() {
+        let digest = RejectionDigest {
+            kind: "rejection_digest",
+            window_start: "2026-10-01T12:00:00Z".parse().unwrap(),
+            window_end: "2026-10-01T13:00:00Z".parse().unwrap(),
+            hospital_id: "h1".to_string(),
+            group_id: Some("g1".to_string()),
+            exam_type: "ecg_exam".to_string(),
+            total: 3,
+            reason_counts: BTreeMap::from([("LEAD_LENGTH", 2), ("AMPLITUDE", 1)]),
+            sample_request_ids: vec!["req-1".to_string(), "req-2".to_string()],
+            summary: "3 ecg_exam rejections".to_string(),
+        };
+        insta::assert_json_snapshot!(digest, @r#"
+        {
+          "kind": "rejection_digest",
+          "window_start": "2026-10-01T12:00:00Z",
+          "window_end": "2026-10-01T13:00:00Z",
+          "hospital_id": "h1",
+          "group_id": "g1",
+          "exam_type": "ecg_exam",
+          "total": 3,
+          "reason_counts": {
+            "AMPLITUDE": 1,
+            "LEAD_LENGTH": 2
+          },
+          "sample_request_ids": [
+            "req-1",
+            "req-2"
+          ],
+          "summary": "3 ecg_exam rejections"
+        }
+        "#);
+    }
+}
@@ -0,0 +1,387 @@
+// Imports *****************************************************************************************
+// External Crates
+use anyhow::{anyhow, Result};
+use chrono::{NaiveDate, Utc};
+use log::{info, warn};
+use polars::io::json::JsonReader;
+use polars::io::parquet::{ParquetWriter, ZstdLevel};
+use polars::prelude::*;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::io::Cursor;
+use std::sync::{Arc, Mutex, OnceLock};
+
+// Internal Modules
+use crate::config::settings::Secret;
+use crate::models::models_consent::ConsentScope;
+use crate::storage::exam_storage::{ExamStorage, ObjectPut};
+use crate::telemetry::trace_context::in_current_trace;
+
+// Constants ***************************************************************************************
+/// Fields identifying the hospital, the patient or the exam in the clinical bucket - dropped from
+/// the samples, the ids are replaced by research pseudonyms
+const IDENTIFYING_FIELDS: [&str; 10] = [
+    "hospital_key",
+    "exam_id",
+    "image_object",
+    "timestamp",
+    "topic",
+    "sop_instance_uid",
+    "study_instance_uid",
+    "series_instance_uid",
+    "acquisition_datetime",
+    "deidentification_method",
+];
+
+// Types *******************************************************************************************
+/// Day of the counts and the samples taken per hospital and exam type that day
+type DailySamples = (NaiveDate, HashMap<(String, &'static str), u32>);
+
+// Structs *****************************************************************************************
+/// Opt-in mirror of accepted exams into a research bucket (RESEARCH_SAMPLE_BUCKET,
+/// RESEARCH_SAMPLE_PERCENT, RESEARCH_SAMPLE_HALF_LIFE, RESEARCH_SAMPLE_SALT)
+/// # Arguments
+/// * `bucket` - The research bucket, distinct from the ingest bucket
+/// * `percent` - The share of the exams sampled while a hospital has few samples of the day
+/// * `half_life` - The samples of one hospital and exam type per day after which the rate halves,
+///   so the largest hospitals do not crowd out the others
+/// * `salt` - The secret of the research pseudonyms, so samples cannot be joined back to the
+///   clinical bucket
+#[derive(Debug, Clone, PartialEq)]
+pub struct SampleSettings {
+    pub bucket: String,
+    pub percent: f64,
+    pub half_life: u32,
+    pub salt: Secret,
+}
+
+impl SampleSettings {
+    /// Probability of sampling the next exam of a hospital and exam type - RESEARCH_SAMPLE_PERCENT,
+    /// halved for every RESEARCH_SAMPLE_HALF_LIFE samples already taken that day
+    /// # Arguments
+    /// * `taken` - The samples of the hospital and exam type taken that day
+    pub fn probability(&self, taken: u32) -> f64 {
+        let decay = 0.5_f64.powf(f64::from(taken) / f64::from(self.half_life));
+        self.percent / 100.0 * decay
+    }
+
+    /// Research pseudonym of an id - a salted SHA256, unrelated to the id of the clinical bucket
+    /// # Arguments
+    /// * `id` - The hospital or patient id
+    pub fn pseudonym(&self, id: &str) -> String {
+        let digest = Sha256::new()
+            .chain_update(self.salt.expose().as_bytes())
+            .chain_update(b":")
+            .chain_update(id.as_bytes())
+            .finalize();
+        format!("{digest:x}")
+    }
+
+    /// Uniform draw in [0, 1) of an exam - derived from its id, so a decision can be replayed from
+    /// the audit trail
+    /// # Arguments
+    /// * `exam_id` - The id of the exam
+    fn draw(&self, exam_id: &str) -> f64 {
+        let digest = Sha256::new()
+            .chain_update(b"draw:")
+            .chain_update(self.salt.expose().as_bytes())
+            .chain_update(exam_id.as_bytes())
+            .finalize();
+        let mut bytes = [0u8; 8];
+        bytes.copy_from_slice(&digest[..8]);
+        (u64::from_be_bytes(bytes) >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+/// Accepted exam offered to the research mirror
+/// # Arguments
+/// * `exam_type` - The exam type key
+/// * `exam_id` - The id of the exam in the clinical bucket
+/// * `hospital_id` - The hospital that sent the exam
+/// * `patient_id` - The patient of the exam
+/// * `consent_scope` - The data-sharing consent of the hospital - only research consents are
+///   sampled
+/// * `image` - The stored image and its extension, for imaging exams
+#[derive(Debug, Clone)]
+pub struct ResearchSample {
+    pub exam_type: &'static str,
+    pub exam_id: String,
+    pub hospital_id: String,
+    pub patient_id: String,
+    pub consent_scope: ConsentScope,
+    pub image: Option<(Vec<u8>, String)>,
+}
+
+// Global variables ********************************************************************************
+/// Sampling settings, set once at startup when RESEARCH_SAMPLE_BUCKET is set
+static SAMPLE_SETTINGS: OnceLock<SampleSettings> = OnceLock::new();
+
+/// Samples taken per hospital and exam type on the current day
+static SAMPLES_TAKEN: Mutex<Option<DailySamples>> = Mutex::new(None);
+
+// MAIN FUNCTIONS **********************************************************************************
+/// Enable the research mirror - called once at startup
+/// # Arguments
+/// * `settings` - The sampling settings
+pub fn init_research_sampling(settings: SampleSettings) {
+    info!(
+        "Research sampling of {}% of the consented exams into gs://{} (half-life {})",
+        settings.percent, settings.bucket, settings.half_life
+    );
+    if SAMPLE_SETTINGS.set(settings).is_err() {
+        warn!("Research sampling settings already initialized");
+    }
+}
+
+/// Offer an accepted exam to the research mirror - a sampled exam is de-identified and copied in
+/// the background, never delaying nor failing the exam
+/// # Arguments
+/// * `sample` - The accepted exam
+/// * `record` - Builds the stored record of the exam (Parquet row or sidecar), only called if the
+///   exam is sampled
+/// * `storage` - The exam storage backend, holding the research bucket
+pub fn offer_research_sample(
+    sample: ResearchSample,
+    record: impl FnOnce() -> Result<serde_json::Value>,
+    storage: &Arc<dyn ExamStorage>,
+) {
+    let Some(settings) = SAMPLE_SETTINGS.get() else {
+        return;
+    };
+    if !sample.consent_scope.allows(ConsentScope::Research) {
+        return;
+    }
+    if !take_sample(settings, &sample) {
+        return;
+    }
+    let record = match record() {
+        Ok(record) => record,
+        Err(e) => {
+            warn!(target: "audit", "research_sample failed exam_type={} exam_id={} hospital_id={} error={e}", sample.exam_type, sample.exam_id, sample.hospital_id);
+            return;
+        }
+    };
+    let storage = storage.clone();
+    actix_web::rt::spawn(in_current_trace(async move {
+        let research_id = research_exam_id(settings, &sample);
+        match store_sample(settings, &sample, &record, &research_id, storage.as_ref()).await {
+            Ok(()) => {
+                info!(target: "audit", "research_sample stored exam_type={} exam_id={} hospital_id={} sample={}", sample.exam_type, sample.exam_id, sample.hospital_id, storage.location(&settings.bucket, &research_id))
+            }
+            Err(e) => {
+                warn!(target: "audit", "research_sample failed exam_type={} exam_id={} hospital_id={} error={e}", sample.exam_type, sample.exam_id, sample.hospital_id)
+            }
+        }
+    }));
+}
+
+/// De-identify the record of a sampled exam - identifying fields are dropped, the hospital and
+/// patient ids replaced by their research pseudonyms and the exam kept only to its month
+/// # Arguments
+/// * `settings` - The sampling settings
+/// * `record` - The stored record of the exam
+/// * `month` - The month the exam was received, `YYYY-MM`
+/// # Returns
+/// * The record written to the research bucket
+/// # Errors
+/// * Returns an error if the record is not a JSON object
+pub fn deidentify(
+    settings: &SampleSettings,
+    record: &serde_json::Value,
+    month: &str,
+) -> Result<serde_json::Value> {
+    let mut record = record
+        .as_object()
+        .cloned()
+        .ok_or_else(|| anyhow!("Sampled record is not an object"))?;
+    for field in IDENTIFYING_FIELDS {
+        record.remove(field);
+    }
+    for field in ["hospital_id", "patient_id"] {
+        if let Some(id) = record.get(field).and_then(|v| v.as_str()) {
+            let pseudonym = settings.pseudonym(id);
+            record.insert(field.to_string(), pseudonym.into());
+        }
+    }
+    record.insert("sampled_month".to_string(), month.into());
+    Ok(serde_json::Value::Object(record))
+}
+
+// SUPPORT FUNCTIONS *******************************************************************************
+/// Decide whether an exam is sampled, counting it against its hospital and exam type for the day
+/// # Arguments
+/// * `settings` - The sampling settings
+/// * `sample` - The accepted exam
+fn take_sample(settings: &SampleSettings, sample: &ResearchSample) -> bool {
+    let Ok(mut taken) = SAMPLES_TAKEN.lock() else {
+        return false;
+    };
+    let today = Utc::now().date_naive();
+    if taken.as_ref().is_none_or(|(day, _)| *day != today) {
+        *taken = Some((today, HashMap::new()));
+    }
+    let Some((_, counts)) = taken.as_mut() else {
+        return false;
+    };
+    let count = counts
+        .entry((sample.hospital_id.clone(), sample.exam_type))
+        .or_default();
+    if settings.draw(&sample.exam_id) >= settings.probability(*count) {
+        return false;
+    }
+    *count += 1;
+    true
+}
+
+/// Object name of a sample in the research bucket, without extension - built from pseudonyms only
+/// # Arguments
+/// * `settings` - The sampling settings
+/// * `sample` - The sampled exam
+fn research_exam_id(settings: &SampleSettings, sample: &ResearchSample) -> String {
+    format!(
+        "{}/{}/{}/{}",
+        sample.exam_type,
+        settings.pseudonym(&sample.hospital_id),
+        settings.pseudonym(&sample.patient_id),
+        &settings.pseudonym(&sample.exam_id)[..16]
+    )
+}
+
+/// De-identify a sampled exam and write it to the research bucket - the image first, then its
+/// record as Parquet
+/// # Arguments
+/// * `settings` - The sampling settings
+/// * `sample` - The sampled exam
+/// * `record` - The stored record of the exam
+/// * `research_id` - The object name of the sample, without extension
+/// * `storage` - The exam storage backend, holding the research bucket
+/// # Errors
+/// * Returns an error if the record cannot be converted or an object cannot be uploaded
+async fn store_sample(
+    settings: &SampleSettings,
+    sample: &ResearchSample,
+    record: &serde_json::Value,
+    research_id: &str,
+    storage: &dyn ExamStorage,
+) -> Result<()> {
+    // STEP 1: Upload the image, named after the sample
+    let mut record = deidentify(settings, record, &Utc::now().format("%Y-%m").to_string())?;
+    if let Some((image, extension)) = &sample.image {
+        let image_object = storage.object_name(research_id, extension);
+        let content_type = format!("image/{extension}");
+        let object = ObjectPut {
+            bucket: &settings.bucket,
+            name: &image_object,
+            content_type: Some(&content_type),
+            operation: "upload_research_sample",
+            retries: 1,
+        };
+        storage.put_object(&object, image.clone()).await?;
+        record["image_object"] = image_object.into();
+    }
+
+    // STEP 2: Convert the record to Parquet and upload it
+    let json = serde_json::to_string(&vec![record])?;
+    let mut df = JsonReader::new(Cursor::new(json))
+        .infer_schema_len(None)
+        .finish()?;
+    let mut buffer = Vec::new();
+    ParquetWriter::new(&mut buffer)
+        .with_compression(ParquetCompression::Zstd(Some(ZstdLevel::try_new(1)?)))
+        .finish(&mut df)?;
+    let object_name = storage.object_name(research_id, "parquet");
+    let object = ObjectPut {
+        bucket: &settings.bucket,
+        name: &object_name,
+        content_type: None,
+        operation: "upload_research_sample",
+        retries: 1,
+    };
+    storage.put_object(&object, buffer).await?;
+    Ok(())
+}
+
+// TESTS *******************************************************************************************
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn settings(percent: f64) -> SampleSettings {
+        SampleSettings {
+            bucket: "research-samples".to_string(),
+            percent,
+            half_life: 10,
+            salt: Secret("research-salt".to_string()),
+        }
+    }
+
+    // Happy path: the rate starts at the configured percentage and halves every half-life
+    #[test]
+    fn probability_decays_exponentially() {
+        let settings = settings(5.0);
+        assert!((settings.probability(0) - 0.05).abs() < 1e-12);
+        assert!((settings.probability(10) - 0.025).abs() < 1e-12);
+        assert!((settings.probability(30) - 0.00625).abs() < 1e-12);
+        assert_eq!(self::settings(0.0).probability(0), 0.0);
+    }
+
+    // Happy path: ids are pseudonymized, identifying fields dropped, the signal kept
+    #[test]
+    fn record_deidentified() {
+        let settings = settings(5.0);
+        let record = json!({
+            "exam_type": "ECG Exam",
+            "exam_id": "ecg_exam/h/p/2026-10-18T101010.5Z",
+            "timestamp": "2026-10-18T101010.5Z",
+            "hospital_id": "hospital-1",
+            "patient_id": "patient-1",
+            "hospital_key": "secret",
+            "study_instance_uid": "1.2.3",
+            "consent_scope": "research",
+            "lead_i": [0.5, 0.0],
+        });
+        let sample = deidentify(&settings, &record, "2026-10").unwrap();
+        for field in ["exam_id", "timestamp", "hospital_key", "study_instance_uid"] {
+            assert!(sample.get(field).is_none(), "{field} kept");
+        }
+        assert_eq!(sample["hospital_id"], settings.pseudonym("hospital-1"));
+        assert_eq!(sample["patient_id"], settings.pseudonym("patient-1"));
+        assert_ne!(sample["patient_id"], "patient-1");
+        assert_eq!(sample["sampled_month"], "2026-10");
+        assert_eq!(sample["lead_i"], json!([0.5, 0.0]));
+        assert!(deidentify(&settings, &json!([1, 2]), "2026-10").is_err());
+    }
+
+    // Borderline: pseudonyms depend on the salt, and the object name leaks no clinical id
+    #[test]
+    fn pseudonyms_salted() {
+        let other = SampleSettings {
+            salt: Secret("another-salt".to_string()),
+            ..settings(5.0)
+        };
+        assert_ne!(settings(5.0).pseudonym("p"), other.pseudonym("p"));
+        let sample = ResearchSample {
+            exam_type: "ecg_exam",
+            exam_id: "ecg_exam/hospital-1/patient-1/ts".to_string(),
+            hospital_id: "hospital-1".to_string(),
+            patient_id: "patient-1".to_string(),
+            consent_scope: ConsentScope::Research,
+            image: None,
+        };
+        let research_id = research_exam_id(&settings(5.0), &sample);
+        assert!(research_id.starts_with("ecg_exam/"));
+        assert!(!research_id.contains("hospital-1") && !research_id.contains("patient-1"));
+    }
+
+    // Borderline: draws are uniform enough that the rate is honoured over many exams
+    #[test]
+    fn draws_follow_rate() {
+        let settings = settings(10.0);
+        let sampled = (0..10_000)
+            .filter(|i| settings.draw(&format!("exam-{i}")) < settings.probability(0))
+            .count();
+        assert!((800..1200).contains(&sampled), "{sampled} sampled");
+        assert_eq!(settings.draw("exam-1"), settings.draw("exam-1"));
+    }
+}
@@ -0,0 +1,346 @@
+// Imports *****************************************************************************************
+// External Crates
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+// Internal Modules
+use crate::services::service_scan::{ScanVerdict, Scanner};
+use crate::utils::external_call::{Dependency, ExternalCall};
+
+// Constants ***************************************************************************************
+/// Bytes sent per chunk to the scanner - the payload is streamed, never sent in one write
+const SCAN_CHUNK_BYTES: usize = 64 * 1024;
+/// Largest reply read from the scanner
+const SCAN_REPLY_MAX_BYTES: usize = 16 * 1024;
+/// ICAP service path when SCAN_ICAP_URL has none
+const DEFAULT_ICAP_SERVICE: &str = "avscan";
+
+// Structs *****************************************************************************************
+/// ClamAV daemon reached over TCP (SCAN_BACKEND=clamd, SCAN_CLAMD_ADDRESS), using INSTREAM
+/// # Arguments
+/// * `address` - The `host:port` of clamd
+/// * `timeout` - The timeout of one scan
+pub struct ClamdScanner {
+    address: String,
+    timeout: Duration,
+}
+
+impl ClamdScanner {
+    /// Create a scanner for the clamd at `address`
+    pub fn new(address: String, timeout: Duration) -> Self {
+        Self { address, timeout }
+    }
+}
+
+#[async_trait]
+impl Scanner for ClamdScanner {
+    fn name(&self) -> &'static str {
+        "clamd"
+    }
+
+    async fn scan(&self, payload: &[u8]) -> Result<ScanVerdict> {
+        let reply = ExternalCall::new(Dependency::Scanner, "clamd_instream")
+            .timeout(self.timeout)
+            .run(|| async {
+                let mut stream = TcpStream::connect(&self.address)
+                    .await
+                    .map_err(|e| e.to_string())?;
+                clamd_instream(&mut stream, payload)
+                    .await
+                    .map_err(|e| e.to_string())
+            })
+            .await?;
+        parse_clamd_reply(&reply)
+    }
+}
+
+/// ICAP server (SCAN_BACKEND=icap, SCAN_ICAP_URL `icap://host:port/service`), the payload sent as
+/// the body of a RESPMOD request
+/// # Arguments
+/// * `address` - The `host:port` of the server
+/// * `service` - The ICAP service path, e.g. `avscan`
+/// * `timeout` - The timeout of one scan
+pub struct IcapScanner {
+    address: String,
+    service: String,
+    timeout: Duration,
+}
+
+impl IcapScanner {
+    /// Create a scanner for an `icap://host:port/service` URL
+    /// # Errors
+    /// * Returns an error if the URL is not an ICAP URL
+    pub fn new(url: &str, timeout: Duration) -> Result<Self> {
+        let rest = url
+            .strip_prefix("icap://")
+            .ok_or_else(|| anyhow!("Invalid SCAN_ICAP_URL: {url} (icap://host:port/service)"))?;
+        let (address, service) = rest.split_once('/').unwrap_or((rest, DEFAULT_ICAP_SERVICE));
+        if address.is_empty() {
+            return Err(anyhow!(
+                "Invalid SCAN_ICAP_URL: {url} (icap://host:port/service)"
+            ));
+        }
+        let address = if address.contains(':') {
+            address.to_string()
+        } else {
+            format!("{address}:1344")
+        };
+        Ok(Self {
+            address,
+            service: service.to_string(),
+            timeout,
+        })
+    }
+}
+
+#[async_trait]
+impl Scanner for IcapScanner {
+    fn name(&self) -> &'static str {
+        "icap"
+    }
+
+    async fn scan(&self, payload: &[u8]) -> Result<ScanVerdict> {
+        let reply = ExternalCall::new(Dependency::Scanner, "icap_respmod")
+            .timeout(self.timeout)
+            .run(|| async {
+                let mut stream = TcpStream::connect(&self.address)
+                    .await
+                    .map_err(|e| e.to_string())?;
+                icap_respmod(&mut stream, &self.address, &self.service, payload)
+                    .await
+                    .map_err(|e| e.to_string())
+            })
+            .await?;
+        parse_icap_reply(&reply)
+    }
+}
+
+// SUPPORT FUNCTIONS *******************************************************************************
+/// Stream a payload to clamd with INSTREAM: length-prefixed chunks, closed by an empty chunk
+/// # Arguments
+/// * `stream` - The connection to clamd
+/// * `payload` - The bytes to scan
+/// # Returns
+/// * The reply of clamd, e.g. `stream: OK`
+async fn clamd_instream<S>(stream: &mut S, payload: &[u8]) -> std::io::Result<String>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    stream.write_all(b"zINSTREAM\0").await?;
+    for chunk in payload.chunks(SCAN_CHUNK_BYTES) {
+        stream
+            .write_all(&(chunk.len() as u32).to_be_bytes())
+            .await?;
+        stream.write_all(chunk).await?;
+    }
+    stream.write_all(&0u32.to_be_bytes()).await?;
+    stream.flush().await?;
+    let reply = read_reply(stream, b"\0").await?;
+    Ok(reply.trim_end_matches('\0').trim().to_string())
+}
+
+/// Verdict of a clamd reply - `stream: OK`, `stream: <signature> FOUND` or `... ERROR`
+/// # Errors
+/// * Returns an error for any reply that is not a verdict (size limit exceeded, scan error)
+fn parse_clamd_reply(reply: &str) -> Result<ScanVerdict> {
+    let result = reply.strip_prefix("stream:").unwrap_or(reply).trim();
+    if result == "OK" {
+        Ok(ScanVerdict::Clean)
+    } else if let Some(signature) = result.strip_suffix("FOUND") {
+        Ok(ScanVerdict::Infected(signature.trim().to_string()))
+    } else {
+        Err(anyhow!("clamd gave no verdict: {reply}"))
+    }
+}
+
+/// Send a payload to an ICAP server as the body of a RESPMOD request, in chunked encoding
+/// # Arguments
+/// * `stream` - The connection to the server
+/// * `address` - The `host:port` of the server
+/// * `service` - The ICAP service path
+/// * `payload` - The bytes to scan
+/// # Returns
+/// * The status line and headers of the ICAP reply
+async fn icap_respmod<S>(
+    stream: &mut S,
+    address: &str,
+    service: &str,
+    payload: &[u8],
+) -> std::io::Result<String>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let http_headers = format!(
+        "HTTP/1.1 200 OK\r\nContent-Type: application/octet-stream\r\nContent-Length: {}\r\n\r\n",
+        payload.len()
+    );
+    let icap_headers = format!(
+        "RESPMOD icap://{address}/{service} ICAP/1.0\r\nHost: {address}\r\nAllow: 204\r\n\
+         Encapsulated: res-hdr=0, res-body={}\r\n\r\n",
+        http_headers.len()
+    );
+    stream.write_all(icap_headers.as_bytes()).await?;
+    stream.write_all(http_headers.as_bytes()).await?;
+    for chunk in payload.chunks(SCAN_CHUNK_BYTES) {
+        stream
+            .write_all(format!("{:x}\r\n", chunk.len()).as_bytes())
+            .await?;
+        stream.write_all(chunk).await?;
+        stream.write_all(b"\r\n").await?;
+    }
+    stream.write_all(b"0\r\n\r\n").await?;
+    stream.flush().await?;
+    read_reply(stream, b"\r\n\r\n").await
+}
+
+/// Verdict of an ICAP reply - `204` is clean; `200` means the server replaced the content, the
+/// signature is read from `X-Infection-Found`, `X-Virus-ID` or `X-Violations-Found`
+/// # Errors
+/// * Returns an error for any other status
+fn parse_icap_reply(reply: &str) -> Result<ScanVerdict> {
+    let mut lines = reply.lines();
+    let status = lines
+        .next()
+        .and_then(|line| line.split_whitespace().nth(1))
+        .ok_or_else(|| anyhow!("ICAP gave no status"))?;
+    match status {
+        "204" => Ok(ScanVerdict::Clean),
+        "200" => {
+            let signature = lines
+                .filter_map(|line| line.split_once(':'))
+                .find(|(name, _)| {
+                    ["x-infection-found", "x-virus-id", "x-violations-found"]
+                        .contains(&name.trim().to_ascii_lowercase().as_str())
+                })
+                .map(|(_, value)| icap_signature(value.trim()))
+                .unwrap_or_else(|| "unknown".to_string());
+            Ok(ScanVerdict::Infected(signature))
+        }
+        other => Err(anyhow!("ICAP answered {other}")),
+    }
+}
+
+/// Signature of an ICAP infection header - `Threat=<name>;` when present, the whole value otherwise
+fn icap_signature(value: &str) -> String {
+    value
+        .split(';')
+        .find_map(|part| part.trim().strip_prefix("Threat="))
+        .unwrap_or(value)
+        .trim()
+        .to_string()
+}
+
+/// Read a reply up to its terminator, or until the connection is closed
+/// # Errors
+/// * Returns an error if the reply exceeds SCAN_REPLY_MAX_BYTES
+async fn read_reply<S>(stream: &mut S, terminator: &[u8]) -> std::io::Result<String>
+where
+    S: AsyncRead + Unpin,
+{
+    let mut reply = Vec::new();
+    let mut buffer = [0u8; 1024];
+    while !reply.ends_with(terminator) {
+        let read = stream.read(&mut buffer).await?;
+        if read == 0 {
+            break;
+        }
+        reply.extend_from_slice(&buffer[..read]);
+        if reply.len() > SCAN_REPLY_MAX_BYTES {
+            return Err(std::io::Error::other("scanner reply too large"));
+        }
+    }
+    Ok(String::from_utf8_lossy(&reply).into_owned())
+}
+
+// TESTS *******************************************************************************************
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::service_scan::scan_payload;
+    use crate::utils::api_error::ApiError;
+
+    // Happy path: clamd frames the payload in length-prefixed chunks and reports its verdict
+    #[tokio::test]
+    async fn clamd_stream_framed() {
+        let (mut client, mut server) = tokio::io::duplex(1 << 20);
+        let payload = vec![7u8; SCAN_CHUNK_BYTES + 10];
+        let clamd = tokio::spawn(async move {
+            let mut received = Vec::new();
+            let mut buffer = [0u8; 4096];
+            while !received.ends_with(&[0, 0, 0, 0]) {
+                let read = server.read(&mut buffer).await.unwrap();
+                received.extend_from_slice(&buffer[..read]);
+            }
+            server.write_all(b"stream: OK\0").await.unwrap();
+            received
+        });
+        let reply = clamd_instream(&mut client, &payload).await.unwrap();
+        assert_eq!(reply, "stream: OK");
+        let received = clamd.await.unwrap();
+        assert!(received.starts_with(b"zINSTREAM\0"));
+        let first = u32::from_be_bytes(received[10..14].try_into().unwrap());
+        assert_eq!(first as usize, SCAN_CHUNK_BYTES);
+        assert_eq!(received.len(), 10 + 4 + SCAN_CHUNK_BYTES + 4 + 10 + 4);
+    }
+
+    // Happy path: clamd verdicts
+    #[test]
+    fn clamd_replies_parsed() {
+        assert_eq!(parse_clamd_reply("stream: OK").unwrap(), ScanVerdict::Clean);
+        assert_eq!(
+            parse_clamd_reply("stream: Win.Test.EICAR_HDB-1 FOUND").unwrap(),
+            ScanVerdict::Infected("Win.Test.EICAR_HDB-1".to_string())
+        );
+        assert!(parse_clamd_reply("INSTREAM size limit exceeded. ERROR").is_err());
+    }
+
+    // Borderline: ICAP servers name the threat in different headers, or not at all
+    #[test]
+    fn icap_replies_parsed() {
+        assert_eq!(
+            parse_icap_reply("ICAP/1.0 204 No Content\r\nISTag: \"1\"\r\n\r\n").unwrap(),
+            ScanVerdict::Clean
+        );
+        assert_eq!(
+            parse_icap_reply(
+                "ICAP/1.0 200 OK\r\nX-Infection-Found: Type=0; Resolution=2; Threat=Eicar-Test;\r\n\r\n"
+            )
+            .unwrap(),
+            ScanVerdict::Infected("Eicar-Test".to_string())
+        );
+        assert_eq!(
+            parse_icap_reply("ICAP/1.0 200 OK\r\nX-Virus-ID: EICAR\r\n\r\n").unwrap(),
+            ScanVerdict::Infected("EICAR".to_string())
+        );
+        assert_eq!(
+            parse_icap_reply("ICAP/1.0 200 OK\r\n\r\n").unwrap(),
+            ScanVerdict::Infected("unknown".to_string())
+        );
+        assert!(parse_icap_reply("ICAP/1.0 500 Server Error\r\n\r\n").is_err());
+        assert!(parse_icap_reply("").is_err());
+    }
+
+    // Borderline: ICAP URLs default their port and service
+    #[test]
+    fn icap_url_parsed() {
+        let icap = IcapScanner::new("icap://scanner", Duration::from_secs(5)).unwrap();
+        assert_eq!(icap.address, "scanner:1344");
+        assert_eq!(icap.service, DEFAULT_ICAP_SERVICE);
+        let icap = IcapScanner::new("icap://scanner:1345/srv_clamav", Duration::from_secs(5));
+        assert_eq!(icap.unwrap().service, "srv_clamav");
+        assert!(IcapScanner::new("http://scanner", Duration::from_secs(5)).is_err());
+    }
+
+    // Error handling: unreachable scanners fail closed
+    #[tokio::test]
+    async fn unreachable_scanner_fails_closed() {
+        let clamd = ClamdScanner::new("127.0.0.1:1".to_string(), Duration::from_secs(1));
+        assert_eq!(
+            scan_payload(&clamd, b"x", "xray_exam", "h").await,
+            Err(ApiError::ScanUnavailable)
+        );
+    }
+}
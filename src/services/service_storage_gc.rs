@@ -0,0 +1,251 @@
+// Imports *****************************************************************************************
+// External Crates
+use anyhow::{anyhow, Result};
+use log::{info, warn};
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+// Internal Modules
+use crate::config::settings::LifecycleSettings;
+use crate::storage::exam_storage::ExamStorage;
+
+// Structs *****************************************************************************************
+/// Retention rule of one prefix
+/// # Arguments
+/// * `prefix` - The object name prefix, e.g. `staging/`
+/// * `max_age_s` - Objects older than this are removed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RetentionRule {
+    pub prefix: String,
+    pub max_age_s: i64,
+}
+
+/// Deletion metrics of one prefix since startup
+#[derive(Debug, Clone, Default, PartialEq, Eq, serde::Serialize)]
+pub struct GcStats {
+    pub runs: u64,
+    pub scanned: u64,
+    pub expired: u64,
+    pub deleted: u64,
+    pub deleted_bytes: u64,
+    pub failures: u64,
+}
+
+// Global variables ********************************************************************************
+/// GC metrics per prefix
+static GC_STATS: Mutex<BTreeMap<String, GcStats>> = Mutex::new(BTreeMap::new());
+
+// MAIN FUNCTIONS **********************************************************************************
+/// Run the garbage collection of the staging/quarantine prefixes forever
+/// Disabled with GC_ENABLED=false; GC_DRY_RUN=true only reports what would be removed
+/// # Arguments
+/// * `lifecycle` - The retention settings (GC_*)
+/// * `bucket` - The exam bucket
+/// * `storage` - The exam storage backend
+pub async fn run_storage_gc(
+    lifecycle: LifecycleSettings,
+    bucket: String,
+    storage: Arc<dyn ExamStorage>,
+) {
+    if !lifecycle.gc_enabled {
+        info!("GC_ENABLED=false - storage garbage collection disabled");
+        return;
+    }
+    let (rules, dry_run, interval) = (
+        lifecycle.gc_rules,
+        lifecycle.gc_dry_run,
+        lifecycle.gc_interval_s,
+    );
+    info!("Storage garbage collection every {interval}s (dry run: {dry_run}): {rules:?}");
+
+    loop {
+        for rule in &rules {
+            if let Err(e) = collect_prefix(storage.as_ref(), &bucket, rule, dry_run).await {
+                warn!(
+                    "Storage garbage collection of '{}' failed: {e}",
+                    rule.prefix
+                );
+            }
+        }
+        tokio::time::sleep(Duration::from_secs(interval)).await;
+    }
+}
+
+/// Snapshot of the GC metrics, keyed by prefix
+pub fn gc_stats() -> BTreeMap<String, GcStats> {
+    GC_STATS
+        .lock()
+        .map(|stats| stats.clone())
+        .unwrap_or_default()
+}
+
+// SUPPORT FUNCTIONS *******************************************************************************
+/// Remove the expired objects of one prefix
+/// # Arguments
+/// * `storage` - The exam storage backend
+/// * `bucket` - The bucket name
+/// * `rule` - The retention rule of the prefix
+/// * `dry_run` - Only log the objects that would be removed
+/// # Errors
+/// * Returns an error if the listing fails - failed deletions are counted and retried next run
+async fn collect_prefix(
+    storage: &dyn ExamStorage,
+    bucket: &str,
+    rule: &RetentionRule,
+    dry_run: bool,
+) -> Result<()> {
+    let now = chrono::Utc::now().timestamp();
+    let mut stats = GcStats {
+        runs: 1,
+        ..Default::default()
+    };
+    let mut page_token = None;
+    loop {
+        // STEP 1: List one page of the prefix
+        let page = match storage
+            .list_objects(bucket, &rule.prefix, page_token.take())
+            .await
+        {
+            Ok(page) => page,
+            Err(e) => {
+                record_stats(&rule.prefix, &stats);
+                return Err(e);
+            }
+        };
+
+        // STEP 2: Remove (or report) the expired objects
+        for object in page.objects {
+            stats.scanned += 1;
+            let updated_at = object
+                .updated_at
+                .or(object.created_at)
+                .map(|at| at.timestamp());
+            if !is_expired(updated_at, rule.max_age_s, now) {
+                continue;
+            }
+            stats.expired += 1;
+            if dry_run {
+                info!(target: "audit", "storage_gc dry_run bucket={bucket} object={} size={}", object.name, object.size);
+                continue;
+            }
+            // Pinning the generation never removes an object rewritten since the listing
+            match storage
+                .delete_object(bucket, &object.name, object.generation)
+                .await
+            {
+                Ok(()) => {
+                    stats.deleted += 1;
+                    stats.deleted_bytes += object.size;
+                    info!(target: "audit", "storage_gc deleted bucket={bucket} object={} size={}", object.name, object.size);
+                }
+                Err(e) => {
+                    stats.failures += 1;
+                    warn!("storage_gc could not delete {}: {e}", object.name);
+                }
+            }
+        }
+
+        page_token = page.next_page_token;
+        if page_token.is_none() {
+            break;
+        }
+    }
+    info!(
+        "Storage garbage collection of '{}': {} scanned, {} expired, {} deleted",
+        rule.prefix, stats.scanned, stats.expired, stats.deleted
+    );
+    record_stats(&rule.prefix, &stats);
+    Ok(())
+}
+
+/// Whether an object is older than the retention of its prefix - objects of unknown age are kept
+/// # Arguments
+/// * `updated_at` - Unix timestamp (seconds) of the last update of the object
+/// * `max_age_s` - The retention in seconds
+/// * `now` - The current unix timestamp in seconds
+fn is_expired(updated_at: Option<i64>, max_age_s: i64, now: i64) -> bool {
+    updated_at.is_some_and(|at| now - at > max_age_s)
+}
+
+/// Add the result of one run to the metrics of the prefix
+fn record_stats(prefix: &str, run: &GcStats) {
+    if let Ok(mut stats) = GC_STATS.lock() {
+        let total = stats.entry(prefix.to_string()).or_default();
+        total.runs += run.runs;
+        total.scanned += run.scanned;
+        total.expired += run.expired;
+        total.deleted += run.deleted;
+        total.deleted_bytes += run.deleted_bytes;
+        total.failures += run.failures;
+    }
+}
+
+/// Parse retention rules of the form `prefix/=hours,prefix/=hours`
+/// # Arguments
+/// * `raw` - The retention list
+/// # Errors
+/// * Returns an error for malformed entries, empty prefixes or exam prefixes without retention
+pub(crate) fn parse_retention(raw: &str) -> Result<Vec<RetentionRule>> {
+    raw.split(',')
+        .map(str::trim)
+        .filter(|e| !e.is_empty())
+        .map(|entry| {
+            let (prefix, hours) = entry
+                .split_once('=')
+                .ok_or_else(|| anyhow!("Invalid GC retention '{entry}': expected prefix=hours"))?;
+            let hours: i64 = hours
+                .trim()
+                .parse()
+                .map_err(|_| anyhow!("Invalid GC retention hours in '{entry}'"))?;
+            let prefix = prefix.trim();
+            // An empty prefix would sweep the whole bucket, including the stored exams
+            if prefix.is_empty() || hours <= 0 {
+                return Err(anyhow!("Invalid GC retention '{entry}'"));
+            }
+            Ok(RetentionRule {
+                prefix: prefix.to_string(),
+                max_age_s: hours * 3600,
+            })
+        })
+        .collect()
+}
+
+// TESTS *******************************************************************************************
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::settings::DEFAULT_GC_RETENTION_HOURS;
+
+    // Happy path: default table parses to seconds
+    #[test]
+    fn retention_default_table() {
+        let rules = parse_retention(DEFAULT_GC_RETENTION_HOURS).unwrap();
+        assert_eq!(
+            rules[0],
+            RetentionRule {
+                prefix: "staging/".to_string(),
+                max_age_s: 86_400
+            }
+        );
+        assert_eq!(rules.len(), 3);
+    }
+
+    // Error handling: an empty prefix would delete every exam
+    #[test]
+    fn retention_invalid() {
+        assert!(parse_retention("=24").is_err());
+        assert!(parse_retention("staging/").is_err());
+        assert!(parse_retention("staging/=abc").is_err());
+        assert!(parse_retention("staging/=0").is_err());
+    }
+
+    #[test]
+    fn expiry_follows_retention() {
+        let now = 1_000_000;
+        assert!(is_expired(Some(now - 3601), 3600, now));
+        assert!(!is_expired(Some(now - 10), 3600, now));
+        // Unknown age is kept
+        assert!(!is_expired(None, 3600, now));
+    }
+}
@@ -0,0 +1,285 @@
+// Imports *****************************************************************************************
+// External Crates
+use chrono::NaiveDate;
+use log::error;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use utoipa::ToSchema;
+
+// Internal Modules
+use crate::config::settings::Secret;
+use crate::utils::api_error::ApiError;
+use crate::utils::hmac::hmac_sha256;
+
+// Structs *****************************************************************************************
+/// Disclosure policy of the hospital-facing statistics (STATS_MIN_COUNT, STATS_NOISE_EPSILON,
+/// STATS_NOISE_KEY)
+/// # Arguments
+/// * `min_count` - Counts below it are suppressed (`null`), 0 or 1 to publish every count
+/// * `noise_epsilon` - Privacy budget of the Laplace noise added to the published counts (scale
+///   1/epsilon), None for exact counts
+/// * `noise_key` - The key deriving the noise of each count and day, required with the noise: a
+///   day always gets the same noise whatever the period queried, so it cannot be averaged out by
+///   repeating or shifting a query
+#[derive(Debug, Clone, PartialEq)]
+pub struct StatsPolicy {
+    pub min_count: u64,
+    pub noise_epsilon: Option<f64>,
+    pub noise_key: Option<Secret>,
+}
+
+/// Counts of one tenant being aggregated - every row added is checked to belong to the tenant,
+/// and the counts only leave through `finish`, which applies the disclosure policy
+/// - Every hospital-facing statistics endpoint builds its body with it, so the scoping check,
+///   the small-count suppression and the noise are never left to the endpoint
+pub struct TenantAggregate {
+    tenant: String,
+    counts: BTreeMap<String, BTreeMap<String, u64>>,
+}
+
+/// Statistics of one tenant, as returned to it
+/// # Arguments
+/// * `hospital_id` - The tenant
+/// * `min_count` - Counts below it are suppressed (`null`)
+/// * `noise` - Whether Laplace noise was added to the published counts
+/// * `counts` - The counts per group (e.g. exam type) and metric (e.g. action)
+#[derive(Debug, Clone, PartialEq, Serialize, ToSchema)]
+pub struct TenantStats {
+    pub hospital_id: String,
+    pub min_count: u64,
+    pub noise: bool,
+    pub counts: BTreeMap<String, BTreeMap<String, Option<u64>>>,
+}
+
+impl TenantAggregate {
+    /// Start aggregating the statistics of a tenant
+    /// # Arguments
+    /// * `tenant` - The authenticated hospital
+    pub fn new(tenant: &str) -> Self {
+        Self {
+            tenant: tenant.to_string(),
+            counts: BTreeMap::new(),
+        }
+    }
+
+    /// Add a count of a row - rows of the same group and metric are summed
+    /// # Arguments
+    /// * `hospital_id` - The hospital the row was read for
+    /// * `group` - The group of the count (e.g. the exam type)
+    /// * `metric` - The metric counted (e.g. the action)
+    /// * `count` - The count
+    /// # Errors
+    /// * Returns Internal, with an `alert` line, if the row belongs to another hospital - the
+    ///   statistics are never returned, not even in part
+    pub fn add(
+        &mut self,
+        hospital_id: &str,
+        group: &str,
+        metric: &str,
+        count: u64,
+    ) -> Result<(), ApiError> {
+        if hospital_id != self.tenant {
+            error!(target: "alert", "stats_scope_violation tenant={} row_hospital_id={hospital_id} group={group} metric={metric}", self.tenant);
+            return Err(ApiError::Internal);
+        }
+        let total = self
+            .counts
+            .entry(group.to_string())
+            .or_default()
+            .entry(metric.to_string())
+            .or_default();
+        *total = total.saturating_add(count);
+        Ok(())
+    }
+
+    /// Statistics to return, under the policy of the instance
+    /// # Arguments
+    /// * `policy` - The disclosure policy of the instance (STATS_*)
+    /// * `from` / `to` - The whole UTC days counted, `to` excluded - the noise of a count is the
+    ///   sum of the noise of each of its days, fixed per day
+    pub fn finish(self, policy: &StatsPolicy, from: NaiveDate, to: NaiveDate) -> TenantStats {
+        let key = policy.noise_key.clone();
+        self.finish_with(policy, |scope, scale| {
+            key.as_ref().map_or(0.0, |key| {
+                period_noise(key.expose(), scope, (from, to), scale)
+            })
+        })
+    }
+
+    /// Statistics under an explicit policy and noise source
+    /// # Arguments
+    /// * `policy` - The disclosure policy
+    /// * `noise` - The Laplace noise of the given scale for a count (`tenant/group/metric`)
+    fn finish_with(self, policy: &StatsPolicy, noise: impl Fn(&str, f64) -> f64) -> TenantStats {
+        let tenant = &self.tenant;
+        let disclose = |group: &str, metric: &str, count: u64| {
+            // STEP 1: Small counts single out patients or exams - never published
+            if count < policy.min_count {
+                return None;
+            }
+            // STEP 2: Noise on the published counts, kept at or above the threshold so the noise
+            // never reveals a suppressed count
+            let Some(epsilon) = policy.noise_epsilon else {
+                return Some(count);
+            };
+            let sample = noise(&format!("{tenant}/{group}/{metric}"), 1.0 / epsilon);
+            let noisy = (count as f64 + sample).round().max(0.0) as u64;
+            Some(noisy.max(policy.min_count))
+        };
+        let counts = self
+            .counts
+            .into_iter()
+            .map(|(group, metrics)| {
+                let metrics = metrics
+                    .into_iter()
+                    .map(|(metric, count)| {
+                        let disclosed = disclose(&group, &metric, count);
+                        (metric, disclosed)
+                    })
+                    .collect();
+                (group, metrics)
+            })
+            .collect();
+        TenantStats {
+            hospital_id: self.tenant,
+            min_count: policy.min_count,
+            noise: policy.noise_epsilon.is_some(),
+            counts,
+        }
+    }
+}
+
+// SUPPORT FUNCTIONS *******************************************************************************
+/// Noise of a count over whole days: the sum of the fixed sample of each day, so that a period
+/// shifted by a day only changes the noise of the days added and removed
+/// # Arguments
+/// * `key` - The noise key (STATS_NOISE_KEY)
+/// * `scope` - The count, `tenant/group/metric`
+/// * `(from, to)` - The days counted, `to` excluded
+/// * `scale` - The scale of the noise of each day
+fn period_noise(key: &str, scope: &str, (from, to): (NaiveDate, NaiveDate), scale: f64) -> f64 {
+    from.iter_days()
+        .take_while(|day| *day < to)
+        .map(|day| laplace_noise(key, &format!("{scope}/{day}"), scale))
+        .sum()
+}
+
+/// Sample of a Laplace distribution centred on 0, derived from the noise key and the scope of the
+/// count - the same count of the same day always gets the same sample
+/// # Arguments
+/// * `key` - The noise key (STATS_NOISE_KEY)
+/// * `scope` - The count and its day, `tenant/group/metric/day`
+/// * `scale` - The scale of the distribution (sensitivity / epsilon)
+fn laplace_noise(key: &str, scope: &str, scale: f64) -> f64 {
+    let digest = hmac_sha256(key.as_bytes(), scope.as_bytes());
+    let mut bytes = [0u8; 8];
+    bytes.copy_from_slice(&digest[..8]);
+    // Uniform in (-0.5, 0.5), from the 53 bits a double holds
+    let uniform = ((u64::from_le_bytes(bytes) >> 11) as f64 + 0.5) / (1u64 << 53) as f64 - 0.5;
+    -scale * uniform.signum() * (1.0 - 2.0 * uniform.abs()).ln()
+}
+
+// TESTS *******************************************************************************************
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn aggregate() -> TenantAggregate {
+        let mut aggregate = TenantAggregate::new("h1");
+        aggregate.add("h1", "ecg_exam", "exam_stored", 40).unwrap();
+        aggregate.add("h1", "ecg_exam", "exam_stored", 2).unwrap();
+        aggregate.add("h1", "ecg_exam", "exam_lost", 1).unwrap();
+        aggregate.add("h1", "xray_exam", "exam_stored", 5).unwrap();
+        aggregate
+    }
+
+    // Happy path: counts summed per group and metric, small ones suppressed
+    #[test]
+    fn small_counts_suppressed() {
+        let policy = StatsPolicy {
+            min_count: 5,
+            noise_epsilon: None,
+            noise_key: None,
+        };
+        let stats = aggregate().finish_with(&policy, |_, _| unreachable!());
+        assert_eq!(stats.counts["ecg_exam"]["exam_stored"], Some(42));
+        assert_eq!(stats.counts["ecg_exam"]["exam_lost"], None);
+        assert_eq!(stats.counts["xray_exam"]["exam_stored"], Some(5));
+        assert_eq!(
+            serde_json::to_value(&stats).unwrap()["counts"]["ecg_exam"]["exam_lost"],
+            serde_json::Value::Null
+        );
+    }
+
+    // Error handling: a row of another hospital fails the whole aggregate
+    #[test]
+    fn foreign_rows_refused() {
+        let mut aggregate = aggregate();
+        assert_eq!(
+            aggregate.add("h2", "ecg_exam", "exam_stored", 9),
+            Err(ApiError::Internal)
+        );
+        assert_eq!(
+            aggregate.add("H1", "ecg_exam", "exam_stored", 9),
+            Err(ApiError::Internal)
+        );
+    }
+
+    // Borderline: the noise never takes a published count below the threshold
+    #[test]
+    fn noise_kept_above_threshold() {
+        let policy = StatsPolicy {
+            min_count: 5,
+            noise_epsilon: Some(0.5),
+            noise_key: Some(Secret("stats-key".to_string())),
+        };
+        let stats = aggregate().finish_with(&policy, |scope, scale| {
+            assert!(scope.starts_with("h1/"));
+            assert_eq!(scale, 2.0);
+            -10.0
+        });
+        assert!(stats.noise);
+        assert_eq!(stats.counts["ecg_exam"]["exam_stored"], Some(32));
+        assert_eq!(stats.counts["xray_exam"]["exam_stored"], Some(5));
+        assert_eq!(stats.counts["ecg_exam"]["exam_lost"], None);
+    }
+
+    // Happy path: the Laplace samples are centred and spread by their scale
+    #[test]
+    fn laplace_samples() {
+        let samples: Vec<f64> = (0..20_000)
+            .map(|i| laplace_noise("stats-key", &format!("h1/ecg_exam/{i}"), 2.0))
+            .collect();
+        let mean = samples.iter().sum::<f64>() / samples.len() as f64;
+        let mean_abs = samples.iter().map(|s| s.abs()).sum::<f64>() / samples.len() as f64;
+        assert!(mean.abs() < 0.1, "{mean}");
+        assert!((mean_abs - 2.0).abs() < 0.1, "{mean_abs}");
+    }
+
+    // Happy path: a day gets the same noise in every query, another day or key another one
+    #[test]
+    fn noise_fixed_per_day() {
+        let scope = "h1/ecg_exam/exam_stored/2026-09-18";
+        let noise = laplace_noise("stats-key", scope, 2.0);
+        assert_eq!(laplace_noise("stats-key", scope, 2.0), noise);
+        assert_ne!(
+            laplace_noise("stats-key", "h1/ecg_exam/exam_stored/2026-09-19", 2.0),
+            noise
+        );
+        assert_ne!(laplace_noise("other-key", scope, 2.0), noise);
+    }
+
+    // Security: shifting the period only changes the noise of the days added or removed, so
+    // overlapping queries never give fresh samples of the same days to average out
+    #[test]
+    fn noise_summed_per_day() {
+        let scope = "h1/ecg_exam/exam_stored";
+        let day = |d: u32| NaiveDate::from_ymd_opt(2026, 9, d).unwrap();
+        let sample = |d: u32| laplace_noise("stats-key", &format!("{scope}/{}", day(d)), 1.0);
+        let week = period_noise("stats-key", scope, (day(1), day(8)), 1.0);
+        assert!((week - (1..8).map(sample).sum::<f64>()).abs() < 1e-9);
+        let shifted = period_noise("stats-key", scope, (day(2), day(9)), 1.0);
+        assert!((shifted - (week - sample(1) + sample(8))).abs() < 1e-9);
+        assert_eq!(period_noise("stats-key", scope, (day(8), day(8)), 1.0), 0.0);
+    }
+}
@@ -0,0 +1,396 @@
+// Imports *****************************************************************************************
+// External Crates
+#[cfg(feature = "wasm")]
+use actix_web::web;
+use anyhow::{anyhow, Result};
+#[cfg(feature = "wasm")]
+use log::{info, warn};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+#[cfg(feature = "wasm")]
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+#[cfg(feature = "wasm")]
+use wasmtime::{Config, Engine, Instance, Module, Store, StoreLimits, StoreLimitsBuilder};
+
+// Internal Modules
+use crate::config::settings::PluginSettings;
+use crate::storage::exam_storage::ReadOnlyStorage;
+
+// Structs *****************************************************************************************
+/// Vetted plugin module: a stored object pinned to the SHA256 of its reviewed build
+/// # Arguments
+/// * `object` - The object name in WASM_PLUGIN_BUCKET
+/// * `sha256` - The lowercase hex SHA256 of the module
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PluginRef {
+    pub object: String,
+    pub sha256: String,
+}
+
+/// A compiled plugin, ready to be instantiated for every exam
+#[cfg(feature = "wasm")]
+struct LoadedPlugin {
+    reference: PluginRef,
+    module: Module,
+}
+
+/// Experimental stage running per-hospital WASM transformations over the canonical payload
+///
+/// Plugin ABI: the module imports nothing and exports `memory`, `alloc(len: i32) -> i32` and
+/// `transform(ptr: i32, len: i32) -> i64`. The input is the JSON `{"exam_type", "payload"}`
+/// written at `alloc(len)`; the output is the transformed payload JSON, located by the returned
+/// `(ptr << 32) | len`. Every run gets a fresh instance with bounded fuel and memory.
+#[cfg(feature = "wasm")]
+pub struct PluginRegistry {
+    engine: Engine,
+    fuel: u64,
+    max_memory_bytes: usize,
+    plugins: HashMap<String, LoadedPlugin>,
+}
+
+/// Plugin stage of a build without the `wasm` feature: no module can be loaded (WASM_PLUGINS is
+/// refused at startup) and every payload passes through
+#[cfg(not(feature = "wasm"))]
+pub struct PluginRegistry;
+
+impl PluginRegistry {
+    /// Load the plugins referenced in WASM_PLUGINS (`hospital_id=object@sha256,...`) from
+    /// WASM_PLUGIN_BUCKET (default BUCKET_NAME) - empty registry when WASM_PLUGINS is not set
+    /// # Arguments
+    /// * `plugins` - The plugin settings (WASM_PLUGIN*)
+    /// * `exam_bucket` - The exam bucket (BUCKET_NAME), holding the modules by default
+    /// * `storage` - The storage of the read path, used to download the modules
+    /// # Errors
+    /// * Returns an error if a module cannot be downloaded, does not match its pinned SHA256 or
+    ///   does not compile - a hospital is never served with a missing fix
+    pub async fn from_settings(
+        plugins: &PluginSettings,
+        exam_bucket: &str,
+        storage: &ReadOnlyStorage,
+    ) -> Result<Self> {
+        // STEP 1: Build the sandboxing engine
+        let mut registry = Self::new(plugins.fuel, plugins.max_memory_mb * 1024 * 1024)?;
+
+        // STEP 2: Download, verify and compile every referenced module
+        if plugins.references.is_empty() {
+            return Ok(registry);
+        }
+        let bucket = plugins.bucket.as_deref().unwrap_or(exam_bucket);
+        for (hospital_id, reference) in plugins.references.clone() {
+            let bytes = storage.0.get_object(bucket, &reference.object).await?;
+            registry.load(&hospital_id, reference, &bytes)?;
+        }
+        Ok(registry)
+    }
+}
+
+#[cfg(feature = "wasm")]
+impl PluginRegistry {
+    /// Create an empty registry with the given limits
+    /// # Arguments
+    /// * `fuel` - Fuel available to each run
+    /// * `max_memory_bytes` - Largest linear memory of each run
+    pub(crate) fn new(fuel: u64, max_memory_bytes: usize) -> Result<Self> {
+        let mut config = Config::new();
+        config.consume_fuel(true);
+        Ok(Self {
+            engine: Engine::new(&config)?,
+            fuel,
+            max_memory_bytes,
+            plugins: HashMap::new(),
+        })
+    }
+
+    /// Verify and compile a module for a hospital
+    /// # Errors
+    /// * Returns an error if the module does not match its pinned SHA256 or does not compile
+    fn load(&mut self, hospital_id: &str, reference: PluginRef, bytes: &[u8]) -> Result<()> {
+        let digest = format!("{:x}", Sha256::digest(bytes));
+        if digest != reference.sha256 {
+            return Err(anyhow!(
+                "WASM plugin {} does not match its pinned sha256 (got {digest})",
+                reference.object
+            ));
+        }
+        let module = Module::new(&self.engine, bytes)
+            .map_err(|e| anyhow!("WASM plugin {} does not compile: {e}", reference.object))?;
+        if module.imports().len() > 0 {
+            return Err(anyhow!(
+                "WASM plugin {} must not import anything",
+                reference.object
+            ));
+        }
+        info!(
+            "WASM plugin for hospital {hospital_id}: {} (sha256 {})",
+            reference.object, reference.sha256
+        );
+        self.plugins
+            .insert(hospital_id.to_string(), LoadedPlugin { reference, module });
+        Ok(())
+    }
+
+    /// Run the plugin of a hospital over a payload - payloads of other hospitals pass through
+    /// # Arguments
+    /// * `hospital_id` - The hospital the exam comes from
+    /// * `exam_type` - The exam type key, given to the plugin
+    /// * `payload` - The canonical payload
+    /// # Returns
+    /// * The transformed payload
+    /// # Errors
+    /// * Returns an error if the plugin traps, runs out of fuel or memory, or returns a payload
+    ///   that does not deserialize
+    pub async fn apply<T>(&self, hospital_id: &str, exam_type: &str, payload: T) -> Result<T>
+    where
+        T: Serialize + DeserializeOwned + Send + 'static,
+    {
+        let Some(plugin) = self.plugins.get(hospital_id) else {
+            return Ok(payload);
+        };
+        let input = serde_json::to_vec(&serde_json::json!({
+            "exam_type": exam_type,
+            "payload": payload,
+        }))?;
+
+        // Plugins are CPU-bound: run them off the async workers
+        let engine = self.engine.clone();
+        let module = plugin.module.clone();
+        let (fuel, max_memory_bytes) = (self.fuel, self.max_memory_bytes);
+        let result =
+            web::block(move || run_module(&engine, &module, fuel, max_memory_bytes, &input))
+                .await
+                .map_err(|e| anyhow!("WASM plugin could not be scheduled: {e}"))?;
+
+        let reference = &plugin.reference;
+        match result
+            .and_then(|(output, fuel_used)| Ok((serde_json::from_slice(&output)?, fuel_used)))
+        {
+            Ok((payload, fuel_used)) => {
+                info!(target: "audit", "wasm_plugin applied hospital_id={hospital_id} exam_type={exam_type} plugin={} sha256={} fuel_used={fuel_used}", reference.object, reference.sha256);
+                Ok(payload)
+            }
+            Err(e) => {
+                warn!(target: "audit", "wasm_plugin failed hospital_id={hospital_id} exam_type={exam_type} plugin={} sha256={} error={e}", reference.object, reference.sha256);
+                Err(e)
+            }
+        }
+    }
+}
+
+#[cfg(not(feature = "wasm"))]
+impl PluginRegistry {
+    /// Create the registry - the limits only apply to the plugins of the `wasm` feature
+    pub(crate) fn new(_fuel: u64, _max_memory_bytes: usize) -> Result<Self> {
+        Ok(Self)
+    }
+
+    /// Refuse a module: this build cannot run it
+    /// # Errors
+    /// * Always returns an error naming the missing `wasm` feature
+    fn load(&mut self, _hospital_id: &str, reference: PluginRef, _bytes: &[u8]) -> Result<()> {
+        Err(anyhow!(
+            "WASM plugin {} needs a build with the `wasm` feature",
+            reference.object
+        ))
+    }
+
+    /// Pass the payload through - no plugin is loaded in this build
+    pub async fn apply<T>(&self, _hospital_id: &str, _exam_type: &str, payload: T) -> Result<T>
+    where
+        T: Serialize + DeserializeOwned + Send + 'static,
+    {
+        Ok(payload)
+    }
+}
+
+// SUPPORT FUNCTIONS *******************************************************************************
+/// Instantiate a module in a fresh sandbox and run its `transform` export
+/// # Arguments
+/// * `engine` - The fuel-metering engine
+/// * `module` - The compiled plugin
+/// * `fuel` - Fuel available to the run
+/// * `max_memory_bytes` - Largest linear memory of the run
+/// * `input` - The input JSON
+/// # Returns
+/// * The output bytes and the fuel consumed
+#[cfg(feature = "wasm")]
+fn run_module(
+    engine: &Engine,
+    module: &Module,
+    fuel: u64,
+    max_memory_bytes: usize,
+    input: &[u8],
+) -> Result<(Vec<u8>, u64)> {
+    // STEP 1: Fresh store with fuel and memory limits - no state survives between exams
+    let limits = StoreLimitsBuilder::new()
+        .memory_size(max_memory_bytes)
+        .instances(1)
+        .build();
+    let mut store: Store<StoreLimits> = Store::new(engine, limits);
+    store.limiter(|limits| limits);
+    store.set_fuel(fuel)?;
+    let instance = Instance::new(&mut store, module, &[])?;
+
+    // STEP 2: Copy the input into the plugin memory
+    let memory = instance
+        .get_memory(&mut store, "memory")
+        .ok_or_else(|| anyhow!("WASM plugin does not export its memory"))?;
+    let alloc = instance.get_typed_func::<i32, i32>(&mut store, "alloc")?;
+    let transform = instance.get_typed_func::<(i32, i32), i64>(&mut store, "transform")?;
+    let input_len = i32::try_from(input.len())?;
+    let input_ptr = alloc.call(&mut store, input_len)?;
+    memory.write(&mut store, usize::try_from(input_ptr)?, input)?;
+
+    // STEP 3: Transform and read the output back
+    let packed = transform.call(&mut store, (input_ptr, input_len))? as u64;
+    let (output_ptr, output_len) = ((packed >> 32) as usize, (packed & 0xffff_ffff) as usize);
+    let output = memory
+        .data(&store)
+        .get(output_ptr..output_ptr.saturating_add(output_len))
+        .ok_or_else(|| anyhow!("WASM plugin returned an out-of-bounds output"))?
+        .to_vec();
+    let fuel_used = fuel.saturating_sub(store.get_fuel()?);
+    Ok((output, fuel_used))
+}
+
+/// Parse plugin references of the form `hospital_id=object@sha256,...`
+/// # Arguments
+/// * `raw` - The plugin list
+/// # Returns
+/// * A map of hospital id to plugin reference
+pub(crate) fn parse_plugin_refs(raw: &str) -> Result<HashMap<String, PluginRef>> {
+    let mut references = HashMap::new();
+    for entry in raw.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+        let invalid =
+            || anyhow!("Invalid WASM plugin '{entry}': expected hospital_id=object@sha256");
+        let (hospital_id, reference) = entry.split_once('=').ok_or_else(invalid)?;
+        let (object, sha256) = reference.rsplit_once('@').ok_or_else(invalid)?;
+        let sha256 = sha256.trim().to_ascii_lowercase();
+        if hospital_id.trim().is_empty()
+            || object.trim().is_empty()
+            || sha256.len() != 64
+            || !sha256.chars().all(|c| c.is_ascii_hexdigit())
+        {
+            return Err(invalid());
+        }
+        let reference = PluginRef {
+            object: object.trim().to_string(),
+            sha256,
+        };
+        if references
+            .insert(hospital_id.trim().to_string(), reference)
+            .is_some()
+        {
+            return Err(anyhow!(
+                "Duplicate WASM plugin for hospital '{}'",
+                hospital_id.trim()
+            ));
+        }
+    }
+    Ok(references)
+}
+
+// TESTS *******************************************************************************************
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Plugin returning the `payload` member of its input unchanged, located by a fixed offset:
+    /// the input is `{"exam_type":"test","payload":` + payload + `}`
+    #[cfg(feature = "wasm")]
+    const ECHO_PAYLOAD_WAT: &str = r#"
+        (module
+          (memory (export "memory") 1)
+          (func (export "alloc") (param i32) (result i32) i32.const 0)
+          (func (export "transform") (param $ptr i32) (param $len i32) (result i64)
+            ;; skip the 30-byte prefix and drop the closing brace
+            (i64.or
+              (i64.shl (i64.const 30) (i64.const 32))
+              (i64.extend_i32_u (i32.sub (local.get $len) (i32.const 31))))))
+    "#;
+
+    /// Plugin looping forever - must be stopped by the fuel limit
+    #[cfg(feature = "wasm")]
+    const LOOP_WAT: &str = r#"
+        (module
+          (memory (export "memory") 1)
+          (func (export "alloc") (param i32) (result i32) i32.const 0)
+          (func (export "transform") (param i32 i32) (result i64)
+            (loop $l (br $l))
+            i64.const 0))
+    "#;
+
+    #[cfg(feature = "wasm")]
+    fn registry_with(wat: &str) -> PluginRegistry {
+        let mut registry = PluginRegistry::new(1_000_000, 2 * 1024 * 1024).unwrap();
+        let bytes = wat.as_bytes();
+        let reference = PluginRef {
+            object: "plugins/test.wasm".to_string(),
+            sha256: format!("{:x}", Sha256::digest(bytes)),
+        };
+        registry.load("h1", reference, bytes).unwrap();
+        registry
+    }
+
+    // Happy path: the plugin output replaces the payload, other hospitals pass through
+    #[cfg(feature = "wasm")]
+    #[actix_web::test]
+    async fn plugin_transforms_payload() {
+        let registry = registry_with(ECHO_PAYLOAD_WAT);
+        let payload = serde_json::json!({"a": 1});
+        let out = registry.apply("h1", "test", payload.clone()).await.unwrap();
+        assert_eq!(out, payload);
+        let untouched = registry.apply("h2", "test", payload.clone()).await.unwrap();
+        assert_eq!(untouched, payload);
+    }
+
+    // Error handling: runaway plugins are stopped by the fuel limit
+    #[cfg(feature = "wasm")]
+    #[actix_web::test]
+    async fn plugin_out_of_fuel() {
+        let registry = registry_with(LOOP_WAT);
+        assert!(registry
+            .apply("h1", "test", serde_json::json!({}))
+            .await
+            .is_err());
+    }
+
+    // Error handling: modules not matching their pinned digest are refused
+    #[cfg(feature = "wasm")]
+    #[test]
+    fn plugin_digest_mismatch() {
+        let mut registry = PluginRegistry::new(1_000, 1024 * 1024).unwrap();
+        let bytes = ECHO_PAYLOAD_WAT.as_bytes();
+        let reference = PluginRef {
+            object: "plugins/test.wasm".to_string(),
+            sha256: "0".repeat(64),
+        };
+        assert!(registry.load("h1", reference, bytes).is_err());
+    }
+
+    // Borderline: without the `wasm` feature modules are refused and payloads pass through
+    #[cfg(not(feature = "wasm"))]
+    #[actix_web::test]
+    async fn plugins_without_runtime() {
+        let mut registry = PluginRegistry::new(1_000, 1024 * 1024).unwrap();
+        let reference = PluginRef {
+            object: "plugins/test.wasm".to_string(),
+            sha256: "0".repeat(64),
+        };
+        assert!(registry.load("h1", reference, b"").is_err());
+        let payload = serde_json::json!({"a": 1});
+        let out = registry.apply("h1", "test", payload.clone()).await.unwrap();
+        assert_eq!(out, payload);
+    }
+
+    #[test]
+    fn plugin_refs_parse() {
+        let sha = "A".repeat(64);
+        let refs = parse_plugin_refs(&format!("h1=plugins/fix@v1.wasm@{sha}")).unwrap();
+        assert_eq!(refs["h1"].object, "plugins/fix@v1.wasm");
+        assert_eq!(refs["h1"].sha256, "a".repeat(64));
+        assert!(parse_plugin_refs("h1=plugins/fix.wasm").is_err());
+        assert!(parse_plugin_refs("h1=plugins/fix.wasm@abc").is_err());
+        assert!(parse_plugin_refs("").unwrap().is_empty());
+    }
+}
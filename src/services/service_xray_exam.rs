@@ -1,30 +1,971 @@
 // Imports *****************************************************************************************
 // External Crates
-use anyhow::Result;
-use google_cloud_pubsub::client::Client as PubSubClient;
-use google_cloud_storage::client::Client as GcsClient;
-use log::info;
-use std::sync::Arc;
+use actix_web::web::Bytes;
+use anyhow::{anyhow, Result};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use chrono::{DateTime, Utc};
+use futures_util::{TryStream, TryStreamExt};
+use image::ImageFormat;
+use log::{error, info};
+use polars::io::json::JsonReader;
+use polars::io::parquet::{ParquetWriter, ZstdLevel};
+use polars::prelude::*;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::io::{self, Cursor};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
 // Internal Modules
-use crate::models::models_exams::PayloadXray;
+use crate::audit::audit_event::{AuditAction, AuditEvent};
+use crate::audit::audit_trail::record_audit;
+use crate::config::settings::StorageSettings;
+use crate::models::models_consent::ConsentScope;
+use crate::models::models_dicom::DicomMetadata;
+use crate::models::models_exams::{
+    PayloadXray, XrayImageFormat, XrayUploadMetadata, XRAY_IMAGE_SIZE,
+};
+use crate::models::models_publish_modes::PublishMode;
+use crate::models::models_validation_profiles::{XRAY_PROFILE, XRAY_UPLOAD_PROFILE};
+use crate::publishers::publisher::{NotificationMessage, Publisher};
+use crate::services::service_billing::{BillingEvent, BillingService};
+use crate::services::service_dead_letter::{dead_letter, DeadLetterRecord, Delivery, FailedStage};
+use crate::services::service_downstream_feedback::wait_until_unsaturated;
+use crate::services::service_idempotency::tag_message;
+use crate::services::service_message_format::{
+    encode_message, tag_exam_message, INFERENCE_XRAY_AVRO,
+};
+use crate::services::service_research_sampling::{offer_research_sample, ResearchSample};
+use crate::storage::exam_storage::{ByteStream, ExamStorage, ObjectPut};
+use crate::telemetry::metrics::record_message_published;
+use crate::telemetry::trace_context::{in_current_trace, trace_attributes};
+use crate::utils::api_error::ApiError;
+use crate::utils::external_call::INGEST_RETRIES;
+use crate::utils::ids::ExamId;
+use crate::utils::publish_backlog::{BacklogState, PUBLISH_BACKLOG};
+use crate::utils::reason_code::ReasonCode;
+use crate::utils::stage_metrics::{latency_attributes, observe_stage_between, Stage};
+
+// Constants ***************************************************************************************
+/// Exam type key used for storage prefixes and PubSub routing
+const EXAM_TYPE: &str = "xray_exam";
+/// Timeout of a streamed image upload - the body arrives at the pace of the hospital
+const XRAY_UPLOAD_TIMEOUT: Duration = Duration::from_secs(300);
+/// Version of the Parquet layout of the metadata sidecar - bumped on any change of its columns or
+/// types
+pub const XRAY_PARQUET_SCHEMA_VERSION: u32 = 1;
+
+// Structs *****************************************************************************************
+/// Streamed XRay upload whose metadata was validated, before its image is stored
+/// # Arguments
+/// * `metadata` - The validated metadata of the upload
+/// * `format` - The format of the image, confirmed by its signature
+/// * `deferred` - Publish in the background once downstream is no longer saturated
+/// * `received_at` - When the gateway received the exam, for end-to-end latency
+/// * `consent_scope` - The data-sharing consent of the hospital
+/// * `image_width` / `image_height` - The image dimensions, when known without decoding it
+/// * `dicom` - The metadata extracted from a de-identified DICOM file
+/// * `idempotency_key` - The idempotency key of the request, passed on to the consumers
+/// * `publish_mode` - Whether the exam is answered before its notification
+#[derive(Debug, Clone)]
+pub struct XrayUpload {
+    pub metadata: XrayUploadMetadata,
+    pub format: XrayImageFormat,
+    pub deferred: bool,
+    pub received_at: DateTime<Utc>,
+    pub consent_scope: ConsentScope,
+    pub image_width: Option<u32>,
+    pub image_height: Option<u32>,
+    pub dicom: Option<DicomMetadata>,
+    pub idempotency_key: Option<String>,
+    pub publish_mode: PublishMode,
+}
 
 // MAIN FUNCTIONS **********************************************************************************
-// TODO: Implement the handler for XRay exam processing
+// Follow service protocol for handling XRay exam data
+/// Handles the processing of an XRay exam from decoding to storage and PubSub
+/// # Arguments
+/// * `data` - A PayloadXray struct containing the validated data of the XRay exam
+/// * `storage` - The exam storage backend
+/// * `settings` - The storage settings: exam bucket and dead letters
+/// * `publisher` - The notification backend, routed per exam type
+/// * `billing` - An Arc reference to the billing service recording usage
+/// * `deferred` - Publish in the background once downstream is no longer saturated
+/// * `received_at` - When the gateway received the exam, for end-to-end latency
+/// * `consent_scope` - The data-sharing consent of the hospital
+/// * `idempotency_key` - The idempotency key of the request, passed on to the consumers
+/// * `publish_mode` - Whether the exam is answered before its notification
+/// # Returns
+/// * How the exam left the gateway - exams failing storage or publish after all retries are
+///   dead-lettered for replay
+/// # Errors
+/// * Returns an error if any step in the processing fails and the exam could not be dead-lettered
 pub async fn handler_xray_exam(
-    _data: PayloadXray,
-    _gcs_client: &Arc<GcsClient>,
-    _pubsub_client: &Arc<PubSubClient>,
-) -> Result<()> {
+    data: PayloadXray,
+    storage: &Arc<dyn ExamStorage>,
+    settings: &StorageSettings,
+    publisher: &Arc<dyn Publisher>,
+    billing: &Arc<BillingService>,
+    deferred: bool,
+    received_at: DateTime<Utc>,
+    consent_scope: ConsentScope,
+    idempotency_key: Option<String>,
+    publish_mode: PublishMode,
+) -> Result<Delivery> {
     info!("Handling CXRAY payload - pre-processing the data");
+    let started_at = Utc::now();
+    observe_stage_between(EXAM_TYPE, Stage::Intake, received_at, started_at);
+
+    // STEP 1: Pre-process the data: decode the image and build the sidecar and notification
+    let topic = publisher.topic_name(EXAM_TYPE)?;
+    let mut prep_data = preprocess_xray_data(&data, deferred, topic, consent_scope)?;
+    // The image location depends on the storage backend, only known here
+    prep_data.pubsub.object_path =
+        storage.location(&settings.bucket_name, &prep_data.parquet.image_object);
+    let preprocessed_at = Utc::now();
+    observe_stage_between(EXAM_TYPE, Stage::Preprocess, started_at, preprocessed_at);
 
-    // STEP 1:
+    // STEP 2: Save the image and its metadata sidecar to persistent storage
+    let bytes_stored = match save_xray_exam_data(&prep_data, storage, settings).await {
+        Ok(bytes_stored) => bytes_stored,
+        Err(e) => {
+            // Storage is down: keep the image and its sidecar so they can be replayed
+            let record = DeadLetterRecord {
+                exam_type: EXAM_TYPE.to_string(),
+                exam_id: prep_data.exam_id.clone(),
+                hospital_id: data.hospital_id.to_string(),
+                stage: FailedStage::Storage,
+                error: e.to_string(),
+                reason: ReasonCode::of_error(&e),
+                failed_at: Utc::now(),
+                payload: serde_json::json!({
+                    "sidecar": prep_data.parquet,
+                    "image": STANDARD.encode(&prep_data.image),
+                }),
+                attributes: HashMap::new(),
+            };
+            dead_letter(&record, storage, settings).await?;
+            return Ok(Delivery::DeadLettered);
+        }
+    };
+    let stored_at = Utc::now();
+    observe_stage_between(EXAM_TYPE, Stage::Storage, preprocessed_at, stored_at);
+    info!("Handling CXRAY payload - image and metadata saved");
 
-    // STEP FINAL: Log the successful processing and return Ok
+    // STEP 2b: Offer the stored exam to the research mirror (background, consented hospitals only)
+    offer_research_sample(
+        ResearchSample {
+            exam_type: EXAM_TYPE,
+            exam_id: prep_data.exam_id.clone(),
+            hospital_id: data.hospital_id.to_string(),
+            patient_id: data.patient_id.to_string(),
+            consent_scope,
+            image: Some((prep_data.image, prep_data.parquet.image_format.clone())),
+        },
+        || Ok(serde_json::to_value(&prep_data.parquet)?),
+        storage,
+    );
+
+    // STEP 3: Record the usage for billing and send to PubSub for further processing
+    let delivery = deliver_xray_exam(
+        prep_data.pubsub,
+        bytes_stored,
+        received_at,
+        stored_at,
+        idempotency_key,
+        publish_mode,
+        storage,
+        settings,
+        publisher,
+        billing,
+    )
+    .await?;
+
+    // STEP FINAL: Log the successful processing and return how the exam was delivered
     info!("CXRAY payload processed successfully");
-    Ok(())
+    Ok(delivery)
+}
+
+/// Handles a streamed XRay upload: the image goes to storage as it is received, never buffered
+/// # Arguments
+/// * `upload` - The validated metadata and format of the upload
+/// * `image` - The image bytes as they are received from the hospital
+/// * `storage` - The exam storage backend
+/// * `settings` - The storage settings: exam bucket and dead letters
+/// * `publisher` - The notification backend, routed per exam type
+/// * `billing` - An Arc reference to the billing service recording usage
+/// # Returns
+/// * How the exam left the gateway - an exam whose sidecar or publish failed after all retries is
+///   dead-lettered for replay
+/// # Errors
+/// * Returns a storage failure if the image could not be streamed - it is not kept, the hospital
+///   must send it again
+pub async fn handler_xray_upload<S>(
+    upload: XrayUpload,
+    image: S,
+    storage: &Arc<dyn ExamStorage>,
+    settings: &StorageSettings,
+    publisher: &Arc<dyn Publisher>,
+    billing: &Arc<BillingService>,
+) -> Result<Delivery>
+where
+    S: TryStream + Send + Sync + 'static,
+    S::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+    Bytes: From<S::Ok>,
+{
+    info!("Handling CXRAY upload - streaming the image");
+    let started_at = Utc::now();
+    observe_stage_between(EXAM_TYPE, Stage::Intake, upload.received_at, started_at);
+
+    // STEP 1: Name the exam after its metadata
+    let topic = publisher.topic_name(EXAM_TYPE)?.to_string();
+    let metadata = &upload.metadata;
+    let (exam_id, timestamp) = exam_name(&metadata.hospital_id, &metadata.patient_id);
+    let image_object = storage.object_name(&exam_id, upload.format.extension());
+
+    // STEP 2: Stream the image - single attempt: the body can neither be replayed nor dead-lettered
+    let bucket_name = &settings.bucket_name;
+    let object = ObjectPut {
+        bucket: bucket_name,
+        name: &image_object,
+        content_type: Some(upload.format.content_type()),
+        operation: "upload_streamed_object",
+        retries: 0,
+    };
+    // The image is hashed as it streams, for the audit trail
+    let hasher = Arc::new(Mutex::new(Sha256::new()));
+    let image_hasher = Arc::clone(&hasher);
+    let body: ByteStream = Box::pin(
+        image
+            .into_stream()
+            .map_ok(Bytes::from)
+            .inspect_ok(move |chunk| {
+                if let Ok(mut hasher) = image_hasher.lock() {
+                    hasher.update(chunk);
+                }
+            })
+            .map_err(io::Error::other),
+    );
+    let image_bytes = storage
+        .put_stream(&object, body, XRAY_UPLOAD_TIMEOUT)
+        .await
+        .map_err(|e| match e.downcast_ref::<ApiError>() {
+            Some(_) => e,
+            None => anyhow::Error::new(ApiError::StorageFailure).context(e),
+        })?;
+    info!("Handling CXRAY upload - image of {image_bytes} bytes stored");
+    let mut event = AuditEvent::exam(
+        AuditAction::ExamStored,
+        EXAM_TYPE,
+        &exam_id,
+        &metadata.hospital_id,
+    )
+    .object_path(storage.location(bucket_name, &image_object));
+    if let Ok(hasher) = hasher.lock() {
+        event.payload_hash = Some(format!("{:x}", hasher.clone().finalize()));
+    }
+    record_audit(event);
+
+    // STEP 3: Save the metadata sidecar next to the image
+    let parquet = XrayExamParquet {
+        exam_type: "XRay Exam".to_string(),
+        timestamp: timestamp.clone(),
+        patient_id: metadata.patient_id.to_string(),
+        hospital_id: metadata.hospital_id.to_string(),
+        consent_scope: upload.consent_scope,
+        image_object: image_object.clone(),
+        image_format: upload.format.extension().to_string(),
+        image_width: upload.image_width,
+        image_height: upload.image_height,
+        image_bytes,
+        view_position: metadata.view_position.clone(),
+        validation_profile_id: XRAY_UPLOAD_PROFILE.id.to_string(),
+        validation_profile_version: XRAY_UPLOAD_PROFILE.version,
+        dicom: upload.dicom.clone(),
+    };
+    let sidecar_bytes = match upload_sidecar(&parquet, &exam_id, storage, settings).await {
+        Ok(sidecar_bytes) => sidecar_bytes,
+        Err(e) => {
+            // The image is stored: keep its sidecar so it can be replayed
+            let record = DeadLetterRecord {
+                exam_type: EXAM_TYPE.to_string(),
+                exam_id: exam_id.into(),
+                hospital_id: metadata.hospital_id.to_string(),
+                stage: FailedStage::Storage,
+                error: e.to_string(),
+                reason: ReasonCode::of_error(&e),
+                failed_at: Utc::now(),
+                payload: serde_json::json!({ "sidecar": parquet }),
+                attributes: HashMap::new(),
+            };
+            dead_letter(&record, storage, settings).await?;
+            return Ok(Delivery::DeadLettered);
+        }
+    };
+    let stored_at = Utc::now();
+    observe_stage_between(EXAM_TYPE, Stage::Storage, started_at, stored_at);
+
+    // STEP 4: Record the usage for billing and send to PubSub for further processing
+    let pubsub = XrayExamPubSub {
+        topic,
+        exam_id: exam_id.into(),
+        exam_type: "XRay Exam".to_string(),
+        timestamp,
+        patient_id: metadata.patient_id.to_string(),
+        hospital_id: metadata.hospital_id.to_string(),
+        object_path: storage.location(bucket_name, &image_object),
+        image_object,
+        deferred: upload.deferred,
+        consent_scope: upload.consent_scope,
+        dicom: upload.dicom,
+    };
+    let delivery = deliver_xray_exam(
+        pubsub,
+        image_bytes + sidecar_bytes,
+        upload.received_at,
+        stored_at,
+        upload.idempotency_key,
+        upload.publish_mode,
+        storage,
+        settings,
+        publisher,
+        billing,
+    )
+    .await?;
+
+    // STEP FINAL: Log the successful processing and return how the exam was delivered
+    info!("CXRAY upload processed successfully");
+    Ok(delivery)
 }
 
 // SUPPORT FUNCTIONS *******************************************************************************
+/// Metadata sidecar stored as Parquet next to the image - the image itself is not duplicated
+/// # Arguments
+/// * `exam_type` - A string representing the type of the exam
+/// * `timestamp` - A string representing the timestamp of the exam
+/// * `patient_id` - A string representing the patient id (SHA256 hash)
+/// * `hospital_id` - A string representing the hospital id (SHA256 hash)
+/// * `consent_scope` - The data-sharing consent of the hospital, kept with the exam
+/// * `image_object` - The object name of the stored image
+/// * `image_format` - `png`, `jpeg` or `dcm` (streamed uploads only)
+/// * `image_width` / `image_height` - The image dimensions in pixels - unknown for streamed
+///   uploads, which are never decoded
+/// * `image_bytes` - The size of the stored image
+/// * `view_position` - The projection of the image, if provided
+/// * `validation_profile_id` / `validation_profile_version` - The profile the exam passed
+/// * `dicom` - The metadata of a DICOM file (modality, pseudonymized UIDs, acquisition time)
+#[derive(Serialize, Debug)]
+struct XrayExamParquet {
+    exam_type: String,
+    timestamp: String,
+    patient_id: String,
+    hospital_id: String,
+    consent_scope: ConsentScope,
+    image_object: String,
+    image_format: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    image_width: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    image_height: Option<u32>,
+    image_bytes: u64,
+    view_position: Option<String>,
+    validation_profile_id: String,
+    validation_profile_version: u32,
+    #[serde(flatten)]
+    dicom: Option<DicomMetadata>,
+}
+
+/// Struct to represent the XRay exam data in a format suitable for PubSub
+/// # Arguments
+/// * `topic` - A string representing the PubSub topic
+/// * `exam_id` - A string identifying the stored exam (sidecar object name without extension)
+/// * `exam_type` - A string representing the type of the exam
+/// * `timestamp` - A string representing the timestamp of the exam
+/// * `patient_id` - A string representing the patient id (SHA256 hash)
+/// * `hospital_id` - A string representing the hospital id (SHA256 hash)
+/// * `image_object` - The object name of the stored image
+/// * `deferred` - Whether the publish was delayed because downstream was saturated
+/// * `consent_scope` - The data-sharing consent of the hospital
+/// * `object_path` - The location of the stored image, e.g. `gs://bucket/xray_exam/...`
+/// * `dicom` - The metadata of a DICOM file (modality, pseudonymized UIDs, acquisition time)
+#[derive(Serialize, Debug)]
+struct XrayExamPubSub {
+    topic: String,
+    exam_id: String,
+    exam_type: String,
+    timestamp: String,
+    patient_id: String,
+    hospital_id: String,
+    image_object: String,
+    deferred: bool,
+    consent_scope: ConsentScope,
+    object_path: String,
+    #[serde(flatten)]
+    dicom: Option<DicomMetadata>,
+}
+
+/// Pre-processed XRay exam, ready for storage and PubSub
+/// # Arguments
+/// * `exam_id` - `xray_exam/{hospital_id}/{patient_id}/{uuid}`
+/// * `image` - The decoded image bytes
+/// * `parquet` - The metadata sidecar
+/// * `pubsub` - The notification
+#[derive(Debug)]
+struct XrayExamPrepared {
+    exam_id: String,
+    image: Vec<u8>,
+    parquet: XrayExamParquet,
+    pubsub: XrayExamPubSub,
+}
+
+/// Pre-process the XRay data for storage and PubSub
+/// # Arguments
+/// * `data` - A PayloadXray struct containing the validated data of the XRay exam
+/// * `deferred` - Whether the PubSub message will be published in the background
+/// * `topic` - The PubSub topic routed for XRay exams
+/// * `consent_scope` - The data-sharing consent of the hospital
+/// # Returns
+/// * The decoded image with its metadata sidecar and notification
+/// # Errors
+/// * Returns an error if the image cannot be decoded or is not a PNG or JPEG
+fn preprocess_xray_data(
+    data: &PayloadXray,
+    deferred: bool,
+    topic: &str,
+    consent_scope: ConsentScope,
+) -> Result<XrayExamPrepared> {
+    // STEP 1: Decode the image (already validated by the model)
+    let image = STANDARD.decode(&data.image)?;
+    let image_format = match image::guess_format(&image)? {
+        ImageFormat::Png => "png",
+        ImageFormat::Jpeg => "jpeg",
+        other => return Err(anyhow!("Unsupported X-ray image format {other:?}")),
+    };
+
+    // STEP 2: Get name variables
+    let (exam_id, utc_timestamp_string) = exam_name(&data.hospital_id, &data.patient_id);
+    let image_object = format!("{exam_id}.{image_format}");
+
+    // STEP 3: Create the metadata sidecar and the notification
+    let parquet = XrayExamParquet {
+        exam_type: "XRay Exam".to_string(),
+        timestamp: utc_timestamp_string.clone(),
+        patient_id: data.patient_id.to_string(),
+        hospital_id: data.hospital_id.to_string(),
+        consent_scope,
+        image_object: image_object.clone(),
+        image_format: image_format.to_string(),
+        image_width: Some(XRAY_IMAGE_SIZE),
+        image_height: Some(XRAY_IMAGE_SIZE),
+        image_bytes: image.len() as u64,
+        view_position: data.view_position.clone(),
+        validation_profile_id: XRAY_PROFILE.id.to_string(),
+        validation_profile_version: XRAY_PROFILE.version,
+        dicom: None,
+    };
+    let pubsub = XrayExamPubSub {
+        topic: topic.to_string(),
+        exam_id: exam_id.to_string(),
+        exam_type: "XRay Exam".to_string(),
+        timestamp: utc_timestamp_string,
+        patient_id: data.patient_id.to_string(),
+        hospital_id: data.hospital_id.to_string(),
+        image_object,
+        deferred,
+        consent_scope,
+        // Set by the handler, which knows the storage backend
+        object_path: String::new(),
+        dicom: None,
+    };
+
+    Ok(XrayExamPrepared {
+        exam_id: exam_id.into(),
+        image,
+        parquet,
+        pubsub,
+    })
+}
+
+/// Save the XRay image and its Parquet metadata sidecar to persistent storage
+/// The image is written first, so a sidecar always points to an existing image
+/// # Arguments
+/// * `prepared` - The pre-processed exam
+/// * `storage` - The exam storage backend
+/// * `settings` - The storage settings, naming the exam bucket
+/// # Returns
+/// * A Result containing the number of bytes stored (image and sidecar)
+/// # Errors
+/// * Returns an error if any step in the saving process fails
+async fn save_xray_exam_data(
+    prepared: &XrayExamPrepared,
+    storage: &Arc<dyn ExamStorage>,
+    settings: &StorageSettings,
+) -> Result<u64> {
+    // STEP 1: Upload the image
+    let bucket_name = &settings.bucket_name;
+    let content_type = format!("image/{}", prepared.parquet.image_format);
+    let object = ObjectPut {
+        bucket: bucket_name,
+        name: &prepared.parquet.image_object,
+        content_type: Some(&content_type),
+        operation: "upload_object",
+        retries: INGEST_RETRIES,
+    };
+    storage.put_object(&object, prepared.image.clone()).await?;
+    record_audit(
+        AuditEvent::exam(
+            AuditAction::ExamStored,
+            EXAM_TYPE,
+            &prepared.exam_id,
+            &prepared.parquet.hospital_id,
+        )
+        .payload(&prepared.image)
+        .object_path(storage.location(bucket_name, &prepared.parquet.image_object)),
+    );
+
+    // STEP 2: Upload the sidecar once the image is stored
+    let sidecar_bytes =
+        upload_sidecar(&prepared.parquet, &prepared.exam_id, storage, settings).await?;
+    Ok(prepared.image.len() as u64 + sidecar_bytes)
+}
+
+/// Name of a new XRay exam
+/// # Arguments
+/// * `hospital_id` - The hospital id (SHA256 hash)
+/// * `patient_id` - The patient id (SHA256 hash)
+/// # Returns
+/// * The exam id `xray_exam/{hospital_id}/{patient_id}/{uuid}` and its timestamp
+fn exam_name(hospital_id: &str, patient_id: &str) -> (ExamId, String) {
+    let now = Utc::now();
+    let timestamp = now.format("%Y-%m-%dT%H%M%S%.fZ").to_string();
+    (
+        ExamId::generate(EXAM_TYPE, hospital_id, patient_id, now),
+        timestamp,
+    )
+}
+
+/// DataFrame of a metadata sidecar: one row, the types inferred from its JSON - absent optional
+/// fields are not columns, and null ones are null-typed columns
+/// # Arguments
+/// * `parquet` - The metadata sidecar
+/// # Errors
+/// * Returns an error if the sidecar cannot be converted
+fn sidecar_frame(parquet: &XrayExamParquet) -> Result<DataFrame> {
+    let json = serde_json::to_string(&vec![parquet])?;
+    Ok(JsonReader::new(Cursor::new(json))
+        .infer_schema_len(None)
+        .finish()?)
+}
+
+/// Sidecar DataFrames of two sample exams, for the schema documentation: the smallest sidecar (an
+/// exam sent as JSON, no optional field) and the largest (a DICOM upload with every field)
+/// # Returns
+/// * The smallest and the largest sidecar, as written by `upload_sidecar`
+/// # Errors
+/// * Returns an error if a sidecar cannot be converted
+pub fn sidecar_schema_samples() -> Result<(DataFrame, DataFrame)> {
+    let sample = |dicom: Option<DicomMetadata>| XrayExamParquet {
+        exam_type: "XRay Exam".to_string(),
+        timestamp: "2026-10-01T120000.000Z".to_string(),
+        patient_id: "0".repeat(64),
+        hospital_id: "0".repeat(64),
+        consent_scope: ConsentScope::Clinical,
+        image_object: format!("{EXAM_TYPE}/h/p/0192.png"),
+        image_format: "png".to_string(),
+        image_width: dicom.as_ref().map(|_| XRAY_IMAGE_SIZE),
+        image_height: dicom.as_ref().map(|_| XRAY_IMAGE_SIZE),
+        image_bytes: 1,
+        view_position: dicom.as_ref().map(|_| "PA".to_string()),
+        validation_profile_id: XRAY_PROFILE.id.to_string(),
+        validation_profile_version: XRAY_PROFILE.version,
+        dicom,
+    };
+    let dicom = DicomMetadata {
+        modality: Some("DX".to_string()),
+        sop_class_uid: Some("1.2.840.10008.5.1.4.1.1.1.1".to_string()),
+        study_instance_uid: Some("2.25.1".to_string()),
+        series_instance_uid: Some("2.25.2".to_string()),
+        sop_instance_uid: Some("2.25.3".to_string()),
+        acquisition_datetime: Some("20261001120000".to_string()),
+        transfer_syntax_uid: "1.2.840.10008.1.2.1".to_string(),
+    };
+    Ok((
+        sidecar_frame(&sample(None))?,
+        sidecar_frame(&sample(Some(dicom)))?,
+    ))
+}
+
+/// Save the Parquet metadata sidecar of an XRay exam under its exam id, as for the other exam types
+/// # Arguments
+/// * `parquet` - The metadata sidecar
+/// * `exam_id` - The id of the exam
+/// * `storage` - The exam storage backend
+/// * `settings` - The storage settings, naming the exam bucket
+/// # Returns
+/// * A Result containing the size of the sidecar in bytes
+/// # Errors
+/// * Returns an error if the sidecar cannot be converted or uploaded
+async fn upload_sidecar(
+    parquet: &XrayExamParquet,
+    exam_id: &str,
+    storage: &Arc<dyn ExamStorage>,
+    settings: &StorageSettings,
+) -> Result<u64> {
+    // STEP 1: Convert the metadata to Parquet format
+    let mut df = sidecar_frame(parquet)?;
+    let mut buffer = Vec::new();
+    ParquetWriter::new(&mut buffer)
+        .with_compression(ParquetCompression::Zstd(Some(ZstdLevel::try_new(1)?)))
+        .finish(&mut df)?;
+
+    // STEP 2: Upload the sidecar
+    let object_name = storage.object_name(exam_id, "parquet");
+    let object = ObjectPut {
+        bucket: &settings.bucket_name,
+        name: &object_name,
+        content_type: None,
+        operation: "upload_object",
+        retries: INGEST_RETRIES,
+    };
+    storage.put_object(&object, buffer).await
+}
+
+/// Record the usage of a stored XRay exam for billing, then notify it on PubSub - in the
+/// background if downstream is saturated or the hospital is answered once its exams are stored
+/// # Arguments
+/// * `pubsub` - The notification of the stored exam
+/// * `bytes_stored` - The size of the image and its sidecar
+/// * `received_at` - When the gateway received the exam
+/// * `stored_at` - When the exam was written to storage
+/// * `idempotency_key` - The idempotency key of the request, passed on to the consumers
+/// * `publish_mode` - Whether the exam is answered before its notification
+/// * `storage` - The exam storage backend, to dead-letter an unpublished message
+/// * `settings` - The storage settings, locating the dead letters
+/// * `publisher` - The notification backend, routed per exam type
+/// * `billing` - An Arc reference to the billing service recording usage
+/// # Returns
+/// * Published, Deferred, Stored, or DeadLettered if the publish failed after all retries
+/// # Errors
+/// * Returns an error if the routed destination is not covered by the consent, or if the
+///   notification could not be published nor dead-lettered
+async fn deliver_xray_exam(
+    pubsub: XrayExamPubSub,
+    bytes_stored: u64,
+    received_at: DateTime<Utc>,
+    stored_at: DateTime<Utc>,
+    idempotency_key: Option<String>,
+    publish_mode: PublishMode,
+    storage: &Arc<dyn ExamStorage>,
+    settings: &StorageSettings,
+    publisher: &Arc<dyn Publisher>,
+    billing: &Arc<BillingService>,
+) -> Result<Delivery> {
+    // STEP 1: Record the usage for billing (never fails the exam)
+    billing
+        .record(BillingEvent {
+            hospital_id: pubsub.hospital_id.clone(),
+            exam_type: EXAM_TYPE.to_string(),
+            bytes_stored,
+            timestamp: stored_at,
+        })
+        .await;
+
+    // STEP 2: Send to PubSub for further processing
+    let (deferred, consent_scope) = (pubsub.deferred, pubsub.consent_scope);
+    let pubsub_data = serde_json::to_value(&pubsub)?;
+    if deferred {
+        // Downstream is saturated or Pub/Sub is backlogged: the exam is safely stored, notify once
+        // it recovers - the waiting notification counts in the backlog
+        let publisher = publisher.clone();
+        let storage = storage.clone();
+        let settings = settings.clone();
+        let waiting = PUBLISH_BACKLOG.track(BacklogState::Deferred);
+        actix_web::rt::spawn(in_current_trace(async move {
+            wait_until_unsaturated().await;
+            drop(waiting);
+            observe_stage_between(EXAM_TYPE, Stage::Deferral, stored_at, Utc::now());
+            if let Err(e) = send_to_pubsub(
+                pubsub_data,
+                &publisher,
+                &storage,
+                &settings,
+                received_at,
+                stored_at,
+                consent_scope,
+                idempotency_key.as_deref(),
+            )
+            .await
+            {
+                error!("Deferred CXRAY publish failed: {e}");
+            }
+        }));
+        info!("CXRAY exam stored - publish deferred until downstream recovers");
+        return Ok(Delivery::Deferred);
+    }
+    if publish_mode == PublishMode::AfterStorage {
+        // The hospital is answered once the exam is stored: the consent is checked before, the
+        // notification is published in the background - counted in the backlog, so it is drained
+        // at shutdown, and dead-lettered for replay if it fails
+        publisher.check_consent(EXAM_TYPE, consent_scope)?;
+        let publisher = publisher.clone();
+        let storage = storage.clone();
+        let settings = settings.clone();
+        let pending = PUBLISH_BACKLOG.track(BacklogState::InFlight);
+        actix_web::rt::spawn(in_current_trace(async move {
+            drop(pending);
+            if let Err(e) = send_to_pubsub(
+                pubsub_data,
+                &publisher,
+                &storage,
+                &settings,
+                received_at,
+                stored_at,
+                consent_scope,
+                idempotency_key.as_deref(),
+            )
+            .await
+            {
+                error!("Background CXRAY publish failed: {e}");
+            }
+        }));
+        info!("CXRAY exam stored - publishing in the background");
+        return Ok(Delivery::Stored);
+    }
+    send_to_pubsub(
+        pubsub_data,
+        publisher,
+        storage,
+        settings,
+        received_at,
+        stored_at,
+        consent_scope,
+        idempotency_key.as_deref(),
+    )
+    .await
+}
+
+/// Send the XRay exam notification to PubSub for further processing
+/// # Arguments
+/// * `data` - A serde_json::Value containing the XRay exam notification
+/// * `publisher` - The notification backend, routed per exam type
+/// * `storage` - The exam storage backend, to dead-letter an unpublished message
+/// * `settings` - The storage settings, locating the dead letters
+/// * `received_at` - When the gateway received the exam
+/// * `stored_at` - When the exam was written to storage
+/// * `consent_scope` - The data-sharing consent of the hospital
+/// * `idempotency_key` - The idempotency key of the exam, set as attribute
+/// # Returns
+/// * Published, or DeadLettered if the publish failed after all retries
+/// # Errors
+/// * Returns an error if the routed destination is not covered by the consent, or if any step
+///   in the sending process fails and the message could not be dead-lettered
+async fn send_to_pubsub(
+    data: serde_json::Value,
+    publisher: &Arc<dyn Publisher>,
+    storage: &Arc<dyn ExamStorage>,
+    settings: &StorageSettings,
+    received_at: DateTime<Utc>,
+    stored_at: DateTime<Utc>,
+    consent_scope: ConsentScope,
+    idempotency_key: Option<&str>,
+) -> Result<Delivery> {
+    // STEP 1: Resolve the routed topic (possibly in a partner project) - only if consented
+    publisher.check_consent(EXAM_TYPE, consent_scope)?;
+    let topic_name = publisher.topic_name(EXAM_TYPE)?;
+    // The notification counts in the backlog until acked or dead-lettered
+    let _in_flight = PUBLISH_BACKLOG.track(BacklogState::InFlight);
+
+    // STEP 2: Create the PubSub message with the latency and consent attributes, in the format
+    // of the topic (JSON, Avro or both)
+    let published_at = Utc::now();
+    let mut attributes = latency_attributes(received_at, stored_at, published_at);
+    attributes.insert(
+        "consent_scope".to_string(),
+        consent_scope.as_str().to_string(),
+    );
+    // The trace joins the consumers' spans to the gateway's
+    attributes.extend(trace_attributes());
+    let (payload, format) = encode_message(topic_name, INFERENCE_XRAY_AVRO, &data, &mut attributes);
+    let mut message = NotificationMessage {
+        data: payload,
+        attributes,
+        ..Default::default()
+    };
+    // Retries of the same exam share the key, so the consumers can dedupe them
+    tag_message(&mut message, idempotency_key);
+    // Consumers filter on the routing attributes, and read the exams of a patient in order
+    tag_exam_message(&mut message, &data);
+
+    // STEP 3: Publish the message
+    let result = publisher.publish(EXAM_TYPE, &message).await;
+    let done_at = Utc::now();
+    observe_stage_between(EXAM_TYPE, Stage::Publish, published_at, done_at);
+    observe_stage_between(EXAM_TYPE, Stage::Total, received_at, done_at);
+    match result {
+        Ok(message_id) => {
+            info!("✅ Published with message ID: {:?}", message_id);
+            record_message_published(topic_name, format.as_str());
+            record_audit(
+                AuditEvent::exam(
+                    AuditAction::ExamPublished,
+                    EXAM_TYPE,
+                    data["exam_id"].as_str().unwrap_or_default(),
+                    data["hospital_id"].as_str().unwrap_or_default(),
+                )
+                .message_id(message_id),
+            );
+            Ok(Delivery::Published)
+        }
+        Err(e) => {
+            error!("❌ Failed to publish: {:?}", e);
+            // The exam is stored: keep the notification so it can be published later
+            let record = DeadLetterRecord {
+                exam_type: EXAM_TYPE.to_string(),
+                exam_id: data["exam_id"].as_str().unwrap_or_default().to_string(),
+                hospital_id: data["hospital_id"].as_str().unwrap_or_default().to_string(),
+                stage: FailedStage::Publish,
+                error: e.to_string(),
+                reason: ReasonCode::of_error(&e),
+                failed_at: Utc::now(),
+                payload: data,
+                attributes: message.attributes,
+            };
+            dead_letter(&record, storage, settings).await?;
+            Ok(Delivery::DeadLettered)
+        }
+    }
+}
 
 // TESTS *******************************************************************************************
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::schema_compat::{
+        avro_incompatibilities, proto_incompatibilities, ARCHIVE_EXAM_PROTO, INFERENCE_XRAY_AVRO,
+    };
+
+    fn hex64(c: char) -> String {
+        std::iter::repeat_n(c, 64).collect()
+    }
+
+    fn payload_with_png() -> PayloadXray {
+        let img = image::GrayImage::from_pixel(8, 8, image::Luma([10]));
+        let mut buffer = Cursor::new(Vec::new());
+        img.write_to(&mut buffer, ImageFormat::Png).unwrap();
+        PayloadXray {
+            patient_id: hex64('a').into(),
+            hospital_id: hex64('b').into(),
+            hospital_key: Some(hex64('c')),
+            image: STANDARD.encode(buffer.into_inner()),
+            view_position: Some("AP".to_string()),
+        }
+    }
+
+    // Happy path: image and sidecar share the exam id, the key is never stored
+    #[test]
+    fn preprocess_xray_happy_path() {
+        let p = payload_with_png();
+        let prepared =
+            preprocess_xray_data(&p, false, "dev-xray-v1", ConsentScope::Clinical).unwrap();
+        assert!(prepared
+            .exam_id
+            .starts_with(&format!("xray_exam/{}/{}/", p.hospital_id, p.patient_id)));
+        assert_eq!(
+            prepared.parquet.image_object,
+            format!("{}.png", prepared.exam_id)
+        );
+        assert_eq!(prepared.parquet.image_bytes, prepared.image.len() as u64);
+        assert_eq!(prepared.pubsub.topic, "dev-xray-v1");
+
+        let sidecar = serde_json::to_value(&prepared.parquet).unwrap();
+        assert!(sidecar.get("hospital_key").is_none());
+        assert_eq!(sidecar["consent_scope"], "clinical");
+        assert_eq!(sidecar["view_position"], "AP");
+        assert_eq!(sidecar["validation_profile_version"], 3);
+    }
+
+    // The published message stays readable by the downstream consumers (vendored schemas),
+    // with and without the DICOM metadata
+    #[test]
+    fn pubsub_matches_consumer_schemas() {
+        let mut prepared = preprocess_xray_data(
+            &payload_with_png(),
+            false,
+            "dev-xray-v1",
+            ConsentScope::Clinical,
+        )
+        .unwrap();
+        let png = serde_json::to_value(&prepared.pubsub).unwrap();
+        prepared.pubsub.dicom = Some(DicomMetadata {
+            modality: Some("DX".to_string()),
+            sop_class_uid: Some("1.2.840.10008.5.1.4.1.1.1.1".to_string()),
+            study_instance_uid: Some("2.25.1".to_string()),
+            series_instance_uid: Some("2.25.2".to_string()),
+            sop_instance_uid: Some("2.25.3".to_string()),
+            acquisition_datetime: Some("20260101120000".to_string()),
+            transfer_syntax_uid: "1.2.840.10008.1.2.1".to_string(),
+        });
+        let dicom = serde_json::to_value(&prepared.pubsub).unwrap();
+        for message in [png, dicom] {
+            assert_eq!(
+                avro_incompatibilities(INFERENCE_XRAY_AVRO, &message),
+                Vec::<String>::new()
+            );
+            assert_eq!(
+                proto_incompatibilities(ARCHIVE_EXAM_PROTO, "ExamNotification", &message),
+                Vec::<String>::new()
+            );
+        }
+    }
+
+    // Error handling: bytes that are not a supported image
+    #[test]
+    fn preprocess_xray_rejects_non_image() {
+        let mut p = payload_with_png();
+        p.image = STANDARD.encode(b"plain text");
+        assert!(preprocess_xray_data(&p, false, "dev-xray-v1", ConsentScope::Clinical).is_err());
+    }
+
+    // Borderline: the notification read by the consumers keeps its field names, order and types,
+    // with the DICOM metadata flattened into it
+    #[test]
+    fn pubsub_contract() {
+        let notification = XrayExamPubSub {
+            topic: "projects/sentinela/topics/xray".to_string(),
+            exam_id: "xray_exam/h1/p1/2026-10-01T120000.000Z".to_string(),
+            exam_type: "XRay Exam".to_string(),
+            timestamp: "2026-10-01T120000.000Z".to_string(),
+            patient_id: "p1".to_string(),
+            hospital_id: "h1".to_string(),
+            image_object: "xray_exam/h1/p1/2026-10-01T120000.000Z.dcm".to_string(),
+            deferred: true,
+            consent_scope: ConsentScope::Clinical,
+            object_path: "gs://exams/xray_exam/h1/p1/2026-10-01T120000.000Z.dcm".to_string(),
+            dicom: Some(DicomMetadata {
+                modality: Some("DX".to_string()),
+                study_instance_uid: Some("2.25.1".to_string()),
+                transfer_syntax_uid: "1.2.840.10008.1.2.1".to_string(),
+                ..Default::default()
+            }),
+        };
+        insta::assert_json_snapshot!(notification, @r#"
+        {
+          "topic": "projects/sentinela/topics/xray",
+          "exam_id": "xray_exam/h1/p1/2026-10-01T120000.000Z",
+          "exam_type": "XRay Exam",
+          "timestamp": "2026-10-01T120000.000Z",
+          "patient_id": "p1",
+          "hospital_id": "h1",
+          "image_object": "xray_exam/h1/p1/2026-10-01T120000.000Z.dcm",
+          "deferred": true,
+          "consent_scope": "clinical",
+          "object_path": "gs://exams/xray_exam/h1/p1/2026-10-01T120000.000Z.dcm",
+          "modality": "DX",
+          "study_instance_uid": "2.25.1",
+          "transfer_syntax_uid": "1.2.840.10008.1.2.1"
+        }
+        "#);
+    }
+}
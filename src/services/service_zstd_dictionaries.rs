@@ -0,0 +1,264 @@
+// Imports *****************************************************************************************
+// External Crates
+use anyhow::{anyhow, Result};
+use log::{info, warn};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::io::Read;
+use std::path::Path;
+use std::sync::OnceLock;
+use utoipa::ToSchema;
+
+// Internal Modules
+use crate::utils::api_error::ApiError;
+
+// Constants ***************************************************************************************
+/// Magic number opening a zstd dictionary, followed by its little-endian id (RFC 8878)
+const DICTIONARY_MAGIC: u32 = 0xEC30_A437;
+/// Extension of the dictionary files in ZSTD_DICTIONARY_DIR, named after their device model
+const DICTIONARY_EXTENSION: &str = "zdict";
+/// Request header naming the dictionary a `Content-Encoding: zstd` body was compressed with
+pub const DICTIONARY_HEADER: &str = "zstd_dictionary";
+
+// Structs *****************************************************************************************
+/// Pre-shared zstd dictionary, trained on the lead data of one device model
+/// # Arguments
+/// * `id` - The dictionary id, written in the dictionary and in the frames compressed with it
+/// * `device_model` - The device model the dictionary was trained for
+/// * `sha256` - The lowercase hex SHA256 of the dictionary, for the hospitals to check downloads
+/// * `size_bytes` - The size of the dictionary
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct ZstdDictionary {
+    pub id: u32,
+    pub device_model: String,
+    pub sha256: String,
+    pub size_bytes: usize,
+    #[serde(skip)]
+    pub bytes: Vec<u8>,
+}
+
+/// Dictionaries offered to the hospitals, by id
+#[derive(Debug, Default)]
+pub struct ZstdDictionaries {
+    dictionaries: BTreeMap<u32, ZstdDictionary>,
+}
+
+impl ZstdDictionaries {
+    /// Load the dictionaries of ZSTD_DICTIONARY_DIR, one `<device_model>.zdict` file per device
+    /// model - none when ZSTD_DICTIONARY_DIR is not set
+    /// # Arguments
+    /// * `dir` - The dictionary directory, None for no dictionary
+    /// # Errors
+    /// * Returns an error if the directory cannot be read, a file is not a zstd dictionary or two
+    ///   files share an id - the frames of a hospital are never decoded with the wrong dictionary
+    pub fn from_settings(dir: Option<&str>) -> Result<Self> {
+        match dir {
+            Some(dir) => Self::from_dir(Path::new(dir)),
+            None => Ok(Self::default()),
+        }
+    }
+
+    /// Load every `.zdict` file of a directory
+    /// # Errors
+    /// * Returns an error if a file cannot be read or added
+    fn from_dir(dir: &Path) -> Result<Self> {
+        let mut dictionaries = Self::default();
+        let entries = std::fs::read_dir(dir)
+            .map_err(|e| anyhow!("Cannot read ZSTD_DICTIONARY_DIR {}: {e}", dir.display()))?;
+        for entry in entries {
+            let path = entry?.path();
+            if path.extension().and_then(|e| e.to_str()) != Some(DICTIONARY_EXTENSION) {
+                continue;
+            }
+            let device_model = path
+                .file_stem()
+                .and_then(|s| s.to_str())
+                .unwrap_or_default()
+                .to_string();
+            dictionaries.add(&device_model, std::fs::read(&path)?)?;
+        }
+        Ok(dictionaries)
+    }
+
+    /// Add the dictionary of a device model
+    /// # Errors
+    /// * Returns an error if the bytes are not a zstd dictionary with an id, or the id is taken
+    fn add(&mut self, device_model: &str, bytes: Vec<u8>) -> Result<()> {
+        let id = dictionary_id(&bytes).ok_or_else(|| {
+            anyhow!("zstd dictionary of {device_model} has no dictionary header or a zero id")
+        })?;
+        if let Some(existing) = self.dictionaries.get(&id) {
+            return Err(anyhow!(
+                "zstd dictionaries of {} and {device_model} share the id {id}",
+                existing.device_model
+            ));
+        }
+        let dictionary = ZstdDictionary {
+            id,
+            device_model: device_model.to_string(),
+            sha256: format!("{:x}", Sha256::digest(&bytes)),
+            size_bytes: bytes.len(),
+            bytes,
+        };
+        self.dictionaries.insert(id, dictionary);
+        Ok(())
+    }
+}
+
+// Global variables ********************************************************************************
+static ZSTD_DICTIONARIES: OnceLock<ZstdDictionaries> = OnceLock::new();
+
+// MAIN FUNCTIONS **********************************************************************************
+/// Set the dictionaries offered to the hospitals - called once at startup
+/// # Arguments
+/// * `dictionaries` - The dictionaries of ZSTD_DICTIONARY_DIR
+pub fn init_zstd_dictionaries(dictionaries: ZstdDictionaries) {
+    for dictionary in dictionaries.dictionaries.values() {
+        info!(
+            "zstd dictionary {} for {} ({} bytes, sha256 {})",
+            dictionary.id, dictionary.device_model, dictionary.size_bytes, dictionary.sha256
+        );
+    }
+    if ZSTD_DICTIONARIES.set(dictionaries).is_err() {
+        warn!("zstd dictionaries already initialized");
+    }
+}
+
+/// Every dictionary offered to the hospitals, by id
+pub fn list_zstd_dictionaries() -> Vec<&'static ZstdDictionary> {
+    ZSTD_DICTIONARIES
+        .get()
+        .map(|d| d.dictionaries.values().collect())
+        .unwrap_or_default()
+}
+
+/// The dictionary of an id, if the gateway has it
+pub fn zstd_dictionary(id: u32) -> Option<&'static ZstdDictionary> {
+    ZSTD_DICTIONARIES.get()?.dictionaries.get(&id)
+}
+
+/// Dictionary id referenced by the `zstd_dictionary` header of a request
+/// # Arguments
+/// * `value` - The header value, a decimal dictionary id
+/// # Errors
+/// * Returns BadRequest if the value is not a dictionary id
+pub fn dictionary_reference(value: &str) -> Result<u32, ApiError> {
+    match value.trim().parse::<u32>() {
+        Ok(id) if id > 0 => Ok(id),
+        _ => Err(ApiError::BadRequest(format!(
+            "{DICTIONARY_HEADER} must be a dictionary id of /v1/zstd_dictionaries"
+        ))),
+    }
+}
+
+/// Decompress a zstd body with its dictionary, stopping once it expands beyond `limit` bytes
+/// # Arguments
+/// * `body` - The compressed body, within the tier limit
+/// * `dictionary` - The dictionary the body was compressed with
+/// * `limit` - The decompressed size limit in bytes
+/// # Returns
+/// * The decompressed body
+/// # Errors
+/// * Returns PayloadTooLarge beyond the limit, or BadRequest if the body is not a zstd frame
+///   compressed with that dictionary
+pub fn decompress_with_dictionary(
+    body: &[u8],
+    dictionary: &ZstdDictionary,
+    limit: usize,
+) -> Result<Vec<u8>, ApiError> {
+    let undecodable = |_| ApiError::BadRequest("The compressed body could not be decoded".into());
+    let decoder = zstd::stream::read::Decoder::with_dictionary(body, &dictionary.bytes)
+        .map_err(undecodable)?;
+    let mut decompressed = Vec::new();
+    decoder
+        .take(limit as u64 + 1)
+        .read_to_end(&mut decompressed)
+        .map_err(undecodable)?;
+    if decompressed.len() > limit {
+        return Err(ApiError::PayloadTooLarge);
+    }
+    Ok(decompressed)
+}
+
+// SUPPORT FUNCTIONS *******************************************************************************
+/// Id written in the header of a zstd dictionary
+/// # Returns
+/// * None for raw content (no dictionary header) or the reserved id 0
+fn dictionary_id(bytes: &[u8]) -> Option<u32> {
+    let magic = u32::from_le_bytes(bytes.get(0..4)?.try_into().ok()?);
+    let id = u32::from_le_bytes(bytes.get(4..8)?.try_into().ok()?);
+    (magic == DICTIONARY_MAGIC && id != 0).then_some(id)
+}
+
+// TESTS *******************************************************************************************
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::ResponseError;
+
+    /// Dictionary trained on samples shaped like serialized leads
+    fn trained_dictionary() -> Vec<u8> {
+        let samples: Vec<Vec<u8>> = (0..200)
+            .map(|i| {
+                let lead: Vec<String> = (0..50)
+                    .map(|t| format!("{:.3}", ((i * 7 + t) % 40) as f32 * 0.025))
+                    .collect();
+                format!("{{\"lead_i\":[{}]}}", lead.join(",")).into_bytes()
+            })
+            .collect();
+        zstd::dict::from_samples(&samples, 4_096).unwrap()
+    }
+
+    // Happy path: a body compressed with the dictionary is decoded with it
+    #[test]
+    fn round_trip_with_dictionary() {
+        let mut dictionaries = ZstdDictionaries::default();
+        dictionaries.add("monitor_x", trained_dictionary()).unwrap();
+        let dictionary = dictionaries.dictionaries.values().next().unwrap();
+        assert_eq!(dictionary_id(&dictionary.bytes), Some(dictionary.id));
+        assert_eq!(dictionary.sha256.len(), 64);
+
+        let body = br#"{"lead_i":[0.025,0.050,0.075,0.100]}"#.repeat(20);
+        let mut compressor = zstd::bulk::Compressor::with_dictionary(3, &dictionary.bytes).unwrap();
+        let compressed = compressor.compress(&body).unwrap();
+        assert_eq!(
+            decompress_with_dictionary(&compressed, dictionary, body.len()).unwrap(),
+            body
+        );
+        assert_eq!(
+            decompress_with_dictionary(&compressed, dictionary, body.len() - 1),
+            Err(ApiError::PayloadTooLarge)
+        );
+    }
+
+    // Error handling: raw content and duplicate ids are refused at startup
+    #[test]
+    fn invalid_dictionaries_refused() {
+        let mut dictionaries = ZstdDictionaries::default();
+        assert!(dictionaries
+            .add("raw", b"just some bytes".to_vec())
+            .is_err());
+        let dictionary = trained_dictionary();
+        dictionaries.add("monitor_x", dictionary.clone()).unwrap();
+        let error = dictionaries.add("monitor_y", dictionary).unwrap_err();
+        assert!(error.to_string().contains("share the id"));
+    }
+
+    // Borderline: the header must name a dictionary id, and garbage is not a zstd frame
+    #[test]
+    fn references_and_garbage() {
+        assert_eq!(dictionary_reference(" 42 "), Ok(42));
+        for refused in ["0", "-1", "monitor_x", ""] {
+            assert_eq!(
+                dictionary_reference(refused).unwrap_err().status_code(),
+                400
+            );
+        }
+        let mut dictionaries = ZstdDictionaries::default();
+        dictionaries.add("monitor_x", trained_dictionary()).unwrap();
+        let dictionary = dictionaries.dictionaries.values().next().unwrap();
+        let error = decompress_with_dictionary(b"not zstd", dictionary, 1_000).unwrap_err();
+        assert_eq!(error.status_code(), 400);
+    }
+}
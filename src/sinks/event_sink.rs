@@ -0,0 +1,58 @@
+// Imports *****************************************************************************************
+// External Crates
+use anyhow::Result;
+use async_trait::async_trait;
+
+// Internal Modules
+use crate::authentication::gcp_identity::GcpIdentity;
+use crate::config::settings::SinkTarget;
+use crate::publishers::publisher::PubSubClients;
+#[cfg(feature = "bigquery")]
+use crate::sinks::sink_bigquery::BigQuerySink;
+use crate::sinks::sink_log::LogSink;
+#[cfg(feature = "grpc")]
+use crate::sinks::sink_pubsub::PubSubSink;
+
+// MAIN TRAIT **************************************************************************************
+/// Destination of structured events (billing, audit, digests) emitted by the services
+/// Implementations must be cheap to share between workers
+#[async_trait]
+pub trait EventSink: Send + Sync {
+    /// Short name of the sink, used in logs
+    fn name(&self) -> &'static str;
+
+    /// Emit one event
+    /// # Arguments
+    /// * `event` - The event as a JSON object
+    /// # Errors
+    /// * Returns an error if the event could not be delivered
+    async fn emit(&self, event: &serde_json::Value) -> Result<()>;
+}
+
+// MAIN FUNCTIONS **********************************************************************************
+/// Sink of an event stream as configured: a Pub/Sub topic, a BigQuery table, or log lines
+/// # Arguments
+/// * `target` - The configured sink
+/// * `pubsub` - The Pub/Sub clients, used by the `pubsub` sink
+/// * `identity` - The identity of the service, used by the `bigquery` sink
+/// * `log_target` - The log target of the `log` sink
+#[cfg_attr(
+    not(all(feature = "grpc", feature = "bigquery")),
+    allow(unused_variables)
+)]
+pub fn sink_of(
+    target: &SinkTarget,
+    pubsub: &PubSubClients,
+    identity: &GcpIdentity,
+    log_target: &'static str,
+) -> Box<dyn EventSink> {
+    match target {
+        SinkTarget::Log => Box::new(LogSink::new(log_target)),
+        #[cfg(feature = "grpc")]
+        SinkTarget::PubSub(topic) => Box::new(PubSubSink::new(
+            pubsub.client.topic(topic.as_str()).new_publisher(None),
+        )),
+        #[cfg(feature = "bigquery")]
+        SinkTarget::BigQuery(table) => Box::new(BigQuerySink::new(table.clone(), identity.clone())),
+    }
+}
@@ -0,0 +1,7 @@
+pub mod event_sink;
+#[cfg(feature = "bigquery")]
+pub mod sink_bigquery;
+pub mod sink_cloud_logging;
+pub mod sink_log;
+#[cfg(feature = "grpc")]
+pub mod sink_pubsub;
@@ -0,0 +1,43 @@
+// Imports *****************************************************************************************
+// External Crates
+use anyhow::Result;
+use async_trait::async_trait;
+use std::io::Write;
+
+// Internal Modules
+use crate::sinks::event_sink::EventSink;
+
+// MAIN STRUCT *************************************************************************************
+/// Sink writing every event as one JSON line on stdout - on Cloud Run and GKE the logging agent
+/// turns each line into a structured Cloud Logging entry, reading its `severity`, `message`,
+/// `logging.googleapis.com/labels` and `logging.googleapis.com/trace` fields
+/// Log lines go to stderr, so the two never interleave
+pub struct CloudLoggingSink;
+
+#[async_trait]
+impl EventSink for CloudLoggingSink {
+    fn name(&self) -> &'static str {
+        "cloud_logging"
+    }
+
+    async fn emit(&self, event: &serde_json::Value) -> Result<()> {
+        let line = serde_json::to_string(event)?;
+        let mut stdout = std::io::stdout().lock();
+        writeln!(stdout, "{line}")?;
+        stdout.flush()?;
+        Ok(())
+    }
+}
+
+// TESTS *******************************************************************************************
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn cloud_logging_sink_accepts_events() {
+        let sink = CloudLoggingSink;
+        let event = serde_json::json!({ "severity": "INFO", "message": "test" });
+        assert!(sink.emit(&event).await.is_ok());
+    }
+}
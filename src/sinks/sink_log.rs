@@ -0,0 +1,45 @@
+// Imports *****************************************************************************************
+// External Crates
+use anyhow::Result;
+use async_trait::async_trait;
+use log::info;
+
+// Internal Modules
+use crate::sinks::event_sink::EventSink;
+
+// MAIN STRUCT *************************************************************************************
+/// Sink writing events as log lines under a dedicated target - for local development
+pub struct LogSink {
+    target: &'static str,
+}
+
+impl LogSink {
+    /// Create a sink logging under the given target (e.g. `billing`)
+    pub fn new(target: &'static str) -> Self {
+        Self { target }
+    }
+}
+
+#[async_trait]
+impl EventSink for LogSink {
+    fn name(&self) -> &'static str {
+        "log"
+    }
+
+    async fn emit(&self, event: &serde_json::Value) -> Result<()> {
+        info!(target: self.target, "{event}");
+        Ok(())
+    }
+}
+
+// TESTS *******************************************************************************************
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn log_sink_accepts_events() {
+        let sink = LogSink::new("test");
+        assert!(sink.emit(&serde_json::json!({ "a": 1 })).await.is_ok());
+    }
+}
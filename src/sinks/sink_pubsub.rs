@@ -0,0 +1,46 @@
+// Imports *****************************************************************************************
+// External Crates
+use anyhow::Result;
+use async_trait::async_trait;
+use google_cloud_googleapis::pubsub::v1::PubsubMessage;
+use google_cloud_pubsub::publisher::Publisher;
+
+// Internal Modules
+use crate::sinks::event_sink::EventSink;
+use crate::utils::external_call::{Dependency, ExternalCall};
+
+// MAIN STRUCT *************************************************************************************
+/// Sink publishing every event as a JSON message to a Pub/Sub topic
+/// A BigQuery subscription on the topic lands the events in a table without extra code
+pub struct PubSubSink {
+    publisher: Publisher,
+}
+
+impl PubSubSink {
+    /// Create a sink over an existing publisher
+    pub fn new(publisher: Publisher) -> Self {
+        Self { publisher }
+    }
+}
+
+#[async_trait]
+impl EventSink for PubSubSink {
+    fn name(&self) -> &'static str {
+        "pubsub"
+    }
+
+    async fn emit(&self, event: &serde_json::Value) -> Result<()> {
+        let message = PubsubMessage {
+            data: serde_json::to_vec(event)?,
+            ..Default::default()
+        };
+        ExternalCall::new(Dependency::PubSub, "publish_event")
+            .retries(1)
+            .run(|| async { self.publisher.publish(message.clone()).await.get().await })
+            .await?;
+        Ok(())
+    }
+}
+
+// TESTS *******************************************************************************************
+// DOCUMENTATION: Thin wrapper over the Pub/Sub publisher - covered by integration tests
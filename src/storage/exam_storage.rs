@@ -0,0 +1,316 @@
+// Imports *****************************************************************************************
+// External Crates
+use actix_web::web::Bytes;
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use futures_util::Stream;
+use log::info;
+use std::fmt::Display;
+use std::io;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::Duration;
+
+// Internal Modules
+use crate::authentication::gcp_identity::GcsAccess;
+use crate::config::settings::{GcpSettings, StorageBackend, StorageSettings};
+use crate::storage::storage_gcs::GcsStorage;
+use crate::storage::storage_local::LocalStorage;
+use crate::storage::storage_s3::S3Storage;
+
+// Constants ***************************************************************************************
+/// Content type of an object written without one (Parquet records and sidecars)
+pub const DEFAULT_CONTENT_TYPE: &str = "application/octet-stream";
+
+// Types *******************************************************************************************
+/// Body of a streamed upload, as received from the hospital
+pub type ByteStream = Pin<Box<dyn Stream<Item = Result<Bytes, io::Error>> + Send + Sync>>;
+/// Content of a streamed download, as read from the backend
+pub type DownloadStream = Pin<Box<dyn Stream<Item = Result<Bytes, io::Error>> + Send>>;
+
+// Structs *****************************************************************************************
+/// Object to write
+/// # Arguments
+/// * `bucket` - The bucket (GCS, S3) or directory (local) of the object
+/// * `name` - The object name, from `ExamStorage::object_name`
+/// * `content_type` - The content type, DEFAULT_CONTENT_TYPE if None
+/// * `operation` - A short name of the write for logs and metrics, e.g. `upload_object`
+/// * `retries` - Additional attempts on timeouts and transient errors
+#[derive(Debug, Clone, Copy)]
+pub struct ObjectPut<'a> {
+    pub bucket: &'a str,
+    pub name: &'a str,
+    pub content_type: Option<&'a str>,
+    pub operation: &'static str,
+    pub retries: u32,
+}
+
+impl ObjectPut<'_> {
+    /// The content type of the object
+    pub fn content_type(&self) -> &str {
+        self.content_type.unwrap_or(DEFAULT_CONTENT_TYPE)
+    }
+}
+
+/// Object as listed by the backend
+/// # Arguments
+/// * `name` - The object name
+/// * `size` - The size of the object in bytes
+/// * `generation` - The GCS generation of the object, None for the backends without generations
+/// * `created_at` - When the object was written, if known
+/// * `updated_at` - When the object was last updated, if known
+/// * `md5_hash` - The base64 MD5 of the object, as reported by GCS
+/// * `crc32c` - The base64 CRC32C of the object, as reported by GCS
+/// * `held` - Whether a retention hold keeps the object from being deleted (GCS)
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct StoredObject {
+    pub name: String,
+    pub size: u64,
+    pub generation: Option<i64>,
+    pub created_at: Option<DateTime<Utc>>,
+    pub updated_at: Option<DateTime<Utc>>,
+    pub md5_hash: Option<String>,
+    pub crc32c: Option<String>,
+    pub held: bool,
+}
+
+/// One page of a listing
+/// # Arguments
+/// * `objects` - The objects of the page, by name
+/// * `next_page_token` - The token of the next page, None on the last one
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ObjectPage {
+    pub objects: Vec<StoredObject>,
+    pub next_page_token: Option<String>,
+}
+
+/// Storage of the read path (exam export, plugins) - with GCS, a client of its own read-only
+/// identity
+#[derive(Clone)]
+pub struct ReadOnlyStorage(pub Arc<dyn ExamStorage>);
+
+/// Answers of the backend that are not failures of the storage - callers find them back in the
+/// context chain of the error (`downcast_ref`)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StorageError {
+    /// The object does not exist
+    NotFound(String),
+    /// The object changed since it was listed, or the destination of a copy already exists
+    Conflict(String),
+}
+
+impl StorageError {
+    /// Answer of an HTTP status - 404 is not found, any other status a conflict (409, 412)
+    /// # Arguments
+    /// * `status` - The HTTP status answered
+    /// * `location` - The location of the object
+    pub(crate) fn from_status(status: u16, location: String) -> Self {
+        match status {
+            404 => StorageError::NotFound(location),
+            _ => StorageError::Conflict(location),
+        }
+    }
+
+    /// Whether an error is the not-found answer of a backend
+    pub fn is_not_found(error: &anyhow::Error) -> bool {
+        matches!(
+            error.downcast_ref::<StorageError>(),
+            Some(StorageError::NotFound(_))
+        )
+    }
+
+    /// Whether an error is the conflict answer of a backend
+    pub fn is_conflict(error: &anyhow::Error) -> bool {
+        matches!(
+            error.downcast_ref::<StorageError>(),
+            Some(StorageError::Conflict(_))
+        )
+    }
+}
+
+impl Display for StorageError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StorageError::NotFound(location) => write!(f, "Object {location} not found"),
+            StorageError::Conflict(location) => {
+                write!(f, "Object {location} changed or already exists")
+            }
+        }
+    }
+}
+
+impl std::error::Error for StorageError {}
+
+// MAIN TRAIT **************************************************************************************
+/// Object storage of the exams, their sidecars, dead letters and research samples
+/// Implementations must be cheap to share between workers
+#[async_trait]
+pub trait ExamStorage: Send + Sync {
+    /// Short name of the backend, used in logs
+    fn name(&self) -> &'static str;
+
+    /// Location of an object, for logs and audit events (e.g. `gs://bucket/name`)
+    fn location(&self, bucket: &str, name: &str) -> String;
+
+    /// Object naming policy: every object of an exam is named after its exam id
+    /// # Arguments
+    /// * `exam_id` - The exam id, `{exam_type}/{hospital_id}/{patient_id}/{uuid}`
+    /// * `extension` - The extension of the object, e.g. `parquet`
+    fn object_name(&self, exam_id: &str, extension: &str) -> String {
+        format!("{exam_id}.{extension}")
+    }
+
+    /// Write an object in one request
+    /// # Arguments
+    /// * `object` - The object to write
+    /// * `bytes` - The content of the object
+    /// # Returns
+    /// * The number of bytes stored
+    /// # Errors
+    /// * Returns an error if the object could not be written after its retries
+    async fn put_object(&self, object: &ObjectPut<'_>, bytes: Vec<u8>) -> Result<u64>;
+
+    /// Write an object as its body is received - single attempt, the body cannot be replayed
+    /// # Arguments
+    /// * `object` - The object to write
+    /// * `body` - The content of the object, as received
+    /// * `timeout` - The timeout of the whole upload
+    /// # Returns
+    /// * The number of bytes stored
+    /// # Errors
+    /// * Returns an error if the body was interrupted or the object could not be written
+    async fn put_stream(
+        &self,
+        object: &ObjectPut<'_>,
+        body: ByteStream,
+        timeout: Duration,
+    ) -> Result<u64>;
+
+    /// List one page of the objects under a prefix
+    /// # Arguments
+    /// * `bucket` - The bucket (GCS, S3) or directory (local)
+    /// * `prefix` - The prefix of the object names, empty for all
+    /// * `page_token` - The token of the page, None for the first one
+    /// # Errors
+    /// * Returns an error if the listing failed after its retries
+    async fn list_objects(
+        &self,
+        bucket: &str,
+        prefix: &str,
+        page_token: Option<String>,
+    ) -> Result<ObjectPage>;
+
+    /// Read a whole object
+    /// # Arguments
+    /// * `bucket` - The bucket (GCS, S3) or directory (local)
+    /// * `name` - The object name
+    /// # Errors
+    /// * Returns `StorageError::NotFound` if the object does not exist, or an error if it could
+    ///   not be read after its retries
+    async fn get_object(&self, bucket: &str, name: &str) -> Result<Vec<u8>>;
+
+    /// Describe an object without reading it
+    /// # Arguments
+    /// * `bucket` - The bucket (GCS, S3) or directory (local)
+    /// * `name` - The object name
+    /// # Errors
+    /// * Returns `StorageError::NotFound` if the object does not exist, or an error if it could
+    ///   not be described after its retries
+    async fn object_info(&self, bucket: &str, name: &str) -> Result<StoredObject>;
+
+    /// Read an object, or a byte range of it, as it is downloaded
+    /// # Arguments
+    /// * `bucket` - The bucket (GCS, S3) or directory (local)
+    /// * `name` - The object name
+    /// * `range` - Inclusive byte range to read, None for the whole object
+    /// # Returns
+    /// * The content, ending with an error if the download fails midway
+    /// # Errors
+    /// * Returns `StorageError::NotFound` if the object does not exist, or an error if the
+    ///   download could not start after its retries
+    async fn get_stream(
+        &self,
+        bucket: &str,
+        name: &str,
+        range: Option<(u64, u64)>,
+    ) -> Result<DownloadStream>;
+
+    /// Copy an object within its bucket, unless the destination already exists
+    /// # Arguments
+    /// * `bucket` - The bucket (GCS, S3) or directory (local)
+    /// * `from` - The object copied, as listed - with GCS, only the generation listed is copied
+    /// * `to` - The name of the copy
+    /// # Errors
+    /// * Returns `StorageError::Conflict` if the destination exists (or the source changed since
+    ///   it was listed), `StorageError::NotFound` if the source does not exist, or an error if the
+    ///   object could not be copied
+    async fn copy_object(&self, bucket: &str, from: &StoredObject, to: &str) -> Result<()>;
+
+    /// Delete an object
+    /// # Arguments
+    /// * `bucket` - The bucket (GCS, S3) or directory (local)
+    /// * `name` - The object name
+    /// * `generation` - The generation listed (GCS): only that generation is deleted, never an
+    ///   object rewritten since the listing, and no noncurrent version is left behind
+    /// # Errors
+    /// * Returns `StorageError::NotFound` if the object (or its generation) does not exist, or an
+    ///   error if it could not be deleted
+    async fn delete_object(&self, bucket: &str, name: &str, generation: Option<i64>) -> Result<()>;
+}
+
+// MAIN FUNCTIONS **********************************************************************************
+/// Storage backend selected by STORAGE_BACKEND - a GCS client is only built for the `gcs`
+/// backend, with the identity and scope of its access level
+/// # Arguments
+/// * `settings` - The storage settings
+/// * `gcp` - The GCP settings, naming the service account of each access level
+/// * `access` - Read-write for ingestion, read-only for the read path (export, plugins)
+/// # Errors
+/// * Returns an error if the GCS client cannot authenticate or the S3 backend has no endpoint or
+///   credentials
+pub async fn storage_from_settings(
+    settings: &StorageSettings,
+    gcp: &GcpSettings,
+    access: GcsAccess,
+) -> Result<Arc<dyn ExamStorage>> {
+    let storage: Arc<dyn ExamStorage> = match settings.backend {
+        StorageBackend::Gcs => {
+            let client = crate::init_gcs_client(gcp, access)
+                .await
+                .map_err(|e| anyhow!(e.to_string()))?;
+            Arc::new(GcsStorage::new(client))
+        }
+        StorageBackend::Local => Arc::new(LocalStorage::new(&settings.local_dir)),
+        StorageBackend::S3 => {
+            let s3 = settings
+                .s3
+                .clone()
+                .ok_or_else(|| anyhow!("STORAGE_BACKEND=s3 requires the S3_* settings"))?;
+            Arc::new(S3Storage::new(s3)?)
+        }
+    };
+    info!(
+        "Exam storage ({}): {} ({})",
+        access.as_str(),
+        storage.name(),
+        storage.location(&settings.bucket_name, "")
+    );
+    Ok(storage)
+}
+
+// TESTS *******************************************************************************************
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Happy path: objects are named after their exam id, whatever the backend
+    #[test]
+    fn objects_named_after_exam_id() {
+        let storage = LocalStorage::new("/tmp/sentinela");
+        assert_eq!(
+            storage.object_name("ecg_exam/h/p/2026", "parquet"),
+            "ecg_exam/h/p/2026.parquet"
+        );
+    }
+}
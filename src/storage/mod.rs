@@ -0,0 +1,4 @@
+pub mod exam_storage;
+pub mod storage_gcs;
+pub mod storage_local;
+pub mod storage_s3;
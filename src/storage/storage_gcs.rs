@@ -0,0 +1,255 @@
+// Imports *****************************************************************************************
+// External Crates
+use anyhow::Result;
+use async_trait::async_trait;
+use chrono::DateTime;
+use futures_util::TryStreamExt;
+use google_cloud_storage::client::Client as GcsClient;
+use google_cloud_storage::http::objects::copy::CopyObjectRequest;
+use google_cloud_storage::http::objects::delete::DeleteObjectRequest;
+use google_cloud_storage::http::objects::download::Range;
+use google_cloud_storage::http::objects::get::GetObjectRequest;
+use google_cloud_storage::http::objects::list::ListObjectsRequest;
+use google_cloud_storage::http::objects::upload::{Media, UploadObjectRequest};
+use google_cloud_storage::http::objects::Object;
+use google_cloud_storage::http::Error as GcsError;
+use std::borrow::Cow;
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+
+// Internal Modules
+use crate::storage::exam_storage::{
+    ByteStream, DownloadStream, ExamStorage, ObjectPage, ObjectPut, StorageError, StoredObject,
+};
+use crate::telemetry::trace_context::traced_upload_type;
+use crate::utils::external_call::{Dependency, ExternalCall};
+
+// MAIN STRUCT *************************************************************************************
+/// Google Cloud Storage, with the client of the ingestion identity (STORAGE_BACKEND=gcs)
+/// The trace of the request is attached to every object as metadata
+pub struct GcsStorage {
+    client: Arc<GcsClient>,
+}
+
+impl GcsStorage {
+    /// Create a storage over an existing client
+    pub fn new(client: Arc<GcsClient>) -> Self {
+        Self { client }
+    }
+}
+
+#[async_trait]
+impl ExamStorage for GcsStorage {
+    fn name(&self) -> &'static str {
+        "gcs"
+    }
+
+    fn location(&self, bucket: &str, name: &str) -> String {
+        format!("gs://{bucket}/{name}")
+    }
+
+    async fn put_object(&self, object: &ObjectPut<'_>, bytes: Vec<u8>) -> Result<u64> {
+        let request = UploadObjectRequest {
+            bucket: object.bucket.to_string(),
+            ..Default::default()
+        };
+        let mut media = Media::new(Cow::Owned(object.name.to_string()));
+        media.content_type = Cow::Owned(object.content_type().to_string());
+        let upload_type = traced_upload_type(media);
+        let size = bytes.len() as u64;
+        ExternalCall::new(Dependency::Gcs, object.operation)
+            .bucket(object.bucket)
+            .retries(object.retries)
+            .run(|| {
+                self.client
+                    .upload_object(&request, bytes.clone(), &upload_type)
+            })
+            .await?;
+        Ok(size)
+    }
+
+    async fn put_stream(
+        &self,
+        object: &ObjectPut<'_>,
+        body: ByteStream,
+        timeout: Duration,
+    ) -> Result<u64> {
+        let request = UploadObjectRequest {
+            bucket: object.bucket.to_string(),
+            ..Default::default()
+        };
+        let mut media = Media::new(Cow::Owned(object.name.to_string()));
+        media.content_type = Cow::Owned(object.content_type().to_string());
+        let upload_type = traced_upload_type(media);
+        let mut body = Some(body);
+        let stored = ExternalCall::new(Dependency::Gcs, object.operation)
+            .bucket(object.bucket)
+            .timeout(timeout)
+            .run(|| {
+                let body = body.take();
+                let (request, upload_type) = (&request, &upload_type);
+                async move {
+                    match body {
+                        Some(body) => self
+                            .client
+                            .upload_streamed_object(request, body, upload_type)
+                            .await
+                            .map_err(|e| e.to_string()),
+                        None => Err("upload body already consumed".to_string()),
+                    }
+                }
+            })
+            .await?;
+        Ok(u64::try_from(stored.size).unwrap_or_default())
+    }
+
+    async fn list_objects(
+        &self,
+        bucket: &str,
+        prefix: &str,
+        page_token: Option<String>,
+    ) -> Result<ObjectPage> {
+        let request = ListObjectsRequest {
+            bucket: bucket.to_string(),
+            prefix: Some(prefix.to_string()).filter(|prefix| !prefix.is_empty()),
+            page_token,
+            ..Default::default()
+        };
+        let page = ExternalCall::new(Dependency::Gcs, "list_objects")
+            .bucket(bucket)
+            .retries(2)
+            .run(|| self.client.list_objects(&request))
+            .await?;
+        Ok(ObjectPage {
+            objects: page
+                .items
+                .unwrap_or_default()
+                .into_iter()
+                .map(stored_object)
+                .collect(),
+            next_page_token: page.next_page_token,
+        })
+    }
+
+    async fn get_object(&self, bucket: &str, name: &str) -> Result<Vec<u8>> {
+        let request = GetObjectRequest {
+            bucket: bucket.to_string(),
+            object: name.to_string(),
+            ..Default::default()
+        };
+        let range = Range::default();
+        ExternalCall::new(Dependency::Gcs, "download_object")
+            .bucket(bucket)
+            .retries(2)
+            .run(|| answered(self.client.download_object(&request, &range)))
+            .await?
+            .map_err(|status| StorageError::from_status(status, self.location(bucket, name)).into())
+    }
+
+    async fn object_info(&self, bucket: &str, name: &str) -> Result<StoredObject> {
+        let request = GetObjectRequest {
+            bucket: bucket.to_string(),
+            object: name.to_string(),
+            ..Default::default()
+        };
+        let object = ExternalCall::new(Dependency::Gcs, "get_object")
+            .bucket(bucket)
+            .retries(2)
+            .run(|| answered(self.client.get_object(&request)))
+            .await?
+            .map_err(|status| StorageError::from_status(status, self.location(bucket, name)))?;
+        Ok(stored_object(object))
+    }
+
+    async fn get_stream(
+        &self,
+        bucket: &str,
+        name: &str,
+        range: Option<(u64, u64)>,
+    ) -> Result<DownloadStream> {
+        let request = GetObjectRequest {
+            bucket: bucket.to_string(),
+            object: name.to_string(),
+            ..Default::default()
+        };
+        let range = match range {
+            Some((from, to)) => Range(Some(from), Some(to)),
+            None => Range::default(),
+        };
+        let chunks = ExternalCall::new(Dependency::Gcs, "download_streamed_object")
+            .bucket(bucket)
+            .retries(2)
+            .run(|| answered(self.client.download_streamed_object(&request, &range)))
+            .await?
+            .map_err(|status| StorageError::from_status(status, self.location(bucket, name)))?;
+        Ok(Box::pin(chunks.map_err(std::io::Error::other)))
+    }
+
+    async fn copy_object(&self, bucket: &str, from: &StoredObject, to: &str) -> Result<()> {
+        // Generation 0 only creates the destination if it does not exist yet
+        let request = CopyObjectRequest {
+            source_bucket: bucket.to_string(),
+            source_object: from.name.clone(),
+            destination_bucket: bucket.to_string(),
+            destination_object: to.to_string(),
+            if_generation_match: Some(0),
+            if_source_generation_match: from.generation,
+            ..Default::default()
+        };
+        ExternalCall::new(Dependency::Gcs, "copy_object")
+            .bucket(bucket)
+            .run(|| answered(self.client.copy_object(&request)))
+            .await?
+            .map(|_| ())
+            .map_err(|status| StorageError::from_status(status, self.location(bucket, to)).into())
+    }
+
+    async fn delete_object(&self, bucket: &str, name: &str, generation: Option<i64>) -> Result<()> {
+        let request = DeleteObjectRequest {
+            bucket: bucket.to_string(),
+            object: name.to_string(),
+            generation,
+            ..Default::default()
+        };
+        ExternalCall::new(Dependency::Gcs, "delete_object")
+            .bucket(bucket)
+            .run(|| answered(self.client.delete_object(&request)))
+            .await?
+            .map_err(|status| StorageError::from_status(status, self.location(bucket, name)).into())
+    }
+}
+
+// SUPPORT FUNCTIONS *******************************************************************************
+/// Outcome of a GCS call, with its not-found (404) and precondition (412) statuses as answers
+/// rather than failures, so they are neither retried nor counted as storage faults
+async fn answered<T>(
+    call: impl Future<Output = Result<T, GcsError>>,
+) -> Result<Result<T, u16>, GcsError> {
+    match call.await {
+        Ok(value) => Ok(Ok(value)),
+        Err(GcsError::Response(e)) if matches!(e.code, 404 | 412) => Ok(Err(e.code)),
+        Err(e) => Err(e),
+    }
+}
+
+/// Object of a GCS listing
+fn stored_object(object: Object) -> StoredObject {
+    StoredObject {
+        size: u64::try_from(object.size).unwrap_or_default(),
+        generation: Some(object.generation),
+        created_at: object
+            .time_created
+            .and_then(|at| DateTime::from_timestamp(at.unix_timestamp(), at.nanosecond())),
+        updated_at: object
+            .updated
+            .and_then(|at| DateTime::from_timestamp(at.unix_timestamp(), at.nanosecond())),
+        md5_hash: object.md5_hash,
+        crc32c: object.crc32c,
+        held: object.temporary_hold == Some(true) || object.event_based_hold == Some(true),
+        name: object.name,
+    }
+}
+
+// TESTS *******************************************************************************************
+// DOCUMENTATION: Thin wrapper over the GCS client - covered by integration tests
@@ -0,0 +1,455 @@
+// Imports *****************************************************************************************
+// External Crates
+use actix_web::web::{self, Bytes};
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use futures_util::StreamExt;
+use std::fs::{File, Metadata};
+use std::io::{ErrorKind, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+// Internal Modules
+use crate::storage::exam_storage::{
+    ByteStream, DownloadStream, ExamStorage, ObjectPage, ObjectPut, StorageError, StoredObject,
+};
+
+// MAIN STRUCT *************************************************************************************
+/// Local filesystem, for development without GCP (STORAGE_BACKEND=local)
+/// Objects are written to `{LOCAL_STORAGE_DIR}/{bucket}/{name}` - a listing is a single page, a
+/// streamed download a single chunk
+pub struct LocalStorage {
+    root: PathBuf,
+}
+
+impl LocalStorage {
+    /// Create a storage under a root directory, created on the first write
+    pub fn new(root: &str) -> Self {
+        Self {
+            root: PathBuf::from(root),
+        }
+    }
+
+    /// Path of an object - parent references are dropped so a name cannot escape the root
+    fn path(&self, bucket: &str, name: &str) -> PathBuf {
+        let mut path = self.root.join(bucket);
+        for part in name.split('/').filter(|p| !p.is_empty() && *p != "..") {
+            path.push(part);
+        }
+        path
+    }
+}
+
+#[async_trait]
+impl ExamStorage for LocalStorage {
+    fn name(&self) -> &'static str {
+        "local"
+    }
+
+    fn location(&self, bucket: &str, name: &str) -> String {
+        self.path(bucket, name).display().to_string()
+    }
+
+    async fn put_object(&self, object: &ObjectPut<'_>, bytes: Vec<u8>) -> Result<u64> {
+        let path = self.path(object.bucket, object.name);
+        let size = bytes.len() as u64;
+        web::block(move || -> std::io::Result<()> {
+            if let Some(parent) = path.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            std::fs::write(&path, bytes)
+        })
+        .await
+        .map_err(|e| anyhow!("local.{} could not be scheduled: {e}", object.operation))?
+        .map_err(|e| anyhow!("local.{} failed: {e}", object.operation))?;
+        Ok(size)
+    }
+
+    async fn put_stream(
+        &self,
+        object: &ObjectPut<'_>,
+        mut body: ByteStream,
+        timeout: Duration,
+    ) -> Result<u64> {
+        let path = self.path(object.bucket, object.name);
+        let write = async move {
+            // STEP 1: Create the object
+            let mut file = web::block(move || -> std::io::Result<File> {
+                if let Some(parent) = path.parent() {
+                    std::fs::create_dir_all(parent)?;
+                }
+                File::create(&path)
+            })
+            .await??;
+
+            // STEP 2: Append the body as it is received
+            let mut size = 0;
+            while let Some(chunk) = body.next().await {
+                let chunk = chunk?;
+                size += chunk.len() as u64;
+                file = web::block(move || -> std::io::Result<File> {
+                    file.write_all(&chunk)?;
+                    Ok(file)
+                })
+                .await??;
+            }
+            anyhow::Ok(size)
+        };
+        tokio::time::timeout(timeout, write)
+            .await
+            .map_err(|_| anyhow!("local.{} timed out after {timeout:?}", object.operation))?
+            .map_err(|e| anyhow!("local.{} failed: {e}", object.operation))
+    }
+
+    async fn list_objects(
+        &self,
+        bucket: &str,
+        prefix: &str,
+        _page_token: Option<String>,
+    ) -> Result<ObjectPage> {
+        let root = self.root.join(bucket);
+        let prefix = prefix.to_string();
+        let objects = web::block(move || -> std::io::Result<Vec<StoredObject>> {
+            let mut objects = Vec::new();
+            let mut dirs = vec![root.clone()];
+            while let Some(dir) = dirs.pop() {
+                let entries = match std::fs::read_dir(&dir) {
+                    Ok(entries) => entries,
+                    Err(e) if e.kind() == ErrorKind::NotFound => continue,
+                    Err(e) => return Err(e),
+                };
+                for entry in entries {
+                    let (path, metadata) = entry.and_then(|e| Ok((e.path(), e.metadata()?)))?;
+                    if metadata.is_dir() {
+                        dirs.push(path);
+                    } else if let Some(name) = object_name(&root, &path) {
+                        if name.starts_with(&prefix) {
+                            objects.push(stored_object(name, &metadata));
+                        }
+                    }
+                }
+            }
+            objects.sort_by(|a, b| a.name.cmp(&b.name));
+            Ok(objects)
+        })
+        .await
+        .map_err(|e| anyhow!("local.list_objects could not be scheduled: {e}"))?
+        .map_err(|e| anyhow!("local.list_objects failed: {e}"))?;
+        Ok(ObjectPage {
+            objects,
+            next_page_token: None,
+        })
+    }
+
+    async fn get_object(&self, bucket: &str, name: &str) -> Result<Vec<u8>> {
+        let path = self.path(bucket, name);
+        let location = path.display().to_string();
+        match web::block(move || std::fs::read(path))
+            .await
+            .map_err(|e| anyhow!("local.download_object could not be scheduled: {e}"))?
+        {
+            Ok(bytes) => Ok(bytes),
+            Err(e) if e.kind() == ErrorKind::NotFound => {
+                Err(StorageError::NotFound(location).into())
+            }
+            Err(e) => Err(anyhow!("local.download_object failed: {e}")),
+        }
+    }
+
+    async fn object_info(&self, bucket: &str, name: &str) -> Result<StoredObject> {
+        let path = self.path(bucket, name);
+        let location = path.display().to_string();
+        match web::block(move || std::fs::metadata(path))
+            .await
+            .map_err(|e| anyhow!("local.get_object could not be scheduled: {e}"))?
+        {
+            Ok(metadata) if metadata.is_file() => Ok(stored_object(name.to_string(), &metadata)),
+            Ok(_) => Err(StorageError::NotFound(location).into()),
+            Err(e) if e.kind() == ErrorKind::NotFound => {
+                Err(StorageError::NotFound(location).into())
+            }
+            Err(e) => Err(anyhow!("local.get_object failed: {e}")),
+        }
+    }
+
+    async fn get_stream(
+        &self,
+        bucket: &str,
+        name: &str,
+        range: Option<(u64, u64)>,
+    ) -> Result<DownloadStream> {
+        let path = self.path(bucket, name);
+        let location = path.display().to_string();
+        let read = web::block(move || -> std::io::Result<Vec<u8>> {
+            let mut file = File::open(path)?;
+            let mut bytes = Vec::new();
+            match range {
+                Some((from, to)) => {
+                    file.seek(SeekFrom::Start(from))?;
+                    file.take(to.saturating_sub(from) + 1)
+                        .read_to_end(&mut bytes)?;
+                }
+                None => {
+                    file.read_to_end(&mut bytes)?;
+                }
+            }
+            Ok(bytes)
+        })
+        .await
+        .map_err(|e| anyhow!("local.download_streamed_object could not be scheduled: {e}"))?;
+        match read {
+            Ok(bytes) => Ok(Box::pin(futures_util::stream::iter([Ok(Bytes::from(
+                bytes,
+            ))]))),
+            Err(e) if e.kind() == ErrorKind::NotFound => {
+                Err(StorageError::NotFound(location).into())
+            }
+            Err(e) => Err(anyhow!("local.download_streamed_object failed: {e}")),
+        }
+    }
+
+    async fn copy_object(&self, bucket: &str, from: &StoredObject, to: &str) -> Result<()> {
+        let source = self.path(bucket, &from.name);
+        let destination = self.path(bucket, to);
+        let source_location = source.display().to_string();
+        let location = destination.display().to_string();
+        let copied = web::block(move || -> std::io::Result<()> {
+            let mut reader = File::open(&source)?;
+            if let Some(parent) = destination.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            // create_new only creates the destination if it does not exist yet
+            let mut writer = std::fs::OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(&destination)?;
+            std::io::copy(&mut reader, &mut writer).map(|_| ())
+        })
+        .await
+        .map_err(|e| anyhow!("local.copy_object could not be scheduled: {e}"))?;
+        match copied {
+            Ok(()) => Ok(()),
+            Err(e) if e.kind() == ErrorKind::NotFound => {
+                Err(StorageError::NotFound(source_location).into())
+            }
+            Err(e) if e.kind() == ErrorKind::AlreadyExists => {
+                Err(StorageError::Conflict(location).into())
+            }
+            Err(e) => Err(anyhow!("local.copy_object failed: {e}")),
+        }
+    }
+
+    async fn delete_object(
+        &self,
+        bucket: &str,
+        name: &str,
+        _generation: Option<i64>,
+    ) -> Result<()> {
+        let path = self.path(bucket, name);
+        let location = path.display().to_string();
+        match web::block(move || std::fs::remove_file(path))
+            .await
+            .map_err(|e| anyhow!("local.delete_object could not be scheduled: {e}"))?
+        {
+            Ok(()) => Ok(()),
+            Err(e) if e.kind() == ErrorKind::NotFound => {
+                Err(StorageError::NotFound(location).into())
+            }
+            Err(e) => Err(anyhow!("local.delete_object failed: {e}")),
+        }
+    }
+}
+
+// SUPPORT FUNCTIONS *******************************************************************************
+/// Name of the object stored at a path under the directory of its bucket
+fn object_name(root: &Path, path: &Path) -> Option<String> {
+    let parts: Option<Vec<&str>> = path
+        .strip_prefix(root)
+        .ok()?
+        .components()
+        .map(|part| part.as_os_str().to_str())
+        .collect();
+    Some(parts?.join("/"))
+}
+
+/// Object of a listing, dated by its file - the creation time falls back to the last update on
+/// the filesystems without one
+fn stored_object(name: String, metadata: &Metadata) -> StoredObject {
+    let updated_at = metadata.modified().ok().map(DateTime::<Utc>::from);
+    StoredObject {
+        name,
+        size: metadata.len(),
+        created_at: metadata
+            .created()
+            .ok()
+            .map(DateTime::<Utc>::from)
+            .or(updated_at),
+        updated_at,
+        ..Default::default()
+    }
+}
+
+// TESTS *******************************************************************************************
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::web::Bytes;
+
+    fn object<'a>(name: &'a str) -> ObjectPut<'a> {
+        ObjectPut {
+            bucket: "sentinela-local",
+            name,
+            content_type: None,
+            operation: "upload_object",
+            retries: 0,
+        }
+    }
+
+    // Happy path: objects land under their bucket, streamed or not
+    #[actix_web::test]
+    async fn local_writes_objects() {
+        let root = std::env::temp_dir().join(format!("sentinela-storage-{}", std::process::id()));
+        let storage = LocalStorage::new(root.to_str().unwrap());
+        let size = storage
+            .put_object(&object("ecg_exam/h/p/1.parquet"), b"exam".to_vec())
+            .await
+            .unwrap();
+        assert_eq!(size, 4);
+        let body: ByteStream = Box::pin(futures_util::stream::iter(vec![
+            Ok(Bytes::from_static(b"ima")),
+            Ok(Bytes::from_static(b"ge")),
+        ]));
+        let size = storage
+            .put_stream(&object("xray_exam/h/p/1.png"), body, Duration::from_secs(5))
+            .await
+            .unwrap();
+        assert_eq!(size, 5);
+        let image = std::fs::read(root.join("sentinela-local/xray_exam/h/p/1.png")).unwrap();
+        assert_eq!(image, b"image");
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    // Happy path: objects are listed by prefix, read back and deleted, a missing one is not found
+    #[actix_web::test]
+    async fn local_lists_reads_and_deletes() {
+        let root = std::env::temp_dir().join(format!("sentinela-listing-{}", std::process::id()));
+        let storage = LocalStorage::new(root.to_str().unwrap());
+        for name in ["storage/b/2.json", "storage/a/1.json", "publish/1.json"] {
+            storage
+                .put_object(&object(name), name.as_bytes().to_vec())
+                .await
+                .unwrap();
+        }
+        let page = storage
+            .list_objects("sentinela-local", "storage/", None)
+            .await
+            .unwrap();
+        let names: Vec<&str> = page.objects.iter().map(|o| o.name.as_str()).collect();
+        assert_eq!(names, ["storage/a/1.json", "storage/b/2.json"]);
+        assert_eq!(page.objects[0].size, 16);
+        assert!(page.objects[0].created_at.is_some());
+        assert_eq!(page.next_page_token, None);
+        let bytes = storage
+            .get_object("sentinela-local", "publish/1.json")
+            .await
+            .unwrap();
+        assert_eq!(bytes, b"publish/1.json");
+        let info = storage
+            .object_info("sentinela-local", "publish/1.json")
+            .await
+            .unwrap();
+        assert_eq!(info.size, 14);
+        let range: Vec<Bytes> = storage
+            .get_stream("sentinela-local", "publish/1.json", Some((8, 11)))
+            .await
+            .unwrap()
+            .map(Result::unwrap)
+            .collect()
+            .await;
+        assert_eq!(range.concat(), b"1.js");
+
+        storage
+            .delete_object("sentinela-local", "publish/1.json", None)
+            .await
+            .unwrap();
+        let missing = storage
+            .get_object("sentinela-local", "publish/1.json")
+            .await
+            .unwrap_err();
+        assert!(StorageError::is_not_found(&missing));
+        let missing = storage
+            .delete_object("sentinela-local", "publish/1.json", None)
+            .await
+            .unwrap_err();
+        assert!(StorageError::is_not_found(&missing));
+        let empty = storage.list_objects("nope", "", None).await.unwrap();
+        assert!(empty.objects.is_empty());
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    // Borderline: a copy never overwrites its destination, the copy of a missing object is not
+    // found
+    #[actix_web::test]
+    async fn local_copy_create_only() {
+        let root = std::env::temp_dir().join(format!("sentinela-copy-{}", std::process::id()));
+        let storage = LocalStorage::new(root.to_str().unwrap());
+        for name in ["ecg_exam/AB/1.parquet", "ecg_exam/ab/2.parquet"] {
+            storage
+                .put_object(&object(name), name.as_bytes().to_vec())
+                .await
+                .unwrap();
+        }
+        let from = |name: &str| StoredObject {
+            name: name.to_string(),
+            ..Default::default()
+        };
+        storage
+            .copy_object(
+                "sentinela-local",
+                &from("ecg_exam/AB/1.parquet"),
+                "ecg_exam/ab/1.parquet",
+            )
+            .await
+            .unwrap();
+        let bytes = storage
+            .get_object("sentinela-local", "ecg_exam/ab/1.parquet")
+            .await
+            .unwrap();
+        assert_eq!(bytes, b"ecg_exam/AB/1.parquet");
+        let taken = storage
+            .copy_object(
+                "sentinela-local",
+                &from("ecg_exam/AB/1.parquet"),
+                "ecg_exam/ab/2.parquet",
+            )
+            .await
+            .unwrap_err();
+        assert!(StorageError::is_conflict(&taken));
+        let bytes = storage
+            .get_object("sentinela-local", "ecg_exam/ab/2.parquet")
+            .await
+            .unwrap();
+        assert_eq!(bytes, b"ecg_exam/ab/2.parquet");
+        let missing = storage
+            .copy_object(
+                "sentinela-local",
+                &from("ecg_exam/AB/9.parquet"),
+                "ecg_exam/ab/9.parquet",
+            )
+            .await
+            .unwrap_err();
+        assert!(StorageError::is_not_found(&missing));
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    // Security: object names cannot escape the storage root
+    #[test]
+    fn local_path_confined() {
+        let storage = LocalStorage::new("/data");
+        assert_eq!(
+            storage.path("bucket", "../../etc/passwd"),
+            PathBuf::from("/data/bucket/etc/passwd")
+        );
+    }
+}
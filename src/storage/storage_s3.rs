@@ -0,0 +1,593 @@
+// Imports *****************************************************************************************
+// External Crates
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use futures_util::TryStreamExt;
+use reqwest::Method;
+use sha2::{Digest, Sha256};
+use std::time::Duration;
+
+// Internal Modules
+use crate::config::settings::S3Settings;
+use crate::storage::exam_storage::{
+    ByteStream, DownloadStream, ExamStorage, ObjectPage, ObjectPut, StorageError, StoredObject,
+};
+use crate::utils::external_call::{Dependency, ExternalCall};
+use crate::utils::hmac::{hex, hmac_sha256};
+
+// Constants ***************************************************************************************
+/// Signature algorithm of the requests
+const ALGORITHM: &str = "AWS4-HMAC-SHA256";
+
+// Structs *****************************************************************************************
+/// Request to sign and send
+/// # Arguments
+/// * `method` - The HTTP method
+/// * `bucket` - The bucket
+/// * `name` - The object name, empty for the requests on the bucket (listings)
+/// * `query` - The query parameters
+/// * `headers` - The headers covered by the signature, besides `host` and `x-amz-*` of the payload
+/// * `body` - The body of the request
+struct S3Request<'a> {
+    method: Method,
+    bucket: &'a str,
+    name: &'a str,
+    query: Vec<(&'static str, String)>,
+    headers: Vec<(&'static str, String)>,
+    body: Vec<u8>,
+}
+
+impl<'a> S3Request<'a> {
+    /// Request without body on an object
+    fn new(method: Method, bucket: &'a str, name: &'a str) -> Self {
+        Self {
+            method,
+            bucket,
+            name,
+            query: Vec::new(),
+            headers: Vec::new(),
+            body: Vec::new(),
+        }
+    }
+}
+
+// MAIN STRUCT *************************************************************************************
+/// S3-compatible object storage - AWS S3, MinIO, Ceph (STORAGE_BACKEND=s3)
+/// Objects are written with path-style URLs (`{endpoint}/{bucket}/{name}`) and Signature V4
+pub struct S3Storage {
+    settings: S3Settings,
+    host: String,
+    http: reqwest::Client,
+}
+
+impl S3Storage {
+    /// Create a storage for an endpoint
+    /// # Errors
+    /// * Returns an error if the endpoint is not an http(s) URL
+    pub fn new(settings: S3Settings) -> Result<Self> {
+        let host = settings
+            .endpoint
+            .strip_prefix("https://")
+            .or_else(|| settings.endpoint.strip_prefix("http://"))
+            .map(|host| host.trim_end_matches('/').to_string())
+            .filter(|host| !host.is_empty() && !host.contains('/'))
+            .ok_or_else(|| anyhow!("Invalid S3_ENDPOINT '{}'", settings.endpoint))?;
+        Ok(Self {
+            settings,
+            host,
+            http: reqwest::Client::new(),
+        })
+    }
+
+    /// Sign and send a request
+    /// # Arguments
+    /// * `request` - The request
+    /// * `now` - The signing time
+    /// # Returns
+    /// * The response, whatever its status
+    async fn send(
+        &self,
+        request: S3Request<'_>,
+        now: DateTime<Utc>,
+    ) -> std::result::Result<reqwest::Response, String> {
+        let path = match request.name {
+            "" => format!("/{}", request.bucket),
+            name => format!("/{}/{}", request.bucket, uri_encode(name)),
+        };
+        let mut query = request.query;
+        query.sort();
+        let query = query
+            .iter()
+            .map(|(key, value)| format!("{}={}", query_encode(key), query_encode(value)))
+            .collect::<Vec<_>>()
+            .join("&");
+        let payload_hash = format!("{:x}", Sha256::digest(&request.body));
+        let amz_date = now.format("%Y%m%dT%H%M%SZ").to_string();
+        let mut headers = request.headers;
+        headers.push(("x-amz-content-sha256", payload_hash.clone()));
+        headers.push(("x-amz-date", amz_date.clone()));
+        let authorization = self.authorization(
+            request.method.as_str(),
+            &path,
+            &query,
+            &headers,
+            &payload_hash,
+            &amz_date,
+        );
+        let url = match query.as_str() {
+            "" => format!("{}{path}", self.settings.endpoint.trim_end_matches('/')),
+            query => format!(
+                "{}{path}?{query}",
+                self.settings.endpoint.trim_end_matches('/')
+            ),
+        };
+        let mut builder = self
+            .http
+            .request(request.method, url)
+            .header("authorization", authorization);
+        for (name, value) in headers {
+            builder = builder.header(name, value);
+        }
+        builder
+            .body(request.body)
+            .send()
+            .await
+            .map_err(|e| e.to_string())
+    }
+
+    /// Signed PUT of an object
+    /// # Arguments
+    /// * `object` - The object to write
+    /// * `bytes` - The content of the object
+    /// * `now` - The signing time
+    async fn put(
+        &self,
+        object: &ObjectPut<'_>,
+        bytes: Vec<u8>,
+        now: DateTime<Utc>,
+    ) -> std::result::Result<(), String> {
+        let request = S3Request {
+            headers: vec![("content-type", object.content_type().to_string())],
+            body: bytes,
+            ..S3Request::new(Method::PUT, object.bucket, object.name)
+        };
+        successful(self.send(request, now).await?).await?;
+        Ok(())
+    }
+
+    /// Signed request whose 404 is an answer rather than a failure
+    /// # Returns
+    /// * The successful response, or the status 404
+    async fn answered(
+        &self,
+        request: S3Request<'_>,
+        now: DateTime<Utc>,
+    ) -> std::result::Result<std::result::Result<reqwest::Response, u16>, String> {
+        let response = self.send(request, now).await?;
+        if response.status().as_u16() == 404 {
+            return Ok(Err(404));
+        }
+        successful(response).await.map(Ok)
+    }
+
+    /// Authorization header of a request, following Signature V4
+    /// # Arguments
+    /// * `method` - The HTTP method
+    /// * `path` - The canonical URI
+    /// * `query` - The canonical query string
+    /// * `headers` - The signed headers, besides `host`
+    /// * `payload_hash` - The hex SHA-256 of the body
+    /// * `amz_date` - The signing time, `%Y%m%dT%H%M%SZ`
+    fn authorization(
+        &self,
+        method: &str,
+        path: &str,
+        query: &str,
+        headers: &[(&'static str, String)],
+        payload_hash: &str,
+        amz_date: &str,
+    ) -> String {
+        // STEP 1: Canonical request
+        let mut signed: Vec<(&str, &str)> = headers
+            .iter()
+            .map(|(name, value)| (*name, value.trim()))
+            .chain([("host", self.host.as_str())])
+            .collect();
+        signed.sort();
+        let canonical_headers: String = signed
+            .iter()
+            .map(|(name, value)| format!("{name}:{value}\n"))
+            .collect();
+        let signed_headers = signed
+            .iter()
+            .map(|(name, _)| *name)
+            .collect::<Vec<_>>()
+            .join(";");
+        let canonical_request = format!(
+            "{method}\n{path}\n{query}\n{canonical_headers}\n{signed_headers}\n{payload_hash}"
+        );
+
+        // STEP 2: String to sign
+        let date = &amz_date[..8];
+        let scope = format!("{date}/{}/s3/aws4_request", self.settings.region);
+        let string_to_sign = format!(
+            "{ALGORITHM}\n{amz_date}\n{scope}\n{:x}",
+            Sha256::digest(canonical_request.as_bytes())
+        );
+
+        // STEP 3: Signature
+        let key = signing_key(
+            &self.settings.secret_access_key,
+            date,
+            &self.settings.region,
+            "s3",
+        );
+        let signature = hex(&hmac_sha256(&key, string_to_sign.as_bytes()));
+        format!(
+            "{ALGORITHM} Credential={}/{scope}, SignedHeaders={signed_headers}, Signature={signature}",
+            self.settings.access_key_id
+        )
+    }
+}
+
+#[async_trait]
+impl ExamStorage for S3Storage {
+    fn name(&self) -> &'static str {
+        "s3"
+    }
+
+    fn location(&self, bucket: &str, name: &str) -> String {
+        format!("s3://{bucket}/{name}")
+    }
+
+    async fn put_object(&self, object: &ObjectPut<'_>, bytes: Vec<u8>) -> Result<u64> {
+        let size = bytes.len() as u64;
+        ExternalCall::new(Dependency::S3, object.operation)
+            .bucket(object.bucket)
+            .retries(object.retries)
+            .run(|| self.put(object, bytes.clone(), Utc::now()))
+            .await?;
+        Ok(size)
+    }
+
+    /// S3 needs the length of the object up front: the body is buffered, then sent in one request
+    async fn put_stream(
+        &self,
+        object: &ObjectPut<'_>,
+        body: ByteStream,
+        timeout: Duration,
+    ) -> Result<u64> {
+        let bytes = tokio::time::timeout(
+            timeout,
+            body.try_fold(Vec::new(), |mut bytes, chunk| async move {
+                bytes.extend_from_slice(&chunk);
+                Ok(bytes)
+            }),
+        )
+        .await
+        .map_err(|_| anyhow!("s3.{} timed out after {timeout:?}", object.operation))??;
+        let size = bytes.len() as u64;
+        let mut bytes = Some(bytes);
+        ExternalCall::new(Dependency::S3, object.operation)
+            .bucket(object.bucket)
+            .timeout(timeout)
+            .run(|| {
+                let bytes = bytes.take();
+                async move {
+                    match bytes {
+                        Some(bytes) => self.put(object, bytes, Utc::now()).await,
+                        None => Err("upload body already consumed".to_string()),
+                    }
+                }
+            })
+            .await?;
+        Ok(size)
+    }
+
+    async fn list_objects(
+        &self,
+        bucket: &str,
+        prefix: &str,
+        page_token: Option<String>,
+    ) -> Result<ObjectPage> {
+        let listing = ExternalCall::new(Dependency::S3, "list_objects")
+            .bucket(bucket)
+            .retries(2)
+            .run(|| {
+                let mut query = vec![("list-type", "2".to_string())];
+                if !prefix.is_empty() {
+                    query.push(("prefix", prefix.to_string()));
+                }
+                if let Some(token) = &page_token {
+                    query.push(("continuation-token", token.clone()));
+                }
+                let request = S3Request {
+                    query,
+                    ..S3Request::new(Method::GET, bucket, "")
+                };
+                async move {
+                    let response = successful(self.send(request, Utc::now()).await?).await?;
+                    response.text().await.map_err(|e| e.to_string())
+                }
+            })
+            .await?;
+        Ok(parse_listing(&listing))
+    }
+
+    async fn get_object(&self, bucket: &str, name: &str) -> Result<Vec<u8>> {
+        let response = ExternalCall::new(Dependency::S3, "download_object")
+            .bucket(bucket)
+            .retries(2)
+            .run(|| self.answered(S3Request::new(Method::GET, bucket, name), Utc::now()))
+            .await?
+            .map_err(|status| StorageError::from_status(status, self.location(bucket, name)))?;
+        Ok(response.bytes().await?.to_vec())
+    }
+
+    async fn object_info(&self, bucket: &str, name: &str) -> Result<StoredObject> {
+        let response = ExternalCall::new(Dependency::S3, "get_object")
+            .bucket(bucket)
+            .retries(2)
+            .run(|| self.answered(S3Request::new(Method::HEAD, bucket, name), Utc::now()))
+            .await?
+            .map_err(|status| StorageError::from_status(status, self.location(bucket, name)))?;
+        let header = |name: &str| {
+            response
+                .headers()
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(str::to_string)
+        };
+        let updated_at = header("last-modified")
+            .and_then(|at| DateTime::parse_from_rfc2822(&at).ok())
+            .map(|at| at.with_timezone(&Utc));
+        Ok(StoredObject {
+            name: name.to_string(),
+            size: header("content-length")
+                .and_then(|size| size.parse().ok())
+                .unwrap_or_default(),
+            created_at: updated_at,
+            updated_at,
+            ..Default::default()
+        })
+    }
+
+    async fn get_stream(
+        &self,
+        bucket: &str,
+        name: &str,
+        range: Option<(u64, u64)>,
+    ) -> Result<DownloadStream> {
+        let response = ExternalCall::new(Dependency::S3, "download_streamed_object")
+            .bucket(bucket)
+            .retries(2)
+            .run(|| {
+                let mut request = S3Request::new(Method::GET, bucket, name);
+                if let Some((from, to)) = range {
+                    request
+                        .headers
+                        .push(("range", format!("bytes={from}-{to}")));
+                }
+                self.answered(request, Utc::now())
+            })
+            .await?
+            .map_err(|status| StorageError::from_status(status, self.location(bucket, name)))?;
+        Ok(Box::pin(
+            response.bytes_stream().map_err(std::io::Error::other),
+        ))
+    }
+
+    /// S3 deletes answer 204 whether the object exists or not: it is looked up first
+    async fn copy_object(&self, bucket: &str, from: &StoredObject, to: &str) -> Result<()> {
+        // S3 has no create-only copy: the destination is checked first, racing concurrent copies
+        let source = format!("/{bucket}/{}", uri_encode(&from.name));
+        ExternalCall::new(Dependency::S3, "copy_object")
+            .bucket(bucket)
+            .run(|| async {
+                if self
+                    .answered(S3Request::new(Method::HEAD, bucket, to), Utc::now())
+                    .await?
+                    .is_ok()
+                {
+                    return Ok(Err(409));
+                }
+                let request = S3Request {
+                    headers: vec![("x-amz-copy-source", source.clone())],
+                    ..S3Request::new(Method::PUT, bucket, to)
+                };
+                self.answered(request, Utc::now())
+                    .await
+                    .map(|copied| copied.map(|_| ()))
+            })
+            .await?
+            .map_err(|status| {
+                let location = match status {
+                    404 => self.location(bucket, &from.name),
+                    _ => self.location(bucket, to),
+                };
+                StorageError::from_status(status, location).into()
+            })
+    }
+
+    async fn delete_object(
+        &self,
+        bucket: &str,
+        name: &str,
+        _generation: Option<i64>,
+    ) -> Result<()> {
+        ExternalCall::new(Dependency::S3, "delete_object")
+            .bucket(bucket)
+            .run(|| async {
+                if let Err(status) = self
+                    .answered(S3Request::new(Method::HEAD, bucket, name), Utc::now())
+                    .await?
+                {
+                    return Ok(Err(status));
+                }
+                self.answered(S3Request::new(Method::DELETE, bucket, name), Utc::now())
+                    .await
+                    .map(|deleted| deleted.map(|_| ()))
+            })
+            .await?
+            .map_err(|status| StorageError::from_status(status, self.location(bucket, name)).into())
+    }
+}
+
+// SUPPORT FUNCTIONS *******************************************************************************
+/// Signing key of a day, region and service
+fn signing_key(secret: &str, date: &str, region: &str, service: &str) -> Vec<u8> {
+    let key = hmac_sha256(format!("AWS4{secret}").as_bytes(), date.as_bytes());
+    let key = hmac_sha256(&key, region.as_bytes());
+    let key = hmac_sha256(&key, service.as_bytes());
+    hmac_sha256(&key, b"aws4_request")
+}
+
+/// The response of a successful request, or the error of a failed one - the status leads the
+/// message, so the failure is classified as for GCS
+async fn successful(response: reqwest::Response) -> std::result::Result<reqwest::Response, String> {
+    let status = response.status();
+    if status.is_success() {
+        return Ok(response);
+    }
+    let body = response.text().await.unwrap_or_default();
+    Err(format!("{} {body}", status.as_u16()))
+}
+
+/// Objects of a ListObjectsV2 response
+fn parse_listing(xml: &str) -> ObjectPage {
+    let objects = xml
+        .split("<Contents>")
+        .skip(1)
+        .filter_map(|contents| {
+            let name = xml_value(contents, "Key")?;
+            Some(StoredObject {
+                name,
+                size: xml_value(contents, "Size")?.parse().ok()?,
+                updated_at: xml_value(contents, "LastModified")
+                    .and_then(|at| DateTime::parse_from_rfc3339(&at).ok())
+                    .map(|at| at.with_timezone(&Utc)),
+                ..Default::default()
+            })
+        })
+        .map(|object| StoredObject {
+            // S3 keeps no creation time apart from the last write
+            created_at: object.updated_at,
+            ..object
+        })
+        .collect();
+    let next_page_token = xml_value(xml, "NextContinuationToken")
+        .filter(|_| xml_value(xml, "IsTruncated").as_deref() == Some("true"));
+    ObjectPage {
+        objects,
+        next_page_token,
+    }
+}
+
+/// Text of the first element of a tag, unescaped
+fn xml_value(xml: &str, tag: &str) -> Option<String> {
+    let start = xml.find(&format!("<{tag}>"))? + tag.len() + 2;
+    let end = start + xml[start..].find(&format!("</{tag}>"))?;
+    Some(
+        xml[start..end]
+            .replace("&lt;", "<")
+            .replace("&gt;", ">")
+            .replace("&quot;", "\"")
+            .replace("&apos;", "'")
+            .replace("&amp;", "&"),
+    )
+}
+
+/// Percent-encode an object name for the canonical URI - `/` separates the path segments
+fn uri_encode(name: &str) -> String {
+    percent_encode(name, true)
+}
+
+/// Percent-encode a query parameter name or value for the canonical query string
+fn query_encode(value: &str) -> String {
+    percent_encode(value, false)
+}
+
+/// Percent-encode all but the unreserved characters, and `/` if kept
+fn percent_encode(value: &str, keep_slash: bool) -> String {
+    value
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                (b as char).to_string()
+            }
+            b'/' if keep_slash => "/".to_string(),
+            _ => format!("%{b:02X}"),
+        })
+        .collect()
+}
+
+// TESTS *******************************************************************************************
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Happy path: signing key of the AWS Signature V4 documentation
+    #[test]
+    fn signing_key_known_vector() {
+        let key = signing_key(
+            "wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY",
+            "20120215",
+            "us-east-1",
+            "iam",
+        );
+        assert_eq!(
+            hex(&key),
+            "f4780e2d9f65fa895f9c67b32ce1baf0b0d8a43505a000a1a9e090d414db404d"
+        );
+    }
+
+    // Happy path: exam ids keep their path, other characters are escaped
+    #[test]
+    fn object_names_encoded() {
+        assert_eq!(
+            uri_encode("ecg_exam/h/p/2026-01-01T000000.000Z.parquet"),
+            "ecg_exam/h/p/2026-01-01T000000.000Z.parquet"
+        );
+        assert_eq!(uri_encode("a b+c"), "a%20b%2Bc");
+        assert_eq!(query_encode("storage/a b"), "storage%2Fa%20b");
+    }
+
+    // Happy path: a ListObjectsV2 page, with its continuation token while truncated
+    #[test]
+    fn listing_parsed() {
+        let xml = r#"<?xml version="1.0" encoding="UTF-8"?>
+<ListBucketResult><Name>b</Name><IsTruncated>true</IsTruncated>
+<Contents><Key>storage/a&amp;b.json</Key><LastModified>2026-01-02T03:04:05.000Z</LastModified><Size>12</Size></Contents>
+<Contents><Key>storage/c.json</Key><LastModified>2026-01-03T00:00:00.000Z</LastModified><Size>0</Size></Contents>
+<NextContinuationToken>next/token=</NextContinuationToken></ListBucketResult>"#;
+        let page = parse_listing(xml);
+        assert_eq!(page.objects.len(), 2);
+        assert_eq!(page.objects[0].name, "storage/a&b.json");
+        assert_eq!(page.objects[0].size, 12);
+        assert_eq!(
+            page.objects[0].created_at.unwrap().to_rfc3339(),
+            "2026-01-02T03:04:05+00:00"
+        );
+        assert_eq!(page.next_page_token.as_deref(), Some("next/token="));
+        let last = parse_listing(&xml.replace("<IsTruncated>true", "<IsTruncated>false"));
+        assert_eq!(last.next_page_token, None);
+    }
+
+    // Error handling: the endpoint must be a bare http(s) origin
+    #[test]
+    fn endpoint_validated() {
+        let settings = |endpoint: &str| S3Settings {
+            endpoint: endpoint.to_string(),
+            region: "us-east-1".to_string(),
+            access_key_id: "key".to_string(),
+            secret_access_key: "secret".to_string(),
+        };
+        assert_eq!(
+            S3Storage::new(settings("http://minio:9000/")).unwrap().host,
+            "minio:9000"
+        );
+        assert!(S3Storage::new(settings("minio:9000")).is_err());
+        assert!(S3Storage::new(settings("https://minio/bucket")).is_err());
+    }
+}
@@ -0,0 +1,296 @@
+// Imports *****************************************************************************************
+// External Crates
+use chrono::{DateTime, Utc};
+use log::{info, warn, Level};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::sync::OnceLock;
+use tokio::sync::mpsc;
+
+// Internal Modules
+use crate::config::settings::AuditSink;
+use crate::sinks::event_sink::EventSink;
+use crate::sinks::sink_cloud_logging::CloudLoggingSink;
+use crate::sinks::sink_log::LogSink;
+use crate::telemetry::metrics::record_audit_export;
+use crate::telemetry::trace_context::current_trace;
+use crate::utils::request_id::current_request_id;
+
+// Constants ***************************************************************************************
+/// Log target of the audit entries
+pub const AUDIT_TARGET: &str = "audit";
+/// Audit entries waiting for the sink - beyond, entries are dropped (they stay in the log lines)
+const AUDIT_QUEUE_CAPACITY: usize = 1_024;
+/// Hex characters of the hospital id hash used as label
+const HOSPITAL_HASH_LEN: usize = 16;
+/// Fields naming the outcome of an audited action, in order of preference
+const OUTCOME_FIELDS: [&str; 3] = ["outcome", "decision", "status"];
+
+// Structs *****************************************************************************************
+/// Audit log line parsed into its parts
+/// `event [outcome] key=value key=value ...` - a value runs until the next `key=`
+/// # Arguments
+/// * `event` - The audited action, e.g. `malware_scan`
+/// * `outcome` - `outcome`, `decision` or `status` field, else the word after the event, else
+///   the event itself
+/// * `fields` - Every `key=value` pair of the line
+#[derive(Debug, Clone, PartialEq)]
+pub struct AuditEntry {
+    pub event: String,
+    pub outcome: String,
+    pub fields: BTreeMap<String, String>,
+}
+
+impl AuditEntry {
+    /// Parse an audit log line
+    /// # Arguments
+    /// * `line` - The message of the log record
+    pub fn parse(line: &str) -> Self {
+        let mut words = Vec::new();
+        let mut fields: BTreeMap<String, String> = BTreeMap::new();
+        let mut current: Option<String> = None;
+        for token in line.split_whitespace() {
+            match token.split_once('=').filter(|(key, _)| is_field_key(key)) {
+                Some((key, value)) => {
+                    fields.insert(key.to_string(), value.to_string());
+                    current = Some(key.to_string());
+                }
+                None => match current.as_ref().and_then(|key| fields.get_mut(key)) {
+                    Some(value) => {
+                        value.push(' ');
+                        value.push_str(token);
+                    }
+                    None => words.push(token),
+                },
+            }
+        }
+        let event = words.first().copied().unwrap_or("audit").to_string();
+        let outcome = OUTCOME_FIELDS
+            .iter()
+            .find_map(|key| fields.get(*key).cloned())
+            .or_else(|| words.get(1).map(|w| w.to_string()))
+            .unwrap_or_else(|| event.clone());
+        Self {
+            event,
+            outcome,
+            fields,
+        }
+    }
+
+    /// Structured Cloud Logging entry of the audit line
+    /// # Arguments
+    /// * `level` - The level of the log record, mapped to the severity
+    /// * `message` - The original line, kept as message
+    /// * `timestamp` - When the entry was logged
+    /// * `request_id` / `trace_id` - The correlation ids of the request, if any
+    /// * `project` - The GCP project, to link the entry to its Cloud Trace
+    pub fn to_cloud_logging(
+        &self,
+        level: Level,
+        message: &str,
+        timestamp: DateTime<Utc>,
+        request_id: Option<&str>,
+        trace_id: Option<&str>,
+        project: Option<&str>,
+    ) -> serde_json::Value {
+        // STEP 1: Labels, indexed by Cloud Logging for log-based metrics and alerts - the hospital
+        // id is hashed so the label never exposes it
+        let mut labels = serde_json::Map::new();
+        labels.insert("event".into(), self.event.clone().into());
+        labels.insert("outcome".into(), self.outcome.clone().into());
+        if let Some(exam_type) = self.fields.get("exam_type") {
+            labels.insert("exam_type".into(), exam_type.clone().into());
+        }
+        if let Some(reason) = self.fields.get("reason") {
+            labels.insert("reason".into(), reason.clone().into());
+        }
+        if let Some(hospital_id) = self.fields.get("hospital_id") {
+            labels.insert("hospital_id_hash".into(), hospital_hash(hospital_id).into());
+        }
+
+        // STEP 2: The entry, with the fields of the line as payload
+        let mut entry = serde_json::Map::new();
+        entry.insert("severity".into(), severity(level).into());
+        entry.insert("message".into(), message.into());
+        entry.insert("timestamp".into(), timestamp.to_rfc3339().into());
+        entry.insert("logging.googleapis.com/labels".into(), labels.into());
+        entry.insert("event".into(), self.event.clone().into());
+        entry.insert("outcome".into(), self.outcome.clone().into());
+        entry.insert(
+            "fields".into(),
+            serde_json::to_value(&self.fields).unwrap_or_default(),
+        );
+        if let Some(request_id) = request_id {
+            entry.insert("request_id".into(), request_id.into());
+        }
+        if let Some(trace_id) = trace_id {
+            entry.insert("trace_id".into(), trace_id.into());
+            if let Some(project) = project {
+                entry.insert(
+                    "logging.googleapis.com/trace".into(),
+                    format!("projects/{project}/traces/{trace_id}").into(),
+                );
+            }
+        }
+        serde_json::Value::Object(entry)
+    }
+}
+
+/// Queue of the export and the project of its traces
+struct AuditExport {
+    sender: mpsc::Sender<serde_json::Value>,
+    project: Option<String>,
+}
+
+// Global variables ********************************************************************************
+/// Queue of the audit entries to export, set when AUDIT_SINK is configured
+static AUDIT_EXPORT: OnceLock<AuditExport> = OnceLock::new();
+
+// MAIN FUNCTIONS **********************************************************************************
+/// Start exporting the audit log lines to the sink of AUDIT_SINK - `cloud_logging` (structured
+/// JSON lines on stdout) or `log`; unset keeps the audit in the log lines only
+/// The trace of an entry is linked to Cloud Trace in GOOGLE_CLOUD_PROJECT, when set
+/// # Arguments
+/// * `audit_sink` - The sink of the audit entries (AUDIT_SINK)
+/// * `project` - The Cloud Trace project (GOOGLE_CLOUD_PROJECT)
+pub fn start_audit_export(audit_sink: AuditSink, project: Option<String>) {
+    let sink: Box<dyn EventSink> = match audit_sink {
+        AuditSink::None => return,
+        AuditSink::CloudLogging => Box::new(CloudLoggingSink),
+        AuditSink::Log => Box::new(LogSink::new("audit_export")),
+    };
+    info!("Audit export sink: {}", sink.name());
+    let (sender, mut receiver) = mpsc::channel(AUDIT_QUEUE_CAPACITY);
+    let export = AuditExport { sender, project };
+    if AUDIT_EXPORT.set(export).is_err() {
+        return;
+    }
+    actix_web::rt::spawn(async move {
+        while let Some(entry) = receiver.recv().await {
+            match sink.emit(&entry).await {
+                Ok(()) => record_audit_export("exported"),
+                Err(e) => {
+                    record_audit_export("failed");
+                    warn!("Audit entry could not be exported: {e}");
+                }
+            }
+        }
+    });
+}
+
+/// Queue an audit log record for export - called by the log formatter, never blocks
+/// # Arguments
+/// * `record` - The log record, ignored unless its target is AUDIT_TARGET
+pub fn export_audit(record: &log::Record) {
+    if record.target() != AUDIT_TARGET {
+        return;
+    }
+    let Some(export) = AUDIT_EXPORT.get() else {
+        return;
+    };
+    let message = record.args().to_string();
+    let trace_id = current_trace().map(|trace| trace.trace_id);
+    let entry = AuditEntry::parse(&message).to_cloud_logging(
+        record.level(),
+        &message,
+        Utc::now(),
+        current_request_id().as_deref().filter(|id| !id.is_empty()),
+        trace_id.as_deref(),
+        export.project.as_deref(),
+    );
+    if export.sender.try_send(entry).is_err() {
+        record_audit_export("dropped");
+    }
+}
+
+// SUPPORT FUNCTIONS *******************************************************************************
+/// Whether a token prefix is a field key (`hospital_id` in `hospital_id=...`)
+fn is_field_key(key: &str) -> bool {
+    !key.is_empty()
+        && key
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_')
+}
+
+/// Cloud Logging severity of a log level
+fn severity(level: Level) -> &'static str {
+    match level {
+        Level::Error => "ERROR",
+        Level::Warn => "WARNING",
+        Level::Info => "INFO",
+        Level::Debug | Level::Trace => "DEBUG",
+    }
+}
+
+/// Short hash of a hospital id, used as label
+fn hospital_hash(hospital_id: &str) -> String {
+    format!("{:x}", Sha256::digest(hospital_id.as_bytes()))[..HOSPITAL_HASH_LEN].to_string()
+}
+
+// TESTS *******************************************************************************************
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Happy path: event, outcome word and fields, values running until the next key
+    #[test]
+    fn parse_scan_line() {
+        let entry = AuditEntry::parse(
+            "malware_scan failed exam_type=xray_exam hospital_id=h1 bytes=12 error=clamd: connection refused",
+        );
+        assert_eq!(entry.event, "malware_scan");
+        assert_eq!(entry.outcome, "failed");
+        assert_eq!(entry.fields["exam_type"], "xray_exam");
+        assert_eq!(entry.fields["error"], "clamd: connection refused");
+    }
+
+    // Outcome from a decision field, else the event itself
+    #[test]
+    fn parse_outcome_fallbacks() {
+        let entry = AuditEntry::parse(
+            "exam_validation exam_type=ecg_exam hospital_id=h profile=ecg_exam@1 decision=rejected reason=LEAD_LENGTH",
+        );
+        assert_eq!(entry.outcome, "rejected");
+        let json = entry.to_cloud_logging(Level::Info, "line", Utc::now(), None, None, None);
+        assert_eq!(
+            json["logging.googleapis.com/labels"]["reason"],
+            "LEAD_LENGTH"
+        );
+        let entry = AuditEntry::parse("exam_lost exam_type=ecg_exam exam_id=e stage=storage");
+        assert_eq!(entry.outcome, "exam_lost");
+    }
+
+    // Happy path: labels and trace of the structured entry, the hospital id is never a label
+    #[test]
+    fn cloud_logging_entry() {
+        let entry = AuditEntry::parse("exam_export granted exam_id=e format=csv hospital_id=h1");
+        let json = entry.to_cloud_logging(
+            Level::Warn,
+            "line",
+            Utc::now(),
+            Some("req-1"),
+            Some("0af7651916cd43dd8448eb211c80319c"),
+            Some("sentinela-prod"),
+        );
+        assert_eq!(json["severity"], "WARNING");
+        let labels = &json["logging.googleapis.com/labels"];
+        assert_eq!(labels["outcome"], "granted");
+        assert_eq!(
+            labels["hospital_id_hash"].as_str().unwrap().len(),
+            HOSPITAL_HASH_LEN
+        );
+        assert!(labels.get("hospital_id").is_none());
+        assert_eq!(
+            json["logging.googleapis.com/trace"],
+            "projects/sentinela-prod/traces/0af7651916cd43dd8448eb211c80319c"
+        );
+    }
+
+    // Borderline: only lowercase keys start a field, other `=` stay in the value
+    #[test]
+    fn parse_value_with_equals() {
+        let entry = AuditEntry::parse("wasm_plugin failed plugin=p error=trap: Fuel=0 exhausted");
+        assert_eq!(entry.fields["error"], "trap: Fuel=0 exhausted");
+        assert_eq!(entry.fields.len(), 2);
+    }
+}
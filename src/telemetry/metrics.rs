@@ -0,0 +1,887 @@
+// Imports *****************************************************************************************
+// External Crates
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::Mutex;
+use std::time::Duration;
+
+// Internal Modules
+use crate::utils::clock_drift::clock_check;
+use crate::utils::external_call::{call_stats, retry_budget_stats, BudgetStats, CallStats};
+use crate::utils::publish_backlog::{BacklogState, PUBLISH_BACKLOG};
+use crate::utils::reason_code::ReasonCode;
+use crate::utils::stage_metrics::{stage_durations, BUCKETS_MS};
+
+// Constants ***************************************************************************************
+/// Prefix of every metric name
+const PREFIX: &str = "sentinela";
+/// Content type of the Prometheus text exposition format
+pub const PROMETHEUS_TEXT: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+// Types *******************************************************************************************
+/// Name, help and value of a metric derived from the external call stats
+type CallMetric = (&'static str, &'static str, fn(&CallStats) -> f64);
+/// Name, type, help and value of a metric derived from the retry budgets
+type BudgetMetric = (
+    &'static str,
+    &'static str,
+    &'static str,
+    fn(&BudgetStats) -> f64,
+);
+
+// Structs *****************************************************************************************
+/// Latency histogram, with the bucket bounds of the stage histograms
+#[derive(Debug, Clone, Default)]
+struct LatencyHistogram {
+    counts: [u64; BUCKETS_MS.len() + 1],
+    count: u64,
+    sum: Duration,
+}
+
+/// Counters and histograms kept only by the telemetry module - the stage and external call
+/// metrics are read from their own modules when rendered
+#[derive(Debug, Default)]
+struct Registry {
+    /// Requests per (route pattern, method, status)
+    http_requests: BTreeMap<(String, &'static str, u16), u64>,
+    /// Request latency per (route pattern, method)
+    http_latency: BTreeMap<(String, &'static str), LatencyHistogram>,
+    /// Requests per protocol version (`http/1.1`, `h2`, ...)
+    http_protocol_requests: BTreeMap<&'static str, u64>,
+    /// Connections accepted by the server
+    http_connections_accepted: u64,
+    /// Connections that served at least one request, per protocol of their first request
+    http_connections: BTreeMap<&'static str, u64>,
+    /// Accepted exams and bytes stored per (hospital_id, exam_type)
+    exams_accepted: BTreeMap<(String, String), (u64, u64)>,
+    /// Rejected exams per (exam_type, reason)
+    exams_rejected: BTreeMap<(&'static str, &'static str), u64>,
+    /// Authentication failures per (scope, reason)
+    auth_failures: BTreeMap<(&'static str, &'static str), u64>,
+    /// Dead-lettered exams per (exam_type, stage, reason)
+    exams_dead_lettered: BTreeMap<(String, &'static str, &'static str), u64>,
+    /// Storage faults of the deployment per (code, operation)
+    storage_faults: BTreeMap<(&'static str, &'static str), u64>,
+    /// Published messages per (topic, message format)
+    messages_published: BTreeMap<(String, &'static str), u64>,
+    /// Fallbacks left out of a message for exceeding the attribute size per topic
+    fallbacks_omitted: BTreeMap<String, u64>,
+    /// Messages read by the consumers per (consumer, topic, decoded from)
+    consumer_reads: BTreeMap<(String, String, &'static str), u64>,
+    /// Audit entries per export outcome
+    audit_exports: BTreeMap<&'static str, u64>,
+    /// Events of the audit trail per write outcome
+    audit_trail: BTreeMap<&'static str, u64>,
+    /// Rate limit decisions per (API scope, outcome)
+    rate_limit_decisions: BTreeMap<(&'static str, &'static str), u64>,
+    /// Lockouts started per API scope
+    rate_limit_lockouts: BTreeMap<&'static str, u64>,
+    /// Invalidations of the cached hospital registry per source
+    registry_invalidations: BTreeMap<&'static str, u64>,
+    /// Checks of the host clock per outcome
+    clock_drift_checks: BTreeMap<&'static str, u64>,
+    /// Bodies compressed with a pre-shared zstd dictionary per (dictionary id, outcome)
+    zstd_dictionary_bodies: BTreeMap<(String, &'static str), u64>,
+}
+
+// Global variables ********************************************************************************
+static REGISTRY: Mutex<Registry> = Mutex::new(Registry {
+    http_requests: BTreeMap::new(),
+    http_latency: BTreeMap::new(),
+    http_protocol_requests: BTreeMap::new(),
+    http_connections_accepted: 0,
+    http_connections: BTreeMap::new(),
+    exams_accepted: BTreeMap::new(),
+    exams_rejected: BTreeMap::new(),
+    auth_failures: BTreeMap::new(),
+    exams_dead_lettered: BTreeMap::new(),
+    storage_faults: BTreeMap::new(),
+    messages_published: BTreeMap::new(),
+    fallbacks_omitted: BTreeMap::new(),
+    consumer_reads: BTreeMap::new(),
+    audit_exports: BTreeMap::new(),
+    audit_trail: BTreeMap::new(),
+    rate_limit_decisions: BTreeMap::new(),
+    rate_limit_lockouts: BTreeMap::new(),
+    registry_invalidations: BTreeMap::new(),
+    clock_drift_checks: BTreeMap::new(),
+    zstd_dictionary_bodies: BTreeMap::new(),
+});
+
+// MAIN FUNCTIONS **********************************************************************************
+/// Record a served request
+/// # Arguments
+/// * `route` - The route pattern (e.g. `/v1/exam_status/{exam_id}`), never the raw path
+/// * `method` - The HTTP method
+/// * `status` - The status code of the response
+/// * `latency` - How long the request took
+pub fn record_request(route: &str, method: &'static str, status: u16, latency: Duration) {
+    if let Ok(mut registry) = REGISTRY.lock() {
+        *registry
+            .http_requests
+            .entry((route.to_string(), method, status))
+            .or_default() += 1;
+        let histogram = registry
+            .http_latency
+            .entry((route.to_string(), method))
+            .or_default();
+        let ms = latency.as_millis() as u64;
+        let bucket = BUCKETS_MS
+            .iter()
+            .position(|bound| ms <= *bound)
+            .unwrap_or(BUCKETS_MS.len());
+        histogram.counts[bucket] += 1;
+        histogram.count += 1;
+        histogram.sum += latency;
+    }
+}
+
+/// Record the protocol of a served request
+/// # Arguments
+/// * `protocol` - `http/1.0`, `http/1.1`, `h2` or `other`
+/// * `first_on_connection` - Whether it is the first request of its connection
+pub fn record_protocol_request(protocol: &'static str, first_on_connection: bool) {
+    if let Ok(mut registry) = REGISTRY.lock() {
+        *registry.http_protocol_requests.entry(protocol).or_default() += 1;
+        if first_on_connection {
+            *registry.http_connections.entry(protocol).or_default() += 1;
+        }
+    }
+}
+
+/// Record a connection accepted by the server
+pub fn record_connection_accepted() {
+    if let Ok(mut registry) = REGISTRY.lock() {
+        registry.http_connections_accepted += 1;
+    }
+}
+
+/// Record an accepted (billed) exam
+/// # Arguments
+/// * `hospital_id` - The hospital id (SHA256 hash)
+/// * `exam_type` - The exam type key
+/// * `bytes_stored` - Size of the objects written to storage for the exam
+pub fn record_exam_accepted(hospital_id: &str, exam_type: &str, bytes_stored: u64) {
+    if let Ok(mut registry) = REGISTRY.lock() {
+        let (exams, bytes) = registry
+            .exams_accepted
+            .entry((hospital_id.to_string(), exam_type.to_string()))
+            .or_default();
+        *exams += 1;
+        *bytes += bytes_stored;
+    }
+}
+
+/// Record a refused exam - not labelled by hospital, refused callers may send any hospital id
+/// # Arguments
+/// * `exam_type` - The exam type key
+/// * `reason` - Why the exam was refused
+pub fn record_exam_rejected(exam_type: &'static str, reason: ReasonCode) {
+    if let Ok(mut registry) = REGISTRY.lock() {
+        *registry
+            .exams_rejected
+            .entry((exam_type, reason.as_str()))
+            .or_default() += 1;
+    }
+}
+
+/// Record a failed authentication
+/// # Arguments
+/// * `scope` - `hospital` or `admin`
+/// * `reason` - Why the credential was refused, e.g. AUTH_BAD_KEY
+pub fn record_auth_failure(scope: &'static str, reason: ReasonCode) {
+    if let Ok(mut registry) = REGISTRY.lock() {
+        *registry
+            .auth_failures
+            .entry((scope, reason.as_str()))
+            .or_default() += 1;
+    }
+}
+
+/// Record an exam kept for replay after its storage or publish failed
+/// # Arguments
+/// * `exam_type` - The exam type key
+/// * `stage` - `storage` or `publish`
+/// * `reason` - Why the stage failed, e.g. GCS_TIMEOUT
+pub fn record_exam_dead_lettered(exam_type: &str, stage: &'static str, reason: ReasonCode) {
+    if let Ok(mut registry) = REGISTRY.lock() {
+        *registry
+            .exams_dead_lettered
+            .entry((exam_type.to_string(), stage, reason.as_str()))
+            .or_default() += 1;
+    }
+}
+
+/// Record a storage call failed by a fault of the deployment
+/// # Arguments
+/// * `code` - The fault code, e.g. `gcs_permission_denied`
+/// * `operation` - The storage operation, e.g. `upload_object`
+pub fn record_storage_fault(code: &'static str, operation: &'static str) {
+    if let Ok(mut registry) = REGISTRY.lock() {
+        *registry
+            .storage_faults
+            .entry((code, operation))
+            .or_default() += 1;
+    }
+}
+
+/// Record a message published to Pub/Sub
+/// # Arguments
+/// * `topic` - The topic name
+/// * `format` - The message format, e.g. `avro+json`
+pub fn record_message_published(topic: &str, format: &'static str) {
+    if let Ok(mut registry) = REGISTRY.lock() {
+        *registry
+            .messages_published
+            .entry((topic.to_string(), format))
+            .or_default() += 1;
+    }
+}
+
+/// Record a fallback left out of a message because it exceeds the attribute size
+/// # Arguments
+/// * `topic` - The topic name
+pub fn record_fallback_omitted(topic: &str) {
+    if let Ok(mut registry) = REGISTRY.lock() {
+        *registry
+            .fallbacks_omitted
+            .entry(topic.to_string())
+            .or_default() += 1;
+    }
+}
+
+/// Record the messages a consumer reports having read since its last report
+/// # Arguments
+/// * `consumer` - The consumer name, e.g. `inference_service`
+/// * `topic` - The topic name
+/// * `decoded_from` - `body` or `fallback`
+/// * `count` - Number of messages read
+#[cfg_attr(not(feature = "grpc"), allow(dead_code))]
+pub fn record_consumer_reads(consumer: &str, topic: &str, decoded_from: &'static str, count: u64) {
+    if let Ok(mut registry) = REGISTRY.lock() {
+        *registry
+            .consumer_reads
+            .entry((consumer.to_string(), topic.to_string(), decoded_from))
+            .or_default() += count;
+    }
+}
+
+/// Record an audit entry handed to the audit sink
+/// # Arguments
+/// * `outcome` - `exported`, `failed` (sink error) or `dropped` (export queue full)
+pub fn record_audit_export(outcome: &'static str) {
+    if let Ok(mut registry) = REGISTRY.lock() {
+        *registry.audit_exports.entry(outcome).or_default() += 1;
+    }
+}
+
+/// Record an event handed to the audit trail
+/// # Arguments
+/// * `outcome` - `written`, `failed` (database error) or `dropped` (trail queue full)
+pub fn record_audit_trail(outcome: &'static str) {
+    if let Ok(mut registry) = REGISTRY.lock() {
+        *registry.audit_trail.entry(outcome).or_default() += 1;
+    }
+}
+
+/// Record an invalidation of the cached hospital registry
+/// # Arguments
+/// * `source` - `notification` (registry change), `local` (admin API of this instance) or
+///   `reconnect` (notifications possibly missed, everything forgotten)
+pub fn record_registry_invalidation(source: &'static str) {
+    if let Ok(mut registry) = REGISTRY.lock() {
+        *registry.registry_invalidations.entry(source).or_default() += 1;
+    }
+}
+
+/// Record a check of the host clock
+/// # Arguments
+/// * `outcome` - `ok`, `exceeded` (drift above CLOCK_DRIFT_MAX_MS) or `failed` (server not reached)
+pub fn record_clock_drift_check(outcome: &'static str) {
+    if let Ok(mut registry) = REGISTRY.lock() {
+        *registry.clock_drift_checks.entry(outcome).or_default() += 1;
+    }
+}
+
+/// Record a body compressed with a pre-shared zstd dictionary
+/// # Arguments
+/// * `dictionary` - The dictionary id, `unknown` when the gateway does not have it
+/// * `outcome` - `decoded`, `unknown`, `invalid` (not zstd with that dictionary) or `too_large`
+pub fn record_zstd_dictionary_body(dictionary: &str, outcome: &'static str) {
+    if let Ok(mut registry) = REGISTRY.lock() {
+        *registry
+            .zstd_dictionary_bodies
+            .entry((dictionary.to_string(), outcome))
+            .or_default() += 1;
+    }
+}
+
+/// Record a rate limit decision
+/// # Arguments
+/// * `scope` - The API scope: `ingest`, `admin` or `internal`
+/// * `outcome` - `allowed`, `limited` (rate exceeded) or `locked_out`
+pub fn record_rate_limit(scope: &'static str, outcome: &'static str) {
+    if let Ok(mut registry) = REGISTRY.lock() {
+        *registry
+            .rate_limit_decisions
+            .entry((scope, outcome))
+            .or_default() += 1;
+    }
+}
+
+/// Record a client locked out of an API scope after repeated authentication failures
+pub fn record_rate_limit_lockout(scope: &'static str) {
+    if let Ok(mut registry) = REGISTRY.lock() {
+        *registry.rate_limit_lockouts.entry(scope).or_default() += 1;
+    }
+}
+
+/// Render every metric in the Prometheus text exposition format
+pub fn render_metrics() -> String {
+    let mut out = String::new();
+
+    // STEP 1: Metrics of the registry
+    if let Ok(registry) = REGISTRY.lock() {
+        header(
+            &mut out,
+            "http_requests_total",
+            "counter",
+            "Requests served per route, method and status",
+        );
+        for ((route, method, status), count) in &registry.http_requests {
+            sample(
+                &mut out,
+                "http_requests_total",
+                &[
+                    ("route", route),
+                    ("method", method),
+                    ("status", &status.to_string()),
+                ],
+                *count as f64,
+            );
+        }
+        header(
+            &mut out,
+            "http_request_duration_seconds",
+            "histogram",
+            "Request latency per route and method",
+        );
+        for ((route, method), histogram) in &registry.http_latency {
+            let cumulative: Vec<u64> = histogram
+                .counts
+                .iter()
+                .scan(0, |running, count| {
+                    *running += count;
+                    Some(*running)
+                })
+                .collect();
+            histogram_samples(
+                &mut out,
+                "http_request_duration_seconds",
+                &[("route", route), ("method", method)],
+                &cumulative,
+                histogram.count,
+                histogram.sum.as_secs_f64(),
+            );
+        }
+        header(
+            &mut out,
+            "http_requests_by_protocol_total",
+            "counter",
+            "Requests served per protocol version",
+        );
+        for (protocol, count) in &registry.http_protocol_requests {
+            sample(
+                &mut out,
+                "http_requests_by_protocol_total",
+                &[("protocol", protocol)],
+                *count as f64,
+            );
+        }
+        header(
+            &mut out,
+            "http_connections_accepted_total",
+            "counter",
+            "Connections accepted by the server",
+        );
+        sample(
+            &mut out,
+            "http_connections_accepted_total",
+            &[],
+            registry.http_connections_accepted as f64,
+        );
+        header(
+            &mut out,
+            "http_connections_total",
+            "counter",
+            "Connections that served a request, per protocol - requests per connection is the \
+             ratio with http_requests_by_protocol_total",
+        );
+        for (protocol, count) in &registry.http_connections {
+            sample(
+                &mut out,
+                "http_connections_total",
+                &[("protocol", protocol)],
+                *count as f64,
+            );
+        }
+        header(
+            &mut out,
+            "exams_accepted_total",
+            "counter",
+            "Exams accepted per hospital and exam type",
+        );
+        for ((hospital_id, exam_type), (exams, _)) in &registry.exams_accepted {
+            sample(
+                &mut out,
+                "exams_accepted_total",
+                &[("hospital_id", hospital_id), ("exam_type", exam_type)],
+                *exams as f64,
+            );
+        }
+        header(
+            &mut out,
+            "gcs_bytes_stored_total",
+            "counter",
+            "Bytes written to storage per hospital and exam type",
+        );
+        for ((hospital_id, exam_type), (_, bytes)) in &registry.exams_accepted {
+            sample(
+                &mut out,
+                "gcs_bytes_stored_total",
+                &[("hospital_id", hospital_id), ("exam_type", exam_type)],
+                *bytes as f64,
+            );
+        }
+        header(
+            &mut out,
+            "exams_rejected_total",
+            "counter",
+            "Exams refused per exam type and reason",
+        );
+        for ((exam_type, reason), count) in &registry.exams_rejected {
+            sample(
+                &mut out,
+                "exams_rejected_total",
+                &[("exam_type", exam_type), ("reason", reason)],
+                *count as f64,
+            );
+        }
+        header(
+            &mut out,
+            "auth_failures_total",
+            "counter",
+            "Failed authentications per scope and reason",
+        );
+        for ((scope, reason), count) in &registry.auth_failures {
+            sample(
+                &mut out,
+                "auth_failures_total",
+                &[("scope", scope), ("reason", reason)],
+                *count as f64,
+            );
+        }
+        header(
+            &mut out,
+            "exams_dead_lettered_total",
+            "counter",
+            "Exams kept for replay per exam type, failed stage and reason",
+        );
+        for ((exam_type, stage, reason), count) in &registry.exams_dead_lettered {
+            sample(
+                &mut out,
+                "exams_dead_lettered_total",
+                &[
+                    ("exam_type", exam_type),
+                    ("stage", stage),
+                    ("reason", reason),
+                ],
+                *count as f64,
+            );
+        }
+        header(
+            &mut out,
+            "storage_faults_total",
+            "counter",
+            "Storage calls failed by a fault of the deployment per code and operation",
+        );
+        for ((code, operation), count) in &registry.storage_faults {
+            sample(
+                &mut out,
+                "storage_faults_total",
+                &[("code", code), ("operation", operation)],
+                *count as f64,
+            );
+        }
+        header(
+            &mut out,
+            "pubsub_messages_published_total",
+            "counter",
+            "Messages published to Pub/Sub per topic and message format",
+        );
+        for ((topic, format), count) in &registry.messages_published {
+            sample(
+                &mut out,
+                "pubsub_messages_published_total",
+                &[("topic", topic), ("format", format)],
+                *count as f64,
+            );
+        }
+        header(
+            &mut out,
+            "pubsub_fallbacks_omitted_total",
+            "counter",
+            "Fallbacks left out of a message for exceeding the attribute size per topic",
+        );
+        for (topic, count) in &registry.fallbacks_omitted {
+            sample(
+                &mut out,
+                "pubsub_fallbacks_omitted_total",
+                &[("topic", topic)],
+                *count as f64,
+            );
+        }
+        header(
+            &mut out,
+            "pubsub_consumer_reads_total",
+            "counter",
+            "Messages read by the consumers per topic, decoded from the body or the fallback",
+        );
+        for ((consumer, topic, decoded_from), count) in &registry.consumer_reads {
+            sample(
+                &mut out,
+                "pubsub_consumer_reads_total",
+                &[
+                    ("consumer", consumer),
+                    ("topic", topic),
+                    ("decoded_from", decoded_from),
+                ],
+                *count as f64,
+            );
+        }
+        header(
+            &mut out,
+            "audit_exports_total",
+            "counter",
+            "Audit entries handed to the audit sink per outcome",
+        );
+        for (outcome, count) in &registry.audit_exports {
+            sample(
+                &mut out,
+                "audit_exports_total",
+                &[("outcome", outcome)],
+                *count as f64,
+            );
+        }
+        header(
+            &mut out,
+            "audit_trail_events_total",
+            "counter",
+            "Events handed to the audit trail per outcome",
+        );
+        for (outcome, count) in &registry.audit_trail {
+            sample(
+                &mut out,
+                "audit_trail_events_total",
+                &[("outcome", outcome)],
+                *count as f64,
+            );
+        }
+        header(
+            &mut out,
+            "rate_limit_decisions_total",
+            "counter",
+            "Rate limit decisions per API scope and outcome",
+        );
+        for ((scope, outcome), count) in &registry.rate_limit_decisions {
+            sample(
+                &mut out,
+                "rate_limit_decisions_total",
+                &[("scope", scope), ("outcome", outcome)],
+                *count as f64,
+            );
+        }
+        header(
+            &mut out,
+            "rate_limit_lockouts_total",
+            "counter",
+            "Clients locked out per API scope after repeated authentication failures",
+        );
+        for (scope, count) in &registry.rate_limit_lockouts {
+            sample(
+                &mut out,
+                "rate_limit_lockouts_total",
+                &[("scope", scope)],
+                *count as f64,
+            );
+        }
+        header(
+            &mut out,
+            "registry_invalidations_total",
+            "counter",
+            "Invalidations of the cached hospital registry per source",
+        );
+        for (source, count) in &registry.registry_invalidations {
+            sample(
+                &mut out,
+                "registry_invalidations_total",
+                &[("source", source)],
+                *count as f64,
+            );
+        }
+        header(
+            &mut out,
+            "clock_drift_checks_total",
+            "counter",
+            "Checks of the host clock against the time server per outcome",
+        );
+        for (outcome, count) in &registry.clock_drift_checks {
+            sample(
+                &mut out,
+                "clock_drift_checks_total",
+                &[("outcome", outcome)],
+                *count as f64,
+            );
+        }
+        header(
+            &mut out,
+            "zstd_dictionary_bodies_total",
+            "counter",
+            "Bodies compressed with a pre-shared zstd dictionary per dictionary and outcome",
+        );
+        for ((dictionary, outcome), count) in &registry.zstd_dictionary_bodies {
+            sample(
+                &mut out,
+                "zstd_dictionary_bodies_total",
+                &[("dictionary", dictionary), ("outcome", outcome)],
+                *count as f64,
+            );
+        }
+    }
+
+    // STEP 2: Stage durations - storage is the GCS upload, publish the Pub/Sub publish
+    header(
+        &mut out,
+        "stage_duration_seconds",
+        "histogram",
+        "Duration of the internal stages per exam type",
+    );
+    for (key, histogram) in stage_durations() {
+        let (exam_type, stage) = key.rsplit_once('.').unwrap_or((&key, ""));
+        let cumulative: Vec<u64> = histogram.buckets.values().copied().collect();
+        histogram_samples(
+            &mut out,
+            "stage_duration_seconds",
+            &[("exam_type", exam_type), ("stage", stage)],
+            &cumulative,
+            histogram.count,
+            histogram.sum_ms as f64 / 1000.0,
+        );
+    }
+
+    // STEP 3: Calls to the external dependencies
+    let calls = call_stats();
+    let call_metrics: [CallMetric; 5] = [
+        (
+            "external_calls_total",
+            "Calls per dependency and operation",
+            |s| s.calls as f64,
+        ),
+        (
+            "external_call_attempts_total",
+            "Attempts, retries included",
+            |s| s.attempts as f64,
+        ),
+        (
+            "external_call_failures_total",
+            "Calls failed after their last attempt",
+            |s| s.failures as f64,
+        ),
+        (
+            "external_call_timeouts_total",
+            "Attempts that timed out",
+            |s| s.timeouts as f64,
+        ),
+        (
+            "external_call_latency_seconds_total",
+            "Total latency of the calls",
+            |s| s.total_latency_ms as f64 / 1000.0,
+        ),
+    ];
+    for (name, help, value) in call_metrics {
+        header(&mut out, name, "counter", help);
+        for (key, stats) in &calls {
+            let (dependency, operation) = key.split_once('.').unwrap_or((key, ""));
+            sample(
+                &mut out,
+                name,
+                &[("dependency", dependency), ("operation", operation)],
+                value(stats),
+            );
+        }
+    }
+    let budgets = retry_budget_stats();
+    let budget_metrics: [BudgetMetric; 3] = [
+        (
+            "retry_budget_retries_total",
+            "counter",
+            "Retries allowed by the retry budget of the dependency",
+            |s| s.retried as f64,
+        ),
+        (
+            "retry_budget_exhausted_total",
+            "counter",
+            "Retries refused because the retry budget of the dependency was used up",
+            |s| s.exhausted as f64,
+        ),
+        (
+            "retry_budget_remaining",
+            "gauge",
+            "Retries left in the current window of the dependency",
+            |s| s.remaining as f64,
+        ),
+    ];
+    for (name, kind, help, value) in budget_metrics {
+        header(&mut out, name, kind, help);
+        for (dependency, stats) in &budgets {
+            sample(&mut out, name, &[("dependency", dependency)], value(stats));
+        }
+    }
+
+    // STEP 4: Publishes held in memory, read by the exam routes to throttle
+    header(
+        &mut out,
+        "publish_backlog",
+        "gauge",
+        "Pub/Sub publishes awaiting their ack or deferred, held in memory",
+    );
+    for state in [BacklogState::InFlight, BacklogState::Deferred] {
+        sample(
+            &mut out,
+            "publish_backlog",
+            &[("state", state.as_str())],
+            PUBLISH_BACKLOG.count(state) as f64,
+        );
+    }
+
+    // STEP 5: Last measured drift of the host clock, the source of the exam timestamps
+    if let Some(offset_ms) = clock_check().and_then(|check| check.offset_ms) {
+        header(
+            &mut out,
+            "clock_drift_seconds",
+            "gauge",
+            "Offset of the host clock from the time server, positive when the host is behind",
+        );
+        sample(&mut out, "clock_drift_seconds", &[], offset_ms / 1000.0);
+    }
+    out
+}
+
+// SUPPORTING FUNCTIONS ****************************************************************************
+/// HELP and TYPE lines of a metric
+fn header(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {PREFIX}_{name} {help}");
+    let _ = writeln!(out, "# TYPE {PREFIX}_{name} {kind}");
+}
+
+/// One sample line, with escaped label values
+fn sample(out: &mut String, name: &str, labels: &[(&str, &str)], value: f64) {
+    let labels = labels
+        .iter()
+        .map(|(label, value)| format!("{label}=\"{}\"", escape_label(value)))
+        .collect::<Vec<_>>()
+        .join(",");
+    let _ = writeln!(out, "{PREFIX}_{name}{{{labels}}} {value}");
+}
+
+/// Bucket, sum and count lines of a histogram
+/// # Arguments
+/// * `cumulative` - Cumulative counts per BUCKETS_MS bound, then +Inf
+/// * `sum_s` - Sum of the observations in seconds
+fn histogram_samples(
+    out: &mut String,
+    name: &str,
+    labels: &[(&str, &str)],
+    cumulative: &[u64],
+    count: u64,
+    sum_s: f64,
+) {
+    for (i, running) in cumulative.iter().enumerate() {
+        let le = BUCKETS_MS
+            .get(i)
+            .map(|bound| (*bound as f64 / 1000.0).to_string())
+            .unwrap_or("+Inf".to_string());
+        let mut bucket_labels = labels.to_vec();
+        bucket_labels.push(("le", &le));
+        sample(
+            out,
+            &format!("{name}_bucket"),
+            &bucket_labels,
+            *running as f64,
+        );
+    }
+    sample(out, &format!("{name}_sum"), labels, sum_s);
+    sample(out, &format!("{name}_count"), labels, count as f64);
+}
+
+/// Escape a label value: backslash, double quote and line feed
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+// TESTS *******************************************************************************************
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Happy path: counters and histograms are rendered with their labels
+    #[test]
+    fn metrics_rendered() {
+        record_request("/v1/metrics_test", "POST", 202, Duration::from_millis(30));
+        record_request("/v1/metrics_test", "POST", 202, Duration::from_secs(20));
+        record_exam_accepted("metrics-h1", "ecg_exam", 1_000);
+        record_exam_rejected("ecg_exam", ReasonCode::LeadLength);
+        record_auth_failure("hospital", ReasonCode::AuthBadKey);
+        record_exam_dead_lettered("ecg_exam", "storage", ReasonCode::GcsTimeout);
+        record_storage_fault("gcs_permission_denied", "metrics_test_upload");
+        record_message_published("metrics-topic", "avro+json");
+        record_fallback_omitted("metrics-topic");
+        record_consumer_reads("metrics-consumer", "metrics-topic", "fallback", 3);
+        record_protocol_request("h2", true);
+        record_protocol_request("h2", false);
+
+        let text = render_metrics();
+        assert!(text.contains(
+            "sentinela_http_requests_total{route=\"/v1/metrics_test\",method=\"POST\",status=\"202\"} 2"
+        ));
+        assert!(text.contains("sentinela_http_request_duration_seconds_bucket{route=\"/v1/metrics_test\",method=\"POST\",le=\"0.05\"} 1"));
+        assert!(text.contains("sentinela_http_request_duration_seconds_bucket{route=\"/v1/metrics_test\",method=\"POST\",le=\"+Inf\"} 2"));
+        assert!(text.contains("sentinela_http_request_duration_seconds_count{route=\"/v1/metrics_test\",method=\"POST\"} 2"));
+        assert!(text.contains(
+            "sentinela_gcs_bytes_stored_total{hospital_id=\"metrics-h1\",exam_type=\"ecg_exam\"} 1000"
+        ));
+        assert!(text.contains("# TYPE sentinela_exams_rejected_total counter"));
+        assert!(text
+            .contains("sentinela_auth_failures_total{scope=\"hospital\",reason=\"AUTH_BAD_KEY\"}"));
+        assert!(text.contains(
+            "sentinela_exams_dead_lettered_total{exam_type=\"ecg_exam\",stage=\"storage\",reason=\"GCS_TIMEOUT\"} 1"
+        ));
+        assert!(text.contains("sentinela_storage_faults_total{code=\"gcs_permission_denied\",operation=\"metrics_test_upload\"} 1"));
+        assert!(text.contains("sentinela_pubsub_messages_published_total{topic=\"metrics-topic\",format=\"avro+json\"} 1"));
+        assert!(
+            text.contains("sentinela_pubsub_fallbacks_omitted_total{topic=\"metrics-topic\"} 1")
+        );
+        assert!(text.contains("sentinela_pubsub_consumer_reads_total{consumer=\"metrics-consumer\",topic=\"metrics-topic\",decoded_from=\"fallback\"} 3"));
+        assert!(text.contains("# TYPE sentinela_http_connections_accepted_total counter"));
+        assert!(text.contains("sentinela_http_requests_by_protocol_total{protocol=\"h2\"}"));
+        assert!(text.contains("sentinela_http_connections_total{protocol=\"h2\"}"));
+    }
+
+    #[test]
+    fn label_values_escaped() {
+        assert_eq!(escape_label("a\"b\\c\nd"), "a\\\"b\\\\c\\nd");
+    }
+}
@@ -0,0 +1,283 @@
+// Imports *****************************************************************************************
+// External Crates
+use actix_web::body::MessageBody;
+use actix_web::dev::Extensions;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::header::{HeaderName, HeaderValue};
+use actix_web::http::{Method, Version};
+use actix_web::middleware::Next;
+use actix_web::{Error, HttpMessage};
+use std::any::Any;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::time::Instant;
+
+// Internal Modules
+use crate::telemetry::metrics::{
+    record_connection_accepted, record_protocol_request, record_request,
+};
+use crate::telemetry::trace_context::{scope_trace, TraceContext, TRACEPARENT};
+use crate::utils::ids::RequestId;
+use crate::utils::request_id::{request_id, scope_request_id};
+use crate::utils::response_format::{scope_response_format, ResponseFormat};
+
+// Constants ***************************************************************************************
+/// Route label of the requests matching no route - raw paths would make the label unbounded
+const UNMATCHED_ROUTE: &str = "unmatched";
+
+// Structs *****************************************************************************************
+/// State of a connection, kept in its connection data - set by `connection_metrics`
+#[derive(Debug, Default)]
+struct ConnectionState {
+    served: AtomicBool,
+}
+
+/// Request counted in REQUESTS_IN_FLIGHT until dropped - also when its client disconnects
+struct InFlightRequest;
+
+impl InFlightRequest {
+    fn start() -> Self {
+        REQUESTS_IN_FLIGHT.fetch_add(1, Ordering::Relaxed);
+        InFlightRequest
+    }
+}
+
+impl Drop for InFlightRequest {
+    fn drop(&mut self) {
+        REQUESTS_IN_FLIGHT.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+// Global variables ********************************************************************************
+/// Requests of this instance being handled
+static REQUESTS_IN_FLIGHT: AtomicUsize = AtomicUsize::new(0);
+
+// MAIN FUNCTIONS **********************************************************************************
+/// Count the connections accepted by the server - passed to `HttpServer::on_connect`
+/// # Arguments
+/// * `_connection` - The accepted connection
+/// * `data` - The connection data, shared by every request of the connection
+pub fn connection_metrics(_connection: &dyn Any, data: &mut Extensions) {
+    record_connection_accepted();
+    data.insert(ConnectionState::default());
+}
+
+/// Give every request an id and a trace: both are visible to the handlers and the log lines, and
+/// echoed in the 'x-request-id' and 'traceparent' headers - the handlers add them to their bodies,
+/// in the format of the Accept header, through `NegotiatedResponse`
+/// # Arguments
+/// * `req` - The incoming request, possibly carrying 'x-request-id', 'traceparent' and 'accept'
+/// * `next` - The rest of the service chain
+/// # Returns
+/// * The response of the handler, with its correlation headers
+pub async fn correlation_middleware(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    // STEP 1: Honor the caller's ids, or generate them
+    let id = request_id(req.request());
+    let trace = TraceContext::from_request(req.request());
+    let format = ResponseFormat::of_request(req.request());
+    req.extensions_mut().insert(RequestId(id.clone()));
+
+    // STEP 2: Handle the request within its ids and format
+    let handling = scope_response_format(format, next.call(req));
+    let mut response = scope_request_id(id.clone(), scope_trace(trace.clone(), handling)).await?;
+
+    // STEP 3: Echo the ids to the caller
+    for (name, value) in [("x-request-id", id), (TRACEPARENT, trace.traceparent())] {
+        if let Ok(value) = HeaderValue::from_str(&value) {
+            response
+                .headers_mut()
+                .insert(HeaderName::from_static(name), value);
+        }
+    }
+    Ok(response)
+}
+
+/// Count every request and its latency per route pattern, method and status code
+/// # Arguments
+/// * `req` - The incoming request
+/// * `next` - The rest of the service chain
+/// # Returns
+/// * The response of the handler, unchanged
+pub async fn request_metrics_middleware(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    let started = Instant::now();
+    let _in_flight = InFlightRequest::start();
+    let method = method_label(req.method());
+    // The first request of a connection labels the connection with its protocol - an h2
+    // connection then carries many more requests than an HTTP/1.1 one
+    let first_on_connection = req
+        .conn_data::<ConnectionState>()
+        .is_some_and(|state| !state.served.swap(true, Ordering::Relaxed));
+    record_protocol_request(protocol_label(req.version()), first_on_connection);
+    let route = req.match_pattern();
+    let response = next.call(req).await;
+
+    // The pattern is only known once the request was routed
+    let (route, status) = match &response {
+        Ok(response) => (
+            response.request().match_pattern(),
+            response.status().as_u16(),
+        ),
+        Err(e) => (route, e.as_response_error().status_code().as_u16()),
+    };
+    record_request(
+        route.as_deref().unwrap_or(UNMATCHED_ROUTE),
+        method,
+        status,
+        started.elapsed(),
+    );
+    response
+}
+
+/// Requests of this instance being handled, counted by `request_metrics_middleware`
+pub fn requests_in_flight() -> usize {
+    REQUESTS_IN_FLIGHT.load(Ordering::Relaxed)
+}
+
+// SUPPORTING FUNCTIONS ****************************************************************************
+/// Method label - extension methods are counted together
+fn method_label(method: &Method) -> &'static str {
+    match *method {
+        Method::GET => "GET",
+        Method::POST => "POST",
+        Method::PUT => "PUT",
+        Method::DELETE => "DELETE",
+        Method::PATCH => "PATCH",
+        Method::HEAD => "HEAD",
+        Method::OPTIONS => "OPTIONS",
+        _ => "OTHER",
+    }
+}
+
+/// Bounded label of a protocol version
+fn protocol_label(version: Version) -> &'static str {
+    match version {
+        Version::HTTP_10 => "http/1.0",
+        Version::HTTP_11 => "http/1.1",
+        Version::HTTP_2 => "h2",
+        _ => "other",
+    }
+}
+
+// TESTS *******************************************************************************************
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::telemetry::metrics::render_metrics;
+    use crate::utils::response_format::NegotiatedResponse;
+    use actix_web::middleware::from_fn;
+    use actix_web::{get, test as actix_test, web, App, HttpResponse};
+    use serde_json::{json, Value};
+
+    #[get("/item/{id}")]
+    async fn item() -> HttpResponse {
+        HttpResponse::Ok().finish()
+    }
+
+    #[get("/json")]
+    async fn json_body() -> HttpResponse {
+        HttpResponse::Ok().negotiated(&json!({"status": "ok"}))
+    }
+
+    #[get("/text")]
+    async fn text_body() -> HttpResponse {
+        HttpResponse::Ok().body("ok")
+    }
+
+    // The caller's trace is joined and echoed in the headers and the JSON bodies
+    #[actix_web::test]
+    async fn correlation_ids_echoed() {
+        let app = actix_test::init_service(
+            App::new()
+                .wrap(from_fn(correlation_middleware))
+                .service(json_body)
+                .service(text_body),
+        )
+        .await;
+        let req = actix_test::TestRequest::get()
+            .uri("/json")
+            .insert_header(("x-request-id", "req-1"))
+            .insert_header((
+                TRACEPARENT,
+                "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01",
+            ))
+            .to_request();
+        let res = actix_test::call_service(&app, req).await;
+        assert_eq!(res.headers().get("x-request-id").unwrap(), "req-1");
+        let traceparent = res
+            .headers()
+            .get(TRACEPARENT)
+            .unwrap()
+            .to_str()
+            .unwrap()
+            .to_string();
+        assert!(traceparent.starts_with("00-4bf92f3577b34da6a3ce929d0e0e4736-"));
+        let body: Value = actix_test::read_body_json(res).await;
+        assert_eq!(
+            body,
+            json!({"status": "ok", "request_id": "req-1", "trace_id": "4bf92f3577b34da6a3ce929d0e0e4736"})
+        );
+
+        let req = actix_test::TestRequest::get().uri("/text").to_request();
+        let res = actix_test::call_service(&app, req).await;
+        assert!(res.headers().contains_key(TRACEPARENT));
+        assert_eq!(actix_test::read_body(res).await, "ok");
+    }
+
+    // Requests are labelled by route pattern, never by raw path
+    #[actix_web::test]
+    async fn requests_counted_per_pattern() {
+        let app = actix_test::init_service(
+            App::new()
+                .wrap(from_fn(request_metrics_middleware))
+                .service(web::scope("/metrics_mw").service(item)),
+        )
+        .await;
+        for path in [
+            "/metrics_mw/item/1",
+            "/metrics_mw/item/2",
+            "/metrics_mw/nope",
+        ] {
+            actix_test::call_service(&app, actix_test::TestRequest::get().uri(path).to_request())
+                .await;
+        }
+
+        let text = render_metrics();
+        assert!(text.contains(
+            "sentinela_http_requests_total{route=\"/metrics_mw/item/{id}\",method=\"GET\",status=\"200\"} 2"
+        ));
+        assert!(!text.contains("/metrics_mw/item/1"));
+        assert!(text.contains("route=\"unmatched\",method=\"GET\",status=\"404\""));
+    }
+
+    // Happy path: a request counts as in flight while it is handled
+    #[actix_web::test]
+    async fn requests_in_flight_counted() {
+        #[get("/busy")]
+        async fn busy() -> HttpResponse {
+            HttpResponse::Ok().body(requests_in_flight().to_string())
+        }
+        let app = actix_test::init_service(
+            App::new()
+                .wrap(from_fn(request_metrics_middleware))
+                .service(busy),
+        )
+        .await;
+        let req = actix_test::TestRequest::get().uri("/busy").to_request();
+        let body = actix_test::call_and_read_body(&app, req).await;
+        let in_flight: usize = std::str::from_utf8(&body).unwrap().parse().unwrap();
+        assert!(in_flight >= 1);
+    }
+
+    // Borderline: protocol labels stay bounded
+    #[test]
+    fn protocol_labels() {
+        assert_eq!(protocol_label(Version::HTTP_2), "h2");
+        assert_eq!(protocol_label(Version::HTTP_11), "http/1.1");
+        assert_eq!(protocol_label(Version::HTTP_09), "other");
+    }
+}
@@ -0,0 +1,4 @@
+pub mod audit_export;
+pub mod metrics;
+pub mod middleware;
+pub mod trace_context;
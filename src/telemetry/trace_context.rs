@@ -0,0 +1,346 @@
+// Imports *****************************************************************************************
+// External Crates
+use actix_web::HttpRequest;
+use google_cloud_storage::http::objects::upload::{Media, UploadType};
+use google_cloud_storage::http::objects::Object;
+use log::info;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::future::Future;
+use std::io::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Instant;
+
+// Internal Modules
+use crate::telemetry::audit_export::export_audit;
+use crate::utils::request_id::{current_request_id, scope_request_id};
+
+// Constants ***************************************************************************************
+/// W3C trace context header, honored on requests and echoed on responses
+pub const TRACEPARENT: &str = "traceparent";
+/// Version of the W3C trace context format
+const TRACEPARENT_VERSION: &str = "00";
+
+// Global variables ********************************************************************************
+/// Sequence making generated ids unique within the instance
+static SEQUENCE: AtomicU64 = AtomicU64::new(0);
+
+tokio::task_local! {
+    /// Trace of the request (or background job) being handled
+    static CURRENT_TRACE: TraceContext;
+}
+
+// Structs *****************************************************************************************
+/// W3C trace context of a request: the trace it belongs to and the span of this gateway
+/// # Arguments
+/// * `trace_id` - 32 lowercase hex characters, shared by every service handling the exam
+/// * `span_id` - 16 lowercase hex characters, the current span
+/// * `sampled` - The caller's sampling decision, generated traces are sampled
+#[derive(Debug, Clone, PartialEq)]
+pub struct TraceContext {
+    pub trace_id: String,
+    pub span_id: String,
+    pub sampled: bool,
+}
+
+impl TraceContext {
+    /// Trace of a request: a child of the caller's 'traceparent' header if valid, otherwise a new
+    /// trace
+    /// # Arguments
+    /// * `req` - The HTTP request
+    pub fn from_request(req: &HttpRequest) -> Self {
+        req.headers()
+            .get(TRACEPARENT)
+            .and_then(|v| v.to_str().ok())
+            .and_then(Self::parse)
+            .map(|parent| parent.child())
+            .unwrap_or_else(Self::new_root)
+    }
+
+    /// Parse a 'traceparent' header: `00-<trace_id>-<parent_id>-<flags>`
+    /// # Returns
+    /// * None if the header is malformed or carries the all-zero ids forbidden by the spec
+    pub fn parse(traceparent: &str) -> Option<Self> {
+        let parts: Vec<&str> = traceparent.trim().split('-').collect();
+        let [version, trace_id, span_id, flags] = parts[..] else {
+            return None;
+        };
+        let valid = version == TRACEPARENT_VERSION
+            && is_lower_hex(trace_id, 32)
+            && is_lower_hex(span_id, 16)
+            && is_lower_hex(flags, 2)
+            && trace_id.chars().any(|c| c != '0')
+            && span_id.chars().any(|c| c != '0');
+        if !valid {
+            return None;
+        }
+        let flags = u8::from_str_radix(flags, 16).ok()?;
+        Some(Self {
+            trace_id: trace_id.to_string(),
+            span_id: span_id.to_string(),
+            sampled: flags & 1 == 1,
+        })
+    }
+
+    /// A new, sampled trace
+    pub fn new_root() -> Self {
+        let id = random_hex(48);
+        Self {
+            trace_id: id[..32].to_string(),
+            span_id: id[32..].to_string(),
+            sampled: true,
+        }
+    }
+
+    /// A span of the same trace, child of this one
+    pub fn child(&self) -> Self {
+        Self {
+            span_id: random_hex(16),
+            ..self.clone()
+        }
+    }
+
+    /// The 'traceparent' header value of this span
+    pub fn traceparent(&self) -> String {
+        format!(
+            "{TRACEPARENT_VERSION}-{}-{}-{:02x}",
+            self.trace_id,
+            self.span_id,
+            u8::from(self.sampled)
+        )
+    }
+}
+
+/// Timed step of a request, logged with its trace when dropped
+/// # Arguments
+/// * `name` - The step (validation, gcs.upload_ecg, pubsub.publish, ...)
+/// * `context` - The span's own context, child of the current trace
+/// * `parent_id` - The span id of the request
+pub struct TraceSpan {
+    name: String,
+    context: TraceContext,
+    parent_id: String,
+    started: Instant,
+}
+
+impl Drop for TraceSpan {
+    fn drop(&mut self) {
+        info!(target: "trace", "span name={} trace_id={} span_id={} parent_id={} duration_ms={}",
+            self.name, self.context.trace_id, self.context.span_id, self.parent_id,
+            self.started.elapsed().as_millis());
+    }
+}
+
+// MAIN FUNCTIONS **********************************************************************************
+/// Run the handling of a request (or job) with its trace available to `current_trace`
+/// # Arguments
+/// * `trace` - The trace context
+/// * `handling` - The future handling the request
+pub async fn scope_trace<F: Future>(trace: TraceContext, handling: F) -> F::Output {
+    CURRENT_TRACE.scope(trace, handling).await
+}
+
+/// Trace of the request being handled, if called within `scope_trace`
+pub fn current_trace() -> Option<TraceContext> {
+    CURRENT_TRACE.try_with(|trace| trace.clone()).ok()
+}
+
+/// Carry the current trace and request id into a future spawned in the background
+/// # Arguments
+/// * `task` - The future to spawn
+pub fn in_current_trace<F: Future>(task: F) -> impl Future<Output = F::Output> {
+    let trace = current_trace().unwrap_or_else(TraceContext::new_root);
+    let request_id = current_request_id().unwrap_or_default();
+    scope_request_id(request_id, scope_trace(trace, task))
+}
+
+/// Start a timed step of the current trace - untraced work returns None
+/// # Arguments
+/// * `name` - The step name
+pub fn start_span(name: &str) -> Option<TraceSpan> {
+    let parent = current_trace()?;
+    Some(TraceSpan {
+        name: name.to_string(),
+        context: parent.child(),
+        parent_id: parent.span_id,
+        started: Instant::now(),
+    })
+}
+
+/// Correlation attributes of the current trace, for Pub/Sub attributes and GCS object metadata
+/// # Returns
+/// * `trace_id` and `traceparent`, empty outside of a trace
+pub fn trace_attributes() -> HashMap<String, String> {
+    current_trace()
+        .map(|trace| {
+            HashMap::from([
+                ("trace_id".to_string(), trace.trace_id.clone()),
+                (TRACEPARENT.to_string(), trace.traceparent()),
+            ])
+        })
+        .unwrap_or_default()
+}
+
+/// GCS upload carrying the correlation attributes of the current trace as object metadata
+/// # Arguments
+/// * `media` - The object name and content type
+/// # Returns
+/// * A multipart upload with the metadata, or the simple upload outside of a trace
+pub fn traced_upload_type(media: Media) -> UploadType {
+    let attributes = trace_attributes();
+    if attributes.is_empty() {
+        return UploadType::Simple(media);
+    }
+    UploadType::Multipart(Box::new(Object {
+        name: media.name.into_owned(),
+        content_type: Some(media.content_type.into_owned()),
+        metadata: Some(attributes),
+        ..Default::default()
+    }))
+}
+
+/// Log line format: the env_logger default with the request id and trace id appended - audit
+/// lines are also queued for the audit sink
+/// # Arguments
+/// * `buf` - The env_logger buffer
+/// * `record` - The log record
+pub fn format_log_line(
+    buf: &mut env_logger::fmt::Formatter,
+    record: &log::Record,
+) -> std::io::Result<()> {
+    export_audit(record);
+    write!(
+        buf,
+        "[{} {} {}] {}",
+        buf.timestamp_millis(),
+        record.level(),
+        record.target(),
+        record.args()
+    )?;
+    writeln!(buf, "{}", correlation_suffix())
+}
+
+// SUPPORT FUNCTIONS *******************************************************************************
+/// ' request_id=.. trace_id=..' of the current request, empty outside of a request
+fn correlation_suffix() -> String {
+    let mut suffix = String::new();
+    if let Some(id) = current_request_id().filter(|id| !id.is_empty()) {
+        suffix.push_str(&format!(" request_id={id}"));
+    }
+    if let Some(trace) = current_trace() {
+        suffix.push_str(&format!(" trace_id={}", trace.trace_id));
+    }
+    suffix
+}
+
+/// Whether a value is exactly `len` lowercase hex characters
+fn is_lower_hex(value: &str, len: usize) -> bool {
+    value.len() == len
+        && value
+            .chars()
+            .all(|c| c.is_ascii_digit() || ('a'..='f').contains(&c))
+}
+
+/// Unpredictable-enough hex id from the time, the process and the instance sequence
+fn random_hex(len: usize) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(
+        chrono::Utc::now()
+            .timestamp_nanos_opt()
+            .unwrap_or_default()
+            .to_le_bytes(),
+    );
+    hasher.update(std::process::id().to_le_bytes());
+    hasher.update(SEQUENCE.fetch_add(1, Ordering::Relaxed).to_le_bytes());
+    let hex: String = hasher
+        .finalize()
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect();
+    hex[..len].to_string()
+}
+
+// TESTS *******************************************************************************************
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::test;
+
+    const PARENT: &str = "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01";
+
+    #[test]
+    async fn traceparent_round_trip() {
+        let trace = TraceContext::parse(PARENT).unwrap();
+        assert_eq!(trace.trace_id, "4bf92f3577b34da6a3ce929d0e0e4736");
+        assert!(trace.sampled);
+        assert_eq!(trace.traceparent(), PARENT);
+    }
+
+    // Malformed headers, unknown versions and all-zero ids start a new trace
+    #[test]
+    async fn traceparent_invalid() {
+        for header in [
+            "",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7",
+            "01-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01",
+            "00-4BF92F3577B34DA6A3CE929D0E0E4736-00f067aa0ba902b7-01",
+            "00-00000000000000000000000000000000-00f067aa0ba902b7-01",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-0000000000000000-01",
+        ] {
+            assert_eq!(TraceContext::parse(header), None, "{header}");
+        }
+        let root = TraceContext::new_root();
+        assert!(TraceContext::parse(&root.traceparent()).is_some());
+        assert_ne!(root.trace_id, TraceContext::new_root().trace_id);
+    }
+
+    // The gateway joins the caller's trace with a span of its own
+    #[test]
+    async fn trace_from_request() {
+        let req = test::TestRequest::default()
+            .insert_header((TRACEPARENT, PARENT))
+            .to_http_request();
+        let trace = TraceContext::from_request(&req);
+        assert_eq!(trace.trace_id, "4bf92f3577b34da6a3ce929d0e0e4736");
+        assert_ne!(trace.span_id, "00f067aa0ba902b7");
+
+        let untraced = TraceContext::from_request(&test::TestRequest::default().to_http_request());
+        assert_ne!(untraced.trace_id, trace.trace_id);
+    }
+
+    // Attributes, spans and background tasks follow the current trace
+    #[test]
+    async fn trace_scoped() {
+        assert!(trace_attributes().is_empty());
+        assert!(start_span("validation").is_none());
+        let trace = TraceContext::parse(PARENT).unwrap();
+        let (attributes, span, spawned) = scope_trace(trace.clone(), async {
+            let spawned = actix_web::rt::spawn(in_current_trace(async { current_trace() }));
+            (trace_attributes(), start_span("validation"), spawned.await)
+        })
+        .await;
+        assert_eq!(attributes["trace_id"], trace.trace_id);
+        assert_eq!(attributes[TRACEPARENT], PARENT);
+        let span = span.unwrap();
+        assert_eq!(span.context.trace_id, trace.trace_id);
+        assert_eq!(span.parent_id, trace.span_id);
+        assert_eq!(spawned.unwrap(), Some(trace));
+    }
+
+    // Objects stored within a trace carry its ids as metadata
+    #[test]
+    async fn upload_metadata() {
+        let media = || Media::new("ecg_exam/a.parquet");
+        assert!(matches!(traced_upload_type(media()), UploadType::Simple(_)));
+        let trace = TraceContext::parse(PARENT).unwrap();
+        let upload = scope_trace(trace, async { traced_upload_type(media()) }).await;
+        let UploadType::Multipart(object) = upload else {
+            panic!("expected a multipart upload");
+        };
+        assert_eq!(object.name, "ecg_exam/a.parquet");
+        assert_eq!(
+            object.metadata.unwrap()["trace_id"],
+            "4bf92f3577b34da6a3ce929d0e0e4736"
+        );
+    }
+}
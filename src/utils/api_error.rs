@@ -0,0 +1,602 @@
+// Imports *****************************************************************************************
+// External Crates
+use actix_web::error::{JsonPayloadError, PayloadError};
+use actix_web::http::StatusCode;
+use actix_web::{HttpResponse, ResponseError};
+use serde::Serialize;
+use serde_json::json;
+use std::collections::BTreeMap;
+use std::fmt;
+use utoipa::openapi::header::HeaderBuilder;
+use utoipa::openapi::schema::{Object, Type};
+use utoipa::openapi::{ContentBuilder, Ref, RefOr, Response, ResponseBuilder};
+use utoipa::{IntoResponses, ToSchema};
+use validator::ValidationErrors;
+
+// Internal Modules
+use crate::telemetry::trace_context::current_trace;
+use crate::utils::reason_code::ReasonCode;
+use crate::utils::request_id::current_request_id;
+use crate::utils::response_format::NegotiatedResponse;
+
+// Constants ***************************************************************************************
+/// Delay advertised to the hospitals while the storage of the deployment is misconfigured
+const STORAGE_RETRY_AFTER_S: u64 = 300;
+
+// Structs *****************************************************************************************
+/// Error returned by every route, rendered as
+/// `{"error": message, "code": code, "reason": reason, "request_id": id, "trace_id": id,
+/// "fields": {field: [messages]}}`
+/// - `reason` is the ReasonCode also used by the metrics, audit records and rejection digests
+/// - `fields` is only present for validation errors
+#[derive(Debug, Clone, PartialEq)]
+pub enum ApiError {
+    /// The payload failed validation - messages per field, and the reason of the first field
+    Validation {
+        reason: ReasonCode,
+        fields: BTreeMap<String, Vec<String>>,
+    },
+    /// The request is malformed (unparseable body, invalid parameter)
+    BadRequest(String),
+    /// The content of the request is invalid for a reason of its own (image not matching its
+    /// format, unreadable DICOM) - answered as a bad request
+    InvalidContent(ReasonCode, String),
+    /// The hospital or operator could not be authenticated
+    Unauthorized(String),
+    /// The bearer token of the hospital was refused - the refusal tells why
+    InvalidToken(TokenRefusal),
+    /// The caller is authenticated but not allowed to act on the resource
+    Forbidden(String),
+    /// The resource does not exist or is not visible to the caller
+    NotFound(&'static str),
+    /// The body is larger than the configured limit
+    PayloadTooLarge,
+    /// The body is compressed with an encoding the route does not accept - names the encoding
+    UnsupportedEncoding(String),
+    /// The malware scanner found the signature in the payload - nothing was stored
+    Infected(String),
+    /// The malware scanner gave no verdict - payloads are never stored unscanned
+    ScanUnavailable,
+    /// The gateway cannot take more work - the caller should retry after the delay; the reason
+    /// tells the full ingest queue from the critical publish backlog
+    RateLimited {
+        retry_after_s: u64,
+        reason: ReasonCode,
+    },
+    /// The monthly exam quota of the hospital or its group is used up - it resets after the delay
+    QuotaExceeded { retry_after_s: u64 },
+    /// The idempotency key of an accepted exam was sent again with another payload - nothing is
+    /// replayed nor processed
+    IdempotencyKeyReused,
+    /// The exam could not be stored, nor kept for replay
+    StorageFailure,
+    /// The storage of the deployment is misconfigured (permissions, bucket, quota) - the hospital
+    /// gets a neutral 503, the diagnostic is in the operator readiness endpoint
+    StorageUnavailable,
+    /// The exam notification could not be published, nor kept for replay
+    PublishFailure,
+    /// The transformation plugin of the hospital failed - details are logged, never returned
+    PluginFailed,
+    /// Any other processing error - details are logged, never returned
+    Internal,
+}
+
+/// JSON body of every error response, as documented in the OpenAPI specification
+/// # Arguments
+/// * `error` - A human-readable message
+/// * `code` - The stable machine-readable code of the error, e.g. `validation_failed`
+/// * `reason` - The reason code, e.g. `LEAD_LENGTH`
+/// * `request_id` - The id of the failed request, for correlation with the gateway logs
+/// * `trace_id` - The W3C trace of the failed request, for correlation with the downstream logs
+/// * `fields` - The messages per invalid field, for validation errors only
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ErrorBody {
+    pub error: String,
+    #[schema(value_type = String)]
+    pub code: &'static str,
+    pub reason: ReasonCode,
+    pub request_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub trace_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fields: Option<BTreeMap<String, Vec<String>>>,
+}
+
+/// Why a bearer token was refused - each refusal has its own error code, so expired tokens, wrong
+/// audiences and unknown keys are told apart by the hospitals and the auth failure metrics
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenRefusal {
+    /// Past its `exp` (or before its `nbf`), beyond the leeway
+    Expired,
+    /// Issued for another audience
+    Audience,
+    /// Issued by another identity provider
+    Issuer,
+    /// Signed by a key id absent from the JWKS, even once refreshed
+    UnknownKey,
+    /// Malformed, missing a claim or with a signature that does not verify
+    Invalid,
+}
+
+impl TokenRefusal {
+    /// Error code of the refusal
+    pub fn code(&self) -> &'static str {
+        match self {
+            TokenRefusal::Expired => "token_expired",
+            TokenRefusal::Audience => "token_audience",
+            TokenRefusal::Issuer => "token_issuer",
+            TokenRefusal::UnknownKey => "token_unknown_key",
+            TokenRefusal::Invalid => "token_invalid",
+        }
+    }
+
+    /// Reason code of the refusal
+    pub fn reason(&self) -> ReasonCode {
+        match self {
+            TokenRefusal::Expired => ReasonCode::TokenExpired,
+            TokenRefusal::Audience => ReasonCode::TokenAudience,
+            TokenRefusal::Issuer => ReasonCode::TokenIssuer,
+            TokenRefusal::UnknownKey => ReasonCode::TokenUnknownKey,
+            TokenRefusal::Invalid => ReasonCode::TokenInvalid,
+        }
+    }
+}
+
+impl ApiError {
+    /// Stable machine-readable code of the error
+    pub fn code(&self) -> &'static str {
+        match self {
+            ApiError::Validation { .. } => "validation_failed",
+            ApiError::BadRequest(_) | ApiError::InvalidContent(..) => "bad_request",
+            ApiError::Unauthorized(_) => "unauthorized",
+            ApiError::InvalidToken(refusal) => refusal.code(),
+            ApiError::Forbidden(_) => "forbidden",
+            ApiError::NotFound(_) => "not_found",
+            ApiError::PayloadTooLarge => "payload_too_large",
+            ApiError::UnsupportedEncoding(_) => "unsupported_encoding",
+            ApiError::Infected(_) => "payload_infected",
+            ApiError::ScanUnavailable => "scan_unavailable",
+            ApiError::RateLimited { .. } => "rate_limited",
+            ApiError::QuotaExceeded { .. } => "quota_exceeded",
+            ApiError::IdempotencyKeyReused => "idempotency_key_reused",
+            ApiError::StorageFailure => "storage_failure",
+            ApiError::StorageUnavailable => "service_unavailable",
+            ApiError::PublishFailure => "publish_failure",
+            ApiError::PluginFailed | ApiError::Internal => "internal_error",
+        }
+    }
+
+    /// Reason code of the error, shared with the metrics, audit records and rejection digests
+    pub fn reason(&self) -> ReasonCode {
+        match self {
+            ApiError::Validation { reason, .. } | ApiError::InvalidContent(reason, _) => *reason,
+            ApiError::BadRequest(_) => ReasonCode::MalformedRequest,
+            ApiError::Unauthorized(message) => ReasonCode::of_auth_failure(message),
+            ApiError::InvalidToken(refusal) => refusal.reason(),
+            ApiError::Forbidden(_) => ReasonCode::Forbidden,
+            ApiError::NotFound(_) => ReasonCode::NotFound,
+            ApiError::PayloadTooLarge => ReasonCode::PayloadTooLarge,
+            ApiError::UnsupportedEncoding(_) => ReasonCode::UnsupportedEncoding,
+            ApiError::Infected(_) => ReasonCode::Malware,
+            ApiError::ScanUnavailable => ReasonCode::ScanUnavailable,
+            ApiError::RateLimited { reason, .. } => *reason,
+            ApiError::QuotaExceeded { .. } => ReasonCode::QuotaExceeded,
+            ApiError::IdempotencyKeyReused => ReasonCode::IdempotencyKeyReused,
+            ApiError::StorageFailure => ReasonCode::StorageFailure,
+            ApiError::StorageUnavailable => ReasonCode::StorageUnavailable,
+            ApiError::PublishFailure => ReasonCode::PublishFailure,
+            ApiError::PluginFailed => ReasonCode::PluginFailed,
+            ApiError::Internal => ReasonCode::Internal,
+        }
+    }
+
+    /// JSON body of the error
+    /// # Arguments
+    /// * `request_id` - The id of the failed request, for correlation with the gateway logs
+    fn body(&self, request_id: Option<String>) -> serde_json::Value {
+        let fields = match self {
+            ApiError::Validation { fields, .. } => Some(fields.clone()),
+            _ => None,
+        };
+        json!(ErrorBody {
+            error: self.to_string(),
+            code: self.code(),
+            reason: self.reason(),
+            request_id,
+            trace_id: current_trace().map(|trace| trace.trace_id),
+            fields,
+        })
+    }
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ApiError::Validation { .. } => write!(f, "Invalid Input"),
+            ApiError::BadRequest(message)
+            | ApiError::InvalidContent(_, message)
+            | ApiError::Unauthorized(message)
+            | ApiError::Forbidden(message) => {
+                write!(f, "{message}")
+            }
+            ApiError::InvalidToken(_) => write!(f, "Authentication failed: Invalid bearer token"),
+            ApiError::NotFound(message) => write!(f, "{message}"),
+            ApiError::PayloadTooLarge => write!(f, "Payload Too Large"),
+            ApiError::UnsupportedEncoding(encoding) => {
+                write!(f, "Unsupported Content-Encoding: {encoding}")
+            }
+            ApiError::Infected(signature) => write!(f, "Malware Found: {signature}"),
+            ApiError::ScanUnavailable => write!(f, "Malware Scan Unavailable"),
+            ApiError::RateLimited { .. } => write!(f, "Service Busy"),
+            ApiError::QuotaExceeded { .. } => write!(f, "Monthly Quota Exceeded"),
+            ApiError::IdempotencyKeyReused => {
+                write!(f, "Idempotency-Key Already Used With Another Payload")
+            }
+            ApiError::StorageFailure => write!(f, "Storage Error"),
+            ApiError::StorageUnavailable => write!(f, "Service Unavailable"),
+            ApiError::PublishFailure => write!(f, "Publish Error"),
+            ApiError::PluginFailed | ApiError::Internal => write!(f, "Processing Error"),
+        }
+    }
+}
+
+impl std::error::Error for ApiError {}
+
+impl ResponseError for ApiError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            ApiError::Validation { .. }
+            | ApiError::BadRequest(_)
+            | ApiError::InvalidContent(..) => StatusCode::BAD_REQUEST,
+            ApiError::Unauthorized(_) | ApiError::InvalidToken(_) => StatusCode::UNAUTHORIZED,
+            ApiError::Forbidden(_) => StatusCode::FORBIDDEN,
+            ApiError::NotFound(_) => StatusCode::NOT_FOUND,
+            ApiError::PayloadTooLarge => StatusCode::PAYLOAD_TOO_LARGE,
+            ApiError::UnsupportedEncoding(_) => StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            ApiError::Infected(_) | ApiError::IdempotencyKeyReused => {
+                StatusCode::UNPROCESSABLE_ENTITY
+            }
+            ApiError::ScanUnavailable | ApiError::StorageUnavailable => {
+                StatusCode::SERVICE_UNAVAILABLE
+            }
+            ApiError::RateLimited { .. } | ApiError::QuotaExceeded { .. } => {
+                StatusCode::TOO_MANY_REQUESTS
+            }
+            ApiError::StorageFailure
+            | ApiError::PublishFailure
+            | ApiError::PluginFailed
+            | ApiError::Internal => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let mut response = HttpResponse::build(self.status_code());
+        if let ApiError::RateLimited { retry_after_s, .. }
+        | ApiError::QuotaExceeded { retry_after_s } = self
+        {
+            response.insert_header(("Retry-After", retry_after_s.to_string()));
+        }
+        if let ApiError::StorageUnavailable = self {
+            response.insert_header(("Retry-After", STORAGE_RETRY_AFTER_S.to_string()));
+        }
+        response.negotiated_plain(&self.body(current_request_id()))
+    }
+}
+
+impl IntoResponses for ApiError {
+    /// Error responses shared by the routes in the OpenAPI specification, one per status code
+    fn responses() -> BTreeMap<String, RefOr<Response>> {
+        [
+            (
+                StatusCode::BAD_REQUEST,
+                "Malformed request or invalid payload - `fields` holds the messages per field",
+            ),
+            (StatusCode::UNAUTHORIZED, "Missing or invalid credentials"),
+            (
+                StatusCode::FORBIDDEN,
+                "The hospital may not submit this exam type",
+            ),
+            (StatusCode::NOT_FOUND, "Unknown resource"),
+            (
+                StatusCode::PAYLOAD_TOO_LARGE,
+                "Body over the limit of the hospital's size tier",
+            ),
+            (
+                StatusCode::UNSUPPORTED_MEDIA_TYPE,
+                "Content-Encoding other than gzip or zstd",
+            ),
+            (
+                StatusCode::UNPROCESSABLE_ENTITY,
+                "Malware found in the payload, or Idempotency-Key already used with another payload",
+            ),
+            (
+                StatusCode::TOO_MANY_REQUESTS,
+                "Gateway busy or monthly quota used up - retry after the delay",
+            ),
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "The exam could not be processed",
+            ),
+            (
+                StatusCode::SERVICE_UNAVAILABLE,
+                "Storage or malware scanner unavailable - retry after the delay",
+            ),
+        ]
+        .into_iter()
+        .map(|(status, description)| {
+            (
+                status.as_u16().to_string(),
+                documented_error(status, description),
+            )
+        })
+        .collect()
+    }
+}
+
+impl From<ValidationErrors> for ApiError {
+    /// Field-level messages - custom validators carry their message as the error code, or as
+    /// message when the code names the reason; the reason is that of the first field in order
+    fn from(errors: ValidationErrors) -> Self {
+        let field_errors = errors.field_errors();
+        let reason = field_errors
+            .iter()
+            .min_by(|(a, _), (b, _)| a.cmp(b))
+            .and_then(|(_, errors)| errors.first())
+            .map(|e| ReasonCode::of_validation(&e.code))
+            .unwrap_or(ReasonCode::FieldInvalid);
+        let fields = field_errors
+            .into_iter()
+            .map(|(field, errors)| {
+                let messages = errors
+                    .iter()
+                    .map(|e| {
+                        e.message
+                            .as_ref()
+                            .map(|m| m.to_string())
+                            .unwrap_or_else(|| e.code.to_string())
+                    })
+                    .collect();
+                (field.to_string(), messages)
+            })
+            .collect();
+        ApiError::Validation { reason, fields }
+    }
+}
+
+impl From<JsonPayloadError> for ApiError {
+    fn from(error: JsonPayloadError) -> Self {
+        match error {
+            JsonPayloadError::Overflow { .. }
+            | JsonPayloadError::OverflowKnownLength { .. }
+            | JsonPayloadError::Payload(PayloadError::Overflow) => ApiError::PayloadTooLarge,
+            JsonPayloadError::Payload(PayloadError::EncodingCorrupted | PayloadError::Io(_)) => {
+                ApiError::BadRequest("The compressed body could not be decoded".to_string())
+            }
+            JsonPayloadError::ContentType => {
+                ApiError::BadRequest("Content type must be application/json".to_string())
+            }
+            JsonPayloadError::Deserialize(e) => ApiError::BadRequest(e.to_string()),
+            _ => ApiError::BadRequest("Invalid Input".to_string()),
+        }
+    }
+}
+
+impl From<anyhow::Error> for ApiError {
+    /// Services attach an `ApiError` to the errors that must reach the caller; anything else is an
+    /// internal error
+    fn from(error: anyhow::Error) -> Self {
+        error
+            .downcast_ref::<ApiError>()
+            .cloned()
+            .unwrap_or(ApiError::Internal)
+    }
+}
+
+// SUPPORT FUNCTIONS *******************************************************************************
+/// Documented error response with the ErrorBody schema
+/// # Arguments
+/// * `status` - The status code, 429 and 503 carry a Retry-After header
+/// * `description` - When the status is returned
+fn documented_error(status: StatusCode, description: &str) -> RefOr<Response> {
+    let content = ContentBuilder::new()
+        .schema(Some(Ref::from_schema_name("ErrorBody")))
+        .build();
+    let mut response = ResponseBuilder::new()
+        .description(description)
+        .content("application/json", content);
+    if matches!(
+        status,
+        StatusCode::TOO_MANY_REQUESTS | StatusCode::SERVICE_UNAVAILABLE
+    ) {
+        let header = HeaderBuilder::new()
+            .schema(Object::with_type(Type::Integer))
+            .description(Some("Seconds to wait before retrying"))
+            .build();
+        response = response.header("Retry-After", header);
+    }
+    response.build().into()
+}
+
+/// Error handler of the JSON extractor: oversized and malformed bodies get the same error shape
+/// as the route errors
+pub fn json_error_handler(
+    error: JsonPayloadError,
+    _req: &actix_web::HttpRequest,
+) -> actix_web::Error {
+    ApiError::from(error).into()
+}
+
+// TESTS *******************************************************************************************
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Context;
+    use validator::Validate;
+
+    #[derive(validator::Validate)]
+    struct Sample {
+        #[validate(length(min = 1, message = "Patient_id must not be empty"))]
+        patient_id: String,
+        #[validate(range(min = 1))]
+        leads: u32,
+    }
+
+    // Happy path: validation errors keep one list of messages per field
+    #[test]
+    fn validation_fields() {
+        let errors = Sample {
+            patient_id: String::new(),
+            leads: 0,
+        }
+        .validate()
+        .unwrap_err();
+        let ApiError::Validation { reason, fields } = ApiError::from(errors) else {
+            panic!("expected a validation error");
+        };
+        assert_eq!(fields["patient_id"], vec!["Patient_id must not be empty"]);
+        assert_eq!(fields["leads"], vec!["range"]);
+        assert_eq!(reason, ReasonCode::FieldInvalid);
+    }
+
+    #[test]
+    fn error_body_shape() {
+        let mut fields = BTreeMap::new();
+        fields.insert("patient_id".to_string(), vec!["too long".to_string()]);
+        let body = ApiError::Validation {
+            reason: ReasonCode::LeadLength,
+            fields,
+        }
+        .body(Some("abc".to_string()));
+        assert_eq!(body["error"], "Invalid Input");
+        assert_eq!(body["code"], "validation_failed");
+        assert_eq!(body["reason"], "LEAD_LENGTH");
+        assert_eq!(body["request_id"], "abc");
+        assert_eq!(body["fields"]["patient_id"][0], "too long");
+
+        let body = ApiError::Internal.body(None);
+        assert_eq!(body["code"], "internal_error");
+        assert_eq!(body["reason"], "INTERNAL");
+        assert!(body.get("fields").is_none());
+    }
+
+    // Errors tagged by the services survive added context; anything else is internal
+    #[test]
+    fn from_anyhow() {
+        let tagged: anyhow::Result<()> =
+            Err(anyhow::Error::new(ApiError::StorageFailure)).context("dead-letter failed");
+        assert_eq!(
+            ApiError::from(tagged.unwrap_err()),
+            ApiError::StorageFailure
+        );
+        assert_eq!(ApiError::from(anyhow::anyhow!("boom")), ApiError::Internal);
+    }
+
+    #[test]
+    fn rate_limited_response() {
+        let error = ApiError::RateLimited {
+            retry_after_s: 5,
+            reason: ReasonCode::QueueFull,
+        };
+        assert_eq!(error.body(None)["reason"], "QUEUE_FULL");
+        let response = error.error_response();
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(response.headers().get("Retry-After").unwrap(), "5");
+    }
+
+    #[test]
+    fn payload_too_large() {
+        let error = ApiError::from(JsonPayloadError::Overflow { limit: 10 });
+        assert_eq!(error.status_code(), StatusCode::PAYLOAD_TOO_LARGE);
+        // Cut by the body limit of the hospital tier
+        let error = ApiError::from(JsonPayloadError::Payload(PayloadError::Overflow));
+        assert_eq!(error, ApiError::PayloadTooLarge);
+    }
+
+    #[test]
+    fn compressed_body_refused() {
+        let error = ApiError::UnsupportedEncoding("br".to_string());
+        assert_eq!(error.status_code(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+        assert_eq!(error.body(None)["reason"], "UNSUPPORTED_ENCODING");
+        // Corrupt gzip or zstd stream
+        let error = ApiError::from(JsonPayloadError::Payload(PayloadError::EncodingCorrupted));
+        assert_eq!(error.status_code(), StatusCode::BAD_REQUEST);
+    }
+
+    #[test]
+    fn storage_unavailable_neutral() {
+        let error = ApiError::StorageUnavailable;
+        let body = error.body(None);
+        assert_eq!(body["error"], "Service Unavailable");
+        assert_eq!(body["code"], "service_unavailable");
+        let response = error.error_response();
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(response.headers().get("Retry-After").unwrap(), "300");
+    }
+
+    #[test]
+    fn infected_payload() {
+        let error = ApiError::Infected("Eicar-Test".to_string());
+        assert_eq!(error.status_code(), StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(error.body(None)["code"], "payload_infected");
+        assert_eq!(
+            ApiError::ScanUnavailable.status_code(),
+            StatusCode::SERVICE_UNAVAILABLE
+        );
+    }
+
+    // Happy path: credential refusals keep apart missing and refused keys
+    #[test]
+    fn auth_reasons() {
+        let missing = ApiError::Unauthorized("Authentication failed: Missing valid headers".into());
+        assert_eq!(missing.reason(), ReasonCode::AuthMissing);
+        let refused = ApiError::Unauthorized("Authentication failed: Invalid credentials".into());
+        assert_eq!(refused.reason(), ReasonCode::AuthBadKey);
+        assert_eq!(
+            ApiError::InvalidToken(TokenRefusal::Expired).reason(),
+            ReasonCode::TokenExpired
+        );
+    }
+
+    // Happy path: the error body parsed by the hospitals keeps its fields, codes and reasons
+    #[test]
+    fn error_body_contract() {
+        let mut fields = BTreeMap::new();
+        fields.insert(
+            "lead_i".to_string(),
+            vec!["lead_i has 2 samples, expected 5000".to_string()],
+        );
+        let validation = ApiError::Validation {
+            reason: ReasonCode::LeadLength,
+            fields,
+        }
+        .body(Some("req-1".to_string()));
+        let busy = ApiError::RateLimited {
+            retry_after_s: 5,
+            reason: ReasonCode::QueueFull,
+        }
+        .body(None);
+        insta::with_settings!({ sort_maps => true }, {
+            insta::assert_json_snapshot!(validation, @r#"
+            {
+              "code": "validation_failed",
+              "error": "Invalid Input",
+              "fields": {
+                "lead_i": [
+                  "lead_i has 2 samples, expected 5000"
+                ]
+              },
+              "reason": "LEAD_LENGTH",
+              "request_id": "req-1"
+            }
+            "#);
+            insta::assert_json_snapshot!(busy, @r#"
+            {
+              "code": "rate_limited",
+              "error": "Service Busy",
+              "reason": "QUEUE_FULL",
+              "request_id": null
+            }
+            "#);
+        });
+    }
+}
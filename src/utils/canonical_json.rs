@@ -0,0 +1,233 @@
+// Imports *****************************************************************************************
+// External Crates
+use anyhow::Result;
+use serde::Serialize;
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::fmt::Write;
+
+// Internal Modules
+
+// Constants ***************************************************************************************
+/// Largest integer a double holds exactly (2^53) - larger ones are written as doubles, as in
+/// JavaScript
+const MAX_EXACT_INTEGER: u64 = 1 << 53;
+
+// MAIN FUNCTIONS **********************************************************************************
+/// Canonical JSON of a value (RFC 8785, JSON Canonicalization Scheme): object members sorted by
+/// the UTF-16 code units of their names, no whitespace, strings escaped minimally and numbers
+/// written as JavaScript does (shortest round-trip digits, exponent from 1e21 and below 1e-6) - so
+/// `jcs` (Python), `json-canonicalize` (JavaScript) or `java-json-canonicalization` produce the
+/// same bytes for the same document
+/// - Single-precision fields keep their shortest single-precision digits, e.g. `0.1` stays `0.1`
+/// # Arguments
+/// * `value` - The value to serialize
+/// # Errors
+/// * Returns an error if the value cannot be serialized to JSON (e.g. maps with non-string keys)
+pub fn to_canonical_string<T: Serialize + ?Sized>(value: &T) -> Result<String> {
+    // Through the text form: single-precision floats are written with their own shortest digits,
+    // which a double-precision `to_value` would widen (0.1f32 = 0.10000000149011612)
+    let value: Value = serde_json::from_str(&serde_json::to_string(value)?)?;
+    let mut out = String::new();
+    write_value(&mut out, &value);
+    Ok(out)
+}
+
+/// Lowercase hex SHA256 of the canonical JSON of a value
+/// # Arguments
+/// * `value` - The value to hash
+/// # Errors
+/// * Returns an error if the value cannot be serialized to JSON
+pub fn canonical_sha256<T: Serialize + ?Sized>(value: &T) -> Result<String> {
+    Ok(format!(
+        "{:x}",
+        Sha256::digest(to_canonical_string(value)?.as_bytes())
+    ))
+}
+
+// SUPPORT FUNCTIONS *******************************************************************************
+/// Append the canonical form of a JSON value
+fn write_value(out: &mut String, value: &Value) {
+    match value {
+        Value::Null => out.push_str("null"),
+        Value::Bool(value) => out.push_str(if *value { "true" } else { "false" }),
+        Value::Number(number) => {
+            match (number.as_u64(), number.as_i64()) {
+                (Some(n), _) if n <= MAX_EXACT_INTEGER => {
+                    let _ = write!(out, "{n}");
+                }
+                (_, Some(n)) if n.unsigned_abs() <= MAX_EXACT_INTEGER => {
+                    let _ = write!(out, "{n}");
+                }
+                // Finite by construction: JSON has no NaN nor infinity
+                _ => out.push_str(&format_number(number.as_f64().unwrap_or_default())),
+            }
+        }
+        Value::String(value) => write_string(out, value),
+        Value::Array(items) => {
+            out.push('[');
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                write_value(out, item);
+            }
+            out.push(']');
+        }
+        Value::Object(members) => {
+            let mut members: Vec<(&String, &Value)> = members.iter().collect();
+            members.sort_by(|(a, _), (b, _)| a.encode_utf16().cmp(b.encode_utf16()));
+            out.push('{');
+            for (i, (name, member)) in members.into_iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                write_string(out, name);
+                out.push(':');
+                write_value(out, member);
+            }
+            out.push('}');
+        }
+    }
+}
+
+/// Append a string literal: only the quote, the backslash and the control characters are escaped
+fn write_string(out: &mut String, value: &str) {
+    out.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\u{8}' => out.push_str("\\b"),
+            '\t' => out.push_str("\\t"),
+            '\n' => out.push_str("\\n"),
+            '\u{c}' => out.push_str("\\f"),
+            '\r' => out.push_str("\\r"),
+            c if c < '\u{20}' => {
+                let _ = write!(out, "\\u{:04x}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+/// A double as JavaScript's `Number.prototype.toString` writes it (ECMA-262, Number::toString)
+fn format_number(value: f64) -> String {
+    if value == 0.0 {
+        // Negative zero included
+        return "0".to_string();
+    }
+
+    // STEP 1: Shortest round-trip digits and the position of the decimal point
+    let scientific = format!("{:e}", value.abs());
+    let (mantissa, exponent) = scientific.split_once('e').unwrap_or((&scientific, "0"));
+    let digits = mantissa.replace('.', "");
+    let k = digits.len() as i32;
+    let n = exponent.parse::<i32>().unwrap_or_default() + 1;
+
+    // STEP 2: Fixed notation from 1e-6 to 1e21, exponent notation otherwise
+    let body = if k <= n && n <= 21 {
+        format!("{digits}{}", "0".repeat((n - k) as usize))
+    } else if 0 < n && n <= 21 {
+        format!("{}.{}", &digits[..n as usize], &digits[n as usize..])
+    } else if -6 < n && n <= 0 {
+        format!("0.{}{digits}", "0".repeat(-n as usize))
+    } else {
+        let sign = if n - 1 < 0 { '-' } else { '+' };
+        let mantissa = match k {
+            1 => digits.clone(),
+            _ => format!("{}.{}", &digits[..1], &digits[1..]),
+        };
+        format!("{mantissa}e{sign}{}", (n - 1).abs())
+    };
+    if value < 0.0 {
+        format!("-{body}")
+    } else {
+        body
+    }
+}
+
+// TESTS *******************************************************************************************
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    // Happy path: the example of RFC 8785 (section 3.2.2), parsed from its input
+    #[test]
+    fn rfc8785_example() {
+        let input: Value = serde_json::from_str(
+            r#"{
+                "numbers": [333333333.33333329, 1E30, 4.50, 2e-3, 0.000000000000000000000000001],
+                "string": "\u20ac$\u000F\u000aA'\u0042\u0022\u005c\\\"\/",
+                "literals": [null, true, false]
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(
+            to_canonical_string(&input).unwrap(),
+            "{\"literals\":[null,true,false],\"numbers\":[333333333.3333333,1e+30,4.5,0.002,1e-27],\
+             \"string\":\"€$\\u000f\\nA'B\\\"\\\\\\\\\\\"/\"}"
+        );
+    }
+
+    // Happy path: numbers are written as JavaScript does (RFC 8785 appendix B)
+    #[test]
+    fn numbers_as_javascript() {
+        for (value, expected) in [
+            (0.0, "0"),
+            (-0.0, "0"),
+            (5e-324, "5e-324"),
+            (1.7976931348623157e308, "1.7976931348623157e+308"),
+            (9007199254740992.0, "9007199254740992"),
+            (295147905179352830000.0, "295147905179352830000"),
+            (1e21, "1e+21"),
+            (0.000001, "0.000001"),
+            (1e-7, "1e-7"),
+            (-1.5, "-1.5"),
+            (123.456, "123.456"),
+        ] {
+            assert_eq!(format_number(value), expected, "{value:e}");
+        }
+        assert_eq!(
+            to_canonical_string(&json!([1, -1, 18446744073709551615_u64])).unwrap(),
+            "[1,-1,18446744073709552000]"
+        );
+    }
+
+    // Borderline: members sort by UTF-16 code units, not by code points
+    #[test]
+    fn members_sorted_by_utf16() {
+        let value = json!({"\u{fb33}": 1, "\u{1f600}": 2, "b": 3, "a": {"d": 4, "c": 5}});
+        assert_eq!(
+            to_canonical_string(&value).unwrap(),
+            "{\"a\":{\"c\":5,\"d\":4},\"b\":3,\"\u{1f600}\":2,\"\u{fb33}\":1}"
+        );
+    }
+
+    // Happy path: single-precision values keep their digits, so the hash of a typed payload is the
+    // one of the document the hospital sent
+    #[test]
+    fn single_precision_digits_kept() {
+        #[derive(Serialize)]
+        struct Leads {
+            lead_i: Vec<f32>,
+            patient_id: &'static str,
+        }
+        let typed = Leads {
+            lead_i: vec![0.1, -0.25, 1.0],
+            patient_id: "ab",
+        };
+        let sent: Value =
+            serde_json::from_str(r#"{"patient_id":"ab","lead_i":[0.1,-0.25,1]}"#).unwrap();
+        assert_eq!(
+            to_canonical_string(&typed).unwrap(),
+            "{\"lead_i\":[0.1,-0.25,1],\"patient_id\":\"ab\"}"
+        );
+        assert_eq!(
+            canonical_sha256(&typed).unwrap(),
+            canonical_sha256(&sent).unwrap()
+        );
+    }
+}
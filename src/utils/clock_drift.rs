@@ -0,0 +1,310 @@
+// Imports *****************************************************************************************
+// External Crates
+use anyhow::{anyhow, bail, Result};
+use chrono::{DateTime, Utc};
+use log::{error, info, warn};
+use serde::Serialize;
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::net::{lookup_host, UdpSocket};
+
+// Internal Modules
+use crate::telemetry::metrics::record_clock_drift_check;
+use crate::utils::external_call::{Dependency, ExternalCall};
+
+// Constants ***************************************************************************************
+/// Size of an SNTP packet without extension fields
+const NTP_PACKET_LEN: usize = 48;
+/// First byte of a request: no leap indicator, version 4, mode 3 (client)
+const NTP_CLIENT_REQUEST: u8 = 0x23;
+/// Mode of a server response
+const NTP_MODE_SERVER: u8 = 4;
+/// Seconds from the NTP epoch (1900) to the Unix epoch (1970)
+const NTP_UNIX_OFFSET_S: i64 = 2_208_988_800;
+
+// Structs *****************************************************************************************
+/// Clock drift check of the gateway host - exam timestamps come from its clock
+/// # Arguments
+/// * `server` - The SNTP server, `host:port` (CLOCK_DRIFT_SERVER)
+/// * `max_drift_ms` - Drift above which exam timestamps are no longer trusted (CLOCK_DRIFT_MAX_MS)
+/// * `interval` - Interval between checks (CLOCK_DRIFT_CHECK_INTERVAL_S)
+/// * `refuse_start` - Whether the gateway refuses to start with a drift above the threshold
+///   (CLOCK_DRIFT_REFUSE_START, default true)
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClockSettings {
+    pub server: String,
+    pub max_drift_ms: f64,
+    pub interval: Duration,
+    pub refuse_start: bool,
+}
+
+/// Last clock drift check, reported by the readiness diagnostics
+/// # Arguments
+/// * `server` - The SNTP server queried
+/// * `offset_ms` - Last measured offset of the host clock: positive when the host is behind
+/// * `max_drift_ms` - The threshold of the offset
+/// * `within_threshold` - Whether the last measured offset is within the threshold
+/// * `measured_at` - When the offset was last measured
+/// * `checked_at` - When the last check ran, measured or not
+/// * `error` - The error of the last check, if the server could not be queried
+#[derive(Debug, Clone, Serialize)]
+pub struct ClockCheck {
+    pub server: String,
+    pub offset_ms: Option<f64>,
+    pub max_drift_ms: f64,
+    pub within_threshold: bool,
+    pub measured_at: Option<DateTime<Utc>>,
+    pub checked_at: DateTime<Utc>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Offset and round-trip delay of an SNTP exchange, in milliseconds
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct NtpSample {
+    offset_ms: f64,
+    delay_ms: f64,
+}
+
+// Global variables ********************************************************************************
+/// Last check of the host clock - None until the first check ran
+static CLOCK_CHECK: Mutex<Option<ClockCheck>> = Mutex::new(None);
+
+// MAIN FUNCTIONS **********************************************************************************
+/// Check the host clock before serving traffic
+/// # Arguments
+/// * `settings` - The settings of the check
+/// # Errors
+/// * Returns an error if the drift exceeds the threshold and CLOCK_DRIFT_REFUSE_START is not
+///   `false` - an unreachable time server is only logged
+pub async fn check_clock_at_startup(settings: &ClockSettings) -> Result<()> {
+    let check = check_clock(settings).await;
+    match check.offset_ms {
+        Some(offset_ms) if !check.within_threshold && settings.refuse_start => bail!(
+            "Host clock drift of {offset_ms:.0}ms exceeds CLOCK_DRIFT_MAX_MS={} - fix the time \
+             synchronisation of the host or set CLOCK_DRIFT_REFUSE_START=false",
+            settings.max_drift_ms
+        ),
+        Some(offset_ms) => info!(
+            "Clock drift at startup: {offset_ms:.1}ms ({})",
+            settings.server
+        ),
+        None => warn!(
+            "Clock drift not checked at startup: {}",
+            check.error.as_deref().unwrap_or_default()
+        ),
+    }
+    Ok(())
+}
+
+/// Check the host clock every CLOCK_DRIFT_CHECK_INTERVAL_S - a drift above the threshold fails
+/// readiness until the clock is back within it
+/// # Arguments
+/// * `settings` - The settings of the check
+pub async fn run_clock_drift_checks(settings: ClockSettings) {
+    loop {
+        tokio::time::sleep(settings.interval).await;
+        check_clock(&settings).await;
+    }
+}
+
+/// Last check of the host clock, for the readiness diagnostics
+pub fn clock_check() -> Option<ClockCheck> {
+    CLOCK_CHECK.lock().ok().and_then(|check| check.clone())
+}
+
+/// Whether exam timestamps can be trusted: true until a measured drift exceeds the threshold
+pub fn clock_within_threshold() -> bool {
+    clock_check().is_none_or(|check| check.within_threshold)
+}
+
+// SUPPORTING FUNCTIONS ****************************************************************************
+/// Query the time server and keep the outcome - a failed query keeps the last measured offset
+/// # Arguments
+/// * `settings` - The settings of the check
+/// # Returns
+/// * The new state of the check
+async fn check_clock(settings: &ClockSettings) -> ClockCheck {
+    let measured = ExternalCall::new(Dependency::Ntp, "clock_drift")
+        .retries(1)
+        .run(|| query_server(&settings.server))
+        .await;
+    let now = Utc::now();
+    let previous = clock_check();
+    let check = match measured {
+        Ok(sample) => {
+            let within_threshold = sample.offset_ms.abs() <= settings.max_drift_ms;
+            if within_threshold {
+                record_clock_drift_check("ok");
+            } else {
+                record_clock_drift_check("exceeded");
+                error!(
+                    "Host clock drift of {:.0}ms exceeds CLOCK_DRIFT_MAX_MS={} - exam timestamps \
+                     are not trusted, readiness fails",
+                    sample.offset_ms, settings.max_drift_ms
+                );
+            }
+            info!(target: "external", "clock_drift offset_ms={:.1} delay_ms={:.1}",
+                sample.offset_ms, sample.delay_ms);
+            ClockCheck {
+                server: settings.server.clone(),
+                offset_ms: Some(sample.offset_ms),
+                max_drift_ms: settings.max_drift_ms,
+                within_threshold,
+                measured_at: Some(now),
+                checked_at: now,
+                error: None,
+            }
+        }
+        Err(e) => {
+            record_clock_drift_check("failed");
+            warn!(
+                "Could not check the host clock against {}: {e}",
+                settings.server
+            );
+            ClockCheck {
+                server: settings.server.clone(),
+                offset_ms: previous.as_ref().and_then(|p| p.offset_ms),
+                max_drift_ms: settings.max_drift_ms,
+                within_threshold: previous.as_ref().is_none_or(|p| p.within_threshold),
+                measured_at: previous.as_ref().and_then(|p| p.measured_at),
+                checked_at: now,
+                error: Some(e.to_string()),
+            }
+        }
+    };
+    if let Ok(mut last) = CLOCK_CHECK.lock() {
+        *last = Some(check.clone());
+    }
+    check
+}
+
+/// One SNTP exchange with the time server
+/// # Arguments
+/// * `server` - The server, `host:port`
+/// # Errors
+/// * Returns an error if the server cannot be resolved or reached, or its response is invalid
+async fn query_server(server: &str) -> Result<NtpSample> {
+    let address = lookup_host(server)
+        .await?
+        .next()
+        .ok_or_else(|| anyhow!("{server} did not resolve"))?;
+    let local = if address.is_ipv6() {
+        "[::]:0"
+    } else {
+        "0.0.0.0:0"
+    };
+    let socket = UdpSocket::bind(local).await?;
+    socket.connect(address).await?;
+
+    let mut request = [0u8; NTP_PACKET_LEN];
+    request[0] = NTP_CLIENT_REQUEST;
+    let sent_ms = unix_now_ms();
+    socket.send(&request).await?;
+    let mut response = [0u8; NTP_PACKET_LEN];
+    let len = socket.recv(&mut response).await?;
+    let received_ms = unix_now_ms();
+    parse_response(&response[..len], sent_ms, received_ms)
+}
+
+/// Offset and delay of an SNTP response (RFC 4330)
+/// # Arguments
+/// * `response` - The packet of the server
+/// * `sent_ms` / `received_ms` - When the request left and the response arrived, Unix ms
+/// # Errors
+/// * Returns an error if the packet is short, not a server response, or a kiss-of-death
+fn parse_response(response: &[u8], sent_ms: f64, received_ms: f64) -> Result<NtpSample> {
+    if response.len() < NTP_PACKET_LEN {
+        bail!("Short SNTP response: {} bytes", response.len());
+    }
+    if response[0] & 0x07 != NTP_MODE_SERVER {
+        bail!("Not an SNTP server response (mode {})", response[0] & 0x07);
+    }
+    // Stratum 0 is a kiss-of-death (rate limited, denied), above 15 unsynchronised
+    if !(1..=15).contains(&response[1]) {
+        bail!("Unsynchronised SNTP server (stratum {})", response[1]);
+    }
+    let server_received_ms = ntp_to_unix_ms(&response[32..40]);
+    let server_sent_ms = ntp_to_unix_ms(&response[40..48]);
+    Ok(NtpSample {
+        offset_ms: ((server_received_ms - sent_ms) + (server_sent_ms - received_ms)) / 2.0,
+        delay_ms: (received_ms - sent_ms) - (server_sent_ms - server_received_ms),
+    })
+}
+
+/// Unix milliseconds of an NTP timestamp (32-bit seconds since 1900, 32-bit fraction) - seconds
+/// below the Unix epoch belong to the era starting in 2036
+fn ntp_to_unix_ms(timestamp: &[u8]) -> f64 {
+    let seconds = u32::from_be_bytes([timestamp[0], timestamp[1], timestamp[2], timestamp[3]]);
+    let fraction = u32::from_be_bytes([timestamp[4], timestamp[5], timestamp[6], timestamp[7]]);
+    let mut unix_s = i64::from(seconds) - NTP_UNIX_OFFSET_S;
+    if unix_s < 0 {
+        unix_s += 1 << 32;
+    }
+    unix_s as f64 * 1000.0 + f64::from(fraction) * 1000.0 / 4_294_967_296.0
+}
+
+/// Current host time, Unix milliseconds
+fn unix_now_ms() -> f64 {
+    Utc::now().timestamp_micros() as f64 / 1000.0
+}
+
+// TESTS *******************************************************************************************
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ntp_timestamp(unix_ms: u64) -> [u8; 8] {
+        let seconds = (unix_ms / 1000 + NTP_UNIX_OFFSET_S as u64) as u32;
+        let fraction = ((unix_ms % 1000) as f64 / 1000.0 * 4_294_967_296.0) as u32;
+        let mut timestamp = [0u8; 8];
+        timestamp[..4].copy_from_slice(&seconds.to_be_bytes());
+        timestamp[4..].copy_from_slice(&fraction.to_be_bytes());
+        timestamp
+    }
+
+    fn response(server_received_ms: u64, server_sent_ms: u64) -> [u8; NTP_PACKET_LEN] {
+        let mut packet = [0u8; NTP_PACKET_LEN];
+        packet[0] = 0x24;
+        packet[1] = 1;
+        packet[32..40].copy_from_slice(&ntp_timestamp(server_received_ms));
+        packet[40..48].copy_from_slice(&ntp_timestamp(server_sent_ms));
+        packet
+    }
+
+    // Happy path: a host 2s behind the server, with a 40ms round trip
+    #[test]
+    fn offset_measured() {
+        let sample = parse_response(
+            &response(1_700_000_002_020, 1_700_000_002_030),
+            1_700_000_000_000.0,
+            1_700_000_000_050.0,
+        )
+        .unwrap();
+        assert!((sample.offset_ms - 2_000.0).abs() < 0.01);
+        assert!((sample.delay_ms - 40.0).abs() < 0.01);
+    }
+
+    // Error handling: short packets, client packets and kiss-of-death are refused
+    #[test]
+    fn invalid_responses_refused() {
+        let valid = response(1_700_000_000_000, 1_700_000_000_000);
+        assert!(parse_response(&valid[..40], 0.0, 0.0).is_err());
+        let mut client = valid;
+        client[0] = NTP_CLIENT_REQUEST;
+        assert!(parse_response(&client, 0.0, 0.0).is_err());
+        let mut kiss = valid;
+        kiss[1] = 0;
+        assert!(parse_response(&kiss, 0.0, 0.0).is_err());
+    }
+
+    // Borderline: timestamps after the 2036 NTP era rollover stay after the Unix epoch
+    #[test]
+    fn ntp_era_rollover() {
+        assert_eq!(
+            ntp_to_unix_ms(&[0, 0, 0, 0, 0, 0, 0, 0]),
+            2_085_978_496_000.0
+        );
+        assert_eq!(ntp_to_unix_ms(&ntp_timestamp(1_000)), 1_000.0);
+    }
+}
@@ -0,0 +1,233 @@
+// Imports *****************************************************************************************
+// External Crates
+use anyhow::Result;
+use log::{info, warn};
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+// Internal Modules
+use crate::config::settings::Secret;
+use crate::config::Settings;
+use crate::utils::hmac::{hex, hmac_sha256};
+
+// Constants ***************************************************************************************
+/// Environment variables that make up the effective configuration of the service, besides the
+/// typed settings
+const TRACKED_ENV_VARS: [&str; 1] = ["RUST_LOG"];
+
+/// Key fragments whose values are never logged nor persisted in clear text
+const SECRET_MARKERS: [&str; 5] = ["PASSWORD", "KEY", "SECRET", "CREDENTIALS", "SALT"];
+
+/// Snapshot value of the secrets when CONFIG_DRIFT_KEY is not set - their changes are not tracked
+const UNTRACKED_SECRET: &str = "<redacted>";
+
+// Global variables ********************************************************************************
+/// Number of configuration keys that changed since the last-seen snapshot (`config_drift` metric)
+pub static CONFIG_DRIFT: AtomicUsize = AtomicUsize::new(0);
+
+/// Names of the keys that changed since the last-seen snapshot
+pub static CONFIG_DRIFT_KEYS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+// MAIN FUNCTIONS **********************************************************************************
+/// Compare the effective configuration against the last-seen snapshot, log a redacted diff,
+/// update the `config_drift` metric and persist the new snapshot.
+/// Meant to be called at startup and whenever the configuration is reloaded.
+/// - Secrets are only reported changed or unchanged: the snapshot keeps an HMAC of their value
+///   under CONFIG_DRIFT_KEY, and without it their changes are not tracked
+/// # Arguments
+/// * `settings` - The typed settings of the service
+/// # Returns
+/// * A Result containing the number of keys that drifted
+/// # Errors
+/// * Returns an error if the new snapshot cannot be persisted
+pub fn check_config_drift(settings: &Settings) -> Result<usize> {
+    // STEP 1: Collect the effective configuration and the previous snapshot
+    let path = &settings.server.config_snapshot_path;
+    let current = effective_config(settings, settings.server.config_drift_key.as_ref());
+    if settings.server.config_drift_key.is_none() {
+        info!("CONFIG_DRIFT_KEY is not set - the changes of the secrets are not tracked");
+    }
+    let previous = match std::fs::read_to_string(path) {
+        Ok(content) => serde_json::from_str(&content).ok(),
+        Err(_) => None,
+    };
+
+    // STEP 2: Diff, log and update the metric
+    let drifted = match previous {
+        Some(previous) => {
+            let diff = diff_config(&previous, &current);
+            for line in &diff {
+                warn!("Configuration drift: {line}");
+            }
+            diff.iter()
+                .filter_map(|line| line.split_whitespace().nth(1))
+                .map(|key| key.trim_end_matches(':').to_string())
+                .collect()
+        }
+        None => {
+            info!("No previous configuration snapshot found at {path} - recording baseline");
+            Vec::new()
+        }
+    };
+    info!("config_drift={}", drifted.len());
+    CONFIG_DRIFT.store(drifted.len(), Ordering::Relaxed);
+    if let Ok(mut keys) = CONFIG_DRIFT_KEYS.lock() {
+        keys.clone_from(&drifted);
+    }
+
+    // STEP 3: Persist the new snapshot
+    std::fs::write(path, serde_json::to_string_pretty(&current)?)?;
+    Ok(drifted.len())
+}
+
+// SUPPORT FUNCTIONS *******************************************************************************
+/// Build the redacted effective configuration (typed settings + tracked environment variables)
+/// # Arguments
+/// * `settings` - The typed settings of the service
+/// * `drift_key` - The key of the fingerprints of the secrets (CONFIG_DRIFT_KEY)
+/// # Returns
+/// * A sorted map of configuration key to (possibly redacted) value
+fn effective_config(settings: &Settings, drift_key: Option<&Secret>) -> BTreeMap<String, String> {
+    let mut config = BTreeMap::new();
+    for (key, value) in settings.entries() {
+        config.insert(key.to_string(), redact(key, &value, drift_key));
+    }
+    for key in TRACKED_ENV_VARS {
+        if let Ok(value) = std::env::var(key) {
+            config.insert(key.to_string(), redact(key, &value, drift_key));
+        }
+    }
+    config
+}
+
+/// Whether the value of a key is a secret
+fn is_secret(key: &str) -> bool {
+    SECRET_MARKERS.iter().any(|marker| key.contains(marker))
+}
+
+/// Replace secret values by a keyed fingerprint so changes are detectable but never revealed -
+/// without the key of the deployment a fingerprint cannot be matched against guessed values
+/// # Arguments
+/// * `key` - The configuration key
+/// * `value` - The configuration value
+/// * `drift_key` - The key of the fingerprints (CONFIG_DRIFT_KEY), None to not track the secrets
+/// # Returns
+/// * The value itself, `hmac:<hex>` for secrets, or UNTRACKED_SECRET without a drift key
+fn redact(key: &str, value: &str, drift_key: Option<&Secret>) -> String {
+    if !is_secret(key) {
+        return value.to_string();
+    }
+    match drift_key {
+        Some(drift_key) => {
+            let message = format!("{key}={value}");
+            let mac = hmac_sha256(drift_key.expose().as_bytes(), message.as_bytes());
+            format!("hmac:{}", hex(&mac))
+        }
+        None => UNTRACKED_SECRET.to_string(),
+    }
+}
+
+/// Compute a line-based diff between two configuration snapshots - secrets are only named
+/// # Arguments
+/// * `previous` - The last-seen snapshot
+/// * `current` - The effective configuration
+/// # Returns
+/// * One line per drifted key, formatted as `<added|removed|changed> <KEY> ...`
+fn diff_config(
+    previous: &BTreeMap<String, String>,
+    current: &BTreeMap<String, String>,
+) -> Vec<String> {
+    let mut diff = Vec::new();
+    for (key, value) in current {
+        match previous.get(key) {
+            None if is_secret(key) => diff.push(format!("added {key} (secret)")),
+            None => diff.push(format!("added {key} = {value}")),
+            Some(old) if old != value && is_secret(key) => {
+                diff.push(format!("changed {key}: (secret)"))
+            }
+            Some(old) if old != value => diff.push(format!("changed {key}: {old} -> {value}")),
+            _ => {}
+        }
+    }
+    for key in previous.keys().filter(|k| !current.contains_key(*k)) {
+        diff.push(format!("removed {key}"));
+    }
+    diff
+}
+
+// TESTS *******************************************************************************************
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snapshot(entries: &[(&str, &str)]) -> BTreeMap<String, String> {
+        entries
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    // Happy path: identical snapshots produce no drift
+    #[test]
+    fn diff_no_drift() {
+        let a = snapshot(&[("PORT", "8080"), ("BUCKET_NAME", "b")]);
+        assert!(diff_config(&a, &a.clone()).is_empty());
+    }
+
+    // Drift: added, changed and removed keys are all reported
+    #[test]
+    fn diff_reports_all_changes() {
+        let previous = snapshot(&[("PORT", "8080"), ("DB_HOST", "old")]);
+        let current = snapshot(&[("PORT", "8080"), ("DB_HOST", "new"), ("BUCKET_NAME", "b")]);
+        let diff = diff_config(&previous, &current);
+        assert_eq!(diff.len(), 2);
+        assert!(diff.contains(&"changed DB_HOST: old -> new".to_string()));
+        assert!(diff.contains(&"added BUCKET_NAME = b".to_string()));
+
+        let diff = diff_config(&current, &previous);
+        assert!(diff.contains(&"removed BUCKET_NAME".to_string()));
+    }
+
+    // Happy path: the typed settings are part of the snapshot, their secrets fingerprinted
+    #[test]
+    fn settings_in_snapshot() {
+        let settings = crate::config::settings::test_settings();
+        let drift_key = Secret("drift-key".to_string());
+        let config = effective_config(&settings, Some(&drift_key));
+        assert_eq!(config["BUCKET_NAME"], "test-bucket_name");
+        assert_eq!(config["PORT"], "8080");
+        assert!(config["DB_PASSWORD"].starts_with("hmac:"));
+        let untracked = effective_config(&settings, None);
+        assert_eq!(untracked["DB_PASSWORD"], UNTRACKED_SECRET);
+    }
+
+    // Security: secrets are fingerprinted under the key of the deployment, never in clear nor as a
+    // plain hash of the value, plain values are kept
+    #[test]
+    fn redact_secrets_only() {
+        let drift_key = Secret("drift-key".to_string());
+        assert_eq!(
+            redact("DB_HOST", "localhost", Some(&drift_key)),
+            "localhost"
+        );
+        let redacted = redact("DB_PASSWORD", "hunter2", Some(&drift_key));
+        assert!(redacted.starts_with("hmac:"));
+        assert!(!redacted.contains("hunter2"));
+        assert_ne!(redacted, redact("DB_PASSWORD", "hunter3", Some(&drift_key)));
+        let other_key = Secret("other-key".to_string());
+        assert_ne!(redacted, redact("DB_PASSWORD", "hunter2", Some(&other_key)));
+        assert_eq!(redact("DB_PASSWORD", "hunter2", None), UNTRACKED_SECRET);
+    }
+
+    // Security: the diff names the changed secrets without their fingerprints
+    #[test]
+    fn diff_secrets_named_only() {
+        let previous = snapshot(&[("DB_PASSWORD", "hmac:aa")]);
+        let current = snapshot(&[("DB_PASSWORD", "hmac:bb"), ("ADMIN_API_KEY", "hmac:cc")]);
+        let diff = diff_config(&previous, &current);
+        assert!(diff.contains(&"changed DB_PASSWORD: (secret)".to_string()));
+        assert!(diff.contains(&"added ADMIN_API_KEY (secret)".to_string()));
+        assert!(diff.iter().all(|line| !line.contains("hmac:")));
+    }
+}
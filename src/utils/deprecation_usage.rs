@@ -0,0 +1,89 @@
+// Imports *****************************************************************************************
+// External Crates
+use chrono::{DateTime, Utc};
+use log::warn;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+
+// Internal Modules
+use crate::models::models_deprecations::DeprecatedField;
+
+// Structs *****************************************************************************************
+/// Use of one deprecated field by one hospital
+/// # Arguments
+/// * `count` - Exams received with the field
+/// * `last_seen` - When the field was last received
+#[derive(Debug, Clone, Serialize)]
+pub struct DeprecatedFieldUsage {
+    pub count: u64,
+    pub last_seen: DateTime<Utc>,
+}
+
+// Global variables ********************************************************************************
+/// Usage per `(hospital_id, "exam_type.field")`
+static DEPRECATED_USAGE: Mutex<BTreeMap<(String, String), DeprecatedFieldUsage>> =
+    Mutex::new(BTreeMap::new());
+
+// MAIN FUNCTIONS **********************************************************************************
+/// Record that a hospital sent deprecated fields
+/// # Arguments
+/// * `hospital_id` - The hospital that sent the exam
+/// * `used` - The deprecated fields in the payload
+pub fn record_deprecated_usage(hospital_id: &str, used: &[DeprecatedField]) {
+    if used.is_empty() {
+        return;
+    }
+    let now = Utc::now();
+    if let Ok(mut usage) = DEPRECATED_USAGE.lock() {
+        for field in used {
+            warn!(
+                "Hospital {hospital_id} sent deprecated field {}.{} (sunset {})",
+                field.exam_type, field.field, field.sunset
+            );
+            let key = (
+                hospital_id.to_string(),
+                format!("{}.{}", field.exam_type, field.field),
+            );
+            let entry = usage.entry(key).or_insert(DeprecatedFieldUsage {
+                count: 0,
+                last_seen: now,
+            });
+            entry.count += 1;
+            entry.last_seen = now;
+        }
+    }
+}
+
+/// Usage report: for every hospital, the deprecated fields it still sends
+/// # Returns
+/// * A map of hospital id to `exam_type.field` to usage
+pub fn deprecated_usage_report() -> BTreeMap<String, BTreeMap<String, DeprecatedFieldUsage>> {
+    let mut report: BTreeMap<String, BTreeMap<String, DeprecatedFieldUsage>> = BTreeMap::new();
+    if let Ok(usage) = DEPRECATED_USAGE.lock() {
+        for ((hospital_id, field), entry) in usage.iter() {
+            report
+                .entry(hospital_id.clone())
+                .or_default()
+                .insert(field.clone(), entry.clone());
+        }
+    }
+    report
+}
+
+// TESTS *******************************************************************************************
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::models_deprecations::DEPRECATED_FIELDS;
+
+    #[test]
+    fn usage_reported_per_hospital() {
+        record_deprecated_usage("usage-test-h1", &DEPRECATED_FIELDS[..1]);
+        record_deprecated_usage("usage-test-h1", &DEPRECATED_FIELDS[..1]);
+        record_deprecated_usage("usage-test-h2", &[]);
+        let report = deprecated_usage_report();
+        assert_eq!(report["usage-test-h1"]["ecg_exam.hospital_key"].count, 2);
+        assert!(!report.contains_key("usage-test-h2"));
+    }
+}
@@ -0,0 +1,97 @@
+// Imports *****************************************************************************************
+// External Crates
+use std::sync::atomic::{AtomicBool, Ordering};
+
+// Internal Modules
+
+// Structs *****************************************************************************************
+/// Reasons for which the instance asks the load balancer to stop routing traffic to it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DrainReason {
+    /// Operator-requested maintenance (MAINTENANCE_MODE or the internal endpoint)
+    Maintenance,
+    /// SIGTERM/SIGINT received, the server stops after the drain grace period
+    ShutdownInitiated,
+}
+
+impl DrainReason {
+    /// Stable lowercase name, used in health check bodies and logs
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DrainReason::Maintenance => "maintenance",
+            DrainReason::ShutdownInitiated => "shutdown_initiated",
+        }
+    }
+}
+
+/// Draining flags of the instance
+#[derive(Debug, Default)]
+pub struct DrainState {
+    maintenance: AtomicBool,
+    shutdown_initiated: AtomicBool,
+}
+
+impl DrainState {
+    /// Create a state with no draining reason set
+    pub const fn new() -> Self {
+        Self {
+            maintenance: AtomicBool::new(false),
+            shutdown_initiated: AtomicBool::new(false),
+        }
+    }
+
+    /// Set or clear a draining reason
+    pub fn set(&self, reason: DrainReason, active: bool) {
+        self.flag(reason).store(active, Ordering::SeqCst);
+    }
+
+    /// Whether a draining reason is set
+    pub fn is_set(&self, reason: DrainReason) -> bool {
+        self.flag(reason).load(Ordering::SeqCst)
+    }
+
+    /// All draining reasons currently set - empty when the instance can take traffic
+    pub fn reasons(&self) -> Vec<DrainReason> {
+        [DrainReason::Maintenance, DrainReason::ShutdownInitiated]
+            .into_iter()
+            .filter(|reason| self.flag(*reason).load(Ordering::SeqCst))
+            .collect()
+    }
+
+    fn flag(&self, reason: DrainReason) -> &AtomicBool {
+        match reason {
+            DrainReason::Maintenance => &self.maintenance,
+            DrainReason::ShutdownInitiated => &self.shutdown_initiated,
+        }
+    }
+}
+
+// Global variables ********************************************************************************
+/// Draining state of this instance, read by the health check
+pub static DRAIN_STATE: DrainState = DrainState::new();
+
+// TESTS *******************************************************************************************
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn drain_state_starts_serving() {
+        assert!(DrainState::new().reasons().is_empty());
+    }
+
+    #[test]
+    fn drain_state_set_and_clear() {
+        let state = DrainState::new();
+        state.set(DrainReason::Maintenance, true);
+        state.set(DrainReason::ShutdownInitiated, true);
+        assert_eq!(
+            state.reasons(),
+            vec![DrainReason::Maintenance, DrainReason::ShutdownInitiated]
+        );
+        state.set(DrainReason::Maintenance, false);
+        assert_eq!(state.reasons(), vec![DrainReason::ShutdownInitiated]);
+        assert!(!state.is_set(DrainReason::Maintenance));
+        assert!(state.is_set(DrainReason::ShutdownInitiated));
+    }
+}
@@ -0,0 +1,609 @@
+// Imports *****************************************************************************************
+// External Crates
+use anyhow::Result;
+use log::{info, warn};
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt::Display;
+use std::future::Future;
+use std::sync::{LazyLock, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+// Internal Modules
+use crate::config::settings::{
+    DEFAULT_RETRY_BUDGET_MIN_RETRIES, DEFAULT_RETRY_BUDGET_RATIO, DEFAULT_RETRY_BUDGET_WINDOW_S,
+};
+use crate::telemetry::trace_context::start_span;
+use crate::utils::api_error::ApiError;
+use crate::utils::storage_diagnostics::{record_storage_failure, record_storage_success};
+
+// Constants ***************************************************************************************
+/// Retries of exam uploads and publishes before the exam is dead-lettered (about 1.5s of backoff)
+pub const INGEST_RETRIES: u32 = 4;
+
+// Types *******************************************************************************************
+/// External systems the service talks to - new dependencies get a variant here
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Dependency {
+    Gcs,
+    S3,
+    #[cfg_attr(not(feature = "grpc"), allow(dead_code))]
+    PubSub,
+    Postgres,
+    Iam,
+    Jwks,
+    /// Only called by the clamd and ICAP scanners (`scanner` feature)
+    #[cfg_attr(not(feature = "scanner"), allow(dead_code))]
+    Scanner,
+    Webhook,
+    Ntp,
+    /// Only called by the BigQuery event sink (`bigquery` feature)
+    #[cfg_attr(not(feature = "bigquery"), allow(dead_code))]
+    BigQuery,
+    /// Only called by the shared rate limit windows (`redis` feature)
+    #[cfg_attr(not(feature = "redis"), allow(dead_code))]
+    Redis,
+}
+
+impl Dependency {
+    /// Stable lowercase name, used in logs and metric labels
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Dependency::Gcs => "gcs",
+            Dependency::S3 => "s3",
+            Dependency::PubSub => "pubsub",
+            Dependency::Postgres => "postgres",
+            Dependency::Iam => "iam",
+            Dependency::Jwks => "jwks",
+            Dependency::Scanner => "scanner",
+            Dependency::Webhook => "webhook",
+            Dependency::Ntp => "ntp",
+            Dependency::BigQuery => "bigquery",
+            Dependency::Redis => "redis",
+        }
+    }
+
+    /// Default timeout per attempt for this dependency
+    fn default_timeout(&self) -> Duration {
+        match self {
+            Dependency::Gcs => Duration::from_secs(30),
+            Dependency::S3 => Duration::from_secs(30),
+            Dependency::PubSub => Duration::from_secs(10),
+            Dependency::Postgres => Duration::from_secs(5),
+            Dependency::Iam => Duration::from_secs(10),
+            Dependency::Jwks => Duration::from_secs(5),
+            Dependency::Scanner => Duration::from_secs(30),
+            Dependency::Webhook => Duration::from_secs(10),
+            Dependency::Ntp => Duration::from_secs(3),
+            Dependency::BigQuery => Duration::from_secs(10),
+            Dependency::Redis => Duration::from_millis(500),
+        }
+    }
+}
+
+/// Classification of a failed external call
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorClass {
+    /// The attempt did not complete within its timeout
+    Timeout,
+    /// Likely to succeed when retried (unavailable, throttled, connection reset)
+    Transient,
+    /// Retrying will not help (permission denied, not found, invalid request)
+    Permanent,
+}
+
+/// Failure of an external call after all its attempts - services find it back in the context
+/// chain of their errors to tell which dependency failed and how
+/// # Arguments
+/// * `dependency` - The system called
+/// * `operation` - The operation of the call
+/// * `class` - How the last attempt failed
+/// * `message` - The error of the last attempt
+#[derive(Debug, Clone)]
+pub struct CallFailure {
+    pub dependency: Dependency,
+    pub operation: &'static str,
+    pub class: ErrorClass,
+    pub message: String,
+}
+
+impl Display for CallFailure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}.{} failed ({:?}): {}",
+            self.dependency.as_str(),
+            self.operation,
+            self.class,
+            self.message
+        )
+    }
+}
+
+impl std::error::Error for CallFailure {}
+
+/// Aggregated metrics of the calls made to one dependency operation
+#[derive(Debug, Clone, Default, serde::Serialize)]
+pub struct CallStats {
+    pub calls: u64,
+    pub attempts: u64,
+    pub failures: u64,
+    pub timeouts: u64,
+    pub total_latency_ms: u64,
+}
+
+/// Settings of the retry budgets, shared by every dependency
+/// # Arguments
+/// * `ratio` - Retries allowed per call in the window (RETRY_BUDGET_RATIO, 0.2 = 20% extra)
+/// * `window_s` - Length of the sliding window in seconds (RETRY_BUDGET_WINDOW_S)
+/// * `min_retries` - Retries allowed per window whatever the calls (RETRY_BUDGET_MIN_RETRIES)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BudgetSettings {
+    pub ratio: f64,
+    pub window_s: u64,
+    pub min_retries: u64,
+}
+
+/// Calls and retries of a dependency in its sliding window, per second
+/// # Arguments
+/// * `seconds` - `(second, calls, retries)`, oldest first, within the window
+/// * `retried` - Retries allowed since the start
+/// * `exhausted` - Retries refused since the start, for an empty budget
+#[derive(Debug, Clone, Default)]
+struct RetryBudget {
+    seconds: VecDeque<(u64, u64, u64)>,
+    retried: u64,
+    exhausted: u64,
+}
+
+impl RetryBudget {
+    /// Count a call - every call earns `ratio` retries for the window
+    fn deposit(&mut self, now_s: u64, settings: &BudgetSettings) {
+        self.current(now_s, settings).1 += 1;
+    }
+
+    /// Take a retry from the budget
+    /// # Returns
+    /// * Whether the retry is allowed: the retries of the window stay within `ratio` of its calls,
+    ///   or `min_retries`
+    fn withdraw(&mut self, now_s: u64, settings: &BudgetSettings) -> bool {
+        self.current(now_s, settings);
+        let allowed = self.allowed(settings);
+        let retries: u64 = self.seconds.iter().map(|(_, _, retries)| retries).sum();
+        if retries >= allowed {
+            self.exhausted += 1;
+            return false;
+        }
+        self.retried += 1;
+        self.current(now_s, settings).2 += 1;
+        true
+    }
+
+    /// Retries left in the window
+    fn remaining(&mut self, now_s: u64, settings: &BudgetSettings) -> u64 {
+        self.current(now_s, settings);
+        let retries: u64 = self.seconds.iter().map(|(_, _, retries)| retries).sum();
+        self.allowed(settings).saturating_sub(retries)
+    }
+
+    /// Retries allowed in the window
+    fn allowed(&self, settings: &BudgetSettings) -> u64 {
+        let calls: u64 = self.seconds.iter().map(|(_, calls, _)| calls).sum();
+        ((calls as f64 * settings.ratio) as u64).max(settings.min_retries)
+    }
+
+    /// Counters of the current second, once the seconds out of the window are dropped
+    fn current(&mut self, now_s: u64, settings: &BudgetSettings) -> &mut (u64, u64, u64) {
+        while self
+            .seconds
+            .front()
+            .is_some_and(|(second, _, _)| second + settings.window_s <= now_s)
+        {
+            self.seconds.pop_front();
+        }
+        if self
+            .seconds
+            .back()
+            .is_none_or(|(second, _, _)| *second != now_s)
+        {
+            self.seconds.push_back((now_s, 0, 0));
+        }
+        let last = self.seconds.len() - 1;
+        &mut self.seconds[last]
+    }
+}
+
+/// Retry budget of a dependency, for the metrics
+/// # Arguments
+/// * `retried` - Retries allowed since the start
+/// * `exhausted` - Retries refused since the start, for an empty budget
+/// * `remaining` - Retries left in the current window
+#[derive(Debug, Clone, Default, serde::Serialize)]
+pub struct BudgetStats {
+    pub retried: u64,
+    pub exhausted: u64,
+    pub remaining: u64,
+}
+
+// Global variables ********************************************************************************
+/// Metrics per `(dependency, operation)`
+static CALL_STATS: Mutex<BTreeMap<(&'static str, &'static str), CallStats>> =
+    Mutex::new(BTreeMap::new());
+/// Retry budget per dependency - every operation of a dependency shares it, since an outage
+/// affects them all
+static RETRY_BUDGETS: Mutex<BTreeMap<&'static str, RetryBudget>> = Mutex::new(BTreeMap::new());
+/// Start of the process, origin of the seconds of the retry budgets
+static BUDGET_EPOCH: LazyLock<Instant> = LazyLock::new(Instant::now);
+/// Settings of the retry budgets, set once at startup
+static BUDGET_SETTINGS: OnceLock<BudgetSettings> = OnceLock::new();
+
+// MAIN STRUCT *************************************************************************************
+/// Uniform wrapper for every call leaving the process (GCS, Pub/Sub, Postgres, Redis, webhooks)
+/// providing timeouts, retries with backoff, metrics, log spans and error classification
+/// - Retries draw from the retry budget of the dependency (RETRY_BUDGET_RATIO of its calls in the
+///   last RETRY_BUDGET_WINDOW_S): during an outage the calls fail after one attempt instead of
+///   multiplying the load on the failing dependency
+#[derive(Debug, Clone)]
+pub struct ExternalCall {
+    dependency: Dependency,
+    operation: &'static str,
+    timeout: Duration,
+    max_attempts: u32,
+    base_backoff: Duration,
+    bucket: Option<String>,
+}
+
+impl ExternalCall {
+    /// Create a call with the defaults of the dependency (single attempt)
+    /// # Arguments
+    /// * `dependency` - The external system being called
+    /// * `operation` - A short name of the operation, e.g. `upload_object`
+    pub fn new(dependency: Dependency, operation: &'static str) -> Self {
+        Self {
+            dependency,
+            operation,
+            timeout: dependency.default_timeout(),
+            max_attempts: 1,
+            base_backoff: Duration::from_millis(100),
+            bucket: None,
+        }
+    }
+
+    /// Override the timeout of each attempt
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Name the bucket of a storage call - its deployment faults (permissions, missing bucket,
+    /// quota) are then alerted, diagnosed for the operators and surfaced as a neutral 503
+    pub fn bucket(mut self, bucket: &str) -> Self {
+        self.bucket = Some(bucket.to_string());
+        self
+    }
+
+    /// Allow up to `retries` additional attempts on timeouts and transient errors, as long as the
+    /// retry budget of the dependency is not used up
+    pub fn retries(mut self, retries: u32) -> Self {
+        self.max_attempts = retries + 1;
+        self
+    }
+
+    /// Run the call, retrying retryable failures with exponential backoff and jitter
+    /// # Arguments
+    /// * `call` - A closure producing a fresh future for every attempt
+    /// # Returns
+    /// * The value of the first successful attempt
+    /// # Errors
+    /// * Returns the last error, prefixed with the dependency, operation and error class
+    pub async fn run<T, E, F, Fut>(&self, mut call: F) -> Result<T>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = std::result::Result<T, E>>,
+        E: Display,
+    {
+        let started = Instant::now();
+        let _span = start_span(&format!("{}.{}", self.dependency.as_str(), self.operation));
+        deposit_call(self.dependency);
+        let mut attempt = 0;
+        loop {
+            attempt += 1;
+            let outcome = tokio::time::timeout(self.timeout, call()).await;
+            let (class, message) = match outcome {
+                Ok(Ok(value)) => {
+                    self.record(attempt, started, None);
+                    if let Some(bucket) = &self.bucket {
+                        record_storage_success(bucket);
+                    }
+                    info!(target: "external", "{}.{} ok attempts={attempt} latency_ms={}",
+                        self.dependency.as_str(), self.operation, started.elapsed().as_millis());
+                    return Ok(value);
+                }
+                Ok(Err(e)) => {
+                    let message = e.to_string();
+                    (classify_error(&message), message)
+                }
+                Err(_) => (
+                    ErrorClass::Timeout,
+                    format!("timed out after {:?}", self.timeout),
+                ),
+            };
+
+            let retryable = class != ErrorClass::Permanent && attempt < self.max_attempts;
+            // The budget is only drawn from when the call would retry
+            let budget_exhausted = retryable && !withdraw_retry(self.dependency);
+            if !retryable || budget_exhausted {
+                self.record(attempt, started, Some(class));
+                warn!(target: "external", "{}.{} failed attempts={attempt} class={class:?} budget_exhausted={budget_exhausted} error={message}",
+                    self.dependency.as_str(), self.operation);
+                let fault = self
+                    .bucket
+                    .as_ref()
+                    .and_then(|bucket| record_storage_failure(bucket, self.operation, &message));
+                let error = CallFailure {
+                    dependency: self.dependency,
+                    operation: self.operation,
+                    class,
+                    message,
+                };
+                return Err(match fault {
+                    Some(_) => {
+                        anyhow::Error::new(ApiError::StorageUnavailable).context(error.to_string())
+                    }
+                    None => anyhow::Error::new(error),
+                });
+            }
+            let backoff = backoff_delay(self.base_backoff, attempt);
+            warn!(target: "external", "{}.{} attempt {attempt} failed ({class:?}), retrying in {backoff:?}: {message}",
+                self.dependency.as_str(), self.operation);
+            tokio::time::sleep(backoff).await;
+        }
+    }
+
+    /// Record the outcome of a call in the global metrics
+    fn record(&self, attempts: u32, started: Instant, failure: Option<ErrorClass>) {
+        if let Ok(mut stats) = CALL_STATS.lock() {
+            let entry = stats
+                .entry((self.dependency.as_str(), self.operation))
+                .or_default();
+            entry.calls += 1;
+            entry.attempts += u64::from(attempts);
+            entry.total_latency_ms += started.elapsed().as_millis() as u64;
+            match failure {
+                Some(ErrorClass::Timeout) => {
+                    entry.failures += 1;
+                    entry.timeouts += 1;
+                }
+                Some(_) => entry.failures += 1,
+                None => {}
+            }
+        }
+    }
+}
+
+// SUPPORT FUNCTIONS *******************************************************************************
+/// Set the retry budgets of the dependencies - called once at startup, the defaults apply until
+/// then
+/// # Arguments
+/// * `settings` - The retry budget settings (RETRY_BUDGET_*)
+pub fn init_retry_budget(settings: BudgetSettings) {
+    if BUDGET_SETTINGS.set(settings).is_err() {
+        warn!("Retry budget settings already initialized");
+    }
+}
+
+/// Snapshot of the metrics of all external calls, keyed by `dependency.operation`
+pub fn call_stats() -> BTreeMap<String, CallStats> {
+    CALL_STATS
+        .lock()
+        .map(|stats| {
+            stats
+                .iter()
+                .map(|((dep, op), s)| (format!("{dep}.{op}"), s.clone()))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Snapshot of the retry budgets, keyed by dependency
+pub fn retry_budget_stats() -> BTreeMap<&'static str, BudgetStats> {
+    let settings = budget_settings();
+    let now_s = BUDGET_EPOCH.elapsed().as_secs();
+    RETRY_BUDGETS
+        .lock()
+        .map(|mut budgets| {
+            budgets
+                .iter_mut()
+                .map(|(dependency, budget)| {
+                    let stats = BudgetStats {
+                        retried: budget.retried,
+                        exhausted: budget.exhausted,
+                        remaining: budget.remaining(now_s, &settings),
+                    };
+                    (*dependency, stats)
+                })
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Settings of the retry budgets - the defaults until `init_retry_budget` is called
+fn budget_settings() -> BudgetSettings {
+    BUDGET_SETTINGS.get().copied().unwrap_or(BudgetSettings {
+        ratio: DEFAULT_RETRY_BUDGET_RATIO,
+        window_s: DEFAULT_RETRY_BUDGET_WINDOW_S,
+        min_retries: DEFAULT_RETRY_BUDGET_MIN_RETRIES,
+    })
+}
+
+/// Count a call in the retry budget of its dependency
+fn deposit_call(dependency: Dependency) {
+    let now_s = BUDGET_EPOCH.elapsed().as_secs();
+    if let Ok(mut budgets) = RETRY_BUDGETS.lock() {
+        budgets
+            .entry(dependency.as_str())
+            .or_default()
+            .deposit(now_s, &budget_settings());
+    }
+}
+
+/// Take a retry from the budget of a dependency
+/// # Returns
+/// * Whether the call may retry - a poisoned budget never blocks retries
+fn withdraw_retry(dependency: Dependency) -> bool {
+    let now_s = BUDGET_EPOCH.elapsed().as_secs();
+    RETRY_BUDGETS
+        .lock()
+        .map(|mut budgets| {
+            budgets
+                .entry(dependency.as_str())
+                .or_default()
+                .withdraw(now_s, &budget_settings())
+        })
+        .unwrap_or(true)
+}
+
+/// Classify an error message from any of the client libraries
+/// # Arguments
+/// * `message` - The error rendered as a string
+/// # Returns
+/// * Permanent for authorization/not-found/invalid requests, Transient otherwise
+pub fn classify_error(message: &str) -> ErrorClass {
+    let lower = message.to_lowercase();
+    const PERMANENT: [&str; 10] = [
+        "401",
+        "403",
+        "404",
+        "412",
+        "precondition",
+        "permission",
+        "not found",
+        "unauthenticated",
+        "invalid",
+        "password authentication failed",
+    ];
+    if PERMANENT.iter().any(|marker| lower.contains(marker)) {
+        ErrorClass::Permanent
+    } else {
+        ErrorClass::Transient
+    }
+}
+
+/// Exponential backoff with up to 50% jitter, capped at 10 seconds
+/// # Arguments
+/// * `base` - The delay before the first retry
+/// * `attempt` - The number of the attempt that just failed (starting at 1)
+fn backoff_delay(base: Duration, attempt: u32) -> Duration {
+    let exponential = base.saturating_mul(2u32.saturating_pow(attempt.saturating_sub(1)));
+    let capped = exponential.min(Duration::from_secs(10));
+    let nanos = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.subsec_nanos())
+        .unwrap_or(0);
+    let jitter = capped.mul_f64(f64::from(nanos % 500) / 1000.0);
+    capped + jitter
+}
+
+// TESTS *******************************************************************************************
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    // Happy path: transient failures are retried until success
+    #[tokio::test]
+    async fn retries_transient_errors() {
+        let attempts = AtomicU32::new(0);
+        let result = ExternalCall::new(Dependency::PubSub, "test_retry")
+            .retries(2)
+            .run(|| async {
+                if attempts.fetch_add(1, Ordering::SeqCst) < 2 {
+                    Err("503 service unavailable")
+                } else {
+                    Ok(7)
+                }
+            })
+            .await;
+        assert_eq!(result.unwrap(), 7);
+        assert_eq!(attempts.load(Ordering::SeqCst), 3);
+        assert_eq!(call_stats()["pubsub.test_retry"].attempts, 3);
+    }
+
+    // Error handling: permanent errors are not retried
+    #[tokio::test]
+    async fn permanent_errors_fail_fast() {
+        let attempts = AtomicU32::new(0);
+        let result: Result<()> = ExternalCall::new(Dependency::Gcs, "test_permanent")
+            .retries(3)
+            .run(|| async {
+                attempts.fetch_add(1, Ordering::SeqCst);
+                Err("403 permission denied")
+            })
+            .await;
+        assert!(result.unwrap_err().to_string().contains("Permanent"));
+        assert_eq!(attempts.load(Ordering::SeqCst), 1);
+    }
+
+    // Error handling: slow calls are cut by the timeout
+    #[tokio::test]
+    async fn slow_calls_time_out() {
+        let result: Result<()> = ExternalCall::new(Dependency::Postgres, "test_timeout")
+            .timeout(Duration::from_millis(10))
+            .run(|| async {
+                tokio::time::sleep(Duration::from_millis(200)).await;
+                Ok::<(), String>(())
+            })
+            .await;
+        assert!(result.unwrap_err().to_string().contains("Timeout"));
+        assert_eq!(call_stats()["postgres.test_timeout"].timeouts, 1);
+    }
+
+    // Error handling: a storage fault of the deployment reaches the hospital as a neutral 503
+    #[tokio::test]
+    async fn storage_faults_surface_as_unavailable() {
+        let result: Result<()> = ExternalCall::new(Dependency::Gcs, "test_storage_fault")
+            .bucket("external-call-test-bucket")
+            .run(|| async { Err("403 sa does not have storage.objects.create access") })
+            .await;
+        let error = result.unwrap_err();
+        assert!(error.to_string().contains("storage.objects.create"));
+        assert_eq!(ApiError::from(error), ApiError::StorageUnavailable);
+        let result: Result<()> = ExternalCall::new(Dependency::Gcs, "test_storage_fault")
+            .bucket("external-call-test-bucket")
+            .run(|| async { Err("503 backend error") })
+            .await;
+        assert_eq!(ApiError::from(result.unwrap_err()), ApiError::Internal);
+    }
+
+    // Borderline: retries stay within the share of the calls of the window, then recover
+    #[test]
+    fn retry_budget_bounds_retries() {
+        let settings = BudgetSettings {
+            ratio: 0.2,
+            window_s: 10,
+            min_retries: 2,
+        };
+        let mut budget = RetryBudget::default();
+        for _ in 0..20 {
+            budget.deposit(0, &settings);
+        }
+        // 20 calls: 4 retries, then exhausted
+        assert!((0..4).all(|_| budget.withdraw(5, &settings)));
+        assert!(!budget.withdraw(5, &settings));
+        assert_eq!((budget.retried, budget.exhausted), (4, 1));
+        // Once the calls left the window, only the minimum is allowed
+        assert_eq!(budget.remaining(14, &settings), 0);
+        assert_eq!(budget.remaining(15, &settings), 2);
+        assert!(budget.withdraw(15, &settings));
+        assert!(budget.withdraw(15, &settings));
+        assert!(!budget.withdraw(16, &settings));
+    }
+
+    // Borderline: backoff grows exponentially and stays capped
+    #[test]
+    fn backoff_is_bounded() {
+        let base = Duration::from_millis(100);
+        assert!(backoff_delay(base, 1) >= base);
+        assert!(backoff_delay(base, 3) >= Duration::from_millis(400));
+        assert!(backoff_delay(base, 30) <= Duration::from_secs(15));
+    }
+}
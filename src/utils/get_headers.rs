@@ -6,6 +6,10 @@ use anyhow::{anyhow, Result};
 
 use actix_web::HttpRequest;
 
+// Constants ***************************************************************************************
+/// Longest 'Idempotency-Key' accepted, as stored in the idempotency_keys table
+const IDEMPOTENCY_KEY_MAX_LEN: usize = 255;
+
 // MAIN FUNCTION ***********************************************************************************
 /// Extracts 'hospital_id' and 'hospital_key' from HTTP request headers.
 /// # Arguments
@@ -28,6 +32,88 @@ pub fn get_headers(req: HttpRequest) -> Result<(String, String)> {
     Ok((hospital_id.to_string(), hospital_key.to_string()))
 }
 
+/// Bearer token of the 'Authorization' header
+/// # Arguments
+/// * `req` - An HttpRequest object containing the headers
+/// # Returns
+/// * The token if the header is `Bearer <token>` (scheme case-insensitive), None otherwise
+pub fn bearer_token(req: &HttpRequest) -> Option<String> {
+    req.headers()
+        .get("authorization")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.split_once(' '))
+        .filter(|(scheme, _)| scheme.eq_ignore_ascii_case("bearer"))
+        .map(|(_, token)| token.trim())
+        .filter(|token| !token.is_empty())
+        .map(str::to_string)
+}
+
+/// Whether the request is flagged as urgent through the 'exam_priority' header
+/// # Arguments
+/// * `req` - An HttpRequest object containing the headers
+/// # Returns
+/// * true if 'exam_priority' is 'urgent' (case-insensitive), false otherwise
+pub fn is_urgent(req: &HttpRequest) -> bool {
+    req.headers()
+        .get("exam_priority")
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.eq_ignore_ascii_case("urgent"))
+}
+
+/// Whether the hospital asks for a provisional accept, through the 'ingest_mode' header
+/// # Arguments
+/// * `req` - An HttpRequest object containing the headers
+/// # Returns
+/// * true if 'ingest_mode' is 'two_phase' (case-insensitive), false otherwise
+pub fn is_two_phase(req: &HttpRequest) -> bool {
+    req.headers()
+        .get("ingest_mode")
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.trim().eq_ignore_ascii_case("two_phase"))
+}
+
+/// URL notified once the exam is confirmed, through the 'confirmation_webhook' header
+/// # Arguments
+/// * `req` - An HttpRequest object containing the headers
+/// # Returns
+/// * The URL if the header is set and not empty, None otherwise - it is checked by the caller
+pub fn confirmation_webhook(req: &HttpRequest) -> Option<String> {
+    req.headers()
+        .get("confirmation_webhook")
+        .and_then(|v| v.to_str().ok())
+        .map(str::trim)
+        .filter(|v| !v.is_empty())
+        .map(str::to_string)
+}
+
+/// Clinic a hospital group submits for, through the 'on_behalf_of' header
+/// # Arguments
+/// * `req` - An HttpRequest object containing the headers
+/// # Returns
+/// * The clinic id if the header is set and not empty, None otherwise
+pub fn on_behalf_of(req: &HttpRequest) -> Option<String> {
+    req.headers()
+        .get("on_behalf_of")
+        .and_then(|v| v.to_str().ok())
+        .map(str::trim)
+        .filter(|v| !v.is_empty())
+        .map(str::to_string)
+}
+
+/// Key of a retried request, through the 'Idempotency-Key' header
+/// # Arguments
+/// * `req` - An HttpRequest object containing the headers
+/// # Returns
+/// * The key if the header is set, not empty and at most 255 characters, None otherwise
+pub fn idempotency_header(req: &HttpRequest) -> Option<String> {
+    req.headers()
+        .get("idempotency-key")
+        .and_then(|v| v.to_str().ok())
+        .map(str::trim)
+        .filter(|v| !v.is_empty() && v.len() <= IDEMPOTENCY_KEY_MAX_LEN)
+        .map(str::to_string)
+}
+
 // TESTS *******************************************************************************************
 #[cfg(test)]
 mod tests {
@@ -46,4 +132,74 @@ mod tests {
         assert_eq!(id, "H123");
         assert_eq!(key, "K456");
     }
+
+    // --- Bearer token header
+    #[tokio::test]
+    async fn test_bearer_token() {
+        let req = TestRequest::default()
+            .insert_header(("Authorization", "bearer abc.def.ghi"))
+            .to_http_request();
+        assert_eq!(bearer_token(&req).as_deref(), Some("abc.def.ghi"));
+        let basic = TestRequest::default()
+            .insert_header(("Authorization", "Basic dXNlcg=="))
+            .to_http_request();
+        assert_eq!(bearer_token(&basic), None);
+        let blank = TestRequest::default()
+            .insert_header(("Authorization", "Bearer  "))
+            .to_http_request();
+        assert_eq!(bearer_token(&blank), None);
+    }
+
+    // --- Priority header
+    #[tokio::test]
+    async fn test_is_urgent() {
+        let req = TestRequest::default()
+            .insert_header(("exam_priority", "URGENT"))
+            .to_http_request();
+        assert!(is_urgent(&req));
+        assert!(!is_urgent(&TestRequest::default().to_http_request()));
+    }
+
+    // --- Two-phase ingestion headers
+    #[tokio::test]
+    async fn test_two_phase_headers() {
+        let req = TestRequest::default()
+            .insert_header(("ingest_mode", "Two_Phase"))
+            .insert_header(("confirmation_webhook", " https://hooks.example/cb "))
+            .to_http_request();
+        assert!(is_two_phase(&req));
+        assert_eq!(
+            confirmation_webhook(&req).as_deref(),
+            Some("https://hooks.example/cb")
+        );
+        let plain = TestRequest::default().to_http_request();
+        assert!(!is_two_phase(&plain));
+        assert_eq!(confirmation_webhook(&plain), None);
+    }
+
+    // --- Delegation header
+    #[tokio::test]
+    async fn test_on_behalf_of() {
+        let req = TestRequest::default()
+            .insert_header(("on_behalf_of", " C1 "))
+            .to_http_request();
+        assert_eq!(on_behalf_of(&req).as_deref(), Some("C1"));
+        let blank = TestRequest::default()
+            .insert_header(("on_behalf_of", ""))
+            .to_http_request();
+        assert_eq!(on_behalf_of(&blank), None);
+    }
+
+    // --- Idempotency header
+    #[tokio::test]
+    async fn test_idempotency_header() {
+        let req = TestRequest::default()
+            .insert_header(("Idempotency-Key", " retry-1 "))
+            .to_http_request();
+        assert_eq!(idempotency_header(&req).as_deref(), Some("retry-1"));
+        let too_long = TestRequest::default()
+            .insert_header(("Idempotency-Key", "k".repeat(256)))
+            .to_http_request();
+        assert_eq!(idempotency_header(&too_long), None);
+    }
 }
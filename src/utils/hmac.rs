@@ -0,0 +1,68 @@
+// Imports *****************************************************************************************
+// External Crates
+use sha2::{Digest, Sha256};
+
+// Constants ***************************************************************************************
+/// Block size of SHA-256, used by HMAC
+const SHA256_BLOCK: usize = 64;
+
+// MAIN FUNCTIONS **********************************************************************************
+/// HMAC-SHA256 (RFC 2104) - signs the S3 requests, webhooks and deletion certificates, and keys
+/// the statistics noise
+/// # Arguments
+/// * `key` - The secret key
+/// * `message` - The message authenticated
+/// # Returns
+/// * The 32-byte MAC
+pub(crate) fn hmac_sha256(key: &[u8], message: &[u8]) -> Vec<u8> {
+    let mut block = [0u8; SHA256_BLOCK];
+    if key.len() > SHA256_BLOCK {
+        block[..32].copy_from_slice(&Sha256::digest(key));
+    } else {
+        block[..key.len()].copy_from_slice(key);
+    }
+    let pad = |byte: u8| block.iter().map(|b| b ^ byte).collect::<Vec<u8>>();
+    let inner = Sha256::new()
+        .chain_update(pad(0x36))
+        .chain_update(message)
+        .finalize();
+    Sha256::new()
+        .chain_update(pad(0x5c))
+        .chain_update(inner)
+        .finalize()
+        .to_vec()
+}
+
+/// Lowercase hexadecimal of bytes
+pub(crate) fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+// TESTS *******************************************************************************************
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Happy path: RFC 4231 test case 2
+    #[test]
+    fn hmac_known_vector() {
+        let mac = hmac_sha256(b"Jefe", b"what do ya want for nothing?");
+        assert_eq!(
+            hex(&mac),
+            "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+    }
+
+    // Borderline: keys longer than a block are hashed first (RFC 4231 test case 6)
+    #[test]
+    fn hmac_long_key() {
+        let mac = hmac_sha256(
+            &[0xaa; 131],
+            b"Test Using Larger Than Block-Size Key - Hash Key First",
+        );
+        assert_eq!(
+            hex(&mac),
+            "60e431591ee0b67f0d8a26aacbf5b77f8e0bc6213728c5140546040f0ee37f54"
+        );
+    }
+}
@@ -0,0 +1,170 @@
+// Imports *****************************************************************************************
+// External Crates
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::ops::Deref;
+use uuid::{NoContext, Timestamp, Uuid};
+
+// Internal Modules
+
+// Structs *****************************************************************************************
+/// Identifier of an exam: `{exam_type}/{hospital_id}/{patient_id}/{uuid}`, also the object name
+/// of its stored files without extension - the UUIDv7 carries the reception time, so the exams of
+/// a patient sort by arrival and two exams received in the same millisecond never collide
+/// Exams stored before the UUIDs end with their timestamp instead, and are read back unchanged
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct ExamId(String);
+
+impl ExamId {
+    /// Identify a new exam
+    /// # Arguments
+    /// * `exam_type` - The exam type key (`ecg_exam`, `xray_exam`)
+    /// * `hospital_id` - The hospital id (SHA256 hash)
+    /// * `patient_id` - The patient id (SHA256 hash)
+    /// * `received_at` - When the gateway received the exam
+    pub fn generate(
+        exam_type: &str,
+        hospital_id: &str,
+        patient_id: &str,
+        received_at: DateTime<Utc>,
+    ) -> Self {
+        Self(format!(
+            "{exam_type}/{hospital_id}/{patient_id}/{}",
+            uuid_v7_at(received_at)
+        ))
+    }
+
+    /// The id as a string slice
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+/// Identifier of the acknowledgement a hospital gets for an accepted exam - a retry answered from
+/// the idempotency store gets the same receipt
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct ReceiptId(String);
+
+impl ReceiptId {
+    /// Identify a new receipt
+    pub fn generate() -> Self {
+        Self(Uuid::now_v7().to_string())
+    }
+}
+
+/// Identifier of a request, stored in the request extensions by the middleware - the caller's
+/// `x-request-id` when safe to log, otherwise a new UUIDv7
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct RequestId(pub String);
+
+impl RequestId {
+    /// Identify a new request
+    pub fn generate() -> Self {
+        Self(Uuid::now_v7().to_string())
+    }
+}
+
+impl Deref for ExamId {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl From<String> for ExamId {
+    /// Exam id read back from a store or a request - not checked
+    fn from(id: String) -> Self {
+        Self(id)
+    }
+}
+
+impl From<ExamId> for String {
+    fn from(id: ExamId) -> Self {
+        id.0
+    }
+}
+
+impl PartialEq<&str> for ExamId {
+    fn eq(&self, other: &&str) -> bool {
+        self.0 == *other
+    }
+}
+
+impl fmt::Display for ExamId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl fmt::Display for ReceiptId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl fmt::Display for RequestId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+// SUPPORT FUNCTIONS *******************************************************************************
+/// UUIDv7 of an instant: its millisecond timestamp followed by random bits
+fn uuid_v7_at(at: DateTime<Utc>) -> Uuid {
+    let seconds = u64::try_from(at.timestamp()).unwrap_or_default();
+    Uuid::new_v7(Timestamp::from_unix(
+        NoContext,
+        seconds,
+        at.timestamp_subsec_nanos(),
+    ))
+}
+
+// TESTS *******************************************************************************************
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Happy path: exam ids keep the storage layout and end with a UUIDv7 of the reception time
+    #[test]
+    fn exam_id_layout() {
+        let received_at: DateTime<Utc> = "2026-10-01T12:00:00.123Z".parse().unwrap();
+        let id = ExamId::generate("ecg_exam", "h1", "p1", received_at);
+        let (prefix, uuid) = id.rsplit_once('/').unwrap();
+        assert_eq!(prefix, "ecg_exam/h1/p1");
+        let uuid = Uuid::parse_str(uuid).unwrap();
+        assert_eq!(uuid.get_version_num(), 7);
+        let (seconds, nanos) = uuid.get_timestamp().unwrap().to_unix();
+        assert_eq!((seconds, nanos / 1_000_000), (1_790_856_000, 123));
+        assert_eq!(serde_json::to_value(&id).unwrap(), id.as_str());
+    }
+
+    // Borderline: ids of the same instant differ, and later ones sort after
+    #[test]
+    fn ids_unique_and_sortable() {
+        let at = Utc::now();
+        let first = ExamId::generate("xray_exam", "h", "p", at);
+        assert_ne!(first, ExamId::generate("xray_exam", "h", "p", at));
+        let later = ExamId::generate(
+            "xray_exam",
+            "h",
+            "p",
+            at + chrono::Duration::milliseconds(1),
+        );
+        assert!(later.as_str() > first.as_str());
+        assert_ne!(ReceiptId::generate(), ReceiptId::generate());
+        assert_eq!(RequestId::generate().0.len(), 36);
+    }
+
+    // Borderline: ids written before the UUIDs are read back as they are
+    #[test]
+    fn legacy_exam_ids_read_back() {
+        let legacy = "ecg_exam/h1/p1/2026-10-01T120000.000Z";
+        let id: ExamId = serde_json::from_value(serde_json::json!(legacy)).unwrap();
+        assert_eq!(id, legacy);
+        assert_eq!(String::from(id), legacy);
+    }
+}
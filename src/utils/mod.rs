@@ -1 +1,20 @@
+pub mod api_error;
+pub mod canonical_json;
+pub mod clock_drift;
+pub mod config_drift;
+pub mod deprecation_usage;
+pub mod drain_state;
+pub mod external_call;
 pub mod get_headers;
+pub mod hmac;
+pub mod ids;
+pub mod openapi;
+pub mod publish_backlog;
+pub mod reason_code;
+pub mod request_id;
+pub mod response_format;
+#[cfg(test)]
+pub mod schema_compat;
+pub mod stage_metrics;
+pub mod storage_diagnostics;
+pub mod upload_stream;
@@ -0,0 +1,199 @@
+// Imports *****************************************************************************************
+// External Crates
+use utoipa::openapi::security::{ApiKey, ApiKeyValue, HttpAuthScheme, HttpBuilder, SecurityScheme};
+use utoipa::{Modify, OpenApi};
+
+// Internal Modules
+use crate::models::models_exams::{PayloadEcg, PayloadXray, XrayUploadMetadata};
+use crate::models::models_hash_check::{HashCheckRequest, HashCheckResult, HashMistake};
+use crate::routes::{
+    health_checker, route_get_ecg_stream, route_get_exam_status, route_get_stats,
+    route_get_zstd_dictionaries, route_post_ecg_exam, route_post_fhir_observation,
+    route_post_hash_check, route_post_xray_exam, route_post_xray_upload,
+};
+use crate::services::service_ingest_queue::{ExamState, ExamStatus};
+use crate::services::service_tenant_stats::TenantStats;
+use crate::services::service_zstd_dictionaries::ZstdDictionary;
+use crate::utils::api_error::ErrorBody;
+use crate::utils::reason_code::ReasonCode;
+
+// Structs *****************************************************************************************
+/// OpenAPI 3 specification of the hospital API (`/v1`), served at `/v1/openapi.json` - the payload
+/// constraints come from the schema annotations of the models, next to their validators
+/// The operator APIs (`/v1/admin`, `/internal/v1`) are not part of the hospital contract
+#[derive(OpenApi)]
+#[openapi(
+    info(
+        title = "Sentinela Exam Receiver",
+        description = "Ingestion of ECG and X-ray exams from the hospitals: every exam is \
+            validated, stored and notified to the downstream consumers. Errors share one body \
+            (ErrorBody) whose `reason` is a stable ReasonCode."
+    ),
+    paths(
+        health_checker::health_check_handler,
+        health_checker::liveness_handler,
+        health_checker::readyz_handler,
+        route_post_ecg_exam::ecg_exam_handler,
+        route_get_ecg_stream::ecg_stream_handler,
+        route_post_fhir_observation::fhir_observation_handler,
+        route_get_exam_status::exam_status_handler,
+        route_get_stats::exam_stats_handler,
+        route_post_xray_exam::xray_exam_handler,
+        route_post_xray_upload::xray_upload_handler,
+        route_post_hash_check::hash_check_handler,
+        route_get_zstd_dictionaries::zstd_dictionaries_handler,
+        route_get_zstd_dictionaries::zstd_dictionary_handler,
+    ),
+    components(schemas(
+        PayloadEcg,
+        PayloadXray,
+        XrayUploadMetadata,
+        ExamStatus,
+        ExamState,
+        TenantStats,
+        HashCheckRequest,
+        HashCheckResult,
+        HashMistake,
+        ZstdDictionary,
+        ErrorBody,
+        ReasonCode,
+    )),
+    modifiers(&HospitalCredentials),
+    tags(
+        (name = "exams", description = "Exam submission and status"),
+        (name = "probes", description = "Public health, liveness and readiness probes"),
+        (name = "tools", description = "Onboarding self-checks (sandbox only)"),
+    )
+)]
+pub struct ApiDoc;
+
+/// Security schemes of the hospitals: the `hospital_id` and `hospital_key` headers together, or a
+/// bearer token issued by the identity provider of the hospital
+struct HospitalCredentials;
+
+impl Modify for HospitalCredentials {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+        for header in ["hospital_id", "hospital_key"] {
+            components.add_security_scheme(
+                header,
+                SecurityScheme::ApiKey(ApiKey::Header(ApiKeyValue::new(header))),
+            );
+        }
+        components.add_security_scheme(
+            "bearer_token",
+            SecurityScheme::Http(
+                HttpBuilder::new()
+                    .scheme(HttpAuthScheme::Bearer)
+                    .bearer_format("JWT")
+                    .build(),
+            ),
+        );
+    }
+}
+
+// TESTS *******************************************************************************************
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::models_ecg_quality::{
+        ECG_MAX_AMPLITUDE, ECG_MAX_DURATION_S, ECG_MAX_SAMPLING_RATE_HZ,
+    };
+    use crate::models::models_exams::{
+        PATIENT_ID_MAX_LENGTH, SHA256_HEX_LENGTH, XRAY_VIEW_POSITIONS,
+    };
+    use serde_json::{json, Value};
+
+    fn spec() -> Value {
+        serde_json::to_value(ApiDoc::openapi()).unwrap()
+    }
+
+    // Happy path: every hospital route is documented, the operator APIs are not
+    #[test]
+    fn hospital_routes_documented() {
+        let spec = spec();
+        let paths = spec["paths"].as_object().unwrap();
+        for path in [
+            "/v1/health_check",
+            "/v1/liveness",
+            "/v1/readyz",
+            "/v1/ecg_exam",
+            "/v1/ecg_stream",
+            "/v1/fhir/observation",
+            "/v1/exam_status/{exam_id}",
+            "/v1/stats/exams",
+            "/v1/xray_exam",
+            "/v1/xray_exam/upload",
+            "/v1/tools/hash_check",
+            "/v1/zstd_dictionaries",
+            "/v1/zstd_dictionaries/{id}",
+        ] {
+            assert!(paths.contains_key(path), "{path} is not documented");
+        }
+        assert!(paths.keys().all(|path| !path.starts_with("/v1/admin")));
+        assert!(spec["paths"]["/v1/ecg_exam"]["post"]["responses"]["429"].is_object());
+        assert!(spec["paths"]["/v1/liveness"]["get"]["security"].is_null());
+    }
+
+    // Borderline: the documented constraints are those of the validators
+    #[test]
+    fn constraints_match_validators() {
+        let spec = spec();
+        let ecg = &spec["components"]["schemas"]["PayloadEcg"];
+        assert_eq!(ecg["additionalProperties"], json!(false));
+        let lead = &ecg["properties"]["lead_v6"];
+        // Whole bounds are written as integers: compare them as numbers
+        assert_eq!(
+            lead["items"]["maximum"].as_f64(),
+            Some(f64::from(ECG_MAX_AMPLITUDE))
+        );
+        assert_eq!(
+            lead["items"]["minimum"].as_f64(),
+            Some(-f64::from(ECG_MAX_AMPLITUDE))
+        );
+        assert_eq!(
+            lead["maxItems"],
+            json!((ECG_MAX_SAMPLING_RATE_HZ * ECG_MAX_DURATION_S) as u64)
+        );
+        assert_eq!(
+            ecg["properties"]["hospital_id"]["pattern"],
+            format!("^[0-9a-fA-F]{{{SHA256_HEX_LENGTH}}}$")
+        );
+        assert_eq!(
+            ecg["properties"]["patient_id"]["maxLength"],
+            json!(PATIENT_ID_MAX_LENGTH)
+        );
+        let required = ecg["required"].as_array().unwrap();
+        assert!(required.contains(&json!("lead_i")));
+        assert!(!required.contains(&json!("sampling_rate_hz")));
+
+        let xray = &spec["components"]["schemas"]["PayloadXray"];
+        assert_eq!(
+            xray["properties"]["view_position"]["enum"],
+            json!(XRAY_VIEW_POSITIONS)
+        );
+    }
+
+    // Happy path: the error schema has the fields of the error body
+    #[test]
+    fn error_body_documented() {
+        let spec = spec();
+        let properties = spec["components"]["schemas"]["ErrorBody"]["properties"]
+            .as_object()
+            .unwrap();
+        let mut names: Vec<&str> = properties.keys().map(String::as_str).collect();
+        names.sort_unstable();
+        assert_eq!(
+            names,
+            [
+                "code",
+                "error",
+                "fields",
+                "reason",
+                "request_id",
+                "trace_id"
+            ]
+        );
+        assert!(spec["components"]["securitySchemes"]["hospital_key"].is_object());
+    }
+}
@@ -0,0 +1,236 @@
+// Imports *****************************************************************************************
+// External Crates
+use std::sync::atomic::{AtomicU64, Ordering};
+
+// Internal Modules
+use crate::config::settings::{PublishSettings, DEFAULT_DEFER_AT, DEFAULT_REJECT_AT};
+
+// Constants ***************************************************************************************
+/// Seconds a hospital is asked to wait before retrying an exam refused for the backlog
+pub const BACKLOG_RETRY_AFTER_S: u64 = 10;
+
+// Structs *****************************************************************************************
+/// Admission of a new exam given the publish backlog
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Admission {
+    /// Store and publish as usual
+    Accept,
+    /// Store now, publish once the backlog drains (202 with `deferred`)
+    Defer,
+    /// Refuse with 429 - the backlog already holds too many exams in memory
+    Reject,
+}
+
+/// Kinds of publish held in memory by the gateway
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BacklogState {
+    /// Published, waiting for the Pub/Sub ack (retries included)
+    InFlight,
+    /// Stored, waiting for downstream or the backlog to recover before publishing
+    Deferred,
+}
+
+impl BacklogState {
+    /// Stable lowercase name, used as metric label
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            BacklogState::InFlight => "in_flight",
+            BacklogState::Deferred => "deferred",
+        }
+    }
+}
+
+/// Publishes held in memory by this instance - every exam counts until its notification is
+/// acked by Pub/Sub or dead-lettered
+/// The thresholds are the defaults until `configure` is called at startup
+#[derive(Debug)]
+pub struct PublishBacklog {
+    in_flight: AtomicU64,
+    deferred: AtomicU64,
+    defer_at: AtomicU64,
+    reject_at: AtomicU64,
+}
+
+impl Default for PublishBacklog {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PublishBacklog {
+    /// Create an empty backlog
+    pub const fn new() -> Self {
+        Self {
+            in_flight: AtomicU64::new(0),
+            deferred: AtomicU64::new(0),
+            defer_at: AtomicU64::new(DEFAULT_DEFER_AT),
+            reject_at: AtomicU64::new(DEFAULT_REJECT_AT),
+        }
+    }
+
+    /// Set the thresholds of the backlog
+    /// # Arguments
+    /// * `settings` - The publish settings (PUBLISH_BACKLOG_DEFER_AT, PUBLISH_BACKLOG_REJECT_AT)
+    pub fn configure(&self, settings: &PublishSettings) {
+        self.defer_at
+            .store(settings.backlog_defer_at, Ordering::SeqCst);
+        self.reject_at
+            .store(settings.backlog_reject_at, Ordering::SeqCst);
+    }
+
+    /// Count a publish until the returned guard is dropped
+    /// # Arguments
+    /// * `state` - Whether the publish is in flight or deferred
+    pub fn track(&self, state: BacklogState) -> BacklogGuard<'_> {
+        self.counter(state).fetch_add(1, Ordering::SeqCst);
+        BacklogGuard {
+            backlog: self,
+            state,
+        }
+    }
+
+    /// Publishes currently held in a state
+    pub fn count(&self, state: BacklogState) -> u64 {
+        self.counter(state).load(Ordering::SeqCst)
+    }
+
+    /// Admission of a new exam, against PUBLISH_BACKLOG_DEFER_AT and PUBLISH_BACKLOG_REJECT_AT
+    /// - Urgent exams are always accepted
+    /// # Arguments
+    /// * `urgent` - Whether the exam is flagged as urgent
+    pub fn admission(&self, urgent: bool) -> Admission {
+        if urgent {
+            return Admission::Accept;
+        }
+        let (defer_at, reject_at) = self.thresholds();
+        self.admission_at(defer_at, reject_at)
+    }
+
+    /// Whether deferred publishes should keep waiting - Pub/Sub is still slow to ack
+    pub fn is_backlogged(&self) -> bool {
+        let (defer_at, _) = self.thresholds();
+        self.count(BacklogState::InFlight) >= defer_at
+    }
+
+    /// Share of PUBLISH_BACKLOG_REJECT_AT held in memory, from 0 to 1 - the pressure of the
+    /// publish backlog, as reported to the autoscaler
+    pub fn pressure(&self) -> f64 {
+        let (_, reject_at) = self.thresholds();
+        self.pressure_at(reject_at)
+    }
+
+    /// Pressure of the backlog against an explicit reject threshold
+    fn pressure_at(&self, reject_at: u64) -> f64 {
+        let held = self.count(BacklogState::InFlight) + self.count(BacklogState::Deferred);
+        (held as f64 / reject_at.max(1) as f64).min(1.0)
+    }
+
+    /// Admission of a non-urgent exam against explicit thresholds
+    /// # Arguments
+    /// * `defer_at` - Publishes in flight from which exams are deferred
+    /// * `reject_at` - Publishes in flight or deferred from which exams are refused
+    fn admission_at(&self, defer_at: u64, reject_at: u64) -> Admission {
+        let in_flight = self.count(BacklogState::InFlight);
+        let held = in_flight + self.count(BacklogState::Deferred);
+        if held >= reject_at {
+            Admission::Reject
+        } else if in_flight >= defer_at {
+            Admission::Defer
+        } else {
+            Admission::Accept
+        }
+    }
+
+    /// Defer and reject thresholds
+    fn thresholds(&self) -> (u64, u64) {
+        (
+            self.defer_at.load(Ordering::SeqCst),
+            self.reject_at.load(Ordering::SeqCst),
+        )
+    }
+
+    fn counter(&self, state: BacklogState) -> &AtomicU64 {
+        match state {
+            BacklogState::InFlight => &self.in_flight,
+            BacklogState::Deferred => &self.deferred,
+        }
+    }
+}
+
+/// Publish counted in the backlog until dropped
+pub struct BacklogGuard<'a> {
+    backlog: &'a PublishBacklog,
+    state: BacklogState,
+}
+
+impl Drop for BacklogGuard<'_> {
+    fn drop(&mut self) {
+        self.backlog
+            .counter(self.state)
+            .fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+// Global variables ********************************************************************************
+/// Publish backlog of this instance, read by the exam routes and the metrics
+pub static PUBLISH_BACKLOG: PublishBacklog = PublishBacklog::new();
+
+// TESTS *******************************************************************************************
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Happy path: guards count while alive
+    #[test]
+    fn guards_tracked_until_dropped() {
+        let backlog = PublishBacklog::new();
+        let first = backlog.track(BacklogState::InFlight);
+        let second = backlog.track(BacklogState::Deferred);
+        assert_eq!(backlog.count(BacklogState::InFlight), 1);
+        assert_eq!(backlog.count(BacklogState::Deferred), 1);
+        drop(first);
+        drop(second);
+        assert_eq!(backlog.count(BacklogState::InFlight), 0);
+        assert_eq!(backlog.count(BacklogState::Deferred), 0);
+    }
+
+    // Thresholds: defer on publishes in flight, reject on everything held in memory
+    #[test]
+    fn admission_follows_backlog() {
+        let backlog = PublishBacklog::new();
+        assert_eq!(backlog.admission_at(2, 3), Admission::Accept);
+        let _in_flight = [
+            backlog.track(BacklogState::InFlight),
+            backlog.track(BacklogState::InFlight),
+        ];
+        assert_eq!(backlog.admission_at(2, 3), Admission::Defer);
+        let _deferred = backlog.track(BacklogState::Deferred);
+        assert_eq!(backlog.admission_at(2, 3), Admission::Reject);
+        // Borderline: deferred publishes alone do not defer more exams
+        assert_eq!(backlog.admission_at(3, 4), Admission::Accept);
+    }
+
+    // Borderline: the pressure counts everything held, and saturates at the reject threshold
+    #[test]
+    fn pressure_of_backlog() {
+        let backlog = PublishBacklog::new();
+        assert_eq!(backlog.pressure_at(4), 0.0);
+        let _held = [
+            backlog.track(BacklogState::InFlight),
+            backlog.track(BacklogState::Deferred),
+        ];
+        assert_eq!(backlog.pressure_at(4), 0.5);
+        assert_eq!(backlog.pressure_at(1), 1.0);
+        assert_eq!(backlog.pressure_at(0), 1.0);
+    }
+
+    // Happy path: urgent exams are never throttled
+    #[test]
+    fn urgent_always_accepted() {
+        let backlog = PublishBacklog::new();
+        let _held: Vec<_> = (0..DEFAULT_REJECT_AT)
+            .map(|_| backlog.track(BacklogState::Deferred))
+            .collect();
+        assert_eq!(backlog.admission(true), Admission::Accept);
+    }
+}
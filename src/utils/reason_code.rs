@@ -0,0 +1,405 @@
+// Imports *****************************************************************************************
+// External Crates
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use utoipa::ToSchema;
+
+// Internal Modules
+use crate::utils::api_error::ApiError;
+use crate::utils::external_call::{CallFailure, Dependency, ErrorClass};
+
+// Structs *****************************************************************************************
+/// Why a request or an exam failed - one code shared by the error responses, the metric labels,
+/// the audit records and the rejection digests, so the dashboards count what the hospitals see
+/// Codes are stable: new ones may be added, existing ones are never renamed
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
+    Default,
+    Serialize,
+    Deserialize,
+    ToSchema,
+)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum ReasonCode {
+    // Validation of the payload
+    /// A lead does not have the number of samples declared by the sampling metadata
+    LeadLength,
+    /// A lead sample is beyond the accepted amplitude
+    Amplitude,
+    /// A lead is flat (all samples zero)
+    FlatLine,
+    /// A lead sample is NaN or infinite
+    NonFinite,
+    /// A lead stays at the amplitude limit (saturated amplifier)
+    Clipping,
+    /// Lead III does not match lead II - lead I
+    LeadInconsistent,
+    /// The sampling rate or duration is out of range
+    SamplingMetadata,
+    /// The hospital or patient id is malformed
+    InvalidId,
+    /// The image exceeds its size limit
+    ImageTooLarge,
+    /// The image is not valid base64
+    ImageEncoding,
+    /// The image is not in an accepted format, or does not match its declared format
+    ImageFormat,
+    /// The image does not have the expected dimensions
+    ImageDimensions,
+    /// The view position is not an accepted one
+    ViewPosition,
+    /// The DICOM file could not be read or de-identified
+    DicomInvalid,
+    /// Any other field failed validation
+    FieldInvalid,
+    /// The FHIR resource could not be mapped to an exam
+    FhirMapping,
+    /// The request could not be read (unparseable body, invalid parameter)
+    MalformedRequest,
+    /// The body is larger than the limit of the hospital
+    PayloadTooLarge,
+    /// The body is compressed with an encoding the route does not accept
+    UnsupportedEncoding,
+    /// The confirmation webhook is not an allowed HTTPS URL
+    WebhookRefused,
+
+    // Authentication
+    /// No credential was sent
+    AuthMissing,
+    /// The hospital key or admin key was refused
+    AuthBadKey,
+    /// The bearer token is expired
+    TokenExpired,
+    /// The bearer token was issued for another audience
+    TokenAudience,
+    /// The bearer token was issued by another identity provider
+    TokenIssuer,
+    /// The bearer token is signed by an unknown key
+    TokenUnknownKey,
+    /// The bearer token is malformed or its signature does not verify
+    TokenInvalid,
+    /// The caller may not act on the resource (payload of another hospital, no delegation)
+    Forbidden,
+
+    // Admission
+    /// The ingest queue was full
+    QueueFull,
+    /// The publish backlog was critical
+    PublishBacklog,
+    /// The gateway was busy, or the client exceeded the rate limit of the API scope
+    RateLimited,
+    /// The client is locked out of the API scope after repeated authentication failures
+    AuthLockedOut,
+    /// The monthly quota of the hospital or its group was used up
+    QuotaExceeded,
+    /// The same exam was still processed by another request
+    ExamInFlight,
+    /// The idempotency key was already used for another payload
+    IdempotencyKeyReused,
+
+    // Content
+    /// The transformation plugin of the hospital failed
+    PluginFailed,
+    /// The malware scanner found a signature in the payload
+    Malware,
+    /// The malware scanner gave no verdict
+    ScanUnavailable,
+
+    // Dependencies, after all retries
+    /// A GCS call timed out
+    GcsTimeout,
+    /// A GCS call failed
+    GcsError,
+    /// An S3 call timed out
+    S3Timeout,
+    /// An S3 call failed
+    S3Error,
+    /// A Pub/Sub call timed out
+    #[serde(rename = "PUBSUB_TIMEOUT")]
+    PubSubTimeout,
+    /// A Pub/Sub call failed
+    #[serde(rename = "PUBSUB_ERROR")]
+    PubSubError,
+    /// A database call timed out
+    DatabaseTimeout,
+    /// A database call failed
+    DatabaseError,
+    /// A call to another dependency (IAM, JWKS) failed
+    DependencyError,
+
+    // Outcome
+    /// The storage of the deployment is misconfigured
+    StorageUnavailable,
+    /// The exam could not be stored, nor kept for replay
+    StorageFailure,
+    /// The notification could not be published, nor kept for replay
+    PublishFailure,
+    /// The resource does not exist
+    NotFound,
+    /// Any other error
+    #[default]
+    Internal,
+}
+
+impl ReasonCode {
+    /// Every code, in declaration order
+    #[cfg(test)]
+    pub const ALL: [ReasonCode; 52] = [
+        ReasonCode::LeadLength,
+        ReasonCode::Amplitude,
+        ReasonCode::FlatLine,
+        ReasonCode::NonFinite,
+        ReasonCode::Clipping,
+        ReasonCode::LeadInconsistent,
+        ReasonCode::SamplingMetadata,
+        ReasonCode::InvalidId,
+        ReasonCode::ImageTooLarge,
+        ReasonCode::ImageEncoding,
+        ReasonCode::ImageFormat,
+        ReasonCode::ImageDimensions,
+        ReasonCode::ViewPosition,
+        ReasonCode::DicomInvalid,
+        ReasonCode::FieldInvalid,
+        ReasonCode::FhirMapping,
+        ReasonCode::MalformedRequest,
+        ReasonCode::PayloadTooLarge,
+        ReasonCode::UnsupportedEncoding,
+        ReasonCode::WebhookRefused,
+        ReasonCode::AuthMissing,
+        ReasonCode::AuthBadKey,
+        ReasonCode::TokenExpired,
+        ReasonCode::TokenAudience,
+        ReasonCode::TokenIssuer,
+        ReasonCode::TokenUnknownKey,
+        ReasonCode::TokenInvalid,
+        ReasonCode::Forbidden,
+        ReasonCode::QueueFull,
+        ReasonCode::PublishBacklog,
+        ReasonCode::RateLimited,
+        ReasonCode::AuthLockedOut,
+        ReasonCode::QuotaExceeded,
+        ReasonCode::ExamInFlight,
+        ReasonCode::IdempotencyKeyReused,
+        ReasonCode::PluginFailed,
+        ReasonCode::Malware,
+        ReasonCode::ScanUnavailable,
+        ReasonCode::GcsTimeout,
+        ReasonCode::GcsError,
+        ReasonCode::S3Timeout,
+        ReasonCode::S3Error,
+        ReasonCode::PubSubTimeout,
+        ReasonCode::PubSubError,
+        ReasonCode::DatabaseTimeout,
+        ReasonCode::DatabaseError,
+        ReasonCode::DependencyError,
+        ReasonCode::StorageUnavailable,
+        ReasonCode::StorageFailure,
+        ReasonCode::PublishFailure,
+        ReasonCode::NotFound,
+        ReasonCode::Internal,
+    ];
+
+    /// Stable uppercase code, used in responses, metric labels, audit records and digests
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ReasonCode::LeadLength => "LEAD_LENGTH",
+            ReasonCode::Amplitude => "AMPLITUDE",
+            ReasonCode::FlatLine => "FLAT_LINE",
+            ReasonCode::NonFinite => "NON_FINITE",
+            ReasonCode::Clipping => "CLIPPING",
+            ReasonCode::LeadInconsistent => "LEAD_INCONSISTENT",
+            ReasonCode::SamplingMetadata => "SAMPLING_METADATA",
+            ReasonCode::InvalidId => "INVALID_ID",
+            ReasonCode::ImageTooLarge => "IMAGE_TOO_LARGE",
+            ReasonCode::ImageEncoding => "IMAGE_ENCODING",
+            ReasonCode::ImageFormat => "IMAGE_FORMAT",
+            ReasonCode::ImageDimensions => "IMAGE_DIMENSIONS",
+            ReasonCode::ViewPosition => "VIEW_POSITION",
+            ReasonCode::DicomInvalid => "DICOM_INVALID",
+            ReasonCode::FieldInvalid => "FIELD_INVALID",
+            ReasonCode::FhirMapping => "FHIR_MAPPING",
+            ReasonCode::MalformedRequest => "MALFORMED_REQUEST",
+            ReasonCode::PayloadTooLarge => "PAYLOAD_TOO_LARGE",
+            ReasonCode::UnsupportedEncoding => "UNSUPPORTED_ENCODING",
+            ReasonCode::WebhookRefused => "WEBHOOK_REFUSED",
+            ReasonCode::AuthMissing => "AUTH_MISSING",
+            ReasonCode::AuthBadKey => "AUTH_BAD_KEY",
+            ReasonCode::TokenExpired => "TOKEN_EXPIRED",
+            ReasonCode::TokenAudience => "TOKEN_AUDIENCE",
+            ReasonCode::TokenIssuer => "TOKEN_ISSUER",
+            ReasonCode::TokenUnknownKey => "TOKEN_UNKNOWN_KEY",
+            ReasonCode::TokenInvalid => "TOKEN_INVALID",
+            ReasonCode::Forbidden => "FORBIDDEN",
+            ReasonCode::QueueFull => "QUEUE_FULL",
+            ReasonCode::PublishBacklog => "PUBLISH_BACKLOG",
+            ReasonCode::RateLimited => "RATE_LIMITED",
+            ReasonCode::AuthLockedOut => "AUTH_LOCKED_OUT",
+            ReasonCode::QuotaExceeded => "QUOTA_EXCEEDED",
+            ReasonCode::ExamInFlight => "EXAM_IN_FLIGHT",
+            ReasonCode::IdempotencyKeyReused => "IDEMPOTENCY_KEY_REUSED",
+            ReasonCode::PluginFailed => "PLUGIN_FAILED",
+            ReasonCode::Malware => "MALWARE",
+            ReasonCode::ScanUnavailable => "SCAN_UNAVAILABLE",
+            ReasonCode::GcsTimeout => "GCS_TIMEOUT",
+            ReasonCode::GcsError => "GCS_ERROR",
+            ReasonCode::S3Timeout => "S3_TIMEOUT",
+            ReasonCode::S3Error => "S3_ERROR",
+            ReasonCode::PubSubTimeout => "PUBSUB_TIMEOUT",
+            ReasonCode::PubSubError => "PUBSUB_ERROR",
+            ReasonCode::DatabaseTimeout => "DATABASE_TIMEOUT",
+            ReasonCode::DatabaseError => "DATABASE_ERROR",
+            ReasonCode::DependencyError => "DEPENDENCY_ERROR",
+            ReasonCode::StorageUnavailable => "STORAGE_UNAVAILABLE",
+            ReasonCode::StorageFailure => "STORAGE_FAILURE",
+            ReasonCode::PublishFailure => "PUBLISH_FAILURE",
+            ReasonCode::NotFound => "NOT_FOUND",
+            ReasonCode::Internal => "INTERNAL",
+        }
+    }
+
+    /// Code of a validation error - the custom validators of the models name their error with
+    /// the lowercase form of the code, the built-in ones (`length`, `range`...) are FIELD_INVALID
+    /// # Arguments
+    /// * `code` - The code of the `ValidationError`
+    pub fn of_validation(code: &str) -> Self {
+        match code {
+            "lead_length" => ReasonCode::LeadLength,
+            "amplitude" => ReasonCode::Amplitude,
+            "flat_line" => ReasonCode::FlatLine,
+            "non_finite" => ReasonCode::NonFinite,
+            "clipping" => ReasonCode::Clipping,
+            "lead_inconsistent" => ReasonCode::LeadInconsistent,
+            "sampling" => ReasonCode::SamplingMetadata,
+            "invalid_id" => ReasonCode::InvalidId,
+            "image_too_large" => ReasonCode::ImageTooLarge,
+            "invalid_base64" => ReasonCode::ImageEncoding,
+            "invalid_image_format" | "decode_error" => ReasonCode::ImageFormat,
+            "invalid_dimensions" => ReasonCode::ImageDimensions,
+            "invalid_view_position" => ReasonCode::ViewPosition,
+            _ => ReasonCode::FieldInvalid,
+        }
+    }
+
+    /// Code of a failed external call
+    /// # Arguments
+    /// * `dependency` - The system called
+    /// * `class` - How the last attempt failed
+    pub fn of_call(dependency: Dependency, class: ErrorClass) -> Self {
+        let timeout = class == ErrorClass::Timeout;
+        match (dependency, timeout) {
+            (Dependency::Gcs, true) => ReasonCode::GcsTimeout,
+            (Dependency::Gcs, false) => ReasonCode::GcsError,
+            (Dependency::S3, true) => ReasonCode::S3Timeout,
+            (Dependency::S3, false) => ReasonCode::S3Error,
+            (Dependency::PubSub, true) => ReasonCode::PubSubTimeout,
+            (Dependency::PubSub, false) => ReasonCode::PubSubError,
+            (Dependency::Postgres, true) => ReasonCode::DatabaseTimeout,
+            (Dependency::Postgres, false) => ReasonCode::DatabaseError,
+            (Dependency::Scanner, _) => ReasonCode::ScanUnavailable,
+            (
+                Dependency::Iam
+                | Dependency::Jwks
+                | Dependency::Webhook
+                | Dependency::Ntp
+                | Dependency::BigQuery
+                | Dependency::Redis,
+                _,
+            ) => ReasonCode::DependencyError,
+        }
+    }
+
+    /// Most precise code of a service error: the failed external call if any, else the
+    /// `ApiError` attached by the services, else INTERNAL
+    /// # Arguments
+    /// * `error` - The error, with its context chain
+    pub fn of_error(error: &anyhow::Error) -> Self {
+        if let Some(failure) = error.chain().find_map(|e| e.downcast_ref::<CallFailure>()) {
+            return ReasonCode::of_call(failure.dependency, failure.class);
+        }
+        error
+            .chain()
+            .find_map(|e| e.downcast_ref::<ApiError>())
+            .map(ApiError::reason)
+            .unwrap_or(ReasonCode::Internal)
+    }
+
+    /// Code of a refused credential, from the message of the authentication error
+    /// # Arguments
+    /// * `message` - The message of the error, e.g. `Authentication failed: Missing valid headers`
+    pub fn of_auth_failure(message: &str) -> Self {
+        if message.contains("Missing") {
+            ReasonCode::AuthMissing
+        } else {
+            ReasonCode::AuthBadKey
+        }
+    }
+}
+
+impl fmt::Display for ReasonCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+// TESTS *******************************************************************************************
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Happy path: the serialized form is the code, for every code
+    #[test]
+    fn serialized_as_code() {
+        for reason in ReasonCode::ALL {
+            assert_eq!(
+                serde_json::to_value(reason).unwrap(),
+                serde_json::json!(reason.as_str())
+            );
+        }
+        let parsed: ReasonCode = serde_json::from_str("\"GCS_TIMEOUT\"").unwrap();
+        assert_eq!(parsed, ReasonCode::GcsTimeout);
+    }
+
+    // Happy path: failed calls are found through the context added by the services
+    #[test]
+    fn reason_of_errors() {
+        let failure = anyhow::Error::new(CallFailure {
+            dependency: Dependency::Gcs,
+            operation: "upload_object",
+            class: ErrorClass::Timeout,
+            message: "timed out after 30s".to_string(),
+        })
+        .context("saving the exam");
+        assert_eq!(ReasonCode::of_error(&failure), ReasonCode::GcsTimeout);
+        let tagged = anyhow::Error::new(ApiError::QuotaExceeded { retry_after_s: 1 });
+        assert_eq!(ReasonCode::of_error(&tagged), ReasonCode::QuotaExceeded);
+        assert_eq!(
+            ReasonCode::of_error(&anyhow::anyhow!("boom")),
+            ReasonCode::Internal
+        );
+    }
+
+    // Borderline: unknown validator codes fall back to FIELD_INVALID
+    #[test]
+    fn reason_of_validation_codes() {
+        assert_eq!(
+            ReasonCode::of_validation("lead_length"),
+            ReasonCode::LeadLength
+        );
+        assert_eq!(
+            ReasonCode::of_validation("decode_error"),
+            ReasonCode::ImageFormat
+        );
+        assert_eq!(
+            ReasonCode::of_validation("sampling"),
+            ReasonCode::SamplingMetadata
+        );
+        assert_eq!(ReasonCode::of_validation("range"), ReasonCode::FieldInvalid);
+    }
+}
@@ -0,0 +1,105 @@
+// Imports *****************************************************************************************
+// External Crates
+use actix_web::{HttpMessage, HttpRequest};
+use std::future::Future;
+
+// Internal Modules
+use crate::utils::ids::RequestId;
+
+// Constants ***************************************************************************************
+/// Longest request id accepted from the caller
+const MAX_REQUEST_ID_LENGTH: usize = 64;
+
+// Global variables ********************************************************************************
+tokio::task_local! {
+    /// Id of the request being handled, for error bodies built away from the request
+    static CURRENT_REQUEST_ID: String;
+}
+
+// MAIN FUNCTIONS **********************************************************************************
+/// Id of a request: the id assigned by the middleware, else the caller's 'x-request-id' header if
+/// it is safe to log, otherwise a new one
+/// # Arguments
+/// * `req` - The HTTP request
+/// # Returns
+/// * An id of at most 64 ASCII alphanumeric characters, '-' or '_'
+pub fn request_id(req: &HttpRequest) -> String {
+    if let Some(RequestId(id)) = req.extensions().get::<RequestId>() {
+        return id.clone();
+    }
+    req.headers()
+        .get("x-request-id")
+        .and_then(|v| v.to_str().ok())
+        .filter(|id| is_valid_request_id(id))
+        .map(str::to_string)
+        .unwrap_or_else(new_request_id)
+}
+
+/// Run the handling of a request with its id available to `current_request_id`
+/// # Arguments
+/// * `id` - The request id
+/// * `handling` - The future handling the request
+pub async fn scope_request_id<F: Future>(id: String, handling: F) -> F::Output {
+    CURRENT_REQUEST_ID.scope(id, handling).await
+}
+
+/// Id of the request being handled, if called within `scope_request_id`
+pub fn current_request_id() -> Option<String> {
+    CURRENT_REQUEST_ID.try_with(|id| id.clone()).ok()
+}
+
+// SUPPORT FUNCTIONS *******************************************************************************
+/// Whether a caller-provided id can be logged and echoed as is
+fn is_valid_request_id(id: &str) -> bool {
+    !id.is_empty()
+        && id.len() <= MAX_REQUEST_ID_LENGTH
+        && id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+/// Generate a new id, time-sortable across the instances
+fn new_request_id() -> String {
+    RequestId::generate().0
+}
+
+// TESTS *******************************************************************************************
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::test;
+
+    #[test]
+    async fn request_id_from_header() {
+        let req = test::TestRequest::default()
+            .insert_header(("x-request-id", "abc-123"))
+            .to_http_request();
+        assert_eq!(request_id(&req), "abc-123");
+    }
+
+    // Ids that could forge log lines are replaced
+    #[test]
+    async fn request_id_generated() {
+        let req = test::TestRequest::default()
+            .insert_header(("x-request-id", "a b=c"))
+            .to_http_request();
+        let id = request_id(&req);
+        assert_ne!(id, "a b=c");
+        assert!(is_valid_request_id(&id));
+        assert_ne!(new_request_id(), new_request_id());
+    }
+
+    // The id assigned by the middleware wins and is visible while the request is handled
+    #[test]
+    async fn request_id_scoped() {
+        let req = test::TestRequest::default()
+            .insert_header(("x-request-id", "from-header"))
+            .to_http_request();
+        req.extensions_mut()
+            .insert(RequestId("assigned".to_string()));
+        assert_eq!(request_id(&req), "assigned");
+        assert_eq!(current_request_id(), None);
+        let seen = scope_request_id("assigned".to_string(), async { current_request_id() }).await;
+        assert_eq!(seen.as_deref(), Some("assigned"));
+    }
+}
@@ -0,0 +1,346 @@
+// Imports *****************************************************************************************
+// External Crates
+use actix_web::error::ErrorInternalServerError;
+use actix_web::http::header::{ACCEPT, VARY};
+use actix_web::{HttpRequest, HttpResponse, HttpResponseBuilder};
+use anyhow::Result;
+use log::error;
+use serde::Serialize;
+use std::future::Future;
+
+// Internal Modules
+use crate::telemetry::trace_context::current_trace;
+use crate::utils::request_id::current_request_id;
+
+// Constants ***************************************************************************************
+/// Media type of the CBOR responses (RFC 8949)
+pub const CBOR_CONTENT_TYPE: &str = "application/cbor";
+/// Media type of the JSON responses
+pub const JSON_CONTENT_TYPE: &str = "application/json";
+
+// Structs *****************************************************************************************
+/// Encoding of a response body, chosen from the Accept header of the request
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResponseFormat {
+    Json,
+    Cbor,
+}
+
+impl ResponseFormat {
+    /// Format preferred by a request - JSON unless its Accept header ranks CBOR strictly higher
+    /// # Arguments
+    /// * `req` - The HTTP request
+    pub fn of_request(req: &HttpRequest) -> Self {
+        Self::from_accept(req.headers().get(ACCEPT).and_then(|v| v.to_str().ok()))
+    }
+
+    /// Format preferred by an Accept header: the quality of each media range is compared, `*/*` and
+    /// `application/*` counting for both formats - ties, a missing or unparsable header keep JSON
+    /// # Arguments
+    /// * `accept` - The value of the Accept header, if any
+    pub fn from_accept(accept: Option<&str>) -> Self {
+        let Some(accept) = accept else {
+            return ResponseFormat::Json;
+        };
+        // Best quality of each format, explicit ranges overriding the wildcards
+        let (mut json, mut cbor, mut wildcard) = (None::<f32>, None::<f32>, None::<f32>);
+        for (media_type, quality) in accept.split(',').filter_map(media_range) {
+            let best = match media_type.as_str() {
+                JSON_CONTENT_TYPE => &mut json,
+                CBOR_CONTENT_TYPE => &mut cbor,
+                "*/*" | "application/*" => &mut wildcard,
+                _ => continue,
+            };
+            *best = Some(best.map_or(quality, |best| best.max(quality)));
+        }
+        let json = json.or(wildcard).unwrap_or(0.0);
+        let cbor = cbor.or(wildcard).unwrap_or(0.0);
+        if cbor > json {
+            ResponseFormat::Cbor
+        } else {
+            ResponseFormat::Json
+        }
+    }
+
+    /// Media type of the format, for the Content-Type header
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            ResponseFormat::Json => JSON_CONTENT_TYPE,
+            ResponseFormat::Cbor => CBOR_CONTENT_TYPE,
+        }
+    }
+
+    /// Encode a response body in the format
+    /// # Arguments
+    /// * `body` - The body, as the handlers serialize it to JSON
+    /// # Errors
+    /// * Returns an error if the body cannot be serialized
+    pub fn encode<T: Serialize + ?Sized>(&self, body: &T) -> Result<Vec<u8>> {
+        match self {
+            ResponseFormat::Json => Ok(serde_json::to_vec(body)?),
+            ResponseFormat::Cbor => {
+                let mut bytes = Vec::new();
+                ciborium::into_writer(body, &mut bytes)?;
+                Ok(bytes)
+            }
+        }
+    }
+}
+
+/// Body of a response with the correlation ids of its request added to its fields
+#[derive(Serialize)]
+struct Correlated<'a, T: ?Sized> {
+    #[serde(flatten)]
+    body: &'a T,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    request_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    trace_id: Option<String>,
+}
+
+/// Responder shared by the routes: the body is serialized once, straight into the format of the
+/// request - JSON, or CBOR when the Accept header asks for it
+pub trait NegotiatedResponse {
+    /// Respond with a body and the `request_id` and `trace_id` of the request - bodies that are not
+    /// objects (lists) cannot carry them, and go out as written
+    /// # Arguments
+    /// * `body` - The body of the response
+    fn negotiated<T: Serialize + ?Sized>(&mut self, body: &T) -> HttpResponse;
+
+    /// Respond with a body as written, e.g. an error body already carrying its ids
+    /// # Arguments
+    /// * `body` - The body of the response
+    fn negotiated_plain<T: Serialize + ?Sized>(&mut self, body: &T) -> HttpResponse;
+}
+
+impl NegotiatedResponse for HttpResponseBuilder {
+    fn negotiated<T: Serialize + ?Sized>(&mut self, body: &T) -> HttpResponse {
+        let correlated = Correlated {
+            body,
+            request_id: current_request_id(),
+            trace_id: current_trace().map(|trace| trace.trace_id),
+        };
+        let format = current_response_format();
+        match format.encode(&correlated) {
+            Ok(bytes) => respond(self, format, bytes),
+            Err(_) => self.negotiated_plain(body),
+        }
+    }
+
+    fn negotiated_plain<T: Serialize + ?Sized>(&mut self, body: &T) -> HttpResponse {
+        let format = current_response_format();
+        match format.encode(body) {
+            Ok(bytes) => respond(self, format, bytes),
+            Err(e) => {
+                error!("Response body could not be encoded: {e}");
+                HttpResponse::from_error(ErrorInternalServerError(e))
+            }
+        }
+    }
+}
+
+// Global variables ********************************************************************************
+tokio::task_local! {
+    /// Format of the request being handled, for the responses built away from the request
+    static CURRENT_RESPONSE_FORMAT: ResponseFormat;
+}
+
+// MAIN FUNCTIONS **********************************************************************************
+/// Run the handling of a request with its response format available to `NegotiatedResponse`
+/// # Arguments
+/// * `format` - The format preferred by the request
+/// * `handling` - The future handling the request
+pub async fn scope_response_format<F: Future>(format: ResponseFormat, handling: F) -> F::Output {
+    CURRENT_RESPONSE_FORMAT.scope(format, handling).await
+}
+
+/// Format of the request being handled - JSON outside `scope_response_format`
+pub fn current_response_format() -> ResponseFormat {
+    CURRENT_RESPONSE_FORMAT
+        .try_with(|format| *format)
+        .unwrap_or(ResponseFormat::Json)
+}
+
+// SUPPORT FUNCTIONS *******************************************************************************
+/// Lowercase media type and quality of a media range, e.g. `application/cbor;q=0.9` - None for an
+/// empty range
+fn media_range(range: &str) -> Option<(String, f32)> {
+    let mut parts = range.split(';').map(str::trim);
+    let media_type = parts.next().filter(|t| !t.is_empty())?.to_ascii_lowercase();
+    let quality = parts
+        .filter_map(|param| param.split_once('='))
+        .find(|(name, _)| name.trim().eq_ignore_ascii_case("q"))
+        .and_then(|(_, q)| q.trim().parse::<f32>().ok())
+        .map_or(1.0, |q| q.clamp(0.0, 1.0));
+    Some((media_type, quality))
+}
+
+/// Response with an encoded body - the body depends on the Accept header, for the caches in between
+fn respond(
+    builder: &mut HttpResponseBuilder,
+    format: ResponseFormat,
+    bytes: Vec<u8>,
+) -> HttpResponse {
+    builder
+        .insert_header((VARY, "accept"))
+        .content_type(format.content_type())
+        .body(bytes)
+}
+
+// TESTS *******************************************************************************************
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::telemetry::middleware::correlation_middleware;
+    use actix_web::http::header::CONTENT_TYPE;
+    use actix_web::middleware::from_fn;
+    use actix_web::{get, test as actix_test, App};
+    use serde::Deserialize;
+    use serde_json::{json, Value};
+
+    #[derive(Serialize)]
+    struct Reading {
+        lead: &'static str,
+        quality: f32,
+    }
+
+    /// A reading as received, ids included
+    #[derive(Debug, PartialEq, Deserialize)]
+    struct ReceivedReading {
+        lead: String,
+        quality: f32,
+        request_id: String,
+        trace_id: String,
+    }
+
+    #[get("/reading")]
+    async fn reading() -> HttpResponse {
+        HttpResponse::Ok().negotiated(&Reading {
+            lead: "lead_i",
+            quality: 0.1,
+        })
+    }
+
+    #[get("/leads")]
+    async fn leads() -> HttpResponse {
+        HttpResponse::Ok().negotiated(&["lead_i", "lead_ii"])
+    }
+
+    // Happy path: CBOR only when the caller ranks it above JSON
+    #[test]
+    fn format_from_accept() {
+        for (accept, expected) in [
+            (None, ResponseFormat::Json),
+            (Some("application/json"), ResponseFormat::Json),
+            (Some("application/cbor"), ResponseFormat::Cbor),
+            (Some("Application/CBOR"), ResponseFormat::Cbor),
+            (
+                Some("application/cbor, application/json;q=0.5"),
+                ResponseFormat::Cbor,
+            ),
+            (
+                Some("application/json, application/cbor;q=0.5"),
+                ResponseFormat::Json,
+            ),
+            (Some("application/cbor, */*;q=0.1"), ResponseFormat::Cbor),
+            (Some("*/*"), ResponseFormat::Json),
+        ] {
+            assert_eq!(ResponseFormat::from_accept(accept), expected, "{accept:?}");
+        }
+    }
+
+    // Borderline: ties, refusals and malformed headers keep JSON
+    #[test]
+    fn json_by_default() {
+        for accept in [
+            "application/cbor, application/json",
+            "application/cbor;q=0",
+            "application/cbor;q=0.5, */*",
+            "text/html",
+            ";;,",
+        ] {
+            assert_eq!(
+                ResponseFormat::from_accept(Some(accept)),
+                ResponseFormat::Json,
+                "{accept}"
+            );
+        }
+    }
+
+    // Happy path: both encodings carry the same document
+    #[test]
+    fn encodings_parity() {
+        let body = json!({
+            "exam_id": "ecg_exam/ab/cd/0192",
+            "status": "accepted",
+            "published": false,
+            "size_bytes": 4_500_000_u64,
+            "offset": -3,
+            "quality": 0.25,
+            "fields": [{"field": "lead_i", "reason": "LEAD_LENGTH"}],
+            "retry_after": null,
+        });
+        let json_bytes = ResponseFormat::Json.encode(&body).unwrap();
+        let cbor_bytes = ResponseFormat::Cbor.encode(&body).unwrap();
+        let from_json: Value = serde_json::from_slice(&json_bytes).unwrap();
+        let from_cbor: Value = ciborium::from_reader(cbor_bytes.as_slice()).unwrap();
+        assert_eq!(from_json, body);
+        assert_eq!(from_cbor, body);
+        assert!(cbor_bytes.len() < json_bytes.len());
+    }
+
+    // Happy path: a caller asking for CBOR gets the JSON document, ids included, in CBOR
+    #[actix_web::test]
+    async fn cbor_negotiated() {
+        let app = actix_test::init_service(
+            App::new()
+                .wrap(from_fn(correlation_middleware))
+                .service(reading)
+                .service(leads),
+        )
+        .await;
+        let call = |uri: &'static str, accept: &'static str| {
+            actix_test::TestRequest::get()
+                .uri(uri)
+                .insert_header(("x-request-id", "req-2"))
+                .insert_header((
+                    "traceparent",
+                    "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01",
+                ))
+                .insert_header((ACCEPT, accept))
+                .to_request()
+        };
+        let res = actix_test::call_service(&app, call("/reading", "application/json")).await;
+        assert_eq!(res.headers().get(CONTENT_TYPE).unwrap(), JSON_CONTENT_TYPE);
+        assert_eq!(res.headers().get(VARY).unwrap(), "accept");
+        let json_bytes = actix_test::read_body(res).await;
+        // The body is serialized once, so the floats keep their own formatting
+        assert_eq!(
+            std::str::from_utf8(&json_bytes).unwrap(),
+            r#"{"lead":"lead_i","quality":0.1,"request_id":"req-2","trace_id":"4bf92f3577b34da6a3ce929d0e0e4736"}"#
+        );
+        let from_json: ReceivedReading = serde_json::from_slice(&json_bytes).unwrap();
+
+        let res =
+            actix_test::call_service(&app, call("/reading", "application/cbor, */*;q=0.5")).await;
+        assert_eq!(res.headers().get(CONTENT_TYPE).unwrap(), CBOR_CONTENT_TYPE);
+        let body = actix_test::read_body(res).await;
+        let from_cbor: ReceivedReading = ciborium::from_reader(body.as_ref()).unwrap();
+        assert_eq!(from_cbor, from_json);
+
+        // Borderline: lists cannot carry the ids, and go out as written in either format
+        let res = actix_test::call_service(&app, call("/leads", "application/cbor")).await;
+        let body = actix_test::read_body(res).await;
+        let from_cbor: Value = ciborium::from_reader(body.as_ref()).unwrap();
+        assert_eq!(from_cbor, json!(["lead_i", "lead_ii"]));
+    }
+
+    // Borderline: outside of a request, the responder answers in JSON without ids
+    #[actix_web::test]
+    async fn json_outside_requests() {
+        let res = HttpResponse::Ok().negotiated(&json!({"status": "ok"}));
+        assert_eq!(res.headers().get(CONTENT_TYPE).unwrap(), JSON_CONTENT_TYPE);
+        let body = actix_web::body::to_bytes(res.into_body()).await.unwrap();
+        assert_eq!(body, r#"{"status":"ok"}"#);
+    }
+}
@@ -0,0 +1,360 @@
+// Imports *****************************************************************************************
+// External Crates
+use serde_json::Value;
+use std::collections::HashMap;
+
+// Internal Modules
+pub use crate::services::service_message_format::{INFERENCE_ECG_AVRO, INFERENCE_XRAY_AVRO};
+
+// Constants ***************************************************************************************
+/// Schema of the exam notifications read by the exam archive (Go, protobuf JSON mapping)
+pub const ARCHIVE_EXAM_PROTO: &str = include_str!(concat!(
+    env!("CARGO_MANIFEST_DIR"),
+    "/schemas/consumers/exam_archive/exam_notification.proto"
+));
+
+// MAIN FUNCTIONS **********************************************************************************
+/// Incompatibilities of a published JSON message with an Avro record schema, as decoded by the
+/// consumer: every field without default must be present, every present field must match its type
+/// # Arguments
+/// * `schema` - The `.avsc` file of the consumer
+/// * `message` - The JSON message published by the gateway
+/// # Returns
+/// * One line per incompatibility - empty if the consumer can read the message
+pub fn avro_incompatibilities(schema: &str, message: &Value) -> Vec<String> {
+    let schema: Value = match serde_json::from_str(schema) {
+        Ok(schema) => schema,
+        Err(e) => return vec![format!("invalid Avro schema: {e}")],
+    };
+    let mut issues = Vec::new();
+    check_avro(&schema, message, "$", &mut issues);
+    issues
+}
+
+/// Incompatibilities of a published JSON message with a protobuf message, as decoded by a strict
+/// proto3 JSON parser: every field must be declared (by name or lowerCamelCase json name) and match
+/// its type - absent fields take their default
+/// # Arguments
+/// * `proto` - The `.proto` file of the consumer
+/// * `message_name` - The message the consumer decodes
+/// * `message` - The JSON message published by the gateway
+/// # Returns
+/// * One line per incompatibility - empty if the consumer can read the message
+pub fn proto_incompatibilities(proto: &str, message_name: &str, message: &Value) -> Vec<String> {
+    let file = match ProtoFile::parse(proto) {
+        Ok(file) => file,
+        Err(e) => return vec![format!("invalid proto file: {e}")],
+    };
+    let mut issues = Vec::new();
+    file.check(message_name, message, "$", &mut issues);
+    issues
+}
+
+// SUPPORTING FUNCTIONS ****************************************************************************
+/// Check a JSON value against an Avro type (primitive name, union array or complex object)
+fn check_avro(schema: &Value, value: &Value, path: &str, issues: &mut Vec<String>) {
+    match schema {
+        Value::String(primitive) => {
+            let ok = match primitive.as_str() {
+                "null" => value.is_null(),
+                "boolean" => value.is_boolean(),
+                "int" | "long" => value.is_i64() || value.is_u64(),
+                "float" | "double" => value.is_number(),
+                "string" | "bytes" => value.is_string(),
+                other => {
+                    issues.push(format!("{path}: unsupported Avro type '{other}'"));
+                    return;
+                }
+            };
+            if !ok {
+                issues.push(format!("{path}: expected {primitive}, got {value}"));
+            }
+        }
+        Value::Array(branches) => {
+            let matched = branches.iter().any(|branch| {
+                let mut branch_issues = Vec::new();
+                check_avro(branch, value, path, &mut branch_issues);
+                branch_issues.is_empty()
+            });
+            if !matched {
+                issues.push(format!("{path}: {value} matches no branch of {schema}"));
+            }
+        }
+        Value::Object(complex) => match complex.get("type").and_then(Value::as_str) {
+            Some("record") => {
+                let Some(object) = value.as_object() else {
+                    issues.push(format!("{path}: expected a record, got {value}"));
+                    return;
+                };
+                for field in complex
+                    .get("fields")
+                    .and_then(Value::as_array)
+                    .into_iter()
+                    .flatten()
+                {
+                    let name = field["name"].as_str().unwrap_or_default();
+                    let field_path = format!("{path}.{name}");
+                    match object.get(name) {
+                        Some(value) => check_avro(&field["type"], value, &field_path, issues),
+                        None if field.get("default").is_some() => {}
+                        None => issues.push(format!("{field_path}: required by the consumer")),
+                    }
+                }
+            }
+            Some("enum") => {
+                let symbols = complex.get("symbols").and_then(Value::as_array);
+                if !symbols.is_some_and(|symbols| symbols.contains(value)) {
+                    issues.push(format!("{path}: {value} is not a symbol of {schema}"));
+                }
+            }
+            Some("array") => match value.as_array() {
+                Some(items) => {
+                    for (index, item) in items.iter().enumerate() {
+                        check_avro(&complex["items"], item, &format!("{path}[{index}]"), issues);
+                    }
+                }
+                None => issues.push(format!("{path}: expected an array, got {value}")),
+            },
+            // Logical types (timestamps, decimals) are checked as their underlying type
+            Some(_) => check_avro(&complex["type"], value, path, issues),
+            None => issues.push(format!("{path}: Avro type without 'type'")),
+        },
+        other => issues.push(format!("{path}: invalid Avro type {other}")),
+    }
+}
+
+/// Top-level messages and enums of a `.proto` file - nested declarations, maps and oneofs are not
+/// supported, the consumer schemas do not use them
+struct ProtoFile {
+    messages: HashMap<String, Vec<ProtoField>>,
+    enums: HashMap<String, Vec<String>>,
+}
+
+/// Field of a protobuf message
+struct ProtoField {
+    name: String,
+    kind: String,
+    repeated: bool,
+}
+
+impl ProtoFile {
+    fn parse(proto: &str) -> Result<Self, String> {
+        // STEP 1: Tokens, without comments
+        let source: String = proto
+            .lines()
+            .map(|line| line.split("//").next().unwrap_or_default())
+            .collect::<Vec<_>>()
+            .join("\n");
+        let spaced = source
+            .replace('{', " { ")
+            .replace('}', " } ")
+            .replace(';', " ; ")
+            .replace('=', " = ");
+        let tokens: Vec<&str> = spaced.split_whitespace().collect();
+
+        // STEP 2: Blocks of declarations
+        let mut file = ProtoFile {
+            messages: HashMap::new(),
+            enums: HashMap::new(),
+        };
+        let mut i = 0;
+        while i < tokens.len() {
+            match tokens[i] {
+                "message" | "enum" if tokens.get(i + 2) == Some(&"{") => {
+                    let end = tokens[i..]
+                        .iter()
+                        .position(|t| *t == "}")
+                        .map(|p| i + p)
+                        .ok_or("unclosed block")?;
+                    let body = &tokens[i + 3..end];
+                    if body.contains(&"{") {
+                        return Err(format!("nested declarations in {}", tokens[i + 1]));
+                    }
+                    let statements = body.split(|t| *t == ";").filter(|s| !s.is_empty());
+                    if tokens[i] == "enum" {
+                        let values = statements.map(|s| s[0].to_string()).collect();
+                        file.enums.insert(tokens[i + 1].to_string(), values);
+                    } else {
+                        let fields = statements
+                            .map(ProtoField::parse)
+                            .collect::<Result<_, _>>()?;
+                        file.messages.insert(tokens[i + 1].to_string(), fields);
+                    }
+                    i = end + 1;
+                }
+                _ => i += 1,
+            }
+        }
+        Ok(file)
+    }
+
+    fn check(&self, message_name: &str, value: &Value, path: &str, issues: &mut Vec<String>) {
+        let Some(fields) = self.messages.get(message_name) else {
+            issues.push(format!("{path}: message {message_name} is not declared"));
+            return;
+        };
+        let Some(object) = value.as_object() else {
+            issues.push(format!(
+                "{path}: expected a {message_name} object, got {value}"
+            ));
+            return;
+        };
+        for (name, value) in object {
+            let field_path = format!("{path}.{name}");
+            match fields
+                .iter()
+                .find(|field| field.name == *name || json_name(&field.name) == *name)
+            {
+                Some(field) => self.check_field(field, value, &field_path, issues),
+                None => issues.push(format!(
+                    "{field_path}: unknown field of {message_name}, rejected by the consumer"
+                )),
+            }
+        }
+    }
+
+    fn check_field(&self, field: &ProtoField, value: &Value, path: &str, issues: &mut Vec<String>) {
+        if field.repeated {
+            match value.as_array() {
+                Some(items) => {
+                    for (index, item) in items.iter().enumerate() {
+                        self.check_scalar(&field.kind, item, &format!("{path}[{index}]"), issues);
+                    }
+                }
+                None => issues.push(format!("{path}: expected a repeated field, got {value}")),
+            }
+        } else {
+            self.check_scalar(&field.kind, value, path, issues);
+        }
+    }
+
+    fn check_scalar(&self, kind: &str, value: &Value, path: &str, issues: &mut Vec<String>) {
+        // null is read as the default value of any field
+        if value.is_null() {
+            return;
+        }
+        let ok = match kind {
+            "string" | "bytes" => value.is_string(),
+            "bool" => value.is_boolean(),
+            "int32" | "int64" | "sint32" | "sint64" | "sfixed32" | "sfixed64" => value.is_i64(),
+            "uint32" | "uint64" | "fixed32" | "fixed64" => value.is_u64(),
+            "float" | "double" => value.is_number(),
+            _ if self.enums.contains_key(kind) => {
+                let symbol = value.as_str().unwrap_or_default().to_string();
+                self.enums[kind].contains(&symbol)
+            }
+            _ if self.messages.contains_key(kind) => {
+                self.check(kind, value, path, issues);
+                return;
+            }
+            other => {
+                issues.push(format!("{path}: unsupported proto type '{other}'"));
+                return;
+            }
+        };
+        if !ok {
+            issues.push(format!("{path}: expected {kind}, got {value}"));
+        }
+    }
+}
+
+impl ProtoField {
+    /// Field statement: `[optional|repeated] type name = number [options]`
+    fn parse(statement: &[&str]) -> Result<Self, String> {
+        let (repeated, rest) = match statement.first() {
+            Some(&"repeated") => (true, &statement[1..]),
+            Some(&"optional") => (false, &statement[1..]),
+            _ => (false, statement),
+        };
+        match rest {
+            [kind, name, "=", _number, ..] => Ok(ProtoField {
+                name: name.to_string(),
+                kind: kind.to_string(),
+                repeated,
+            }),
+            _ => Err(format!("unsupported statement '{}'", statement.join(" "))),
+        }
+    }
+}
+
+/// lowerCamelCase json name of a proto field, e.g. `exam_id` -> `examId`
+fn json_name(name: &str) -> String {
+    let mut parts = name.split('_');
+    let mut json = parts.next().unwrap_or_default().to_string();
+    for part in parts {
+        let mut chars = part.chars();
+        if let Some(first) = chars.next() {
+            json.extend(first.to_uppercase());
+            json.push_str(chars.as_str());
+        }
+    }
+    json
+}
+
+// TESTS *******************************************************************************************
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn ecg_message() -> Value {
+        json!({
+            "topic": "dev-ecg-v1",
+            "exam_id": "ecg_exam/b/a/2026-01-01T000000Z",
+            "exam_type": "ECG Exam",
+            "timestamp": "2026-01-01T000000Z",
+            "patient_id": "a",
+            "hospital_id": "b",
+            "deferred": false,
+            "consent_scope": "clinical",
+        })
+    }
+
+    // The checkers catch the drifts that broke the consumers: renamed, removed or retyped fields
+    #[test]
+    fn avro_drift_detected() {
+        assert!(avro_incompatibilities(INFERENCE_ECG_AVRO, &ecg_message()).is_empty());
+
+        let mut renamed = ecg_message();
+        let exam_id = renamed.as_object_mut().unwrap().remove("exam_id").unwrap();
+        renamed["examId"] = exam_id;
+        assert_eq!(
+            avro_incompatibilities(INFERENCE_ECG_AVRO, &renamed),
+            vec!["$.exam_id: required by the consumer"]
+        );
+
+        let mut retyped = ecg_message();
+        retyped["deferred"] = json!("false");
+        retyped["consent_scope"] = json!("CLINICAL");
+        assert_eq!(
+            avro_incompatibilities(INFERENCE_ECG_AVRO, &retyped).len(),
+            2
+        );
+    }
+
+    #[test]
+    fn proto_drift_detected() {
+        let message = ecg_message();
+        assert!(
+            proto_incompatibilities(ARCHIVE_EXAM_PROTO, "ExamNotification", &message).is_empty()
+        );
+
+        let mut added = ecg_message();
+        added["priority"] = json!(1);
+        added["deferred"] = json!(0);
+        let issues = proto_incompatibilities(ARCHIVE_EXAM_PROTO, "ExamNotification", &added);
+        assert_eq!(issues.len(), 2);
+        assert!(issues
+            .iter()
+            .any(|issue| issue.starts_with("$.priority: unknown field")));
+    }
+
+    #[test]
+    fn proto_json_names() {
+        assert_eq!(json_name("sop_instance_uid"), "sopInstanceUid");
+        let message = json!({"examId": "e", "consentScope": "research"});
+        assert!(
+            proto_incompatibilities(ARCHIVE_EXAM_PROTO, "ExamNotification", &message).is_empty()
+        );
+    }
+}
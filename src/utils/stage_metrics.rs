@@ -0,0 +1,203 @@
+// Imports *****************************************************************************************
+// External Crates
+use chrono::{DateTime, SecondsFormat, Utc};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+use std::time::Duration;
+
+// Internal Modules
+
+// Constants ***************************************************************************************
+/// Upper bounds (inclusive, in milliseconds) of the histogram buckets - the last bucket is +Inf
+pub(crate) const BUCKETS_MS: [u64; 11] =
+    [5, 10, 25, 50, 100, 250, 500, 1_000, 2_500, 5_000, 10_000];
+
+// Types *******************************************************************************************
+/// Gateway-internal stages of an exam, from receipt to publication
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Stage {
+    /// Authentication and validation, before the exam service takes over
+    Intake,
+    /// Conversion to the storage and notification formats
+    Preprocess,
+    /// Upload of the exam to storage
+    Storage,
+    /// Time a deferred publish waited for downstream to recover
+    Deferral,
+    /// Publication of the notification
+    Publish,
+    /// Receipt to publication
+    Total,
+}
+
+impl Stage {
+    /// Stable lowercase name, used in metric labels
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Stage::Intake => "intake",
+            Stage::Preprocess => "preprocess",
+            Stage::Storage => "storage",
+            Stage::Deferral => "deferral",
+            Stage::Publish => "publish",
+            Stage::Total => "total",
+        }
+    }
+}
+
+/// Duration histogram of one stage of one exam type
+/// # Arguments
+/// * `buckets` - Cumulative counts per upper bound in milliseconds (`+Inf` for the last one)
+/// * `count` - Number of observations
+/// * `sum_ms` - Sum of the observations in milliseconds
+#[derive(Debug, Clone, Default, serde::Serialize)]
+pub struct StageHistogram {
+    pub buckets: BTreeMap<String, u64>,
+    pub count: u64,
+    pub sum_ms: u64,
+}
+
+/// Raw (non-cumulative) bucket counts, one more than BUCKETS_MS for +Inf
+#[derive(Debug, Clone, Default)]
+struct RawHistogram {
+    counts: [u64; BUCKETS_MS.len() + 1],
+    count: u64,
+    sum_ms: u64,
+}
+
+// Global variables ********************************************************************************
+/// Histograms per `(exam_type, stage)`
+static STAGE_DURATIONS: Mutex<BTreeMap<(&'static str, Stage), RawHistogram>> =
+    Mutex::new(BTreeMap::new());
+
+// MAIN FUNCTIONS **********************************************************************************
+/// Record the duration of a stage
+/// # Arguments
+/// * `exam_type` - The exam type key, e.g. `ecg_exam`
+/// * `stage` - The stage
+/// * `duration` - How long the stage took
+pub fn observe_stage(exam_type: &'static str, stage: Stage, duration: Duration) {
+    let ms = duration.as_millis() as u64;
+    let bucket = BUCKETS_MS
+        .iter()
+        .position(|bound| ms <= *bound)
+        .unwrap_or(BUCKETS_MS.len());
+    if let Ok(mut durations) = STAGE_DURATIONS.lock() {
+        let histogram = durations.entry((exam_type, stage)).or_default();
+        histogram.counts[bucket] += 1;
+        histogram.count += 1;
+        histogram.sum_ms += ms;
+    }
+}
+
+/// Record the duration between two timestamps as a stage (negative spans count as zero)
+pub fn observe_stage_between(
+    exam_type: &'static str,
+    stage: Stage,
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+) {
+    observe_stage(exam_type, stage, (to - from).to_std().unwrap_or_default());
+}
+
+/// Snapshot of the stage histograms, keyed by `exam_type.stage`
+pub fn stage_durations() -> BTreeMap<String, StageHistogram> {
+    STAGE_DURATIONS
+        .lock()
+        .map(|durations| {
+            durations
+                .iter()
+                .map(|((exam_type, stage), raw)| {
+                    (format!("{exam_type}.{}", stage.as_str()), cumulative(raw))
+                })
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Pub/Sub message attributes carrying the end-to-end latency timestamps
+/// # Arguments
+/// * `received_at` - When the gateway received the exam
+/// * `stored_at` - When the exam was written to storage
+/// * `published_at` - When the notification was handed to Pub/Sub
+/// # Returns
+/// * `received_at`, `stored_at` and `published_at` as RFC 3339 UTC with milliseconds
+pub fn latency_attributes(
+    received_at: DateTime<Utc>,
+    stored_at: DateTime<Utc>,
+    published_at: DateTime<Utc>,
+) -> HashMap<String, String> {
+    [
+        ("received_at", received_at),
+        ("stored_at", stored_at),
+        ("published_at", published_at),
+    ]
+    .into_iter()
+    .map(|(key, at)| {
+        (
+            key.to_string(),
+            at.to_rfc3339_opts(SecondsFormat::Millis, true),
+        )
+    })
+    .collect()
+}
+
+// SUPPORT FUNCTIONS *******************************************************************************
+/// Convert raw bucket counts into cumulative, labelled buckets
+fn cumulative(raw: &RawHistogram) -> StageHistogram {
+    let mut running = 0;
+    let mut buckets = BTreeMap::new();
+    for (i, count) in raw.counts.iter().enumerate() {
+        running += count;
+        let label = BUCKETS_MS
+            .get(i)
+            .map(|bound| format!("le_{bound:05}"))
+            .unwrap_or("le_inf".to_string());
+        buckets.insert(label, running);
+    }
+    StageHistogram {
+        buckets,
+        count: raw.count,
+        sum_ms: raw.sum_ms,
+    }
+}
+
+// TESTS *******************************************************************************************
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    // Happy path: observations land in cumulative buckets
+    #[test]
+    fn stage_histogram_buckets() {
+        observe_stage("test_exam", Stage::Storage, Duration::from_millis(3));
+        observe_stage("test_exam", Stage::Storage, Duration::from_millis(40));
+        observe_stage("test_exam", Stage::Storage, Duration::from_secs(60));
+
+        let snapshot = stage_durations();
+        let histogram = &snapshot["test_exam.storage"];
+        assert_eq!(histogram.count, 3);
+        assert_eq!(histogram.sum_ms, 60_043);
+        assert_eq!(histogram.buckets["le_00005"], 1);
+        assert_eq!(histogram.buckets["le_00050"], 2);
+        assert_eq!(histogram.buckets["le_10000"], 2);
+        assert_eq!(histogram.buckets["le_inf"], 3);
+    }
+
+    // Borderline: clock going backwards is recorded as zero
+    #[test]
+    fn stage_between_negative_span() {
+        let later = Utc.with_ymd_and_hms(2025, 1, 1, 0, 0, 1).unwrap();
+        let earlier = Utc.with_ymd_and_hms(2025, 1, 1, 0, 0, 0).unwrap();
+        observe_stage_between("test_exam_neg", Stage::Total, later, earlier);
+        assert_eq!(stage_durations()["test_exam_neg.total"].sum_ms, 0);
+    }
+
+    #[test]
+    fn latency_attributes_format() {
+        let at = Utc.with_ymd_and_hms(2025, 1, 2, 3, 4, 5).unwrap();
+        let attributes = latency_attributes(at, at, at);
+        assert_eq!(attributes["received_at"], "2025-01-02T03:04:05.000Z");
+        assert_eq!(attributes.len(), 3);
+    }
+}
@@ -0,0 +1,248 @@
+// Imports *****************************************************************************************
+// External Crates
+use chrono::{DateTime, Utc};
+use log::{error, info};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+
+// Internal Modules
+use crate::telemetry::metrics::record_storage_fault;
+
+// Structs *****************************************************************************************
+/// Storage failure caused by the deployment rather than by the request - retrying will not help
+/// until an operator acts, so each one raises its own alert
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StorageFault {
+    /// The credentials of the service account were refused (401)
+    Unauthenticated,
+    /// The service account lacks a permission on the bucket (403), e.g. `storage.objects.create`
+    PermissionDenied,
+    /// The bucket does not exist, or not in the project of the service account (404)
+    BucketNotFound,
+    /// A rate or storage quota of the project or bucket is exhausted (429)
+    QuotaExceeded,
+}
+
+impl StorageFault {
+    /// Stable code of the fault, used in alerts, metrics and diagnostics
+    pub fn code(&self) -> &'static str {
+        match self {
+            StorageFault::Unauthenticated => "gcs_unauthenticated",
+            StorageFault::PermissionDenied => "gcs_permission_denied",
+            StorageFault::BucketNotFound => "gcs_bucket_not_found",
+            StorageFault::QuotaExceeded => "gcs_quota_exceeded",
+        }
+    }
+
+    /// What the operator should do about the fault
+    /// # Arguments
+    /// * `bucket` - The bucket of the failed call
+    pub fn hint(&self, bucket: &str) -> String {
+        match self {
+            StorageFault::Unauthenticated => format!(
+                "The credentials used for gs://{bucket} were refused - check \
+                 GOOGLE_APPLICATION_CREDENTIALS and the impersonated service accounts"
+            ),
+            StorageFault::PermissionDenied => format!(
+                "The service account lacks a permission on gs://{bucket} - grant \
+                 roles/storage.objectCreator (uploads) or roles/storage.objectViewer (reads) to the \
+                 account logged at startup for this client"
+            ),
+            StorageFault::BucketNotFound => format!(
+                "gs://{bucket} does not exist or is in another project - check BUCKET_NAME, \
+                 DEAD_LETTER_BUCKET and RESEARCH_SAMPLE_BUCKET"
+            ),
+            StorageFault::QuotaExceeded => format!(
+                "A quota of gs://{bucket} is exhausted - request a quota increase or lower \
+                 INGEST_WORKERS"
+            ),
+        }
+    }
+}
+
+/// Operator-facing diagnostic of a bucket whose calls keep failing
+/// # Arguments
+/// * `bucket` - The bucket of the failed calls
+/// * `operation` - The last failed operation, e.g. `upload_object`
+/// * `fault` - The classified fault
+/// * `code` - The stable code of the fault
+/// * `hint` - What the operator should do
+/// * `failures` - Failures since the last successful call to the bucket
+/// * `first_seen` / `last_seen` - When the fault was first and last observed
+/// * `last_error` - The last error, as returned by the client
+#[derive(Debug, Clone, Serialize)]
+pub struct StorageDiagnostic {
+    pub bucket: String,
+    pub operation: &'static str,
+    pub fault: StorageFault,
+    pub code: &'static str,
+    pub hint: String,
+    pub failures: u64,
+    pub first_seen: DateTime<Utc>,
+    pub last_seen: DateTime<Utc>,
+    pub last_error: String,
+}
+
+// Global variables ********************************************************************************
+/// Active faults per bucket - cleared by the next successful call to the bucket
+static STORAGE_FAULTS: Mutex<BTreeMap<String, StorageDiagnostic>> = Mutex::new(BTreeMap::new());
+
+// MAIN FUNCTIONS **********************************************************************************
+/// Classify a storage error rendered as a string
+/// # Arguments
+/// * `message` - The error of the GCS client
+/// # Returns
+/// * The fault if the error is caused by the deployment, None for transient or request errors
+pub fn classify_storage_fault(message: &str) -> Option<StorageFault> {
+    let lower = message.to_lowercase();
+    let has = |markers: &[&str]| markers.iter().any(|marker| lower.contains(marker));
+    if has(&["429", "quota", "ratelimitexceeded", "rate limit"]) {
+        Some(StorageFault::QuotaExceeded)
+    } else if has(&[
+        "401",
+        "unauthenticated",
+        "invalid_grant",
+        "invalid credentials",
+    ]) {
+        Some(StorageFault::Unauthenticated)
+    } else if has(&["403", "permission", "forbidden", "does not have storage."]) {
+        Some(StorageFault::PermissionDenied)
+    } else if has(&[
+        "bucket does not exist",
+        "notfound: bucket",
+        "no such bucket",
+    ]) || (lower.contains("404") && lower.contains("bucket"))
+    {
+        Some(StorageFault::BucketNotFound)
+    } else {
+        None
+    }
+}
+
+/// Record a failed storage call - a classified fault raises an alert the first time it is seen
+/// for the bucket and is kept for the readiness diagnostics
+/// # Arguments
+/// * `bucket` - The bucket of the call
+/// * `operation` - The operation, e.g. `upload_object`
+/// * `message` - The last error of the call
+/// # Returns
+/// * The fault, None if the error is not caused by the deployment
+pub fn record_storage_failure(
+    bucket: &str,
+    operation: &'static str,
+    message: &str,
+) -> Option<StorageFault> {
+    let fault = classify_storage_fault(message)?;
+    record_storage_fault(fault.code(), operation);
+    let now = Utc::now();
+    if let Ok(mut faults) = STORAGE_FAULTS.lock() {
+        let diagnostic = faults
+            .entry(bucket.to_string())
+            .and_modify(|diagnostic| {
+                if diagnostic.fault != fault {
+                    diagnostic.fault = fault;
+                    diagnostic.code = fault.code();
+                    diagnostic.hint = fault.hint(bucket);
+                    diagnostic.first_seen = now;
+                    diagnostic.failures = 0;
+                }
+            })
+            .or_insert_with(|| StorageDiagnostic {
+                bucket: bucket.to_string(),
+                operation,
+                fault,
+                code: fault.code(),
+                hint: fault.hint(bucket),
+                failures: 0,
+                first_seen: now,
+                last_seen: now,
+                last_error: String::new(),
+            });
+        diagnostic.operation = operation;
+        diagnostic.failures += 1;
+        diagnostic.last_seen = now;
+        diagnostic.last_error = message.to_string();
+        if diagnostic.failures == 1 {
+            error!(target: "alert", "storage_fault code={} bucket={bucket} operation={operation} hint={} error={message}", fault.code(), diagnostic.hint);
+        }
+    }
+    Some(fault)
+}
+
+/// Record a successful storage call - the fault of the bucket, if any, is resolved
+/// # Arguments
+/// * `bucket` - The bucket of the call
+pub fn record_storage_success(bucket: &str) {
+    if let Ok(mut faults) = STORAGE_FAULTS.lock() {
+        if let Some(resolved) = faults.remove(bucket) {
+            info!(target: "alert", "storage_fault_resolved code={} bucket={bucket} failures={}", resolved.code, resolved.failures);
+        }
+    }
+}
+
+/// Active storage faults, for the readiness endpoint
+pub fn storage_diagnostics() -> Vec<StorageDiagnostic> {
+    STORAGE_FAULTS
+        .lock()
+        .map(|faults| faults.values().cloned().collect())
+        .unwrap_or_default()
+}
+
+// TESTS *******************************************************************************************
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Happy path: the GCS errors of a misconfigured deployment are told apart
+    #[test]
+    fn faults_classified() {
+        assert_eq!(
+            classify_storage_fault(
+                "gcs.upload_object failed (Permanent): 403 sa@p.iam does not have \
+                 storage.objects.create access"
+            ),
+            Some(StorageFault::PermissionDenied)
+        );
+        assert_eq!(
+            classify_storage_fault("404 Not Found: The specified bucket does not exist."),
+            Some(StorageFault::BucketNotFound)
+        );
+        assert_eq!(
+            classify_storage_fault("429 rateLimitExceeded"),
+            Some(StorageFault::QuotaExceeded)
+        );
+        assert_eq!(
+            classify_storage_fault("401 Unauthenticated"),
+            Some(StorageFault::Unauthenticated)
+        );
+    }
+
+    // Borderline: transient errors and missing objects are not deployment faults
+    #[test]
+    fn request_errors_not_faults() {
+        assert_eq!(classify_storage_fault("503 service unavailable"), None);
+        assert_eq!(classify_storage_fault("timed out after 30s"), None);
+        assert_eq!(classify_storage_fault("404 No such object: b/o"), None);
+    }
+
+    // Happy path: a fault is kept until the next successful call to its bucket
+    #[test]
+    fn fault_cleared_by_success() {
+        let bucket = "diagnostics-test-bucket";
+        let recorded = record_storage_failure(bucket, "upload_object", "403 permission denied");
+        assert_eq!(recorded, Some(StorageFault::PermissionDenied));
+        record_storage_failure(bucket, "upload_object", "403 permission denied");
+        let diagnostic = storage_diagnostics()
+            .into_iter()
+            .find(|d| d.bucket == bucket)
+            .unwrap();
+        assert_eq!(diagnostic.code, "gcs_permission_denied");
+        assert_eq!(diagnostic.failures, 2);
+        assert!(diagnostic.hint.contains("roles/storage.objectCreator"));
+        assert_eq!(record_storage_failure(bucket, "upload_object", "503"), None);
+        record_storage_success(bucket);
+        assert!(storage_diagnostics().iter().all(|d| d.bucket != bucket));
+    }
+}
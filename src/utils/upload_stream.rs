@@ -0,0 +1,186 @@
+// Imports *****************************************************************************************
+// External Crates
+use actix_multipart::MultipartError;
+use actix_web::error::PayloadError;
+use actix_web::web::{Bytes, BytesMut};
+use futures_util::stream::{self, LocalBoxStream};
+use futures_util::{Stream, StreamExt};
+use std::io;
+use tokio::sync::mpsc;
+
+// Internal Modules
+use crate::utils::api_error::ApiError;
+
+// Constants ***************************************************************************************
+/// Chunks buffered between the hospital connection and the storage upload
+const UPLOAD_CHANNEL_CHUNKS: usize = 8;
+
+// Types *******************************************************************************************
+/// Body chunks of an upload, as received from the hospital (request bound, not `Send`)
+pub type UploadChunks = LocalBoxStream<'static, Result<Bytes, ApiError>>;
+
+// MAIN FUNCTIONS **********************************************************************************
+/// Read the first `len` bytes of an upload, to check its signature before anything is stored
+/// # Arguments
+/// * `chunks` - The body chunks of the upload
+/// * `len` - The number of leading bytes needed
+/// # Returns
+/// * The leading bytes (fewer if the body is shorter) and the rest of the body
+/// # Errors
+/// * Returns the error of the body, e.g. PayloadTooLarge once the body limit is exceeded
+pub async fn read_head(
+    mut chunks: UploadChunks,
+    len: usize,
+) -> Result<(Bytes, UploadChunks), ApiError> {
+    let mut head = BytesMut::new();
+    while head.len() < len {
+        match chunks.next().await {
+            Some(chunk) => head.extend_from_slice(&chunk?),
+            None => break,
+        }
+    }
+    Ok((head.freeze(), chunks))
+}
+
+/// Channel bridging the request body to the storage client, which needs a `Send` stream
+/// # Returns
+/// * The sender fed by `pump_chunks` and the stream given to the storage upload
+pub fn upload_channel() -> (
+    mpsc::Sender<Result<Bytes, io::Error>>,
+    impl Stream<Item = Result<Bytes, io::Error>> + Send + Sync + 'static,
+) {
+    let (sender, mut receiver) = mpsc::channel(UPLOAD_CHANNEL_CHUNKS);
+    (sender, stream::poll_fn(move |cx| receiver.poll_recv(cx)))
+}
+
+/// Forward the body of an upload to the storage stream, head first
+/// - A body error is forwarded too, so the upload is aborted and nothing partial is stored
+/// - If the upload stops reading (it failed), the rest of the body is left unread
+/// # Arguments
+/// * `head` - The leading bytes already read by `read_head`
+/// * `chunks` - The rest of the body
+/// * `sender` - The sender of `upload_channel`
+/// # Returns
+/// * The number of bytes forwarded
+/// # Errors
+/// * Returns the error of the body - it takes precedence over the failed upload
+pub async fn pump_chunks(
+    head: Bytes,
+    mut chunks: UploadChunks,
+    sender: mpsc::Sender<Result<Bytes, io::Error>>,
+) -> Result<u64, ApiError> {
+    let mut forwarded = head.len() as u64;
+    if !head.is_empty() && sender.send(Ok(head)).await.is_err() {
+        return Ok(forwarded);
+    }
+    while let Some(chunk) = chunks.next().await {
+        match chunk {
+            Ok(chunk) => {
+                forwarded += chunk.len() as u64;
+                if sender.send(Ok(chunk)).await.is_err() {
+                    break;
+                }
+            }
+            Err(e) => {
+                let _ = sender.send(Err(io::Error::other(e.to_string()))).await;
+                return Err(e);
+            }
+        }
+    }
+    Ok(forwarded)
+}
+
+/// Read the rest of an upload into memory, for files that must be rewritten before storage
+/// # Arguments
+/// * `head` - The leading bytes already read by `read_head`
+/// * `chunks` - The rest of the body, bounded by the body limit of the route
+/// # Errors
+/// * Returns the error of the body, e.g. PayloadTooLarge once the body limit is exceeded
+pub async fn collect_chunks(head: Bytes, mut chunks: UploadChunks) -> Result<Bytes, ApiError> {
+    let mut body = BytesMut::from(&head[..]);
+    while let Some(chunk) = chunks.next().await {
+        body.extend_from_slice(&chunk?);
+    }
+    Ok(body.freeze())
+}
+
+// SUPPORTING FUNCTIONS ****************************************************************************
+/// Error of a body stream: cut at the body limit, or interrupted by the hospital
+pub fn payload_error(error: PayloadError) -> ApiError {
+    match error {
+        PayloadError::Overflow => ApiError::PayloadTooLarge,
+        other => ApiError::BadRequest(format!("Incomplete body: {other}")),
+    }
+}
+
+/// Error of a multipart body: malformed parts are the fault of the hospital
+pub fn multipart_error(error: MultipartError) -> ApiError {
+    match error {
+        MultipartError::Payload(error) => payload_error(error),
+        other => ApiError::BadRequest(format!("Invalid multipart body: {other}")),
+    }
+}
+
+// TESTS *******************************************************************************************
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chunks_of(parts: Vec<Result<&'static [u8], ApiError>>) -> UploadChunks {
+        stream::iter(parts.into_iter().map(|part| part.map(Bytes::from_static))).boxed_local()
+    }
+
+    // Happy path: the head spans several chunks, every byte reaches the upload once
+    #[actix_web::test]
+    async fn head_then_body_forwarded() {
+        let chunks = chunks_of(vec![Ok(b"ab"), Ok(b"cd"), Ok(b"ef")]);
+        let (head, rest) = read_head(chunks, 3).await.unwrap();
+        assert_eq!(&head[..], b"abcd");
+
+        let (sender, upload) = upload_channel();
+        let (forwarded, received) =
+            futures_util::future::join(pump_chunks(head, rest, sender), upload.collect::<Vec<_>>())
+                .await;
+        assert_eq!(forwarded.unwrap(), 6);
+        let received: Vec<u8> = received
+            .into_iter()
+            .flat_map(|chunk| chunk.unwrap().to_vec())
+            .collect();
+        assert_eq!(received, b"abcdef");
+    }
+
+    // A body cut at the limit aborts the upload and is reported as too large
+    #[actix_web::test]
+    async fn overflow_aborts_upload() {
+        let chunks = chunks_of(vec![Ok(b"ab"), Err(payload_error(PayloadError::Overflow))]);
+        let (sender, upload) = upload_channel();
+        let (forwarded, received) = futures_util::future::join(
+            pump_chunks(Bytes::new(), chunks, sender),
+            upload.collect::<Vec<_>>(),
+        )
+        .await;
+        assert_eq!(forwarded, Err(ApiError::PayloadTooLarge));
+        assert!(received.last().unwrap().is_err());
+    }
+
+    #[actix_web::test]
+    async fn collect_after_head() {
+        let chunks = chunks_of(vec![Ok(b"cd"), Ok(b"ef")]);
+        let body = collect_chunks(Bytes::from_static(b"ab"), chunks)
+            .await
+            .unwrap();
+        assert_eq!(&body[..], b"abcdef");
+    }
+
+    #[test]
+    fn multipart_errors() {
+        assert_eq!(
+            multipart_error(MultipartError::Payload(PayloadError::Overflow)),
+            ApiError::PayloadTooLarge
+        );
+        assert!(matches!(
+            multipart_error(MultipartError::BoundaryMissing),
+            ApiError::BadRequest(_)
+        ));
+    }
+}
@@ -0,0 +1,53 @@
+// Imports *****************************************************************************************
+// External Crates
+use criterion::{criterion_group, criterion_main, Criterion};
+use sentinela_exam_receiver::models::models_consent::ConsentScope;
+use sentinela_exam_receiver::models::models_exams::{PayloadEcg, ECG_LEAD_LENGTH};
+use sentinela_exam_receiver::services::service_ecg_exam::ecg_exam_frame;
+use sentinela_exam_receiver::utils::canonical_json::canonical_sha256;
+use std::hint::black_box;
+use std::time::Duration;
+use validator::Validate;
+
+// Internal Modules
+mod fixtures;
+
+use fixtures::synthetic_ecg;
+
+// MAIN FUNCTIONS **********************************************************************************
+/// The steps of the ECG hot path - JSON parse, validation, idempotency hash and columnar frame -
+/// measured on a full 12-lead exam
+fn ecg_hot_path(criterion: &mut Criterion) {
+    let payload = synthetic_ecg();
+    let body = serde_json::to_vec(&payload).unwrap();
+
+    criterion.bench_function("ecg_parse_json", |b| {
+        b.iter(|| serde_json::from_slice::<PayloadEcg>(black_box(&body)).unwrap())
+    });
+    criterion.bench_function("ecg_validate", |b| {
+        b.iter(|| black_box(&payload).validate().unwrap())
+    });
+    criterion.bench_function("ecg_canonical_sha256", |b| {
+        b.iter(|| canonical_sha256(black_box(&payload)).unwrap())
+    });
+    criterion.bench_function("ecg_exam_frame", |b| {
+        b.iter(|| {
+            ecg_exam_frame(
+                black_box(&payload),
+                "2026-01-01T00:00:00Z",
+                ConsentScope::Clinical,
+            )
+            .unwrap()
+        })
+    });
+}
+
+criterion_group! {
+    name = benches;
+    config = Criterion::default()
+        .sample_size(20)
+        .warm_up_time(Duration::from_secs(1))
+        .measurement_time(Duration::from_secs(3));
+    targets = ecg_hot_path
+}
+criterion_main!(benches);
@@ -0,0 +1,39 @@
+// Imports *****************************************************************************************
+// Internal Modules
+// Shared by the load tests (built with the unit tests) and the benchmarks (a target of their own):
+// the parent module of each imports the exam model
+use super::{PayloadEcg, ECG_LEAD_LENGTH};
+
+// Constants ***************************************************************************************
+/// Hospital every synthetic exam comes from
+pub const LOAD_HOSPITAL_ID: &str =
+    "bbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbb";
+
+// MAIN FUNCTIONS **********************************************************************************
+/// Valid 12-lead ECG of ECG_LEAD_LENGTH samples: a 1 Hz wave at 500 Hz, with lead III = II - I
+pub fn synthetic_ecg() -> PayloadEcg {
+    let wave = |gain: f32| -> Vec<f32> {
+        (0..ECG_LEAD_LENGTH)
+            .map(|i| gain * (i as f32 * std::f32::consts::TAU / 500.0).sin())
+            .collect()
+    };
+    PayloadEcg {
+        patient_id: "a".repeat(64).into(),
+        hospital_id: LOAD_HOSPITAL_ID.to_string().into(),
+        hospital_key: None,
+        lead_i: wave(0.5),
+        lead_ii: wave(1.0),
+        lead_iii: wave(0.5),
+        lead_avr: wave(-0.75),
+        lead_avl: wave(0.25),
+        lead_avf: wave(0.75),
+        lead_v1: wave(0.3),
+        lead_v2: wave(0.6),
+        lead_v3: wave(0.9),
+        lead_v4: wave(1.2),
+        lead_v5: wave(1.0),
+        lead_v6: wave(0.8),
+        sampling_rate_hz: None,
+        duration_s: None,
+    }
+}
@@ -0,0 +1,497 @@
+// Imports *****************************************************************************************
+// External Crates
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServerHandle, ServiceRequest, ServiceResponse};
+use actix_web::middleware::{from_fn, Next};
+use actix_web::{web, App, HttpMessage, HttpServer};
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use futures_util::stream::{self, StreamExt};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+// Internal Modules
+use crate::authentication::auth::AuthenticatedHospital;
+use crate::config::settings::test_settings;
+use crate::models::models_consent::ConsentScope;
+use crate::models::models_exams::{PayloadEcg, ECG_LEAD_LENGTH};
+use crate::models::models_publish_modes::PublishMode;
+use crate::models::models_rate_tiers::RateTier;
+use crate::models::models_size_tiers::SizeTier;
+use crate::publishers::publisher_log::LogPublisher;
+use crate::routes::route_post_ecg_exam::ecg_exam_handler;
+use crate::services::service_autoscale_hint::resident_memory_bytes;
+use crate::services::service_billing::BillingService;
+use crate::services::service_idempotency::IdempotencyStore;
+use crate::services::service_ingest_queue::{ExamState, IngestQueue};
+use crate::services::service_wasm_plugins::PluginRegistry;
+use crate::sinks::sink_log::LogSink;
+use crate::storage::exam_storage::{
+    ByteStream, DownloadStream, ExamStorage, ObjectPage, ObjectPut, StoredObject,
+};
+use crate::storage::storage_local::LocalStorage;
+use crate::telemetry::middleware::{correlation_middleware, request_metrics_middleware};
+use fixtures::{synthetic_ecg, LOAD_HOSPITAL_ID};
+
+mod fixtures;
+mod scenarios;
+
+// Constants ***************************************************************************************
+/// Recorded limits of every scenario - rewritten by a run with LOAD_TEST_RECORD=1
+const BASELINES_FILE: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/load/baselines.json");
+/// Share by which a measure may be worse than its baseline when LOAD_TEST_TOLERANCE is not set
+const DEFAULT_TOLERANCE: f64 = 0.25;
+/// Memory growth always tolerated, whatever the baseline: allocator and runtime noise
+const MEMORY_NOISE_MB: f64 = 32.0;
+/// Longest wait for the ingest workers to settle the accepted exams
+const SETTLE_TIMEOUT: Duration = Duration::from_secs(300);
+
+// Structs *****************************************************************************************
+/// Answer to a submission: its status, whether it has a Retry-After header, and its body
+type Answer = (u16, bool, String);
+
+/// Load of a scenario and the measures it must stay within
+/// # Arguments
+/// * `exams` - ECG exams submitted
+/// * `concurrency` - Submissions in flight at once
+/// * `storage_delay_ms` - Latency added to every object written, to saturate the ingest queue
+/// * `throughput_per_s` - Exams settled (stored and published) per second
+/// * `p99_ms` - 99th percentile of the response time of the submissions
+/// * `memory_growth_mb` - Resident memory gained by the process over the run
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Baseline {
+    pub exams: usize,
+    pub concurrency: usize,
+    pub storage_delay_ms: u64,
+    pub throughput_per_s: f64,
+    pub p99_ms: f64,
+    pub memory_growth_mb: f64,
+}
+
+/// Measures of a scenario run
+/// # Arguments
+/// * `accepted` - Exam ids answered with 202
+/// * `refused` - Submissions answered with 429, all with a Retry-After header
+/// * `unexpected` - Statuses other than 202 and 429, or 429 without Retry-After
+/// * `settled` - Accepted exams committed by the workers
+/// * `throughput_per_s` - Exams settled per second, from the first submission to the last settle
+/// * `p50_ms` - Median response time of the submissions
+/// * `p99_ms` - 99th percentile of the response time of the submissions
+/// * `memory_growth_mb` - Resident memory gained by the process over the run
+#[derive(Debug, Clone)]
+pub struct LoadReport {
+    pub accepted: Vec<String>,
+    pub refused: usize,
+    pub unexpected: Vec<String>,
+    pub settled: usize,
+    pub throughput_per_s: f64,
+    pub p50_ms: f64,
+    pub p99_ms: f64,
+    pub memory_growth_mb: f64,
+}
+
+/// Storage of the local backend with a latency added to every write - a saturated GCS
+struct SlowStorage {
+    inner: LocalStorage,
+    delay: Duration,
+}
+
+/// Gateway running in the process over the fake backends: local storage, log publisher and sink
+/// # Arguments
+/// * `address` - The address the server listens on
+/// * `ingest_queue` - The queue of the accepted exams
+/// * `server` - The handle stopping the server
+/// * `root` - The directory of the local storage, removed at the end of the run
+pub struct LoadGateway {
+    pub address: SocketAddr,
+    pub ingest_queue: Arc<IngestQueue>,
+    server: ServerHandle,
+    root: std::path::PathBuf,
+}
+
+#[async_trait]
+impl ExamStorage for SlowStorage {
+    fn name(&self) -> &'static str {
+        "slow_local"
+    }
+
+    fn location(&self, bucket: &str, name: &str) -> String {
+        self.inner.location(bucket, name)
+    }
+
+    async fn put_object(&self, object: &ObjectPut<'_>, bytes: Vec<u8>) -> Result<u64> {
+        tokio::time::sleep(self.delay).await;
+        self.inner.put_object(object, bytes).await
+    }
+
+    async fn put_stream(
+        &self,
+        object: &ObjectPut<'_>,
+        body: ByteStream,
+        timeout: Duration,
+    ) -> Result<u64> {
+        tokio::time::sleep(self.delay).await;
+        self.inner.put_stream(object, body, timeout).await
+    }
+
+    async fn list_objects(
+        &self,
+        bucket: &str,
+        prefix: &str,
+        page_token: Option<String>,
+    ) -> Result<ObjectPage> {
+        self.inner.list_objects(bucket, prefix, page_token).await
+    }
+
+    async fn get_object(&self, bucket: &str, name: &str) -> Result<Vec<u8>> {
+        self.inner.get_object(bucket, name).await
+    }
+
+    async fn object_info(&self, bucket: &str, name: &str) -> Result<StoredObject> {
+        self.inner.object_info(bucket, name).await
+    }
+
+    async fn get_stream(
+        &self,
+        bucket: &str,
+        name: &str,
+        range: Option<(u64, u64)>,
+    ) -> Result<DownloadStream> {
+        self.inner.get_stream(bucket, name, range).await
+    }
+
+    async fn copy_object(&self, bucket: &str, from: &StoredObject, to: &str) -> Result<()> {
+        self.inner.copy_object(bucket, from, to).await
+    }
+
+    async fn delete_object(&self, bucket: &str, name: &str, generation: Option<i64>) -> Result<()> {
+        self.inner.delete_object(bucket, name, generation).await
+    }
+}
+
+impl LoadGateway {
+    /// Start the gateway: the ECG route behind the correlation and metrics middlewares, with the
+    /// hospital authenticated without Postgres and idempotent replays off
+    /// # Arguments
+    /// * `name` - The scenario, naming the storage directory
+    /// * `storage_delay` - Latency added to every object written
+    /// # Errors
+    /// * Returns an error if the server cannot bind a local port
+    pub async fn start(name: &str, storage_delay: Duration) -> Result<Self> {
+        let settings = Arc::new(test_settings());
+        let root =
+            std::env::temp_dir().join(format!("sentinela_load_{name}_{}", std::process::id()));
+        let storage: Arc<dyn ExamStorage> = Arc::new(SlowStorage {
+            inner: LocalStorage::new(&root.to_string_lossy()),
+            delay: storage_delay,
+        });
+        let publisher = Arc::new(LogPublisher::new(settings.publish.routes.clone(), None));
+        let billing = Arc::new(BillingService::new(Box::new(LogSink::new("billing"))));
+        let ingest_queue = IngestQueue::start(settings.clone(), storage, publisher, billing);
+        let plugins = Arc::new(PluginRegistry::new(1_000_000, 2 * 1024 * 1024)?);
+        // Never reached: a zero window skips the idempotency store
+        let pool = sqlx::postgres::PgPoolOptions::new().connect_lazy("postgres://invalid")?;
+        // Never reached either: the load hospital has no monthly quota
+        let quota_pool = pool.clone();
+        let idempotency = Arc::new(IdempotencyStore::with_window(
+            pool,
+            Duration::ZERO,
+            Duration::ZERO,
+        ));
+
+        let queue = ingest_queue.clone();
+        let server = HttpServer::new(move || {
+            App::new()
+                .app_data(web::Data::new(settings.clone()))
+                .app_data(web::Data::new(queue.clone()))
+                .app_data(web::Data::new(plugins.clone()))
+                .app_data(web::Data::new(idempotency.clone()))
+                .app_data(web::Data::new(quota_pool.clone()))
+                .app_data(
+                    web::JsonConfig::default().limit(SizeTier::max_json_limit(&settings.server)),
+                )
+                .wrap(from_fn(correlation_middleware))
+                .wrap(from_fn(request_metrics_middleware))
+                .service(
+                    web::scope("/v1")
+                        .wrap(from_fn(authenticate_load_hospital))
+                        .service(ecg_exam_handler),
+                )
+        })
+        .workers(2)
+        .disable_signals()
+        .bind(("127.0.0.1", 0))?;
+        let address = *server
+            .addrs()
+            .first()
+            .ok_or_else(|| anyhow!("Load gateway not bound"))?;
+        let server = server.run();
+        let handle = server.handle();
+        actix_web::rt::spawn(server);
+        Ok(Self {
+            address,
+            ingest_queue,
+            server: handle,
+            root,
+        })
+    }
+
+    /// Stop the server and remove the stored exams
+    pub async fn stop(self) {
+        self.server.stop(true).await;
+        let _ = std::fs::remove_dir_all(&self.root);
+    }
+}
+
+// MAIN FUNCTIONS **********************************************************************************
+/// Submit the exams of a scenario over HTTP and wait for the workers to settle the accepted ones
+/// # Arguments
+/// * `gateway` - The gateway under load
+/// * `baseline` - The load of the scenario
+/// # Errors
+/// * Returns an error if the accepted exams are not settled within SETTLE_TIMEOUT
+pub async fn run_load(gateway: &LoadGateway, baseline: &Baseline) -> Result<LoadReport> {
+    // STEP 1: One body for every submission - each is given its own exam id
+    let body = serde_json::to_vec(&synthetic_ecg())?;
+    let client = reqwest::Client::builder()
+        .pool_max_idle_per_host(baseline.concurrency)
+        .build()?;
+    let url = format!("http://{}/v1/ecg_exam", gateway.address);
+    let memory_before = resident_memory_bytes().unwrap_or_default();
+    let started = Instant::now();
+
+    // STEP 2: Submit with the concurrency of the scenario, timing every response
+    let responses: Vec<(Duration, Result<Answer>)> = stream::iter(0..baseline.exams)
+        .map(|_| {
+            let request = client
+                .post(&url)
+                .header("content-type", "application/json")
+                .body(body.clone());
+            async move {
+                let sent = Instant::now();
+                let response = async {
+                    let response = request.send().await?;
+                    let status = response.status().as_u16();
+                    let retry_after = response.headers().contains_key("retry-after");
+                    Ok::<_, anyhow::Error>((status, retry_after, response.text().await?))
+                }
+                .await;
+                (sent.elapsed(), response)
+            }
+        })
+        .buffer_unordered(baseline.concurrency)
+        .collect()
+        .await;
+
+    // STEP 3: Sort the outcomes
+    let mut latencies_ms = Vec::with_capacity(responses.len());
+    let (mut accepted, mut refused, mut unexpected) = (Vec::new(), 0, Vec::new());
+    for (latency, response) in responses {
+        latencies_ms.push(latency.as_secs_f64() * 1000.0);
+        match response {
+            Ok((202, _, text)) => match serde_json::from_str::<serde_json::Value>(&text)
+                .ok()
+                .and_then(|body| body["exam_id"].as_str().map(str::to_string))
+            {
+                Some(exam_id) => accepted.push(exam_id),
+                None => unexpected.push(format!("202 without exam_id: {text}")),
+            },
+            Ok((429, true, _)) => refused += 1,
+            Ok((status, _, text)) => unexpected.push(format!("{status}: {text}")),
+            Err(e) => unexpected.push(e.to_string()),
+        }
+    }
+
+    // STEP 4: Wait for the workers, then count the exams they committed
+    let deadline = Instant::now() + SETTLE_TIMEOUT;
+    while gateway.ingest_queue.in_flight() > 0 {
+        if Instant::now() > deadline {
+            return Err(anyhow!(
+                "{} exams still in the ingest queue after {SETTLE_TIMEOUT:?}",
+                gateway.ingest_queue.in_flight()
+            ));
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+    let elapsed = started.elapsed().as_secs_f64();
+    let mut settled = 0;
+    for exam_id in &accepted {
+        let status = gateway.ingest_queue.status(exam_id, LOAD_HOSPITAL_ID).await;
+        if status.is_some_and(|status| status.state == ExamState::Committed) {
+            settled += 1;
+        }
+    }
+    let memory_after = resident_memory_bytes().unwrap_or_default();
+
+    latencies_ms.sort_by(f64::total_cmp);
+    Ok(LoadReport {
+        throughput_per_s: settled as f64 / elapsed,
+        p50_ms: percentile(&latencies_ms, 0.50),
+        p99_ms: percentile(&latencies_ms, 0.99),
+        memory_growth_mb: memory_after.saturating_sub(memory_before) as f64 / (1024.0 * 1024.0),
+        accepted,
+        refused,
+        unexpected,
+        settled,
+    })
+}
+
+/// Compare a run with the baseline of its scenario - with LOAD_TEST_RECORD=1 the measures are
+/// recorded as the new baseline instead
+/// # Arguments
+/// * `scenario` - The name of the scenario in BASELINES_FILE
+/// * `report` - The measures of the run
+/// # Returns
+/// * The regressions beyond LOAD_TEST_TOLERANCE, empty when the run is within its baseline
+pub fn regressions(scenario: &str, report: &LoadReport) -> Vec<String> {
+    let mut baselines = baselines();
+    let Some(baseline) = baselines.get_mut(scenario) else {
+        return vec![format!("{scenario} has no baseline in {BASELINES_FILE}")];
+    };
+    if std::env::var("LOAD_TEST_RECORD").is_ok_and(|v| v == "1") {
+        baseline.throughput_per_s = round(report.throughput_per_s);
+        baseline.p99_ms = round(report.p99_ms);
+        baseline.memory_growth_mb = round(report.memory_growth_mb);
+        let recorded = serde_json::to_string_pretty(&baselines).unwrap_or_default();
+        if let Err(e) = std::fs::write(BASELINES_FILE, recorded + "\n") {
+            return vec![format!("Baseline of {scenario} not recorded: {e}")];
+        }
+        return Vec::new();
+    }
+    compare(baseline, report, tolerance())
+}
+
+/// Baselines of every scenario
+/// # Panics
+/// * Panics if BASELINES_FILE is missing or malformed - the suite cannot run without it
+pub fn baselines() -> BTreeMap<String, Baseline> {
+    let text = std::fs::read_to_string(BASELINES_FILE)
+        .unwrap_or_else(|e| panic!("{BASELINES_FILE} unreadable: {e}"));
+    serde_json::from_str(&text).unwrap_or_else(|e| panic!("{BASELINES_FILE} malformed: {e}"))
+}
+
+// SUPPORT FUNCTIONS *******************************************************************************
+/// Hospital of the synthetic exams, set in place of the authentication middleware (no Postgres)
+async fn authenticate_load_hospital(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    req.extensions_mut().insert(AuthenticatedHospital {
+        hospital_id: LOAD_HOSPITAL_ID.to_string(),
+        consent_scope: ConsentScope::Clinical,
+        size_tier: SizeTier::Standard,
+        monthly_quota: None,
+        group: None,
+        allowed_exam_types: None,
+        rate_tier: RateTier::Standard,
+        publish_mode: PublishMode::Inline,
+        paused: false,
+    });
+    next.call(req).await
+}
+
+/// Measures of a run worse than their baseline by more than the tolerance
+fn compare(baseline: &Baseline, report: &LoadReport, tolerance: f64) -> Vec<String> {
+    let mut regressions = Vec::new();
+    let min_throughput = baseline.throughput_per_s * (1.0 - tolerance);
+    if report.throughput_per_s < min_throughput {
+        regressions.push(format!(
+            "throughput {:.1}/s below {min_throughput:.1}/s",
+            report.throughput_per_s
+        ));
+    }
+    let max_p99 = baseline.p99_ms * (1.0 + tolerance);
+    if report.p99_ms > max_p99 {
+        regressions.push(format!("p99 {:.1}ms above {max_p99:.1}ms", report.p99_ms));
+    }
+    let max_memory = baseline.memory_growth_mb * (1.0 + tolerance) + MEMORY_NOISE_MB;
+    if report.memory_growth_mb > max_memory {
+        regressions.push(format!(
+            "memory growth {:.1}MB above {max_memory:.1}MB",
+            report.memory_growth_mb
+        ));
+    }
+    regressions
+}
+
+/// Tolerance of the comparisons (LOAD_TEST_TOLERANCE, e.g. `0.25` for 25%)
+fn tolerance() -> f64 {
+    std::env::var("LOAD_TEST_TOLERANCE")
+        .ok()
+        .and_then(|v| v.parse::<f64>().ok())
+        .filter(|t| t.is_finite() && *t >= 0.0)
+        .unwrap_or(DEFAULT_TOLERANCE)
+}
+
+/// Value at a quantile of sorted measures, 0 when there is none
+fn percentile(sorted: &[f64], quantile: f64) -> f64 {
+    if sorted.is_empty() {
+        return 0.0;
+    }
+    let index = ((sorted.len() - 1) as f64 * quantile).round() as usize;
+    sorted[index.min(sorted.len() - 1)]
+}
+
+/// Round a measure to 1 decimal, for a readable baseline file
+fn round(value: f64) -> f64 {
+    (value * 10.0).round() / 10.0
+}
+
+// TESTS *******************************************************************************************
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use validator::Validate;
+
+    fn report(throughput_per_s: f64, p99_ms: f64, memory_growth_mb: f64) -> LoadReport {
+        LoadReport {
+            accepted: Vec::new(),
+            refused: 0,
+            unexpected: Vec::new(),
+            settled: 0,
+            throughput_per_s,
+            p50_ms: 0.0,
+            p99_ms,
+            memory_growth_mb,
+        }
+    }
+
+    // Happy path: the synthetic exam passes validation, every scenario has a baseline
+    #[test]
+    fn harness_inputs_valid() {
+        assert!(synthetic_ecg().validate().is_ok());
+        let baselines = baselines();
+        for scenario in ["ecg_burst", "ecg_backpressure"] {
+            assert!(baselines.contains_key(scenario), "{scenario}");
+        }
+    }
+
+    // Error handling: each measure beyond its tolerance is reported
+    #[test]
+    fn regressions_reported() {
+        let baseline = Baseline {
+            exams: 10,
+            concurrency: 2,
+            storage_delay_ms: 0,
+            throughput_per_s: 100.0,
+            p99_ms: 50.0,
+            memory_growth_mb: 100.0,
+        };
+        assert!(compare(&baseline, &report(80.0, 60.0, 150.0), 0.25).is_empty());
+        let regressions = compare(&baseline, &report(70.0, 70.0, 200.0), 0.25);
+        assert_eq!(regressions.len(), 3, "{regressions:?}");
+    }
+
+    // Borderline: percentiles of empty and single measures
+    #[test]
+    fn percentile_bounds() {
+        assert_eq!(percentile(&[], 0.99), 0.0);
+        assert_eq!(percentile(&[4.0], 0.5), 4.0);
+        let sorted: Vec<f64> = (1..=100).map(f64::from).collect();
+        assert_eq!(percentile(&sorted, 0.5), 51.0);
+        assert_eq!(percentile(&sorted, 0.99), 99.0);
+    }
+}
@@ -0,0 +1,70 @@
+// Imports *****************************************************************************************
+// External Crates
+use std::time::Duration;
+
+// Internal Modules
+use super::{baselines, regressions, run_load, LoadGateway, LoadReport};
+
+// TESTS *******************************************************************************************
+/// Scenarios run one after the other: they share the process, its memory and its global state
+/// (publish backlog, retry budget)
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Run a scenario on its own gateway and check it against its baseline
+    async fn scenario(name: &str) -> (LoadReport, Vec<String>) {
+        let baseline = baselines()[name].clone();
+        let gateway = LoadGateway::start(name, Duration::from_millis(baseline.storage_delay_ms))
+            .await
+            .unwrap();
+        let report = run_load(&gateway, &baseline).await.unwrap();
+        gateway.stop().await;
+        println!(
+            "{name}: accepted={} refused={} settled={} throughput={:.1}/s p50={:.1}ms p99={:.1}ms memory_growth={:.1}MB",
+            report.accepted.len(), report.refused, report.settled, report.throughput_per_s,
+            report.p50_ms, report.p99_ms, report.memory_growth_mb
+        );
+
+        // No exam is ever lost: every submission is accepted then committed, or refused with a
+        // Retry-After for the hospital to send it again
+        assert!(
+            report.unexpected.is_empty(),
+            "{name}: {:?}",
+            report.unexpected
+        );
+        assert_eq!(
+            report.accepted.len() + report.refused,
+            baseline.exams,
+            "{name}"
+        );
+        assert_eq!(
+            report.settled,
+            report.accepted.len(),
+            "{name}: accepted exams lost"
+        );
+        let regressions = regressions(name, &report)
+            .into_iter()
+            .map(|regression| format!("{name}: {regression}"))
+            .collect();
+        (report, regressions)
+    }
+
+    // Happy path: a burst within the capacity of the queue is accepted in full; a burst beyond
+    // it, over a slow storage, is shed with 429 and no accepted exam is lost
+    #[actix_web::test]
+    async fn ecg_ingestion_under_load() {
+        let (burst, mut regressions) = scenario("ecg_burst").await;
+        assert_eq!(
+            burst.refused, 0,
+            "ecg_burst: refused within the queue capacity"
+        );
+        let (backpressure, shed) = scenario("ecg_backpressure").await;
+        assert!(
+            backpressure.refused > 0,
+            "ecg_backpressure: the queue never filled"
+        );
+        regressions.extend(shed);
+        assert!(regressions.is_empty(), "{regressions:#?}");
+    }
+}